@@ -0,0 +1,67 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc::compile();
+}
+
+/// Generate the tonic client and server for `proto/inference.proto`
+///
+/// Messages are defined by hand in `src/grpc.rs`, so no `protoc` is needed at build time.
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    fn method(name: &str, route: &str, input: &str, output: &str) -> Method {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::pb::{input}"))
+            .output_type(format!("crate::grpc::pb::{output}"))
+            .codec_path("tonic::codec::ProstCodec")
+            .build()
+    }
+
+    pub fn compile() {
+        println!("cargo:rerun-if-changed=build.rs");
+
+        let infer_stream = Method::builder()
+            .name("infer_stream")
+            .route_name("InferStream")
+            .input_type("crate::grpc::pb::InferenceRequest")
+            .output_type("crate::grpc::pb::StreamChunk")
+            .codec_path("tonic::codec::ProstCodec")
+            .server_streaming()
+            .build();
+
+        let service = Service::builder()
+            .name("InferenceService")
+            .package("tyl.inference.v1")
+            .method(method(
+                "infer",
+                "Infer",
+                "InferenceRequest",
+                "InferenceResponse",
+            ))
+            .method(infer_stream)
+            .method(method(
+                "health_check",
+                "HealthCheck",
+                "HealthCheckRequest",
+                "HealthCheckResponse",
+            ))
+            .method(method(
+                "supported_models",
+                "SupportedModels",
+                "SupportedModelsRequest",
+                "SupportedModelsResponse",
+            ))
+            .method(method(
+                "count_tokens",
+                "CountTokens",
+                "CountTokensRequest",
+                "CountTokensResponse",
+            ))
+            .build();
+
+        Builder::new().compile(&[service]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Derive macros for `tyl-llm-inference-port`
+//!
+//! `#[derive(Prompt)]` implements `tyl_llm_inference_port::Prompt` for a struct whose fields are
+//! the parameters of its template. Placeholders are checked against the fields at compile time:
+//! a placeholder without a field, or a field the template never uses, is a compile error.
+//!
+//! ```rust,ignore
+//! #[derive(Prompt)]
+//! #[prompt(template = "Summarize in {{words}} words:\n{{text}}", model_type = "Fast")]
+//! struct Summarize {
+//!     text: String,
+//!     words: usize,
+//!     #[prompt(skip)]
+//!     request_id: u64,
+//! }
+//! ```
+//!
+//! Field values are rendered with `Display`. `#[prompt(rename = "name")]` binds a field to a
+//! differently named placeholder and `#[prompt(skip)]` leaves it out of the parameters.
+//!
+//! `template!` applies the same check to an inline template and its arguments, and expands to
+//! an `InferenceRequest`. An argument without a value uses the variable of the same name:
+//!
+//! ```rust,ignore
+//! let request = template!("Hello {{name}}, you have {{count}} messages", name = user.name, count);
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, Ident, LitStr, Token};
+
+#[proc_macro_derive(Prompt, attributes(prompt))]
+pub fn derive_prompt(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro]
+pub fn template(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as TemplateInput);
+    expand_template(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct Parameter {
+    field: Ident,
+    name: String,
+}
+
+/// `template!` arguments: the template literal, then `name = value` or `name` pairs
+struct TemplateInput {
+    template: LitStr,
+    arguments: Vec<(Ident, Expr)>,
+}
+
+impl Parse for TemplateInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let template: LitStr = input.parse()?;
+        let mut arguments = Vec::new();
+        if input.parse::<Option<Token![,]>>()?.is_some() {
+            let pairs =
+                Punctuated::<(Ident, Expr), Token![,]>::parse_terminated_with(input, |input| {
+                    let name: Ident = input.parse()?;
+                    let value = if input.parse::<Option<Token![=]>>()?.is_some() {
+                        input.parse()?
+                    } else {
+                        syn::parse_quote!(#name)
+                    };
+                    Ok((name, value))
+                })?;
+            arguments.extend(pairs);
+        }
+        Ok(Self {
+            template,
+            arguments,
+        })
+    }
+}
+
+fn expand_template(input: TemplateInput) -> syn::Result<proc_macro2::TokenStream> {
+    let TemplateInput {
+        template,
+        arguments,
+    } = input;
+    let placeholders = placeholders(&template.value());
+    for (index, (name, _)) in arguments.iter().enumerate() {
+        if arguments[..index].iter().any(|(other, _)| other == name) {
+            return Err(syn::Error::new_spanned(name, "duplicate template argument"));
+        }
+        if !placeholders.contains(&name.to_string()) {
+            return Err(syn::Error::new_spanned(
+                name,
+                format!("argument is not used by the template; add `{{{{{name}}}}}`"),
+            ));
+        }
+    }
+    for placeholder in &placeholders {
+        if !arguments.iter().any(|(name, _)| name == placeholder) {
+            return Err(syn::Error::new_spanned(
+                &template,
+                format!("template placeholder `{{{{{placeholder}}}}}` has no matching argument"),
+            ));
+        }
+    }
+
+    let capacity = arguments.len();
+    let inserts = arguments.iter().map(|(name, value)| {
+        let key = name.to_string();
+        quote! {
+            parameters.insert(
+                ::std::string::String::from(#key),
+                ::std::string::ToString::to_string(&(#value)),
+            );
+        }
+    });
+    Ok(quote! {
+        {
+            let mut parameters = ::std::collections::HashMap::with_capacity(#capacity);
+            #(#inserts)*
+            ::tyl_llm_inference_port::InferenceRequest::new(
+                #template,
+                parameters,
+                ::tyl_llm_inference_port::ModelType::General,
+            )
+        }
+    })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut template: Option<LitStr> = None;
+    let mut model_type: Option<Ident> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("prompt")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("template") {
+                template = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("model_type") {
+                let variant: LitStr = meta.value()?.parse()?;
+                model_type = Some(variant.parse()?);
+            } else {
+                return Err(meta.error("expected `template` or `model_type`"));
+            }
+            Ok(())
+        })?;
+    }
+    let template = template.ok_or_else(|| {
+        syn::Error::new(
+            Span::call_site(),
+            "missing #[prompt(template = \"...\")] attribute",
+        )
+    })?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Prompt can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Prompt can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut parameters = Vec::new();
+    for field in fields {
+        let ident = field.ident.clone().expect("named field");
+        let mut name = ident.to_string().trim_start_matches("r#").to_string();
+        let mut skip = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("prompt")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("skip") {
+                    skip = true;
+                } else {
+                    return Err(meta.error("expected `rename` or `skip`"));
+                }
+                Ok(())
+            })?;
+        }
+        if !skip {
+            parameters.push(Parameter { field: ident, name });
+        }
+    }
+
+    let placeholders = placeholders(&template.value());
+    for placeholder in &placeholders {
+        if !parameters.iter().any(|p| &p.name == placeholder) {
+            return Err(syn::Error::new_spanned(
+                &template,
+                format!("template placeholder `{{{{{placeholder}}}}}` has no matching field"),
+            ));
+        }
+    }
+    for parameter in &parameters {
+        if !placeholders.contains(&parameter.name) {
+            return Err(syn::Error::new_spanned(
+                &parameter.field,
+                format!(
+                    "field is not used by the template; add `{{{{{}}}}}` or mark it #[prompt(skip)]",
+                    parameter.name
+                ),
+            ));
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let capacity = parameters.len();
+    let inserts = parameters.iter().map(|Parameter { field, name }| {
+        quote! {
+            parameters.insert(
+                ::std::string::String::from(#name),
+                ::std::string::ToString::to_string(&self.#field),
+            );
+        }
+    });
+    let model_type = model_type.map(|variant| {
+        quote! {
+            fn model_type(&self) -> ::tyl_llm_inference_port::ModelType {
+                ::tyl_llm_inference_port::ModelType::#variant
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::tyl_llm_inference_port::Prompt for #name #ty_generics #where_clause {
+            const TEMPLATE: &'static str = #template;
+
+            #model_type
+
+            fn parameters(
+                &self,
+            ) -> ::std::collections::HashMap<::std::string::String, ::std::string::String> {
+                let mut parameters = ::std::collections::HashMap::with_capacity(#capacity);
+                #(#inserts)*
+                parameters
+            }
+        }
+    })
+}
+
+/// Distinct `{{name}}` placeholders of `template`, in order of first use
+///
+/// Names are taken verbatim, as `InferenceRequest::render_template` substitutes them.
+fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let name = &after[..end];
+        if !name.is_empty() && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders("Hi {{name}}, {{question}} ({{name}}) {{}} {{open"),
+            vec!["name", "question"]
+        );
+        assert!(placeholders("no parameters").is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+//! Adaptive temperature based on validation failure history
+//!
+//! `AdaptiveTemperatureService` checks every response with an `AnswerValidator` (typically a
+//! schema check) and counts failures per template. While a template keeps failing, subsequent
+//! requests for it run with a lower temperature and, once `with_escalation` is configured and
+//! enough failures pile up, on a stronger model type. Every accepted response lowers the count
+//! by one, so the adjustment wears off once the template produces valid output again.
+//!
+//! Adjustments are recorded in the response metadata under `ADAPTIVE_TEMPERATURE_METADATA_KEY`
+//! and `ADAPTIVE_MODEL_TYPE_METADATA_KEY`.
+
+use crate::speculative::AnswerValidator;
+use crate::*;
+use std::sync::Mutex;
+
+/// Response metadata key holding the temperature used after an adjustment
+pub const ADAPTIVE_TEMPERATURE_METADATA_KEY: &str = "adaptive_temperature";
+/// Response metadata key holding the model type used after an escalation
+pub const ADAPTIVE_MODEL_TYPE_METADATA_KEY: &str = "adaptive_model_type";
+/// Response metadata key holding the failure count that caused an adjustment
+pub const VALIDATION_FAILURES_METADATA_KEY: &str = "validation_failures";
+
+/// Key identifying a template: its `template_name` metadata, or the raw template text
+fn template_key(request: &InferenceRequest) -> String {
+    request
+        .metadata
+        .get("template_name")
+        .cloned()
+        .unwrap_or_else(|| request.template.clone())
+}
+
+/// Inference service decorator lowering temperature for templates that fail validation
+pub struct AdaptiveTemperatureService<S> {
+    inner: S,
+    validator: Box<dyn AnswerValidator>,
+    step: f32,
+    min_temperature: f32,
+    escalation: Option<(u32, ModelType)>,
+    failures: Mutex<HashMap<String, u32>>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for AdaptiveTemperatureService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdaptiveTemperatureService")
+            .field("inner", &self.inner)
+            .field("step", &self.step)
+            .field("min_temperature", &self.min_temperature)
+            .field("escalation", &self.escalation)
+            .finish()
+    }
+}
+
+impl<S: InferenceService> AdaptiveTemperatureService<S> {
+    /// Lower temperature by 0.2 per recorded failure, down to 0.0
+    pub fn new(inner: S, validator: impl AnswerValidator + 'static) -> Self {
+        Self {
+            inner,
+            validator: Box::new(validator),
+            step: 0.2,
+            min_temperature: 0.0,
+            escalation: None,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Temperature decrease per recorded failure
+    pub fn with_step(mut self, step: f32) -> Self {
+        self.step = step.max(0.0);
+        self
+    }
+
+    /// Lowest temperature an adjustment may reach
+    pub fn with_min_temperature(mut self, min_temperature: f32) -> Self {
+        self.min_temperature = min_temperature.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Switch to `model_type` once a template has `after_failures` recorded failures
+    pub fn with_escalation(mut self, after_failures: u32, model_type: ModelType) -> Self {
+        self.escalation = Some((after_failures.max(1), model_type));
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Recorded failures for a template name (or raw template text)
+    pub fn failures(&self, template: &str) -> u32 {
+        self.failures
+            .lock()
+            .unwrap()
+            .get(template)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Forget the failure history of a template
+    pub fn reset(&self, template: &str) {
+        self.failures.lock().unwrap().remove(template);
+    }
+
+    fn record(&self, key: &str, accepted: bool) {
+        let mut failures = self.failures.lock().unwrap();
+        if accepted {
+            if let Some(count) = failures.get_mut(key) {
+                *count -= 1;
+                if *count == 0 {
+                    failures.remove(key);
+                }
+            }
+        } else {
+            *failures.entry(key.to_string()).or_insert(0) += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for AdaptiveTemperatureService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let key = template_key(&request);
+        let failures = self.failures(&key);
+
+        let mut adjusted = request.clone();
+        let mut adjustments = Vec::new();
+        if failures > 0 {
+            let base = request.effective_temperature();
+            let temperature = (base - self.step * failures as f32).max(self.min_temperature);
+            if temperature < base {
+                adjusted.temperature = Some(temperature);
+                adjustments.push((ADAPTIVE_TEMPERATURE_METADATA_KEY, temperature.to_string()));
+            }
+            match self.escalation {
+                Some((after, model_type))
+                    if failures >= after && request.model_type != model_type =>
+                {
+                    adjusted.model_type = model_type;
+                    adjusted.model_override = None;
+                    adjustments.push((ADAPTIVE_MODEL_TYPE_METADATA_KEY, format!("{model_type:?}")));
+                }
+                _ => {}
+            }
+        }
+
+        let mut response = self.inner.infer(adjusted).await?;
+        let accepted = self.validator.accept(&request, &response);
+        self.record(&key, accepted);
+        if !accepted {
+            return Err(inference_errors::response_validation_failed(key));
+        }
+
+        if !adjustments.is_empty() {
+            adjustments.push((VALIDATION_FAILURES_METADATA_KEY, failures.to_string()));
+        }
+        for (metadata_key, value) in adjustments {
+            response.metadata = response.metadata.with_metadata(metadata_key, value);
+        }
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Extract {{text}}", HashMap::new(), ModelType::Fast)
+            .with_metadata("template_name", "extract")
+            .with_temperature(0.8)
+    }
+
+    fn metadata<'a>(response: &'a InferenceResponse, key: &str) -> Option<&'a str> {
+        response.metadata.metadata.get(key).map(String::as_str)
+    }
+
+    #[tokio::test]
+    async fn test_failures_lower_temperature_and_escalate() {
+        let valid = Arc::new(AtomicBool::new(false));
+        let validator = {
+            let valid = Arc::clone(&valid);
+            move |_: &InferenceRequest, _: &InferenceResponse| valid.load(Ordering::SeqCst)
+        };
+        let service =
+            AdaptiveTemperatureService::new(MockInferenceService::new().with_latency(0), validator)
+                .with_step(0.3)
+                .with_escalation(2, ModelType::Reasoning);
+
+        assert!(service.infer(request()).await.is_err());
+        assert!(service.infer(request()).await.is_err());
+        assert_eq!(service.failures("extract"), 2);
+
+        valid.store(true, Ordering::SeqCst);
+        let response = service.infer(request()).await.unwrap();
+        let temperature: f32 = metadata(&response, ADAPTIVE_TEMPERATURE_METADATA_KEY)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((temperature - 0.2).abs() < 1e-6);
+        assert_eq!(
+            metadata(&response, ADAPTIVE_MODEL_TYPE_METADATA_KEY),
+            Some("Reasoning")
+        );
+        assert_eq!(
+            response.metadata.model,
+            ModelType::Reasoning.optimal_openai_model()
+        );
+        assert_eq!(
+            metadata(&response, VALIDATION_FAILURES_METADATA_KEY),
+            Some("2")
+        );
+
+        // Successes wear the adjustment off
+        assert_eq!(service.failures("extract"), 1);
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(metadata(&response, ADAPTIVE_MODEL_TYPE_METADATA_KEY), None);
+        assert_eq!(service.failures("extract"), 0);
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(metadata(&response, ADAPTIVE_TEMPERATURE_METADATA_KEY), None);
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_tracked_per_template() {
+        let service = AdaptiveTemperatureService::new(
+            MockInferenceService::new().with_latency(0),
+            |request: &InferenceRequest, _: &InferenceResponse| {
+                !request.metadata.contains_key("template_name")
+            },
+        );
+
+        assert!(service.infer(request()).await.is_err());
+        let other = InferenceRequest::new("Translate {{text}}", HashMap::new(), ModelType::Fast);
+        let response = service.infer(other).await.unwrap();
+        assert_eq!(metadata(&response, ADAPTIVE_TEMPERATURE_METADATA_KEY), None);
+        assert_eq!(service.failures("extract"), 1);
+        assert_eq!(service.failures("Translate {{text}}"), 0);
+
+        service.reset("extract");
+        assert_eq!(service.failures("extract"), 0);
+    }
+}
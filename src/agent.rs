@@ -0,0 +1,560 @@
+//! Agent loop with tool execution
+//!
+//! `AgentRunner` drives a model through a task that needs tools. Every iteration sends the task,
+//! the registered tools and the results of the calls made so far; a response whose content is a
+//! JSON object with a `tool_calls` array asks for tools to run, anything else is the final
+//! answer:
+//!
+//! ```json
+//! {"tool_calls": [{"id": "call_1", "name": "weather", "arguments": {"city": "Oslo"}}]}
+//! ```
+//!
+//! Tools implement `Tool`, or wrap an async closure in a `FnTool`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::agent::*;
+//! # use serde_json::json;
+//! # async fn example(openai: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! let weather = FnTool::new(
+//!     ToolDefinition::new("weather", "Current weather in a city")
+//!         .with_parameters(json!({"type": "object", "properties": {"city": {"type": "string"}}})),
+//!     |arguments: serde_json::Value| async move { Ok(json!({"celsius": 21})) },
+//! );
+//! let run = AgentRunner::new(openai).with_tool(weather).with_max_iterations(5).run(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The calls of one response run concurrently, at most `max_parallel_tools` at a time, and their
+//! results are fed back in the order the model listed the calls, whichever finishes first.
+//! Unknown tools and failing tools do not end the run: the error goes back to the model as the
+//! call's result so it can recover. The run stops on a final answer, after `max_iterations`
+//! model calls, or once the tokens used by the run reach `max_tokens`.
+
+use crate::ensemble::{BoxedFuture, JoinAll};
+use crate::*;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Iterations allowed by default
+pub const DEFAULT_MAX_ITERATIONS: usize = 10;
+/// Tool calls of one response run at once by default
+pub const DEFAULT_MAX_PARALLEL_TOOLS: usize = 4;
+
+const AGENT_INSTRUCTIONS: &str = "You can use the tools listed below. To call tools, answer \
+with only a JSON object of the form {\"tool_calls\": [{\"id\": \"call_1\", \"name\": \
+\"<tool>\", \"arguments\": {...}}]}; their results are sent back to you. When you can answer \
+the task, answer it directly without calling tools.";
+
+/// Tool invocation requested by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Identifier of the call within the run, assigned when the model omits it
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+impl ToolCall {
+    pub fn new(
+        id: impl Into<String>,
+        name: impl Into<String>,
+        arguments: serde_json::Value,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            arguments,
+        }
+    }
+
+    /// Tool calls requested by response content, `None` when the content is a final answer
+    pub fn parse_all(content: &serde_json::Value) -> Option<Vec<ToolCall>> {
+        let calls = content.get("tool_calls")?.as_array()?;
+        let mut parsed = Vec::with_capacity(calls.len());
+        for (index, call) in calls.iter().enumerate() {
+            let mut call: ToolCall = serde_json::from_value(call.clone()).ok()?;
+            if call.id.is_empty() {
+                call.id = format!("call_{}", index + 1);
+            }
+            parsed.push(call);
+        }
+        Some(parsed)
+    }
+}
+
+/// Name, purpose and JSON-schema arguments of a tool, as shown to the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments object
+    pub parameters: serde_json::Value,
+}
+
+impl ToolDefinition {
+    /// Tool taking no arguments
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters: serde_json::json!({"type": "object", "properties": {}}),
+        }
+    }
+
+    pub fn with_parameters(mut self, schema: serde_json::Value) -> Self {
+        self.parameters = schema;
+        self
+    }
+}
+
+/// Capability the agent can invoke
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn definition(&self) -> ToolDefinition;
+
+    /// Run the tool with the arguments chosen by the model
+    async fn call(&self, arguments: serde_json::Value) -> InferenceResult<serde_json::Value>;
+}
+
+/// Tool backed by an async closure `Fn(serde_json::Value) -> impl Future<Output =
+/// InferenceResult<serde_json::Value>>`
+pub struct FnTool<F> {
+    definition: ToolDefinition,
+    handler: F,
+}
+
+impl<F> std::fmt::Debug for FnTool<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnTool")
+            .field("definition", &self.definition)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> FnTool<F> {
+    pub fn new(definition: ToolDefinition, handler: F) -> Self {
+        Self {
+            definition,
+            handler,
+        }
+    }
+}
+
+#[async_trait]
+impl<F, Fut> Tool for FnTool<F>
+where
+    F: Fn(serde_json::Value) -> Fut + Send + Sync,
+    Fut: Future<Output = InferenceResult<serde_json::Value>> + Send,
+{
+    fn definition(&self) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    async fn call(&self, arguments: serde_json::Value) -> InferenceResult<serde_json::Value> {
+        (self.handler)(arguments).await
+    }
+}
+
+/// Outcome of one tool call, as fed back to the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    pub call: ToolCall,
+    /// Tool output, or `{"error": "..."}` when the call failed
+    pub output: serde_json::Value,
+    pub is_error: bool,
+}
+
+/// Why an agent run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentStopReason {
+    /// The model answered without calling tools
+    FinalAnswer,
+    /// `max_iterations` model calls were made
+    MaxIterations,
+    /// The run's token usage reached `max_tokens`
+    TokenLimit,
+}
+
+/// Result of `AgentRunner::run`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentRun {
+    /// Content of the last response: the final answer, or the pending tool calls when a limit
+    /// stopped the run
+    pub answer: serde_json::Value,
+    pub stop_reason: AgentStopReason,
+    /// Every tool call made, in order
+    pub steps: Vec<ToolResult>,
+    /// Model calls made
+    pub iterations: usize,
+    /// Tokens used by all model calls
+    pub token_usage: TokenUsage,
+}
+
+/// Runs the call-tools-and-feed-back loop of an agent against an inference service
+pub struct AgentRunner<S> {
+    service: S,
+    tools: Vec<Arc<dyn Tool>>,
+    max_iterations: usize,
+    max_tokens: Option<usize>,
+    max_parallel_tools: usize,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for AgentRunner<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tools: Vec<String> = self
+            .tools
+            .iter()
+            .map(|tool| tool.definition().name)
+            .collect();
+        f.debug_struct("AgentRunner")
+            .field("service", &self.service)
+            .field("tools", &tools)
+            .field("max_iterations", &self.max_iterations)
+            .field("max_tokens", &self.max_tokens)
+            .field("max_parallel_tools", &self.max_parallel_tools)
+            .finish()
+    }
+}
+
+impl<S: InferenceService> AgentRunner<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            tools: Vec::new(),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_tokens: None,
+            max_parallel_tools: DEFAULT_MAX_PARALLEL_TOOLS,
+        }
+    }
+
+    /// Register a tool, replacing any tool of the same name
+    pub fn with_tool(mut self, tool: impl Tool + 'static) -> Self {
+        let name = tool.definition().name;
+        self.tools
+            .retain(|existing| existing.definition().name != name);
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    /// Model calls allowed per run (at least one)
+    pub fn with_max_iterations(mut self, iterations: usize) -> Self {
+        self.max_iterations = iterations.max(1);
+        self
+    }
+
+    /// Stop once the run's model calls used this many tokens
+    pub fn with_max_tokens(mut self, tokens: usize) -> Self {
+        self.max_tokens = Some(tokens);
+        self
+    }
+
+    /// Tool calls of one response run concurrently (at least one; one runs them in sequence)
+    pub fn with_max_parallel_tools(mut self, parallelism: usize) -> Self {
+        self.max_parallel_tools = parallelism.max(1);
+        self
+    }
+
+    pub fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.iter().map(|tool| tool.definition()).collect()
+    }
+
+    /// Get the wrapped service
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+
+    /// Run the agent on `request`, whose rendered template is the task
+    pub async fn run(&self, request: InferenceRequest) -> InferenceResult<AgentRun> {
+        let task = request.render_template();
+        let mut steps = Vec::new();
+        let mut usage = TokenUsage::new(0, 0);
+        let mut iterations = 0;
+        loop {
+            let mut step_request = request.clone();
+            step_request.template = self.prompt(&task, &steps);
+            step_request.parameters.clear();
+            let response = self.service.infer(step_request).await?;
+            iterations += 1;
+            usage = TokenUsage::new(
+                usage.prompt_tokens + response.metadata.token_usage.prompt_tokens,
+                usage.completion_tokens + response.metadata.token_usage.completion_tokens,
+            );
+
+            let finish = |stop_reason| AgentRun {
+                answer: response.content.clone(),
+                stop_reason,
+                steps: steps.clone(),
+                iterations,
+                token_usage: usage.clone(),
+            };
+            let Some(calls) = ToolCall::parse_all(&response.content) else {
+                return Ok(finish(AgentStopReason::FinalAnswer));
+            };
+            if iterations >= self.max_iterations {
+                return Ok(finish(AgentStopReason::MaxIterations));
+            }
+            if self
+                .max_tokens
+                .is_some_and(|max| usage.total_tokens as usize >= max)
+            {
+                return Ok(finish(AgentStopReason::TokenLimit));
+            }
+            for batch in calls.chunks(self.max_parallel_tools) {
+                let results = JoinAll::new(
+                    batch
+                        .iter()
+                        .map(|call| Box::pin(self.execute(call.clone())) as BoxedFuture<'_, _>),
+                )
+                .await;
+                steps.extend(results);
+            }
+        }
+    }
+
+    /// Run one tool call, turning failures into an error result
+    async fn execute(&self, call: ToolCall) -> ToolResult {
+        let tool = self
+            .tools
+            .iter()
+            .find(|tool| tool.definition().name == call.name);
+        let outcome = match tool {
+            Some(tool) => tool.call(call.arguments.clone()).await,
+            None => Err(TylError::validation(
+                "tool",
+                format!("Unknown tool: {}", call.name),
+            )),
+        };
+        match outcome {
+            Ok(output) => ToolResult {
+                call,
+                output,
+                is_error: false,
+            },
+            Err(error) => ToolResult {
+                call,
+                output: serde_json::json!({ "error": error.to_string() }),
+                is_error: true,
+            },
+        }
+    }
+
+    /// Prompt of the next iteration: instructions, tools, task and the results so far
+    fn prompt(&self, task: &str, steps: &[ToolResult]) -> String {
+        let mut prompt = format!("{AGENT_INSTRUCTIONS}\n\nTools:");
+        for definition in self.tool_definitions() {
+            prompt.push_str(&format!(
+                "\n- {}: {} Arguments: {}",
+                definition.name, definition.description, definition.parameters
+            ));
+        }
+        prompt.push_str(&format!("\n\nTask:\n{task}"));
+        if !steps.is_empty() {
+            prompt.push_str("\n\nTool results:");
+            for step in steps {
+                prompt.push_str(&format!(
+                    "\n[{}] {}({}) -> {}",
+                    step.call.id, step.call.name, step.call.arguments, step.output
+                ));
+            }
+        }
+        prompt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Answers from a script, one entry per call, recording the prompts
+    struct Scripted {
+        answers: Vec<serde_json::Value>,
+        calls: AtomicUsize,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl Scripted {
+        fn new(answers: Vec<serde_json::Value>) -> Self {
+            Self {
+                answers,
+                calls: AtomicUsize::new(0),
+                prompts: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl InferenceService for Scripted {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            self.prompts.lock().unwrap().push(request.render_template());
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(InferenceResponse::new(
+                self.answers[call.min(self.answers.len() - 1)].clone(),
+                ResponseMetadata::new("gpt-4o".to_string(), TokenUsage::new(10, 5), 1),
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["gpt-4o".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn weather() -> impl Tool {
+        FnTool::new(
+            ToolDefinition::new("weather", "Current weather in a city.").with_parameters(
+                serde_json::json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+            ),
+            |arguments: serde_json::Value| async move {
+                match arguments["city"].as_str() {
+                    Some(city) => Ok(serde_json::json!({"city": city, "celsius": 21})),
+                    None => Err(TylError::validation("city", "missing")),
+                }
+            },
+        )
+    }
+
+    fn request() -> InferenceRequest {
+        let mut parameters = HashMap::new();
+        parameters.insert("city".to_string(), "Oslo".to_string());
+        InferenceRequest::new("Is it warm in {{city}}?", parameters, ModelType::General)
+    }
+
+    #[test]
+    fn test_parse_tool_calls() {
+        let content = serde_json::json!({"tool_calls": [
+            {"name": "weather", "arguments": {"city": "Oslo"}},
+            {"id": "x", "name": "clock"},
+        ]});
+        let calls = ToolCall::parse_all(&content).unwrap();
+        assert_eq!(
+            calls,
+            vec![
+                ToolCall::new("call_1", "weather", serde_json::json!({"city": "Oslo"})),
+                ToolCall::new("x", "clock", serde_json::Value::Null),
+            ]
+        );
+        assert!(ToolCall::parse_all(&serde_json::json!({"answer": "yes"})).is_none());
+        assert!(ToolCall::parse_all(&serde_json::json!("tool_calls")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_runs_tools_until_final_answer() {
+        let service = Scripted::new(vec![
+            serde_json::json!({"tool_calls": [
+                {"id": "a", "name": "weather", "arguments": {"city": "Oslo"}},
+                {"id": "b", "name": "stocks", "arguments": {}},
+            ]}),
+            serde_json::json!({"answer": "Yes, 21 degrees."}),
+        ]);
+        let runner = AgentRunner::new(service).with_tool(weather());
+        let run = runner.run(request()).await.unwrap();
+
+        assert_eq!(run.stop_reason, AgentStopReason::FinalAnswer);
+        assert_eq!(run.answer["answer"], "Yes, 21 degrees.");
+        assert_eq!(run.iterations, 2);
+        assert_eq!(run.token_usage, TokenUsage::new(20, 10));
+        assert_eq!(run.steps[0].output["celsius"], 21);
+        assert!(run.steps[1].is_error);
+        assert!(run.steps[1].output["error"]
+            .as_str()
+            .unwrap()
+            .contains("Unknown tool: stocks"));
+
+        let prompts = runner.service().prompts.lock().unwrap();
+        assert!(prompts[0].contains("- weather: Current weather in a city. Arguments: {"));
+        assert!(prompts[0].ends_with("Task:\nIs it warm in Oslo?"));
+        assert!(
+            prompts[1].contains(r#"[a] weather({"city":"Oslo"}) -> {"celsius":21,"city":"Oslo"}"#)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parallel_calls_keep_call_order() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (current, highest) = (in_flight.clone(), peak.clone());
+        // Later calls finish first
+        let slow = FnTool::new(
+            ToolDefinition::new("slow", "Sleeps."),
+            move |arguments: serde_json::Value| {
+                let (current, highest) = (current.clone(), highest.clone());
+                async move {
+                    let running = current.fetch_add(1, Ordering::SeqCst) + 1;
+                    highest.fetch_max(running, Ordering::SeqCst);
+                    let ms = arguments["ms"].as_u64().unwrap_or(0);
+                    tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+                    current.fetch_sub(1, Ordering::SeqCst);
+                    Ok(serde_json::json!(ms))
+                }
+            },
+        );
+        let service = Scripted::new(vec![
+            serde_json::json!({"tool_calls": [
+                {"name": "slow", "arguments": {"ms": 30}},
+                {"name": "slow", "arguments": {"ms": 20}},
+                {"name": "slow", "arguments": {"ms": 10}},
+            ]}),
+            serde_json::json!("done"),
+        ]);
+        let run = AgentRunner::new(service)
+            .with_tool(slow)
+            .with_max_parallel_tools(2)
+            .run(request())
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = run.steps.iter().map(|step| step.call.id.as_str()).collect();
+        assert_eq!(ids, vec!["call_1", "call_2", "call_3"]);
+        let outputs: Vec<&serde_json::Value> = run.steps.iter().map(|step| &step.output).collect();
+        assert_eq!(
+            outputs,
+            vec![
+                &serde_json::json!(30),
+                &serde_json::json!(20),
+                &serde_json::json!(10)
+            ]
+        );
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stops_on_limits() {
+        let looping = || {
+            Scripted::new(vec![serde_json::json!({"tool_calls": [
+                {"name": "weather", "arguments": {"city": "Oslo"}},
+            ]})])
+        };
+        let run = AgentRunner::new(looping())
+            .with_tool(weather())
+            .with_max_iterations(3)
+            .run(request())
+            .await
+            .unwrap();
+        assert_eq!(run.stop_reason, AgentStopReason::MaxIterations);
+        assert_eq!(run.iterations, 3);
+        assert_eq!(run.steps.len(), 2);
+
+        let run = AgentRunner::new(looping())
+            .with_tool(weather())
+            .with_max_tokens(30)
+            .run(request())
+            .await
+            .unwrap();
+        assert_eq!(run.stop_reason, AgentStopReason::TokenLimit);
+        assert_eq!(run.iterations, 2);
+    }
+}
@@ -0,0 +1,477 @@
+//! Adaptive concurrency limiting (AIMD)
+//!
+//! `AdaptiveConcurrencyService` limits in-flight requests like `ConcurrencyLimitedService`, but
+//! finds the limit by itself: every time as many requests as the current limit have completed
+//! in time, the limit grows by one (additive increase); a rate-limit error, a timeout or a
+//! response slower than the latency threshold cuts it by the decrease factor (multiplicative
+//! decrease). The limit settles just below what the provider sustains and follows it when the
+//! provider's capacity changes:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::aimd::*;
+//! # use std::time::Duration;
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let service = AdaptiveConcurrencyService::new(openai)
+//!     .with_limits(2, 64)
+//!     .with_latency_threshold(Duration::from_secs(20));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Failures of requests started before the last decrease do not decrease the limit again, so a
+//! burst of 429s for requests already in flight counts once. Other errors leave the limit
+//! unchanged. Requests beyond the limit wait for a free slot.
+//!
+//! A stream holds its slot until it ends or is dropped; it counts as slow when opening it took
+//! longer than the latency threshold.
+
+use crate::rate_limit::RateLimitError;
+use crate::*;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+struct LimiterState {
+    limit: usize,
+    in_flight: usize,
+    /// Requests completed in time since the limit last changed
+    healthy: usize,
+    /// Bumped on every decrease
+    generation: u64,
+    min_limit: usize,
+    max_limit: usize,
+    decrease_factor: f64,
+}
+
+/// Limit shared by the service and the slots of its requests and streams
+struct Limiter {
+    state: Mutex<LimiterState>,
+    released: Notify,
+}
+
+impl Limiter {
+    fn record(&self, generation: u64, outcome: Outcome) {
+        let mut state = self.state.lock().unwrap();
+        match outcome {
+            Outcome::Healthy => {
+                state.healthy += 1;
+                if state.healthy >= state.limit && state.limit < state.max_limit {
+                    state.limit += 1;
+                    state.healthy = 0;
+                    drop(state);
+                    self.released.notify_waiters();
+                }
+            }
+            Outcome::Overloaded if generation == state.generation => {
+                let decreased = (state.limit as f64 * state.decrease_factor) as usize;
+                state.limit = decreased.max(state.min_limit);
+                state.healthy = 0;
+                state.generation += 1;
+            }
+            Outcome::Overloaded | Outcome::Neutral => {}
+        }
+    }
+}
+
+/// How a completed request reflects on the provider's capacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Healthy,
+    Overloaded,
+    Neutral,
+}
+
+/// Releases a slot when the request finishes or is cancelled
+struct Slot {
+    limiter: Arc<Limiter>,
+    generation: u64,
+}
+
+impl Slot {
+    /// Feed the outcome of the request back into the limit, then release the slot
+    fn finish(self, outcome: Outcome) {
+        self.limiter.record(self.generation, outcome);
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().in_flight -= 1;
+        self.limiter.released.notify_waiters();
+    }
+}
+
+/// How a failed request reflects on the provider's capacity
+fn error_outcome(error: &TylError) -> Outcome {
+    if RateLimitError::from_error(error).is_some() || inference_errors::is_timeout(error) {
+        Outcome::Overloaded
+    } else {
+        Outcome::Neutral
+    }
+}
+
+/// Inference service decorator adapting its concurrency limit to the provider's throughput
+pub struct AdaptiveConcurrencyService<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+    latency_threshold: Duration,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for AdaptiveConcurrencyService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.limiter.state.lock().unwrap();
+        f.debug_struct("AdaptiveConcurrencyService")
+            .field("inner", &self.inner)
+            .field("limit", &state.limit)
+            .field("in_flight", &state.in_flight)
+            .field("min_limit", &state.min_limit)
+            .field("max_limit", &state.max_limit)
+            .field("latency_threshold", &self.latency_threshold)
+            .field("decrease_factor", &state.decrease_factor)
+            .finish()
+    }
+}
+
+impl<S: InferenceService> AdaptiveConcurrencyService<S> {
+    /// Start at 4 in-flight requests, adapting between 1 and 256 with a 30s latency threshold
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            limiter: Arc::new(Limiter {
+                state: Mutex::new(LimiterState {
+                    limit: 4,
+                    in_flight: 0,
+                    healthy: 0,
+                    generation: 0,
+                    min_limit: 1,
+                    max_limit: 256,
+                    decrease_factor: 0.5,
+                }),
+                released: Notify::new(),
+            }),
+            latency_threshold: Duration::from_secs(30),
+        }
+    }
+
+    /// Limit before any feedback, kept within the bounds
+    pub fn with_initial_limit(self, limit: usize) -> Self {
+        {
+            let mut state = self.limiter.state.lock().unwrap();
+            state.limit = limit.clamp(state.min_limit, state.max_limit);
+        }
+        self
+    }
+
+    /// Bounds of the limit (`min` is at least 1)
+    pub fn with_limits(self, min: usize, max: usize) -> Self {
+        {
+            let mut state = self.limiter.state.lock().unwrap();
+            state.min_limit = min.max(1);
+            state.max_limit = max.max(state.min_limit);
+            state.limit = state.limit.clamp(state.min_limit, state.max_limit);
+        }
+        self
+    }
+
+    /// Responses slower than `threshold` count as overload
+    pub fn with_latency_threshold(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = threshold;
+        self
+    }
+
+    /// Factor the limit is multiplied by on overload (clamped to 0.1..=0.9, 0.5 by default)
+    pub fn with_decrease_factor(self, factor: f64) -> Self {
+        self.limiter.state.lock().unwrap().decrease_factor = factor.clamp(0.1, 0.9);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Current concurrency limit
+    pub fn limit(&self) -> usize {
+        self.limiter.state.lock().unwrap().limit
+    }
+
+    /// Number of requests currently being processed
+    pub fn in_flight(&self) -> usize {
+        self.limiter.state.lock().unwrap().in_flight
+    }
+
+    async fn acquire(&self) -> Slot {
+        loop {
+            // Registered before the check so that a release right after it still wakes us
+            let released = self.limiter.released.notified();
+            {
+                let mut state = self.limiter.state.lock().unwrap();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return Slot {
+                        limiter: Arc::clone(&self.limiter),
+                        generation: state.generation,
+                    };
+                }
+            }
+            released.await;
+        }
+    }
+
+    fn outcome<T>(&self, result: &InferenceResult<T>, latency: Duration) -> Outcome {
+        match result {
+            Ok(_) if latency > self.latency_threshold => Outcome::Overloaded,
+            Ok(_) => Outcome::Healthy,
+            Err(error) => error_outcome(error),
+        }
+    }
+}
+
+/// `InferenceLayer` wrapping services in an `AdaptiveConcurrencyService`; every wrapped service
+/// adapts its own limit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AdaptiveConcurrencyLayer {
+    initial_limit: Option<usize>,
+    limits: Option<(usize, usize)>,
+    latency_threshold: Option<Duration>,
+}
+
+impl AdaptiveConcurrencyLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_initial_limit(mut self, limit: usize) -> Self {
+        self.initial_limit = Some(limit);
+        self
+    }
+
+    pub fn with_limits(mut self, min: usize, max: usize) -> Self {
+        self.limits = Some((min, max));
+        self
+    }
+
+    pub fn with_latency_threshold(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = Some(threshold);
+        self
+    }
+}
+
+impl<S: InferenceService> InferenceLayer<S> for AdaptiveConcurrencyLayer {
+    type Service = AdaptiveConcurrencyService<S>;
+
+    fn layer(&self, inner: S) -> AdaptiveConcurrencyService<S> {
+        let mut service = AdaptiveConcurrencyService::new(inner);
+        if let Some((min, max)) = self.limits {
+            service = service.with_limits(min, max);
+        }
+        if let Some(limit) = self.initial_limit {
+            service = service.with_initial_limit(limit);
+        }
+        if let Some(threshold) = self.latency_threshold {
+            service = service.with_latency_threshold(threshold);
+        }
+        service
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for AdaptiveConcurrencyService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let slot = self.acquire().await;
+        let started = Instant::now();
+        let result = self.inner.infer(request).await;
+        slot.finish(self.outcome(&result, started.elapsed()));
+        result
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let slot = self.acquire().await;
+        let started = Instant::now();
+        let result = self.inner.infer_stream(request).await;
+        let opened = self.outcome(&result, started.elapsed());
+        let stream = match result {
+            Ok(stream) => stream,
+            Err(error) => {
+                slot.finish(opened);
+                return Err(error);
+            }
+        };
+        Ok(stream.on_end(move |end| {
+            let outcome = match end {
+                StreamEnd::Completed(_) => opened,
+                StreamEnd::Failed(error) => error_outcome(error),
+                StreamEnd::Dropped(_) => Outcome::Neutral,
+            };
+            slot.finish(outcome);
+        }))
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        Ok(self
+            .inner
+            .health_check()
+            .await?
+            .with_metadata("in_flight", serde_json::Value::from(self.in_flight()))
+            .with_metadata("concurrency_limit", serde_json::Value::from(self.limit())))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Answers after `delay`, rate-limited while `throttled`, tracking peak concurrency
+    #[derive(Default)]
+    struct Provider {
+        delay: Duration,
+        throttled: AtomicBool,
+        active: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl InferenceService for Provider {
+        async fn infer(&self, _request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(active, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            if self.throttled.load(Ordering::SeqCst) {
+                return Err(RateLimitError::new("provider").into());
+            }
+            Ok(InferenceResponse::new(
+                serde_json::json!("ok"),
+                ResponseMetadata::new("provider-1".to_string(), TokenUsage::new(3, 1), 1),
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["provider-1".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast)
+    }
+
+    #[tokio::test]
+    async fn test_additive_increase() {
+        let service = AdaptiveConcurrencyService::new(Provider::default())
+            .with_limits(1, 4)
+            .with_initial_limit(2);
+        // Two completions grow the limit to 3, three more to 4, which is the maximum
+        for expected in [2, 2, 3, 3, 3, 4, 4, 4] {
+            assert_eq!(service.limit(), expected);
+            service.infer(request()).await.unwrap();
+        }
+        assert_eq!(service.in_flight(), 0);
+
+        // Slow responses count as overload
+        let slow = AdaptiveConcurrencyService::new(Provider {
+            delay: Duration::from_millis(20),
+            ..Provider::default()
+        })
+        .with_latency_threshold(Duration::from_millis(5));
+        slow.infer(request()).await.unwrap();
+        assert_eq!(slow.limit(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_multiplicative_decrease_and_limit() {
+        let service = AdaptiveConcurrencyService::new(Provider {
+            delay: Duration::from_millis(20),
+            ..Provider::default()
+        })
+        .with_initial_limit(8);
+        service.inner().throttled.store(true, Ordering::SeqCst);
+
+        // Six throttled requests in flight at once only halve the limit once
+        let calls = futures::future::join_all((0..6).map(|_| service.infer(request())));
+        assert!(calls.await.iter().all(Result::is_err));
+        assert_eq!(service.limit(), 4);
+        assert_eq!(service.inner().peak.load(Ordering::SeqCst), 6);
+
+        // Later failures keep halving down to the minimum
+        for expected in [2, 1, 1] {
+            assert!(service.infer(request()).await.is_err());
+            assert_eq!(service.limit(), expected);
+        }
+
+        // Requests beyond the limit wait for a free slot
+        service.inner().throttled.store(false, Ordering::SeqCst);
+        service.inner().peak.store(0, Ordering::SeqCst);
+        futures::future::join_all((0..3).map(|_| service.infer(request()))).await;
+        assert!(service.inner().peak.load(Ordering::SeqCst) <= 2);
+
+        let health = service.health_check().await.unwrap();
+        assert_eq!(health.metadata["concurrency_limit"], service.limit());
+        assert_eq!(health.metadata["in_flight"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_holds_its_slot() {
+        let service = AdaptiveConcurrencyService::new(Provider::default())
+            .with_limits(1, 4)
+            .with_initial_limit(1);
+        let stream = service.infer_stream(request()).await.unwrap();
+        assert_eq!(service.in_flight(), 1);
+        assert_eq!(stream.assemble().await.unwrap().len(), 1);
+        assert_eq!(service.in_flight(), 0);
+        // The completed stream counted as healthy, growing the limit
+        assert_eq!(service.limit(), 2);
+
+        service.inner().throttled.store(true, Ordering::SeqCst);
+        assert!(service.infer_stream(request()).await.is_err());
+        assert_eq!(service.limit(), 1);
+    }
+
+    #[test]
+    fn test_error_outcomes() {
+        let timeout = inference_errors::request_timeout(Duration::from_secs(1));
+        assert_eq!(error_outcome(&timeout), Outcome::Overloaded);
+        let throttled = RateLimitError::new("provider").into();
+        assert_eq!(error_outcome(&throttled), Outcome::Overloaded);
+        // Only timeouts count, not messages that happen to mention one
+        let invalid = TylError::validation("template", "Prompt says: the job timed out");
+        assert_eq!(error_outcome(&invalid), Outcome::Neutral);
+    }
+}
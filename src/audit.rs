@@ -0,0 +1,401 @@
+//! Audit records of executed requests
+//!
+//! An `AuditRecord` captures one production exchange: the request as sent (template,
+//! parameters, model settings), its fingerprint, the rendered prompt, the end user, the
+//! response or error and its cost. Records are the input of `replay`, which re-runs them
+//! against new template versions.
+//!
+//! `AuditedService` appends a record for every request it serves to an `InferenceAuditSink`,
+//! for deployments that must retain every model interaction:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::audit::*;
+//! # use std::sync::Arc;
+//! # async fn example(openai: impl InferenceService + 'static, compliance_sink: impl InferenceAuditSink + 'static, request: InferenceRequest) -> TylResult<()> {
+//! let service = AuditedService::new(openai, Arc::new(compliance_sink));
+//! service.infer(request.with_metadata(USER_METADATA_KEY, "user-42")).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! An interaction that cannot be retained is not served: when the sink fails, the caller gets
+//! the sink's error instead of the response. `SqliteAuditSink` (feature `sqlite`) keeps the
+//! history in a local database file and answers `AuditQuery` filters.
+
+use crate::canonical::request_fingerprint;
+use crate::ledger::request_scope;
+use crate::pricing::{PricingTable, SharedPricing};
+use crate::*;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Request metadata key holding the end user the request was made for
+pub const USER_METADATA_KEY: &str = "user";
+
+/// One executed request and its outcome
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub id: Uuid,
+    pub recorded_at: DateTime<Utc>,
+    pub request: InferenceRequest,
+    /// `request_fingerprint` of the request
+    #[serde(default)]
+    pub request_hash: String,
+    /// Prompt as sent to the model
+    pub rendered_prompt: String,
+    /// End user from the request's `user` metadata
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Response, when the request succeeded
+    pub response: Option<InferenceResponse>,
+    /// Error message, when the request failed
+    pub error: Option<String>,
+    /// Cost in USD, `None` for failures and models without a known price
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+}
+
+impl AuditRecord {
+    /// Record the outcome of `request`
+    pub fn new(request: InferenceRequest, result: &InferenceResult<InferenceResponse>) -> Self {
+        let (response, error) = match result {
+            Ok(response) => (Some(response.clone()), None),
+            Err(error) => (None, Some(error.to_string())),
+        };
+        Self {
+            id: Uuid::new_v4(),
+            recorded_at: Utc::now(),
+            request_hash: request_fingerprint(&request),
+            rendered_prompt: request.render_template(),
+            user: request.metadata.get(USER_METADATA_KEY).cloned(),
+            request,
+            response,
+            error,
+            cost_usd: None,
+        }
+    }
+
+    pub fn with_cost(mut self, cost_usd: f64) -> Self {
+        self.cost_usd = Some(cost_usd);
+        self
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.response.is_some()
+    }
+
+    /// Tenant the request was made for, its ledger scope
+    pub fn tenant(&self) -> &str {
+        request_scope(&self.request)
+    }
+
+    /// Model that answered, else the model the request asked for (empty when neither is known)
+    pub fn model(&self) -> &str {
+        self.response
+            .as_ref()
+            .map(|response| response.metadata.model.as_str())
+            .or(self.request.model_override.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+/// Filter over audit records; unset criteria match every record
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditQuery {
+    /// Records with `recorded_at >= since`
+    pub since: Option<DateTime<Utc>>,
+    /// Records with `recorded_at < until`
+    pub until: Option<DateTime<Utc>>,
+    pub tenant: Option<String>,
+    pub model: Option<String>,
+    pub user: Option<String>,
+    /// At most this many records, the oldest first
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records with `since <= recorded_at < until`
+    pub fn between(mut self, since: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self.until = Some(until);
+        self
+    }
+
+    pub fn for_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    pub fn for_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn for_user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether `record` meets every criterion except the limit
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.since.map_or(true, |since| record.recorded_at >= since)
+            && self.until.map_or(true, |until| record.recorded_at < until)
+            && self
+                .tenant
+                .as_deref()
+                .map_or(true, |tenant| record.tenant() == tenant)
+            && self
+                .model
+                .as_deref()
+                .map_or(true, |model| record.model() == model)
+            && self
+                .user
+                .as_ref()
+                .map_or(true, |user| record.user.as_ref() == Some(user))
+    }
+}
+
+/// Destination of audit records
+#[async_trait]
+pub trait InferenceAuditSink: Send + Sync {
+    /// Durably append `record`
+    async fn append(&self, record: AuditRecord) -> InferenceResult<()>;
+}
+
+/// Process-local audit sink, for tests and short-lived tools
+#[derive(Debug, Default)]
+pub struct InMemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl InMemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appended records, oldest first
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Records matching `query`, oldest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditRecord> {
+        let mut records: Vec<AuditRecord> = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| query.matches(record))
+            .cloned()
+            .collect();
+        records.sort_by_key(|record| record.recorded_at);
+        records.truncate(query.limit.unwrap_or(usize::MAX));
+        records
+    }
+}
+
+#[async_trait]
+impl InferenceAuditSink for InMemoryAuditSink {
+    async fn append(&self, record: AuditRecord) -> InferenceResult<()> {
+        self.records.lock().unwrap().push(record);
+        Ok(())
+    }
+}
+
+/// Inference service decorator appending an `AuditRecord` for every request
+#[derive(Clone)]
+pub struct AuditedService<S> {
+    inner: S,
+    sink: Arc<dyn InferenceAuditSink>,
+    pricing: SharedPricing,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for AuditedService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditedService")
+            .field("inner", &self.inner)
+            .field("pricing", &self.pricing)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> AuditedService<S> {
+    /// Audit to `sink`, pricing responses with `PricingTable::with_defaults`
+    pub fn new(inner: S, sink: Arc<dyn InferenceAuditSink>) -> Self {
+        Self {
+            inner,
+            sink,
+            pricing: PricingTable::with_defaults().into(),
+        }
+    }
+
+    /// Price responses with a fixed table or a `SharedPricing` refreshed at runtime
+    pub fn with_pricing(mut self, pricing: impl Into<SharedPricing>) -> Self {
+        self.pricing = pricing.into();
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn sink(&self) -> &Arc<dyn InferenceAuditSink> {
+        &self.sink
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for AuditedService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let result = self.inner.infer(request.clone()).await;
+        let mut record = AuditRecord::new(request, &result);
+        if let Ok(response) = &result {
+            if let Some(cost) = self
+                .pricing
+                .cost(&response.metadata.model, &response.metadata.token_usage)
+            {
+                record = record.with_cost(cost);
+            }
+        }
+        self.sink.append(record).await?;
+        result
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers as `gpt-4o`, failing templates starting with "fail"
+    struct Model;
+
+    #[async_trait]
+    impl InferenceService for Model {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            if request.template.starts_with("fail") {
+                return Err(TylError::network("upstream unavailable"));
+            }
+            Ok(InferenceResponse::new(
+                serde_json::json!("ok"),
+                ResponseMetadata::new("gpt-4o".to_string(), TokenUsage::new(1000, 500), 1),
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["gpt-4o".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    /// Rejects every record
+    struct Unavailable;
+
+    #[async_trait]
+    impl InferenceAuditSink for Unavailable {
+        async fn append(&self, _record: AuditRecord) -> InferenceResult<()> {
+            Err(TylError::network("audit database unreachable"))
+        }
+    }
+
+    fn request(template: &str) -> InferenceRequest {
+        InferenceRequest::new(
+            template,
+            HashMap::from([("name".to_string(), "Ada".to_string())]),
+            ModelType::General,
+        )
+        .with_metadata(USER_METADATA_KEY, "user-42")
+    }
+
+    #[tokio::test]
+    async fn test_audits_every_interaction() {
+        let sink = Arc::new(InMemoryAuditSink::new());
+        let service = AuditedService::new(Model, sink.clone());
+
+        service.infer(request("Hello {{name}}")).await.unwrap();
+        let error = service.infer(request("fail {{name}}")).await.unwrap_err();
+        assert!(error.to_string().contains("upstream unavailable"));
+
+        let records = sink.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].rendered_prompt, "Hello Ada");
+        assert_eq!(records[0].user.as_deref(), Some("user-42"));
+        assert_eq!(
+            records[0].request_hash,
+            request_fingerprint(&request("Hello {{name}}"))
+        );
+        assert!(records[0].cost_usd.unwrap() > 0.0);
+        assert!(records[1].response.is_none());
+        assert!(records[1].error.as_deref().unwrap().contains("upstream"));
+        assert_eq!(records[1].cost_usd, None);
+
+        let query = AuditQuery::new().for_user("user-42").for_model("gpt-4o");
+        assert_eq!(sink.query(&query).len(), 1);
+        assert_eq!(
+            sink.query(&AuditQuery::new().for_tenant("default")).len(),
+            2
+        );
+        assert!(sink
+            .query(&AuditQuery::new().for_tenant("team-b"))
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sink_failure_withholds_the_response() {
+        let service = AuditedService::new(Model, Arc::new(Unavailable));
+        let error = service.infer(request("Hello {{name}}")).await.unwrap_err();
+        assert!(error.to_string().contains("audit database unreachable"));
+    }
+}
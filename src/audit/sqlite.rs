@@ -0,0 +1,267 @@
+//! SQLite audit sink (feature `sqlite`)
+//!
+//! Keeps the audit history in a local database file, so small deployments retain every
+//! interaction without running a database server. The full record is stored as JSON next to
+//! indexed columns for the time, tenant, model and user filters of an `AuditQuery`.
+//!
+//! The schema is versioned: `migrate()` applies the migrations the database has not seen yet
+//! and records them in `<table>_migrations`, so upgrading the crate upgrades existing history
+//! files in place.
+
+use super::{AuditQuery, AuditRecord, InferenceAuditSink};
+use crate::*;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::path::Path;
+
+const DEFAULT_TABLE: &str = "inference_audit";
+
+/// Schema migrations in order; `{table}` is replaced by the table name
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS {table} (
+        id TEXT PRIMARY KEY,
+        recorded_at INTEGER NOT NULL,
+        tenant TEXT NOT NULL,
+        model TEXT NOT NULL,
+        user_id TEXT,
+        request_hash TEXT NOT NULL,
+        success INTEGER NOT NULL,
+        cost_usd REAL,
+        record TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS {table}_recorded_at_idx ON {table} (recorded_at);
+    CREATE INDEX IF NOT EXISTS {table}_tenant_recorded_at_idx ON {table} (tenant, recorded_at);
+    CREATE INDEX IF NOT EXISTS {table}_model_recorded_at_idx ON {table} (model, recorded_at)",
+    "CREATE INDEX IF NOT EXISTS {table}_request_hash_idx ON {table} (request_hash)",
+];
+
+fn store_error(error: sqlx::Error) -> TylError {
+    inference_errors::audit_store_failed(error.to_string())
+}
+
+/// `InferenceAuditSink` backed by a SQLite table
+#[derive(Debug, Clone)]
+pub struct SqliteAuditSink {
+    pool: SqlitePool,
+    table: String,
+}
+
+impl SqliteAuditSink {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            table: DEFAULT_TABLE.to_string(),
+        }
+    }
+
+    /// Open (creating if needed) the database file at `path` and migrate its audit table
+    pub async fn open(path: impl AsRef<Path>) -> InferenceResult<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .map_err(store_error)?;
+        let sink = Self::new(pool);
+        sink.migrate().await?;
+        Ok(sink)
+    }
+
+    /// Use a custom table name (letters, digits, and underscores only)
+    pub fn with_table(mut self, table: impl Into<String>) -> InferenceResult<Self> {
+        self.table = valid_table_name("audit", table.into())?;
+        Ok(self)
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Number of migrations applied to the table
+    pub async fn schema_version(&self) -> InferenceResult<usize> {
+        let table = &self.table;
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {table}_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at INTEGER NOT NULL
+            )"
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        let version: i64 = sqlx::query(&format!(
+            "SELECT COALESCE(MAX(version), 0) AS version FROM {table}_migrations"
+        ))
+        .fetch_one(&self.pool)
+        .await
+        .and_then(|row| row.try_get("version"))
+        .map_err(store_error)?;
+        Ok(version as usize)
+    }
+
+    /// Apply the migrations the table has not seen yet, returning the new schema version
+    pub async fn migrate(&self) -> InferenceResult<usize> {
+        let applied = self.schema_version().await?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            let mut transaction = self.pool.begin().await.map_err(store_error)?;
+            sqlx::raw_sql(&migration.replace("{table}", &self.table))
+                .execute(&mut *transaction)
+                .await
+                .map_err(store_error)?;
+            sqlx::query(&format!(
+                "INSERT INTO {}_migrations (version, applied_at) VALUES (?, ?)",
+                self.table
+            ))
+            .bind(index as i64 + 1)
+            .bind(Utc::now().timestamp_millis())
+            .execute(&mut *transaction)
+            .await
+            .map_err(store_error)?;
+            transaction.commit().await.map_err(store_error)?;
+        }
+        Ok(MIGRATIONS.len())
+    }
+
+    /// Records matching `query`, oldest first
+    pub async fn query(&self, query: &AuditQuery) -> InferenceResult<Vec<AuditRecord>> {
+        let rows = sqlx::query(&format!(
+            "SELECT record FROM {} \
+             WHERE (?1 IS NULL OR recorded_at >= ?1) AND (?2 IS NULL OR recorded_at < ?2) \
+               AND (?3 IS NULL OR tenant = ?3) AND (?4 IS NULL OR model = ?4) \
+               AND (?5 IS NULL OR user_id = ?5) \
+             ORDER BY recorded_at LIMIT ?6",
+            self.table
+        ))
+        .bind(query.since.map(|since| since.timestamp_millis()))
+        .bind(query.until.map(|until| until.timestamp_millis()))
+        .bind(&query.tenant)
+        .bind(&query.model)
+        .bind(&query.user)
+        .bind(query.limit.map_or(-1, |limit| limit as i64))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        rows.iter().map(Self::record_from_row).collect()
+    }
+
+    /// Records of `tenant` with `since <= recorded_at < until`, oldest first
+    pub async fn tenant_history(
+        &self,
+        tenant: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<Vec<AuditRecord>> {
+        self.query(&AuditQuery::new().between(since, until).for_tenant(tenant))
+            .await
+    }
+
+    /// Records answered by (or asked of) `model` with `since <= recorded_at < until`
+    pub async fn model_history(
+        &self,
+        model: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<Vec<AuditRecord>> {
+        self.query(&AuditQuery::new().between(since, until).for_model(model))
+            .await
+    }
+
+    fn record_from_row(row: &SqliteRow) -> InferenceResult<AuditRecord> {
+        let record: String = row.try_get("record").map_err(store_error)?;
+        serde_json::from_str(&record).map_err(|e| {
+            inference_errors::audit_store_failed(format!("Invalid stored audit record: {e}"))
+        })
+    }
+}
+
+#[async_trait]
+impl InferenceAuditSink for SqliteAuditSink {
+    async fn append(&self, record: AuditRecord) -> InferenceResult<()> {
+        let json = serde_json::to_string(&record).map_err(|e| {
+            inference_errors::audit_store_failed(format!("Failed to encode audit record: {e}"))
+        })?;
+
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, recorded_at, tenant, model, user_id, request_hash, success, \
+             cost_usd, record) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (id) DO NOTHING",
+            self.table
+        ))
+        .bind(record.id.to_string())
+        .bind(record.recorded_at.timestamp_millis())
+        .bind(record.tenant())
+        .bind(record.model())
+        .bind(&record.user)
+        .bind(&record.request_hash)
+        .bind(record.is_success())
+        .bind(record.cost_usd)
+        .bind(json)
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::USER_METADATA_KEY;
+    use crate::ledger::SCOPE_METADATA_KEY;
+
+    fn record(tenant: &str, model: &str, minutes_ago: i64) -> AuditRecord {
+        let request = InferenceRequest::new("Classify {{ticket}}", HashMap::new(), ModelType::Fast)
+            .with_metadata(SCOPE_METADATA_KEY, tenant)
+            .with_metadata(USER_METADATA_KEY, "user-1");
+        let response = InferenceResponse::new(
+            serde_json::json!("billing"),
+            ResponseMetadata::new(model.to_string(), TokenUsage::new(20, 2), 5),
+        );
+        let mut record = AuditRecord::new(request, &Ok(response)).with_cost(0.002);
+        record.recorded_at = Utc::now() - chrono::Duration::minutes(minutes_ago);
+        record
+    }
+
+    #[tokio::test]
+    async fn test_history_queries() {
+        let path = std::env::temp_dir().join(format!("tyl-audit-{}.db", uuid::Uuid::new_v4()));
+        let sink = SqliteAuditSink::open(&path).await.unwrap();
+        assert_eq!(sink.schema_version().await.unwrap(), MIGRATIONS.len());
+        // Migrating again is a no-op
+        assert_eq!(sink.migrate().await.unwrap(), MIGRATIONS.len());
+
+        let first = record("team-a", "gpt-4o", 90);
+        sink.append(first.clone()).await.unwrap();
+        sink.append(first.clone()).await.unwrap();
+        sink.append(record("team-a", "gpt-4o-mini", 30))
+            .await
+            .unwrap();
+        sink.append(record("team-b", "gpt-4o", 10)).await.unwrap();
+
+        let now = Utc::now();
+        let hour_ago = now - chrono::Duration::hours(1);
+        let recent = sink.tenant_history("team-a", hour_ago, now).await.unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].model(), "gpt-4o-mini");
+
+        let gpt4o = sink
+            .model_history("gpt-4o", now - chrono::Duration::days(1), now)
+            .await
+            .unwrap();
+        assert_eq!(gpt4o.len(), 2);
+        assert_eq!(gpt4o[0].id, first.id);
+        assert_eq!(gpt4o[0].cost_usd, Some(0.002));
+        assert_eq!(gpt4o[0].user.as_deref(), Some("user-1"));
+
+        let all = AuditQuery::new().for_user("user-1");
+        assert_eq!(sink.query(&all).await.unwrap().len(), 3);
+        let limited = sink.query(&all.with_limit(1)).await.unwrap();
+        assert_eq!(limited[0].id, first.id);
+
+        sink.pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+}
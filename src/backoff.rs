@@ -0,0 +1,227 @@
+//! Backoff policies for retries
+//!
+//! A `BackoffPolicy` decides how long to wait before each retry. Providers differ in how they
+//! recover from overload, so the curve is picked per wrapped service:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::backoff::*;
+//! # use std::time::Duration;
+//! # #[cfg(feature = "decorators")]
+//! # async fn example(openai: impl InferenceService + 'static, ollama: impl InferenceService + 'static) -> TylResult<()> {
+//! # use tyl_llm_inference_port::retry::*;
+//! let (base, max) = (Duration::from_millis(250), Duration::from_secs(20));
+//! let openai = RetryService::new(openai).with_backoff(DecorrelatedJitterBackoff::new(base, max));
+//! let ollama = RetryService::new(ollama).with_backoff(FixedBackoff::new(Duration::from_secs(1)));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Jittered policies spread the retries of many clients failing at the same moment, so they do
+//! not hit the provider again in lockstep. Closures `Fn(u32, Duration) -> Duration` are policies
+//! too.
+
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Wait before a retry
+pub trait BackoffPolicy: Send + Sync {
+    /// Wait before retry `retry` (1 for the first retry), `previous` being the last wait
+    /// (zero before the first retry)
+    fn delay(&self, retry: u32, previous: Duration) -> Duration;
+}
+
+impl<F> BackoffPolicy for F
+where
+    F: Fn(u32, Duration) -> Duration + Send + Sync,
+{
+    fn delay(&self, retry: u32, previous: Duration) -> Duration {
+        (self)(retry, previous)
+    }
+}
+
+/// Uniform random value in `[0, 1)`
+fn random_fraction() -> f64 {
+    // The low 62 bits of a v4 UUID are random
+    (Uuid::new_v4().as_u128() as u64 & ((1 << 53) - 1)) as f64 / (1u64 << 53) as f64
+}
+
+/// Uniform random duration between `low` and `high`
+fn random_between(low: Duration, high: Duration) -> Duration {
+    if high <= low {
+        return low;
+    }
+    low + (high - low).mul_f64(random_fraction())
+}
+
+/// `base * 2^(retry - 1)` up to `max`, by default with full jitter (a random wait between zero
+/// and that value)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExponentialBackoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub jitter: bool,
+}
+
+impl ExponentialBackoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            jitter: true,
+        }
+    }
+
+    /// Wait exactly `base * 2^(retry - 1)`
+    pub fn without_jitter(mut self) -> Self {
+        self.jitter = false;
+        self
+    }
+}
+
+impl Default for ExponentialBackoff {
+    /// From 200ms up to 10s, with jitter
+    fn default() -> Self {
+        Self::new(Duration::from_millis(200), Duration::from_secs(10))
+    }
+}
+
+impl BackoffPolicy for ExponentialBackoff {
+    fn delay(&self, retry: u32, _previous: Duration) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max);
+        if self.jitter {
+            random_between(Duration::ZERO, delay)
+        } else {
+            delay
+        }
+    }
+}
+
+/// AWS-style decorrelated jitter: a random wait between `base` and three times the previous
+/// one, up to `max`
+///
+/// Waits grow like exponential backoff on average without clients staying synchronized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecorrelatedJitterBackoff {
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl DecorrelatedJitterBackoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+}
+
+impl BackoffPolicy for DecorrelatedJitterBackoff {
+    fn delay(&self, _retry: u32, previous: Duration) -> Duration {
+        let high = previous.max(self.base).saturating_mul(3);
+        random_between(self.base, high).min(self.max)
+    }
+}
+
+/// The same wait before every retry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedBackoff {
+    pub delay: Duration,
+}
+
+impl FixedBackoff {
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+}
+
+impl BackoffPolicy for FixedBackoff {
+    fn delay(&self, _retry: u32, _previous: Duration) -> Duration {
+        self.delay
+    }
+}
+
+/// `base` times the Fibonacci sequence (1, 1, 2, 3, 5, ...) up to `max`
+///
+/// Grows slower than doubling, for providers that recover within a few seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FibonacciBackoff {
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl FibonacciBackoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+}
+
+impl BackoffPolicy for FibonacciBackoff {
+    fn delay(&self, retry: u32, _previous: Duration) -> Duration {
+        let (mut current, mut next) = (1u32, 1u32);
+        for _ in 1..retry {
+            (current, next) = (next, current.saturating_add(next));
+        }
+        self.base.saturating_mul(current).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delays(policy: &dyn BackoffPolicy, retries: u32) -> Vec<Duration> {
+        let mut previous = Duration::ZERO;
+        (1..=retries)
+            .map(|retry| {
+                previous = policy.delay(retry, previous);
+                previous
+            })
+            .collect()
+    }
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().copied().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn test_deterministic_policies() {
+        let max = Duration::from_millis(700);
+        let base = Duration::from_millis(100);
+        assert_eq!(
+            delays(&ExponentialBackoff::new(base, max).without_jitter(), 5),
+            ms(&[100, 200, 400, 700, 700])
+        );
+        assert_eq!(
+            delays(&FibonacciBackoff::new(base, max), 7),
+            ms(&[100, 100, 200, 300, 500, 700, 700])
+        );
+        assert_eq!(delays(&FixedBackoff::new(base), 3), ms(&[100, 100, 100]));
+        let linear = |retry: u32, _: Duration| Duration::from_millis(10 * retry as u64);
+        assert_eq!(delays(&linear, 3), ms(&[10, 20, 30]));
+    }
+
+    #[test]
+    fn test_jittered_policies_stay_in_bounds() {
+        let base = Duration::from_millis(100);
+        let max = Duration::from_secs(2);
+        let exponential = ExponentialBackoff::new(base, max);
+        let decorrelated = DecorrelatedJitterBackoff::new(base, max);
+        for _ in 0..100 {
+            for (retry, delay) in (1..).zip(delays(&exponential, 6)) {
+                let ceiling = (base * 2u32.pow(retry - 1)).min(max);
+                assert!(delay <= ceiling);
+            }
+
+            let mut previous = Duration::ZERO;
+            for delay in delays(&decorrelated, 6) {
+                assert!(delay >= base && delay <= max);
+                assert!(delay <= previous.max(base) * 3);
+                previous = delay;
+            }
+        }
+        // Full jitter is not a constant
+        let samples: Vec<_> = (0..20).map(|_| exponential.delay(4, base)).collect();
+        assert!(samples.iter().any(|delay| *delay != samples[0]));
+    }
+}
@@ -0,0 +1,709 @@
+//! Offline batch jobs
+//!
+//! `BatchJobService` is the port for provider batch APIs: a whole set of requests is submitted
+//! at once, the provider works through it within a completion window (hours rather than
+//! seconds) and the results are collected afterwards, at a fraction of the realtime price.
+//! Each request carries a caller-chosen `custom_id` that its result is matched back by.
+//!
+//! `BatchWatcher` polls a submitted job and hands back each result once, as soon as the
+//! provider makes it available; the caller decides how long to wait between polls:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::batch_jobs::*;
+//! # use std::time::Duration;
+//! # async fn store(custom_id: String, response: InferenceResponse) -> TylResult<()> { Ok(()) }
+//! # #[cfg(feature = "http-client")]
+//! # async fn example(requests: Vec<BatchRequest>) -> TylResult<()> {
+//! let batches = OpenAiBatchService::new(reqwest::Client::new())
+//!     .with_credentials(EnvCredentials::new("OPENAI_API_KEY"));
+//! let job = batches.submit_batch(requests).await?;
+//! let mut watcher = BatchWatcher::new(&batches, job);
+//! while !watcher.is_finished() {
+//!     tokio::time::sleep(Duration::from_secs(300)).await;
+//!     for result in watcher.poll().await? {
+//!         store(result.custom_id, result.result?).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `OpenAiBatchService` uploads the requests as a JSONL file (`POST /files`), creates a batch
+//! against `/v1/chat/completions` (`POST /batches`) and downloads the output and error files
+//! once the batch has finished.
+
+use crate::credentials::CredentialsProvider;
+use crate::http_client::{
+    parse_json_response, response_error, send_authorized, HttpMethod, HttpRequest, HttpResponse,
+    HttpTransport,
+};
+use crate::*;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Response metadata key holding the id of the batch job the response came from
+pub const BATCH_JOB_METADATA_KEY: &str = "batch_job_id";
+
+/// Request of a batch, identified by a caller-chosen id unique within the batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    pub request: InferenceRequest,
+}
+
+impl BatchRequest {
+    pub fn new(custom_id: impl Into<String>, request: InferenceRequest) -> Self {
+        Self {
+            custom_id: custom_id.into(),
+            request,
+        }
+    }
+}
+
+/// Outcome of one request of a batch
+#[derive(Debug)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub result: InferenceResult<InferenceResponse>,
+}
+
+/// Lifecycle of a batch job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchJobStatus {
+    /// The input is being checked before processing starts
+    Validating,
+    InProgress,
+    /// Every request has run; results are being collected
+    Finalizing,
+    Completed,
+    /// The input was rejected; no request ran
+    Failed,
+    /// The completion window passed; requests that ran have results
+    Expired,
+    Cancelling,
+    Cancelled,
+}
+
+impl BatchJobStatus {
+    /// Whether the job will not change any more
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            Self::Completed | Self::Failed | Self::Expired | Self::Cancelled
+        )
+    }
+}
+
+/// Progress of a batch job's requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchRequestCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+/// Batch job as last reported by the provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub id: String,
+    pub status: BatchJobStatus,
+    pub request_counts: BatchRequestCounts,
+    /// Provider file holding the successful results, once there are any
+    pub output_file_id: Option<String>,
+    /// Provider file holding the failed results, once there are any
+    pub error_file_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Port for provider batch APIs
+#[async_trait]
+pub trait BatchJobService: Send + Sync {
+    /// Submit `requests` as one job; `custom_id`s must be unique within the batch
+    async fn submit_batch(&self, requests: Vec<BatchRequest>) -> InferenceResult<BatchJob>;
+
+    /// Current state of job `id`
+    async fn batch_status(&self, id: &str) -> InferenceResult<BatchJob>;
+
+    /// Results available so far for `job` (as returned by `batch_status`), in any order
+    async fn batch_results(&self, job: &BatchJob) -> InferenceResult<Vec<BatchResult>>;
+
+    /// Ask the provider to stop job `id`; requests already run keep their results
+    async fn cancel_batch(&self, id: &str) -> InferenceResult<BatchJob>;
+}
+
+/// Polls a batch job, returning every result exactly once
+pub struct BatchWatcher<'a, B: ?Sized> {
+    service: &'a B,
+    job: BatchJob,
+    seen: HashSet<String>,
+    finished: bool,
+}
+
+impl<'a, B: BatchJobService + ?Sized> BatchWatcher<'a, B> {
+    pub fn new(service: &'a B, job: BatchJob) -> Self {
+        Self {
+            service,
+            job,
+            seen: HashSet::new(),
+            finished: false,
+        }
+    }
+
+    /// Job as of the last poll
+    pub fn job(&self) -> &BatchJob {
+        &self.job
+    }
+
+    /// Whether the job has ended and all its results were returned
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Refresh the job and return the results that became available since the last poll
+    pub async fn poll(&mut self) -> InferenceResult<Vec<BatchResult>> {
+        let job = self.service.batch_status(&self.job.id).await?;
+        let results = self.service.batch_results(&job).await?;
+        self.finished = job.status.is_terminal();
+        self.job = job;
+        Ok(results
+            .into_iter()
+            .filter(|result| self.seen.insert(result.custom_id.clone()))
+            .collect())
+    }
+}
+
+/// `BatchJobService` over the OpenAI Batch API or a compatible server
+#[derive(Clone)]
+pub struct OpenAiBatchService {
+    base_url: String,
+    completion_window: String,
+    transport: Arc<dyn HttpTransport>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+}
+
+impl std::fmt::Debug for OpenAiBatchService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiBatchService")
+            .field("base_url", &self.base_url)
+            .field("completion_window", &self.completion_window)
+            .field("credentials", &self.credentials.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAiFile {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct OpenAiBatch {
+    id: String,
+    status: BatchJobStatus,
+    #[serde(default)]
+    request_counts: Option<BatchRequestCounts>,
+    #[serde(default)]
+    output_file_id: Option<String>,
+    #[serde(default)]
+    error_file_id: Option<String>,
+    created_at: i64,
+}
+
+impl From<OpenAiBatch> for BatchJob {
+    fn from(batch: OpenAiBatch) -> Self {
+        Self {
+            id: batch.id,
+            status: batch.status,
+            request_counts: batch.request_counts.unwrap_or_default(),
+            output_file_id: batch.output_file_id,
+            error_file_id: batch.error_file_id,
+            created_at: DateTime::from_timestamp(batch.created_at, 0).unwrap_or_default(),
+        }
+    }
+}
+
+/// Line of a batch output or error file
+#[derive(Deserialize)]
+struct OpenAiBatchLine {
+    custom_id: String,
+    #[serde(default)]
+    response: Option<OpenAiBatchResponse>,
+    #[serde(default)]
+    error: Option<OpenAiBatchError>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiBatchResponse {
+    status_code: u16,
+    body: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct OpenAiBatchError {
+    #[serde(default)]
+    code: Option<String>,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletion {
+    model: String,
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
+    #[serde(default)]
+    system_fingerprint: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+/// Chat completions body for `request`
+fn chat_body(request: &InferenceRequest) -> InferenceResult<serde_json::Value> {
+    if !request.attachments.is_empty() {
+        return Err(TylError::validation(
+            "attachments",
+            "batch jobs do not support attachments",
+        ));
+    }
+    // Batch results carry one completion per request, without log probabilities
+    if request.candidates.is_some_and(|candidates| candidates > 1) {
+        return Err(TylError::validation(
+            "candidates",
+            "batch jobs do not support multiple candidates",
+        ));
+    }
+    if request.logprobs.is_some() {
+        return Err(TylError::validation(
+            "logprobs",
+            "batch jobs do not support log probabilities",
+        ));
+    }
+    let model = request
+        .model_override
+        .clone()
+        .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string());
+    let mut body = serde_json::json!({
+        "model": model,
+        "messages": [{"role": "user", "content": request.render_template()}],
+    });
+    if let Some(max_tokens) = request.max_tokens {
+        body["max_tokens"] = max_tokens.into();
+    }
+    body["temperature"] = request.effective_temperature().into();
+    if let Some(top_p) = request.top_p {
+        body["top_p"] = top_p.into();
+    }
+    if let Some(penalty) = request.frequency_penalty {
+        body["frequency_penalty"] = penalty.into();
+    }
+    if let Some(penalty) = request.presence_penalty {
+        body["presence_penalty"] = penalty.into();
+    }
+    if !request.stop.is_empty() {
+        body["stop"] = request.stop.clone().into();
+    }
+    if let Some(seed) = request.seed {
+        body["seed"] = seed.into();
+    }
+    Ok(body)
+}
+
+/// JSONL input file for `requests`
+fn input_file(requests: &[BatchRequest]) -> InferenceResult<Vec<u8>> {
+    let mut ids = HashSet::new();
+    let mut file = Vec::new();
+    for batch_request in requests {
+        if !ids.insert(batch_request.custom_id.as_str()) {
+            return Err(TylError::validation(
+                "custom_id",
+                format!("duplicate custom_id '{}'", batch_request.custom_id),
+            ));
+        }
+        let line = serde_json::json!({
+            "custom_id": batch_request.custom_id,
+            "method": "POST",
+            "url": "/v1/chat/completions",
+            "body": chat_body(&batch_request.request)?,
+        });
+        serde_json::to_writer(&mut file, &line)
+            .map_err(|e| TylError::internal(format!("Failed to encode batch request: {e}")))?;
+        file.push(b'\n');
+    }
+    Ok(file)
+}
+
+/// Result of one line of an output or error file
+fn parse_line(line: OpenAiBatchLine, job_id: &str) -> BatchResult {
+    let result = match (line.response, line.error) {
+        (_, Some(error)) => Err(inference_errors::generation_failed(match error.code {
+            Some(code) => format!("{code}: {}", error.message),
+            None => error.message,
+        })),
+        (Some(response), None) => {
+            let http = HttpResponse::new(
+                response.status_code,
+                serde_json::to_vec(&response.body).unwrap_or_default(),
+            );
+            parse_json_response::<ChatCompletion>(&http, "OpenAI").and_then(|completion| {
+                let content = completion
+                    .choices
+                    .into_iter()
+                    .next()
+                    .and_then(|choice| choice.message.content)
+                    .ok_or_else(|| {
+                        inference_errors::generation_failed("Batch response has no content")
+                    })?;
+                let usage = completion.usage.map_or(TokenUsage::new(0, 0), |usage| {
+                    TokenUsage::new(usage.prompt_tokens, usage.completion_tokens)
+                });
+                let mut metadata = ResponseMetadata::new(completion.model, usage, 0)
+                    .with_metadata(BATCH_JOB_METADATA_KEY, job_id);
+                if let Some(fingerprint) = completion.system_fingerprint {
+                    metadata = metadata.with_system_fingerprint(fingerprint);
+                }
+                Ok(InferenceResponse::new(content.into(), metadata))
+            })
+        }
+        (None, None) => Err(inference_errors::generation_failed(
+            "Batch result has neither a response nor an error",
+        )),
+    };
+    BatchResult {
+        custom_id: line.custom_id,
+        result,
+    }
+}
+
+impl OpenAiBatchService {
+    /// `https://api.openai.com/v1` with a 24h completion window through `transport`
+    pub fn new(transport: impl HttpTransport + 'static) -> Self {
+        Self {
+            base_url: "https://api.openai.com/v1".to_string(),
+            completion_window: "24h".to_string(),
+            transport: Arc::new(transport),
+            credentials: None,
+        }
+    }
+
+    /// Base URL of an OpenAI-compatible server, including the version path
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Time the provider has to finish a batch (OpenAI only accepts `24h`)
+    pub fn with_completion_window(mut self, window: impl Into<String>) -> Self {
+        self.completion_window = window.into();
+        self
+    }
+
+    /// Authenticate with bearer credentials from `provider`, refreshing them once on 401
+    pub fn with_credentials(mut self, provider: impl CredentialsProvider + 'static) -> Self {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+        send_authorized(
+            self.transport.as_ref(),
+            self.credentials.as_deref(),
+            request,
+        )
+        .await
+    }
+
+    /// Upload `content` as a `batch` file, returning its id
+    async fn upload(&self, content: Vec<u8>, timeout: Option<Duration>) -> InferenceResult<String> {
+        let boundary = format!("tyl-batch-{}", uuid::Uuid::new_v4().simple());
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n\
+             --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; \
+             filename=\"batch.jsonl\"\r\nContent-Type: application/jsonl\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(&content);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        let request = HttpRequest {
+            method: HttpMethod::Post,
+            url: format!("{}/files", self.base_url),
+            headers: vec![(
+                "Content-Type".to_string(),
+                format!("multipart/form-data; boundary={boundary}"),
+            )],
+            body: Some(body),
+            timeout,
+        };
+        let file: OpenAiFile = parse_json_response(&self.send(request).await?, "OpenAI")?;
+        Ok(file.id)
+    }
+
+    async fn download(&self, file_id: &str, job_id: &str) -> InferenceResult<Vec<BatchResult>> {
+        let request = HttpRequest::get(format!("{}/files/{file_id}/content", self.base_url));
+        let response = self.send(request).await?;
+        if !response.is_success() {
+            return Err(response_error(&response, "OpenAI"));
+        }
+        String::from_utf8_lossy(&response.body)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str::<OpenAiBatchLine>(line)
+                    .map(|line| parse_line(line, job_id))
+                    .map_err(|e| TylError::internal(format!("Invalid batch result line: {e}")))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl BatchJobService for OpenAiBatchService {
+    async fn submit_batch(&self, requests: Vec<BatchRequest>) -> InferenceResult<BatchJob> {
+        if requests.is_empty() {
+            return Err(TylError::validation(
+                "requests",
+                "a batch needs at least one request",
+            ));
+        }
+        // Submission is bounded by the tightest deadline of the requests it carries
+        let timeout = requests
+            .iter()
+            .filter_map(|batched| batched.request.attempt_timeout())
+            .min();
+        let input_file_id = self.upload(input_file(&requests)?, timeout).await?;
+        let body = serde_json::json!({
+            "input_file_id": input_file_id,
+            "endpoint": "/v1/chat/completions",
+            "completion_window": self.completion_window,
+        });
+        let body = serde_json::to_vec(&body)
+            .map_err(|e| TylError::internal(format!("Failed to encode batch: {e}")))?;
+        let request = HttpRequest::post_json(format!("{}/batches", self.base_url), body)
+            .with_attempt_timeout(timeout);
+        let batch: OpenAiBatch = parse_json_response(&self.send(request).await?, "OpenAI")?;
+        Ok(batch.into())
+    }
+
+    async fn batch_status(&self, id: &str) -> InferenceResult<BatchJob> {
+        let request = HttpRequest::get(format!("{}/batches/{id}", self.base_url));
+        let batch: OpenAiBatch = parse_json_response(&self.send(request).await?, "OpenAI")?;
+        Ok(batch.into())
+    }
+
+    async fn batch_results(&self, job: &BatchJob) -> InferenceResult<Vec<BatchResult>> {
+        let mut results = Vec::new();
+        for file_id in [&job.output_file_id, &job.error_file_id]
+            .into_iter()
+            .flatten()
+        {
+            results.extend(self.download(file_id, &job.id).await?);
+        }
+        Ok(results)
+    }
+
+    async fn cancel_batch(&self, id: &str) -> InferenceResult<BatchJob> {
+        let request = HttpRequest::post_json(
+            format!("{}/batches/{id}/cancel", self.base_url),
+            b"{}".to_vec(),
+        );
+        let batch: OpenAiBatch = parse_json_response(&self.send(request).await?, "OpenAI")?;
+        Ok(batch.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::StaticCredentials;
+    use crate::test_support::{scripted, ScriptedTransport};
+
+    fn json(value: serde_json::Value) -> HttpResponse {
+        HttpResponse::new(200, serde_json::to_vec(&value).unwrap())
+    }
+
+    fn batch(status: &str, output: Option<&str>, error: Option<&str>) -> HttpResponse {
+        json(serde_json::json!({
+            "id": "batch_1",
+            "object": "batch",
+            "status": status,
+            "created_at": 1_760_000_000,
+            "request_counts": {"total": 3, "completed": 2, "failed": 1},
+            "output_file_id": output,
+            "error_file_id": error,
+        }))
+    }
+
+    fn completion(custom_id: &str, content: &str) -> String {
+        serde_json::json!({
+            "id": "batch_req_1",
+            "custom_id": custom_id,
+            "response": {
+                "status_code": 200,
+                "body": {
+                    "model": "gpt-4o-mini-2024-07-18",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": content}}],
+                    "usage": {"prompt_tokens": 9, "completion_tokens": 4, "total_tokens": 13}
+                }
+            },
+            "error": null
+        })
+        .to_string()
+    }
+
+    fn requests() -> Vec<BatchRequest> {
+        let request = |text: &str| {
+            let parameters = HashMap::from([("text".to_string(), text.to_string())]);
+            InferenceRequest::new("Summarize {{text}}", parameters, ModelType::Fast)
+                .with_max_tokens(50)
+        };
+        vec![
+            BatchRequest::new("doc-1", request("first")),
+            BatchRequest::new("doc-2", request("second")),
+            BatchRequest::new("doc-3", request("third")),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_openai_batch_round_trip() {
+        let errors = serde_json::json!({
+            "custom_id": "doc-3",
+            "response": {"status_code": 400, "body": {"error": {"message": "prompt too long"}}},
+            "error": null
+        });
+        let transport = scripted(vec![
+            json(serde_json::json!({"id": "file-in", "purpose": "batch"})),
+            batch("validating", None, None),
+            batch("in_progress", None, None),
+            batch("completed", Some("file-out"), Some("file-err")),
+            HttpResponse::new(
+                200,
+                format!(
+                    "{}\n{}\n",
+                    completion("doc-1", "one"),
+                    completion("doc-2", "two")
+                ),
+            ),
+            HttpResponse::new(200, errors.to_string()),
+        ]);
+        let service = OpenAiBatchService::new(Arc::clone(&transport))
+            .with_credentials(StaticCredentials::new("sk-test"));
+
+        let job = service.submit_batch(requests()).await.unwrap();
+        assert_eq!(job.status, BatchJobStatus::Validating);
+        let mut watcher = BatchWatcher::new(&service, job);
+        assert!(watcher.poll().await.unwrap().is_empty());
+        assert!(!watcher.is_finished());
+
+        let mut results = watcher.poll().await.unwrap();
+        assert!(watcher.is_finished());
+        assert_eq!(watcher.job().request_counts.failed, 1);
+        results.sort_by(|a, b| a.custom_id.cmp(&b.custom_id));
+        let ids: Vec<_> = results
+            .iter()
+            .map(|result| result.custom_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["doc-1", "doc-2", "doc-3"]);
+        let first = results[0].result.as_ref().unwrap();
+        assert_eq!(first.content, serde_json::json!("one"));
+        assert_eq!(first.metadata.token_usage, TokenUsage::new(9, 4));
+        assert_eq!(first.metadata.metadata[BATCH_JOB_METADATA_KEY], "batch_1");
+        let error = results[2].result.as_ref().unwrap_err();
+        assert!(error.to_string().contains("prompt too long"));
+
+        let requests = transport.requests.lock().unwrap();
+        let urls: Vec<_> = requests
+            .iter()
+            .map(|request| request.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://api.openai.com/v1/files",
+                "https://api.openai.com/v1/batches",
+                "https://api.openai.com/v1/batches/batch_1",
+                "https://api.openai.com/v1/batches/batch_1",
+                "https://api.openai.com/v1/files/file-out/content",
+                "https://api.openai.com/v1/files/file-err/content",
+            ]
+        );
+        assert_eq!(requests[0].header("authorization"), Some("Bearer sk-test"));
+        assert!(requests[0]
+            .header("content-type")
+            .unwrap()
+            .starts_with("multipart/form-data; boundary="));
+        let upload = String::from_utf8(requests[0].body.clone().unwrap()).unwrap();
+        assert!(upload.contains("name=\"purpose\"\r\n\r\nbatch\r\n"));
+        let mut line: serde_json::Value =
+            serde_json::from_str(upload.lines().find(|line| line.contains("doc-2")).unwrap())
+                .unwrap();
+        // `DEFAULT_TEMPERATURE`, since the request sets none
+        assert!(line["body"]
+            .as_object_mut()
+            .unwrap()
+            .remove("temperature")
+            .is_some());
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "custom_id": "doc-2",
+                "method": "POST",
+                "url": "/v1/chat/completions",
+                "body": {
+                    "model": ModelType::Fast.optimal_openai_model(),
+                    "messages": [{"role": "user", "content": "Summarize second"}],
+                    "max_tokens": 50
+                }
+            })
+        );
+        let create: serde_json::Value =
+            serde_json::from_slice(requests[1].body.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            create,
+            serde_json::json!({
+                "input_file_id": "file-in",
+                "endpoint": "/v1/chat/completions",
+                "completion_window": "24h"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_batches() {
+        let transport = Arc::new(ScriptedTransport::default());
+        let service = OpenAiBatchService::new(Arc::clone(&transport));
+        assert!(service.submit_batch(Vec::new()).await.is_err());
+
+        let mut duplicated = requests();
+        duplicated[1].custom_id = "doc-1".to_string();
+        let error = service.submit_batch(duplicated).await.unwrap_err();
+        assert!(error.to_string().contains("duplicate custom_id 'doc-1'"));
+
+        // Settings a batch result cannot honor are rejected instead of dropped
+        let mut sampled = requests();
+        sampled[0].request = sampled[0].request.clone().with_candidates(3);
+        let error = service.submit_batch(sampled).await.unwrap_err();
+        assert!(error.to_string().contains("multiple candidates"));
+        let mut scored = requests();
+        scored[1].request = scored[1].request.clone().with_logprobs(2);
+        let error = service.submit_batch(scored).await.unwrap_err();
+        assert!(error.to_string().contains("log probabilities"));
+        assert!(transport.requests.lock().unwrap().is_empty());
+    }
+}
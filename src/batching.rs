@@ -0,0 +1,438 @@
+//! Micro-batching decorator
+//!
+//! `MicroBatchService` holds each request for up to a short window and sends every request that
+//! arrived in the meantime to the wrapped service as one `infer_batch` call. Local GPU adapters
+//! decode a batch in little more time than a single request, so under concurrent load a few
+//! milliseconds of added latency buy several times the throughput:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::batching::*;
+//! # use std::time::Duration;
+//! # async fn example(local_gpu: impl InferenceService + 'static) -> TylResult<()> {
+//! let service =
+//!     MicroBatchService::new(local_gpu, Duration::from_millis(10)).with_max_batch_size(32);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only requests for the same model type and model are batched together. A batch is sent as
+//! soon as it is full or its window has elapsed, whichever comes first; the size of the batch
+//! each response came from is recorded under `batch_size`. Batches are sent from a spawned task,
+//! so a caller that gives up waiting does not cancel the batch it joined; a batch every caller
+//! gave up on before its window elapsed is discarded instead of being joined by later requests.
+//! Streams are not batched: they go straight to the wrapped service, and so do `infer_batch` calls.
+
+use crate::*;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+/// Response metadata key holding the number of requests in the batch the response came from
+pub const BATCH_SIZE_METADATA_KEY: &str = "batch_size";
+
+/// Requests that may share a batch
+type BatchKey = (ModelType, Option<String>);
+
+type Reply = oneshot::Sender<InferenceResult<InferenceResponse>>;
+
+struct Batch {
+    id: u64,
+    deadline: Instant,
+    entries: Vec<(InferenceRequest, Reply)>,
+}
+
+#[derive(Default)]
+struct Batches {
+    open: HashMap<BatchKey, Batch>,
+    next_id: u64,
+}
+
+/// Inference service decorator grouping concurrent requests into `infer_batch` calls
+pub struct MicroBatchService<S> {
+    inner: Arc<S>,
+    window: Duration,
+    max_batch_size: usize,
+    batches: Mutex<Batches>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for MicroBatchService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MicroBatchService")
+            .field("inner", &self.inner)
+            .field("window", &self.window)
+            .field("max_batch_size", &self.max_batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService + 'static> MicroBatchService<S> {
+    /// Collect requests for up to `window`, at most 16 per batch
+    pub fn new(inner: S, window: Duration) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            window,
+            max_batch_size: 16,
+            batches: Mutex::default(),
+        }
+    }
+
+    /// Send a batch as soon as it holds `max_batch_size` requests (at least 1)
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    /// Add `request` to the open batch for its key; returns the batch id and deadline, the
+    /// batch itself when this request filled it, and the batch it replaced when that one's
+    /// window had already elapsed
+    fn join(
+        &self,
+        request: InferenceRequest,
+        reply: Reply,
+    ) -> (u64, Instant, Option<Batch>, Option<Batch>) {
+        let key = (request.model_type, request.model_override.clone());
+        let mut batches = self.batches.lock().unwrap();
+        let batches = &mut *batches;
+        // A batch is only left open past its deadline when every request in it was cancelled
+        // before sending it; never send a new request with it
+        let stale = match batches.open.get(&key) {
+            Some(batch) if batch.deadline <= Instant::now() => {
+                batches.open.remove(&key).and_then(|mut batch| {
+                    batch.entries.retain(|(_, reply)| !reply.is_closed());
+                    (!batch.entries.is_empty()).then_some(batch)
+                })
+            }
+            _ => None,
+        };
+        let batch = batches.open.entry(key.clone()).or_insert_with(|| {
+            batches.next_id += 1;
+            Batch {
+                id: batches.next_id,
+                deadline: Instant::now() + self.window,
+                entries: Vec::new(),
+            }
+        });
+        batch.entries.push((request, reply));
+        let (id, deadline) = (batch.id, batch.deadline);
+        let full = batch.entries.len() >= self.max_batch_size;
+        (
+            id,
+            deadline,
+            full.then(|| batches.open.remove(&key)).flatten(),
+            stale,
+        )
+    }
+
+    /// Take batch `id` out for dispatch, unless another request already did
+    fn take(&self, id: u64) -> Option<Batch> {
+        let mut batches = self.batches.lock().unwrap();
+        let key = batches
+            .open
+            .iter()
+            .find(|(_, batch)| batch.id == id)
+            .map(|(key, _)| key.clone())?;
+        batches.open.remove(&key)
+    }
+
+    /// Send a batch from its own task, so that it outlives the request that sends it
+    fn dispatch(&self, batch: Batch) {
+        let inner = Arc::clone(&self.inner);
+        tokio::spawn(async move {
+            let size = batch.entries.len();
+            let (requests, replies): (Vec<_>, Vec<_>) = batch.entries.into_iter().unzip();
+            let results = inner.infer_batch(requests).await;
+            for (reply, result) in replies.into_iter().zip(results) {
+                // The caller may have given up waiting
+                let _ = reply.send(with_batch_size(result, size));
+            }
+        });
+    }
+}
+
+fn with_batch_size(
+    result: InferenceResult<InferenceResponse>,
+    size: usize,
+) -> InferenceResult<InferenceResponse> {
+    result.map(|mut response| {
+        response.metadata = response
+            .metadata
+            .with_metadata(BATCH_SIZE_METADATA_KEY, size.to_string());
+        response
+    })
+}
+
+/// `InferenceLayer` wrapping services in a `MicroBatchService`; every wrapped service batches
+/// its own requests
+#[derive(Debug, Clone, Copy)]
+pub struct MicroBatchLayer {
+    window: Duration,
+    max_batch_size: Option<usize>,
+}
+
+impl MicroBatchLayer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            max_batch_size: None,
+        }
+    }
+
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = Some(max_batch_size);
+        self
+    }
+}
+
+impl<S: InferenceService + 'static> InferenceLayer<S> for MicroBatchLayer {
+    type Service = MicroBatchService<S>;
+
+    fn layer(&self, inner: S) -> MicroBatchService<S> {
+        let service = MicroBatchService::new(inner, self.window);
+        match self.max_batch_size {
+            Some(max_batch_size) => service.with_max_batch_size(max_batch_size),
+            None => service,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService + 'static> InferenceService for MicroBatchService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let (reply, mut response) = oneshot::channel();
+        let (id, deadline, full, stale) = self.join(request, reply);
+        if let Some(batch) = stale {
+            self.dispatch(batch);
+        }
+        if let Some(batch) = full {
+            self.dispatch(batch);
+        } else {
+            // Whichever request of the batch wakes first at the deadline sends it, so the batch
+            // still goes out when the request that opened it is cancelled
+            tokio::select! {
+                result = &mut response => {
+                    return result.unwrap_or_else(|_| {
+                        Err(inference_errors::request_aborted("batch was dropped"))
+                    });
+                }
+                _ = tokio::time::sleep_until(deadline) => {}
+            }
+            if let Some(batch) = self.take(id) {
+                self.dispatch(batch);
+            }
+        }
+        response
+            .await
+            .unwrap_or_else(|_| Err(inference_errors::request_aborted("batch was dropped")))
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        self.inner.infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        let size = requests.len();
+        self.inner
+            .infer_batch(requests)
+            .await
+            .into_iter()
+            .map(|result| with_batch_size(result, size))
+            .collect()
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers with each request's template, recording the size of every batch
+    #[derive(Default)]
+    struct Gpu {
+        batches: Mutex<Vec<usize>>,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl InferenceService for Gpu {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            self.infer_batch(vec![request]).await.remove(0)
+        }
+
+        async fn infer_batch(
+            &self,
+            requests: Vec<InferenceRequest>,
+        ) -> Vec<InferenceResult<InferenceResponse>> {
+            self.batches.lock().unwrap().push(requests.len());
+            tokio::time::sleep(self.delay).await;
+            requests
+                .into_iter()
+                .map(|request| {
+                    if request.template == "fail" {
+                        return Err(TylError::validation("template", "bad prompt"));
+                    }
+                    Ok(InferenceResponse::new(
+                        serde_json::json!(request.template),
+                        ResponseMetadata::new("gpu-1".to_string(), TokenUsage::new(3, 1), 1),
+                    ))
+                })
+                .collect()
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["gpu-1".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn request(template: &str, model_type: ModelType) -> InferenceRequest {
+        InferenceRequest::new(template, HashMap::new(), model_type)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_a_batch() {
+        let service = MicroBatchService::new(Gpu::default(), Duration::from_millis(20));
+        let (a, b, c, d) = tokio::join!(
+            service.infer(request("a", ModelType::Fast)),
+            service.infer(request("b", ModelType::Fast)),
+            service.infer(request("fail", ModelType::Fast)),
+            service.infer(request("d", ModelType::Coding)),
+        );
+
+        let a = a.unwrap();
+        assert_eq!(a.content, serde_json::json!("a"));
+        assert_eq!(a.metadata.metadata[BATCH_SIZE_METADATA_KEY], "3");
+        assert_eq!(b.unwrap().content, serde_json::json!("b"));
+        assert!(c.unwrap_err().to_string().contains("bad prompt"));
+        assert_eq!(d.unwrap().metadata.metadata[BATCH_SIZE_METADATA_KEY], "1");
+
+        let mut batches = service.inner().batches.lock().unwrap().clone();
+        batches.sort();
+        assert_eq!(batches, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_full_batch_is_sent_before_the_window() {
+        let service =
+            MicroBatchService::new(Gpu::default(), Duration::from_secs(60)).with_max_batch_size(2);
+        let started = std::time::Instant::now();
+        let (a, b) = tokio::join!(
+            service.infer(request("a", ModelType::Fast)),
+            service.infer(request("b", ModelType::Fast)),
+        );
+        assert!(a.is_ok() && b.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(*service.inner().batches.lock().unwrap(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_caller_does_not_drop_the_batch() {
+        let gpu = Gpu {
+            delay: Duration::from_millis(50),
+            ..Gpu::default()
+        };
+        let service =
+            Arc::new(MicroBatchService::new(gpu, Duration::from_secs(60)).with_max_batch_size(2));
+        let first = tokio::spawn({
+            let service = Arc::clone(&service);
+            async move { service.infer(request("a", ModelType::Fast)).await }
+        });
+        tokio::task::yield_now().await;
+
+        // The request filling the batch sends it, then gives up before the batch is answered
+        let filler = service.infer(request("b", ModelType::Fast));
+        assert!(tokio::time::timeout(Duration::from_millis(10), filler)
+            .await
+            .is_err());
+
+        let first = first.await.unwrap().unwrap();
+        assert_eq!(first.metadata.metadata[BATCH_SIZE_METADATA_KEY], "2");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_batch_is_not_joined_after_its_window() {
+        let service = MicroBatchService::new(Gpu::default(), Duration::from_millis(20));
+        let abandoned = service.infer(request("a", ModelType::Fast));
+        assert!(tokio::time::timeout(Duration::from_millis(5), abandoned)
+            .await
+            .is_err());
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        // The next request opens its own batch instead of reviving the abandoned one
+        let started = std::time::Instant::now();
+        let response = service.infer(request("b", ModelType::Fast)).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("b"));
+        assert_eq!(response.metadata.metadata[BATCH_SIZE_METADATA_KEY], "1");
+        assert!(started.elapsed() >= Duration::from_millis(15));
+        assert_eq!(*service.inner().batches.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_batches_pass_through_decorators() {
+        let service = MicroBatchService::new(
+            crate::TimeoutService::new(Gpu::default()),
+            Duration::from_millis(20),
+        );
+        let (a, b, c) = tokio::join!(
+            service.infer(request("a", ModelType::Fast)),
+            service.infer(request("b", ModelType::Fast)),
+            service.infer(request("c", ModelType::Fast)),
+        );
+        assert!(a.is_ok() && b.is_ok() && c.is_ok());
+        assert_eq!(*service.inner().inner().batches.lock().unwrap(), vec![3]);
+    }
+}
@@ -0,0 +1,130 @@
+//! `tyl-infer`: run a prompt template file once and print the response
+//!
+//! ```text
+//! tyl-infer summarize.md --param text="the quarterly report"
+//! TYL_INFERENCE_PROVIDER=grpc TYL_INFERENCE_BASE_URL=http://localhost:50051 tyl-infer prompt.md
+//! ```
+//!
+//! Templates may carry YAML frontmatter (model type, max tokens, post-processors...). The service
+//! is built from `InferenceConfig::from_env`; `--provider`, `--base-url` and `--model` override
+//! the environment.
+
+use clap::Parser;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tyl_llm_inference_port::postprocess::{PostProcessingService, PostProcessorRegistry};
+use tyl_llm_inference_port::pricing::PricingTable;
+use tyl_llm_inference_port::template::PromptTemplate;
+use tyl_llm_inference_port::{
+    build_service, InferenceConfig, InferenceProvider, InferenceResult, InferenceService,
+    ModelType, TylError,
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "tyl-infer", version, about = "Run a prompt template once")]
+struct Args {
+    /// Template file, optionally with YAML frontmatter
+    template: PathBuf,
+
+    /// Template parameter as key=value (repeatable)
+    #[arg(short, long = "param", value_name = "KEY=VALUE", value_parser = parse_param)]
+    params: Vec<(String, String)>,
+
+    /// Provider to run the request with (overrides TYL_INFERENCE_PROVIDER)
+    #[arg(long, value_parser = parse_provider)]
+    provider: Option<InferenceProvider>,
+
+    /// Endpoint of remote providers (overrides TYL_INFERENCE_BASE_URL)
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// Model overriding the template's model type (overrides TYL_INFERENCE_MODEL)
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Model type overriding the template frontmatter (a `ModelType` variant such as Coding
+    /// or Summarization)
+    #[arg(long, env = "TYL_INFERENCE_MODEL_TYPE", value_parser = parse_model_type)]
+    model_type: Option<ModelType>,
+
+    /// Print the rendered prompt to stderr before running it
+    #[arg(long)]
+    show_prompt: bool,
+}
+
+fn parse_param(param: &str) -> Result<(String, String), String> {
+    param
+        .split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or_else(|| format!("expected KEY=VALUE, got {param:?}"))
+}
+
+fn parse_model_type(model_type: &str) -> Result<ModelType, String> {
+    serde_json::from_value(serde_json::Value::String(model_type.to_string()))
+        .map_err(|_| format!("unknown model type {model_type:?}"))
+}
+
+fn parse_provider(provider: &str) -> Result<InferenceProvider, String> {
+    provider.parse().map_err(|e: TylError| e.to_string())
+}
+
+async fn run(args: Args) -> InferenceResult<serde_json::Value> {
+    let source = std::fs::read_to_string(&args.template).map_err(|e| {
+        TylError::configuration(format!("Cannot read {}: {e}", args.template.display()))
+    })?;
+    let mut template = PromptTemplate::parse(&source)?;
+    if let Some(model_type) = args.model_type {
+        template.frontmatter.model_type = Some(model_type);
+    }
+
+    let params: HashMap<String, String> = args.params.iter().cloned().collect();
+    let request = template.to_request(params);
+    if args.show_prompt {
+        eprintln!("{}\n", request.render_template());
+    }
+
+    let mut config = InferenceConfig::from_env()?;
+    if let Some(provider) = args.provider {
+        config.provider = provider;
+    }
+    if let Some(base_url) = args.base_url {
+        config.base_url = Some(base_url);
+    }
+    if let Some(model) = args.model {
+        config.model = Some(model);
+    }
+
+    let service = PostProcessingService::new(
+        build_service(&config).await?,
+        PostProcessorRegistry::with_defaults(),
+    );
+    let response = service.infer(request).await?;
+
+    let metadata = &response.metadata;
+    let cost_usd = PricingTable::with_defaults().cost(&metadata.model, &metadata.token_usage);
+    Ok(serde_json::json!({
+        "content": response.content,
+        "model": metadata.model,
+        "token_usage": metadata.token_usage,
+        "cost_usd": cost_usd,
+        "processing_time_ms": metadata.processing_time_ms,
+        "metadata": metadata.metadata,
+    }))
+}
+
+#[tokio::main]
+async fn main() {
+    match run(Args::parse()).await {
+        Ok(output) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output).unwrap_or_default()
+            );
+        }
+        Err(error) => {
+            eprintln!("tyl-infer: {error}");
+            std::process::exit(1);
+        }
+    }
+}
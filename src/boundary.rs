@@ -0,0 +1,230 @@
+//! Prompt-boundary handling for raw completion backends
+//!
+//! Raw completion backends (llama.cpp-style `/completion` endpoints) continue the prompt token by
+//! token, so how the prompt ends matters: a trailing space becomes its own token the model
+//! rarely saw in training, a BOS token in the template plus the one the runtime adds gives a
+//! doubled BOS, and a prompt ending mid-token (`"https:"`) forces an unnatural tokenization of
+//! the continuation. Adapters for such backends run the rendered prompt through
+//! `PromptBoundary::prepare` and the generated text through `PreparedPrompt::completion`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::boundary::*;
+//! # struct Runtime;
+//! # impl Runtime { async fn complete(&self, prompt: &str) -> TylResult<String> { Ok(String::new()) } }
+//! # async fn example(tokenizer: impl Tokenizer + 'static, runtime: Runtime, request: InferenceRequest) -> TylResult<()> {
+//! let boundary = PromptBoundary::new()
+//!     .with_bos_token("<s>", BosHandling::Strip) // the runtime adds BOS itself
+//!     .with_eos_token("</s>")
+//!     .with_token_healing(tokenizer);
+//! let prepared = boundary.prepare(&request.render_template());
+//! let generated = runtime.complete(&prepared.prompt).await?; // constrained to `prepared.healed_prefix`
+//! let text = prepared.completion(&generated);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::*;
+use std::sync::Arc;
+
+/// Splits text into the token strings of a backend's vocabulary (used for token healing)
+pub trait Tokenizer: Send + Sync {
+    /// Tokens of `text`, in order; concatenated they must equal `text`
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// What to do with a BOS token at the start of the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BosHandling {
+    /// Leave the prompt as rendered
+    #[default]
+    Keep,
+    /// Make sure the prompt starts with exactly one BOS token
+    Add,
+    /// Remove leading BOS tokens (the runtime adds its own)
+    Strip,
+}
+
+/// Boundary rules applied to prompts of a raw completion backend
+#[derive(Clone, Default)]
+pub struct PromptBoundary {
+    bos_token: Option<String>,
+    bos_handling: BosHandling,
+    eos_token: Option<String>,
+    keep_trailing_whitespace: bool,
+    tokenizer: Option<Arc<dyn Tokenizer>>,
+    healing_tokens: usize,
+}
+
+impl std::fmt::Debug for PromptBoundary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PromptBoundary")
+            .field("bos_token", &self.bos_token)
+            .field("bos_handling", &self.bos_handling)
+            .field("eos_token", &self.eos_token)
+            .field("keep_trailing_whitespace", &self.keep_trailing_whitespace)
+            .field("token_healing", &self.tokenizer.is_some())
+            .finish()
+    }
+}
+
+impl PromptBoundary {
+    /// Strip trailing spaces and tabs; no BOS/EOS handling or token healing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_bos_token(mut self, token: impl Into<String>, handling: BosHandling) -> Self {
+        self.bos_token = Some(token.into());
+        self.bos_handling = handling;
+        self
+    }
+
+    /// EOS token stripped from the end of prompts and completions
+    pub fn with_eos_token(mut self, token: impl Into<String>) -> Self {
+        self.eos_token = Some(token.into());
+        self
+    }
+
+    /// Keep trailing spaces and tabs (e.g. for models trained with them)
+    pub fn keep_trailing_whitespace(mut self) -> Self {
+        self.keep_trailing_whitespace = true;
+        self
+    }
+
+    /// Heal the last prompt token (see `with_healing_tokens` to back off further)
+    pub fn with_token_healing(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Some(Arc::new(tokenizer));
+        self.healing_tokens = self.healing_tokens.max(1);
+        self
+    }
+
+    /// Number of trailing prompt tokens removed by token healing
+    pub fn with_healing_tokens(mut self, tokens: usize) -> Self {
+        self.healing_tokens = tokens;
+        self
+    }
+
+    /// Apply the boundary rules to a rendered prompt
+    pub fn prepare(&self, prompt: &str) -> PreparedPrompt {
+        let mut body = prompt;
+        if let (Some(bos), BosHandling::Add | BosHandling::Strip) =
+            (&self.bos_token, self.bos_handling)
+        {
+            while let Some(rest) = body.strip_prefix(bos.as_str()) {
+                body = rest;
+            }
+        }
+        if let Some(eos) = &self.eos_token {
+            while let Some(rest) = body.trim_end().strip_suffix(eos.as_str()) {
+                body = rest;
+            }
+        }
+        if !self.keep_trailing_whitespace {
+            body = body.trim_end_matches([' ', '\t']);
+        }
+
+        let mut healed_prefix = String::new();
+        if let Some(tokenizer) = &self.tokenizer {
+            let tokens = tokenizer.tokenize(body);
+            let keep = tokens.len().saturating_sub(self.healing_tokens);
+            healed_prefix = tokens[keep..].concat();
+            body = &body[..body.len() - healed_prefix.len().min(body.len())];
+        }
+
+        let mut prompt = String::with_capacity(body.len() + 8);
+        if let (Some(bos), BosHandling::Add) = (&self.bos_token, self.bos_handling) {
+            prompt.push_str(bos);
+        }
+        prompt.push_str(body);
+        PreparedPrompt {
+            prompt,
+            healed_prefix,
+            eos_token: self.eos_token.clone(),
+        }
+    }
+}
+
+/// Prompt ready to send to a raw completion backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreparedPrompt {
+    pub prompt: String,
+    /// Text removed from the end of the prompt by token healing; the generation must start with
+    /// it (constrain the backend with a grammar or logit bias where supported)
+    pub healed_prefix: String,
+    eos_token: Option<String>,
+}
+
+impl PreparedPrompt {
+    /// Continuation of the original prompt in `generated`, without the healed prefix and EOS
+    ///
+    /// A generation that diverged from the healed prefix is returned as is.
+    pub fn completion<'a>(&self, generated: &'a str) -> &'a str {
+        let mut text = generated
+            .strip_prefix(self.healed_prefix.as_str())
+            .unwrap_or(generated);
+        if let Some(eos) = &self.eos_token {
+            if let Some(index) = text.find(eos.as_str()) {
+                text = &text[..index];
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words with their leading space; any other character on its own
+    struct WordTokenizer;
+
+    impl Tokenizer for WordTokenizer {
+        fn tokenize(&self, text: &str) -> Vec<String> {
+            let mut tokens: Vec<String> = Vec::new();
+            for c in text.chars() {
+                match tokens.last_mut() {
+                    Some(last)
+                        if c.is_alphanumeric()
+                            && (last == " " || last.ends_with(char::is_alphanumeric)) =>
+                    {
+                        last.push(c)
+                    }
+                    _ => tokens.push(c.to_string()),
+                }
+            }
+            tokens
+        }
+    }
+
+    #[test]
+    fn test_whitespace_bos_and_eos() {
+        let boundary = PromptBoundary::new()
+            .with_bos_token("<s>", BosHandling::Add)
+            .with_eos_token("</s>");
+        let prepared = boundary.prepare("<s><s>Q: What is 2+2?\nA: </s> ");
+        assert_eq!(prepared.prompt, "<s>Q: What is 2+2?\nA:");
+        assert_eq!(prepared.completion(" 4</s> trailing"), " 4");
+
+        let strip = PromptBoundary::new().with_bos_token("<s>", BosHandling::Strip);
+        assert_eq!(strip.prepare("<s>Hello \t").prompt, "Hello");
+        let keep = PromptBoundary::new().keep_trailing_whitespace();
+        assert_eq!(keep.prepare("Hello \n").prompt, "Hello \n");
+    }
+
+    #[test]
+    fn test_token_healing() {
+        let boundary = PromptBoundary::new().with_token_healing(WordTokenizer);
+        let prepared = boundary.prepare("The link is http:");
+        assert_eq!(prepared.prompt, "The link is http");
+        assert_eq!(prepared.healed_prefix, ":");
+        assert_eq!(prepared.completion("://example.com"), "//example.com");
+        // Diverging generations are returned unchanged
+        assert_eq!(prepared.completion(" none"), " none");
+
+        let deeper = PromptBoundary::new()
+            .with_token_healing(WordTokenizer)
+            .with_healing_tokens(2);
+        assert_eq!(deeper.prepare("The link is http:").healed_prefix, " http:");
+    }
+}
@@ -0,0 +1,1072 @@
+//! Spend budgets with threshold alerts
+//!
+//! `BudgetService` prices every response, tracks spend per scope (see
+//! `ledger::SCOPE_METADATA_KEY`) over a `BudgetPeriod`, and notifies registered
+//! `BudgetAlertHandler`s once when a scope's spend reaches a configured threshold (by default
+//! 50%, 80%, and 100% of its limit), whichever request takes it there. Enforced budgets reject
+//! further requests once the limit is reached; requests still in flight count as costing as
+//! much as the scope's last request, so a burst of concurrent requests cannot all slip under
+//! the limit.
+//!
+//! By default spend is kept in memory, summed per time bucket and pruned to each scope's
+//! period. `with_ledger` reads spend from a shared `LedgerStore` instead (typically written by a
+//! `LedgerService` wrapped inside the budget) so budgets survive restarts and are shared
+//! between replicas; it is read again after every request so spend by concurrent requests is
+//! included. Streams are charged when they end, so a stream dropped before its end is not
+//! charged.
+//!
+//! To reach people outside the process, register a `WebhookAlertHandler`, which POSTs each
+//! alert as JSON to a URL (a chat incoming webhook, an incident tool, an internal endpoint):
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::budget::*;
+//! # #[cfg(feature = "http-client")]
+//! # async fn example(inner: impl InferenceService + 'static) -> Result<(), Box<dyn std::error::Error>> {
+//! let service = BudgetService::new(inner)
+//!     .with_budget("team-a", BudgetLimit::new(500.0).with_period(BudgetPeriod::monthly()))
+//!     .with_alert_handler(
+//!         WebhookAlertHandler::new("https://hooks.example.com/finops", reqwest::Client::new())
+//!             .with_secret(std::env::var("FINOPS_WEBHOOK_SECRET")?),
+//!     );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::ensemble::{BoxedFuture, JoinAll};
+use crate::events::{self, EventBus, InferenceEvent};
+use crate::http_client::{HttpRequest, HttpTransport};
+use crate::ledger::{request_scope, LedgerStore};
+use crate::pricing::{PricingTable, SharedPricing};
+use crate::signing::{hex, hmac_sha256};
+use crate::usage_summary::bucket_start;
+use crate::*;
+use chrono::{Datelike, TimeZone};
+use chrono_tz::Tz;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default alert thresholds as fractions of the limit
+pub const DEFAULT_THRESHOLDS: [f64; 3] = [0.5, 0.8, 1.0];
+
+/// Accounting period over which spend is summed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum BudgetPeriod {
+    /// All recorded spend, never resets
+    #[default]
+    Lifetime,
+    /// Calendar month, resetting at midnight on the 1st in the given timezone
+    CalendarMonth { timezone: Tz },
+    /// Trailing window ending now
+    Rolling { window: Duration },
+}
+
+impl BudgetPeriod {
+    /// Calendar month resetting at midnight UTC
+    pub fn monthly() -> Self {
+        Self::CalendarMonth { timezone: Tz::UTC }
+    }
+
+    /// Calendar month resetting at local midnight in `timezone`
+    pub fn monthly_in(timezone: Tz) -> Self {
+        Self::CalendarMonth { timezone }
+    }
+
+    pub fn rolling(window: Duration) -> Self {
+        Self::Rolling { window }
+    }
+
+    /// Start of the period containing `now`
+    pub fn start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::Lifetime => DateTime::<Utc>::UNIX_EPOCH,
+            Self::CalendarMonth { timezone } => {
+                let local = now.with_timezone(timezone);
+                month_start(timezone, local.year(), local.month())
+            }
+            Self::Rolling { window } => {
+                now - chrono::Duration::from_std(*window).unwrap_or(chrono::Duration::MAX)
+            }
+        }
+    }
+
+    /// Length of the buckets spend kept in memory is summed in, `None` for one running total
+    fn bucket(&self) -> Option<chrono::Duration> {
+        match self {
+            Self::Lifetime => None,
+            Self::CalendarMonth { .. } => Some(chrono::Duration::minutes(1)),
+            // Rolling windows are tracked in 1/1024ths of the window, at least a second each
+            Self::Rolling { window } => Some(
+                chrono::Duration::from_std(*window / 1024)
+                    .unwrap_or(chrono::Duration::MAX)
+                    .max(chrono::Duration::seconds(1)),
+            ),
+        }
+    }
+
+    /// When spend next resets to zero, `None` for lifetime and rolling periods
+    pub fn next_reset(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::CalendarMonth { timezone } => {
+                let local = now.with_timezone(timezone);
+                let (year, month) = match local.month() {
+                    12 => (local.year() + 1, 1),
+                    month => (local.year(), month + 1),
+                };
+                Some(month_start(timezone, year, month))
+            }
+            Self::Lifetime | Self::Rolling { .. } => None,
+        }
+    }
+}
+
+/// Local midnight on the 1st of a month, as UTC
+fn month_start(timezone: &Tz, year: i32, month: u32) -> DateTime<Utc> {
+    timezone
+        .with_ymd_and_hms(year, month, 1, 0, 0, 0)
+        .earliest()
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or(DateTime::<Utc>::UNIX_EPOCH)
+}
+
+/// Spend limit for a scope
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetLimit {
+    /// Maximum spend in USD per period
+    pub limit_usd: f64,
+    /// Period over which spend is summed
+    #[serde(default)]
+    pub period: BudgetPeriod,
+    /// Alert thresholds as fractions of the limit (e.g. 0.8 for 80%)
+    pub thresholds: Vec<f64>,
+    /// Reject requests once the limit is reached
+    pub enforce: bool,
+}
+
+impl BudgetLimit {
+    pub fn new(limit_usd: f64) -> Self {
+        Self {
+            limit_usd,
+            period: BudgetPeriod::Lifetime,
+            thresholds: DEFAULT_THRESHOLDS.to_vec(),
+            enforce: false,
+        }
+    }
+
+    pub fn with_period(mut self, period: BudgetPeriod) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn with_thresholds(mut self, thresholds: Vec<f64>) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Reject requests once the limit is reached instead of only alerting
+    pub fn enforced(mut self) -> Self {
+        self.enforce = true;
+        self
+    }
+
+    /// Thresholds reached when `spent_usd` has been spent
+    fn reached(&self, spent_usd: f64) -> Vec<f64> {
+        self.thresholds
+            .iter()
+            .copied()
+            .filter(|threshold| spent_usd >= self.limit_usd * threshold)
+            .collect()
+    }
+}
+
+/// Notification emitted when a scope crosses a budget threshold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetAlert {
+    pub scope: String,
+    /// Threshold crossed, as a fraction of the limit
+    pub threshold: f64,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+    pub triggered_at: DateTime<Utc>,
+}
+
+impl BudgetAlert {
+    /// Spend as a fraction of the limit
+    pub fn utilization(&self) -> f64 {
+        if self.limit_usd > 0.0 {
+            self.spent_usd / self.limit_usd
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Receives budget alerts
+///
+/// Implemented for async closures `Fn(BudgetAlert) -> impl Future<Output = ()>`. A failed
+/// delivery does not fail the request that reached the threshold; `BudgetService` publishes it
+/// as `InferenceEvent::BudgetError` on its event bus (see `BudgetService::with_events`).
+#[async_trait]
+pub trait BudgetAlertHandler: Send + Sync {
+    async fn on_alert(&self, alert: BudgetAlert) -> InferenceResult<()>;
+}
+
+#[async_trait]
+impl<F, Fut> BudgetAlertHandler for F
+where
+    F: Fn(BudgetAlert) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn on_alert(&self, alert: BudgetAlert) -> InferenceResult<()> {
+        (self)(alert).await;
+        Ok(())
+    }
+}
+
+/// Header carrying the HMAC-SHA256 signature of `<timestamp>.<body>`, as `sha256=<hex>`
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Tyl-Signature-256";
+/// Header carrying the Unix time in seconds a webhook delivery was signed at
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Tyl-Timestamp";
+
+/// Alert handler POSTing every alert as JSON to a webhook URL
+///
+/// The body is the `BudgetAlert` plus `"event": "budget_alert"` and its `utilization`. With
+/// `with_secret` every delivery carries its signing time in `WEBHOOK_TIMESTAMP_HEADER` and the
+/// HMAC-SHA256 of `<timestamp>.<body>` in `WEBHOOK_SIGNATURE_HEADER`, so the receiver can reject
+/// forged alerts and, by rejecting old timestamps, replayed ones. A delivery is given up after
+/// `with_timeout` (5 seconds by default) and is not retried.
+pub struct WebhookAlertHandler<T> {
+    url: String,
+    transport: T,
+    headers: Vec<(String, String)>,
+    secret: Option<Vec<u8>>,
+    timeout: Duration,
+}
+
+impl<T> std::fmt::Debug for WebhookAlertHandler<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookAlertHandler")
+            .field("url", &self.url)
+            .field("signed", &self.secret.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: HttpTransport> WebhookAlertHandler<T> {
+    pub fn new(url: impl Into<String>, transport: T) -> Self {
+        Self {
+            url: url.into(),
+            transport,
+            headers: Vec::new(),
+            secret: None,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Extra header on every delivery (e.g. `Authorization`)
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sign deliveries with HMAC-SHA256 using `secret`
+    pub fn with_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Give up on a delivery after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Deliver one alert, failing on transport errors, timeouts and non-2xx responses
+    pub async fn send(&self, alert: &BudgetAlert) -> InferenceResult<()> {
+        self.send_at(alert, Utc::now()).await
+    }
+
+    /// Deliver one alert signed at `now`
+    async fn send_at(&self, alert: &BudgetAlert, now: DateTime<Utc>) -> InferenceResult<()> {
+        let encode_error =
+            |e: serde_json::Error| TylError::internal(format!("Failed to encode alert: {e}"));
+        let mut payload = serde_json::to_value(alert).map_err(encode_error)?;
+        if let Some(fields) = payload.as_object_mut() {
+            fields.insert("event".to_string(), "budget_alert".into());
+            fields.insert("utilization".to_string(), alert.utilization().into());
+        }
+        let body = serde_json::to_vec(&payload).map_err(encode_error)?;
+
+        let mut request =
+            HttpRequest::post_json(&self.url, body.clone()).with_timeout(self.timeout);
+        for (name, value) in &self.headers {
+            request.set_header(name.clone(), value.clone());
+        }
+        if let Some(secret) = &self.secret {
+            let timestamp = now.timestamp().to_string();
+            let signature = hex(&hmac_sha256(secret, &signed_payload(&timestamp, &body)));
+            request.set_header(WEBHOOK_TIMESTAMP_HEADER, timestamp);
+            request.set_header(WEBHOOK_SIGNATURE_HEADER, format!("sha256={signature}"));
+        }
+
+        let response = self.transport.send(request).await?;
+        if !response.is_success() {
+            return Err(TylError::network(format!(
+                "Budget alert webhook {} returned HTTP {}",
+                self.url, response.status
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Bytes a webhook signature covers: `<timestamp>.<body>`
+fn signed_payload(timestamp: &str, body: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(timestamp.len() + 1 + body.len());
+    payload.extend_from_slice(timestamp.as_bytes());
+    payload.push(b'.');
+    payload.extend_from_slice(body);
+    payload
+}
+
+#[async_trait]
+impl<T: HttpTransport> BudgetAlertHandler for WebhookAlertHandler<T> {
+    async fn on_alert(&self, alert: BudgetAlert) -> InferenceResult<()> {
+        self.send(&alert).await
+    }
+}
+
+/// In-process accounting of one scope
+#[derive(Debug, Default)]
+struct ScopeState {
+    /// Spend per time bucket, oldest first (only when the service keeps spend itself)
+    spend: VecDeque<(DateTime<Utc>, f64)>,
+    /// Thresholds alerted on since spend last fell below them, `None` until the scope is seen
+    alerted: Option<Vec<f64>>,
+    /// Admitted requests that have not settled yet
+    in_flight: usize,
+    /// Cost of the last settled request, reserved for each request in flight
+    last_cost_usd: f64,
+}
+
+impl ScopeState {
+    fn add_spend(&mut self, period: &BudgetPeriod, at: DateTime<Utc>, cost_usd: f64) {
+        let bucket = period
+            .bucket()
+            .map_or(DateTime::<Utc>::UNIX_EPOCH, |size| bucket_start(at, size));
+        match self.spend.back_mut() {
+            Some((start, spent)) if *start == bucket => *spent += cost_usd,
+            _ => self.spend.push_back((bucket, cost_usd)),
+        }
+    }
+
+    /// Spend over the period containing `now`, forgetting the buckets before it
+    fn spent(&mut self, period: &BudgetPeriod, now: DateTime<Utc>) -> f64 {
+        let start = period.start(now);
+        if let Some(size) = period.bucket() {
+            while self.spend.front().is_some_and(|(bucket, _)| {
+                bucket
+                    .checked_add_signed(size)
+                    .is_some_and(|end| end <= start)
+            }) {
+                self.spend.pop_front();
+            }
+        }
+        self.spend.iter().map(|(_, spent)| spent).sum()
+    }
+
+    /// Alerts for the thresholds reached at `spent_usd` that were not alerted on yet
+    fn alerts(&mut self, scope: &str, budget: &BudgetLimit, spent_usd: f64) -> Vec<BudgetAlert> {
+        let alerted = self.alerted.get_or_insert_with(Vec::new);
+        // Spend fell back below a threshold (a new period began): alert on it again
+        alerted.retain(|threshold| spent_usd >= budget.limit_usd * threshold);
+        let mut alerts = Vec::new();
+        for threshold in budget.reached(spent_usd) {
+            if !alerted.contains(&threshold) {
+                alerted.push(threshold);
+                alerts.push(BudgetAlert {
+                    scope: scope.to_string(),
+                    threshold,
+                    spent_usd,
+                    limit_usd: budget.limit_usd,
+                    triggered_at: Utc::now(),
+                });
+            }
+        }
+        alerts
+    }
+}
+
+type Scopes = Arc<Mutex<HashMap<String, ScopeState>>>;
+
+/// Spend of a scope over the period containing `now`, read from a ledger
+async fn ledger_spent(
+    store: &dyn LedgerStore,
+    scope: &str,
+    period: &BudgetPeriod,
+    now: DateTime<Utc>,
+) -> InferenceResult<f64> {
+    // Include records appended within the current second
+    let until = now + chrono::Duration::seconds(1);
+    let totals = store.totals(scope, period.start(now), until).await?;
+    Ok(totals.cost_usd)
+}
+
+/// Inference service decorator tracking spend against per-scope budgets
+pub struct BudgetService<S> {
+    inner: S,
+    pricing: SharedPricing,
+    budgets: HashMap<String, BudgetLimit>,
+    default_budget: Option<BudgetLimit>,
+    handlers: Vec<Arc<dyn BudgetAlertHandler>>,
+    /// Shared ledger spend is read from, `None` when the service keeps spend in memory
+    store: Option<Arc<dyn LedgerStore>>,
+    scopes: Scopes,
+    events: Option<EventBus>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for BudgetService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BudgetService")
+            .field("inner", &self.inner)
+            .field("budgets", &self.budgets)
+            .field("default_budget", &self.default_budget)
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+impl<S: InferenceService> BudgetService<S> {
+    /// Track spend in memory, priced with `PricingTable::with_defaults`
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            pricing: PricingTable::with_defaults().into(),
+            budgets: HashMap::new(),
+            default_budget: None,
+            handlers: Vec::new(),
+            store: None,
+            scopes: Arc::default(),
+            events: None,
+        }
+    }
+
+    /// Read spend from a shared ledger store
+    ///
+    /// The budget only reads the store: wrap a `LedgerService` writing to the same store inside
+    /// the budget so every response is recorded exactly once.
+    pub fn with_ledger(mut self, store: Arc<dyn LedgerStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn with_pricing(mut self, pricing: impl Into<SharedPricing>) -> Self {
+        self.pricing = pricing.into();
+        self
+    }
+
+    /// Set the budget of a scope
+    pub fn with_budget(mut self, scope: impl Into<String>, budget: BudgetLimit) -> Self {
+        self.budgets.insert(scope.into(), budget);
+        self
+    }
+
+    /// Budget applied to scopes without an explicit one
+    pub fn with_default_budget(mut self, budget: BudgetLimit) -> Self {
+        self.default_budget = Some(budget);
+        self
+    }
+
+    /// Register an alert handler
+    pub fn with_alert_handler(mut self, handler: impl BudgetAlertHandler + 'static) -> Self {
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    /// Publish `InferenceEvent::BudgetError` when an alert cannot be delivered or the spend of
+    /// an answered request cannot be read back from the ledger
+    pub fn with_events(mut self, bus: EventBus) -> Self {
+        self.events = Some(bus);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Budget that applies to a scope
+    pub fn budget_for(&self, scope: &str) -> Option<&BudgetLimit> {
+        self.budgets.get(scope).or(self.default_budget.as_ref())
+    }
+
+    /// Spend of a scope in USD over its current budget period
+    pub async fn spent(&self, scope: &str) -> InferenceResult<f64> {
+        let period = self
+            .budget_for(scope)
+            .map(|budget| budget.period)
+            .unwrap_or_default();
+        let now = Utc::now();
+        match &self.store {
+            Some(store) => ledger_spent(store.as_ref(), scope, &period, now).await,
+            None => Ok(self
+                .scopes
+                .lock()
+                .unwrap()
+                .get_mut(scope)
+                .map_or(0.0, |state| state.spent(&period, now))),
+        }
+    }
+
+    /// Forget the alerts fired for a scope and, unless spend is read from a shared ledger, its
+    /// spend
+    pub fn reset(&self, scope: &str) {
+        self.scopes.lock().unwrap().remove(scope);
+    }
+
+    /// Admit a request against the budget of its scope, `None` for scopes without a budget
+    async fn admit(&self, request: &InferenceRequest) -> InferenceResult<Option<Settlement>> {
+        let scope = request_scope(request).to_string();
+        let Some(budget) = self.budget_for(&scope).cloned() else {
+            return Ok(None);
+        };
+        let spent = self.spent(&scope).await?;
+        {
+            let mut scopes = self.scopes.lock().unwrap();
+            let state = scopes.entry(scope.clone()).or_default();
+            // Thresholds reached before this process first saw the scope were alerted on then
+            state.alerted.get_or_insert_with(|| budget.reached(spent));
+            // Requests still in flight are expected to cost as much as the last one settled
+            let reserved = state.in_flight as f64 * state.last_cost_usd;
+            if budget.enforce && spent + reserved >= budget.limit_usd {
+                return Err(inference_errors::budget_exceeded(&scope, budget.limit_usd));
+            }
+            state.in_flight += 1;
+        }
+
+        Ok(Some(Settlement {
+            request_id: events::request_id(request).unwrap_or_default().to_string(),
+            scope,
+            budget,
+            pricing: self.pricing.clone(),
+            store: self.store.clone(),
+            scopes: Arc::clone(&self.scopes),
+            handlers: self.handlers.clone(),
+            events: self.events.clone(),
+            settled: false,
+        }))
+    }
+}
+
+/// Spend accounting of one admitted request, settled once its usage is known
+///
+/// Dropping it unsettled (the request failed or was cancelled) releases its reservation.
+struct Settlement {
+    request_id: String,
+    scope: String,
+    budget: BudgetLimit,
+    pricing: SharedPricing,
+    store: Option<Arc<dyn LedgerStore>>,
+    scopes: Scopes,
+    handlers: Vec<Arc<dyn BudgetAlertHandler>>,
+    events: Option<EventBus>,
+    settled: bool,
+}
+
+impl Settlement {
+    /// Add the cost of the request to its scope and notify the thresholds reached
+    async fn settle(mut self, model: &str, token_usage: &TokenUsage) {
+        let cost = self.pricing.cost(model, token_usage).unwrap_or(0.0);
+        let period = self.budget.period;
+        let now = Utc::now();
+        // Re-read the ledger after the request so spend by concurrent requests is included
+        let ledger_spent = match &self.store {
+            Some(store) => match ledger_spent(store.as_ref(), &self.scope, &period, now).await {
+                Ok(spent) => Some(spent),
+                Err(error) => {
+                    self.publish_error(&error);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let alerts = {
+            let mut scopes = self.scopes.lock().unwrap();
+            let state = scopes.entry(self.scope.clone()).or_default();
+            state.in_flight = state.in_flight.saturating_sub(1);
+            state.last_cost_usd = cost;
+            self.settled = true;
+            if self.store.is_none() {
+                state.add_spend(&period, now, cost);
+            }
+            match ledger_spent {
+                Some(spent) => state.alerts(&self.scope, &self.budget, spent),
+                None if self.store.is_none() => {
+                    let spent = state.spent(&period, now);
+                    state.alerts(&self.scope, &self.budget, spent)
+                }
+                None => Vec::new(),
+            }
+        };
+        // Handlers are notified concurrently, so the request waits for the slowest one only
+        let deliveries = alerts.iter().flat_map(|alert| {
+            self.handlers.iter().map(move |handler| {
+                Box::pin(handler.on_alert(alert.clone())) as BoxedFuture<'_, InferenceResult<()>>
+            })
+        });
+        for result in JoinAll::new(deliveries).await {
+            if let Err(error) = result {
+                self.publish_error(&error);
+            }
+        }
+    }
+
+    fn publish_error(&self, error: &TylError) {
+        if let Some(bus) = &self.events {
+            bus.publish(InferenceEvent::BudgetError {
+                request_id: self.request_id.clone(),
+                scope: self.scope.clone(),
+                error: error.to_string(),
+            });
+        }
+    }
+}
+
+impl Drop for Settlement {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        if let Some(state) = self.scopes.lock().unwrap().get_mut(&self.scope) {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for BudgetService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let Some(settlement) = self.admit(&request).await? else {
+            return self.inner.infer(request).await;
+        };
+        let response = self.inner.infer(request).await?;
+        settlement
+            .settle(&response.metadata.model, &response.metadata.token_usage)
+            .await;
+        Ok(response)
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let Some(settlement) = self.admit(&request).await? else {
+            return self.inner.infer_stream(request).await;
+        };
+        let requested_model = request.model_override.clone().unwrap_or_default();
+
+        let stream = self.inner.infer_stream(request).await?;
+        Ok(stream.finish_with(move |outcome| async move {
+            // A failed stream drops its settlement, releasing its reservation
+            if let Ok(summary) = outcome {
+                let model = summary.model.unwrap_or(requested_model);
+                let token_usage = summary.token_usage.unwrap_or_else(|| TokenUsage::new(0, 0));
+                settlement.settle(&model, &token_usage).await;
+            }
+            Ok(())
+        }))
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::http_client::HttpResponse;
+    use crate::ledger::{InMemoryLedgerStore, LedgerService, UsageRecord, SCOPE_METADATA_KEY};
+    use crate::pricing::ModelPricing;
+    use crate::MockInferenceService;
+
+    /// Every mock request costs exactly $1 (one completion token at $1M per million)
+    fn pricing() -> PricingTable {
+        PricingTable::new().with_model("gpt-4o-mini", ModelPricing::new(0.0, 1_000_000.0))
+    }
+
+    fn mock() -> MockInferenceService {
+        MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response("abcd")
+    }
+
+    fn service() -> BudgetService<MockInferenceService> {
+        BudgetService::new(mock()).with_pricing(pricing())
+    }
+
+    fn usage(scope: &str, cost_usd: f64, hours_ago: i64) -> UsageRecord {
+        UsageRecord::new(
+            scope,
+            "gpt-4o-mini",
+            ModelType::General,
+            TokenUsage::new(1, 1),
+        )
+        .with_cost(cost_usd)
+        .with_recorded_at(Utc::now() - chrono::Duration::hours(hours_ago))
+    }
+
+    fn request(scope: &str) -> InferenceRequest {
+        InferenceRequest::new("Hi", HashMap::new(), ModelType::General)
+            .with_metadata(SCOPE_METADATA_KEY, scope)
+    }
+
+    #[tokio::test]
+    async fn test_alerts_fire_once_per_threshold() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&alerts);
+        let service = service()
+            .with_budget("team-a", BudgetLimit::new(4.0))
+            .with_alert_handler(move |alert: BudgetAlert| {
+                let sink = Arc::clone(&sink);
+                async move { sink.lock().unwrap().push(alert) }
+            });
+
+        for _ in 0..2 {
+            service.infer(request("team-a")).await.unwrap();
+        }
+        assert_eq!(service.spent("team-a").await.unwrap(), 2.0);
+        assert_eq!(alerts.lock().unwrap().len(), 1);
+        assert_eq!(alerts.lock().unwrap()[0].threshold, 0.5);
+
+        for _ in 0..3 {
+            service.infer(request("team-a")).await.unwrap();
+        }
+        let thresholds: Vec<f64> = alerts.lock().unwrap().iter().map(|a| a.threshold).collect();
+        assert_eq!(thresholds, vec![0.5, 0.8, 1.0]);
+
+        // Scopes without a budget never alert
+        service.infer(request("team-b")).await.unwrap();
+        assert_eq!(alerts.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_alert_once() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&alerts);
+        let service = BudgetService::new(mock().with_latency(20))
+            .with_pricing(pricing())
+            .with_budget("team-a", BudgetLimit::new(4.0))
+            .with_alert_handler(move |alert: BudgetAlert| {
+                let sink = Arc::clone(&sink);
+                async move { sink.lock().unwrap().push(alert) }
+            });
+
+        // Both requests read $0 before either is charged; together they reach 50%
+        let (a, b) = tokio::join!(
+            service.infer(request("team-a")),
+            service.infer(request("team-a"))
+        );
+        assert!(a.is_ok() && b.is_ok());
+        let thresholds: Vec<f64> = alerts.lock().unwrap().iter().map(|a| a.threshold).collect();
+        assert_eq!(thresholds, vec![0.5]);
+        assert_eq!(alerts.lock().unwrap()[0].spent_usd, 2.0);
+
+        // Forgetting the scope re-arms its alerts
+        service.reset("team-a");
+        assert_eq!(service.spent("team-a").await.unwrap(), 0.0);
+        service.infer(request("team-a")).await.unwrap();
+        service.infer(request("team-a")).await.unwrap();
+        assert_eq!(alerts.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_enforced_budget_reserves_in_flight_requests() {
+        let service = BudgetService::new(mock().with_latency(20))
+            .with_pricing(pricing())
+            .with_default_budget(BudgetLimit::new(2.0).enforced());
+        service.infer(request("team-a")).await.unwrap();
+
+        // $1 spent and $1 expected from the first request in flight: the second is rejected
+        let (a, b) = tokio::join!(
+            service.infer(request("team-a")),
+            service.infer(request("team-a"))
+        );
+        assert!(a.is_ok());
+        assert!(b.unwrap_err().to_string().contains("Budget"));
+        assert_eq!(service.spent("team-a").await.unwrap(), 2.0);
+
+        // A failed request releases its reservation
+        let service = service.with_default_budget(BudgetLimit::new(3.0).enforced());
+        let failing = InferenceRequest::new("", HashMap::new(), ModelType::General)
+            .with_metadata(SCOPE_METADATA_KEY, "team-a");
+        assert!(service.infer(failing).await.is_err());
+        service.infer(request("team-a")).await.unwrap();
+    }
+
+    #[test]
+    fn test_in_memory_spend_is_pruned_to_the_period() {
+        let period = BudgetPeriod::rolling(Duration::from_secs(3600));
+        let start = Utc.with_ymd_and_hms(2024, 3, 14, 9, 0, 0).unwrap();
+        let mut state = ScopeState::default();
+        for second in 0..600 {
+            state.add_spend(&period, start + chrono::Duration::seconds(second), 0.5);
+        }
+        // One bucket per 3.5 seconds (1/1024th of an hour, rounded down to whole seconds)
+        assert!(state.spend.len() <= 200, "{}", state.spend.len());
+        assert_eq!(
+            state.spent(&period, start + chrono::Duration::minutes(30)),
+            300.0
+        );
+
+        assert_eq!(
+            state.spent(&period, start + chrono::Duration::hours(2)),
+            0.0
+        );
+        assert!(state.spend.is_empty());
+
+        let mut lifetime = ScopeState::default();
+        for day in 0..100 {
+            lifetime.add_spend(
+                &BudgetPeriod::Lifetime,
+                start + chrono::Duration::days(day),
+                1.0,
+            );
+        }
+        assert_eq!(lifetime.spend.len(), 1);
+        assert_eq!(lifetime.spent(&BudgetPeriod::Lifetime, start), 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_enforced_budget_rejects_requests() {
+        let service = service().with_default_budget(BudgetLimit::new(2.0).enforced());
+
+        service.infer(request("team-a")).await.unwrap();
+        service.infer(request("team-a")).await.unwrap();
+
+        let error = service.infer(request("team-a")).await.unwrap_err();
+        assert!(error.to_string().contains("Budget"));
+        assert!(service.infer(request("team-b")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_streams_are_charged_when_they_end() {
+        let service = service().with_default_budget(BudgetLimit::new(1.0).enforced());
+
+        let stream = service.infer_stream(request("team-a")).await.unwrap();
+        assert_eq!(service.spent("team-a").await.unwrap(), 0.0);
+        stream.assemble().await.unwrap();
+        assert_eq!(service.spent("team-a").await.unwrap(), 1.0);
+
+        let error = service.infer_stream(request("team-a")).await.unwrap_err();
+        assert!(error.to_string().contains("Budget"));
+    }
+
+    #[tokio::test]
+    async fn test_rolling_budget_reads_shared_ledger() {
+        let store = Arc::new(InMemoryLedgerStore::new());
+        // Spend from before a restart: $3 inside the window, $10 outside it
+        store.append(usage("team-a", 3.0, 1)).await.unwrap();
+        store.append(usage("team-a", 10.0, 30)).await.unwrap();
+
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&alerts);
+        let service =
+            BudgetService::new(LedgerService::new(mock(), store.clone()).with_pricing(pricing()))
+                .with_ledger(store.clone())
+                .with_pricing(pricing())
+                .with_budget(
+                    "team-a",
+                    BudgetLimit::new(5.0)
+                        .with_period(BudgetPeriod::rolling(Duration::from_secs(24 * 3600)))
+                        .enforced(),
+                )
+                .with_alert_handler(move |alert: BudgetAlert| {
+                    let sink = Arc::clone(&sink);
+                    async move { sink.lock().unwrap().push(alert) }
+                });
+
+        assert_eq!(service.spent("team-a").await.unwrap(), 3.0);
+
+        // $3 -> $4 crosses 80% only; 50% was already crossed before the restart
+        service.infer(request("team-a")).await.unwrap();
+        service.infer(request("team-a")).await.unwrap();
+        let thresholds: Vec<f64> = alerts.lock().unwrap().iter().map(|a| a.threshold).collect();
+        assert_eq!(thresholds, vec![0.8, 1.0]);
+        assert_eq!(store.len(), 4);
+
+        assert!(service.infer(request("team-a")).await.is_err());
+    }
+
+    #[test]
+    fn test_calendar_month_period_is_timezone_aware() {
+        let period = BudgetPeriod::monthly_in(chrono_tz::America::New_York);
+
+        // 02:00 UTC on March 1st is still February in New York (EST, UTC-5)
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 2, 0, 0).unwrap();
+        assert_eq!(
+            period.start(now),
+            Utc.with_ymd_and_hms(2024, 2, 1, 5, 0, 0).unwrap()
+        );
+        assert_eq!(
+            period.next_reset(now),
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 5, 0, 0).unwrap())
+        );
+
+        // April starts in daylight saving time (EDT, UTC-4)
+        let now = Utc.with_ymd_and_hms(2024, 4, 15, 12, 0, 0).unwrap();
+        assert_eq!(
+            period.start(now),
+            Utc.with_ymd_and_hms(2024, 4, 1, 4, 0, 0).unwrap()
+        );
+
+        let utc = BudgetPeriod::monthly();
+        let now = Utc.with_ymd_and_hms(2024, 12, 31, 23, 0, 0).unwrap();
+        assert_eq!(
+            utc.next_reset(now),
+            Some(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(BudgetPeriod::Lifetime.next_reset(now), None);
+    }
+
+    struct Hook {
+        status: u16,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    #[async_trait]
+    impl HttpTransport for Arc<Hook> {
+        async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+            self.requests.lock().unwrap().push(request);
+            Ok(HttpResponse::new(self.status, "ok"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_alerts() {
+        let hook = Arc::new(Hook {
+            status: 200,
+            requests: Mutex::new(Vec::new()),
+        });
+        let service = service()
+            .with_budget(
+                "team-a",
+                BudgetLimit::new(2.0)
+                    .with_period(BudgetPeriod::monthly())
+                    .with_thresholds(vec![0.8, 1.0]),
+            )
+            .with_alert_handler(
+                WebhookAlertHandler::new("https://hooks.example.com/finops", Arc::clone(&hook))
+                    .with_header("Authorization", "Bearer hook-token")
+                    .with_secret("s3cret"),
+            );
+
+        service.infer(request("team-a")).await.unwrap();
+        assert!(hook.requests.lock().unwrap().is_empty());
+        service.infer(request("team-a")).await.unwrap();
+
+        let requests = hook.requests.lock().unwrap();
+        // $1 -> $2 crosses both 80% and 100%
+        assert_eq!(requests.len(), 2);
+        let delivery = &requests[1];
+        assert_eq!(delivery.url, "https://hooks.example.com/finops");
+        assert_eq!(delivery.header("Authorization"), Some("Bearer hook-token"));
+        let body = delivery.body.as_deref().unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload["event"], "budget_alert");
+        assert_eq!(payload["scope"], "team-a");
+        assert_eq!(payload["threshold"], 1.0);
+        assert_eq!(payload["utilization"], 1.0);
+        assert_eq!(delivery.timeout, Some(Duration::from_secs(5)));
+
+        // The signature covers the timestamp, so a replayed delivery cannot be re-dated
+        let timestamp = delivery.header(WEBHOOK_TIMESTAMP_HEADER).unwrap();
+        assert!((Utc::now().timestamp() - timestamp.parse::<i64>().unwrap()).abs() < 60);
+        let signed = [timestamp.as_bytes(), b".", body].concat();
+        let signature = format!("sha256={}", hex(&hmac_sha256(b"s3cret", &signed)));
+        assert_eq!(
+            delivery.header(WEBHOOK_SIGNATURE_HEADER),
+            Some(signature.as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery_errors() {
+        let hook = Arc::new(Hook {
+            status: 503,
+            requests: Mutex::new(Vec::new()),
+        });
+        let handler = WebhookAlertHandler::new("https://hooks.example.com/finops", hook);
+        let alert = BudgetAlert {
+            scope: "team-a".to_string(),
+            threshold: 1.0,
+            spent_usd: 100.0,
+            limit_usd: 100.0,
+            triggered_at: Utc::now(),
+        };
+        let error = handler.send(&alert).await.unwrap_err();
+        assert!(error.to_string().contains("503"));
+
+        // Delivery failures do not fail the request that crossed the threshold, they are
+        // published on the event bus
+        let bus = EventBus::new();
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&published);
+        bus.subscribe(move |event: &InferenceEvent| sink.lock().unwrap().push(event.clone()));
+        let service = service()
+            .with_budget("team-a", BudgetLimit::new(1.0))
+            .with_alert_handler(handler)
+            .with_events(bus);
+        let request = request("team-a").with_metadata(events::REQUEST_ID_METADATA_KEY, "req-1");
+        assert!(service.infer(request).await.is_ok());
+
+        // $0 -> $1 reaches all three default thresholds
+        let published = published.lock().unwrap();
+        assert_eq!(published.len(), 3);
+        assert!(published.iter().all(|event| matches!(
+            event,
+            InferenceEvent::BudgetError { request_id, scope, error }
+                if request_id == "req-1" && scope == "team-a" && error.contains("503")
+        )));
+    }
+
+    #[test]
+    fn test_alert_utilization() {
+        let alert = BudgetAlert {
+            scope: "team-a".to_string(),
+            threshold: 0.8,
+            spent_usd: 85.0,
+            limit_usd: 100.0,
+            triggered_at: Utc::now(),
+        };
+        assert!((alert.utilization() - 0.85).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,159 @@
+//! Cancellation support for in-flight inference
+//!
+//! A `CancellationToken` is handed to `InferenceService::infer_with_cancellation`; calling
+//! `cancel()` from anywhere (e.g. when the end user navigates away) makes the pending call return
+//! `inference_errors::request_cancelled`. The in-flight future is dropped, which aborts the
+//! underlying HTTP request for adapters built on async HTTP clients.
+
+use crate::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+/// Cloneable handle used to cancel in-flight inference requests
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every operation observing this token (or one of its clones)
+    pub fn cancel(&self) {
+        if self.state.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let wakers = std::mem::take(&mut *self.state.wakers.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Future that completes once the token is cancelled
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled { token: self }
+    }
+}
+
+/// Future returned by `CancellationToken::cancelled`
+#[derive(Debug)]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        {
+            let mut wakers = self.token.state.wakers.lock().unwrap();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+
+        // Re-check to avoid missing a cancel that happened while registering
+        if self.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Drive `future` to completion unless `token` is cancelled first
+///
+/// On cancellation the future is dropped and `inference_errors::request_cancelled` is returned.
+pub async fn run_until_cancelled<T, F>(token: &CancellationToken, future: F) -> InferenceResult<T>
+where
+    F: Future<Output = InferenceResult<T>>,
+{
+    if token.is_cancelled() {
+        return Err(inference_errors::request_cancelled());
+    }
+
+    let mut future = std::pin::pin!(future);
+    let mut cancelled = std::pin::pin!(token.cancelled());
+
+    std::future::poll_fn(|cx| {
+        if cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(inference_errors::request_cancelled()));
+        }
+        future.as_mut().poll(cx)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_state() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+
+        // Cancelling twice is harmless
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_run_until_cancelled_completes() {
+        let token = CancellationToken::new();
+        let result = run_until_cancelled(&token, async { Ok(42) }).await;
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_run_until_cancelled_aborts() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            canceller.cancel();
+        });
+
+        let result: InferenceResult<()> = run_until_cancelled(&token, async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(())
+        })
+        .await;
+
+        assert!(result.unwrap_err().to_string().contains("cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_already_cancelled_token_skips_work() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let result: InferenceResult<()> =
+            run_until_cancelled(&token, async { panic!("should not run") }).await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Canonical JSON for cache keys and content hashing
+//!
+//! Different serializers may emit the same JSON with different key order, whitespace, or number
+//! formatting (`1.0` vs `1`). `to_canonical_string` produces a single representation (sorted
+//! keys, no insignificant whitespace, integral numbers without a fraction) so semantically
+//! identical values hash identically wherever caching, deduplication, or content hashes are used.
+
+use crate::*;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// Largest integer exactly representable as an f64
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Serialize a JSON value in canonical form
+pub fn to_canonical_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) | Value::String(_) => out.push_str(&value.to_string()),
+        Value::Number(number) => write_number(number, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_number(number: &serde_json::Number, out: &mut String) {
+    if number.is_i64() || number.is_u64() {
+        out.push_str(&number.to_string());
+        return;
+    }
+    match number.as_f64() {
+        Some(float) if float.fract() == 0.0 && float.abs() < MAX_SAFE_INTEGER => {
+            // Integral floats (including -0.0) are written as integers
+            let _ = write!(out, "{}", float as i64);
+        }
+        _ => out.push_str(&number.to_string()),
+    }
+}
+
+/// Hex-encoded SHA-256 of the canonical form of a JSON value
+pub fn content_hash(value: &Value) -> String {
+    let digest = Sha256::digest(to_canonical_string(value).as_bytes());
+    digest
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Content hash of the fields that determine a request's output
+///
+/// Scheduling and tracing fields (priority, timeouts, deadlines, idempotency key, metadata)
+/// are excluded so retries and re-serialized copies of a request share a fingerprint.
+pub fn request_fingerprint(request: &InferenceRequest) -> String {
+    let mut fields = serde_json::json!({
+        "template": request.template,
+        "parameters": request.parameters,
+        "model_type": request.model_type,
+        "model_override": request.model_override,
+        "max_tokens": request.max_tokens,
+        "temperature": request.effective_temperature(),
+    });
+    // Sampling controls only when set, so fingerprints of requests without them are unchanged
+    let optional = [
+        ("top_p", request.top_p),
+        ("frequency_penalty", request.frequency_penalty),
+        ("presence_penalty", request.presence_penalty),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            fields[name] = value.into();
+        }
+    }
+    if !request.stop.is_empty() {
+        fields["stop"] = serde_json::json!(request.stop);
+    }
+    if let Some(seed) = request.seed {
+        fields["seed"] = seed.into();
+    }
+    if request.candidate_count() > 1 {
+        fields["candidates"] = request.candidate_count().into();
+    }
+    if let Some(logprobs) = request.logprobs {
+        fields["logprobs"] = logprobs.into();
+    }
+    content_hash(&fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_string_is_serializer_independent() {
+        let a: Value =
+            serde_json::from_str(r#"{ "b": [1.0, 2.5, -0.0], "a": {"y": null, "x": true} }"#)
+                .unwrap();
+        let b: Value = serde_json::from_str(r#"{"a":{"x":true,"y":null},"b":[1,2.5,0]}"#).unwrap();
+
+        assert_eq!(
+            to_canonical_string(&a),
+            r#"{"a":{"x":true,"y":null},"b":[1,2.5,0]}"#
+        );
+        assert_eq!(to_canonical_string(&a), to_canonical_string(&b));
+        assert_eq!(content_hash(&a), content_hash(&b));
+        assert_eq!(content_hash(&a).len(), 64);
+    }
+
+    #[test]
+    fn test_strings_are_escaped() {
+        let value = serde_json::json!({"quote\"key": "line\nbreak"});
+        assert_eq!(
+            to_canonical_string(&value),
+            r#"{"quote\"key":"line\nbreak"}"#
+        );
+    }
+
+    #[test]
+    fn test_request_fingerprint_ignores_scheduling_fields() {
+        let mut params = HashMap::new();
+        params.insert("name".to_string(), "Juan".to_string());
+        let request = InferenceRequest::new("Hello {{name}}!", params, ModelType::General);
+
+        let retried = request
+            .clone()
+            .with_priority(Priority::High)
+            .with_idempotency_key("retry-1")
+            .with_metadata("trace_id", "abc");
+        assert_eq!(request_fingerprint(&request), request_fingerprint(&retried));
+
+        let changed = request.clone().with_temperature(0.2);
+        assert_ne!(request_fingerprint(&request), request_fingerprint(&changed));
+        let stopped = request.clone().with_stop("END");
+        assert_ne!(request_fingerprint(&request), request_fingerprint(&stopped));
+    }
+}
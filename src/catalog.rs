@@ -0,0 +1,430 @@
+//! Model capability registry
+//!
+//! A `ModelCatalog` describes what each known model can do: its context window, the most tokens
+//! it generates per response, the features it supports and its price. Adapters and routers ask
+//! the catalog instead of hard-coding per-model constants:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::catalog::*;
+//! # async fn example(service: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! let catalog = ModelCatalog::with_defaults().with_model(
+//!     ModelInfo::new("llama3:8b", 8192, 2048)
+//!         .with_provider("ollama")
+//!         .with_features(&[ModelFeature::Streaming, ModelFeature::JsonMode]),
+//! );
+//! let info = catalog
+//!     .for_request(&request)
+//!     .ok_or_else(|| TylError::validation("model", "Unknown model"))?;
+//! request.validate_with(&service, &info.request_limits())?;
+//! catalog.validate_request(&request)?; // e.g. image attachments need a vision model
+//! let vision_models = catalog.models_with(ModelFeature::Vision);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Lookups fall back to the longest registered prefix, like `PricingTable`, so dated model
+//! versions resolve to their family. Catalogs serialize to and from JSON, TOML or YAML.
+//!
+//! Models a provider serves are listed at runtime with `InferenceService::list_models`; adapters
+//! with a models endpoint cache the listing in a `ModelListCache`. `ModelCatalog::discover`
+//! registers newly listed models, so they become routable without a code change:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::catalog::*;
+//! # async fn example(mut catalog: ModelCatalog, ollama: impl InferenceService + 'static) -> TylResult<()> {
+//! let added = catalog
+//!     .discover(&ollama, &ModelInfo::new("", 8192, 2048).with_provider("ollama"))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pricing::{ModelPricing, PricingTable};
+use crate::validation::RequestLimits;
+use crate::*;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// How long adapters reuse a model listing by default
+pub const DEFAULT_MODEL_LIST_TTL: Duration = Duration::from_secs(300);
+
+/// Optional capability of a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelFeature {
+    /// Function/tool calling
+    Tools,
+    /// Image inputs
+    Vision,
+    /// Constrained JSON output
+    JsonMode,
+    /// Token-by-token streaming
+    Streaming,
+}
+
+/// Capabilities and limits of one model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    /// Provider serving the model, e.g. `openai` or `ollama`
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Prompt and completion tokens the model can attend to
+    pub context_window: usize,
+    /// Most tokens generated in one response
+    pub max_output_tokens: usize,
+    #[serde(default)]
+    pub features: BTreeSet<ModelFeature>,
+    #[serde(default)]
+    pub pricing: Option<ModelPricing>,
+}
+
+impl ModelInfo {
+    pub fn new(name: impl Into<String>, context_window: usize, max_output_tokens: usize) -> Self {
+        Self {
+            name: name.into(),
+            provider: None,
+            context_window,
+            max_output_tokens,
+            features: BTreeSet::new(),
+            pricing: None,
+        }
+    }
+
+    pub fn with_provider(mut self, provider: impl Into<String>) -> Self {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    pub fn with_features(mut self, features: &[ModelFeature]) -> Self {
+        self.features.extend(features.iter().copied());
+        self
+    }
+
+    pub fn with_pricing(mut self, pricing: ModelPricing) -> Self {
+        self.pricing = Some(pricing);
+        self
+    }
+
+    pub fn supports(&self, feature: ModelFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Default request limits with this model's context window
+    pub fn request_limits(&self) -> RequestLimits {
+        RequestLimits::default().with_context_window(self.context_window)
+    }
+
+    /// Largest completion a request can ask for when its prompt takes `prompt_tokens`
+    pub fn completion_budget(&self, prompt_tokens: usize) -> usize {
+        self.context_window
+            .saturating_sub(prompt_tokens)
+            .min(self.max_output_tokens)
+    }
+}
+
+/// Registry of known models keyed by name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelCatalog {
+    models: HashMap<String, ModelInfo>,
+}
+
+impl ModelCatalog {
+    /// Create an empty catalog
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a catalog with the default OpenAI and Anthropic models
+    pub fn with_defaults() -> Self {
+        use ModelFeature::*;
+        Self::new()
+            .with_model(
+                ModelInfo::new("gpt-4o", 128_000, 16_384)
+                    .with_provider("openai")
+                    .with_features(&[Tools, Vision, JsonMode, Streaming])
+                    .with_pricing(ModelPricing::new(2.50, 10.00)),
+            )
+            .with_model(
+                ModelInfo::new("gpt-4o-mini", 128_000, 16_384)
+                    .with_provider("openai")
+                    .with_features(&[Tools, Vision, JsonMode, Streaming])
+                    .with_pricing(ModelPricing::new(0.15, 0.60)),
+            )
+            .with_model(
+                ModelInfo::new("gpt-3.5-turbo", 16_385, 4_096)
+                    .with_provider("openai")
+                    .with_features(&[Tools, JsonMode, Streaming])
+                    .with_pricing(ModelPricing::new(0.50, 1.50)),
+            )
+            .with_model(
+                ModelInfo::new("claude-3-5-sonnet", 200_000, 8_192)
+                    .with_provider("anthropic")
+                    .with_features(&[Tools, Vision, Streaming])
+                    .with_pricing(ModelPricing::new(3.00, 15.00)),
+            )
+            .with_model(
+                ModelInfo::new("claude-3-5-haiku", 200_000, 8_192)
+                    .with_provider("anthropic")
+                    .with_features(&[Tools, Streaming])
+                    .with_pricing(ModelPricing::new(0.80, 4.00)),
+            )
+    }
+
+    pub fn with_model(mut self, info: ModelInfo) -> Self {
+        self.set_model(info);
+        self
+    }
+
+    /// Add or replace a model
+    pub fn set_model(&mut self, info: ModelInfo) {
+        self.models.insert(info.name.clone(), info);
+    }
+
+    /// Look up a model, falling back to the longest matching prefix
+    pub fn get(&self, model: &str) -> Option<&ModelInfo> {
+        self.models.get(model).or_else(|| {
+            self.models
+                .iter()
+                .filter(|(name, _)| model.starts_with(name.as_str()))
+                .max_by_key(|(name, _)| name.len())
+                .map(|(_, info)| info)
+        })
+    }
+
+    /// Model `request` resolves to: its override, or the optimal model of its model type
+    pub fn for_request(&self, request: &InferenceRequest) -> Option<&ModelInfo> {
+        match &request.model_override {
+            Some(model) => self.get(model),
+            None => self.get(request.model_type.optimal_openai_model()),
+        }
+    }
+
+    /// Reject requests using a feature their cataloged model lacks, such as image attachments
+    /// for a model without `ModelFeature::Vision`; models missing from the catalog pass
+    pub fn validate_request(&self, request: &InferenceRequest) -> InferenceResult<()> {
+        match self.for_request(request) {
+            Some(info)
+                if !request.attachments.is_empty() && !info.supports(ModelFeature::Vision) =>
+            {
+                Err(inference_errors::unsupported_feature(
+                    &info.name,
+                    "image inputs",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Every model, sorted by name
+    pub fn models(&self) -> Vec<&ModelInfo> {
+        let mut models: Vec<&ModelInfo> = self.models.values().collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        models
+    }
+
+    /// Models supporting `feature`, sorted by name
+    pub fn models_with(&self, feature: ModelFeature) -> Vec<&ModelInfo> {
+        self.models()
+            .into_iter()
+            .filter(|info| info.supports(feature))
+            .collect()
+    }
+
+    /// Pricing table of the priced models
+    pub fn pricing_table(&self) -> PricingTable {
+        self.models
+            .values()
+            .filter_map(|info| info.pricing.map(|pricing| (info.name.clone(), pricing)))
+            .fold(PricingTable::new(), |table, (name, pricing)| {
+                table.with_model(name, pricing)
+            })
+    }
+
+    /// Register the models `service` lists that the catalog does not know yet, as copies of
+    /// `template` renamed to each model; returns the added names
+    pub async fn discover<S: InferenceService + ?Sized>(
+        &mut self,
+        service: &S,
+        template: &ModelInfo,
+    ) -> InferenceResult<Vec<String>> {
+        let mut added = Vec::new();
+        for model in service.list_models().await? {
+            if self.get(&model).is_some() {
+                continue;
+            }
+            self.set_model(ModelInfo {
+                name: model.clone(),
+                ..template.clone()
+            });
+            added.push(model);
+        }
+        Ok(added)
+    }
+
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+}
+
+/// Model listing fetched from a provider and reused until it expires
+#[derive(Debug)]
+pub struct ModelListCache {
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, Vec<String>)>>,
+}
+
+impl Default for ModelListCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MODEL_LIST_TTL)
+    }
+}
+
+impl ModelListCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// The cached listing while fresh, otherwise the result of `fetch`
+    ///
+    /// Failed fetches are not cached.
+    pub async fn get_or_fetch<F, Fut>(&self, fetch: F) -> InferenceResult<Vec<String>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = InferenceResult<Vec<String>>>,
+    {
+        if let Some((fetched_at, models)) = &*self.cached.lock().unwrap() {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(models.clone());
+            }
+        }
+        let models = fetch().await?;
+        *self.cached.lock().unwrap() = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    /// Drop the cached listing so the next call fetches
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_and_feature_queries() {
+        let catalog = ModelCatalog::with_defaults().with_model(
+            ModelInfo::new("llama3:8b", 8192, 2048)
+                .with_provider("ollama")
+                .with_features(&[ModelFeature::Streaming]),
+        );
+        let sonnet = catalog.get("claude-3-5-sonnet-20241022").unwrap();
+        assert_eq!(sonnet.context_window, 200_000);
+        assert!(!sonnet.supports(ModelFeature::JsonMode));
+        assert!(catalog.get("mistral-large").is_none());
+
+        let vision: Vec<&str> = catalog
+            .models_with(ModelFeature::Vision)
+            .iter()
+            .map(|info| info.name.as_str())
+            .collect();
+        assert_eq!(vision, vec!["claude-3-5-sonnet", "gpt-4o", "gpt-4o-mini"]);
+        assert_eq!(catalog.models_with(ModelFeature::Streaming).len(), 6);
+
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast);
+        assert_eq!(catalog.for_request(&request).unwrap().name, "gpt-3.5-turbo");
+        let request = request.with_model("llama3:8b");
+        let info = catalog.for_request(&request).unwrap();
+        assert_eq!(info.request_limits().context_window, Some(8192));
+        assert_eq!(info.completion_budget(7000), 1192);
+        assert_eq!(info.completion_budget(1000), 2048);
+
+        let request =
+            request.with_attachment(crate::MediaInput::from_bytes("image/png", vec![0x89]));
+        assert!(catalog
+            .validate_request(&request)
+            .unwrap_err()
+            .to_string()
+            .contains("Model llama3:8b does not support image inputs"));
+        let request = request.with_model("gpt-4o-2024-08-06");
+        assert!(catalog.validate_request(&request).is_ok());
+        let request = request.with_model("unlisted");
+        assert!(catalog.validate_request(&request).is_ok());
+    }
+
+    #[test]
+    fn test_serialization_and_pricing() {
+        let catalog: ModelCatalog = serde_json::from_value(serde_json::json!({
+            "models": {
+                "local": {
+                    "name": "local",
+                    "context_window": 4096,
+                    "max_output_tokens": 1024,
+                    "features": ["json_mode", "streaming"],
+                    "pricing": { "input_per_million_usd": 0.0, "output_per_million_usd": 0.0 }
+                }
+            }
+        }))
+        .unwrap();
+        assert!(catalog
+            .get("local")
+            .unwrap()
+            .supports(ModelFeature::JsonMode));
+        assert_eq!(
+            catalog
+                .pricing_table()
+                .cost("local", &TokenUsage::new(10, 10)),
+            Some(0.0)
+        );
+        assert_eq!(
+            ModelCatalog::with_defaults()
+                .pricing_table()
+                .get("gpt-4o-2024-08-06"),
+            Some(&ModelPricing::new(2.50, 10.00))
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_discover_and_cache_listings() {
+        let mut catalog = ModelCatalog::with_defaults();
+        let service = crate::mock::MockInferenceService::new();
+        let template = ModelInfo::new("", 4096, 1024).with_provider("mock");
+        let added = catalog.discover(&service, &template).await.unwrap();
+        assert!(!added.is_empty());
+        let info = catalog.get(&added[0]).unwrap();
+        assert_eq!(info.name, added[0]);
+        assert_eq!(info.provider.as_deref(), Some("mock"));
+        assert!(catalog
+            .discover(&service, &template)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let cache = ModelListCache::new(Duration::from_secs(60));
+        let fetches = std::sync::atomic::AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(vec!["a".to_string()])
+        };
+        assert_eq!(cache.get_or_fetch(fetch).await.unwrap(), vec!["a"]);
+        assert_eq!(cache.get_or_fetch(fetch).await.unwrap(), vec!["a"]);
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::Relaxed), 1);
+        cache.invalidate();
+        cache.get_or_fetch(fetch).await.unwrap();
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,342 @@
+//! Token-aware text chunking
+//!
+//! Retrieval, map-reduce and summarization all need long text cut into pieces that fit a token
+//! budget. `TextChunker` measures chunks with a `TokenCounter` (any `InferenceService` is one, so
+//! chunks match the tokenizer of the model that will read them) and cuts at the coarsest
+//! boundary that fits: paragraphs, then sentences, then words. Consecutive chunks can overlap
+//! so that facts spanning a cut appear whole in at least one chunk:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::chunking::*;
+//! # async fn example(service: impl InferenceService + 'static, document: String) -> TylResult<()> {
+//! # let mut index = std::collections::BTreeMap::new();
+//! let chunks = TextChunker::new(512).with_overlap(64).split(&service, &document)?;
+//! for chunk in &chunks {
+//!     index.insert(&document[chunk.start..chunk.end], chunk.tokens);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Chunks carry their byte range in the source text so citations can point back into it.
+
+use crate::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Counts tokens the way a model's tokenizer does
+pub trait TokenCounter {
+    fn count(&self, text: &str) -> InferenceResult<usize>;
+}
+
+impl<S: InferenceService + ?Sized> TokenCounter for S {
+    fn count(&self, text: &str) -> InferenceResult<usize> {
+        self.count_tokens(text)
+    }
+}
+
+/// Tokenizer-free estimate of about four characters per token
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApproximateTokenCounter;
+
+impl TokenCounter for ApproximateTokenCounter {
+    fn count(&self, text: &str) -> InferenceResult<usize> {
+        Ok((text.len() + 3) / 4)
+    }
+}
+
+/// Coarsest boundary chunks are cut at; pieces over the budget fall back to finer ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChunkBoundary {
+    /// Blank-line separated paragraphs
+    #[default]
+    Paragraph,
+    Sentence,
+    Word,
+}
+
+/// A piece of the source text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextChunk {
+    /// Chunk text, without leading or trailing whitespace
+    pub text: String,
+    /// Byte offset of `text` in the source, overlap included
+    pub start: usize,
+    /// Byte offset just past `text` in the source
+    pub end: usize,
+    pub tokens: usize,
+}
+
+/// Splits text into chunks of at most `max_tokens` tokens
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextChunker {
+    pub max_tokens: usize,
+    /// Tokens repeated from the end of the previous chunk, included in `max_tokens`
+    pub overlap_tokens: usize,
+    pub boundary: ChunkBoundary,
+}
+
+/// Granularity of a split, coarsest first
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Paragraph,
+    Sentence,
+    Word,
+    Grapheme,
+}
+
+impl Level {
+    fn finer(self) -> Option<Level> {
+        match self {
+            Level::Paragraph => Some(Level::Sentence),
+            Level::Sentence => Some(Level::Word),
+            Level::Word => Some(Level::Grapheme),
+            Level::Grapheme => None,
+        }
+    }
+
+    /// Contiguous pieces of `text` with their byte offsets
+    fn split(self, text: &str) -> Vec<(usize, &str)> {
+        match self {
+            Level::Paragraph => {
+                let mut pieces = Vec::new();
+                let mut start = 0;
+                while let Some(found) = text[start..].find("\n\n") {
+                    // Keep the whole run of newlines with the paragraph before it
+                    let mut end = start + found;
+                    while text[end..].starts_with('\n') {
+                        end += 1;
+                    }
+                    pieces.push((start, &text[start..end]));
+                    start = end;
+                }
+                if start < text.len() {
+                    pieces.push((start, &text[start..]));
+                }
+                pieces
+            }
+            Level::Sentence => text.split_sentence_bound_indices().collect(),
+            Level::Word => text.split_word_bound_indices().collect(),
+            Level::Grapheme => text.grapheme_indices(true).collect(),
+        }
+    }
+}
+
+impl TextChunker {
+    /// Paragraph-aware chunks of `max_tokens` without overlap
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens: max_tokens.max(1),
+            overlap_tokens: 0,
+            boundary: ChunkBoundary::Paragraph,
+        }
+    }
+
+    /// Repeat up to `overlap_tokens` of the previous chunk (cut between words) at the start of
+    /// every chunk; at most half the budget
+    pub fn with_overlap(mut self, overlap_tokens: usize) -> Self {
+        self.overlap_tokens = overlap_tokens.min(self.max_tokens / 2);
+        self
+    }
+
+    pub fn with_boundary(mut self, boundary: ChunkBoundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Split `text`; whitespace-only text gives no chunks
+    pub fn split<C: TokenCounter + ?Sized>(
+        &self,
+        counter: &C,
+        text: &str,
+    ) -> InferenceResult<Vec<TextChunk>> {
+        let budget = self.max_tokens - self.overlap_tokens;
+        let level = match self.boundary {
+            ChunkBoundary::Paragraph => Level::Paragraph,
+            ChunkBoundary::Sentence => Level::Sentence,
+            ChunkBoundary::Word => Level::Word,
+        };
+        let mut packer = Packer {
+            counter,
+            source: text,
+            budget,
+            ranges: Vec::new(),
+            current: None,
+        };
+        packer.pack(0, text.len(), level)?;
+        let ranges = packer.finish();
+
+        let mut chunks = Vec::with_capacity(ranges.len());
+        for (index, (start, end)) in ranges.iter().copied().enumerate() {
+            let start = match index.checked_sub(1) {
+                Some(previous) if self.overlap_tokens > 0 => {
+                    self.overlap_start(counter, text, ranges[previous].0, start)?
+                }
+                _ => start,
+            };
+            let slice = &text[start..end];
+            let trimmed_start = start + (slice.len() - slice.trim_start().len());
+            let trimmed_end = start + slice.trim_end().len();
+            if trimmed_start >= trimmed_end {
+                continue;
+            }
+            let text = &text[trimmed_start..trimmed_end];
+            chunks.push(TextChunk {
+                text: text.to_string(),
+                start: trimmed_start,
+                end: trimmed_end,
+                tokens: counter.count(text)?,
+            });
+        }
+        Ok(chunks)
+    }
+
+    /// Earliest word boundary in `previous_start..start` whose tail fits the overlap budget
+    fn overlap_start<C: TokenCounter + ?Sized>(
+        &self,
+        counter: &C,
+        text: &str,
+        previous_start: usize,
+        start: usize,
+    ) -> InferenceResult<usize> {
+        let mut overlap_start = start;
+        let words: Vec<usize> = text[previous_start..start]
+            .split_word_bound_indices()
+            .map(|(offset, _)| previous_start + offset)
+            .collect();
+        for word_start in words.into_iter().rev() {
+            if counter.count(&text[word_start..start])? > self.overlap_tokens {
+                break;
+            }
+            overlap_start = word_start;
+        }
+        Ok(overlap_start)
+    }
+}
+
+/// Greedily packs consecutive pieces of `source` into byte ranges within `budget`
+struct Packer<'a, C: ?Sized> {
+    counter: &'a C,
+    source: &'a str,
+    budget: usize,
+    ranges: Vec<(usize, usize)>,
+    current: Option<(usize, usize)>,
+}
+
+impl<C: TokenCounter + ?Sized> Packer<'_, C> {
+    /// Pack `source[start..end]` split at `level`
+    ///
+    /// A piece over the budget starts a new chunk and is packed at the next finer level, so a
+    /// chunk never mixes the end of one paragraph with a fragment of the next.
+    fn pack(&mut self, start: usize, end: usize, level: Level) -> InferenceResult<()> {
+        let source = self.source;
+        for (offset, piece) in level.split(&source[start..end]) {
+            let (piece_start, piece_end) = (start + offset, start + offset + piece.len());
+            match level.finer() {
+                Some(finer) if self.counter.count(piece)? > self.budget => {
+                    self.ranges.extend(self.current.take());
+                    self.pack(piece_start, piece_end, finer)?;
+                }
+                _ => self.push(piece_start, piece_end)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, start: usize, end: usize) -> InferenceResult<()> {
+        self.current = match self.current {
+            Some((chunk_start, chunk_end))
+                if self.counter.count(&self.source[chunk_start..end])? > self.budget =>
+            {
+                self.ranges.push((chunk_start, chunk_end));
+                Some((start, end))
+            }
+            Some((chunk_start, _)) => Some((chunk_start, end)),
+            None => Some((start, end)),
+        };
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<(usize, usize)> {
+        self.ranges.extend(self.current.take());
+        self.ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whitespace-separated words as tokens
+    struct Words;
+
+    impl TokenCounter for Words {
+        fn count(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.split_whitespace().count())
+        }
+    }
+
+    fn texts(chunks: &[TextChunk]) -> Vec<&str> {
+        chunks.iter().map(|chunk| chunk.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_prefers_paragraphs_then_sentences_then_words() {
+        let text =
+            "One two three.\n\nFour five. Six seven.\n\nEight nine ten eleven twelve thirteen.";
+        let chunks = TextChunker::new(5).split(&Words, text).unwrap();
+        assert_eq!(
+            texts(&chunks),
+            vec![
+                "One two three.",
+                "Four five. Six seven.",
+                "Eight nine ten eleven twelve",
+                "thirteen."
+            ]
+        );
+        for chunk in &chunks {
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+            assert!(chunk.tokens <= 5);
+        }
+
+        let text = "One two three. Four five. Six seven eight nine ten eleven. Twelve.";
+        let sentences = TextChunker::new(5)
+            .with_boundary(ChunkBoundary::Sentence)
+            .split(&Words, text)
+            .unwrap();
+        assert_eq!(
+            texts(&sentences),
+            vec![
+                "One two three. Four five.",
+                "Six seven eight nine ten",
+                "eleven. Twelve."
+            ]
+        );
+        assert!(TextChunker::new(5)
+            .split(&Words, " \n\n ")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_overlap_repeats_the_end_of_the_previous_chunk() {
+        let text = "a b c d e f g h i j";
+        let chunks = TextChunker::new(6)
+            .with_overlap(2)
+            .with_boundary(ChunkBoundary::Word)
+            .split(&Words, text)
+            .unwrap();
+        assert_eq!(texts(&chunks), vec!["a b c d", "c d e f g h", "g h i j"]);
+        assert!(chunks.iter().all(|chunk| chunk.tokens <= 6));
+    }
+
+    #[test]
+    fn test_approximate_counter() {
+        assert_eq!(ApproximateTokenCounter.count("abcdefgh").unwrap(), 2);
+        let chunks = TextChunker::new(3)
+            .split(&ApproximateTokenCounter, "Hello world. Bye now.")
+            .unwrap();
+        assert!(chunks.iter().all(|chunk| chunk.tokens <= 3));
+        assert_eq!(texts(&chunks), vec!["Hello world.", "Bye now."]);
+    }
+}
@@ -0,0 +1,190 @@
+//! Prompt compression helpers
+//!
+//! Truncation works on grapheme clusters and Unicode (UAX #29) sentence and word boundaries
+//! rather than bytes or `char`s, so CJK, Arabic/Hebrew (RTL), Devanagari, combining marks, and
+//! emoji sequences are never cut mid-character. Word-level truncation also drops dangling stop
+//! words ("the", "de", "und"...) so the kept text does not end on a function word.
+
+use crate::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Stop words trimmed from the end of word-truncated text (English, Spanish, French, German,
+/// Portuguese, Italian)
+const STOP_WORDS: &[&str] = &[
+    // English
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into", "of", "on", "or", "the",
+    "to", "with", // Spanish
+    "con", "de", "del", "el", "en", "la", "las", "los", "para", "por", "que", "un", "una", "y",
+    // French
+    "au", "aux", "des", "du", "et", "le", "les", "ou", "pour", "sur", // German
+    "der", "die", "das", "dem", "den", "ein", "eine", "mit", "und", "von", "zu",
+    // Portuguese
+    "da", "do", "dos", "das", "e", "em", "na", "no", "o", "os", "um", "uma", // Italian
+    "di", "il", "lo", "per",
+];
+
+/// Where truncated text may end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TruncationBoundary {
+    /// End after a complete sentence, falling back to a word boundary
+    #[default]
+    Sentence,
+    /// End after a complete word, without trailing stop words
+    Word,
+    /// End after any complete grapheme cluster
+    Grapheme,
+}
+
+/// Number of user-perceived characters (extended grapheme clusters) in `text`
+pub fn grapheme_len(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Truncate `text` to at most `max_graphemes` grapheme clusters, ending on `boundary`
+///
+/// Text that already fits is returned unchanged; truncated text has trailing whitespace removed.
+pub fn truncate(text: &str, max_graphemes: usize, boundary: TruncationBoundary) -> String {
+    let Some((cut, _)) = text.grapheme_indices(true).nth(max_graphemes) else {
+        return text.to_string();
+    };
+
+    let end = match boundary {
+        TruncationBoundary::Sentence => sentence_end(text, cut).or_else(|| word_end(text, cut)),
+        TruncationBoundary::Word => word_end(text, cut),
+        TruncationBoundary::Grapheme => None,
+    }
+    .unwrap_or(cut);
+
+    text[..end].trim_end().to_string()
+}
+
+/// Truncate every parameter of a request to at most `max_graphemes`
+pub fn truncate_parameters(
+    mut request: InferenceRequest,
+    max_graphemes: usize,
+    boundary: TruncationBoundary,
+) -> InferenceRequest {
+    for value in request.parameters.values_mut() {
+        if grapheme_len(value) > max_graphemes {
+            *value = truncate(value, max_graphemes, boundary);
+        }
+    }
+    request
+}
+
+/// Byte offset of the last sentence boundary at or before `cut`
+fn sentence_end(text: &str, cut: usize) -> Option<usize> {
+    text.split_sentence_bound_indices()
+        .map(|(start, sentence)| start + sentence.len())
+        .take_while(|end| *end <= cut)
+        .last()
+        .filter(|end| *end > 0)
+}
+
+/// Byte offset of the last word boundary at or before `cut`, skipping trailing stop words
+fn word_end(text: &str, cut: usize) -> Option<usize> {
+    let words: Vec<(usize, &str)> = text
+        .split_word_bound_indices()
+        .take_while(|(start, word)| start + word.len() <= cut)
+        .collect();
+
+    let mut kept = words.len();
+    // Drop trailing separators, then trailing stop words with their separators
+    loop {
+        while kept > 0 && !is_word(words[kept - 1].1) {
+            kept -= 1;
+        }
+        if kept > 1 && is_stop_word(words[kept - 1].1) {
+            kept -= 1;
+            continue;
+        }
+        break;
+    }
+
+    words[..kept]
+        .last()
+        .map(|(start, word)| start + word.len())
+        .filter(|end| *end > 0)
+}
+
+fn is_word(segment: &str) -> bool {
+    segment.chars().any(char::is_alphanumeric)
+}
+
+fn is_stop_word(word: &str) -> bool {
+    STOP_WORDS.contains(&word.to_lowercase().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_within_budget_is_unchanged() {
+        assert_eq!(
+            truncate("Short.", 10, TruncationBoundary::Sentence),
+            "Short."
+        );
+        assert_eq!(grapheme_len("🇪🇸👩‍💻é"), 3);
+    }
+
+    #[test]
+    fn test_sentence_boundaries_in_cjk_and_rtl_text() {
+        let chinese = "今天天气很好。我们去公园散步吧！然后一起吃晚饭。";
+        assert_eq!(
+            truncate(chinese, 18, TruncationBoundary::Sentence),
+            "今天天气很好。我们去公园散步吧！"
+        );
+
+        let arabic = "مرحبا بكم. كيف حالكم اليوم؟ نحن بخير.";
+        assert_eq!(
+            truncate(arabic, 30, TruncationBoundary::Sentence),
+            "مرحبا بكم. كيف حالكم اليوم؟"
+        );
+    }
+
+    #[test]
+    fn test_grapheme_clusters_are_never_split() {
+        // Family emoji (ZWJ sequence), flag (regional indicators), combining accent
+        let text = "👨‍👩‍👧🇯🇵e\u{301}abc";
+        let truncated = truncate(text, 3, TruncationBoundary::Grapheme);
+        assert_eq!(truncated, "👨‍👩‍👧🇯🇵e\u{301}");
+        assert_eq!(grapheme_len(&truncated), 3);
+    }
+
+    #[test]
+    fn test_word_truncation_drops_trailing_stop_words() {
+        let text = "Summarize the report of the committee";
+        assert_eq!(
+            truncate(text, 28, TruncationBoundary::Word),
+            "Summarize the report"
+        );
+
+        let spanish = "El informe anual de la empresa";
+        assert_eq!(
+            truncate(spanish, 22, TruncationBoundary::Word),
+            "El informe anual"
+        );
+    }
+
+    #[test]
+    fn test_long_first_sentence_falls_back_to_words() {
+        let text = "An extremely long opening sentence without any early stop";
+        assert_eq!(
+            truncate(text, 20, TruncationBoundary::Sentence),
+            "An extremely long"
+        );
+    }
+
+    #[test]
+    fn test_truncate_parameters() {
+        let mut params = HashMap::new();
+        params.insert("doc".to_string(), "第一句。第二句。第三句。".to_string());
+        params.insert("name".to_string(), "Juan".to_string());
+        let request = InferenceRequest::new("{{doc}}", params, ModelType::General);
+
+        let request = truncate_parameters(request, 8, TruncationBoundary::Sentence);
+        assert_eq!(request.parameters["doc"], "第一句。第二句。");
+        assert_eq!(request.parameters["name"], "Juan");
+    }
+}
@@ -0,0 +1,303 @@
+//! Concurrency limiting decorator for inference services
+//!
+//! `ConcurrencyLimitedService` caps the number of requests sent to the wrapped service at the
+//! same time. Requests beyond the limit wait in a bounded FIFO queue; once the queue is full,
+//! new requests are rejected immediately with `inference_errors::queue_full` instead of piling
+//! up provider connections.
+//!
+//! A stream holds its slot until it ends or its consumer drops it.
+
+use crate::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Decrements the queued counter when a waiting request leaves the queue
+struct QueueSlot<'a> {
+    queued: &'a AtomicUsize,
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Inference service decorator limiting in-flight requests with a bounded wait queue
+#[derive(Debug)]
+pub struct ConcurrencyLimitedService<S> {
+    inner: S,
+    permits: Arc<Semaphore>,
+    max_in_flight: usize,
+    max_queued: usize,
+    queued: AtomicUsize,
+    limits: RequestLimits,
+}
+
+impl<S: InferenceService> ConcurrencyLimitedService<S> {
+    /// Allow `max_in_flight` concurrent requests, queueing up to as many more
+    pub fn new(inner: S, max_in_flight: usize) -> Self {
+        let max_in_flight = max_in_flight.max(1);
+        Self {
+            inner,
+            permits: Arc::new(Semaphore::new(max_in_flight)),
+            max_in_flight,
+            max_queued: max_in_flight,
+            queued: AtomicUsize::new(0),
+            limits: RequestLimits::structural(),
+        }
+    }
+
+    /// Set how many requests may wait for a free slot (0 rejects as soon as the limit is hit)
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    /// Validate requests with `limits` instead of `RequestLimits::structural` before they queue
+    pub fn with_limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+
+    /// Number of requests currently being processed
+    pub fn in_flight(&self) -> usize {
+        self.max_in_flight - self.permits.available_permits()
+    }
+
+    /// Number of requests waiting for a free slot
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Reserve a place in the wait queue, failing when it is full
+    fn enter_queue(&self) -> InferenceResult<QueueSlot<'_>> {
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(inference_errors::queue_full(self.max_queued));
+        }
+        Ok(QueueSlot {
+            queued: &self.queued,
+        })
+    }
+
+    /// Take a free slot, waiting in the queue while none is
+    async fn acquire(&self) -> InferenceResult<OwnedSemaphorePermit> {
+        if let Ok(permit) = Arc::clone(&self.permits).try_acquire_owned() {
+            return Ok(permit);
+        }
+        let _slot = self.enter_queue()?;
+        Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| inference_errors::request_aborted("concurrency limiter was closed"))
+    }
+}
+
+/// `InferenceLayer` wrapping services in a `ConcurrencyLimitedService`; every wrapped service
+/// gets its own limit
+#[derive(Debug, Clone, Copy)]
+pub struct ConcurrencyLimitLayer {
+    max_in_flight: usize,
+    max_queued: Option<usize>,
+}
+
+impl ConcurrencyLimitLayer {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            max_queued: None,
+        }
+    }
+
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = Some(max_queued);
+        self
+    }
+}
+
+impl<S: InferenceService> InferenceLayer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimitedService<S>;
+
+    fn layer(&self, inner: S) -> ConcurrencyLimitedService<S> {
+        let service = ConcurrencyLimitedService::new(inner, self.max_in_flight);
+        match self.max_queued {
+            Some(max_queued) => service.with_max_queued(max_queued),
+            None => service,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for ConcurrencyLimitedService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        // Fail invalid requests before they take a queue slot
+        request.validate_with(&self.inner, &self.limits)?;
+        let _permit = self.acquire().await?;
+        self.inner.infer(request).await
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        request.validate_with(&self.inner, &self.limits)?;
+        let permit = self.acquire().await?;
+        let stream = self.inner.infer_stream(request).await?;
+        Ok(stream.on_end(move |_| drop(permit)))
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        Ok(self
+            .inner
+            .health_check()
+            .await?
+            .with_metadata("in_flight", serde_json::Value::from(self.in_flight()))
+            .with_metadata("queued", serde_json::Value::from(self.queued()))
+            .with_metadata("max_in_flight", serde_json::Value::from(self.max_in_flight)))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+    use std::time::Duration;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Test", HashMap::new(), ModelType::Fast)
+    }
+
+    #[tokio::test]
+    async fn test_limits_in_flight_requests() {
+        let service = Arc::new(
+            ConcurrencyLimitedService::new(MockInferenceService::new().with_latency(50), 2)
+                .with_max_queued(10),
+        );
+
+        let handles: Vec<_> = (0..5)
+            .map(|_| {
+                let service = Arc::clone(&service);
+                tokio::spawn(async move { service.infer(request()).await })
+            })
+            .collect();
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(service.in_flight(), 2);
+        assert_eq!(service.queued(), 3);
+
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+        assert_eq!(service.in_flight(), 0);
+        assert_eq!(service.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_when_queue_is_full() {
+        let service = Arc::new(
+            ConcurrencyLimitedService::new(MockInferenceService::new().with_latency(100), 1)
+                .with_max_queued(1),
+        );
+
+        let first = {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move { service.infer(request()).await })
+        };
+        let second = {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move { service.infer(request()).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let error = service.infer(request()).await.unwrap_err();
+        assert!(error.to_string().contains("queue is full"));
+
+        assert!(first.await.unwrap().is_ok());
+        assert!(second.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_context_window_is_checked_only_when_configured() {
+        // ~20k tokens: past the typical fast window, within a local model's
+        let mut long = request();
+        long.parameters
+            .insert("text".to_string(), "word ".repeat(16_000));
+        long.template = "{{text}}".to_string();
+
+        let service =
+            ConcurrencyLimitedService::new(MockInferenceService::new().with_latency(0), 1);
+        assert!(service.infer(long.clone()).await.is_ok());
+
+        let limits = RequestLimits::new().with_context_window(8192);
+        let service = service.with_limits(limits);
+        let error = service.infer(long.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("Context window 8192 exceeded"));
+
+        let mock = MockInferenceService::new()
+            .with_latency(0)
+            .with_limits(limits);
+        assert!(mock.infer(long).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_holds_its_slot() {
+        let service =
+            ConcurrencyLimitedService::new(MockInferenceService::new().with_latency(0), 1)
+                .with_max_queued(0);
+        let stream = service.infer_stream(request()).await.unwrap();
+        assert_eq!(service.in_flight(), 1);
+        let error = service.infer(request()).await.unwrap_err();
+        assert!(error.to_string().contains("queue is full"));
+
+        assert_eq!(stream.assemble().await.unwrap().len(), 1);
+        assert_eq!(service.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_reports_load() {
+        let service = ConcurrencyLimitedService::new(MockInferenceService::new(), 4);
+        let health = service.health_check().await.unwrap();
+
+        assert_eq!(health.metadata.get("max_in_flight"), Some(&4.into()));
+        assert_eq!(health.metadata.get("in_flight"), Some(&0.into()));
+    }
+}
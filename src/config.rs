@@ -0,0 +1,688 @@
+//! Service configuration and wiring
+//!
+//! `InferenceConfig` describes which adapter to use and the defaults applied to every request.
+//! It loads from `TYL_INFERENCE_*` environment variables or from the `inference` section of a
+//! TYL YAML config file, and `build_service` turns it into a ready `InferenceService`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::config::*;
+//! # async fn example() -> TylResult<()> {
+//! let config = InferenceConfig::from_env()?;
+//! let service = build_service(&config).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! | Variable | Field |
+//! |---|---|
+//! | `TYL_INFERENCE_PROVIDER` | `provider` (`mock`, `grpc`, `websocket`, `http`) |
+//! | `TYL_INFERENCE_API_KEY` | `api_key` |
+//! | `TYL_INFERENCE_BASE_URL` | `base_url` |
+//! | `TYL_INFERENCE_MODEL` | `model` |
+//! | `TYL_INFERENCE_ALIASES` | `aliases` (e.g. `default-coder=gpt-4o-2024-08-06,fast=gpt-4o-mini`) |
+//! | `TYL_INFERENCE_MIGRATE_DEPRECATED` | `migrate_deprecated` (`true` or `false`) |
+//! | `TYL_INFERENCE_TEMPERATURE_<TYPE>` | `temperatures` (e.g. `TYL_INFERENCE_TEMPERATURE_CODING`) |
+//! | `TYL_INFERENCE_TIMEOUT_MS_<TYPE>` | `timeout_ms` (milliseconds) |
+//! | `TYL_INFERENCE_CA_CERT` | `ca_cert` (PEM bundle path) |
+//! | `TYL_INFERENCE_CLIENT_CERT` / `TYL_INFERENCE_CLIENT_KEY` | `client_cert` / `client_key` (PEM paths) |
+//!
+//! Aliases let applications name a role instead of a model version: requests for
+//! `default-coder` are sent to whatever model the deployment maps it to, and the response
+//! records the alias under `model_alias`, so upgrading a model is a config change.
+
+use crate::ensemble::forward_batch;
+use crate::tls::TlsConfig;
+use crate::*;
+use std::str::FromStr;
+
+const ENV_PREFIX: &str = "TYL_INFERENCE_";
+
+/// Response metadata key holding the alias a request's model was resolved from
+pub const MODEL_ALIAS_METADATA_KEY: &str = "model_alias";
+
+/// Adapter backing the built service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InferenceProvider {
+    /// `MockInferenceService` (requires the `mock` feature)
+    #[default]
+    Mock,
+    /// `GrpcInferenceClient` connected to `base_url` (requires the `grpc` feature)
+    Grpc,
+    /// `TransportService` over a `WebSocketTransport` to `base_url` (requires the `websocket`
+    /// feature)
+    WebSocket,
+    /// `HttpInferenceClient` using a `reqwest::Client` with the configured TLS settings (requires
+    /// the `http-client` feature; build the client yourself to customize its transport further)
+    Http,
+}
+
+impl FromStr for InferenceProvider {
+    type Err = TylError;
+
+    fn from_str(provider: &str) -> Result<Self, Self::Err> {
+        match provider.trim().to_ascii_lowercase().as_str() {
+            "mock" => Ok(Self::Mock),
+            "grpc" => Ok(Self::Grpc),
+            "websocket" => Ok(Self::WebSocket),
+            "http" => Ok(Self::Http),
+            other => Err(TylError::configuration(format!(
+                "Unknown inference provider {other:?} (expected mock, grpc, websocket or http)"
+            ))),
+        }
+    }
+}
+
+/// Inference wiring loaded from the environment or a TYL config file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InferenceConfig {
+    pub provider: InferenceProvider,
+    /// Credential sent to the provider as a bearer token; use the adapters' `with_credentials`
+    /// directly to fetch and rotate keys from a secret store instead
+    pub api_key: Option<String>,
+    /// Endpoint of remote providers
+    pub base_url: Option<String>,
+    /// Model used by requests without a `model_override`
+    pub model: Option<String>,
+    /// Model names substituted for aliases at request time (one level, aliases do not chain)
+    pub aliases: HashMap<String, String>,
+    /// Send requests for a deprecated model to its replacement instead of only flagging them
+    pub migrate_deprecated: bool,
+    /// Temperature used by requests of a model type that do not set one
+    pub temperatures: HashMap<ModelType, f32>,
+    /// Timeout in milliseconds used by requests of a model type that do not set one
+    pub timeout_ms: HashMap<ModelType, u64>,
+    /// PEM bundle of additional root CAs trusted by remote providers (requires the `tls` feature)
+    pub ca_cert: Option<String>,
+    /// PEM client certificate presented to remote providers (mTLS, requires the `tls` feature)
+    pub client_cert: Option<String>,
+    /// PEM private key of `client_cert`
+    pub client_key: Option<String>,
+}
+
+impl InferenceConfig {
+    pub fn new(provider: InferenceProvider) -> Self {
+        Self {
+            provider,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Send requests for the model `alias` to `model`
+    pub fn with_alias(mut self, alias: impl Into<String>, model: impl Into<String>) -> Self {
+        self.aliases.insert(alias.into(), model.into());
+        self
+    }
+
+    pub fn with_migrate_deprecated(mut self, migrate: bool) -> Self {
+        self.migrate_deprecated = migrate;
+        self
+    }
+
+    pub fn with_temperature(mut self, model_type: ModelType, temperature: f32) -> Self {
+        self.temperatures
+            .insert(model_type, temperature.clamp(0.0, 1.0));
+        self
+    }
+
+    pub fn with_timeout(mut self, model_type: ModelType, timeout: Duration) -> Self {
+        self.timeout_ms.insert(
+            model_type,
+            timeout.as_millis().try_into().unwrap_or(u64::MAX),
+        );
+        self
+    }
+
+    /// Trust the root CAs of a PEM bundle file
+    pub fn with_ca_cert(mut self, path: impl Into<String>) -> Self {
+        self.ca_cert = Some(path.into());
+        self
+    }
+
+    /// Present the client certificate and key of PEM files (mTLS)
+    pub fn with_client_cert(mut self, cert: impl Into<String>, key: impl Into<String>) -> Self {
+        self.client_cert = Some(cert.into());
+        self.client_key = Some(key.into());
+        self
+    }
+
+    /// Load from the process environment
+    pub fn from_env() -> InferenceResult<Self> {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// Load from `TYL_INFERENCE_*` variables; other variables are ignored
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> InferenceResult<Self> {
+        let mut config = Self::default();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            match key {
+                "PROVIDER" => config.provider = value.parse()?,
+                "API_KEY" => config.api_key = Some(value),
+                "BASE_URL" => config.base_url = Some(value),
+                "MODEL" => config.model = Some(value),
+                "ALIASES" => config.aliases = parse_aliases(&name, &value)?,
+                "MIGRATE_DEPRECATED" => {
+                    config.migrate_deprecated = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| TylError::validation(name.clone(), "expected true or false"))?
+                }
+                "CA_CERT" => config.ca_cert = Some(value),
+                "CLIENT_CERT" => config.client_cert = Some(value),
+                "CLIENT_KEY" => config.client_key = Some(value),
+                _ => {
+                    if let Some(model_type) = key.strip_prefix("TEMPERATURE_") {
+                        let temperature = value
+                            .parse::<f32>()
+                            .map_err(|_| TylError::validation(name.clone(), "expected a number"))?;
+                        config.temperatures.insert(
+                            parse_model_type(&name, model_type)?,
+                            temperature.clamp(0.0, 1.0),
+                        );
+                    } else if let Some(model_type) = key.strip_prefix("TIMEOUT_MS_") {
+                        let millis = value.parse::<u64>().map_err(|_| {
+                            TylError::validation(name.clone(), "expected whole milliseconds")
+                        })?;
+                        config
+                            .timeout_ms
+                            .insert(parse_model_type(&name, model_type)?, millis);
+                    }
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Load from YAML: either the config itself or a TYL config file with an `inference` section
+    pub fn from_yaml(yaml: &str) -> InferenceResult<Self> {
+        let value: serde_yaml::Value = serde_yaml::from_str(yaml)
+            .map_err(|e| TylError::configuration(format!("Invalid inference config: {e}")))?;
+        let section = value.get("inference").cloned().unwrap_or(value);
+        serde_yaml::from_value(section)
+            .map_err(|e| TylError::configuration(format!("Invalid inference config: {e}")))
+    }
+
+    /// Timeout configured for a model type
+    pub fn timeout_for(&self, model_type: ModelType) -> Option<Duration> {
+        self.timeout_ms
+            .get(&model_type)
+            .map(|millis| Duration::from_millis(*millis))
+    }
+
+    /// Model an alias stands for, or `model` itself when it is not an alias
+    pub fn resolve_model<'a>(&'a self, model: &'a str) -> &'a str {
+        self.aliases.get(model).map_or(model, String::as_str)
+    }
+
+    /// Fill in the configured defaults the request leaves unset and resolve its model alias
+    ///
+    /// Returns the alias the model override was resolved from, if any.
+    pub fn apply_defaults(&self, request: &mut InferenceRequest) -> Option<String> {
+        if request.model_override.is_none() {
+            request.model_override = self.model.clone();
+        }
+        if request.temperature.is_none() {
+            request.temperature = self.temperatures.get(&request.model_type).copied();
+        }
+        if request.timeout.is_none() {
+            request.timeout = self.timeout_for(request.model_type);
+        }
+        let model = request.model_override.as_mut()?;
+        let resolved = self.aliases.get(model.as_str())?;
+        Some(std::mem::replace(model, resolved.clone()))
+    }
+
+    /// TLS settings read from the configured PEM files, `None` when none are configured
+    pub fn tls_config(&self) -> InferenceResult<Option<TlsConfig>> {
+        let mut tls = TlsConfig::new();
+        if let Some(path) = &self.ca_cert {
+            tls = tls.with_root_ca_file(path)?;
+        }
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => tls = tls.with_client_identity_files(cert, key)?,
+            (None, None) => {}
+            _ => {
+                return Err(TylError::validation(
+                    "client_cert",
+                    "client_cert and client_key must be set together",
+                ))
+            }
+        }
+        Ok((tls != TlsConfig::new()).then_some(tls))
+    }
+
+    /// `tls_config`, rejected when the `tls` feature is not compiled in
+    #[cfg_attr(
+        not(any(feature = "grpc", feature = "websocket", feature = "http-client")),
+        allow(dead_code)
+    )]
+    fn adapter_tls(&self) -> InferenceResult<Option<TlsConfig>> {
+        let tls = self.tls_config()?;
+        if cfg!(not(feature = "tls")) && tls.is_some() {
+            return Err(TylError::configuration(
+                "TLS settings require the tls feature",
+            ));
+        }
+        Ok(tls)
+    }
+
+    #[cfg_attr(
+        not(any(feature = "grpc", feature = "websocket", feature = "http-client")),
+        allow(dead_code)
+    )]
+    fn require_base_url(&self) -> InferenceResult<String> {
+        self.base_url.clone().ok_or_else(|| {
+            TylError::configuration(format!(
+                "{ENV_PREFIX}BASE_URL is required for the {:?} provider",
+                self.provider
+            ))
+        })
+    }
+}
+
+/// `alias=model` pairs separated by commas
+fn parse_aliases(name: &str, value: &str) -> InferenceResult<HashMap<String, String>> {
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((alias, model)) if !alias.trim().is_empty() && !model.trim().is_empty() => {
+                Ok((alias.trim().to_string(), model.trim().to_string()))
+            }
+            _ => Err(TylError::validation(
+                name,
+                format!("expected alias=model pairs, got {pair:?}"),
+            )),
+        })
+        .collect()
+}
+
+/// Model type from an upper-case variable suffix such as `CODING`
+fn parse_model_type(name: &str, model_type: &str) -> InferenceResult<ModelType> {
+    match model_type {
+        "CODING" => Ok(ModelType::Coding),
+        "REASONING" => Ok(ModelType::Reasoning),
+        "GENERAL" => Ok(ModelType::General),
+        "FAST" => Ok(ModelType::Fast),
+        "CREATIVE" => Ok(ModelType::Creative),
+        "VISION" => Ok(ModelType::Vision),
+        "EXTRACTION" => Ok(ModelType::Extraction),
+        "SUMMARIZATION" => Ok(ModelType::Summarization),
+        _ => Err(TylError::validation(name, "unknown model type")),
+    }
+}
+
+/// Inference service decorator applying `InferenceConfig` request defaults and model aliases
+#[derive(Debug)]
+pub struct ConfiguredService<S> {
+    inner: S,
+    config: InferenceConfig,
+}
+
+impl<S: InferenceService> ConfiguredService<S> {
+    pub fn new(inner: S, config: InferenceConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn config(&self) -> &InferenceConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for ConfiguredService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let alias = self.config.apply_defaults(&mut request);
+        let mut response = self.inner.infer(request).await?;
+        if let Some(alias) = alias {
+            response.metadata = response
+                .metadata
+                .with_metadata(MODEL_ALIAS_METADATA_KEY, alias);
+        }
+        Ok(response)
+    }
+
+    async fn infer_stream(
+        &self,
+        mut request: InferenceRequest,
+    ) -> InferenceResult<InferenceStream> {
+        self.config.apply_defaults(&mut request);
+        self.inner.infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        forward_batch(&self.inner, requests, |request| {
+            Ok(self.config.apply_defaults(request))
+        })
+        .await
+        .into_iter()
+        .map(|result| {
+            let (alias, response) = result?;
+            let mut response = response?;
+            if let Some(alias) = alias {
+                response.metadata = response
+                    .metadata
+                    .with_metadata(MODEL_ALIAS_METADATA_KEY, alias);
+            }
+            Ok(response)
+        })
+        .collect()
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+/// Build the service described by `config`, with its request defaults applied
+///
+/// Requests for models in `ModelDeprecations::with_defaults` are flagged, or migrated when
+/// `migrate_deprecated` is set, after aliases are resolved. Fails with a configuration error when the provider's feature is not compiled in or a
+/// required setting is missing.
+pub async fn build_service(config: &InferenceConfig) -> InferenceResult<Box<dyn InferenceService>> {
+    let service = DeprecationService::new(
+        provider_service(config).await?,
+        ModelDeprecations::with_defaults(),
+    )
+    .with_auto_migrate(config.migrate_deprecated);
+    Ok(Box::new(ConfiguredService::new(service, config.clone())))
+}
+
+async fn provider_service(config: &InferenceConfig) -> InferenceResult<Box<dyn InferenceService>> {
+    match config.provider {
+        #[cfg(feature = "mock")]
+        InferenceProvider::Mock => Ok(Box::new(crate::MockInferenceService::new())),
+        #[cfg(feature = "grpc")]
+        InferenceProvider::Grpc => {
+            let channel = crate::GrpcInferenceClient::channel(
+                config.require_base_url()?,
+                config.adapter_tls()?.as_ref(),
+            )
+            .await?;
+            let mut client = crate::GrpcInferenceClient::new(channel);
+            if let Some(api_key) = &config.api_key {
+                client = client.with_credentials(StaticCredentials::new(api_key.clone()));
+            }
+            client.refresh_supported_models().await?;
+            Ok(Box::new(client))
+        }
+        #[cfg(feature = "websocket")]
+        InferenceProvider::WebSocket => {
+            let mut transport = crate::WebSocketTransport::new(config.require_base_url()?);
+            if let Some(api_key) = &config.api_key {
+                transport = transport.with_credentials(StaticCredentials::new(api_key.clone()));
+            }
+            #[cfg(feature = "tls")]
+            if let Some(tls) = config.adapter_tls()? {
+                transport = transport.with_tls(&tls)?;
+            }
+            #[cfg(not(feature = "tls"))]
+            config.adapter_tls()?;
+            Ok(Box::new(TransportService::new(transport)))
+        }
+        #[cfg(feature = "http-client")]
+        InferenceProvider::Http => {
+            #[cfg(feature = "tls")]
+            let transport = match config.adapter_tls()? {
+                Some(tls) => tls.reqwest_client()?,
+                None => reqwest::Client::new(),
+            };
+            #[cfg(not(feature = "tls"))]
+            let transport = {
+                config.adapter_tls()?;
+                reqwest::Client::new()
+            };
+            let mut client = crate::HttpInferenceClient::new(config.require_base_url()?, transport);
+            if let Some(api_key) = &config.api_key {
+                client = client.with_credentials(StaticCredentials::new(api_key.clone()));
+            }
+            client.refresh_supported_models().await?;
+            Ok(Box::new(client))
+        }
+        #[allow(unreachable_patterns)]
+        provider => Err(TylError::configuration(format!(
+            "The {provider:?} provider is not compiled in; enable its cargo feature"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let config = InferenceConfig::from_vars(vars(&[
+            ("TYL_INFERENCE_PROVIDER", "grpc"),
+            ("TYL_INFERENCE_BASE_URL", "http://inference:50051"),
+            ("TYL_INFERENCE_TEMPERATURE_CODING", "0.1"),
+            ("TYL_INFERENCE_TIMEOUT_MS_REASONING", "1500"),
+            (
+                "TYL_INFERENCE_ALIASES",
+                "default-coder=gpt-4o-2024-08-06, fast = gpt-4o-mini",
+            ),
+            ("TYL_INFERENCE_MIGRATE_DEPRECATED", "true"),
+            ("HOME", "/root"),
+        ]))
+        .unwrap();
+
+        assert_eq!(config.provider, InferenceProvider::Grpc);
+        assert_eq!(config.base_url.as_deref(), Some("http://inference:50051"));
+        assert_eq!(config.temperatures.get(&ModelType::Coding), Some(&0.1));
+        assert_eq!(
+            config.timeout_for(ModelType::Reasoning),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(config.resolve_model("default-coder"), "gpt-4o-2024-08-06");
+        assert_eq!(config.resolve_model("fast"), "gpt-4o-mini");
+        assert_eq!(config.resolve_model("gpt-4o"), "gpt-4o");
+        assert!(config.migrate_deprecated);
+
+        assert!(InferenceConfig::from_vars(vars(&[("TYL_INFERENCE_PROVIDER", "smoke")])).is_err());
+        assert!(InferenceConfig::from_vars(vars(&[("TYL_INFERENCE_ALIASES", "coder")])).is_err());
+        assert!(
+            InferenceConfig::from_vars(vars(&[("TYL_INFERENCE_TEMPERATURE_HUGE", "0.5")])).is_err()
+        );
+    }
+
+    #[test]
+    fn test_from_yaml_section() {
+        let config = InferenceConfig::from_yaml(
+            "
+logging:
+  level: info
+inference:
+  provider: websocket
+  base_url: wss://inference.example.com
+  model: gpt-4o-mini
+  temperatures:
+    Creative: 0.9
+  aliases:
+    default-coder: gpt-4o-2024-08-06
+",
+        )
+        .unwrap();
+
+        assert_eq!(config.provider, InferenceProvider::WebSocket);
+        assert_eq!(config.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(config.temperatures.get(&ModelType::Creative), Some(&0.9));
+        assert_eq!(config.resolve_model("default-coder"), "gpt-4o-2024-08-06");
+        assert!(config.timeout_ms.is_empty());
+    }
+
+    #[test]
+    fn test_tls_config_from_vars() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+        let config = InferenceConfig::from_vars(vars(&[
+            ("TYL_INFERENCE_CA_CERT", &format!("{dir}/ca.pem")),
+            ("TYL_INFERENCE_CLIENT_CERT", &format!("{dir}/client.pem")),
+            ("TYL_INFERENCE_CLIENT_KEY", &format!("{dir}/client.key")),
+        ]))
+        .unwrap();
+        let tls = config.tls_config().unwrap().unwrap();
+        assert_eq!(tls.root_ca_pem.len(), 1);
+        assert!(tls.client_identity.is_some());
+
+        assert_eq!(InferenceConfig::default().tls_config().unwrap(), None);
+        let half = InferenceConfig {
+            client_cert: Some(format!("{dir}/client.pem")),
+            ..InferenceConfig::default()
+        };
+        assert!(half.tls_config().is_err());
+    }
+
+    #[test]
+    fn test_apply_defaults_keeps_request_settings() {
+        let config = InferenceConfig::default()
+            .with_model("gpt-4o")
+            .with_temperature(ModelType::Fast, 0.2)
+            .with_timeout(ModelType::Fast, Duration::from_millis(2500));
+
+        let mut request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast);
+        config.apply_defaults(&mut request);
+        assert_eq!(request.model_override.as_deref(), Some("gpt-4o"));
+        assert_eq!(request.temperature, Some(0.2));
+        assert_eq!(request.timeout, Some(Duration::from_millis(2500)));
+
+        // Model types without a configured temperature keep the provider default
+        let mut request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Coding);
+        config.apply_defaults(&mut request);
+        assert_eq!(request.temperature, None);
+        assert_eq!(request.effective_temperature(), DEFAULT_TEMPERATURE);
+
+        let mut request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast)
+            .with_model("gpt-3.5-turbo")
+            .with_temperature(0.5);
+        config.apply_defaults(&mut request);
+        assert_eq!(request.model_override.as_deref(), Some("gpt-3.5-turbo"));
+        assert_eq!(request.temperature, Some(0.5));
+    }
+
+    #[test]
+    fn test_apply_defaults_resolves_aliases() {
+        let config = InferenceConfig::default()
+            .with_model("default")
+            .with_alias("default", "gpt-4o-mini")
+            .with_alias("default-coder", "gpt-4o-2024-08-06");
+
+        let mut request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Coding)
+            .with_model("default-coder");
+        assert_eq!(
+            config.apply_defaults(&mut request).as_deref(),
+            Some("default-coder")
+        );
+        assert_eq!(request.model_override.as_deref(), Some("gpt-4o-2024-08-06"));
+
+        // The configured default model may itself be an alias
+        let mut request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast);
+        assert_eq!(
+            config.apply_defaults(&mut request).as_deref(),
+            Some("default")
+        );
+        assert_eq!(request.model_override.as_deref(), Some("gpt-4o-mini"));
+
+        let mut request =
+            InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast).with_model("gpt-4o");
+        assert_eq!(config.apply_defaults(&mut request), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_build_service() {
+        let service = build_service(&InferenceConfig::default().with_model("gpt-4o"))
+            .await
+            .unwrap();
+        let response = service
+            .infer(InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast))
+            .await
+            .unwrap();
+        assert_eq!(response.metadata.model, "gpt-4o");
+
+        let config = InferenceConfig::default().with_alias("default-coder", "gpt-4o-2024-08-06");
+        let service = build_service(&config).await.unwrap();
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Coding)
+            .with_model("default-coder");
+        let response = service.infer(request).await.unwrap();
+        assert_eq!(response.metadata.model, "gpt-4o-2024-08-06");
+        assert_eq!(
+            response.metadata.metadata[MODEL_ALIAS_METADATA_KEY],
+            "default-coder"
+        );
+
+        let config = InferenceConfig::default()
+            .with_alias("long-context", "gpt-4-32k")
+            .with_migrate_deprecated(true);
+        let service = build_service(&config).await.unwrap();
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General)
+            .with_model("long-context");
+        let response = service.infer(request).await.unwrap();
+        assert_eq!(response.metadata.model, "gpt-4o");
+        assert!(response
+            .metadata
+            .metadata
+            .contains_key(crate::DEPRECATION_METADATA_KEY));
+    }
+}
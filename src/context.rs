@@ -0,0 +1,204 @@
+//! Ambient caller context
+//!
+//! Entry points (HTTP handlers, job workers, tracing middleware) describe where the work they run
+//! comes from by wrapping it in `RequestContext::scope`; code running inside that future reads it
+//! back with `RequestContext::current`. Requests without an explicit priority take their
+//! scheduling priority from the context (see `InferenceRequest::effective_priority`), so
+//! interactive traffic outranks background jobs without every call site setting it.
+//!
+//! A context can also carry a deadline: adapters whose calls are not made for a single
+//! `InferenceRequest` (embeddings, moderation, batch management) bound their HTTP calls by the
+//! time left until it, and `InferenceRequest::attempt_timeout` honors it as well.
+//!
+//! The context follows the future across `.await` points on any executor, but not into tasks
+//! spawned from it; wrap those in their own scope.
+
+use crate::*;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+thread_local! {
+    static CURRENT: RefCell<Option<RequestContext>> = const { RefCell::new(None) };
+}
+
+/// Where a request originates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RequestOrigin {
+    /// A user is waiting on the response (e.g. an HTTP request)
+    Interactive,
+    /// Scheduled or queued background work
+    Background,
+    /// Bulk batch processing
+    Batch,
+}
+
+impl RequestOrigin {
+    /// Scheduling priority for requests of this origin
+    pub fn priority(&self) -> Priority {
+        match self {
+            RequestOrigin::Interactive => Priority::High,
+            RequestOrigin::Background | RequestOrigin::Batch => Priority::Low,
+        }
+    }
+}
+
+/// Context of the caller on whose behalf requests are made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub origin: RequestOrigin,
+    /// Priority overriding the origin's default
+    pub priority: Option<Priority>,
+    /// Point in time after which the caller no longer needs the results of work in this scope
+    #[serde(default)]
+    pub deadline: Option<DateTime<Utc>>,
+}
+
+impl RequestContext {
+    pub fn new(origin: RequestOrigin) -> Self {
+        Self {
+            origin,
+            priority: None,
+            deadline: None,
+        }
+    }
+
+    pub fn interactive() -> Self {
+        Self::new(RequestOrigin::Interactive)
+    }
+
+    pub fn background() -> Self {
+        Self::new(RequestOrigin::Background)
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: DateTime<Utc>) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Time left until the deadline (zero once it has passed), `None` without a deadline
+    pub fn remaining_time(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| (deadline - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+    }
+
+    /// Time left until the deadline of the currently running scope, if it has one
+    pub fn current_remaining_time() -> Option<Duration> {
+        Self::current().and_then(|context| context.remaining_time())
+    }
+
+    /// Priority hint for requests made in this context
+    pub fn priority_hint(&self) -> Priority {
+        self.priority.unwrap_or_else(|| self.origin.priority())
+    }
+
+    /// Context of the currently running scope, if any
+    pub fn current() -> Option<RequestContext> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Run `future` with this context as the current one
+    pub fn scope<F: Future>(self, future: F) -> Scoped<F> {
+        Scoped {
+            context: self,
+            future: Box::pin(future),
+        }
+    }
+}
+
+/// Future running with a `RequestContext` set, returned by `RequestContext::scope`
+pub struct Scoped<F> {
+    context: RequestContext,
+    future: Pin<Box<F>>,
+}
+
+impl<F> std::fmt::Debug for Scoped<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scoped")
+            .field("context", &self.context)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Restores the previous context when a poll returns or unwinds
+struct Restore(Option<RequestContext>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let context = self.context.clone();
+        let previous = CURRENT.with(|current| current.replace(Some(context)));
+        let _restore = Restore(previous);
+        self.future.as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Test", HashMap::new(), ModelType::General)
+    }
+
+    #[tokio::test]
+    async fn test_scope_sets_current_context() {
+        assert_eq!(RequestContext::current(), None);
+
+        let origin = RequestContext::interactive()
+            .scope(async {
+                tokio::task::yield_now().await;
+                let outer = RequestContext::current().map(|context| context.origin);
+                let inner = RequestContext::background()
+                    .scope(async { RequestContext::current().map(|context| context.origin) })
+                    .await;
+                assert_eq!(inner, Some(RequestOrigin::Background));
+                outer
+            })
+            .await;
+
+        assert_eq!(origin, Some(RequestOrigin::Interactive));
+        assert_eq!(RequestContext::current(), None);
+    }
+
+    #[tokio::test]
+    async fn test_effective_priority() {
+        assert_eq!(request().effective_priority(), Priority::Normal);
+
+        let (derived, explicit) = RequestContext::interactive()
+            .scope(async {
+                (
+                    request().effective_priority(),
+                    request().with_priority(Priority::Low).effective_priority(),
+                )
+            })
+            .await;
+        assert_eq!(derived, Priority::High);
+        assert_eq!(explicit, Priority::Low);
+
+        let batch = RequestContext::new(RequestOrigin::Batch)
+            .scope(async { request().effective_priority() })
+            .await;
+        assert_eq!(batch, Priority::Low);
+
+        let overridden = RequestContext::background()
+            .with_priority(Priority::Normal)
+            .scope(async { request().effective_priority() })
+            .await;
+        assert_eq!(overridden, Priority::Normal);
+    }
+}
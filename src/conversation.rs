@@ -0,0 +1,561 @@
+//! Conversation memory
+//!
+//! Requests are single-shot templates; multi-turn chat keeps its history in a
+//! `ConversationStore` instead. `SessionInferenceService` loads the history of requests that
+//! carry a `session_id` metadata entry, binds it to the `{{history}}` parameter (prepending the
+//! placeholder to templates that do not place it themselves) and appends the new exchange once
+//! the response arrives:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::conversation::*;
+//! # use std::collections::HashMap;
+//! # use std::sync::Arc;
+//! # async fn example(openai: impl InferenceService + 'static, params: HashMap<String, String>) -> TylResult<()> {
+//! let store = Arc::new(InMemoryConversationStore::new());
+//! let service = SessionInferenceService::new(openai, store.clone());
+//! let request = InferenceRequest::new("{{question}}", params, ModelType::General)
+//!     .with_metadata(SESSION_ID_METADATA_KEY, "chat-42");
+//! service.infer(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The history is rendered as `User: ...` and `Assistant: ...` paragraphs. Requests without a
+//! session id pass through untouched.
+//!
+//! By default the whole history is replayed. `MemoryPolicy::SlidingWindow` drops the oldest
+//! turns until the rendered prompt fits its token limit and reports how many were left out
+//! under `dropped_turns` in the response metadata. `MemoryPolicy::Summarize` instead folds
+//! everything but the most recent turns into a running summary, written by a
+//! `ModelType::Fast` inference on the wrapped service, whenever the stored history grows past
+//! `max_turns`; the summary replaces those turns in the store and the number of turns folded is
+//! reported under `summarized_turns`.
+
+use crate::*;
+use std::sync::{Arc, Mutex};
+
+/// Request metadata key naming the conversation a request belongs to
+pub const SESSION_ID_METADATA_KEY: &str = "session_id";
+/// Response metadata key holding the number of history turns left out of the prompt
+pub const DROPPED_TURNS_METADATA_KEY: &str = "dropped_turns";
+/// Response metadata key holding the number of turns folded into the session summary
+pub const SUMMARIZED_TURNS_METADATA_KEY: &str = "summarized_turns";
+
+/// Prompt of the summarization call; `{{history}}` holds the turns being folded, previous
+/// summary included
+const SUMMARY_TEMPLATE: &str = "Summarize the conversation below in a few sentences. Keep the \
+names, facts, decisions and open questions needed to continue it.\n\n{{history}}";
+
+/// How much of a session's history goes into the prompt
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryPolicy {
+    /// Replay every turn
+    #[default]
+    Full,
+    /// Drop the oldest turns until the rendered prompt, history included, is at most
+    /// `max_tokens` tokens
+    SlidingWindow { max_tokens: usize },
+    /// Once more than `max_turns` turns are stored, replace all but the last `keep_recent`
+    /// with a summary of them
+    Summarize {
+        max_turns: usize,
+        keep_recent: usize,
+    },
+}
+
+/// Speaker of a conversation turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TurnRole {
+    User,
+    Assistant,
+    /// Running summary of earlier turns
+    Summary,
+}
+
+/// One message of a conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationTurn {
+    pub role: TurnRole,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ConversationTurn {
+    pub fn new(role: TurnRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(TurnRole::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(TurnRole::Assistant, content)
+    }
+
+    pub fn summary(content: impl Into<String>) -> Self {
+        Self::new(TurnRole::Summary, content)
+    }
+}
+
+/// Persistence port for conversation histories
+#[async_trait]
+pub trait ConversationStore: Send + Sync {
+    /// Append turns to the end of a session's history
+    async fn append(&self, session_id: &str, turns: Vec<ConversationTurn>) -> InferenceResult<()>;
+
+    /// History of a session, oldest first; empty for unknown sessions
+    async fn load(&self, session_id: &str) -> InferenceResult<Vec<ConversationTurn>>;
+
+    /// Forget a session
+    async fn clear(&self, session_id: &str) -> InferenceResult<()>;
+
+    /// Replace a session's history, e.g. after compaction
+    async fn replace(&self, session_id: &str, turns: Vec<ConversationTurn>) -> InferenceResult<()> {
+        self.clear(session_id).await?;
+        self.append(session_id, turns).await
+    }
+}
+
+/// Process-local conversation store
+#[derive(Debug, Default)]
+pub struct InMemoryConversationStore {
+    sessions: Mutex<HashMap<String, Vec<ConversationTurn>>>,
+}
+
+impl InMemoryConversationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of sessions with a history
+    pub fn session_count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl ConversationStore for InMemoryConversationStore {
+    async fn append(&self, session_id: &str, turns: Vec<ConversationTurn>) -> InferenceResult<()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .entry(session_id.to_string())
+            .or_default()
+            .extend(turns);
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &str) -> InferenceResult<Vec<ConversationTurn>> {
+        Ok(self
+            .sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn clear(&self, session_id: &str) -> InferenceResult<()> {
+        self.sessions.lock().unwrap().remove(session_id);
+        Ok(())
+    }
+
+    async fn replace(&self, session_id: &str, turns: Vec<ConversationTurn>) -> InferenceResult<()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), turns);
+        Ok(())
+    }
+}
+
+/// Session of a request, if it carries a `session_id` metadata entry
+pub fn session_id(request: &InferenceRequest) -> Option<&str> {
+    request
+        .metadata
+        .get(SESSION_ID_METADATA_KEY)
+        .map(String::as_str)
+}
+
+/// Transcript of `turns` as `Summary: ...` / `User: ...` / `Assistant: ...` paragraphs
+pub fn format_history(turns: &[ConversationTurn]) -> String {
+    turns
+        .iter()
+        .map(|turn| match turn.role {
+            TurnRole::User => format!("User: {}", turn.content),
+            TurnRole::Assistant => format!("Assistant: {}", turn.content),
+            TurnRole::Summary => format!("Summary: {}", turn.content),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Text of a response as stored in the history
+fn response_text(response: &InferenceResponse) -> String {
+    match &response.content {
+        serde_json::Value::String(text) => text.clone(),
+        content => content.to_string(),
+    }
+}
+
+/// Inference service decorator giving requests with a session id the history of their session
+pub struct SessionInferenceService<S> {
+    inner: S,
+    store: Arc<dyn ConversationStore>,
+    history_parameter: String,
+    policy: MemoryPolicy,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for SessionInferenceService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionInferenceService")
+            .field("inner", &self.inner)
+            .field("history_parameter", &self.history_parameter)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> SessionInferenceService<S> {
+    pub fn new(inner: S, store: Arc<dyn ConversationStore>) -> Self {
+        Self {
+            inner,
+            store,
+            history_parameter: "history".to_string(),
+            policy: MemoryPolicy::default(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: MemoryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Parameter the history is bound to (default `history`)
+    pub fn with_history_parameter(mut self, name: impl Into<String>) -> Self {
+        self.history_parameter = name.into();
+        self
+    }
+
+    pub fn store(&self) -> &Arc<dyn ConversationStore> {
+        &self.store
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Bind `history` to the history parameter, prepending its placeholder when the template
+    /// does not use it
+    fn bind_history(&self, request: &mut InferenceRequest, history: &[ConversationTurn]) {
+        let placeholder = format!("{{{{{}}}}}", self.history_parameter);
+        if !request.template.contains(&placeholder) {
+            if history.is_empty() {
+                return;
+            }
+            request.template = format!("{placeholder}\n\n{}", request.template);
+        }
+        request
+            .parameters
+            .insert(self.history_parameter.clone(), format_history(history));
+    }
+
+    /// Bind as much of `history` as the policy allows; returns the number of turns dropped
+    fn apply_policy(
+        &self,
+        request: &mut InferenceRequest,
+        history: &[ConversationTurn],
+    ) -> InferenceResult<usize> {
+        let max_tokens = match self.policy {
+            MemoryPolicy::Full | MemoryPolicy::Summarize { .. } => {
+                self.bind_history(request, history);
+                return Ok(0);
+            }
+            MemoryPolicy::SlidingWindow { max_tokens } => max_tokens,
+        };
+        for dropped in 0..history.len() {
+            let mut candidate = request.clone();
+            self.bind_history(&mut candidate, &history[dropped..]);
+            if self.inner.count_tokens(&candidate.render_template())? <= max_tokens {
+                *request = candidate;
+                return Ok(dropped);
+            }
+        }
+        self.bind_history(request, &[]);
+        Ok(history.len())
+    }
+
+    /// Fold the old turns of an oversized history into a summary and store the result; returns
+    /// the history to use and the number of turns folded
+    async fn compact(
+        &self,
+        session: &str,
+        history: Vec<ConversationTurn>,
+    ) -> InferenceResult<(Vec<ConversationTurn>, usize)> {
+        let MemoryPolicy::Summarize {
+            max_turns,
+            keep_recent,
+        } = self.policy
+        else {
+            return Ok((history, 0));
+        };
+        if history.len() <= max_turns {
+            return Ok((history, 0));
+        }
+        let split = history.len().saturating_sub(keep_recent);
+        let (old, recent) = history.split_at(split);
+        let mut parameters = HashMap::new();
+        parameters.insert("history".to_string(), format_history(old));
+        let summary = self
+            .inner
+            .infer(InferenceRequest::new(
+                SUMMARY_TEMPLATE,
+                parameters,
+                ModelType::Fast,
+            ))
+            .await?;
+
+        let mut compacted = vec![ConversationTurn::summary(response_text(&summary))];
+        compacted.extend_from_slice(recent);
+        self.store.replace(session, compacted.clone()).await?;
+        Ok((compacted, old.len()))
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for SessionInferenceService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let Some(session) = session_id(&request).map(str::to_string) else {
+            return self.inner.infer(request).await;
+        };
+        let prompt = request.render_template();
+        let history = self.store.load(&session).await?;
+        let (history, summarized) = self.compact(&session, history).await?;
+        let dropped = self.apply_policy(&mut request, &history)?;
+
+        let mut response = self.inner.infer(request).await?;
+        if matches!(self.policy, MemoryPolicy::SlidingWindow { .. }) {
+            response.metadata = response
+                .metadata
+                .with_metadata(DROPPED_TURNS_METADATA_KEY, dropped.to_string());
+        }
+        if matches!(self.policy, MemoryPolicy::Summarize { .. }) {
+            response.metadata = response
+                .metadata
+                .with_metadata(SUMMARIZED_TURNS_METADATA_KEY, summarized.to_string());
+        }
+        self.store
+            .append(
+                &session,
+                vec![
+                    ConversationTurn::user(prompt),
+                    ConversationTurn::assistant(response_text(&response)),
+                ],
+            )
+            .await?;
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        let store = InMemoryConversationStore::new();
+        store
+            .append("s1", vec![ConversationTurn::user("Hi")])
+            .await
+            .unwrap();
+        store
+            .append("s1", vec![ConversationTurn::assistant("Hello!")])
+            .await
+            .unwrap();
+        let history = store.load("s1").await.unwrap();
+        assert_eq!(format_history(&history), "User: Hi\n\nAssistant: Hello!");
+        assert!(store.load("s2").await.unwrap().is_empty());
+
+        store.clear("s1").await.unwrap();
+        assert_eq!(store.session_count(), 0);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_prepends_session_history() {
+        use crate::mock::MockInferenceService;
+
+        let store = Arc::new(InMemoryConversationStore::new());
+        let service = SessionInferenceService::new(
+            MockInferenceService::new()
+                .with_latency(0)
+                .with_custom_response("\"Paris\""),
+            store.clone(),
+        );
+        let request = |question: &str| {
+            let mut parameters = HashMap::new();
+            parameters.insert("question".to_string(), question.to_string());
+            InferenceRequest::new("Q: {{question}}", parameters, ModelType::General)
+                .with_metadata(SESSION_ID_METADATA_KEY, "chat-1")
+        };
+
+        service.infer(request("Capital of France?")).await.unwrap();
+        service.infer(request("And Italy?")).await.unwrap();
+        let history = store.load("chat-1").await.unwrap();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[2].content, "Q: And Italy?");
+        assert_eq!(history[1].content, "Paris");
+
+        // The second request saw the first exchange before its own prompt
+        let mut second = request("And Italy?");
+        service.bind_history(&mut second, &history[..2]);
+        assert_eq!(
+            second.render_template(),
+            "User: Q: Capital of France?\n\nAssistant: Paris\n\nQ: And Italy?"
+        );
+
+        // Requests without a session id are not recorded
+        let mut anonymous = request("Unrelated");
+        anonymous.metadata.clear();
+        service.infer(anonymous).await.unwrap();
+        assert_eq!(store.session_count(), 1);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_sliding_window_drops_oldest_turns() {
+        use crate::mock::MockInferenceService;
+
+        let store = Arc::new(InMemoryConversationStore::new());
+        store
+            .append(
+                "chat-1",
+                vec![
+                    ConversationTurn::user("a".repeat(40)),
+                    ConversationTurn::assistant("b".repeat(40)),
+                    ConversationTurn::user("c".repeat(40)),
+                    ConversationTurn::assistant("d".repeat(40)),
+                ],
+            )
+            .await
+            .unwrap();
+        let service = SessionInferenceService::new(
+            MockInferenceService::new().with_latency(0),
+            store.clone(),
+        )
+        .with_policy(MemoryPolicy::SlidingWindow { max_tokens: 30 });
+        let mut request = InferenceRequest::new("Next?", HashMap::new(), ModelType::General)
+            .with_metadata(SESSION_ID_METADATA_KEY, "chat-1");
+
+        // "User: cccc..." and "Assistant: dddd..." plus the prompt take 27 tokens
+        let history = store.load("chat-1").await.unwrap();
+        assert_eq!(service.apply_policy(&mut request, &history).unwrap(), 2);
+        let prompt = request.render_template();
+        assert!(prompt.starts_with("User: ccc"));
+        assert!(prompt.ends_with("\n\nNext?"));
+
+        let response = service.infer(request).await.unwrap();
+        assert_eq!(response.metadata.metadata[DROPPED_TURNS_METADATA_KEY], "2");
+
+        // Nothing fits: the prompt goes out without history
+        let service = service.with_policy(MemoryPolicy::SlidingWindow { max_tokens: 1 });
+        let mut request = InferenceRequest::new("Next?", HashMap::new(), ModelType::General);
+        assert_eq!(service.apply_policy(&mut request, &history).unwrap(), 4);
+        assert_eq!(request.render_template(), "Next?");
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_summarize_compacts_old_turns() {
+        use crate::mock::MockInferenceService;
+
+        let store = Arc::new(InMemoryConversationStore::new());
+        let service = SessionInferenceService::new(
+            MockInferenceService::new().with_latency(0),
+            store.clone(),
+        )
+        .with_policy(MemoryPolicy::Summarize {
+            max_turns: 4,
+            keep_recent: 2,
+        });
+        let request = |n: usize| {
+            InferenceRequest::new(format!("Turn {n}"), HashMap::new(), ModelType::General)
+                .with_metadata(SESSION_ID_METADATA_KEY, "chat-1")
+        };
+
+        for n in 0..2 {
+            let response = service.infer(request(n)).await.unwrap();
+            assert_eq!(
+                response.metadata.metadata[SUMMARIZED_TURNS_METADATA_KEY],
+                "0"
+            );
+        }
+        // The third request sees four stored turns, the limit; the fourth sees six and folds the
+        // first two exchanges
+        service.infer(request(2)).await.unwrap();
+        let response = service.infer(request(3)).await.unwrap();
+        assert_eq!(
+            response.metadata.metadata[SUMMARIZED_TURNS_METADATA_KEY],
+            "4"
+        );
+
+        let history = store.load("chat-1").await.unwrap();
+        let roles: Vec<_> = history.iter().map(|turn| turn.role).collect();
+        assert_eq!(
+            roles,
+            vec![
+                TurnRole::Summary,
+                TurnRole::User,
+                TurnRole::Assistant,
+                TurnRole::User,
+                TurnRole::Assistant,
+            ]
+        );
+        assert!(history[0]
+            .content
+            .contains("Quick response: Summarize the conversation"));
+        assert!(history[0].content.contains("User: Turn 0"));
+        assert_eq!(history[1].content, "Turn 2");
+    }
+}
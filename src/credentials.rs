@@ -0,0 +1,198 @@
+//! Credentials for provider adapters
+//!
+//! Adapters take a `CredentialsProvider` instead of a static API key string, so keys can be
+//! fetched from Vault, a KMS or the environment and rotated without a restart. Adapters call
+//! `get_credentials` before each request and, when the provider rejects the key (HTTP 401,
+//! gRPC `UNAUTHENTICATED`), call `refresh_credentials` and retry once with the new key.
+
+use crate::*;
+use std::sync::Mutex;
+
+/// API key handed to an adapter
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    api_key: String,
+    /// When the key stops being valid, if known
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &"<redacted>")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl Credentials {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            expires_at: None,
+        }
+    }
+
+    pub fn with_expiry(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    /// `Authorization` header value
+    pub fn bearer(&self) -> String {
+        format!("Bearer {}", self.api_key)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+}
+
+/// Source of the credentials an adapter authenticates with
+#[async_trait]
+pub trait CredentialsProvider: Send + Sync {
+    /// Credentials to use for the next request
+    async fn get_credentials(&self) -> InferenceResult<Credentials>;
+
+    /// Credentials to retry with after the provider rejected the current ones
+    ///
+    /// Caching providers must bypass their cache here. The default fetches again.
+    async fn refresh_credentials(&self) -> InferenceResult<Credentials> {
+        self.get_credentials().await
+    }
+}
+
+/// Fixed API key
+#[derive(Debug, Clone)]
+pub struct StaticCredentials(Credentials);
+
+impl StaticCredentials {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self(Credentials::new(api_key))
+    }
+}
+
+#[async_trait]
+impl CredentialsProvider for StaticCredentials {
+    async fn get_credentials(&self) -> InferenceResult<Credentials> {
+        Ok(self.0.clone())
+    }
+}
+
+/// API key read from an environment variable on every request, so a rotated value is picked
+/// up without a restart
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    variable: String,
+}
+
+impl EnvCredentials {
+    pub fn new(variable: impl Into<String>) -> Self {
+        Self {
+            variable: variable.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl CredentialsProvider for EnvCredentials {
+    async fn get_credentials(&self) -> InferenceResult<Credentials> {
+        std::env::var(&self.variable)
+            .map(Credentials::new)
+            .map_err(|_| TylError::configuration(format!("{} is not set", self.variable)))
+    }
+}
+
+/// Caches the credentials of a slow provider (Vault, KMS) until they expire or are rejected
+pub struct CachedCredentials<P> {
+    inner: P,
+    cached: Mutex<Option<Credentials>>,
+}
+
+impl<P: std::fmt::Debug> std::fmt::Debug for CachedCredentials<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedCredentials")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P: CredentialsProvider> CachedCredentials<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Get the wrapped provider
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<P: CredentialsProvider> CredentialsProvider for CachedCredentials<P> {
+    async fn get_credentials(&self) -> InferenceResult<Credentials> {
+        let cached = self.cached.lock().unwrap().clone();
+        match cached {
+            Some(credentials) if !credentials.is_expired() => Ok(credentials),
+            _ => self.refresh_credentials().await,
+        }
+    }
+
+    async fn refresh_credentials(&self) -> InferenceResult<Credentials> {
+        let credentials = self.inner.refresh_credentials().await?;
+        *self.cached.lock().unwrap() = Some(credentials.clone());
+        Ok(credentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Vault {
+        fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl CredentialsProvider for Vault {
+        async fn get_credentials(&self) -> InferenceResult<Credentials> {
+            let fetch = self.fetches.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(Credentials::new(format!("key-{fetch}")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_credentials_refresh() {
+        let provider = CachedCredentials::new(Vault::default());
+        assert_eq!(provider.get_credentials().await.unwrap().api_key(), "key-1");
+        assert_eq!(provider.get_credentials().await.unwrap().api_key(), "key-1");
+
+        assert_eq!(
+            provider.refresh_credentials().await.unwrap().api_key(),
+            "key-2"
+        );
+        assert_eq!(provider.get_credentials().await.unwrap().api_key(), "key-2");
+        assert_eq!(provider.inner().fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_credentials_debug_is_redacted() {
+        let credentials = Credentials::new("sk-secret");
+        assert!(!format!("{credentials:?}").contains("sk-secret"));
+        assert_eq!(credentials.bearer(), "Bearer sk-secret");
+        assert!(!credentials.is_expired());
+        assert!(Credentials::new("old")
+            .with_expiry(Utc::now() - chrono::Duration::seconds(1))
+            .is_expired());
+    }
+}
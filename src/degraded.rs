@@ -0,0 +1,379 @@
+//! Degraded mode answering from an FAQ store during outages
+//!
+//! `DegradedModeService` passes requests to its inner service. When a request fails and the
+//! inner service also reports itself unhealthy (all backends down, rather than a bad request),
+//! it answers from an `FaqStore` instead: the query is embedded with a `TextEmbedder` and the
+//! most similar FAQ entry is returned if its cosine similarity reaches the threshold. Degraded
+//! answers are flagged under `DEGRADED_METADATA_KEY` and report no token usage; without a close
+//! enough entry the original error is returned.
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::degraded::*;
+//! # use serde_json::json;
+//! # async fn example(router: impl InferenceService + 'static, embedder: impl TextEmbedder + Clone + 'static) -> TylResult<()> {
+//! let mut faq = FaqStore::new();
+//! faq.add(&embedder, "How do I reset my password?", json!("Use the 'Forgot password' link.")).await?;
+//! let service = DegradedModeService::new(router, embedder, faq)
+//!     .with_query_parameter("question")
+//!     .with_min_similarity(0.8);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::*;
+use std::sync::Arc;
+
+/// Response metadata key set to `"true"` on answers served from the FAQ store
+pub const DEGRADED_METADATA_KEY: &str = "degraded";
+/// Response metadata key holding the cosine similarity of the FAQ entry served
+pub const DEGRADED_SIMILARITY_METADATA_KEY: &str = "degraded_similarity";
+/// Response metadata key holding the question of the FAQ entry served
+pub const DEGRADED_QUESTION_METADATA_KEY: &str = "degraded_question";
+/// Response metadata key holding the error of the inner service
+pub const DEGRADED_REASON_METADATA_KEY: &str = "degraded_reason";
+
+/// Model reported by degraded answers
+pub const DEGRADED_MODEL: &str = "faq-fallback";
+
+/// Turns text into an embedding vector
+#[async_trait]
+pub trait TextEmbedder: Send + Sync {
+    async fn embed(&self, text: &str) -> InferenceResult<Vec<f32>>;
+}
+
+/// Precomputed answer to a frequent question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaqEntry {
+    pub question: String,
+    pub answer: serde_json::Value,
+    /// Embedding of `question`
+    pub embedding: Vec<f32>,
+}
+
+/// FAQ entries searched by embedding similarity
+///
+/// Serializable so embeddings can be computed offline and loaded at startup.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaqStore {
+    entries: Vec<FaqEntry>,
+}
+
+impl FaqStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry whose embedding is already computed
+    pub fn with_entry(mut self, entry: FaqEntry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    /// Embed `question` and add its answer
+    pub async fn add(
+        &mut self,
+        embedder: &dyn TextEmbedder,
+        question: impl Into<String>,
+        answer: serde_json::Value,
+    ) -> InferenceResult<()> {
+        let question = question.into();
+        let embedding = embedder.embed(&question).await?;
+        self.entries.push(FaqEntry {
+            question,
+            answer,
+            embedding,
+        });
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[FaqEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entry most similar to `embedding`, with its cosine similarity
+    pub fn best_match(&self, embedding: &[f32]) -> Option<(&FaqEntry, f32)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry, cosine_similarity(&entry.embedding, embedding)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+/// Cosine similarity of two vectors; 0 when their lengths differ or either is zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Inference service decorator answering from an FAQ store when the inner service is down
+pub struct DegradedModeService<S> {
+    inner: S,
+    embedder: Arc<dyn TextEmbedder>,
+    store: FaqStore,
+    min_similarity: f32,
+    query_parameter: Option<String>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for DegradedModeService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DegradedModeService")
+            .field("inner", &self.inner)
+            .field("faq_entries", &self.store.len())
+            .field("min_similarity", &self.min_similarity)
+            .field("query_parameter", &self.query_parameter)
+            .finish()
+    }
+}
+
+impl<S: InferenceService> DegradedModeService<S> {
+    /// Fall back to `store` (entries need a similarity of at least 0.8 by default)
+    pub fn new(inner: S, embedder: impl TextEmbedder + 'static, store: FaqStore) -> Self {
+        Self {
+            inner,
+            embedder: Arc::new(embedder),
+            store,
+            min_similarity: 0.8,
+            query_parameter: None,
+        }
+    }
+
+    /// Minimum cosine similarity for an FAQ entry to be served
+    pub fn with_min_similarity(mut self, min_similarity: f32) -> Self {
+        self.min_similarity = min_similarity;
+        self
+    }
+
+    /// Match FAQ entries against this request parameter instead of the whole rendered prompt
+    pub fn with_query_parameter(mut self, parameter: impl Into<String>) -> Self {
+        self.query_parameter = Some(parameter.into());
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Whether a failure means the backends are down rather than the request being at fault
+    async fn is_outage(&self) -> bool {
+        match self.inner.health_check().await {
+            Ok(health) => !health.status.is_healthy(),
+            Err(_) => true,
+        }
+    }
+
+    /// Degraded answer for `request`, if an FAQ entry is close enough
+    async fn faq_answer(
+        &self,
+        request: &InferenceRequest,
+        reason: &TylError,
+    ) -> Option<InferenceResponse> {
+        let query = match &self.query_parameter {
+            Some(name) => request.parameters.get(name)?.clone(),
+            None => request.render_template(),
+        };
+        let embedding = self.embedder.embed(&query).await.ok()?;
+        let (entry, similarity) = self.store.best_match(&embedding)?;
+        if similarity < self.min_similarity {
+            return None;
+        }
+
+        let metadata = ResponseMetadata::new(DEGRADED_MODEL.to_string(), TokenUsage::new(0, 0), 0)
+            .with_metadata(DEGRADED_METADATA_KEY, "true")
+            .with_metadata(DEGRADED_SIMILARITY_METADATA_KEY, format!("{similarity:.4}"))
+            .with_metadata(DEGRADED_QUESTION_METADATA_KEY, entry.question.clone())
+            .with_metadata(DEGRADED_REASON_METADATA_KEY, reason.to_string());
+        Some(InferenceResponse::new(entry.answer.clone(), metadata))
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for DegradedModeService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let error = match self.inner.infer(request.clone()).await {
+            Ok(response) => return Ok(response),
+            Err(error) => error,
+        };
+        if self.store.is_empty() || !self.is_outage().await {
+            return Err(error);
+        }
+        match self.faq_answer(&request, &error).await {
+            Some(response) => Ok(response),
+            None => Err(error),
+        }
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Counts of a few keywords, enough to tell the test questions apart
+    struct KeywordEmbedder;
+
+    #[async_trait]
+    impl TextEmbedder for KeywordEmbedder {
+        async fn embed(&self, text: &str) -> InferenceResult<Vec<f32>> {
+            let text = text.to_lowercase();
+            Ok(["password", "invoice", "refund", "reset"]
+                .iter()
+                .map(|word| text.matches(word).count() as f32)
+                .collect())
+        }
+    }
+
+    /// Backend whose outage can be toggled; requests mentioning "bad" are rejected
+    #[derive(Debug, Default)]
+    struct Backend {
+        down: AtomicBool,
+    }
+
+    #[async_trait]
+    impl InferenceService for Backend {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(TylError::network("connection refused"));
+            }
+            if request.render_template().contains("bad") {
+                return Err(TylError::validation("template", "bad request"));
+            }
+            Ok(InferenceResponse::new(
+                serde_json::json!("live answer"),
+                ResponseMetadata::new("gpt-4o".to_string(), TokenUsage::new(10, 5), 100),
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(
+                if self.down.load(Ordering::SeqCst) {
+                    HealthStatus::unhealthy("all backends down")
+                } else {
+                    HealthStatus::healthy()
+                },
+            ))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["gpt-4o".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    async fn service() -> DegradedModeService<Backend> {
+        let mut faq = FaqStore::new();
+        faq.add(
+            &KeywordEmbedder,
+            "How do I reset my password?",
+            serde_json::json!("Use the 'Forgot password' link."),
+        )
+        .await
+        .unwrap();
+        faq.add(
+            &KeywordEmbedder,
+            "Where is my invoice?",
+            serde_json::json!("Invoices are under Billing."),
+        )
+        .await
+        .unwrap();
+        DegradedModeService::new(Backend::default(), KeywordEmbedder, faq)
+            .with_query_parameter("question")
+    }
+
+    fn ask(question: &str) -> InferenceRequest {
+        InferenceRequest::new(
+            "Answer: {{question}}",
+            HashMap::from([("question".to_string(), question.to_string())]),
+            ModelType::General,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_answers_from_faq_during_outage() {
+        let service = service().await;
+        let live = service.infer(ask("password reset please")).await.unwrap();
+        assert!(!live.metadata.metadata.contains_key(DEGRADED_METADATA_KEY));
+
+        service.inner().down.store(true, Ordering::SeqCst);
+        let degraded = service.infer(ask("password reset please")).await.unwrap();
+        assert_eq!(
+            degraded.content,
+            serde_json::json!("Use the 'Forgot password' link.")
+        );
+        assert_eq!(degraded.metadata.model, DEGRADED_MODEL);
+        assert_eq!(
+            degraded.metadata.metadata.get(DEGRADED_METADATA_KEY),
+            Some(&"true".to_string())
+        );
+        assert_eq!(degraded.metadata.token_usage.total_tokens, 0);
+
+        // No close enough entry: the outage error surfaces
+        assert!(service.infer(ask("refund status")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_errors_are_not_degraded() {
+        let service = service().await;
+        assert!(service.infer(ask("bad password reset")).await.is_err());
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+}
@@ -0,0 +1,418 @@
+//! Model deprecations and migration
+//!
+//! `ModelDeprecations` maps retired or retiring models to their replacement and sunset date.
+//! `DeprecationService` checks every request against it: requests for a deprecated model get a
+//! structured warning in their response metadata under `deprecation` (for streams, in the
+//! metadata of each candidate's last chunk), and a `ModelDeprecated` event when an `EventBus` is
+//! attached. With auto-migration on, the request is sent to the
+//! replacement instead:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::deprecation::*;
+//! # use tyl_llm_inference_port::events::*;
+//! # async fn example(openai: impl InferenceService + 'static, bus: EventBus) -> TylResult<()> {
+//! let service = DeprecationService::new(openai, ModelDeprecations::with_defaults())
+//!     .with_auto_migrate(true)
+//!     .with_events(bus.clone());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `build_service` applies the built-in table to every adapter it builds; the
+//! `migrate_deprecated` config flag turns on auto-migration.
+
+use crate::ensemble::forward_batch;
+use crate::events::{self, EventBus, InferenceEvent};
+use crate::*;
+use chrono::NaiveDate;
+
+/// Response metadata key holding the deprecation warning of a request's model, as a JSON object
+/// with `model`, `replacement`, `sunset`, `migrated` and `message`
+pub const DEPRECATION_METADATA_KEY: &str = "deprecation";
+
+/// A deprecated model, its replacement and when it stops being served
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelDeprecation {
+    pub model: String,
+    /// Model requests should move to; deprecations without one are never auto-migrated
+    pub replacement: Option<String>,
+    /// Date the provider stops serving the model
+    pub sunset: Option<NaiveDate>,
+}
+
+impl ModelDeprecation {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            replacement: None,
+            sunset: None,
+        }
+    }
+
+    pub fn with_replacement(mut self, model: impl Into<String>) -> Self {
+        self.replacement = Some(model.into());
+        self
+    }
+
+    pub fn with_sunset(mut self, date: NaiveDate) -> Self {
+        self.sunset = Some(date);
+        self
+    }
+
+    /// Whether the sunset date is on or before `today`
+    pub fn is_sunset(&self, today: NaiveDate) -> bool {
+        self.sunset.is_some_and(|sunset| sunset <= today)
+    }
+
+    /// Human-readable warning, e.g. for logs
+    pub fn message(&self) -> String {
+        let mut message = format!("Model {} is deprecated", self.model);
+        if let Some(sunset) = self.sunset {
+            message.push_str(&format!(" and retired on {sunset}"));
+        }
+        if let Some(replacement) = &self.replacement {
+            message.push_str(&format!("; use {replacement}"));
+        }
+        message
+    }
+}
+
+/// Deprecation table keyed by exact model name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelDeprecations {
+    deprecations: HashMap<String, ModelDeprecation>,
+}
+
+impl ModelDeprecations {
+    /// Empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Announced OpenAI and Anthropic retirements
+    pub fn with_defaults() -> Self {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).expect("valid date");
+        [
+            ("gpt-4-32k", "gpt-4o", date(2025, 6, 6)),
+            ("gpt-4-vision-preview", "gpt-4o", date(2024, 12, 6)),
+            ("gpt-3.5-turbo-0613", "gpt-3.5-turbo", date(2024, 9, 13)),
+            (
+                "text-davinci-003",
+                "gpt-3.5-turbo-instruct",
+                date(2024, 1, 4),
+            ),
+            (
+                "claude-2.1",
+                "claude-3-5-sonnet-20241022",
+                date(2025, 7, 21),
+            ),
+            (
+                "claude-3-sonnet-20240229",
+                "claude-3-5-sonnet-20241022",
+                date(2025, 7, 21),
+            ),
+            (
+                "claude-instant-1.2",
+                "claude-3-5-haiku-20241022",
+                date(2024, 11, 6),
+            ),
+        ]
+        .into_iter()
+        .fold(Self::new(), |table, (model, replacement, sunset)| {
+            table.with_deprecation(
+                ModelDeprecation::new(model)
+                    .with_replacement(replacement)
+                    .with_sunset(sunset),
+            )
+        })
+    }
+
+    /// Add a deprecation, replacing any previous entry for the same model
+    pub fn with_deprecation(mut self, deprecation: ModelDeprecation) -> Self {
+        self.deprecations
+            .insert(deprecation.model.clone(), deprecation);
+        self
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelDeprecation> {
+        self.deprecations.get(model)
+    }
+
+    pub fn len(&self) -> usize {
+        self.deprecations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deprecations.is_empty()
+    }
+}
+
+/// Inference service decorator warning about, and optionally migrating, deprecated models
+#[derive(Debug)]
+pub struct DeprecationService<S> {
+    inner: S,
+    deprecations: ModelDeprecations,
+    auto_migrate: bool,
+    events: Option<EventBus>,
+}
+
+impl<S: InferenceService> DeprecationService<S> {
+    pub fn new(inner: S, deprecations: ModelDeprecations) -> Self {
+        Self {
+            inner,
+            deprecations,
+            auto_migrate: false,
+            events: None,
+        }
+    }
+
+    /// Send requests for a deprecated model to its replacement
+    pub fn with_auto_migrate(mut self, auto_migrate: bool) -> Self {
+        self.auto_migrate = auto_migrate;
+        self
+    }
+
+    /// Publish a `ModelDeprecated` event for every request of a deprecated model
+    pub fn with_events(mut self, bus: EventBus) -> Self {
+        self.events = Some(bus);
+        self
+    }
+
+    pub fn deprecations(&self) -> &ModelDeprecations {
+        &self.deprecations
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Warn about the request's model and migrate it when enabled; returns the warning
+    fn check(&self, request: &mut InferenceRequest) -> Option<String> {
+        let deprecation = self.deprecations.get(request.model_override.as_deref()?)?;
+        let migrated_to = deprecation
+            .replacement
+            .as_ref()
+            .filter(|_| self.auto_migrate);
+        if let Some(replacement) = migrated_to {
+            request.model_override = Some(replacement.clone());
+        }
+        if let (Some(bus), Some(request_id)) = (&self.events, events::request_id(request)) {
+            bus.publish(InferenceEvent::ModelDeprecated {
+                request_id: request_id.to_string(),
+                model: deprecation.model.clone(),
+                replacement: deprecation.replacement.clone(),
+                sunset: deprecation.sunset,
+                migrated: migrated_to.is_some(),
+            });
+        }
+        Some(
+            serde_json::json!({
+                "model": deprecation.model,
+                "replacement": deprecation.replacement,
+                "sunset": deprecation.sunset,
+                "migrated": migrated_to.is_some(),
+                "message": deprecation.message(),
+            })
+            .to_string(),
+        )
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for DeprecationService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let warning = self.check(&mut request);
+        let mut response = self.inner.infer(request).await?;
+        if let Some(warning) = warning {
+            response.metadata = response
+                .metadata
+                .with_metadata(DEPRECATION_METADATA_KEY, warning);
+        }
+        Ok(response)
+    }
+
+    async fn infer_stream(
+        &self,
+        mut request: InferenceRequest,
+    ) -> InferenceResult<InferenceStream> {
+        let warning = self.check(&mut request);
+        let stream = self.inner.infer_stream(request).await?;
+        Ok(match warning {
+            Some(warning) => stream.map_chunks(move |chunk| {
+                if chunk.is_final() {
+                    chunk.with_metadata(DEPRECATION_METADATA_KEY, warning.clone())
+                } else {
+                    chunk
+                }
+            }),
+            None => stream,
+        })
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        forward_batch(&self.inner, requests, |request| Ok(self.check(request)))
+            .await
+            .into_iter()
+            .map(|result| {
+                let (warning, response) = result?;
+                let mut response = response?;
+                if let Some(warning) = warning {
+                    response.metadata = response
+                        .metadata
+                        .with_metadata(DEPRECATION_METADATA_KEY, warning);
+                }
+                Ok(response)
+            })
+            .collect()
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_table() {
+        let table = ModelDeprecations::with_defaults();
+        let gpt4_32k = table.get("gpt-4-32k").unwrap();
+        assert_eq!(gpt4_32k.replacement.as_deref(), Some("gpt-4o"));
+        assert!(gpt4_32k.is_sunset(NaiveDate::from_ymd_opt(2025, 6, 6).unwrap()));
+        assert!(!gpt4_32k.is_sunset(NaiveDate::from_ymd_opt(2025, 6, 5).unwrap()));
+        assert_eq!(
+            gpt4_32k.message(),
+            "Model gpt-4-32k is deprecated and retired on 2025-06-06; use gpt-4o"
+        );
+        assert!(table.get("gpt-4o").is_none());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_warns_and_migrates() {
+        use crate::events::REQUEST_ID_METADATA_KEY;
+        use crate::mock::MockInferenceService;
+        use std::sync::{Arc, Mutex};
+
+        let table = ModelDeprecations::new()
+            .with_deprecation(ModelDeprecation::new("gpt-4-32k").with_replacement("gpt-4o"))
+            .with_deprecation(ModelDeprecation::new("legacy-finetune"));
+        let request = |model: &str| {
+            InferenceRequest::new("Hi", HashMap::new(), ModelType::General)
+                .with_model(model)
+                .with_metadata(REQUEST_ID_METADATA_KEY, "req-1")
+        };
+
+        let warning = |response: &InferenceResponse| -> serde_json::Value {
+            serde_json::from_str(&response.metadata.metadata[DEPRECATION_METADATA_KEY]).unwrap()
+        };
+
+        let service = DeprecationService::new(MockInferenceService::new().with_latency(0), table);
+        let response = service.infer(request("gpt-4-32k")).await.unwrap();
+        assert_eq!(response.metadata.model, "gpt-4-32k");
+        assert_eq!(warning(&response)["replacement"], "gpt-4o");
+        assert_eq!(warning(&response)["migrated"], false);
+        let response = service.infer(request("gpt-4o")).await.unwrap();
+        assert!(!response
+            .metadata
+            .metadata
+            .contains_key(DEPRECATION_METADATA_KEY));
+
+        let bus = EventBus::new();
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let sink = published.clone();
+        bus.subscribe(move |event: &InferenceEvent| sink.lock().unwrap().push(event.clone()));
+        let service = service.with_auto_migrate(true).with_events(bus);
+        let response = service.infer(request("gpt-4-32k")).await.unwrap();
+        assert_eq!(response.metadata.model, "gpt-4o");
+        assert_eq!(warning(&response)["migrated"], true);
+        // Without a replacement the request is only flagged
+        let response = service.infer(request("legacy-finetune")).await.unwrap();
+        assert_eq!(response.metadata.model, "legacy-finetune");
+
+        let published = published.lock().unwrap();
+        assert_eq!(published.len(), 2);
+        assert!(matches!(
+            &published[0],
+            InferenceEvent::ModelDeprecated { model, migrated: true, .. } if model == "gpt-4-32k"
+        ));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_stream_carries_the_warning() {
+        use crate::events::REQUEST_ID_METADATA_KEY;
+        use crate::mock::MockInferenceService;
+        use std::sync::{Arc, Mutex};
+
+        let table = ModelDeprecations::new()
+            .with_deprecation(ModelDeprecation::new("gpt-4-32k").with_replacement("gpt-4o"));
+        let bus = EventBus::new();
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let sink = published.clone();
+        bus.subscribe(move |event: &InferenceEvent| sink.lock().unwrap().push(event.clone()));
+        let service = DeprecationService::new(MockInferenceService::new().with_latency(0), table)
+            .with_auto_migrate(true)
+            .with_events(bus);
+        let request = |model: &str| {
+            InferenceRequest::new("Hi", HashMap::new(), ModelType::General)
+                .with_model(model)
+                .with_metadata(REQUEST_ID_METADATA_KEY, "req-1")
+        };
+
+        let mut stream = service.infer_stream(request("gpt-4-32k")).await.unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next_chunk().await {
+            chunks.push(chunk.unwrap());
+        }
+        let last = chunks.pop().unwrap();
+        assert!(last.is_final());
+        let warning: serde_json::Value =
+            serde_json::from_str(&last.metadata[DEPRECATION_METADATA_KEY]).unwrap();
+        assert_eq!(warning["model"], "gpt-4-32k");
+        assert_eq!(warning["migrated"], true);
+        assert!(chunks.iter().all(|chunk| chunk.metadata.is_empty()));
+        assert_eq!(published.lock().unwrap().len(), 1);
+
+        let mut stream = service.infer_stream(request("gpt-4o")).await.unwrap();
+        while let Some(chunk) = stream.next_chunk().await {
+            assert!(chunk.unwrap().metadata.is_empty());
+        }
+        assert_eq!(published.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,133 @@
+//! Dry-run responses for requests with `dry_run` set
+//!
+//! A dry run renders the template, checks that every placeholder has a parameter, counts the
+//! prompt tokens with the adapter's tokenizer and resolves the concrete model, then returns that
+//! as a `DryRunReport` instead of calling the provider, which makes CI prompt checks free:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::dry_run::*;
+//! # async fn example(service: impl InferenceService + 'static, request: InferenceRequest) -> Result<(), Box<dyn std::error::Error>> {
+//! let response = service.infer(request.with_dry_run(true)).await?;
+//! let report: DryRunReport = serde_json::from_value(response.content)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Adapters answer dry runs with `dry_run_response` before contacting their backend. Dry-run
+//! responses are flagged under `DRY_RUN_METADATA_KEY` and report zero token usage, so ledgers
+//! and budgets see them as free.
+
+use crate::*;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Response metadata key set to `"true"` on dry-run responses
+pub const DRY_RUN_METADATA_KEY: &str = "dry_run";
+
+/// What a request would have sent to the provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DryRunReport {
+    pub rendered_prompt: String,
+    /// Model the request resolves to
+    pub model: String,
+    pub prompt_tokens: usize,
+    pub max_tokens: Option<usize>,
+    /// Parameters without a placeholder in the template
+    pub unused_parameters: Vec<String>,
+}
+
+/// Dry-run response of `service` for `request`
+///
+/// Fails with a validation error when the request is invalid under `limits` or placeholders
+/// remain unresolved after rendering.
+pub fn dry_run_response<S: InferenceService + ?Sized>(
+    service: &S,
+    request: &InferenceRequest,
+    limits: &RequestLimits,
+) -> InferenceResult<InferenceResponse> {
+    request.validate_with(service, limits)?;
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    let placeholder =
+        PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*[^{}\s]+\s*\}\}").expect("valid regex"));
+
+    let rendered_prompt = request.render_template();
+    let unresolved: Vec<&str> = placeholder
+        .find_iter(&rendered_prompt)
+        .map(|unresolved| unresolved.as_str())
+        .collect();
+    if !unresolved.is_empty() {
+        return Err(TylError::validation(
+            "parameters",
+            format!("Unresolved placeholders: {}", unresolved.join(", ")),
+        ));
+    }
+
+    let mut unused_parameters: Vec<String> = request
+        .parameters
+        .keys()
+        .filter(|name| !request.template.contains(&format!("{{{{{name}}}}}")))
+        .cloned()
+        .collect();
+    unused_parameters.sort();
+
+    let model = request
+        .model_override
+        .clone()
+        .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string());
+    let report = DryRunReport {
+        prompt_tokens: service.count_tokens(&rendered_prompt)?,
+        rendered_prompt,
+        model: model.clone(),
+        max_tokens: request.max_tokens,
+        unused_parameters,
+    };
+    let content = serde_json::to_value(&report)
+        .map_err(|e| TylError::internal(format!("Failed to encode dry-run report: {e}")))?;
+    Ok(InferenceResponse::new(
+        content,
+        ResponseMetadata::new(model, TokenUsage::new(0, 0), 0)
+            .with_metadata(DRY_RUN_METADATA_KEY, "true"),
+    ))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+
+    #[tokio::test]
+    async fn test_dry_run_reports_without_calling_provider() {
+        let service = MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response("from provider");
+        let request = InferenceRequest::new(
+            "Summarize {{text}}",
+            HashMap::from([
+                ("text".to_string(), "the report".to_string()),
+                ("tone".to_string(), "dry".to_string()),
+            ]),
+            ModelType::Fast,
+        )
+        .with_dry_run(true);
+
+        let response = service.infer(request.clone()).await.unwrap();
+        let report: DryRunReport = serde_json::from_value(response.content).unwrap();
+        assert_eq!(report.rendered_prompt, "Summarize the report");
+        assert_eq!(report.model, "gpt-3.5-turbo");
+        assert_eq!(
+            report.prompt_tokens,
+            service.count_tokens("Summarize the report").unwrap()
+        );
+        assert_eq!(report.unused_parameters, vec!["tone"]);
+        assert_eq!(response.metadata.token_usage.total_tokens, 0);
+        assert_eq!(
+            response.metadata.metadata.get(DRY_RUN_METADATA_KEY),
+            Some(&"true".to_string())
+        );
+
+        let mut missing = request;
+        missing.parameters.remove("text");
+        assert!(service.infer(missing).await.is_err());
+    }
+}
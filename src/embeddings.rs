@@ -0,0 +1,399 @@
+//! Embeddings port
+//!
+//! `EmbeddingService` is the sibling of `InferenceService` for text embeddings: it turns a batch
+//! of texts into vectors, one per text and in input order, and reports the tokens the provider
+//! billed. Retrieval and semantic caching depend on this contract rather than on a provider:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::embeddings::*;
+//! # #[cfg(feature = "http-client")]
+//! # async fn example(texts: Vec<String>) -> TylResult<()> {
+//! let embedder = OpenAiEmbeddingService::new(reqwest::Client::new())
+//!     .with_credentials(EnvCredentials::new("OPENAI_API_KEY"))
+//!     .with_model("text-embedding-3-large")
+//!     .with_dimensions(1024);
+//! let response = embedder.embed(&texts).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `OpenAiEmbeddingService` (`POST /embeddings`) and `OllamaEmbeddingService` (`POST /api/embed`)
+//! send their requests through an `HttpTransport`; `MockEmbeddingService` (`mock` feature)
+//! embeds deterministically without a backend.
+
+use crate::credentials::CredentialsProvider;
+use crate::http_client::{parse_json_response, send_authorized, HttpRequest, HttpTransport};
+use crate::*;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Vectors for a batch of texts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    /// One vector per input text, in input order
+    pub embeddings: Vec<Vec<f32>>,
+    /// Model that produced the vectors
+    pub model: String,
+    /// Tokens billed for the input; embeddings have no completion tokens
+    pub token_usage: TokenUsage,
+    pub processing_time_ms: u64,
+}
+
+impl EmbeddingResponse {
+    pub fn new(
+        embeddings: Vec<Vec<f32>>,
+        model: String,
+        token_usage: TokenUsage,
+        processing_time_ms: u64,
+    ) -> Self {
+        Self {
+            embeddings,
+            model,
+            token_usage,
+            processing_time_ms,
+        }
+    }
+
+    /// Length of the vectors, `None` without any
+    pub fn dimensions(&self) -> Option<usize> {
+        self.embeddings.first().map(Vec::len)
+    }
+}
+
+/// Port for text embedding backends
+#[async_trait]
+pub trait EmbeddingService: Send + Sync {
+    /// Embed `texts`, returning exactly one vector per text in the same order
+    async fn embed(&self, texts: &[String]) -> InferenceResult<EmbeddingResponse>;
+
+    /// Model used for embeddings
+    fn embedding_model(&self) -> String;
+
+    /// Embed a single text
+    async fn embed_one(&self, text: &str) -> InferenceResult<Vec<f32>> {
+        let response = self.embed(&[text.to_string()]).await?;
+        response.embeddings.into_iter().next().ok_or_else(|| {
+            inference_errors::generation_failed("Embedding backend returned no vector")
+        })
+    }
+}
+
+/// Check that a backend returned one vector per input
+fn check_count(response: EmbeddingResponse, expected: usize) -> InferenceResult<EmbeddingResponse> {
+    if response.embeddings.len() != expected {
+        return Err(inference_errors::generation_failed(format!(
+            "Embedding backend returned {} vectors for {expected} texts",
+            response.embeddings.len()
+        )));
+    }
+    Ok(response)
+}
+
+fn encode(body: &serde_json::Value) -> InferenceResult<Vec<u8>> {
+    serde_json::to_vec(body)
+        .map_err(|e| TylError::internal(format!("Failed to encode embedding request: {e}")))
+}
+
+/// `EmbeddingService` over the OpenAI embeddings API or a compatible server
+#[derive(Clone)]
+pub struct OpenAiEmbeddingService {
+    base_url: String,
+    model: String,
+    dimensions: Option<usize>,
+    transport: Arc<dyn HttpTransport>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+}
+
+impl std::fmt::Debug for OpenAiEmbeddingService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiEmbeddingService")
+            .field("base_url", &self.base_url)
+            .field("model", &self.model)
+            .field("dimensions", &self.dimensions)
+            .field("credentials", &self.credentials.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddings {
+    data: Vec<OpenAiEmbedding>,
+    model: String,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: u32,
+}
+
+impl OpenAiEmbeddingService {
+    /// `text-embedding-3-small` at `https://api.openai.com/v1` through `transport`
+    pub fn new(transport: impl HttpTransport + 'static) -> Self {
+        Self {
+            base_url: "https://api.openai.com/v1".to_string(),
+            model: "text-embedding-3-small".to_string(),
+            dimensions: None,
+            transport: Arc::new(transport),
+            credentials: None,
+        }
+    }
+
+    /// Base URL of an OpenAI-compatible server, including the version path
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Ask for shortened vectors (supported by `text-embedding-3-*`)
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Authenticate with bearer credentials from `provider`, refreshing them once on 401
+    pub fn with_credentials(mut self, provider: impl CredentialsProvider + 'static) -> Self {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+}
+
+#[async_trait]
+impl EmbeddingService for OpenAiEmbeddingService {
+    async fn embed(&self, texts: &[String]) -> InferenceResult<EmbeddingResponse> {
+        if texts.is_empty() {
+            return Ok(EmbeddingResponse::new(
+                Vec::new(),
+                self.model.clone(),
+                TokenUsage::new(0, 0),
+                0,
+            ));
+        }
+        let started = Instant::now();
+        let mut body = serde_json::json!({ "model": self.model, "input": texts });
+        if let Some(dimensions) = self.dimensions {
+            body["dimensions"] = dimensions.into();
+        }
+        let request =
+            HttpRequest::post_json(format!("{}/embeddings", self.base_url), encode(&body)?);
+        let response = send_authorized(
+            self.transport.as_ref(),
+            self.credentials.as_deref(),
+            request,
+        )
+        .await?;
+        let mut parsed: OpenAiEmbeddings = parse_json_response(&response, "OpenAI")?;
+
+        parsed.data.sort_by_key(|embedding| embedding.index);
+        let prompt_tokens = parsed.usage.map_or(0, |usage| usage.prompt_tokens);
+        check_count(
+            EmbeddingResponse::new(
+                parsed
+                    .data
+                    .into_iter()
+                    .map(|embedding| embedding.embedding)
+                    .collect(),
+                parsed.model,
+                TokenUsage::new(prompt_tokens, 0),
+                started.elapsed().as_millis() as u64,
+            ),
+            texts.len(),
+        )
+    }
+
+    fn embedding_model(&self) -> String {
+        self.model.clone()
+    }
+}
+
+/// `EmbeddingService` over a local Ollama server
+#[derive(Clone)]
+pub struct OllamaEmbeddingService {
+    base_url: String,
+    model: String,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl std::fmt::Debug for OllamaEmbeddingService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OllamaEmbeddingService")
+            .field("base_url", &self.base_url)
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddings {
+    model: String,
+    embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    prompt_eval_count: u32,
+}
+
+impl OllamaEmbeddingService {
+    /// `nomic-embed-text` at `http://localhost:11434` through `transport`
+    pub fn new(transport: impl HttpTransport + 'static) -> Self {
+        Self {
+            base_url: "http://localhost:11434".to_string(),
+            model: "nomic-embed-text".to_string(),
+            transport: Arc::new(transport),
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+}
+
+#[async_trait]
+impl EmbeddingService for OllamaEmbeddingService {
+    async fn embed(&self, texts: &[String]) -> InferenceResult<EmbeddingResponse> {
+        if texts.is_empty() {
+            return Ok(EmbeddingResponse::new(
+                Vec::new(),
+                self.model.clone(),
+                TokenUsage::new(0, 0),
+                0,
+            ));
+        }
+        let started = Instant::now();
+        let body = serde_json::json!({ "model": self.model, "input": texts });
+        let request =
+            HttpRequest::post_json(format!("{}/api/embed", self.base_url), encode(&body)?)
+                .with_attempt_timeout(RequestContext::current_remaining_time());
+        let response = self.transport.send(request).await?;
+        let parsed: OllamaEmbeddings = parse_json_response(&response, "Ollama")?;
+        check_count(
+            EmbeddingResponse::new(
+                parsed.embeddings,
+                parsed.model,
+                TokenUsage::new(parsed.prompt_eval_count, 0),
+                started.elapsed().as_millis() as u64,
+            ),
+            texts.len(),
+        )
+    }
+
+    fn embedding_model(&self) -> String {
+        self.model.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::StaticCredentials;
+    use crate::http_client::HttpResponse;
+    use crate::test_support::scripted;
+
+    fn body(request: &HttpRequest) -> serde_json::Value {
+        serde_json::from_slice(request.body.as_ref().unwrap()).unwrap()
+    }
+
+    fn texts() -> Vec<String> {
+        vec!["first".to_string(), "second".to_string()]
+    }
+
+    #[tokio::test]
+    async fn test_openai_embeddings_in_input_order() {
+        let transport = scripted(vec![HttpResponse::new(
+            200,
+            serde_json::to_vec(&serde_json::json!({
+                "object": "list",
+                "data": [
+                    {"object": "embedding", "index": 1, "embedding": [0.0, 1.0]},
+                    {"object": "embedding", "index": 0, "embedding": [1.0, 0.0]}
+                ],
+                "model": "text-embedding-3-small",
+                "usage": {"prompt_tokens": 4, "total_tokens": 4}
+            }))
+            .unwrap(),
+        )]);
+        let service = OpenAiEmbeddingService::new(Arc::clone(&transport))
+            .with_credentials(StaticCredentials::new("sk-test"))
+            .with_dimensions(2);
+
+        let response = service.embed(&texts()).await.unwrap();
+        assert_eq!(response.embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(response.dimensions(), Some(2));
+        assert_eq!(response.token_usage, TokenUsage::new(4, 0));
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url, "https://api.openai.com/v1/embeddings");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer sk-test"));
+        assert_eq!(
+            body(&requests[0]),
+            serde_json::json!({
+                "model": "text-embedding-3-small",
+                "input": ["first", "second"],
+                "dimensions": 2
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openai_errors_and_empty_input() {
+        let transport = scripted(vec![HttpResponse::new(
+            400,
+            br#"{"error": {"message": "input too long", "type": "invalid_request_error"}}"#
+                .to_vec(),
+        )]);
+        let service = OpenAiEmbeddingService::new(Arc::clone(&transport));
+        let error = service.embed(&texts()).await.unwrap_err();
+        assert!(error.to_string().contains("input too long"));
+
+        let response = service.embed(&[]).await.unwrap();
+        assert!(response.embeddings.is_empty());
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ollama_embeddings() {
+        let transport = scripted(vec![
+            HttpResponse::new(
+                200,
+                br#"{"model": "nomic-embed-text", "embeddings": [[0.5], [0.25]], "prompt_eval_count": 3}"#
+                    .to_vec(),
+            ),
+            HttpResponse::new(
+                200,
+                br#"{"model": "nomic-embed-text", "embeddings": [[0.5]]}"#.to_vec(),
+            ),
+        ]);
+        let service = OllamaEmbeddingService::new(Arc::clone(&transport))
+            .with_base_url("http://gpu-box:11434/");
+
+        let response = service.embed(&texts()).await.unwrap();
+        assert_eq!(response.embeddings, vec![vec![0.5], vec![0.25]]);
+        assert_eq!(response.token_usage.prompt_tokens, 3);
+        // One vector for two texts breaks the contract
+        assert!(service.embed(&texts()).await.is_err());
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url, "http://gpu-box:11434/api/embed");
+        assert_eq!(
+            body(&requests[0]),
+            serde_json::json!({"model": "nomic-embed-text", "input": ["first", "second"]})
+        );
+    }
+}
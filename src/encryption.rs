@@ -0,0 +1,279 @@
+//! Encryption envelope for requests persisted at rest
+//!
+//! Job stores and queues (Kafka, Redis...) keep copies of requests long after they run.
+//! `RequestEncryptor::seal` turns a request into an `EncryptedRequest` whose parameter values
+//! (and optionally the rendered prompt) are encrypted field by field with AES-256-GCM, so the
+//! persisted copy never contains plaintext customer data; `open` restores the request.
+//!
+//! Keys come from a `KeyProvider`. Each envelope records the id of the key that sealed it, so
+//! keys can be rotated while older envelopes are still queued.
+
+use crate::*;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+/// Additional authenticated data binding the rendered prompt ciphertext to its field
+const RENDERED_PROMPT_AAD: &str = "rendered_prompt";
+
+/// Source of the 256-bit keys used by `RequestEncryptor`
+pub trait KeyProvider: Send + Sync {
+    /// Id of the key new envelopes are sealed with
+    fn current_key_id(&self) -> String;
+
+    /// Key with the given id (current or retired)
+    fn key(&self, key_id: &str) -> InferenceResult<[u8; 32]>;
+}
+
+/// Keys held in memory, e.g. loaded from a secrets manager at startup
+#[derive(Clone)]
+pub struct StaticKeyProvider {
+    current: String,
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl std::fmt::Debug for StaticKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut key_ids: Vec<&String> = self.keys.keys().collect();
+        key_ids.sort();
+        f.debug_struct("StaticKeyProvider")
+            .field("current", &self.current)
+            .field("key_ids", &key_ids)
+            .finish()
+    }
+}
+
+impl StaticKeyProvider {
+    pub fn new(key_id: impl Into<String>, key: [u8; 32]) -> Self {
+        let key_id = key_id.into();
+        Self {
+            keys: HashMap::from([(key_id.clone(), key)]),
+            current: key_id,
+        }
+    }
+
+    /// Keep a retired key available for opening older envelopes
+    pub fn with_retired_key(mut self, key_id: impl Into<String>, key: [u8; 32]) -> Self {
+        self.keys.entry(key_id.into()).or_insert(key);
+        self
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current_key_id(&self) -> String {
+        self.current.clone()
+    }
+
+    fn key(&self, key_id: &str) -> InferenceResult<[u8; 32]> {
+        self.keys
+            .get(key_id)
+            .copied()
+            .ok_or_else(|| TylError::configuration(format!("Unknown encryption key id {key_id}")))
+    }
+}
+
+/// One encrypted value, base64 encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedField {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Request safe to persist: parameters and rendered prompt are encrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedRequest {
+    /// The request with its parameters removed
+    pub request: InferenceRequest,
+    /// Id of the key that sealed the envelope
+    pub key_id: String,
+    /// Encrypted parameter values by parameter name
+    pub parameters: HashMap<String, EncryptedField>,
+    /// Encrypted rendered prompt, when sealed with `RequestEncryptor::with_rendered_prompt`
+    pub rendered_prompt: Option<EncryptedField>,
+}
+
+/// Seals and opens `EncryptedRequest` envelopes
+pub struct RequestEncryptor {
+    keys: Box<dyn KeyProvider>,
+    include_rendered_prompt: bool,
+}
+
+impl std::fmt::Debug for RequestEncryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestEncryptor")
+            .field("current_key_id", &self.keys.current_key_id())
+            .field("include_rendered_prompt", &self.include_rendered_prompt)
+            .finish()
+    }
+}
+
+impl RequestEncryptor {
+    pub fn new(keys: impl KeyProvider + 'static) -> Self {
+        Self {
+            keys: Box::new(keys),
+            include_rendered_prompt: false,
+        }
+    }
+
+    /// Also store the rendered prompt (encrypted), e.g. for audit trails
+    pub fn with_rendered_prompt(mut self, include: bool) -> Self {
+        self.include_rendered_prompt = include;
+        self
+    }
+
+    /// Encrypt the request's parameters with the current key
+    pub fn seal(&self, request: &InferenceRequest) -> InferenceResult<EncryptedRequest> {
+        let key_id = self.keys.current_key_id();
+        let cipher = self.cipher(&key_id)?;
+
+        let parameters = request
+            .parameters
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), encrypt(&cipher, name, value)?)))
+            .collect::<InferenceResult<_>>()?;
+        let rendered_prompt = if self.include_rendered_prompt {
+            Some(encrypt(
+                &cipher,
+                RENDERED_PROMPT_AAD,
+                &request.render_template(),
+            )?)
+        } else {
+            None
+        };
+
+        let mut request = request.clone();
+        request.parameters.clear();
+        Ok(EncryptedRequest {
+            request,
+            key_id,
+            parameters,
+            rendered_prompt,
+        })
+    }
+
+    /// Decrypt an envelope back into the original request
+    pub fn open(&self, envelope: &EncryptedRequest) -> InferenceResult<InferenceRequest> {
+        let cipher = self.cipher(&envelope.key_id)?;
+        let mut request = envelope.request.clone();
+        for (name, field) in &envelope.parameters {
+            request
+                .parameters
+                .insert(name.clone(), decrypt(&cipher, name, field)?);
+        }
+        Ok(request)
+    }
+
+    /// Decrypt the rendered prompt stored in an envelope, if any
+    pub fn open_rendered_prompt(
+        &self,
+        envelope: &EncryptedRequest,
+    ) -> InferenceResult<Option<String>> {
+        let Some(field) = &envelope.rendered_prompt else {
+            return Ok(None);
+        };
+        let cipher = self.cipher(&envelope.key_id)?;
+        decrypt(&cipher, RENDERED_PROMPT_AAD, field).map(Some)
+    }
+
+    fn cipher(&self, key_id: &str) -> InferenceResult<Aes256Gcm> {
+        let key = self.keys.key(key_id)?;
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+}
+
+fn encrypt(cipher: &Aes256Gcm, field: &str, plaintext: &str) -> InferenceResult<EncryptedField> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plaintext.as_bytes(),
+        aad: field.as_bytes(),
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| inference_errors::encryption_failed(field))?;
+    Ok(EncryptedField {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn decrypt(cipher: &Aes256Gcm, field: &str, encrypted: &EncryptedField) -> InferenceResult<String> {
+    let failed = || inference_errors::encryption_failed(field);
+    let nonce = BASE64.decode(&encrypted.nonce).map_err(|_| failed())?;
+    let ciphertext = BASE64.decode(&encrypted.ciphertext).map_err(|_| failed())?;
+    if nonce.len() != 12 {
+        return Err(failed());
+    }
+    let payload = Payload {
+        msg: &ciphertext,
+        aad: field.as_bytes(),
+    };
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), payload)
+        .map_err(|_| failed())?;
+    String::from_utf8(plaintext).map_err(|_| failed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> InferenceRequest {
+        let parameters = HashMap::from([
+            ("name".to_string(), "Ada Lovelace".to_string()),
+            ("email".to_string(), "ada@example.com".to_string()),
+        ]);
+        InferenceRequest::new(
+            "Write to {{name}} at {{email}}",
+            parameters,
+            ModelType::General,
+        )
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let encryptor =
+            RequestEncryptor::new(StaticKeyProvider::new("k1", [7; 32])).with_rendered_prompt(true);
+
+        let envelope = encryptor.seal(&request()).unwrap();
+        let persisted = serde_json::to_string(&envelope).unwrap();
+        assert!(!persisted.contains("Ada"));
+        assert!(!persisted.contains("ada@example.com"));
+        assert!(envelope.request.parameters.is_empty());
+
+        let envelope: EncryptedRequest = serde_json::from_str(&persisted).unwrap();
+        let opened = encryptor.open(&envelope).unwrap();
+        assert_eq!(opened.parameters, request().parameters);
+        assert_eq!(
+            encryptor
+                .open_rendered_prompt(&envelope)
+                .unwrap()
+                .as_deref(),
+            Some("Write to Ada Lovelace at ada@example.com")
+        );
+    }
+
+    #[test]
+    fn test_key_rotation_and_tampering() {
+        let old = RequestEncryptor::new(StaticKeyProvider::new("k1", [1; 32]));
+        let envelope = old.seal(&request()).unwrap();
+
+        let rotated = RequestEncryptor::new(
+            StaticKeyProvider::new("k2", [2; 32]).with_retired_key("k1", [1; 32]),
+        );
+        assert_eq!(
+            rotated.open(&envelope).unwrap().parameters,
+            request().parameters
+        );
+        assert_eq!(rotated.seal(&request()).unwrap().key_id, "k2");
+
+        // Ciphertexts are bound to their parameter name
+        let mut swapped = envelope.clone();
+        let name = swapped.parameters["name"].clone();
+        swapped.parameters.insert("email".to_string(), name);
+        assert!(rotated.open(&swapped).is_err());
+
+        let unknown = RequestEncryptor::new(StaticKeyProvider::new("k3", [3; 32]));
+        assert!(unknown.open(&envelope).is_err());
+    }
+}
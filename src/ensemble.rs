@@ -0,0 +1,553 @@
+//! Ensemble inference across several backends
+//!
+//! `EnsembleInferenceService` sends the same request to every backend concurrently and merges
+//! the successful responses with an `EnsembleAggregator`:
+//!
+//! - `MajorityVoteAggregator` votes field by field on JSON object responses (or on the whole
+//!   content otherwise); ties go to the earliest backend.
+//! - `JudgeAggregator` asks a judge model to pick the best candidate.
+//!
+//! Every backend is billed, so the merged response reports the summed token usage. The backends
+//! that answered are recorded under `ENSEMBLE_BACKENDS_METADATA_KEY`.
+
+use crate::canonical::to_canonical_string;
+use crate::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Response metadata key listing the backends that answered, comma separated
+pub const ENSEMBLE_BACKENDS_METADATA_KEY: &str = "ensemble_backends";
+/// Response metadata key holding the backend whose answer the judge picked
+pub const ENSEMBLE_SELECTED_METADATA_KEY: &str = "ensemble_selected";
+
+/// Successful answer of one ensemble backend
+#[derive(Debug, Clone)]
+pub struct EnsembleCandidate {
+    pub backend: String,
+    pub response: InferenceResponse,
+}
+
+/// Merges the candidates of an ensemble into one response
+#[async_trait]
+pub trait EnsembleAggregator: Send + Sync {
+    /// `candidates` is never empty and keeps backend declaration order
+    ///
+    /// The returned token usage must cover only the aggregator's own inference calls (zero when
+    /// it makes none); the ensemble adds the usage of every candidate.
+    async fn aggregate(
+        &self,
+        request: &InferenceRequest,
+        candidates: &[EnsembleCandidate],
+    ) -> InferenceResult<InferenceResponse>;
+}
+
+/// Field-wise majority vote
+///
+/// Object responses are merged key by key, each key taking its most common value among the
+/// candidates that have it. Other responses vote on the whole content.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MajorityVoteAggregator;
+
+/// Most common value, ties going to the value seen first
+fn majority<'a>(values: impl IntoIterator<Item = &'a serde_json::Value>) -> serde_json::Value {
+    let mut tally: Vec<(String, &serde_json::Value, usize)> = Vec::new();
+    for value in values {
+        let key = to_canonical_string(value);
+        match tally.iter_mut().find(|(seen, _, _)| *seen == key) {
+            Some((_, _, count)) => *count += 1,
+            None => tally.push((key, value, 1)),
+        }
+    }
+    let mut best: Option<(&serde_json::Value, usize)> = None;
+    for (_, value, count) in tally {
+        if best.map_or(true, |(_, best_count)| count > best_count) {
+            best = Some((value, count));
+        }
+    }
+    best.map(|(value, _)| value.clone()).unwrap_or_default()
+}
+
+#[async_trait]
+impl EnsembleAggregator for MajorityVoteAggregator {
+    async fn aggregate(
+        &self,
+        _request: &InferenceRequest,
+        candidates: &[EnsembleCandidate],
+    ) -> InferenceResult<InferenceResponse> {
+        let contents: Vec<&serde_json::Value> =
+            candidates.iter().map(|c| &c.response.content).collect();
+
+        let content = if contents.iter().all(|content| content.is_object()) {
+            let mut keys: Vec<&String> = Vec::new();
+            for content in &contents {
+                for key in content
+                    .as_object()
+                    .into_iter()
+                    .flat_map(|object| object.keys())
+                {
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+            let merged: serde_json::Map<String, serde_json::Value> = keys
+                .into_iter()
+                .map(|key| {
+                    let votes = contents.iter().filter_map(|content| content.get(key));
+                    (key.clone(), majority(votes))
+                })
+                .collect();
+            serde_json::Value::Object(merged)
+        } else {
+            majority(contents.iter().copied())
+        };
+
+        let mut response = candidates[0].response.clone();
+        response.content = content;
+        response.metadata.token_usage = TokenUsage::new(0, 0);
+        Ok(response)
+    }
+}
+
+/// Asks a judge model which candidate answers the request best
+pub struct JudgeAggregator {
+    judge: Box<dyn InferenceService>,
+    model_type: ModelType,
+}
+
+impl std::fmt::Debug for JudgeAggregator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JudgeAggregator")
+            .field("model_type", &self.model_type)
+            .finish_non_exhaustive()
+    }
+}
+
+const JUDGE_TEMPLATE: &str = "You are judging candidate answers to the same task.\n\n\
+Task:\n{{task}}\n\n\
+Candidates:\n{{candidates}}\n\n\
+Reply with JSON {\"best\": <number of the most accurate and complete candidate>}.";
+
+impl JudgeAggregator {
+    /// Judge with `ModelType::Reasoning` requests to `judge`
+    pub fn new(judge: impl InferenceService + 'static) -> Self {
+        Self {
+            judge: Box::new(judge),
+            model_type: ModelType::Reasoning,
+        }
+    }
+
+    pub fn with_model_type(mut self, model_type: ModelType) -> Self {
+        self.model_type = model_type;
+        self
+    }
+
+    /// 1-based candidate number picked by the judge
+    fn parse_choice(content: &serde_json::Value) -> Option<usize> {
+        match content {
+            serde_json::Value::Object(object) => object
+                .get("best")
+                .and_then(|best| best.as_u64().or_else(|| best.as_str()?.trim().parse().ok()))
+                .map(|best| best as usize),
+            serde_json::Value::Number(number) => number.as_u64().map(|best| best as usize),
+            serde_json::Value::String(text) => text
+                .split(|c: char| !c.is_ascii_digit())
+                .find(|digits| !digits.is_empty())
+                .and_then(|digits| digits.parse().ok()),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl EnsembleAggregator for JudgeAggregator {
+    async fn aggregate(
+        &self,
+        request: &InferenceRequest,
+        candidates: &[EnsembleCandidate],
+    ) -> InferenceResult<InferenceResponse> {
+        let listing = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                format!(
+                    "{}. {}",
+                    index + 1,
+                    to_canonical_string(&candidate.response.content)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let parameters = HashMap::from([
+            ("task".to_string(), request.render_template()),
+            ("candidates".to_string(), listing),
+        ]);
+        let judge_request = InferenceRequest::new(JUDGE_TEMPLATE, parameters, self.model_type)
+            .with_temperature(0.0);
+
+        let verdict = self.judge.infer(judge_request).await?;
+        let choice = Self::parse_choice(&verdict.content)
+            .filter(|choice| (1..=candidates.len()).contains(choice))
+            .ok_or_else(|| {
+                inference_errors::generation_failed(format!(
+                    "Judge returned no valid candidate number: {}",
+                    verdict.content
+                ))
+            })?;
+
+        let chosen = &candidates[choice - 1];
+        let mut response = chosen.response.clone();
+        response.metadata.token_usage = verdict.metadata.token_usage;
+        response.metadata = response
+            .metadata
+            .with_metadata(ENSEMBLE_SELECTED_METADATA_KEY, chosen.backend.clone());
+        Ok(response)
+    }
+}
+
+fn add_usage(a: &TokenUsage, b: &TokenUsage) -> TokenUsage {
+    TokenUsage::new(
+        a.prompt_tokens + b.prompt_tokens,
+        a.completion_tokens + b.completion_tokens,
+    )
+}
+
+pub(crate) type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Polls every future (by default backend calls) to completion, keeping results in input order
+pub(crate) struct JoinAll<'a, T = InferenceResult<InferenceResponse>> {
+    pending: Vec<Option<BoxedFuture<'a, T>>>,
+    results: Vec<Option<T>>,
+}
+
+// Outputs are only moved around, never polled, so they need no pinning
+impl<T> Unpin for JoinAll<'_, T> {}
+
+impl<'a, T> JoinAll<'a, T> {
+    pub(crate) fn new(futures: impl IntoIterator<Item = BoxedFuture<'a, T>>) -> Self {
+        let pending: Vec<_> = futures.into_iter().map(Some).collect();
+        let results = pending.iter().map(|_| None).collect();
+        Self { pending, results }
+    }
+}
+
+impl<T> Future for JoinAll<'_, T> {
+    type Output = Vec<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        for (slot, result) in this.pending.iter_mut().zip(this.results.iter_mut()) {
+            if let Some(future) = slot {
+                if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                    *result = Some(output);
+                    *slot = None;
+                }
+            }
+        }
+        if this.pending.iter().any(Option::is_some) {
+            return Poll::Pending;
+        }
+        Poll::Ready(this.results.iter_mut().filter_map(Option::take).collect())
+    }
+}
+
+/// Send the requests `admit` accepts to `inner` as one `infer_batch` call
+///
+/// Every admitted request yields what `admit` returned for it next to its result; a rejected
+/// request yields the error `admit` returned. Results keep the order of `requests`.
+pub(crate) async fn forward_batch<S, T>(
+    inner: &S,
+    requests: Vec<InferenceRequest>,
+    mut admit: impl FnMut(&mut InferenceRequest) -> InferenceResult<T> + Send,
+) -> Vec<InferenceResult<(T, InferenceResult<InferenceResponse>)>>
+where
+    S: InferenceService + ?Sized,
+    T: Send,
+{
+    let mut admitted = Vec::with_capacity(requests.len());
+    let mut contexts = Vec::with_capacity(requests.len());
+    for mut request in requests {
+        let context = admit(&mut request);
+        if context.is_ok() {
+            admitted.push(request);
+        }
+        contexts.push(context);
+    }
+
+    let responses = if admitted.is_empty() {
+        Vec::new()
+    } else {
+        inner.infer_batch(admitted).await
+    };
+    let mut responses = responses.into_iter();
+    contexts
+        .into_iter()
+        .map(|context| {
+            let context = context?;
+            let response = responses.next().unwrap_or_else(|| {
+                Err(TylError::internal(
+                    "Batch returned fewer responses than requests",
+                ))
+            });
+            Ok((context, response))
+        })
+        .collect()
+}
+
+/// Inference service sending each request to several backends and merging the answers
+pub struct EnsembleInferenceService {
+    backends: Vec<(String, Box<dyn InferenceService>)>,
+    aggregator: Box<dyn EnsembleAggregator>,
+    min_responses: usize,
+}
+
+impl std::fmt::Debug for EnsembleInferenceService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backends: Vec<&String> = self.backends.iter().map(|(name, _)| name).collect();
+        f.debug_struct("EnsembleInferenceService")
+            .field("backends", &backends)
+            .field("min_responses", &self.min_responses)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EnsembleInferenceService {
+    /// Merge with `aggregator`, tolerating failed backends as long as one answers
+    pub fn new(aggregator: impl EnsembleAggregator + 'static) -> Self {
+        Self {
+            backends: Vec::new(),
+            aggregator: Box::new(aggregator),
+            min_responses: 1,
+        }
+    }
+
+    pub fn with_backend(
+        mut self,
+        name: impl Into<String>,
+        service: impl InferenceService + 'static,
+    ) -> Self {
+        self.backends.push((name.into(), Box::new(service)));
+        self
+    }
+
+    /// Fail unless at least `min_responses` backends answer
+    pub fn with_min_responses(mut self, min_responses: usize) -> Self {
+        self.min_responses = min_responses.max(1);
+        self
+    }
+
+    pub fn backends(&self) -> Vec<&str> {
+        self.backends
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl InferenceService for EnsembleInferenceService {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let started = Instant::now();
+        let results = JoinAll::new(
+            self.backends
+                .iter()
+                .map(|(_, service)| service.infer(request.clone())),
+        )
+        .await;
+
+        let mut candidates = Vec::new();
+        let mut first_error = None;
+        for ((name, _), result) in self.backends.iter().zip(results) {
+            match result {
+                Ok(response) => candidates.push(EnsembleCandidate {
+                    backend: name.clone(),
+                    response,
+                }),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        if candidates.len() < self.min_responses {
+            return Err(first_error.unwrap_or_else(|| {
+                inference_errors::generation_failed(format!(
+                    "Ensemble needs {} responses, got {}",
+                    self.min_responses,
+                    candidates.len()
+                ))
+            }));
+        }
+
+        let mut response = self.aggregator.aggregate(&request, &candidates).await?;
+        response.metadata.token_usage = candidates
+            .iter()
+            .fold(response.metadata.token_usage.clone(), |usage, candidate| {
+                add_usage(&usage, &candidate.response.metadata.token_usage)
+            });
+        response.metadata.processing_time_ms = started.elapsed().as_millis() as u64;
+        let names: Vec<&str> = candidates.iter().map(|c| c.backend.as_str()).collect();
+        response.metadata = response
+            .metadata
+            .with_metadata(ENSEMBLE_BACKENDS_METADATA_KEY, names.join(","));
+        Ok(response)
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let mut healthy = 0;
+        for (_, service) in &self.backends {
+            if matches!(service.health_check().await, Ok(health) if health.status.is_healthy()) {
+                healthy += 1;
+            }
+        }
+        let status = if healthy >= self.min_responses {
+            HealthStatus::healthy()
+        } else {
+            HealthStatus::unhealthy(format!(
+                "{healthy} healthy backends, {} required",
+                self.min_responses
+            ))
+        };
+        Ok(HealthCheckResult::new(status).with_metadata("healthy_backends", healthy.into()))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        let mut models = Vec::new();
+        for (_, service) in &self.backends {
+            for model in service.supported_models() {
+                if !models.contains(&model) {
+                    models.push(model);
+                }
+            }
+        }
+        models
+    }
+
+    /// Union of the backends' listings; a backend whose listing fails contributes its
+    /// `supported_models`
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        let mut models = Vec::new();
+        for (_, service) in &self.backends {
+            let listed = match service.list_models().await {
+                Ok(listed) => listed,
+                Err(_) => service.supported_models(),
+            };
+            for model in listed {
+                if !models.contains(&model) {
+                    models.push(model);
+                }
+            }
+        }
+        Ok(models)
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        match self.backends.first() {
+            Some((_, service)) => service.count_tokens(text),
+            None => Ok((text.len() + 3) / 4),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+
+    fn backend(response: &str) -> MockInferenceService {
+        MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response(response)
+    }
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Extract the invoice", HashMap::new(), ModelType::General)
+    }
+
+    #[tokio::test]
+    async fn test_forward_batch_keeps_rejected_requests_in_place() {
+        let requests = ["a", "skip", "c"]
+            .map(|template| InferenceRequest::new(template, HashMap::new(), ModelType::General));
+        let results = forward_batch(&backend("ok"), requests.to_vec(), |request| {
+            if request.template == "skip" {
+                return Err(TylError::validation("template", "rejected"));
+            }
+            Ok(request.template.clone())
+        })
+        .await;
+
+        assert_eq!(results.len(), 3);
+        let (first, response) = results[0].as_ref().unwrap();
+        assert_eq!(first, "a");
+        assert!(response.is_ok());
+        assert!(results[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("rejected"));
+        assert_eq!(results[2].as_ref().unwrap().0, "c");
+    }
+
+    #[tokio::test]
+    async fn test_majority_vote_per_field() {
+        let service = EnsembleInferenceService::new(MajorityVoteAggregator)
+            .with_backend("a", backend(r#"{"total": 100, "currency": "EUR"}"#))
+            .with_backend("b", backend(r#"{"total": 100, "currency": "USD"}"#))
+            .with_backend(
+                "c",
+                backend(r#"{"total": 120, "currency": "USD", "vat": 21}"#),
+            );
+
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(
+            response.content,
+            serde_json::json!({"total": 100, "currency": "USD", "vat": 21})
+        );
+        assert_eq!(
+            response
+                .metadata
+                .metadata
+                .get(ENSEMBLE_BACKENDS_METADATA_KEY),
+            Some(&"a,b,c".to_string())
+        );
+
+        let single = backend("x").infer(request()).await.unwrap();
+        assert_eq!(
+            response.metadata.token_usage.prompt_tokens,
+            3 * single.metadata.token_usage.prompt_tokens
+        );
+    }
+
+    #[tokio::test]
+    async fn test_judge_picks_candidate() {
+        let service =
+            EnsembleInferenceService::new(JudgeAggregator::new(backend(r#"{"best": 2}"#)))
+                .with_backend("fast", backend("Total: 10"))
+                .with_backend("careful", backend("Total: 100"));
+
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("Total: 100"));
+        assert_eq!(
+            response
+                .metadata
+                .metadata
+                .get(ENSEMBLE_SELECTED_METADATA_KEY),
+            Some(&"careful".to_string())
+        );
+
+        let confused =
+            EnsembleInferenceService::new(JudgeAggregator::new(backend("{\"best\": 7}")))
+                .with_backend("only", backend("Total: 100"));
+        assert!(confused.infer(request()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_min_responses() {
+        let service = EnsembleInferenceService::new(MajorityVoteAggregator)
+            .with_backend("up", backend("yes"))
+            .with_min_responses(2);
+        assert!(service.infer(request()).await.is_err());
+        assert!(!service.health_check().await.unwrap().status.is_healthy());
+    }
+}
@@ -0,0 +1,565 @@
+//! Lifecycle events of inference requests
+//!
+//! Decorators publish `InferenceEvent`s to an `EventBus` instead of each growing its own hooks,
+//! so metrics exporters, audit sinks, webhooks and custom integrations consume one stream.
+//! `EventService` publishes the start, chunks and outcome of every request; other decorators
+//! (e.g. `IdempotentService::with_events`) publish what only they know, correlated through the
+//! request id `EventService` stores under `REQUEST_ID_METADATA_KEY`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::events::*;
+//! # #[cfg(feature = "decorators")]
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! # use tyl_llm_inference_port::idempotency::*;
+//! let bus = EventBus::new();
+//! bus.subscribe(|event: &InferenceEvent| println!("{event:?}"));
+//! let mut events = bus.subscribe_channel(256); // tokio broadcast receiver
+//! let service = EventService::new(IdempotentService::new(openai).with_events(bus.clone()), bus);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Callbacks run synchronously on the publishing task and should hand heavy work off.
+
+use crate::ensemble::forward_batch;
+use crate::streaming::ChunkResult;
+use crate::*;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Request metadata key holding the id events of a request are published under
+pub const REQUEST_ID_METADATA_KEY: &str = "request_id";
+
+/// Something that happened to an inference request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum InferenceEvent {
+    RequestStarted {
+        request_id: String,
+        model_type: ModelType,
+        /// Model the request resolves to
+        model: String,
+        streaming: bool,
+    },
+    ChunkReceived {
+        request_id: String,
+        candidate_index: usize,
+        /// Chunks received so far, including this one
+        chunks: usize,
+    },
+    RetryScheduled {
+        request_id: String,
+        /// Attempt about to be made (the first retry is attempt 2)
+        attempt: u32,
+        delay_ms: u64,
+        error: String,
+    },
+    CacheHit {
+        request_id: String,
+        /// Which cache answered (e.g. `idempotency`)
+        cache: String,
+    },
+    /// The request named a deprecated model
+    ModelDeprecated {
+        request_id: String,
+        model: String,
+        replacement: Option<String>,
+        sunset: Option<chrono::NaiveDate>,
+        /// Whether the request was sent to `replacement` instead
+        migrated: bool,
+    },
+    Completed {
+        request_id: String,
+        model: String,
+        token_usage: Option<TokenUsage>,
+        duration_ms: u64,
+    },
+    Failed {
+        request_id: String,
+        error: String,
+        duration_ms: u64,
+    },
+    /// A budget alert could not be delivered, or the spend of an answered request could not be
+    /// accounted for in its budget
+    BudgetError {
+        request_id: String,
+        scope: String,
+        error: String,
+    },
+}
+
+impl InferenceEvent {
+    pub fn request_id(&self) -> &str {
+        match self {
+            Self::RequestStarted { request_id, .. }
+            | Self::ChunkReceived { request_id, .. }
+            | Self::RetryScheduled { request_id, .. }
+            | Self::CacheHit { request_id, .. }
+            | Self::ModelDeprecated { request_id, .. }
+            | Self::Completed { request_id, .. }
+            | Self::Failed { request_id, .. }
+            | Self::BudgetError { request_id, .. } => request_id,
+        }
+    }
+}
+
+/// Receiver of published events
+pub trait EventSubscriber: Send + Sync {
+    fn on_event(&self, event: &InferenceEvent);
+
+    /// Whether the subscriber is gone and can be dropped from the bus
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+impl<F: Fn(&InferenceEvent) + Send + Sync> EventSubscriber for F {
+    fn on_event(&self, event: &InferenceEvent) {
+        self(event)
+    }
+}
+
+/// Fan-out of inference events to subscribers; clones share the subscriber list
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<RwLock<Vec<Arc<dyn EventSubscriber>>>>,
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscriber_count())
+            .finish()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `subscriber` with every event published from now on
+    pub fn subscribe(&self, subscriber: impl EventSubscriber + 'static) {
+        self.subscribers.write().unwrap().push(Arc::new(subscriber));
+    }
+
+    /// Receive events on a broadcast channel buffering up to `capacity` of them
+    ///
+    /// Slow receivers lag (`RecvError::Lagged`) instead of blocking publishers; the subscription
+    /// is dropped once the receiver is.
+    #[cfg(feature = "decorators")]
+    pub fn subscribe_channel(
+        &self,
+        capacity: usize,
+    ) -> tokio::sync::broadcast::Receiver<InferenceEvent> {
+        let (sender, receiver) = tokio::sync::broadcast::channel(capacity.max(1));
+        let subscriber = ChannelSubscriber(sender);
+        self.subscribers.write().unwrap().push(Arc::new(subscriber));
+        receiver
+    }
+
+    pub fn publish(&self, event: InferenceEvent) {
+        let subscribers = self.subscribers.read().unwrap().clone();
+        for subscriber in &subscribers {
+            subscriber.on_event(&event);
+        }
+        if subscribers.iter().any(|subscriber| subscriber.is_closed()) {
+            self.subscribers
+                .write()
+                .unwrap()
+                .retain(|subscriber| !subscriber.is_closed());
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.read().unwrap().len()
+    }
+}
+
+#[cfg(feature = "decorators")]
+struct ChannelSubscriber(tokio::sync::broadcast::Sender<InferenceEvent>);
+
+#[cfg(feature = "decorators")]
+impl EventSubscriber for ChannelSubscriber {
+    fn on_event(&self, event: &InferenceEvent) {
+        let _ = self.0.send(event.clone());
+    }
+
+    fn is_closed(&self) -> bool {
+        self.0.receiver_count() == 0
+    }
+}
+
+/// Id the events of `request` are published under, if `EventService` assigned one
+pub fn request_id(request: &InferenceRequest) -> Option<&str> {
+    request
+        .metadata
+        .get(REQUEST_ID_METADATA_KEY)
+        .map(String::as_str)
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
+
+/// Inference service decorator publishing the lifecycle of every request to an `EventBus`
+///
+/// Requests without a `REQUEST_ID_METADATA_KEY` get a fresh UUID there before reaching the
+/// inner service.
+#[derive(Debug)]
+pub struct EventService<S> {
+    inner: S,
+    bus: EventBus,
+}
+
+impl<S: InferenceService> EventService<S> {
+    pub fn new(inner: S, bus: EventBus) -> Self {
+        Self { inner, bus }
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    fn start(&self, request: &mut InferenceRequest, streaming: bool) -> String {
+        let request_id = request
+            .metadata
+            .entry(REQUEST_ID_METADATA_KEY.to_string())
+            .or_insert_with(|| Uuid::new_v4().to_string())
+            .clone();
+        self.bus.publish(InferenceEvent::RequestStarted {
+            request_id: request_id.clone(),
+            model_type: request.model_type,
+            model: request
+                .model_override
+                .clone()
+                .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string()),
+            streaming,
+        });
+        request_id
+    }
+
+    fn finish(
+        &self,
+        request_id: String,
+        started: Instant,
+        result: InferenceResult<InferenceResponse>,
+    ) -> InferenceResult<InferenceResponse> {
+        match result {
+            Ok(response) => {
+                self.bus.publish(InferenceEvent::Completed {
+                    request_id,
+                    model: response.metadata.model.clone(),
+                    token_usage: Some(response.metadata.token_usage.clone()),
+                    duration_ms: elapsed_ms(started),
+                });
+                Ok(response)
+            }
+            Err(error) => {
+                self.fail(request_id, &error, started);
+                Err(error)
+            }
+        }
+    }
+
+    fn fail(&self, request_id: String, error: &TylError, started: Instant) {
+        self.bus.publish(InferenceEvent::Failed {
+            request_id,
+            error: error.to_string(),
+            duration_ms: elapsed_ms(started),
+        });
+    }
+}
+
+/// `InferenceLayer` wrapping services in an `EventService` publishing to a shared bus
+#[derive(Debug, Clone)]
+pub struct EventLayer {
+    bus: EventBus,
+}
+
+impl EventLayer {
+    pub fn new(bus: EventBus) -> Self {
+        Self { bus }
+    }
+}
+
+impl<S: InferenceService> InferenceLayer<S> for EventLayer {
+    type Service = EventService<S>;
+
+    fn layer(&self, inner: S) -> EventService<S> {
+        EventService::new(inner, self.bus.clone())
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for EventService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let started = Instant::now();
+        let request_id = self.start(&mut request, false);
+        let result = self.inner.infer(request).await;
+        self.finish(request_id, started, result)
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        let started = Instant::now();
+        forward_batch(&self.inner, requests, |request| {
+            Ok(self.start(request, false))
+        })
+        .await
+        .into_iter()
+        .map(|result| {
+            let (request_id, response) = result?;
+            self.finish(request_id, started, response)
+        })
+        .collect()
+    }
+
+    async fn infer_stream(
+        &self,
+        mut request: InferenceRequest,
+    ) -> InferenceResult<InferenceStream> {
+        let started = Instant::now();
+        let request_id = self.start(&mut request, true);
+        match self.inner.infer_stream(request).await {
+            Ok(stream) => Ok(InferenceStream::new(EventChunks {
+                stream,
+                bus: self.bus.clone(),
+                request_id,
+                started,
+                chunks: 0,
+                model: String::new(),
+                token_usage: None,
+                done: false,
+            })),
+            Err(error) => {
+                self.fail(request_id, &error, started);
+                Err(error)
+            }
+        }
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+/// Stream publishing `ChunkReceived` per chunk and the outcome when it ends
+struct EventChunks {
+    stream: InferenceStream,
+    bus: EventBus,
+    request_id: String,
+    started: Instant,
+    chunks: usize,
+    model: String,
+    token_usage: Option<TokenUsage>,
+    done: bool,
+}
+
+impl Stream for EventChunks {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        let this = &mut *self;
+        let polled = Pin::new(&mut this.stream).poll_next(cx);
+        let Poll::Ready(item) = polled else {
+            return Poll::Pending;
+        };
+        if this.done {
+            return Poll::Ready(item);
+        }
+        match &item {
+            Some(Ok(chunk)) => {
+                this.chunks += 1;
+                if let Some(model) = &chunk.model {
+                    this.model = model.clone();
+                }
+                if let Some(usage) = &chunk.token_usage {
+                    this.token_usage = Some(usage.clone());
+                }
+                this.bus.publish(InferenceEvent::ChunkReceived {
+                    request_id: this.request_id.clone(),
+                    candidate_index: chunk.candidate_index,
+                    chunks: this.chunks,
+                });
+            }
+            Some(Err(error)) => {
+                this.done = true;
+                this.bus.publish(InferenceEvent::Failed {
+                    request_id: this.request_id.clone(),
+                    error: error.to_string(),
+                    duration_ms: elapsed_ms(this.started),
+                });
+            }
+            None => {
+                this.done = true;
+                this.bus.publish(InferenceEvent::Completed {
+                    request_id: this.request_id.clone(),
+                    model: std::mem::take(&mut this.model),
+                    token_usage: this.token_usage.take(),
+                    duration_ms: elapsed_ms(this.started),
+                });
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Service answering "ok", or failing for templates starting with "fail"
+    #[derive(Debug)]
+    struct Echo;
+
+    #[async_trait]
+    impl InferenceService for Echo {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            if request.template.starts_with("fail") {
+                return Err(TylError::network("upstream down"));
+            }
+            Ok(InferenceResponse::new(
+                serde_json::json!("ok"),
+                ResponseMetadata::new("echo-1".to_string(), TokenUsage::new(3, 1), 1),
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["echo-1".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn recorded(bus: &EventBus) -> Arc<Mutex<Vec<InferenceEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        bus.subscribe(move |event: &InferenceEvent| sink.lock().unwrap().push(event.clone()));
+        events
+    }
+
+    #[tokio::test]
+    async fn test_publishes_request_lifecycle() {
+        let bus = EventBus::new();
+        let events = recorded(&bus);
+        let service = EventService::new(Echo, bus);
+
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General)
+            .with_metadata(REQUEST_ID_METADATA_KEY, "req-1");
+        service.infer(request).await.unwrap();
+        let failing = InferenceRequest::new("fail", HashMap::new(), ModelType::Fast);
+        assert!(service.infer(failing).await.is_err());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            &events[0],
+            InferenceEvent::RequestStarted { request_id, model, streaming: false, .. }
+                if request_id == "req-1" && model == "gpt-4o-mini"
+        ));
+        assert!(matches!(
+            &events[1],
+            InferenceEvent::Completed { request_id, token_usage: Some(usage), .. }
+                if request_id == "req-1" && usage.total_tokens == 4
+        ));
+        // Requests without an id get a generated one shared by all their events
+        assert_ne!(events[2].request_id(), "req-1");
+        assert_eq!(events[2].request_id(), events[3].request_id());
+        assert!(
+            matches!(&events[3], InferenceEvent::Failed { error, .. } if error.contains("upstream down"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_publishes_chunks_and_completion() {
+        let bus = EventBus::new();
+        let events = recorded(&bus);
+        let service = EventService::new(Echo, bus);
+
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General);
+        let assembled = service
+            .infer_stream(request)
+            .await
+            .unwrap()
+            .assemble()
+            .await
+            .unwrap();
+        assert_eq!(assembled.len(), 1);
+
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                InferenceEvent::RequestStarted { streaming: true, .. },
+                InferenceEvent::ChunkReceived { chunks: 1, .. },
+                InferenceEvent::Completed { model, .. },
+            ] if model == "echo-1"
+        ));
+    }
+
+    #[cfg(feature = "decorators")]
+    #[tokio::test]
+    async fn test_broadcast_subscribers() {
+        let bus = EventBus::new();
+        let mut receiver = bus.subscribe_channel(8);
+        let event = InferenceEvent::CacheHit {
+            request_id: "req-1".to_string(),
+            cache: "idempotency".to_string(),
+        };
+        bus.publish(event.clone());
+        assert_eq!(receiver.recv().await.unwrap(), event);
+
+        // Dropped receivers are unsubscribed on the next publish
+        drop(receiver);
+        bus.publish(event);
+        assert_eq!(bus.subscriber_count(), 0);
+    }
+}
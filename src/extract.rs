@@ -0,0 +1,211 @@
+//! Typed structured extraction
+//!
+//! `extract` pulls a Rust value out of free text: it generates the JSON schema of the target
+//! type with `schemars`, renders it together with the source text into a built-in extraction
+//! prompt, and decodes the answer through a lenient `ResponseSchema`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::extract::*;
+//! # use serde::Deserialize;
+//! # use schemars::JsonSchema;
+//! #[derive(Deserialize, JsonSchema)]
+//! struct Invoice {
+//!     number: String,
+//!     total_cents: u64,
+//!     due_date: Option<String>,
+//! }
+//!
+//! # async fn example(service: impl InferenceService + 'static, email_body: String) -> TylResult<()> {
+//! let invoice: Invoice = extract(&service, &email_body).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `extract_with` takes `ExtractOptions` to pick the model or replace the prompt; custom
+//! templates receive the schema as `{{schema}}` and the source text as `{{text}}`.
+
+use crate::schema::infer_typed;
+use crate::*;
+use schemars::generate::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+/// Built-in extraction prompt
+pub const EXTRACTION_TEMPLATE: &str = "Extract the requested information from the text below.\n\
+Answer with a single JSON value matching this JSON schema, without any other text.\n\
+Use null for optional fields the text does not mention; do not invent values.\n\n\
+JSON schema:\n{{schema}}\n\n\
+Text:\n{{text}}";
+
+/// JSON schema of `T`, with subschemas inlined so it can be checked by `ResponseSchema`
+pub fn json_schema_for<T: JsonSchema>() -> serde_json::Value {
+    SchemaSettings::draft2020_12()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator()
+        .into_root_schema_for::<T>()
+        .to_value()
+}
+
+/// How `extract_with` builds the extraction request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractOptions {
+    pub template: String,
+    pub model_type: ModelType,
+    pub model_override: Option<String>,
+    pub temperature: f32,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            template: EXTRACTION_TEMPLATE.to_string(),
+            model_type: ModelType::General,
+            model_override: None,
+            temperature: 0.0,
+        }
+    }
+}
+
+impl ExtractOptions {
+    /// Built-in template on a general model, at temperature 0.0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the prompt; it should reference `{{schema}}` and `{{text}}`
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    pub fn with_model_type(mut self, model_type: ModelType) -> Self {
+        self.model_type = model_type;
+        self
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model_override = Some(model.into());
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Request asking for a value matching `schema` out of `text`
+    pub fn request(
+        &self,
+        schema: &serde_json::Value,
+        text: &str,
+    ) -> InferenceResult<InferenceRequest> {
+        let schema = serde_json::to_string_pretty(schema)
+            .map_err(|e| TylError::internal(format!("Failed to serialize schema: {e}")))?;
+        let mut parameters = HashMap::new();
+        parameters.insert("schema".to_string(), schema);
+        parameters.insert("text".to_string(), text.to_string());
+        let mut request = InferenceRequest::new(&self.template, parameters, self.model_type)
+            .with_temperature(self.temperature);
+        if let Some(model) = &self.model_override {
+            request = request.with_model(model.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Extract a `T` from `text` with the built-in template
+pub async fn extract<T, S>(service: &S, text: &str) -> InferenceResult<T>
+where
+    T: JsonSchema + DeserializeOwned,
+    S: InferenceService + ?Sized,
+{
+    extract_with(service, text, &ExtractOptions::default()).await
+}
+
+/// Extract a `T` from `text` as configured by `options`
+pub async fn extract_with<T, S>(
+    service: &S,
+    text: &str,
+    options: &ExtractOptions,
+) -> InferenceResult<T>
+where
+    T: JsonSchema + DeserializeOwned,
+    S: InferenceService + ?Sized,
+{
+    let schema = json_schema_for::<T>();
+    let request = options.request(&schema, text)?;
+    infer_typed(service, request, &ResponseSchema::new(schema).lenient()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct Address {
+        city: String,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct Contact {
+        name: String,
+        age: u32,
+        email: Option<String>,
+        address: Address,
+    }
+
+    #[test]
+    fn test_schema_inlines_nested_types() {
+        let schema = json_schema_for::<Contact>();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["address"]["type"], "object");
+        assert_eq!(
+            schema["properties"]["address"]["properties"]["city"]["type"],
+            "string"
+        );
+        assert!(schema.get("$defs").is_none());
+    }
+
+    #[test]
+    fn test_request_embeds_schema_and_text() {
+        let schema = json_schema_for::<Contact>();
+        let request = ExtractOptions::new()
+            .with_model("small-extractor")
+            .request(&schema, "Ana, 34, lives in Lisbon")
+            .unwrap();
+        let prompt = request.render_template();
+        assert!(prompt.contains("\"age\""));
+        assert!(prompt.ends_with("Text:\nAna, 34, lives in Lisbon"));
+        assert_eq!(request.temperature, Some(0.0));
+        assert_eq!(request.model_override.as_deref(), Some("small-extractor"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_extracts_typed_value() {
+        use crate::mock::MockInferenceService;
+
+        let service = MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response(r#"{"name": "Ana", "age": "34", "address": {"city": "Lisbon"}}"#);
+        let contact: Contact = extract(&service, "Ana, 34, lives in Lisbon").await.unwrap();
+        assert_eq!(
+            contact,
+            Contact {
+                name: "Ana".to_string(),
+                age: 34,
+                email: None,
+                address: Address {
+                    city: "Lisbon".to_string()
+                },
+            }
+        );
+
+        let service = MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response(r#"{"name": "Ana"}"#);
+        let error = extract::<Contact, _>(&service, "Ana").await.unwrap_err();
+        assert!(error.to_string().contains("$.age is required"));
+    }
+}
@@ -0,0 +1,1108 @@
+//! gRPC adapter
+//!
+//! `proto/inference.proto` is the cross-language contract of the port. The `pb` module mirrors
+//! its messages with prost derives and includes the tonic client and server generated by
+//! `build.rs`, so building needs no `protoc`.
+//!
+//! - `GrpcInferenceClient` implements `InferenceService` on top of a remote gRPC endpoint. With a
+//!   `CredentialsProvider` every call carries `authorization: Bearer` metadata, and a call failing
+//!   with `UNAUTHENTICATED` is retried once with refreshed credentials.
+//! - `GrpcInferenceServer` exposes any `InferenceService` over gRPC:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::grpc::*;
+//! # async fn example(service: impl InferenceService + 'static, addr: std::net::SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+//! tonic::transport::Server::builder()
+//!     .add_service(GrpcInferenceServer::new(service).into_service())
+//!     .serve(addr)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::catalog::ModelListCache;
+use crate::credentials::{Credentials, CredentialsProvider};
+use crate::http_client::HttpRequest;
+use crate::signing::AuthSigner;
+use crate::*;
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::metadata::MetadataKey;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status, Streaming};
+
+/// Protobuf messages and generated gRPC stubs for `tyl.inference.v1`
+pub mod pb {
+    use std::collections::HashMap;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum ModelType {
+        General = 0,
+        Coding = 1,
+        Reasoning = 2,
+        Fast = 3,
+        Creative = 4,
+        Vision = 5,
+        Extraction = 6,
+        Summarization = 7,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Priority {
+        Normal = 0,
+        Low = 1,
+        High = 2,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InferenceRequest {
+        #[prost(string, tag = "1")]
+        pub template: String,
+        #[prost(map = "string, string", tag = "2")]
+        pub parameters: HashMap<String, String>,
+        #[prost(enumeration = "ModelType", tag = "3")]
+        pub model_type: i32,
+        #[prost(string, optional, tag = "4")]
+        pub model_override: Option<String>,
+        #[prost(uint32, optional, tag = "5")]
+        pub max_tokens: Option<u32>,
+        #[prost(float, optional, tag = "6")]
+        pub temperature: Option<f32>,
+        #[prost(uint64, optional, tag = "7")]
+        pub timeout_ms: Option<u64>,
+        #[prost(int64, optional, tag = "8")]
+        pub deadline_unix_ms: Option<i64>,
+        #[prost(enumeration = "Priority", optional, tag = "9")]
+        pub priority: Option<i32>,
+        #[prost(string, optional, tag = "10")]
+        pub idempotency_key: Option<String>,
+        #[prost(map = "string, string", tag = "11")]
+        pub metadata: HashMap<String, String>,
+        #[prost(bool, tag = "12")]
+        pub dry_run: bool,
+        #[prost(float, optional, tag = "13")]
+        pub top_p: Option<f32>,
+        #[prost(float, optional, tag = "14")]
+        pub frequency_penalty: Option<f32>,
+        #[prost(float, optional, tag = "15")]
+        pub presence_penalty: Option<f32>,
+        #[prost(string, repeated, tag = "16")]
+        pub stop: Vec<String>,
+        #[prost(uint64, optional, tag = "17")]
+        pub seed: Option<u64>,
+        #[prost(uint32, optional, tag = "18")]
+        pub candidates: Option<u32>,
+        #[prost(uint32, optional, tag = "19")]
+        pub logprobs: Option<u32>,
+        #[prost(message, repeated, tag = "20")]
+        pub attachments: Vec<MediaInput>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MediaInput {
+        #[prost(string, tag = "1")]
+        pub mime_type: String,
+        #[prost(bytes = "vec", tag = "2")]
+        pub data: Vec<u8>,
+        #[prost(string, tag = "3")]
+        pub url: String,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct TokenUsage {
+        #[prost(uint32, tag = "1")]
+        pub prompt_tokens: u32,
+        #[prost(uint32, tag = "2")]
+        pub completion_tokens: u32,
+        #[prost(uint32, tag = "3")]
+        pub total_tokens: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InferenceResponse {
+        #[prost(string, tag = "1")]
+        pub content_json: String,
+        #[prost(string, tag = "2")]
+        pub model: String,
+        #[prost(message, optional, tag = "3")]
+        pub token_usage: Option<TokenUsage>,
+        #[prost(uint64, tag = "4")]
+        pub processing_time_ms: u64,
+        #[prost(int64, tag = "5")]
+        pub created_at_unix_ms: i64,
+        #[prost(map = "string, string", tag = "6")]
+        pub metadata: HashMap<String, String>,
+        #[prost(uint64, optional, tag = "7")]
+        pub seed: Option<u64>,
+        #[prost(string, optional, tag = "8")]
+        pub system_fingerprint: Option<String>,
+        #[prost(message, repeated, tag = "9")]
+        pub candidates: Vec<Candidate>,
+        #[prost(message, optional, tag = "10")]
+        pub logprobs: Option<Logprobs>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Candidate {
+        #[prost(uint32, tag = "1")]
+        pub index: u32,
+        #[prost(string, tag = "2")]
+        pub content_json: String,
+        #[prost(message, optional, tag = "3")]
+        pub token_usage: Option<TokenUsage>,
+        #[prost(string, optional, tag = "4")]
+        pub finish_reason: Option<String>,
+        #[prost(message, optional, tag = "5")]
+        pub logprobs: Option<Logprobs>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TopLogprob {
+        #[prost(string, tag = "1")]
+        pub token: String,
+        #[prost(double, tag = "2")]
+        pub logprob: f64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TokenLogprob {
+        #[prost(string, tag = "1")]
+        pub token: String,
+        #[prost(double, tag = "2")]
+        pub logprob: f64,
+        #[prost(message, repeated, tag = "3")]
+        pub top_logprobs: Vec<TopLogprob>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Logprobs {
+        #[prost(message, repeated, tag = "1")]
+        pub tokens: Vec<TokenLogprob>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamChunk {
+        #[prost(uint32, tag = "1")]
+        pub candidate_index: u32,
+        #[prost(string, tag = "2")]
+        pub delta: String,
+        #[prost(string, optional, tag = "3")]
+        pub finish_reason: Option<String>,
+        #[prost(message, optional, tag = "4")]
+        pub token_usage: Option<TokenUsage>,
+        #[prost(string, optional, tag = "5")]
+        pub model: Option<String>,
+        #[prost(map = "string, string", tag = "6")]
+        pub metadata: HashMap<String, String>,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct HealthCheckRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct HealthCheckResponse {
+        #[prost(bool, tag = "1")]
+        pub healthy: bool,
+        #[prost(string, tag = "2")]
+        pub reason: String,
+        #[prost(int64, tag = "3")]
+        pub timestamp_unix_ms: i64,
+        #[prost(string, tag = "4")]
+        pub metadata_json: String,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct SupportedModelsRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SupportedModelsResponse {
+        #[prost(string, repeated, tag = "1")]
+        pub models: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CountTokensRequest {
+        #[prost(string, tag = "1")]
+        pub text: String,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct CountTokensResponse {
+        #[prost(uint64, tag = "1")]
+        pub tokens: u64,
+    }
+
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/tyl.inference.v1.InferenceService.rs"
+    ));
+}
+
+use pb::inference_service_client::InferenceServiceClient;
+use pb::inference_service_server::{self, InferenceServiceServer};
+
+fn timestamp(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_else(Utc::now)
+}
+
+/// Map an error to a gRPC status for remote callers
+fn to_status(error: TylError) -> Status {
+    Status::internal(error.to_string())
+}
+
+/// Map a gRPC status from a remote service to an error
+fn from_status(status: Status) -> TylError {
+    let message = format!("gRPC {:?}: {}", status.code(), status.message());
+    match status.code() {
+        Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted | Code::Aborted => {
+            TylError::network(message)
+        }
+        Code::InvalidArgument | Code::OutOfRange => TylError::validation("grpc", message),
+        Code::Unauthenticated | Code::PermissionDenied | Code::FailedPrecondition => {
+            TylError::configuration(message)
+        }
+        _ => TylError::internal(message),
+    }
+}
+
+impl From<ModelType> for pb::ModelType {
+    fn from(model_type: ModelType) -> Self {
+        match model_type {
+            ModelType::General => Self::General,
+            ModelType::Coding => Self::Coding,
+            ModelType::Reasoning => Self::Reasoning,
+            ModelType::Fast => Self::Fast,
+            ModelType::Creative => Self::Creative,
+            ModelType::Vision => Self::Vision,
+            ModelType::Extraction => Self::Extraction,
+            ModelType::Summarization => Self::Summarization,
+        }
+    }
+}
+
+impl From<pb::ModelType> for ModelType {
+    fn from(model_type: pb::ModelType) -> Self {
+        match model_type {
+            pb::ModelType::General => Self::General,
+            pb::ModelType::Coding => Self::Coding,
+            pb::ModelType::Reasoning => Self::Reasoning,
+            pb::ModelType::Fast => Self::Fast,
+            pb::ModelType::Creative => Self::Creative,
+            pb::ModelType::Vision => Self::Vision,
+            pb::ModelType::Extraction => Self::Extraction,
+            pb::ModelType::Summarization => Self::Summarization,
+        }
+    }
+}
+
+impl From<Priority> for pb::Priority {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::Low => Self::Low,
+            Priority::Normal => Self::Normal,
+            Priority::High => Self::High,
+        }
+    }
+}
+
+impl From<pb::Priority> for Priority {
+    fn from(priority: pb::Priority) -> Self {
+        match priority {
+            pb::Priority::Low => Self::Low,
+            pb::Priority::Normal => Self::Normal,
+            pb::Priority::High => Self::High,
+        }
+    }
+}
+
+impl From<TokenUsage> for pb::TokenUsage {
+    fn from(usage: TokenUsage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+}
+
+impl From<pb::TokenUsage> for TokenUsage {
+    fn from(usage: pb::TokenUsage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+}
+
+impl From<Logprobs> for pb::Logprobs {
+    fn from(logprobs: Logprobs) -> Self {
+        Self {
+            tokens: logprobs
+                .tokens
+                .into_iter()
+                .map(|token| pb::TokenLogprob {
+                    token: token.token,
+                    logprob: token.logprob,
+                    top_logprobs: token
+                        .top_logprobs
+                        .into_iter()
+                        .map(|top| pb::TopLogprob {
+                            token: top.token,
+                            logprob: top.logprob,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<pb::Logprobs> for Logprobs {
+    fn from(logprobs: pb::Logprobs) -> Self {
+        Self::new(
+            logprobs
+                .tokens
+                .into_iter()
+                .map(|token| TokenLogprob {
+                    token: token.token,
+                    logprob: token.logprob,
+                    top_logprobs: token
+                        .top_logprobs
+                        .into_iter()
+                        .map(|top| TopLogprob {
+                            token: top.token,
+                            logprob: top.logprob,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<MediaInput> for pb::MediaInput {
+    fn from(media: MediaInput) -> Self {
+        let (data, url) = match media.source {
+            MediaSource::Bytes(data) => (data, String::new()),
+            MediaSource::Url(url) => (Vec::new(), url),
+        };
+        Self {
+            mime_type: media.mime_type,
+            data,
+            url,
+        }
+    }
+}
+
+impl From<pb::MediaInput> for MediaInput {
+    fn from(media: pb::MediaInput) -> Self {
+        if media.url.is_empty() {
+            Self::from_bytes(media.mime_type, media.data)
+        } else {
+            Self::from_url(media.mime_type, media.url)
+        }
+    }
+}
+
+impl From<InferenceRequest> for pb::InferenceRequest {
+    fn from(request: InferenceRequest) -> Self {
+        Self {
+            template: request.template,
+            parameters: request.parameters,
+            attachments: request.attachments.into_iter().map(Into::into).collect(),
+            model_type: pb::ModelType::from(request.model_type).into(),
+            model_override: request.model_override,
+            max_tokens: request.max_tokens.map(|tokens| tokens as u32),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            frequency_penalty: request.frequency_penalty,
+            presence_penalty: request.presence_penalty,
+            stop: request.stop,
+            seed: request.seed,
+            candidates: request.candidates.map(|n| n as u32),
+            logprobs: request.logprobs.map(|top| top as u32),
+            timeout_ms: request.timeout.map(|timeout| timeout.as_millis() as u64),
+            deadline_unix_ms: request.deadline.map(|deadline| deadline.timestamp_millis()),
+            priority: request
+                .priority
+                .map(|priority| pb::Priority::from(priority).into()),
+            idempotency_key: request.idempotency_key,
+            metadata: request.metadata,
+            dry_run: request.dry_run,
+        }
+    }
+}
+
+impl TryFrom<pb::InferenceRequest> for InferenceRequest {
+    type Error = TylError;
+
+    fn try_from(request: pb::InferenceRequest) -> InferenceResult<Self> {
+        let model_type = pb::ModelType::try_from(request.model_type)
+            .map_err(|_| inference_errors::invalid_model_type(request.model_type.to_string()))?;
+        let priority = request
+            .priority
+            .and_then(|priority| pb::Priority::try_from(priority).ok());
+        Ok(Self {
+            template: request.template,
+            parameters: request.parameters,
+            attachments: request.attachments.into_iter().map(Into::into).collect(),
+            model_type: model_type.into(),
+            model_override: request.model_override,
+            max_tokens: request.max_tokens.map(|tokens| tokens as usize),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            frequency_penalty: request.frequency_penalty,
+            presence_penalty: request.presence_penalty,
+            stop: request.stop,
+            seed: request.seed,
+            candidates: request.candidates.map(|n| n as usize),
+            logprobs: request.logprobs.map(|top| top as usize),
+            timeout: request.timeout_ms.map(Duration::from_millis),
+            deadline: request.deadline_unix_ms.map(timestamp),
+            priority: priority.map(Into::into),
+            idempotency_key: request.idempotency_key,
+            metadata: request.metadata,
+            dry_run: request.dry_run,
+        })
+    }
+}
+
+impl From<InferenceResponse> for pb::InferenceResponse {
+    fn from(response: InferenceResponse) -> Self {
+        Self {
+            content_json: response.content.to_string(),
+            model: response.metadata.model,
+            token_usage: Some(response.metadata.token_usage.into()),
+            processing_time_ms: response.metadata.processing_time_ms,
+            created_at_unix_ms: response.metadata.created_at.timestamp_millis(),
+            metadata: response.metadata.metadata,
+            seed: response.metadata.seed,
+            system_fingerprint: response.metadata.system_fingerprint,
+            candidates: response
+                .candidates
+                .into_iter()
+                .map(|candidate| pb::Candidate {
+                    index: candidate.index as u32,
+                    content_json: candidate.content.to_string(),
+                    token_usage: Some(candidate.token_usage.into()),
+                    finish_reason: candidate.finish_reason,
+                    logprobs: candidate.logprobs.map(Into::into),
+                })
+                .collect(),
+            logprobs: response.metadata.logprobs.map(Into::into),
+        }
+    }
+}
+
+impl TryFrom<pb::InferenceResponse> for InferenceResponse {
+    type Error = TylError;
+
+    fn try_from(response: pb::InferenceResponse) -> InferenceResult<Self> {
+        let decode = |content_json: &str| {
+            serde_json::from_str(content_json).map_err(|e| {
+                inference_errors::generation_failed(format!("Invalid gRPC response content: {e}"))
+            })
+        };
+        let candidates = response
+            .candidates
+            .iter()
+            .map(|candidate| {
+                Ok(Candidate {
+                    index: candidate.index as usize,
+                    content: decode(&candidate.content_json)?,
+                    token_usage: candidate.token_usage.unwrap_or_default().into(),
+                    finish_reason: candidate.finish_reason.clone(),
+                    logprobs: candidate.logprobs.clone().map(Into::into),
+                })
+            })
+            .collect::<InferenceResult<Vec<_>>>()?;
+        let mut converted = Self::new(
+            decode(&response.content_json)?,
+            ResponseMetadata {
+                model: response.model,
+                token_usage: response.token_usage.unwrap_or_default().into(),
+                processing_time_ms: response.processing_time_ms,
+                created_at: timestamp(response.created_at_unix_ms),
+                seed: response.seed,
+                system_fingerprint: response.system_fingerprint,
+                logprobs: response.logprobs.map(Into::into),
+                metadata: response.metadata,
+            },
+        );
+        converted.candidates = candidates;
+        Ok(converted)
+    }
+}
+
+impl From<StreamChunk> for pb::StreamChunk {
+    fn from(chunk: StreamChunk) -> Self {
+        Self {
+            candidate_index: chunk.candidate_index as u32,
+            delta: chunk.delta,
+            finish_reason: chunk.finish_reason,
+            token_usage: chunk.token_usage.map(Into::into),
+            model: chunk.model,
+            metadata: chunk.metadata,
+        }
+    }
+}
+
+impl From<pb::StreamChunk> for StreamChunk {
+    fn from(chunk: pb::StreamChunk) -> Self {
+        Self {
+            candidate_index: chunk.candidate_index as usize,
+            delta: chunk.delta,
+            finish_reason: chunk.finish_reason,
+            token_usage: chunk.token_usage.map(Into::into),
+            model: chunk.model,
+            metadata: chunk.metadata,
+        }
+    }
+}
+
+/// Chunks received from a remote `InferStream` call
+struct RemoteChunks(Streaming<pb::StreamChunk>);
+
+impl Stream for RemoteChunks {
+    type Item = InferenceResult<StreamChunk>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0)
+            .poll_next(cx)
+            .map(|item| item.map(|chunk| chunk.map(Into::into).map_err(from_status)))
+    }
+}
+
+/// `InferenceService` backed by a remote gRPC endpoint
+#[derive(Clone)]
+pub struct GrpcInferenceClient {
+    client: InferenceServiceClient<Channel>,
+    supported_models: Vec<String>,
+    model_list: Arc<ModelListCache>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+    signer: Option<Arc<dyn AuthSigner>>,
+    limits: RequestLimits,
+}
+
+impl std::fmt::Debug for GrpcInferenceClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GrpcInferenceClient")
+            .field("supported_models", &self.supported_models)
+            .field("credentials", &self.credentials.is_some())
+            .field("signer", &self.signer.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl GrpcInferenceClient {
+    /// Connect to `endpoint` (e.g. `http://inference:50051`) and fetch its supported models
+    pub async fn connect(endpoint: impl Into<String>) -> InferenceResult<Self> {
+        let mut client = Self::new(Self::channel(endpoint, None).await?);
+        client.refresh_supported_models().await?;
+        Ok(client)
+    }
+
+    /// Like `connect`, authenticating every call (including the initial model fetch)
+    pub async fn connect_with_credentials(
+        endpoint: impl Into<String>,
+        provider: impl CredentialsProvider + 'static,
+    ) -> InferenceResult<Self> {
+        let mut client = Self::new(Self::channel(endpoint, None).await?).with_credentials(provider);
+        client.refresh_supported_models().await?;
+        Ok(client)
+    }
+
+    /// Like `connect`, over TLS with custom root CAs and/or a client certificate (mTLS)
+    #[cfg(feature = "tls")]
+    pub async fn connect_with_tls(
+        endpoint: impl Into<String>,
+        tls: &crate::tls::TlsConfig,
+    ) -> InferenceResult<Self> {
+        let mut client = Self::new(Self::channel(endpoint, Some(tls)).await?);
+        client.refresh_supported_models().await?;
+        Ok(client)
+    }
+
+    /// Open a channel to `endpoint`, for combining TLS settings with `with_credentials`
+    ///
+    /// `tls` requires the `tls` feature; without it, passing settings is a configuration error.
+    pub async fn channel(
+        endpoint: impl Into<String>,
+        tls: Option<&crate::tls::TlsConfig>,
+    ) -> InferenceResult<Channel> {
+        let endpoint = endpoint.into();
+        let mut builder = Endpoint::from_shared(endpoint.clone()).map_err(|e| {
+            TylError::configuration(format!("Invalid gRPC endpoint {endpoint}: {e}"))
+        })?;
+        if let Some(tls) = tls {
+            builder = with_tls(builder, tls)?;
+        }
+        builder
+            .connect()
+            .await
+            .map_err(|e| TylError::network(format!("gRPC connection failed: {e}")))
+    }
+
+    /// Use an existing channel; supported models start empty
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            client: InferenceServiceClient::new(channel),
+            supported_models: Vec::new(),
+            model_list: Arc::default(),
+            credentials: None,
+            signer: None,
+            limits: RequestLimits::structural(),
+        }
+    }
+
+    /// Validate requests with `limits` instead of `RequestLimits::structural`
+    pub fn with_limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Authenticate calls with bearer credentials from `provider`
+    pub fn with_credentials(mut self, provider: impl CredentialsProvider + 'static) -> Self {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    /// Pass every call through `signer` before sending; see `signing` for how calls are presented
+    pub fn with_signer(mut self, signer: impl AuthSigner + 'static) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// How long `list_models` reuses the server's listing (5 minutes by default); clones share
+    /// the cached listing
+    pub fn with_model_list_ttl(mut self, ttl: Duration) -> Self {
+        self.model_list = Arc::new(ModelListCache::new(ttl));
+        self
+    }
+
+    /// Reload the models reported by `supported_models` from the server
+    pub async fn refresh_supported_models(&mut self) -> InferenceResult<()> {
+        self.supported_models = self.fetch_models().await?;
+        Ok(())
+    }
+
+    async fn fetch_models(&self) -> InferenceResult<Vec<String>> {
+        let response = self
+            .call(
+                "SupportedModels",
+                pb::SupportedModelsRequest {},
+                |mut client, request| async move { client.supported_models(request).await },
+            )
+            .await?;
+        Ok(response.models)
+    }
+
+    /// Count tokens with the server's tokenizer
+    pub async fn count_tokens_remote(&self, text: &str) -> InferenceResult<usize> {
+        let request = pb::CountTokensRequest {
+            text: text.to_string(),
+        };
+        let response = self
+            .call("CountTokens", request, |mut client, request| async move {
+                client.count_tokens(request).await
+            })
+            .await?;
+        Ok(response.tokens as usize)
+    }
+
+    /// Run `call` with credentials attached, refreshing them once on `UNAUTHENTICATED`
+    async fn call<M, R, F, Fut>(&self, method: &str, message: M, call: F) -> InferenceResult<R>
+    where
+        M: prost::Message + Clone + Send,
+        F: Fn(InferenceServiceClient<Channel>, Request<M>) -> Fut + Send + Sync,
+        Fut: Future<Output = Result<Response<R>, Status>> + Send,
+    {
+        let Some(provider) = &self.credentials else {
+            let request = self.prepare(method, message, None).await?;
+            return call(self.client.clone(), request)
+                .await
+                .map(Response::into_inner)
+                .map_err(from_status);
+        };
+
+        let credentials = provider.get_credentials().await?;
+        let request = self
+            .prepare(method, message.clone(), Some(&credentials))
+            .await?;
+        let result = match call(self.client.clone(), request).await {
+            Err(status) if status.code() == Code::Unauthenticated => {
+                let credentials = provider.refresh_credentials().await?;
+                let request = self.prepare(method, message, Some(&credentials)).await?;
+                call(self.client.clone(), request).await
+            }
+            result => result,
+        };
+        result.map(Response::into_inner).map_err(from_status)
+    }
+
+    /// Request carrying the bearer credentials and the signer's headers as metadata
+    async fn prepare<M: prost::Message>(
+        &self,
+        method: &str,
+        message: M,
+        credentials: Option<&Credentials>,
+    ) -> InferenceResult<Request<M>> {
+        let mut signable = HttpRequest::get(format!("/tyl.inference.v1.InferenceService/{method}"));
+        signable.method = crate::http_client::HttpMethod::Post;
+        if let Some(credentials) = credentials {
+            signable.set_header("authorization", credentials.bearer());
+        }
+        if let Some(signer) = &self.signer {
+            signable.body = Some(message.encode_to_vec());
+            signer.sign(&mut signable).await?;
+        }
+
+        let mut request = Request::new(message);
+        for (name, value) in signable.headers {
+            let key = MetadataKey::from_bytes(name.to_ascii_lowercase().as_bytes())
+                .map_err(|_| invalid_metadata(&name))?;
+            let value = value.parse().map_err(|_| invalid_metadata(&name))?;
+            request.metadata_mut().insert(key, value);
+        }
+        Ok(request)
+    }
+}
+
+fn invalid_metadata(name: &str) -> TylError {
+    TylError::configuration(format!("{name} is not a valid gRPC metadata entry"))
+}
+
+#[cfg(feature = "tls")]
+fn with_tls(endpoint: Endpoint, tls: &crate::tls::TlsConfig) -> InferenceResult<Endpoint> {
+    endpoint
+        .tls_config(tls.tonic_config())
+        .map_err(|e| TylError::configuration(format!("Invalid TLS configuration: {e}")))
+}
+
+#[cfg(not(feature = "tls"))]
+fn with_tls(_endpoint: Endpoint, _tls: &crate::tls::TlsConfig) -> InferenceResult<Endpoint> {
+    Err(TylError::configuration(
+        "TLS settings require the tls feature",
+    ))
+}
+
+#[async_trait]
+impl InferenceService for GrpcInferenceClient {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        if request.dry_run {
+            return dry_run_response(self, &request, &self.limits);
+        }
+        request.validate_with(self, &self.limits)?;
+        self.call(
+            "Infer",
+            pb::InferenceRequest::from(request),
+            |mut client, request| async move { client.infer(request).await },
+        )
+        .await?
+        .try_into()
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        if request.dry_run {
+            return Ok(InferenceStream::from_response(dry_run_response(
+                self,
+                &request,
+                &self.limits,
+            )?));
+        }
+        request.validate_with(self, &self.limits)?;
+        let chunks = self
+            .call(
+                "InferStream",
+                pb::InferenceRequest::from(request),
+                |mut client, request| async move { client.infer_stream(request).await },
+            )
+            .await?;
+        Ok(InferenceStream::new(RemoteChunks(chunks)))
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let response = self
+            .call(
+                "HealthCheck",
+                pb::HealthCheckRequest {},
+                |mut client, request| async move { client.health_check(request).await },
+            )
+            .await?;
+
+        let status = if response.healthy {
+            HealthStatus::healthy()
+        } else {
+            HealthStatus::unhealthy(response.reason)
+        };
+        let metadata = serde_json::from_str(&response.metadata_json).unwrap_or_default();
+        Ok(HealthCheckResult {
+            status,
+            timestamp: timestamp(response.timestamp_unix_ms),
+            metadata,
+        })
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.supported_models.clone()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.model_list.get_or_fetch(|| self.fetch_models()).await
+    }
+
+    /// Local approximation; use `count_tokens_remote` for the server's tokenizer
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        // Simple approximation: ~4 characters per token
+        Ok((text.len() + 3) / 4)
+    }
+}
+
+/// Chunks of a local stream sent to a remote `InferStream` caller
+struct LocalChunks(InferenceStream);
+
+// `tonic::Status` is large, but it is the item type tonic expects
+#[allow(clippy::result_large_err)]
+impl Stream for LocalChunks {
+    type Item = Result<pb::StreamChunk, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0)
+            .poll_next(cx)
+            .map(|item| item.map(|chunk| chunk.map(Into::into).map_err(to_status)))
+    }
+}
+
+/// gRPC server exposing an `InferenceService`
+#[derive(Debug)]
+pub struct GrpcInferenceServer<S> {
+    inner: Arc<S>,
+}
+
+impl<S: InferenceService + 'static> GrpcInferenceServer<S> {
+    pub fn new(inner: S) -> Self {
+        Self::from_arc(Arc::new(inner))
+    }
+
+    /// Share a service that is also used elsewhere in the process
+    pub fn from_arc(inner: Arc<S>) -> Self {
+        Self { inner }
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Tower service to add to a `tonic::transport::Server`
+    pub fn into_service(self) -> InferenceServiceServer<Self> {
+        InferenceServiceServer::new(self)
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService + 'static> inference_service_server::InferenceService
+    for GrpcInferenceServer<S>
+{
+    type InferStreamStream =
+        Pin<Box<dyn Stream<Item = Result<pb::StreamChunk, Status>> + Send + 'static>>;
+
+    async fn infer(
+        &self,
+        request: Request<pb::InferenceRequest>,
+    ) -> Result<Response<pb::InferenceResponse>, Status> {
+        let request = InferenceRequest::try_from(request.into_inner())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let response = self.inner.infer(request).await.map_err(to_status)?;
+        Ok(Response::new(response.into()))
+    }
+
+    async fn infer_stream(
+        &self,
+        request: Request<pb::InferenceRequest>,
+    ) -> Result<Response<Self::InferStreamStream>, Status> {
+        let request = InferenceRequest::try_from(request.into_inner())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let stream = self.inner.infer_stream(request).await.map_err(to_status)?;
+        Ok(Response::new(Box::pin(LocalChunks(stream))))
+    }
+
+    async fn health_check(
+        &self,
+        _request: Request<pb::HealthCheckRequest>,
+    ) -> Result<Response<pb::HealthCheckResponse>, Status> {
+        let health = self.inner.health_check().await.map_err(to_status)?;
+        let reason = match &health.status {
+            HealthStatus::Unhealthy { reason } => reason.clone(),
+            HealthStatus::Healthy => String::new(),
+        };
+        Ok(Response::new(pb::HealthCheckResponse {
+            healthy: health.status.is_healthy(),
+            reason,
+            timestamp_unix_ms: health.timestamp.timestamp_millis(),
+            metadata_json: serde_json::to_string(&health.metadata).unwrap_or_default(),
+        }))
+    }
+
+    async fn supported_models(
+        &self,
+        _request: Request<pb::SupportedModelsRequest>,
+    ) -> Result<Response<pb::SupportedModelsResponse>, Status> {
+        Ok(Response::new(pb::SupportedModelsResponse {
+            models: self.inner.list_models().await.map_err(to_status)?,
+        }))
+    }
+
+    async fn count_tokens(
+        &self,
+        request: Request<pb::CountTokensRequest>,
+    ) -> Result<Response<pb::CountTokensResponse>, Status> {
+        let tokens = self
+            .inner
+            .count_tokens(&request.into_inner().text)
+            .map_err(to_status)?;
+        Ok(Response::new(pb::CountTokensResponse {
+            tokens: tokens as u64,
+        }))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+
+    async fn serve() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let server = GrpcInferenceServer::new(MockInferenceService::new().with_latency(0));
+        tokio::spawn(
+            Server::builder()
+                .add_service(server.into_service())
+                .serve_with_incoming(incoming),
+        );
+        endpoint
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let deadline = timestamp(Utc::now().timestamp_millis() + 5_000);
+        let request = InferenceRequest::new("Hi {{name}}", HashMap::new(), ModelType::Coding)
+            .with_priority(Priority::High)
+            .with_timeout(Duration::from_millis(1500))
+            .with_deadline(deadline)
+            .with_idempotency_key("k-1")
+            .with_metadata("template_name", "greet")
+            .with_attachment(MediaInput::from_bytes("image/png", vec![1, 2, 3]))
+            .with_attachment(MediaInput::from_url(
+                "image/jpeg",
+                "https://example.com/a.jpg",
+            ));
+
+        let decoded =
+            InferenceRequest::try_from(pb::InferenceRequest::from(request.clone())).unwrap();
+        assert_eq!(decoded.template, request.template);
+        assert_eq!(decoded.model_type, ModelType::Coding);
+        assert_eq!(decoded.priority, Some(Priority::High));
+        assert_eq!(decoded.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(decoded.deadline, Some(deadline));
+        assert_eq!(decoded.idempotency_key.as_deref(), Some("k-1"));
+        assert_eq!(decoded.metadata, request.metadata);
+        assert_eq!(decoded.attachments, request.attachments);
+
+        let invalid = pb::InferenceRequest {
+            model_type: 42,
+            ..Default::default()
+        };
+        assert!(InferenceRequest::try_from(invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_and_server() {
+        let client = GrpcInferenceClient::connect(serve().await).await.unwrap();
+        assert!(!client.supported_models().is_empty());
+        assert!(client.health_check().await.unwrap().status.is_healthy());
+        assert_eq!(client.count_tokens_remote("abcdefgh").await.unwrap(), 2);
+
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::General);
+        let response = client.infer(request.clone()).await.unwrap();
+        assert_eq!(
+            response.metadata.model,
+            ModelType::General.optimal_openai_model()
+        );
+        assert!(response.content.get("message").is_some());
+
+        let chunks = client
+            .infer_stream(request)
+            .await
+            .unwrap()
+            .assemble()
+            .await
+            .unwrap();
+        assert!(chunks.all_complete());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_credentials_are_refreshed() {
+        use crate::credentials::CachedCredentials;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Default)]
+        struct Rotating(AtomicUsize);
+
+        #[async_trait]
+        impl CredentialsProvider for Rotating {
+            async fn get_credentials(&self) -> InferenceResult<Credentials> {
+                let fetch = self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(Credentials::new(if fetch == 0 {
+                    "revoked"
+                } else {
+                    "current"
+                }))
+            }
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let rejected = Arc::new(AtomicUsize::new(0));
+        #[allow(clippy::result_large_err)]
+        let check = {
+            let rejected = Arc::clone(&rejected);
+            move |request: Request<()>| match request.metadata().get("authorization") {
+                Some(value) if value == "Bearer current" => Ok(request),
+                _ => {
+                    rejected.fetch_add(1, Ordering::SeqCst);
+                    Err(Status::unauthenticated("invalid API key"))
+                }
+            }
+        };
+        let server = GrpcInferenceServer::new(MockInferenceService::new().with_latency(0));
+        tokio::spawn(
+            Server::builder()
+                .add_service(InferenceServiceServer::with_interceptor(server, check))
+                .serve_with_incoming(incoming),
+        );
+
+        let client = GrpcInferenceClient::connect_with_credentials(
+            endpoint,
+            CachedCredentials::new(Rotating::default()),
+        )
+        .await
+        .unwrap();
+        assert!(!client.supported_models().is_empty());
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::General);
+        assert!(client.infer(request).await.is_ok());
+        assert_eq!(rejected.load(Ordering::SeqCst), 1);
+    }
+}
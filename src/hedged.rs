@@ -0,0 +1,207 @@
+//! Hedged requests across two backends
+//!
+//! `HedgedService` sends each request to the primary backend and, if no response has arrived
+//! after the hedge delay, sends the same request to the secondary backend. The first successful
+//! response wins and the other attempt is cancelled by dropping its future. If one backend fails,
+//! the other attempt is still awaited, so an early primary failure starts the secondary right away.
+//!
+//! Streams are hedged the same way until one of them opens; the losing stream is dropped.
+
+use crate::*;
+use std::future::Future;
+use std::time::Duration;
+
+/// Response metadata key naming the backend that produced the response (`primary`/`secondary`)
+pub const HEDGE_METADATA_KEY: &str = "hedge";
+
+/// Inference service decorator racing a delayed secondary backend against the primary
+#[derive(Debug)]
+pub struct HedgedService<P, S> {
+    primary: P,
+    secondary: S,
+    delay: Duration,
+}
+
+impl<P: InferenceService, S: InferenceService> HedgedService<P, S> {
+    /// Start the secondary attempt when the primary has not answered within `delay`
+    pub fn new(primary: P, secondary: S, delay: Duration) -> Self {
+        Self {
+            primary,
+            secondary,
+            delay,
+        }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Race `primary` against `secondary` started after the hedge delay, returning the first
+    /// success and the backend that produced it
+    async fn race<T>(
+        &self,
+        primary: impl Future<Output = InferenceResult<T>>,
+        secondary: impl Future<Output = InferenceResult<T>>,
+    ) -> InferenceResult<(T, &'static str)> {
+        let mut primary = std::pin::pin!(primary);
+
+        tokio::select! {
+            result = &mut primary => {
+                return match result {
+                    Ok(value) => Ok((value, "primary")),
+                    Err(_) => secondary.await.map(|value| (value, "secondary")),
+                };
+            }
+            _ = tokio::time::sleep(self.delay) => {}
+        }
+
+        let mut secondary = std::pin::pin!(secondary);
+        tokio::select! {
+            result = &mut primary => match result {
+                Ok(value) => Ok((value, "primary")),
+                Err(_) => secondary.await.map(|value| (value, "secondary")),
+            },
+            result = &mut secondary => match result {
+                Ok(value) => Ok((value, "secondary")),
+                Err(_) => primary.await.map(|value| (value, "primary")),
+            },
+        }
+    }
+}
+
+fn tag(mut response: InferenceResponse, backend: &str) -> InferenceResponse {
+    response.metadata = response.metadata.with_metadata(HEDGE_METADATA_KEY, backend);
+    response
+}
+
+#[async_trait]
+impl<P: InferenceService, S: InferenceService> InferenceService for HedgedService<P, S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let primary = self.primary.infer(request.clone());
+        let (response, backend) = self.race(primary, self.secondary.infer(request)).await?;
+        Ok(tag(response, backend))
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let primary = self.primary.infer_stream(request.clone());
+        let (stream, _) = self
+            .race(primary, self.secondary.infer_stream(request))
+            .await?;
+        Ok(stream)
+    }
+
+    /// Healthy when either backend is healthy
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let primary = self.primary.health_check().await;
+        if let Ok(health) = &primary {
+            if health.status == HealthStatus::Healthy {
+                return primary;
+            }
+        }
+        match self.secondary.health_check().await {
+            Ok(health) if health.status == HealthStatus::Healthy => Ok(health),
+            secondary => primary.or(secondary),
+        }
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        let mut models = self.primary.supported_models();
+        for model in self.secondary.supported_models() {
+            if !models.contains(&model) {
+                models.push(model);
+            }
+        }
+        models
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        let mut models = self.primary.list_models().await?;
+        for model in self.secondary.list_models().await? {
+            if !models.contains(&model) {
+                models.push(model);
+            }
+        }
+        Ok(models)
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.primary.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Test", HashMap::new(), ModelType::Fast)
+    }
+
+    fn hedge(response: &InferenceResponse) -> Option<&str> {
+        response
+            .metadata
+            .metadata
+            .get(HEDGE_METADATA_KEY)
+            .map(String::as_str)
+    }
+
+    #[tokio::test]
+    async fn test_fast_primary_is_not_hedged() {
+        let service = HedgedService::new(
+            MockInferenceService::new().with_latency(5),
+            MockInferenceService::new().with_latency(0),
+            Duration::from_millis(50),
+        );
+
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(hedge(&response), Some("primary"));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_primary_loses_to_secondary() {
+        let service = HedgedService::new(
+            MockInferenceService::new().with_latency(1_000),
+            MockInferenceService::new().with_latency(10),
+            Duration::from_millis(20),
+        );
+
+        let started = std::time::Instant::now();
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(hedge(&response), Some("secondary"));
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_streams_are_hedged() {
+        let service = HedgedService::new(
+            MockInferenceService::new().with_latency(1_000),
+            MockInferenceService::new().with_latency(10),
+            Duration::from_millis(20),
+        );
+
+        let started = std::time::Instant::now();
+        let stream = service.infer_stream(request()).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert_eq!(stream.assemble().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_uses_any_healthy_backend() {
+        let service = HedgedService::new(
+            MockInferenceService::new().with_health_failure(),
+            MockInferenceService::new(),
+            Duration::from_millis(20),
+        );
+        let health = service.health_check().await.unwrap();
+        assert_eq!(health.status, HealthStatus::Healthy);
+    }
+}
@@ -0,0 +1,317 @@
+//! Request and response lifecycle hooks
+//!
+//! `Hooks` collects async callbacks run around every request of a wrapped service: `on_request`
+//! before the call, then `on_response` or `on_error` once it completes. Each receives a
+//! `HookContext` with the rendered prompt, model and request metadata, which is enough for
+//! custom auditing or logging without writing a decorator:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::hooks::*;
+//! # struct AuditLog;
+//! # impl AuditLog {
+//! #     async fn started(&self, prompt: &str) {}
+//! #     async fn completed(&self, metadata: &std::collections::HashMap<String, String>, response: &ResponseMetadata) {}
+//! #     async fn failed(&self, error: String) {}
+//! # }
+//! # #[allow(non_upper_case_globals)]
+//! # static audit: AuditLog = AuditLog;
+//! # #[cfg(feature = "decorators")]
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let hooks = Hooks::new()
+//!     .on_request(|context: HookContext| async move { audit.started(&context.prompt).await })
+//!     .on_response(|context: HookContext, response: InferenceResponse| async move {
+//!         audit.completed(&context.metadata, &response.metadata).await
+//!     })
+//!     .on_error(|context: HookContext, error: String| async move { audit.failed(error).await });
+//! let service = HookedService::new(openai, hooks.clone());
+//! // or as a layer: ServiceBuilder::new().layer(hooks).service(openai)
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Hooks are awaited in registration order on the request's task, so slow work should be
+//! spawned off. Closures receive owned copies; implement `RequestHook`, `ResponseHook` or
+//! `ErrorHook` directly to work on references instead.
+
+use crate::*;
+use std::future::Future;
+use std::sync::Arc;
+
+/// What hooks learn about a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookContext {
+    /// Template rendered with the request parameters
+    pub prompt: String,
+    pub model_type: ModelType,
+    /// Explicit model of the request, if any
+    pub model: Option<String>,
+    /// Request metadata
+    pub metadata: HashMap<String, String>,
+}
+
+impl HookContext {
+    pub fn from_request(request: &InferenceRequest) -> Self {
+        Self {
+            prompt: request.render_template(),
+            model_type: request.model_type,
+            model: request.model_override.clone(),
+            metadata: request.metadata.clone(),
+        }
+    }
+}
+
+/// Runs before a request reaches the wrapped service
+///
+/// Implemented for async closures `Fn(HookContext) -> impl Future<Output = ()>`.
+#[async_trait]
+pub trait RequestHook: Send + Sync {
+    async fn on_request(&self, context: &HookContext);
+}
+
+#[async_trait]
+impl<F, Fut> RequestHook for F
+where
+    F: Fn(HookContext) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn on_request(&self, context: &HookContext) {
+        (self)(context.clone()).await
+    }
+}
+
+/// Runs after the wrapped service answered
+///
+/// Implemented for async closures `Fn(HookContext, InferenceResponse) -> impl Future`, which
+/// receive a copy of the response.
+#[async_trait]
+pub trait ResponseHook: Send + Sync {
+    async fn on_response(&self, context: &HookContext, response: &InferenceResponse);
+}
+
+#[async_trait]
+impl<F, Fut> ResponseHook for F
+where
+    F: Fn(HookContext, InferenceResponse) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn on_response(&self, context: &HookContext, response: &InferenceResponse) {
+        (self)(context.clone(), response.clone()).await
+    }
+}
+
+/// Runs after the wrapped service failed
+///
+/// Implemented for async closures `Fn(HookContext, String) -> impl Future<Output = ()>`, which
+/// receive the error message.
+#[async_trait]
+pub trait ErrorHook: Send + Sync {
+    async fn on_error(&self, context: &HookContext, error: &TylError);
+}
+
+#[async_trait]
+impl<F, Fut> ErrorHook for F
+where
+    F: Fn(HookContext, String) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn on_error(&self, context: &HookContext, error: &TylError) {
+        (self)(context.clone(), error.to_string()).await
+    }
+}
+
+/// Registered lifecycle hooks; also an `InferenceLayer` wrapping services in `HookedService`
+#[derive(Clone, Default)]
+pub struct Hooks {
+    on_request: Vec<Arc<dyn RequestHook>>,
+    on_response: Vec<Arc<dyn ResponseHook>>,
+    on_error: Vec<Arc<dyn ErrorHook>>,
+}
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_request", &self.on_request.len())
+            .field("on_response", &self.on_response.len())
+            .field("on_error", &self.on_error.len())
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_request(mut self, hook: impl RequestHook + 'static) -> Self {
+        self.on_request.push(Arc::new(hook));
+        self
+    }
+
+    pub fn on_response(mut self, hook: impl ResponseHook + 'static) -> Self {
+        self.on_response.push(Arc::new(hook));
+        self
+    }
+
+    pub fn on_error(mut self, hook: impl ErrorHook + 'static) -> Self {
+        self.on_error.push(Arc::new(hook));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.on_request.is_empty() && self.on_response.is_empty() && self.on_error.is_empty()
+    }
+}
+
+impl<S: InferenceService> InferenceLayer<S> for Hooks {
+    type Service = HookedService<S>;
+
+    fn layer(&self, inner: S) -> HookedService<S> {
+        HookedService::new(inner, self.clone())
+    }
+}
+
+/// Inference service decorator running `Hooks` around every request
+#[derive(Debug)]
+pub struct HookedService<S> {
+    inner: S,
+    hooks: Hooks,
+}
+
+impl<S: InferenceService> HookedService<S> {
+    pub fn new(inner: S, hooks: Hooks) -> Self {
+        Self { inner, hooks }
+    }
+
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for HookedService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        if self.hooks.is_empty() {
+            return self.inner.infer(request).await;
+        }
+        let context = HookContext::from_request(&request);
+        for hook in &self.hooks.on_request {
+            hook.on_request(&context).await;
+        }
+
+        let result = self.inner.infer(request).await;
+        match &result {
+            Ok(response) => {
+                for hook in &self.hooks.on_response {
+                    hook.on_response(&context, response).await;
+                }
+            }
+            Err(error) => {
+                for hook in &self.hooks.on_error {
+                    hook.on_error(&context, error).await;
+                }
+            }
+        }
+        result
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockInferenceService;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_hooks_see_prompt_and_outcome() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (requests, responses, errors) = (log.clone(), log.clone(), log.clone());
+        let hooks = Hooks::new()
+            .on_request(move |context: HookContext| {
+                let log = requests.clone();
+                async move {
+                    log.lock().unwrap().push(format!(
+                        "request {} [{}]",
+                        context.prompt, context.metadata["team"]
+                    ));
+                }
+            })
+            .on_response(move |_: HookContext, response: InferenceResponse| {
+                let log = responses.clone();
+                async move {
+                    log.lock()
+                        .unwrap()
+                        .push(format!("response {}", response.metadata.model));
+                }
+            })
+            .on_error(move |context: HookContext, _: String| {
+                let log = errors.clone();
+                async move {
+                    log.lock()
+                        .unwrap()
+                        .push(format!("error {}", context.prompt))
+                }
+            });
+
+        let mut parameters = HashMap::new();
+        parameters.insert("name".to_string(), "Ada".to_string());
+        let request = InferenceRequest::new("Hi {{name}}", parameters, ModelType::General)
+            .with_model("gpt-4o")
+            .with_metadata("team", "search");
+
+        let service = ServiceBuilder::new()
+            .layer(hooks.clone())
+            .service(MockInferenceService::new().with_latency(0));
+        service.infer(request.clone()).await.unwrap();
+
+        let failing = HookedService::new(MockInferenceService::new().with_latency(0), hooks);
+        assert!(failing.infer(request.with_max_tokens(0)).await.is_err());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "request Hi Ada [search]",
+                "response gpt-4o",
+                "request Hi Ada [search]",
+                "error Hi Ada",
+            ]
+        );
+    }
+}
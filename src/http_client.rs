@@ -0,0 +1,620 @@
+//! HTTP transport injection and HTTP client adapter
+//!
+//! HTTP adapters send their requests through an `HttpTransport` instead of building their own
+//! client, so corporate proxies, custom TLS roots, connection pools and unit-test transports can
+//! be injected uniformly. With the `http-client` feature `reqwest::Client` implements the trait:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::http_client::*;
+//! # #[cfg(feature = "http-client")]
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = reqwest::Client::builder()
+//!     .proxy(reqwest::Proxy::all("http://proxy.corp:3128")?)
+//!     .build()?;
+//! let service = HttpInferenceClient::new("http://inference:8080", client);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! For private CAs and client certificates without building the client yourself, see
+//! `HttpInferenceClient::new_with_tls` (`tls` feature).
+//!
+//! `HttpInferenceClient` is the client side of the `http_server` facade (`POST /infer`,
+//! `GET /health`, `GET /models`).
+
+use crate::catalog::ModelListCache;
+use crate::credentials::{Credentials, CredentialsProvider};
+use crate::rate_limit::{RateLimitError, RateLimitState, RATE_LIMIT_HEALTH_KEY};
+use crate::signing::AuthSigner;
+use crate::*;
+use std::sync::{Arc, Mutex};
+
+/// HTTP method of an `HttpRequest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Get,
+    Post,
+}
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Get => "GET",
+            Self::Post => "POST",
+        }
+    }
+}
+
+/// Request sent through an `HttpTransport`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: HttpMethod,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// Give up on the request after this long (the transport's own timeout otherwise)
+    pub timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            method: HttpMethod::Get,
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+        }
+    }
+
+    /// POST with a JSON body
+    pub fn post_json(url: impl Into<String>, body: Vec<u8>) -> Self {
+        Self {
+            method: HttpMethod::Post,
+            url: url.into(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: Some(body),
+            timeout: None,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Bound the request by `timeout`, when there is one, keeping a shorter timeout already set
+    pub fn with_attempt_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = match (self.timeout, timeout) {
+            (Some(current), Some(timeout)) => Some(current.min(timeout)),
+            (current, timeout) => current.or(timeout),
+        };
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Replace every header named `name` (case-insensitive) with one `value`
+    pub fn set_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+    }
+
+    /// Value of the first header named `name` (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Response returned by an `HttpTransport`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Value of the first header named `name` (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends HTTP requests for HTTP adapters
+///
+/// Transport failures (DNS, connect, TLS) are errors; any HTTP status is a response.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse>;
+}
+
+#[cfg(feature = "http-client")]
+#[async_trait]
+impl HttpTransport for reqwest::Client {
+    async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+        let method = match request.method {
+            HttpMethod::Get => reqwest::Method::GET,
+            HttpMethod::Post => reqwest::Method::POST,
+        };
+        let mut builder = self.request(method, &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let response = builder.send().await.map_err(|e| match request.timeout {
+            Some(timeout) if e.is_timeout() => inference_errors::request_timeout(timeout),
+            _ => TylError::network(format!("HTTP request to {} failed: {e}", request.url)),
+        })?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| TylError::network(format!("HTTP response read failed: {e}")))?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: body.to_vec(),
+        })
+    }
+}
+
+/// `InferenceService` backed by a remote `http_server` facade
+#[derive(Clone)]
+pub struct HttpInferenceClient {
+    base_url: String,
+    transport: Arc<dyn HttpTransport>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+    signer: Option<Arc<dyn AuthSigner>>,
+    supported_models: Vec<String>,
+    model_list: Arc<ModelListCache>,
+    rate_limit: Arc<Mutex<Option<RateLimitState>>>,
+    limits: RequestLimits,
+}
+
+impl std::fmt::Debug for HttpInferenceClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpInferenceClient")
+            .field("base_url", &self.base_url)
+            .field("credentials", &self.credentials.is_some())
+            .field("signer", &self.signer.is_some())
+            .field("supported_models", &self.supported_models)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpInferenceClient {
+    /// Send requests to `base_url` through `transport`; supported models start empty
+    pub fn new(base_url: impl Into<String>, transport: impl HttpTransport + 'static) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            transport: Arc::new(transport),
+            credentials: None,
+            signer: None,
+            supported_models: Vec::new(),
+            model_list: Arc::default(),
+            rate_limit: Arc::default(),
+            limits: RequestLimits::structural(),
+        }
+    }
+
+    /// Like `new`, over a `reqwest::Client` built from `tls` (private root CAs, mTLS)
+    #[cfg(all(feature = "http-client", feature = "tls"))]
+    pub fn new_with_tls(
+        base_url: impl Into<String>,
+        tls: &crate::tls::TlsConfig,
+    ) -> InferenceResult<Self> {
+        Ok(Self::new(base_url, tls.reqwest_client()?))
+    }
+
+    /// Validate requests with `limits` instead of `RequestLimits::structural`
+    pub fn with_limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Authenticate requests with bearer credentials from `provider`, refreshing them once when
+    /// a request is rejected with 401
+    pub fn with_credentials(mut self, provider: impl CredentialsProvider + 'static) -> Self {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    /// Pass every request through `signer` right before it is sent
+    pub fn with_signer(mut self, signer: impl AuthSigner + 'static) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// How long `list_models` reuses the server's listing (5 minutes by default); clones share
+    /// the cached listing
+    pub fn with_model_list_ttl(mut self, ttl: Duration) -> Self {
+        self.model_list = Arc::new(ModelListCache::new(ttl));
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Quota reported by the rate-limit headers of the latest response that had them; clones
+    /// share it
+    pub fn rate_limit_state(&self) -> Option<RateLimitState> {
+        self.rate_limit.lock().unwrap().clone()
+    }
+
+    /// Reload the models reported by `supported_models` from the server
+    pub async fn refresh_supported_models(&mut self) -> InferenceResult<()> {
+        self.supported_models = self.fetch_models().await?;
+        Ok(())
+    }
+
+    async fn fetch_models(&self) -> InferenceResult<Vec<String>> {
+        let response = self.send(HttpRequest::get(self.url("/models"))).await?;
+        let body: serde_json::Value = self.parse(&response)?;
+        serde_json::from_value(body["models"].clone())
+            .map_err(|e| TylError::internal(format!("Invalid models response: {e}")))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+        let response = self.send_with_credentials(request).await?;
+        if let Some(state) = RateLimitState::from_response(&response, Utc::now()) {
+            *self.rate_limit.lock().unwrap() = Some(state);
+        }
+        Ok(response)
+    }
+
+    async fn send_with_credentials(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+        let Some(provider) = &self.credentials else {
+            return self
+                .transport
+                .send(self.prepare(request, None).await?)
+                .await;
+        };
+        let credentials = provider.get_credentials().await?;
+        let response = self
+            .transport
+            .send(self.prepare(request.clone(), Some(&credentials)).await?)
+            .await?;
+        if response.status != 401 {
+            return Ok(response);
+        }
+        let credentials = provider.refresh_credentials().await?;
+        self.transport
+            .send(self.prepare(request, Some(&credentials)).await?)
+            .await
+    }
+
+    /// Attach the bearer credentials, then let the signer have the last word
+    async fn prepare(
+        &self,
+        mut request: HttpRequest,
+        credentials: Option<&Credentials>,
+    ) -> InferenceResult<HttpRequest> {
+        if let Some(credentials) = credentials {
+            request.set_header("Authorization", credentials.bearer());
+        }
+        if let Some(signer) = &self.signer {
+            signer.sign(&mut request).await?;
+        }
+        Ok(request)
+    }
+
+    fn parse<T: serde::de::DeserializeOwned>(&self, response: &HttpResponse) -> InferenceResult<T> {
+        parse_json_response(response, &self.base_url)
+    }
+}
+
+/// Send `request` with bearer credentials from `credentials`, refreshing them once on 401
+///
+/// The request is bounded by the deadline of the current `RequestContext`.
+pub(crate) async fn send_authorized(
+    transport: &dyn HttpTransport,
+    credentials: Option<&dyn CredentialsProvider>,
+    request: HttpRequest,
+) -> InferenceResult<HttpResponse> {
+    let request = request.with_attempt_timeout(RequestContext::current_remaining_time());
+    let Some(provider) = credentials else {
+        return transport.send(request).await;
+    };
+    let mut authorized = request.clone();
+    authorized.set_header("Authorization", provider.get_credentials().await?.bearer());
+    let response = transport.send(authorized).await?;
+    if response.status != 401 {
+        return Ok(response);
+    }
+    let mut authorized = request;
+    authorized.set_header(
+        "Authorization",
+        provider.refresh_credentials().await?.bearer(),
+    );
+    transport.send(authorized).await
+}
+
+/// Error for an error status from `provider`
+///
+/// The error message is taken from an `error` string or an `error.message` field, falling back
+/// to the raw body. 429 responses become a `RateLimitError` with the wait their headers ask for.
+pub(crate) fn response_error(response: &HttpResponse, provider: &str) -> TylError {
+    let message = serde_json::from_slice::<serde_json::Value>(&response.body)
+        .ok()
+        .and_then(|body| {
+            let error = body.get("error")?;
+            error
+                .as_str()
+                .or_else(|| error.get("message")?.as_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(&response.body).into_owned());
+    match response.status {
+        401 | 403 => inference_errors::invalid_api_key(provider),
+        429 => RateLimitError::from_response(provider, response).into(),
+        400..=499 => TylError::validation("http", message),
+        _ => TylError::network(format!("HTTP {}: {message}", response.status)),
+    }
+}
+
+/// Decode a JSON body, turning error statuses from `provider` into errors (see `response_error`)
+pub(crate) fn parse_json_response<T: serde::de::DeserializeOwned>(
+    response: &HttpResponse,
+    provider: &str,
+) -> InferenceResult<T> {
+    if !response.is_success() {
+        return Err(response_error(response, provider));
+    }
+    serde_json::from_slice(&response.body)
+        .map_err(|e| TylError::internal(format!("Invalid response body: {e}")))
+}
+
+#[async_trait]
+impl InferenceService for HttpInferenceClient {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        if request.dry_run {
+            return dry_run_response(self, &request, &self.limits);
+        }
+        request.validate_with(self, &self.limits)?;
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| TylError::internal(format!("Failed to encode request: {e}")))?;
+        let http_request = HttpRequest::post_json(self.url("/infer"), body)
+            .with_attempt_timeout(request.attempt_timeout());
+        let response = self.send(http_request).await?;
+        self.parse(&response)
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let response = self.send(HttpRequest::get(self.url("/health"))).await?;
+        let health: HealthCheckResult = match serde_json::from_slice(&response.body) {
+            Ok(health) if response.status == 503 => health,
+            _ => self.parse(&response)?,
+        };
+        Ok(match self.rate_limit_state() {
+            Some(state) => health.with_metadata(
+                RATE_LIMIT_HEALTH_KEY,
+                serde_json::to_value(state).unwrap_or_default(),
+            ),
+            None => health,
+        })
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.supported_models.clone()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.model_list.get_or_fetch(|| self.fetch_models()).await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        // Simple approximation: ~4 characters per token
+        Ok((text.len() + 3) / 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::StaticCredentials;
+    use crate::test_support::scripted;
+
+    fn response_body() -> Vec<u8> {
+        let response = InferenceResponse::from_string(
+            "hi".to_string(),
+            "gpt-4o-mini".to_string(),
+            TokenUsage::new(3, 1),
+            10,
+        );
+        serde_json::to_vec(&response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_infer_through_injected_transport() {
+        let transport = scripted(vec![
+            HttpResponse::new(200, br#"{"models": ["gpt-4o-mini"]}"#.to_vec()),
+            HttpResponse::new(200, response_body())
+                .with_header("x-ratelimit-remaining-requests", "41")
+                .with_header("x-ratelimit-remaining-tokens", "9000"),
+            HttpResponse::new(
+                200,
+                serde_json::to_vec(&HealthCheckResult::new(HealthStatus::healthy())).unwrap(),
+            ),
+        ]);
+        let mut client = HttpInferenceClient::new("http://inference:8080/", Arc::clone(&transport));
+        client.refresh_supported_models().await.unwrap();
+        assert_eq!(client.supported_models(), vec!["gpt-4o-mini"]);
+
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::Fast);
+        assert_eq!(client.rate_limit_state(), None);
+        let response = client.infer(request).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("hi"));
+        let state = client.rate_limit_state().unwrap();
+        assert_eq!(state.remaining_requests, Some(41));
+        assert_eq!(state.remaining_tokens, Some(9000));
+
+        let health = client.health_check().await.unwrap();
+        assert_eq!(RateLimitState::from_health(&health), Some(state));
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url, "http://inference:8080/models");
+        assert_eq!(requests[1].method, HttpMethod::Post);
+        assert_eq!(requests[1].header("content-type"), Some("application/json"));
+        assert_eq!(requests[1].header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn test_deadlines_bound_http_timeouts() {
+        let transport = scripted(vec![
+            HttpResponse::new(200, response_body()),
+            HttpResponse::new(200, response_body()),
+            HttpResponse::new(200, b"{}".to_vec()),
+        ]);
+        let client = HttpInferenceClient::new("http://inference:8080", Arc::clone(&transport));
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::Fast)
+            .with_timeout(Duration::from_secs(30));
+        client.infer(request.clone()).await.unwrap();
+        let near = Utc::now() + chrono::Duration::seconds(2);
+        client.infer(request.with_deadline(near)).await.unwrap();
+
+        // Calls made for no single request are bounded by the context deadline
+        RequestContext::background()
+            .with_deadline(near)
+            .scope(send_authorized(
+                &transport,
+                None,
+                HttpRequest::get("http://inference:8080/models"),
+            ))
+            .await
+            .unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].timeout, Some(Duration::from_secs(30)));
+        for request in &requests[1..] {
+            let timeout = request.timeout.unwrap();
+            assert!(timeout <= Duration::from_secs(2) && timeout > Duration::ZERO);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_models_is_cached() {
+        let transport = scripted(vec![
+            HttpResponse::new(200, br#"{"models": ["llama3:8b"]}"#.to_vec()),
+            HttpResponse::new(200, br#"{"models": ["llama3:8b", "qwen2:7b"]}"#.to_vec()),
+        ]);
+        let client = HttpInferenceClient::new("http://inference:8080", Arc::clone(&transport));
+        assert_eq!(client.list_models().await.unwrap(), vec!["llama3:8b"]);
+        assert_eq!(client.list_models().await.unwrap(), vec!["llama3:8b"]);
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+
+        let client = client.with_model_list_ttl(Duration::ZERO);
+        assert_eq!(client.list_models().await.unwrap().len(), 2);
+        assert!(client.supported_models().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_and_error_statuses() {
+        let transport = scripted(vec![
+            HttpResponse::new(401, br#"{"error": "invalid key"}"#.to_vec()),
+            HttpResponse::new(200, response_body()),
+            HttpResponse::new(500, br#"{"error": "boom"}"#.to_vec()),
+            HttpResponse::new(429, br#"{"error": "slow down"}"#.to_vec())
+                .with_header("Retry-After", "2"),
+        ]);
+        let client = HttpInferenceClient::new("http://inference:8080", Arc::clone(&transport))
+            .with_credentials(StaticCredentials::new("sk-test"));
+
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::Fast);
+        assert!(client.infer(request.clone()).await.is_ok());
+        let error = client.infer(request.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("boom"));
+        let error = client.infer(request).await.unwrap_err();
+        assert_eq!(
+            RateLimitError::from_error(&error).unwrap().retry_after,
+            Some(Duration::from_secs(2))
+        );
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[1].header("authorization"), Some("Bearer sk-test"));
+    }
+
+    #[tokio::test]
+    async fn test_signer_runs_after_credentials() {
+        let transport = scripted(vec![HttpResponse::new(200, response_body())]);
+        let client = HttpInferenceClient::new("https://gw.internal", Arc::clone(&transport))
+            .with_credentials(StaticCredentials::new("sk-test"))
+            .with_signer(crate::signing::HmacSigner::new("team-a", "secret"));
+
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::Fast);
+        client.infer(request).await.unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].header("authorization"), Some("Bearer sk-test"));
+        assert_eq!(requests[0].header("x-tyl-key-id"), Some("team-a"));
+        assert!(requests[0].header("x-tyl-signature").is_some());
+    }
+
+    #[cfg(all(feature = "http-client", feature = "http-server", feature = "mock"))]
+    #[tokio::test]
+    async fn test_reqwest_against_http_server() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let app = crate::http_server::router(Box::new(
+            crate::MockInferenceService::new()
+                .with_latency(0)
+                .with_custom_response("hello"),
+        ));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut client = HttpInferenceClient::new(base_url, reqwest::Client::new());
+        client.refresh_supported_models().await.unwrap();
+        assert!(!client.supported_models().is_empty());
+        assert!(client.health_check().await.unwrap().status.is_healthy());
+
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::Fast);
+        let response = client.infer(request).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("hello"));
+    }
+}
@@ -0,0 +1,242 @@
+//! HTTP server facade
+//!
+//! `router` exposes any `InferenceService` as a small JSON API:
+//!
+//! - `POST /infer` takes an `InferenceRequest` and returns the `InferenceResponse`. With
+//!   `?stream=true` or `Accept: text/event-stream` it streams `StreamChunk` server-sent events
+//!   instead, ending with a `[DONE]` event.
+//! - `GET /health` returns the `HealthCheckResult` (503 when unhealthy).
+//! - `GET /models` returns `{"models": [...]}`.
+//!
+//! Failures are returned as `{"error": "..."}`, the format `JsonFrameCodec` understands.
+//! Requests are handled in an interactive `RequestContext`, so they are scheduled ahead of
+//! background work unless they set a priority explicitly.
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::http_server::*;
+//! # #[cfg(feature = "mock")]
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let service = Box::new(MockInferenceService::new());
+//! tyl_llm_inference_port::http_server::serve(service, "0.0.0.0:8080".parse()?).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::sse::DONE_SENTINEL;
+use crate::*;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_core::Stream;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+type SharedService = Arc<dyn InferenceService>;
+
+/// Query parameters accepted by `POST /infer`
+#[derive(Debug, Default, Deserialize)]
+struct InferQuery {
+    #[serde(default)]
+    stream: bool,
+}
+
+fn error_response(status: StatusCode, error: TylError) -> Response {
+    (
+        status,
+        Json(serde_json::json!({ "error": error.to_string() })),
+    )
+        .into_response()
+}
+
+fn wants_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// Build the router for `service`
+pub fn router(service: Box<dyn InferenceService>) -> Router {
+    Router::new()
+        .route("/infer", post(infer))
+        .route("/health", get(health))
+        .route("/models", get(models))
+        .with_state(SharedService::from(service))
+}
+
+/// Serve `service` on `addr` until the process is stopped
+pub async fn serve(service: Box<dyn InferenceService>, addr: SocketAddr) -> InferenceResult<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| TylError::network(format!("Failed to bind {addr}: {e}")))?;
+    axum::serve(listener, router(service))
+        .await
+        .map_err(|e| TylError::network(format!("HTTP server failed: {e}")))
+}
+
+async fn infer(
+    State(service): State<SharedService>,
+    Query(query): Query<InferQuery>,
+    headers: HeaderMap,
+    Json(request): Json<InferenceRequest>,
+) -> Response {
+    RequestContext::interactive()
+        .scope(run_infer(
+            service,
+            query.stream || wants_event_stream(&headers),
+            request,
+        ))
+        .await
+}
+
+async fn run_infer(service: SharedService, stream: bool, request: InferenceRequest) -> Response {
+    if stream {
+        return match service.infer_stream(request).await {
+            Ok(stream) => Sse::new(ChunkEvents {
+                stream,
+                done: false,
+            })
+            .keep_alive(KeepAlive::default())
+            .into_response(),
+            Err(error) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error),
+        };
+    }
+
+    match service.infer(request).await {
+        Ok(response) => Json(response).into_response(),
+        Err(error) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error),
+    }
+}
+
+async fn health(State(service): State<SharedService>) -> Response {
+    match service.health_check().await {
+        Ok(health) if health.status.is_healthy() => Json(health).into_response(),
+        Ok(health) => (StatusCode::SERVICE_UNAVAILABLE, Json(health)).into_response(),
+        Err(error) => error_response(StatusCode::SERVICE_UNAVAILABLE, error),
+    }
+}
+
+async fn models(State(service): State<SharedService>) -> Response {
+    match service.list_models().await {
+        Ok(models) => Json(serde_json::json!({ "models": models })).into_response(),
+        Err(error) => error_response(StatusCode::BAD_GATEWAY, error),
+    }
+}
+
+/// Server-sent events for a chunk stream
+///
+/// Each chunk becomes a `data:` event; a stream error becomes an `error` event and ends the
+/// stream, otherwise a final `[DONE]` event is sent.
+struct ChunkEvents {
+    stream: InferenceStream,
+    done: bool,
+}
+
+impl Stream for ChunkEvents {
+    type Item = Result<Event, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let event = match Pin::new(&mut self.stream).poll_next(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some(Ok(chunk))) => {
+                Event::default().data(serde_json::to_string(&chunk).unwrap_or_default())
+            }
+            Poll::Ready(Some(Err(error))) => {
+                self.done = true;
+                Event::default()
+                    .event("error")
+                    .data(serde_json::json!({ "error": error.to_string() }).to_string())
+            }
+            Poll::Ready(None) => {
+                self.done = true;
+                Event::default().data(DONE_SENTINEL)
+            }
+        };
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::sse::SseParser;
+    use crate::MockInferenceService;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        router(Box::new(
+            MockInferenceService::new()
+                .with_latency(0)
+                .with_custom_response("hello"),
+        ))
+    }
+
+    async fn call(request: Request<Body>) -> (StatusCode, Vec<u8>) {
+        let response = app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, body.to_vec())
+    }
+
+    fn infer_request(uri: &str) -> Request<Body> {
+        let request = InferenceRequest::new("Say hi", HashMap::new(), ModelType::Fast);
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&request).unwrap()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_infer_health_and_models() {
+        let (status, body) = call(infer_request("/infer")).await;
+        assert_eq!(status, StatusCode::OK);
+        let response: InferenceResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response.content, serde_json::Value::String("hello".into()));
+
+        let (status, body) = call(Request::get("/health").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        let health: HealthCheckResult = serde_json::from_slice(&body).unwrap();
+        assert!(health.status.is_healthy());
+
+        let (status, body) = call(Request::get("/models").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        let models: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(!models["models"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_infer_streams_server_sent_events() {
+        let (status, body) = call(infer_request("/infer?stream=true")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let mut parser = SseParser::new();
+        let events = parser.feed(&body);
+        let chunk: StreamChunk = events[0].json().unwrap();
+        assert_eq!(chunk.delta, "hello");
+        assert!(chunk.is_final());
+        assert!(parser.is_done());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_body_is_rejected() {
+        let request = Request::post("/infer")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let (status, _) = call(request).await;
+        assert!(status.is_client_error());
+    }
+}
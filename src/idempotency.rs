@@ -0,0 +1,403 @@
+//! Idempotency keys and in-flight request deduplication
+//!
+//! `IdempotentService` coalesces concurrent requests sharing an `idempotency_key` (within the
+//! same ledger scope) into a single upstream call, and replays the successful response to
+//! retries arriving within the TTL. Failed calls are not cached: waiting duplicates retry the
+//! call themselves. Wrap usage-recording decorators such as `LedgerService` inside this one so
+//! replayed responses are billed once.
+//!
+//! With `with_content_dedup`, requests without an explicit key are keyed by
+//! `canonical::request_fingerprint`, so semantically identical requests are deduplicated too.
+//!
+//! Streams of keyed requests are deduplicated like `infer` calls and delivered as a single
+//! chunk, since a response can only be shared once complete; other streams pass through.
+
+use crate::canonical::request_fingerprint;
+use crate::events::{self, EventBus, InferenceEvent};
+use crate::ledger::request_scope;
+use crate::*;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Response metadata key set on deduplicated responses (`coalesced` or `replayed`)
+pub const IDEMPOTENCY_METADATA_KEY: &str = "idempotency";
+
+/// How long successful responses are replayed by default
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(600);
+
+type IdempotencyKey = (String, String);
+
+enum Entry {
+    InFlight(watch::Receiver<Option<InferenceResponse>>),
+    Completed {
+        response: Box<InferenceResponse>,
+        expires_at: Instant,
+    },
+}
+
+/// Removes the in-flight entry when the leading call fails or is cancelled
+struct Leader<'a> {
+    entries: &'a Mutex<HashMap<IdempotencyKey, Entry>>,
+    key: IdempotencyKey,
+    completed: bool,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(Entry::InFlight(_)) = entries.get(&self.key) {
+                entries.remove(&self.key);
+            }
+        }
+    }
+}
+
+fn mark(mut response: InferenceResponse, how: &str) -> InferenceResponse {
+    response.metadata = response
+        .metadata
+        .with_metadata(IDEMPOTENCY_METADATA_KEY, how);
+    response
+}
+
+/// Inference service decorator deduplicating requests by idempotency key
+pub struct IdempotentService<S> {
+    inner: S,
+    ttl: Duration,
+    content_dedup: bool,
+    events: Option<EventBus>,
+    entries: Mutex<HashMap<IdempotencyKey, Entry>>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for IdempotentService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdempotentService")
+            .field("inner", &self.inner)
+            .field("ttl", &self.ttl)
+            .field("content_dedup", &self.content_dedup)
+            .finish()
+    }
+}
+
+impl<S: InferenceService> IdempotentService<S> {
+    /// Replay successful responses for `DEFAULT_IDEMPOTENCY_TTL`
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            ttl: DEFAULT_IDEMPOTENCY_TTL,
+            content_dedup: false,
+            events: None,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Key requests without an idempotency key by their content fingerprint
+    pub fn with_content_dedup(mut self) -> Self {
+        self.content_dedup = true;
+        self
+    }
+
+    /// Publish a `CacheHit` event for every coalesced or replayed response
+    pub fn with_events(mut self, bus: EventBus) -> Self {
+        self.events = Some(bus);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of keys currently in flight or cached
+    pub fn tracked_keys(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        Self::purge_expired(&mut entries);
+        entries.len()
+    }
+
+    fn purge_expired(entries: &mut HashMap<IdempotencyKey, Entry>) {
+        let now = Instant::now();
+        entries.retain(|_, entry| match entry {
+            Entry::InFlight(_) => true,
+            Entry::Completed { expires_at, .. } => *expires_at > now,
+        });
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for IdempotentService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let idempotency_key = match &request.idempotency_key {
+            Some(key) => key.clone(),
+            None if self.content_dedup => request_fingerprint(&request),
+            None => return self.inner.infer(request).await,
+        };
+        let key = (request_scope(&request).to_string(), idempotency_key);
+        let cache_hit = |how: &str| {
+            if let (Some(bus), Some(request_id)) = (&self.events, events::request_id(&request)) {
+                bus.publish(InferenceEvent::CacheHit {
+                    request_id: request_id.to_string(),
+                    cache: format!("{IDEMPOTENCY_METADATA_KEY}:{how}"),
+                });
+            }
+        };
+
+        let sender = loop {
+            let mut in_flight = {
+                let mut entries = self.entries.lock().unwrap();
+                Self::purge_expired(&mut entries);
+                match entries.get(&key) {
+                    Some(Entry::Completed { response, .. }) => {
+                        cache_hit("replayed");
+                        return Ok(mark((**response).clone(), "replayed"));
+                    }
+                    Some(Entry::InFlight(receiver)) => receiver.clone(),
+                    None => {
+                        // Claim the key under the lock that found it free, so that concurrent
+                        // requests with the same key wait for this one
+                        let (sender, receiver) = watch::channel(None);
+                        entries.insert(key.clone(), Entry::InFlight(receiver));
+                        break sender;
+                    }
+                }
+            };
+
+            // Wait for the leading call; if it fails the sender is dropped and we retry
+            while in_flight.borrow().is_none() {
+                if in_flight.changed().await.is_err() {
+                    break;
+                }
+            }
+            let response = in_flight.borrow().clone();
+            if let Some(response) = response {
+                cache_hit("coalesced");
+                return Ok(mark(response, "coalesced"));
+            }
+        };
+
+        let mut leader = Leader {
+            entries: &self.entries,
+            key,
+            completed: false,
+        };
+
+        let response = self.inner.infer(request).await?;
+
+        self.entries.lock().unwrap().insert(
+            leader.key.clone(),
+            Entry::Completed {
+                response: Box::new(response.clone()),
+                expires_at: Instant::now() + self.ttl,
+            },
+        );
+        leader.completed = true;
+        let _ = sender.send(Some(response.clone()));
+
+        Ok(response)
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        if request.idempotency_key.is_none() && !self.content_dedup {
+            return self.inner.infer_stream(request).await;
+        }
+        Ok(InferenceStream::from_response(self.infer(request).await?))
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::ledger::{InMemoryLedgerStore, LedgerService, SCOPE_METADATA_KEY};
+    use crate::MockInferenceService;
+    use std::sync::Arc;
+
+    fn request(key: &str) -> InferenceRequest {
+        InferenceRequest::new("Test", HashMap::new(), ModelType::Fast).with_idempotency_key(key)
+    }
+
+    fn how(response: &InferenceResponse) -> Option<&str> {
+        response
+            .metadata
+            .metadata
+            .get(IDEMPOTENCY_METADATA_KEY)
+            .map(String::as_str)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_duplicates_are_coalesced() {
+        let store = Arc::new(InMemoryLedgerStore::new());
+        let service = Arc::new(IdempotentService::new(LedgerService::new(
+            MockInferenceService::new().with_latency(50),
+            store.clone(),
+        )));
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let service = Arc::clone(&service);
+                tokio::spawn(async move { service.infer(request("order-42")).await })
+            })
+            .collect();
+
+        let mut coalesced = 0;
+        for handle in handles {
+            let response = handle.await.unwrap().unwrap();
+            if how(&response) == Some("coalesced") {
+                coalesced += 1;
+            }
+        }
+        assert_eq!(coalesced, 2);
+        assert_eq!(store.len(), 1);
+
+        // A later retry is replayed without another upstream call
+        let response = service.infer(request("order-42")).await.unwrap();
+        assert_eq!(how(&response), Some("replayed"));
+        assert_eq!(store.len(), 1);
+
+        // Keys are scoped, and requests without a key are never deduplicated
+        service
+            .infer(request("order-42").with_metadata(SCOPE_METADATA_KEY, "tenant-b"))
+            .await
+            .unwrap();
+        service
+            .infer(InferenceRequest::new(
+                "Test",
+                HashMap::new(),
+                ModelType::Fast,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_racing_duplicates_call_upstream_once() {
+        let store = Arc::new(InMemoryLedgerStore::new());
+        let service = Arc::new(IdempotentService::new(LedgerService::new(
+            MockInferenceService::new().with_latency(5),
+            store.clone(),
+        )));
+
+        for round in 0..10 {
+            let key = format!("order-{round}");
+            let handles: Vec<_> = (0..16)
+                .map(|_| {
+                    let service = Arc::clone(&service);
+                    let key = key.clone();
+                    tokio::spawn(async move { service.infer(request(&key)).await })
+                })
+                .collect();
+            for handle in handles {
+                handle.await.unwrap().unwrap();
+            }
+            assert_eq!(store.len(), round + 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_content_dedup_without_keys() {
+        let store = Arc::new(InMemoryLedgerStore::new());
+        let service = IdempotentService::new(LedgerService::new(
+            MockInferenceService::new().with_latency(0),
+            store.clone(),
+        ))
+        .with_content_dedup();
+
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast);
+        service.infer(request.clone()).await.unwrap();
+        let response = service
+            .infer(request.clone().with_metadata("trace_id", "retry"))
+            .await
+            .unwrap();
+        assert_eq!(how(&response), Some("replayed"));
+
+        service.infer(request.with_temperature(0.1)).await.unwrap();
+        assert_eq!(store.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_replay_expires_after_ttl() {
+        let service = IdempotentService::new(MockInferenceService::new().with_latency(0))
+            .with_ttl(Duration::from_millis(20));
+
+        service.infer(request("order-1")).await.unwrap();
+        assert_eq!(service.tracked_keys(), 1);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(service.tracked_keys(), 0);
+        let response = service.infer(request("order-1")).await.unwrap();
+        assert_eq!(how(&response), None);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_streams_are_deduplicated() {
+        let service = IdempotentService::new(MockInferenceService::new().with_latency(0));
+        let first = service.infer_stream(request("order-1")).await.unwrap();
+        assert_eq!(first.assemble().await.unwrap().len(), 1);
+        assert_eq!(service.tracked_keys(), 1);
+        let replayed = service.infer(request("order-1")).await.unwrap();
+        assert_eq!(how(&replayed), Some("replayed"));
+
+        let unkeyed = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast);
+        service.infer_stream(unkeyed).await.unwrap();
+        assert_eq!(service.tracked_keys(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_replays_publish_cache_hits() {
+        let bus = EventBus::new();
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&hits);
+        bus.subscribe(move |event: &InferenceEvent| {
+            if let InferenceEvent::CacheHit { cache, .. } = event {
+                sink.lock().unwrap().push(cache.clone());
+            }
+        });
+        let service = crate::EventService::new(
+            IdempotentService::new(MockInferenceService::new().with_latency(0))
+                .with_events(bus.clone()),
+            bus,
+        );
+
+        service.infer(request("order-7")).await.unwrap();
+        service.infer(request("order-7")).await.unwrap();
+        assert_eq!(*hits.lock().unwrap(), vec!["idempotency:replayed"]);
+    }
+}
@@ -0,0 +1,577 @@
+//! Durable job queue
+//!
+//! `JobRunner` is a queue-backed `InferenceService`: `submit` persists the request in a
+//! `JobStore` and returns immediately, and workers (in this process or any other sharing the
+//! store) run the queued jobs with `run_pending`. With a durable store such as
+//! `SqliteJobStore` (feature `sqlite`), jobs outlive the process that submitted them:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::job_queue::*;
+//! # use tyl_llm_inference_port::backoff::*;
+//! # use std::sync::Arc;
+//! # use std::time::Duration;
+//! # #[cfg(feature = "sqlite")]
+//! # async fn example(openai: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! # use tyl_llm_inference_port::job_queue::sqlite::*;
+//! let store = Arc::new(SqliteJobStore::open("jobs.db").await?);
+//! let runner = JobRunner::new(openai, store)
+//!     .with_max_attempts(5)
+//!     .with_backoff(ExponentialBackoff::new(Duration::from_secs(5), Duration::from_secs(600)));
+//! let job = runner.submit(request).await?;
+//! // In the worker loop
+//! runner.run_pending().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A claimed job is leased to its worker for `with_lease` (10 minutes by default). If the
+//! worker dies, the lease runs out and the next worker takes the job over, so every job is
+//! completed at least once even across restarts. A job may therefore run more than once; each
+//! request is submitted with its job id as idempotency key (unless it already has one) so
+//! providers and `IdempotentService` can recognize repeats. Failed attempts are retried after
+//! the provider's `Retry-After` hint or the `BackoffPolicy` wait, until `max_attempts` have run;
+//! a job whose last allowed attempt lost its lease is failed instead of taken over.
+//!
+//! Workers report outcomes for the attempt they claimed: once a lease ran out and the job was
+//! taken over, the stale worker's `complete` or `fail` is ignored. `result` removes finished
+//! jobs from the store, so collect it once.
+
+use crate::backoff::{BackoffPolicy, ExponentialBackoff};
+use crate::jobs::{JobId, JobStatus};
+use crate::rate_limit::RateLimitError;
+use crate::*;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Lifecycle of a job in a `JobStore`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuedJobState {
+    /// Waiting to be claimed, possibly for a retry
+    Pending,
+    /// Claimed by a worker until its lease runs out
+    Running,
+    Completed,
+    /// Every attempt failed
+    Failed,
+}
+
+impl QueuedJobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// Persisted job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub id: JobId,
+    pub request: InferenceRequest,
+    pub state: QueuedJobState,
+    /// Attempts started so far
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub response: Option<InferenceResponse>,
+    pub enqueued_at: DateTime<Utc>,
+    /// Earliest time the job may be claimed
+    pub available_at: DateTime<Utc>,
+    /// End of the running worker's claim
+    pub lease_until: Option<DateTime<Utc>>,
+}
+
+impl QueuedJob {
+    /// Pending job for `request`, available now
+    pub fn new(request: InferenceRequest) -> Self {
+        let now = Utc::now();
+        Self {
+            id: JobId::generate(),
+            request,
+            state: QueuedJobState::Pending,
+            attempts: 0,
+            last_error: None,
+            response: None,
+            enqueued_at: now,
+            available_at: now,
+            lease_until: None,
+        }
+    }
+
+    /// Whether a worker may claim the job at `now`
+    pub fn is_claimable(&self, now: DateTime<Utc>) -> bool {
+        match self.state {
+            QueuedJobState::Pending => self.available_at <= now,
+            QueuedJobState::Running => self.lease_until.map_or(true, |lease| lease <= now),
+            QueuedJobState::Completed | QueuedJobState::Failed => false,
+        }
+    }
+
+    /// Status reported by `InferenceService::status`
+    pub fn status(&self) -> JobStatus {
+        match self.state {
+            QueuedJobState::Pending => JobStatus::Queued,
+            QueuedJobState::Running => JobStatus::Running,
+            QueuedJobState::Completed => JobStatus::Completed,
+            QueuedJobState::Failed => JobStatus::Failed {
+                error: self.last_error.clone().unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// Persistence port for queued jobs
+///
+/// `claim` must be atomic: two workers never get the same job while its lease holds.
+#[async_trait]
+pub trait JobStore: Send + Sync {
+    /// Add a job (enqueuing the same job id twice must keep a single job)
+    async fn enqueue(&self, job: QueuedJob) -> InferenceResult<()>;
+
+    /// Take the oldest job claimable at `now`, marking it running until `lease_until` and
+    /// counting one more attempt
+    async fn claim(
+        &self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> InferenceResult<Option<QueuedJob>>;
+
+    /// Store the response of claimed `attempt` of a job
+    ///
+    /// Returns `false` without changing anything unless the job is still running that attempt,
+    /// i.e. when its lease ran out and another worker took it over.
+    async fn complete(
+        &self,
+        id: &JobId,
+        attempt: u32,
+        response: InferenceResponse,
+    ) -> InferenceResult<bool>;
+
+    /// Record the failure of claimed `attempt`; the job is pending again from `retry_at`, or
+    /// failed for good without one
+    ///
+    /// Returns `false` without changing anything unless the job is still running that attempt.
+    async fn fail(
+        &self,
+        id: &JobId,
+        attempt: u32,
+        error: String,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> InferenceResult<bool>;
+
+    async fn get(&self, id: &JobId) -> InferenceResult<Option<QueuedJob>>;
+
+    /// Delete a job, e.g. once its result was collected
+    async fn remove(&self, id: &JobId) -> InferenceResult<()>;
+}
+
+/// Process-local job store; jobs are lost on restart
+#[derive(Debug, Default)]
+pub struct InMemoryJobStore {
+    jobs: Mutex<Vec<QueuedJob>>,
+}
+
+impl InMemoryJobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored jobs
+    pub fn len(&self) -> usize {
+        self.jobs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Apply `update` to a job still running `attempt`
+    fn update_claimed(
+        &self,
+        id: &JobId,
+        attempt: u32,
+        update: impl FnOnce(&mut QueuedJob),
+    ) -> InferenceResult<bool> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .iter_mut()
+            .find(|job| &job.id == id)
+            .ok_or_else(|| inference_errors::job_not_found(id.as_str()))?;
+        if job.state != QueuedJobState::Running || job.attempts != attempt {
+            return Ok(false);
+        }
+        update(job);
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl JobStore for InMemoryJobStore {
+    async fn enqueue(&self, job: QueuedJob) -> InferenceResult<()> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if !jobs.iter().any(|existing| existing.id == job.id) {
+            jobs.push(job);
+        }
+        Ok(())
+    }
+
+    async fn claim(
+        &self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> InferenceResult<Option<QueuedJob>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .iter_mut()
+            .filter(|job| job.is_claimable(now))
+            .min_by_key(|job| job.enqueued_at);
+        Ok(job.map(|job| {
+            job.state = QueuedJobState::Running;
+            job.attempts += 1;
+            job.lease_until = Some(lease_until);
+            job.clone()
+        }))
+    }
+
+    async fn complete(
+        &self,
+        id: &JobId,
+        attempt: u32,
+        response: InferenceResponse,
+    ) -> InferenceResult<bool> {
+        self.update_claimed(id, attempt, |job| {
+            job.state = QueuedJobState::Completed;
+            job.response = Some(response);
+            job.lease_until = None;
+        })
+    }
+
+    async fn fail(
+        &self,
+        id: &JobId,
+        attempt: u32,
+        error: String,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> InferenceResult<bool> {
+        self.update_claimed(id, attempt, |job| {
+            job.last_error = Some(error);
+            job.lease_until = None;
+            match retry_at {
+                Some(retry_at) => {
+                    job.state = QueuedJobState::Pending;
+                    job.available_at = retry_at;
+                }
+                None => job.state = QueuedJobState::Failed,
+            }
+        })
+    }
+
+    async fn get(&self, id: &JobId) -> InferenceResult<Option<QueuedJob>> {
+        let jobs = self.jobs.lock().unwrap();
+        Ok(jobs.iter().find(|job| &job.id == id).cloned())
+    }
+
+    async fn remove(&self, id: &JobId) -> InferenceResult<()> {
+        self.jobs.lock().unwrap().retain(|job| &job.id != id);
+        Ok(())
+    }
+}
+
+/// Queue-backed inference service running persisted jobs with retries
+#[derive(Clone)]
+pub struct JobRunner<S> {
+    inner: S,
+    store: Arc<dyn JobStore>,
+    max_attempts: u32,
+    backoff: Arc<dyn BackoffPolicy>,
+    lease: Duration,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for JobRunner<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobRunner")
+            .field("inner", &self.inner)
+            .field("max_attempts", &self.max_attempts)
+            .field("lease", &self.lease)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> JobRunner<S> {
+    /// Run jobs from `store` with `inner`, 3 attempts per job, the default
+    /// `ExponentialBackoff` between them and 10-minute leases
+    pub fn new(inner: S, store: Arc<dyn JobStore>) -> Self {
+        Self {
+            inner,
+            store,
+            max_attempts: 3,
+            backoff: Arc::new(ExponentialBackoff::default()),
+            lease: Duration::from_secs(600),
+        }
+    }
+
+    /// Attempts per job, the first one included (at least 1)
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Wait before retrying failures without a provider hint
+    pub fn with_backoff(mut self, backoff: impl BackoffPolicy + 'static) -> Self {
+        self.backoff = Arc::new(backoff);
+        self
+    }
+
+    /// How long a worker may run a job before others take it over; should exceed the longest
+    /// expected request
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn store(&self) -> &Arc<dyn JobStore> {
+        &self.store
+    }
+
+    /// Claim and run one job, returning its id, or `None` when no job is claimable
+    pub async fn run_once(&self) -> InferenceResult<Option<JobId>> {
+        let now = Utc::now();
+        let lease_until = now + chrono::Duration::from_std(self.lease).unwrap_or_default();
+        let Some(job) = self.store.claim(now, lease_until).await? else {
+            return Ok(None);
+        };
+        if job.attempts > self.max_attempts {
+            // Taken over after the last allowed attempt lost its lease
+            let error = format!(
+                "Lease expired on attempt {}: {}",
+                job.attempts - 1,
+                job.last_error.as_deref().unwrap_or("worker stopped")
+            );
+            self.store.fail(&job.id, job.attempts, error, None).await?;
+            return Ok(Some(job.id));
+        }
+
+        // A `false` from the store means the lease ran out and another worker owns the job
+        match self.inner.infer(job.request).await {
+            Ok(response) => {
+                self.store.complete(&job.id, job.attempts, response).await?;
+            }
+            Err(error) => {
+                let retry_at = (job.attempts < self.max_attempts).then(|| {
+                    let delay = RateLimitError::from_error(&error)
+                        .and_then(|error| error.retry_after)
+                        .unwrap_or_else(|| self.backoff.delay(job.attempts, Duration::ZERO));
+                    Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default()
+                });
+                self.store
+                    .fail(&job.id, job.attempts, error.to_string(), retry_at)
+                    .await?;
+            }
+        }
+        Ok(Some(job.id))
+    }
+
+    /// Run jobs until none is claimable, returning how many attempts were made
+    pub async fn run_pending(&self) -> InferenceResult<usize> {
+        let mut attempts = 0;
+        while self.run_once().await?.is_some() {
+            attempts += 1;
+        }
+        Ok(attempts)
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for JobRunner<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        self.inner.infer(request).await
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        self.inner.infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        self.inner.infer_batch(requests).await
+    }
+
+    fn handles_jobs(&self) -> bool {
+        true
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        let mut job = QueuedJob::new(request);
+        if job.request.idempotency_key.is_none() {
+            job.request.idempotency_key = Some(job.id.to_string());
+        }
+        let id = job.id.clone();
+        self.store.enqueue(job).await?;
+        Ok(id)
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        match self.store.get(job).await? {
+            Some(job) => Ok(job.status()),
+            None => Err(inference_errors::job_not_found(job.as_str())),
+        }
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        let Some(queued) = self.store.get(job).await? else {
+            return Err(inference_errors::job_not_found(job.as_str()));
+        };
+        let outcome = match (queued.state, queued.response) {
+            (QueuedJobState::Completed, Some(response)) => Ok(response),
+            (QueuedJobState::Failed, _) => Err(inference_errors::generation_failed(format!(
+                "Job {job} failed after {} attempts: {}",
+                queued.attempts,
+                queued.last_error.unwrap_or_default()
+            ))),
+            _ => return Err(inference_errors::job_not_finished(job.as_str())),
+        };
+        self.store.remove(job).await?;
+        outcome
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backoff::FixedBackoff;
+    use crate::test_support::ScriptedInferenceService;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Enrich", HashMap::new(), ModelType::Reasoning)
+    }
+
+    fn runner(failures: usize) -> JobRunner<ScriptedInferenceService> {
+        let errors = (0..failures).map(|_| TylError::network("connection reset"));
+        JobRunner::new(
+            ScriptedInferenceService::new(errors),
+            Arc::new(InMemoryJobStore::new()),
+        )
+        .with_backoff(FixedBackoff::new(Duration::ZERO))
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_completed() {
+        let runner = runner(2);
+        let job = runner.submit(request()).await.unwrap();
+        assert_eq!(runner.status(&job).await.unwrap(), JobStatus::Queued);
+        assert!(runner.result(&job).await.is_err());
+        assert!(runner.inner().requests.lock().unwrap().is_empty());
+
+        assert_eq!(runner.run_pending().await.unwrap(), 3);
+        assert_eq!(runner.status(&job).await.unwrap(), JobStatus::Completed);
+        let response = runner.result(&job).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("Enrich"));
+        // Every attempt carries the job id as idempotency key
+        let requests = runner.inner().requests.lock().unwrap();
+        assert!(requests
+            .iter()
+            .all(|request| request.idempotency_key.as_deref() == Some(job.as_str())));
+    }
+
+    #[tokio::test]
+    async fn test_fails_after_max_attempts() {
+        let runner = runner(5).with_max_attempts(2);
+        let job = runner.submit(request()).await.unwrap();
+        assert_eq!(runner.run_pending().await.unwrap(), 2);
+
+        let JobStatus::Failed { error } = runner.status(&job).await.unwrap() else {
+            panic!("job should have failed");
+        };
+        assert!(error.contains("connection reset"));
+        let error = runner.result(&job).await.unwrap_err();
+        assert!(error.to_string().contains("after 2 attempts"));
+        // Collected jobs leave the store
+        assert!(runner.status(&job).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_is_taken_over() {
+        let runner = runner(0);
+        // A worker claimed the job twenty minutes ago and died
+        let crashed_at = Utc::now() - chrono::Duration::minutes(20);
+        let mut queued = QueuedJob::new(request());
+        queued.available_at = crashed_at;
+        let job = queued.id.clone();
+        runner.store().enqueue(queued).await.unwrap();
+        let lease_until = crashed_at + chrono::Duration::minutes(10);
+        let claimed = runner.store().claim(crashed_at, lease_until).await.unwrap();
+        assert_eq!(claimed.unwrap().id, job);
+        let within_lease = crashed_at + chrono::Duration::minutes(1);
+        let claimed = runner
+            .store()
+            .claim(within_lease, lease_until)
+            .await
+            .unwrap();
+        assert!(claimed.is_none());
+
+        assert_eq!(runner.run_once().await.unwrap(), Some(job.clone()));
+        let stored = runner.store().get(&job).await.unwrap().unwrap();
+        assert_eq!(stored.attempts, 2);
+        assert_eq!(runner.run_once().await.unwrap(), None);
+
+        // The crashed worker coming back cannot overwrite the result
+        let late = InferenceResponse::new(
+            serde_json::json!("stale"),
+            ResponseMetadata::new("flaky-1".to_string(), TokenUsage::new(1, 1), 1),
+        );
+        assert!(!runner.store().complete(&job, 1, late).await.unwrap());
+        let failed = runner.store().fail(&job, 1, "late".to_string(), None);
+        assert!(!failed.await.unwrap());
+        let response = runner.result(&job).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("Enrich"));
+        assert!(runner.store().get(&job).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_last_attempt_fails() {
+        let runner = runner(0).with_max_attempts(1);
+        let crashed_at = Utc::now() - chrono::Duration::minutes(20);
+        let mut queued = QueuedJob::new(request());
+        queued.available_at = crashed_at;
+        let job = queued.id.clone();
+        runner.store().enqueue(queued).await.unwrap();
+        let lease_until = crashed_at + chrono::Duration::minutes(10);
+        runner.store().claim(crashed_at, lease_until).await.unwrap();
+
+        assert_eq!(runner.run_once().await.unwrap(), Some(job.clone()));
+        assert!(runner.inner().requests.lock().unwrap().is_empty());
+        let JobStatus::Failed { error } = runner.status(&job).await.unwrap() else {
+            panic!("job should have failed");
+        };
+        assert!(error.contains("Lease expired on attempt 1"));
+    }
+}
@@ -0,0 +1,319 @@
+//! SQLite job store (feature `sqlite`)
+//!
+//! Keeps the queue in a single table of a local database file, so queued and running jobs
+//! survive restarts of the worker process. `open` creates the file and the table when needed;
+//! with a pool of your own, call `migrate()` once at startup.
+
+use super::{JobStore, QueuedJob, QueuedJobState};
+use crate::jobs::JobId;
+use crate::*;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::path::Path;
+
+const DEFAULT_TABLE: &str = "inference_jobs";
+
+const COLUMNS: &str = "id, request, state, attempts, last_error, response, enqueued_at, \
+                       available_at, lease_until";
+
+fn store_error(error: sqlx::Error) -> TylError {
+    inference_errors::job_store_failed(error.to_string())
+}
+
+fn encode(value: &impl Serialize) -> InferenceResult<String> {
+    serde_json::to_string(value)
+        .map_err(|e| inference_errors::job_store_failed(format!("Failed to encode job: {e}")))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(json: &str) -> InferenceResult<T> {
+    serde_json::from_str(json)
+        .map_err(|e| inference_errors::job_store_failed(format!("Invalid stored job: {e}")))
+}
+
+fn timestamp(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+/// `JobStore` backed by a SQLite table
+#[derive(Debug, Clone)]
+pub struct SqliteJobStore {
+    pool: SqlitePool,
+    table: String,
+}
+
+impl SqliteJobStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            table: DEFAULT_TABLE.to_string(),
+        }
+    }
+
+    /// Open (creating if needed) the database file at `path` and its job table
+    pub async fn open(path: impl AsRef<Path>) -> InferenceResult<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options)
+            .await
+            .map_err(store_error)?;
+        let store = Self::new(pool);
+        store.migrate().await?;
+        Ok(store)
+    }
+
+    /// Use a custom table name (letters, digits, and underscores only)
+    pub fn with_table(mut self, table: impl Into<String>) -> InferenceResult<Self> {
+        self.table = valid_table_name("job", table.into())?;
+        Ok(self)
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Create the job table and index if they do not exist
+    pub async fn migrate(&self) -> InferenceResult<()> {
+        let table = &self.table;
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {table} (
+                id TEXT PRIMARY KEY,
+                request TEXT NOT NULL,
+                state TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                last_error TEXT,
+                response TEXT,
+                enqueued_at INTEGER NOT NULL,
+                available_at INTEGER NOT NULL,
+                lease_until INTEGER
+            )"
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS {table}_state_available_at_idx \
+             ON {table} (state, available_at)"
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        Ok(())
+    }
+
+    fn job_from_row(row: &SqliteRow) -> InferenceResult<QueuedJob> {
+        let request: String = row.try_get("request").map_err(store_error)?;
+        let state: String = row.try_get("state").map_err(store_error)?;
+        let response: Option<String> = row.try_get("response").map_err(store_error)?;
+        let attempts: i64 = row.try_get("attempts").map_err(store_error)?;
+        let enqueued_at: i64 = row.try_get("enqueued_at").map_err(store_error)?;
+        let available_at: i64 = row.try_get("available_at").map_err(store_error)?;
+        let lease_until: Option<i64> = row.try_get("lease_until").map_err(store_error)?;
+
+        Ok(QueuedJob {
+            id: JobId::new(row.try_get::<String, _>("id").map_err(store_error)?),
+            request: decode(&request)?,
+            state: decode(&serde_json::Value::String(state).to_string())?,
+            attempts: attempts as u32,
+            last_error: row.try_get("last_error").map_err(store_error)?,
+            response: response.as_deref().map(decode).transpose()?,
+            enqueued_at: timestamp(enqueued_at),
+            available_at: timestamp(available_at),
+            lease_until: lease_until.map(timestamp),
+        })
+    }
+
+    /// Outcome of a fenced update: `false` when the attempt lost its claim, an error when the
+    /// job does not exist
+    async fn claim_held(&self, id: &JobId, rows_affected: u64) -> InferenceResult<bool> {
+        if rows_affected > 0 {
+            return Ok(true);
+        }
+        match self.get(id).await? {
+            Some(_) => Ok(false),
+            None => Err(inference_errors::job_not_found(id.as_str())),
+        }
+    }
+}
+
+#[async_trait]
+impl JobStore for SqliteJobStore {
+    async fn enqueue(&self, job: QueuedJob) -> InferenceResult<()> {
+        sqlx::query(&format!(
+            "INSERT INTO {} ({COLUMNS}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (id) DO NOTHING",
+            self.table
+        ))
+        .bind(job.id.as_str())
+        .bind(encode(&job.request)?)
+        .bind(job.state.as_str())
+        .bind(i64::from(job.attempts))
+        .bind(&job.last_error)
+        .bind(job.response.as_ref().map(encode).transpose()?)
+        .bind(job.enqueued_at.timestamp_millis())
+        .bind(job.available_at.timestamp_millis())
+        .bind(job.lease_until.map(|lease| lease.timestamp_millis()))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        Ok(())
+    }
+
+    async fn claim(
+        &self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+    ) -> InferenceResult<Option<QueuedJob>> {
+        // A single statement, so concurrent workers cannot claim the same job
+        let table = &self.table;
+        let row = sqlx::query(&format!(
+            "UPDATE {table} SET state = 'running', attempts = attempts + 1, lease_until = ?1 \
+             WHERE id = (SELECT id FROM {table} \
+                 WHERE (state = 'pending' AND available_at <= ?2) \
+                    OR (state = 'running' AND (lease_until IS NULL OR lease_until <= ?2)) \
+                 ORDER BY enqueued_at LIMIT 1) \
+             RETURNING {COLUMNS}"
+        ))
+        .bind(lease_until.timestamp_millis())
+        .bind(now.timestamp_millis())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        row.as_ref().map(Self::job_from_row).transpose()
+    }
+
+    async fn complete(
+        &self,
+        id: &JobId,
+        attempt: u32,
+        response: InferenceResponse,
+    ) -> InferenceResult<bool> {
+        let result = sqlx::query(&format!(
+            "UPDATE {} SET state = ?, response = ?, lease_until = NULL \
+             WHERE id = ? AND state = 'running' AND attempts = ?",
+            self.table
+        ))
+        .bind(QueuedJobState::Completed.as_str())
+        .bind(encode(&response)?)
+        .bind(id.as_str())
+        .bind(i64::from(attempt))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        self.claim_held(id, result.rows_affected()).await
+    }
+
+    async fn fail(
+        &self,
+        id: &JobId,
+        attempt: u32,
+        error: String,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> InferenceResult<bool> {
+        let state = match retry_at {
+            Some(_) => QueuedJobState::Pending,
+            None => QueuedJobState::Failed,
+        };
+        let result = sqlx::query(&format!(
+            "UPDATE {} SET state = ?, last_error = ?, lease_until = NULL, \
+             available_at = COALESCE(?, available_at) \
+             WHERE id = ? AND state = 'running' AND attempts = ?",
+            self.table
+        ))
+        .bind(state.as_str())
+        .bind(error)
+        .bind(retry_at.map(|retry_at| retry_at.timestamp_millis()))
+        .bind(id.as_str())
+        .bind(i64::from(attempt))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        self.claim_held(id, result.rows_affected()).await
+    }
+
+    async fn get(&self, id: &JobId) -> InferenceResult<Option<QueuedJob>> {
+        let row = sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM {} WHERE id = ?",
+            self.table
+        ))
+        .bind(id.as_str())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        row.as_ref().map(Self::job_from_row).transpose()
+    }
+
+    async fn remove(&self, id: &JobId) -> InferenceResult<()> {
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ?", self.table))
+            .bind(id.as_str())
+            .execute(&self.pool)
+            .await
+            .map_err(store_error)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_jobs_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("tyl-jobs-{}.db", uuid::Uuid::new_v4()));
+        let request = InferenceRequest::new("Enrich", HashMap::new(), ModelType::Fast)
+            .with_idempotency_key("order-7");
+        let job = QueuedJob::new(request);
+        let id = job.id.clone();
+
+        let now = Utc::now();
+        {
+            let store = SqliteJobStore::open(&path).await.unwrap();
+            store.enqueue(job.clone()).await.unwrap();
+            store.enqueue(job).await.unwrap();
+            // Claimed by a worker that then died
+            let claimed = store.claim(now, now + chrono::Duration::minutes(10)).await;
+            assert_eq!(claimed.unwrap().unwrap().attempts, 1);
+            assert!(store.claim(now, now).await.unwrap().is_none());
+            store.pool.close().await;
+        }
+
+        let store = SqliteJobStore::open(&path).await.unwrap();
+        let later = now + chrono::Duration::minutes(11);
+        let claimed = store.claim(later, later).await.unwrap().unwrap();
+        assert_eq!(claimed.id, id);
+        assert_eq!(claimed.attempts, 2);
+        assert_eq!(claimed.state, QueuedJobState::Running);
+        assert_eq!(claimed.request.idempotency_key.as_deref(), Some("order-7"));
+
+        // The first worker's lease was taken over, its late report is ignored
+        let stale = store.fail(&id, 1, "late".to_string(), None).await;
+        assert!(!stale.unwrap());
+        let failed = store.fail(&id, 2, "overloaded".to_string(), Some(later));
+        assert!(failed.await.unwrap());
+        let retried = store.claim(later, later).await.unwrap().unwrap();
+        assert_eq!(retried.last_error.as_deref(), Some("overloaded"));
+        let response = InferenceResponse::new(
+            serde_json::json!("done"),
+            ResponseMetadata::new("gpt-4o-mini".to_string(), TokenUsage::new(5, 2), 10),
+        );
+        assert!(store.complete(&id, 3, response).await.unwrap());
+
+        let stored = store.get(&id).await.unwrap().unwrap();
+        assert_eq!(stored.state, QueuedJobState::Completed);
+        assert_eq!(stored.attempts, 3);
+        assert_eq!(stored.response.unwrap().content, serde_json::json!("done"));
+        store.remove(&id).await.unwrap();
+        assert!(store.get(&id).await.unwrap().is_none());
+
+        store.pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+}
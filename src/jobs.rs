@@ -0,0 +1,388 @@
+//! Asynchronous inference jobs
+//!
+//! `InferenceService::submit` starts a request and returns a `JobId` right away; the caller
+//! checks `status` and collects the response with `result` later, possibly from another
+//! process. Adapters backed by a queue override the three methods, so a Reasoning request that
+//! takes minutes does not need an HTTP connection held open for its whole duration:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::jobs::*;
+//! # use std::time::Duration;
+//! # async fn example(service: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! let job = service.submit(request).await?;
+//! while !service.status(&job).await?.is_finished() {
+//!     tokio::time::sleep(Duration::from_secs(5)).await;
+//! }
+//! let response = service.result(&job).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The default implementation runs the request inline inside `submit` and keeps the outcome in
+//! memory until `result` collects it, so callers can use the job API against any service.
+//! Outcomes are kept by their random job id, at most `MAX_INLINE_JOBS` of them in the process,
+//! and expire after `INLINE_JOB_TTL` when nobody collects them. Only a service of the type that
+//! ran a job can collect it, so a job run by a decorator is not found through its inner service.
+//!
+//! Decorators forward the three methods (through `submit_via`, `status_via` and `result_via`)
+//! when the service they wrap `handles_jobs`, so a `JobRunner` keeps its queue under any stack of
+//! decorators; otherwise the decorator runs the job inline through its own `infer`.
+
+use crate::*;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Identifier of a submitted job
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct JobId(String);
+
+impl JobId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    /// Fresh random id
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for JobId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// State of a submitted job
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for capacity
+    Queued,
+    Running,
+    /// The response is ready to be collected with `result`
+    Completed,
+    /// `result` returns the error
+    Failed {
+        error: String,
+    },
+}
+
+impl JobStatus {
+    /// Whether `result` will return the job's outcome
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed { .. })
+    }
+}
+
+/// How long the outcome of a job run inline waits to be collected
+pub const INLINE_JOB_TTL: Duration = Duration::from_secs(3600);
+
+/// Uncollected inline outcomes kept by the process; the oldest is dropped beyond this
+pub const MAX_INLINE_JOBS: usize = 8192;
+
+/// When an outcome was stored, the type of service that ran it, and the outcome
+type Outcome = (Instant, &'static str, InferenceResult<InferenceResponse>);
+
+type Outcomes = HashMap<JobId, Outcome>;
+
+/// Outcomes of jobs run inline by the default `submit`, by job id, until collected
+fn inline_outcomes() -> &'static Mutex<Outcomes> {
+    static OUTCOMES: OnceLock<Mutex<Outcomes>> = OnceLock::new();
+    OUTCOMES.get_or_init(Mutex::default)
+}
+
+fn service_type<S: ?Sized>(_service: &S) -> &'static str {
+    std::any::type_name::<S>()
+}
+
+/// Outcome of `id` if a service of type `service` ran it and it has not expired
+fn find<'a>(outcomes: &'a Outcomes, service: &str, id: &JobId) -> Option<&'a Outcome> {
+    outcomes
+        .get(id)
+        .filter(|(stored_at, ran_by, _)| *ran_by == service && !expired(*stored_at, Instant::now()))
+}
+
+fn expired(stored_at: Instant, now: Instant) -> bool {
+    now.saturating_duration_since(stored_at) >= INLINE_JOB_TTL
+}
+
+/// Keep an outcome of a service of type `service`, dropping expired outcomes and the oldest
+/// outcome when the map is full
+fn store_outcome(
+    outcomes: &mut Outcomes,
+    service: &'static str,
+    outcome: InferenceResult<InferenceResponse>,
+    now: Instant,
+) -> JobId {
+    outcomes.retain(|_, (stored_at, _, _)| !expired(*stored_at, now));
+    while outcomes.len() >= MAX_INLINE_JOBS {
+        let Some(oldest) = outcomes
+            .iter()
+            .min_by_key(|(_, (stored_at, _, _))| *stored_at)
+            .map(|(id, _)| id.clone())
+        else {
+            break;
+        };
+        outcomes.remove(&oldest);
+    }
+    let id = JobId::generate();
+    outcomes.insert(id.clone(), (now, service, outcome));
+    id
+}
+
+/// Run `request` on `service` and keep the outcome until `take_inline` collects it
+///
+/// This is the default `InferenceService::submit`. Outcomes are kept by their random job id and
+/// can be collected through any service of the type that ran them, so the service may move or
+/// be boxed in between. Uncollected outcomes expire after `INLINE_JOB_TTL`.
+pub async fn submit_inline<S: InferenceService + ?Sized>(
+    service: &S,
+    request: InferenceRequest,
+) -> InferenceResult<JobId> {
+    let outcome = service.infer(request).await;
+    let mut outcomes = inline_outcomes().lock().unwrap();
+    Ok(store_outcome(
+        &mut outcomes,
+        service_type(service),
+        outcome,
+        Instant::now(),
+    ))
+}
+
+/// Status of a job `service` ran inline
+pub fn inline_status<S: ?Sized>(service: &S, id: &JobId) -> InferenceResult<JobStatus> {
+    let outcomes = inline_outcomes().lock().unwrap();
+    match find(&outcomes, service_type(service), id) {
+        Some((_, _, Ok(_))) => Ok(JobStatus::Completed),
+        Some((_, _, Err(error))) => Ok(JobStatus::Failed {
+            error: error.to_string(),
+        }),
+        None => Err(inference_errors::job_not_found(id.as_str())),
+    }
+}
+
+/// Take the outcome of a job `service` ran inline; a second call reports the job as unknown
+pub fn take_inline<S: ?Sized>(service: &S, id: &JobId) -> InferenceResult<InferenceResponse> {
+    let mut outcomes = inline_outcomes().lock().unwrap();
+    if find(&outcomes, service_type(service), id).is_none() {
+        return Err(inference_errors::job_not_found(id.as_str()));
+    }
+    match outcomes.remove(id) {
+        Some((_, _, outcome)) => outcome,
+        None => Err(inference_errors::job_not_found(id.as_str())),
+    }
+}
+
+/// `submit` of a decorator: the wrapped service's queue when it has one, otherwise the
+/// decorator itself runs the request inline so its own behavior applies
+pub async fn submit_via<S, I>(
+    service: &S,
+    inner: &I,
+    request: InferenceRequest,
+) -> InferenceResult<JobId>
+where
+    S: InferenceService + ?Sized,
+    I: InferenceService + ?Sized,
+{
+    if inner.handles_jobs() {
+        inner.submit(request).await
+    } else {
+        submit_inline(service, request).await
+    }
+}
+
+/// `status` of a decorator, see `submit_via`
+pub async fn status_via<S, I>(service: &S, inner: &I, id: &JobId) -> InferenceResult<JobStatus>
+where
+    S: ?Sized,
+    I: InferenceService + ?Sized,
+{
+    if inner.handles_jobs() {
+        inner.status(id).await
+    } else {
+        inline_status(service, id)
+    }
+}
+
+/// `result` of a decorator, see `submit_via`
+pub async fn result_via<S, I>(
+    service: &S,
+    inner: &I,
+    id: &JobId,
+) -> InferenceResult<InferenceResponse>
+where
+    S: ?Sized,
+    I: InferenceService + ?Sized,
+{
+    if inner.handles_jobs() {
+        inner.result(id).await
+    } else {
+        take_inline(service, id)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockInferenceService;
+    use std::sync::Arc;
+
+    /// Queue-backed adapter: `submit` only enqueues, `work` processes the queue
+    #[derive(Default)]
+    struct Queue {
+        pending: Mutex<Vec<(JobId, InferenceRequest)>>,
+        done: Mutex<HashMap<JobId, InferenceResponse>>,
+    }
+
+    impl Queue {
+        fn work(&self) {
+            for (id, request) in self.pending.lock().unwrap().drain(..) {
+                let response = InferenceResponse::new(
+                    serde_json::json!(request.template),
+                    ResponseMetadata::new("queue-1".to_string(), TokenUsage::new(2, 1), 1),
+                );
+                self.done.lock().unwrap().insert(id, response);
+            }
+        }
+    }
+
+    #[async_trait]
+    impl InferenceService for Queue {
+        fn handles_jobs(&self) -> bool {
+            true
+        }
+
+        async fn infer(&self, _request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            Err(inference_errors::unsupported_feature(
+                "queue-1",
+                "synchronous inference",
+            ))
+        }
+
+        async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+            let id = JobId::generate();
+            self.pending.lock().unwrap().push((id.clone(), request));
+            Ok(id)
+        }
+
+        async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+            if self.done.lock().unwrap().contains_key(job) {
+                Ok(JobStatus::Completed)
+            } else {
+                Ok(JobStatus::Queued)
+            }
+        }
+
+        async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+            self.done
+                .lock()
+                .unwrap()
+                .remove(job)
+                .ok_or_else(|| inference_errors::job_not_finished(job.as_str()))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["queue-1".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Prove it", HashMap::new(), ModelType::Reasoning)
+    }
+
+    #[tokio::test]
+    async fn test_default_runs_inline() {
+        let service = MockInferenceService::new().with_latency(0);
+        let job = service.submit(request()).await.unwrap();
+        assert_eq!(service.status(&job).await.unwrap(), JobStatus::Completed);
+        assert!(service.result(&job).await.is_ok());
+        // Collected outcomes are gone
+        assert!(service.status(&job).await.is_err());
+        let error = service.result(&job).await.unwrap_err();
+        assert!(error.to_string().contains(job.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_inline_outcomes_survive_moves() {
+        let service = MockInferenceService::new().with_latency(0);
+        let job = service.submit(request()).await.unwrap();
+        let moved: Box<dyn InferenceService> = Box::new(service);
+        assert_eq!(moved.status(&job).await.unwrap(), JobStatus::Completed);
+        assert!(moved.result(&job).await.is_ok());
+        assert!(moved.status(&job).await.is_err());
+
+        // A decorator without a queue below runs the job itself
+        let service = MockInferenceService::new().with_latency(0);
+        let limited = RequestLimitService::new(service, RequestLimits::default());
+        let job = limited.submit(request()).await.unwrap();
+        assert!(limited.inner().status(&job).await.is_err());
+        assert_eq!(limited.status(&job).await.unwrap(), JobStatus::Completed);
+    }
+
+    #[test]
+    fn test_inline_outcomes_bounded_and_expiring() {
+        let ok = || {
+            Ok(InferenceResponse::new(
+                serde_json::json!("done"),
+                ResponseMetadata::new("mock".to_string(), TokenUsage::new(1, 1), 1),
+            ))
+        };
+        let mut outcomes = Outcomes::new();
+        let now = Instant::now();
+        let later = now + Duration::from_millis(1);
+        let first = store_outcome(&mut outcomes, "bounded", ok(), now);
+        for _ in 0..MAX_INLINE_JOBS {
+            store_outcome(&mut outcomes, "bounded", ok(), later);
+        }
+        assert_eq!(outcomes.len(), MAX_INLINE_JOBS);
+        assert!(!outcomes.contains_key(&first));
+
+        // Storing past the TTL drops every expired outcome
+        let last = store_outcome(&mut outcomes, "expiring", ok(), later + INLINE_JOB_TTL);
+        assert_eq!(outcomes.len(), 1);
+        assert!(outcomes.contains_key(&last));
+    }
+
+    #[tokio::test]
+    async fn test_queue_backed_adapter() {
+        let queue = Arc::new(Queue::default());
+        let service: Box<dyn InferenceService> = Box::new(Arc::clone(&queue));
+        let job = service.submit(request()).await.unwrap();
+        assert_eq!(service.status(&job).await.unwrap(), JobStatus::Queued);
+        assert!(service.result(&job).await.is_err());
+
+        queue.work();
+        assert!(service.status(&job).await.unwrap().is_finished());
+        let response = service.result(&job).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("Prove it"));
+    }
+
+    #[tokio::test]
+    async fn test_decorators_forward_to_queue() {
+        let queue = Arc::new(Queue::default());
+        let service = RequestLimitService::new(Arc::clone(&queue), RequestLimits::default());
+        assert!(service.handles_jobs());
+        let job = service.submit(request()).await.unwrap();
+        assert_eq!(service.status(&job).await.unwrap(), JobStatus::Queued);
+
+        queue.work();
+        let response = service.result(&job).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("Prove it"));
+    }
+}
@@ -0,0 +1,404 @@
+//! Latency histograms and percentiles
+//!
+//! `LatencyHistogram` buckets durations the way HDR histograms do: exact counts below 256µs, then
+//! 128 linear sub-buckets per power of two, so any recorded value is reported within 1% of its
+//! true value while a histogram spanning microseconds to hours stays a few kilobytes.
+//!
+//! `LatencyTracker` keeps the latencies of each (provider, model) over a sliding window (5
+//! minutes by default), so a backend that slows down or recovers shows it within one window.
+//! `MetricsService` fills one as it times requests, and `RoutingInferenceService` reads it for
+//! `CostPolicy::Fastest`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::latency::*;
+//! # use tyl_llm_inference_port::metrics::*;
+//! # use std::sync::Arc;
+//! # async fn example(openai_client: impl InferenceService + 'static) -> TylResult<()> {
+//! let tracker = Arc::new(LatencyTracker::new());
+//! let openai = MetricsService::new(openai_client, "openai").with_latency_tracker(tracker.clone());
+//! // ...
+//! if let Some(latency) = tracker.snapshot("openai", "gpt-4o") {
+//!     println!("p50 {:?}, p95 {:?}, p99 {:?}", latency.p50, latency.p95, latency.p99);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::usage_summary::bucket_start;
+use crate::*;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Window `LatencyTracker::new` keeps latencies for
+pub const DEFAULT_LATENCY_WINDOW: Duration = Duration::from_secs(300);
+/// Slices a tracker window is divided into; whole slices age out at once
+const WINDOW_SLICES: u32 = 10;
+
+/// Values below this many microseconds get one bucket each
+const LINEAR_BUCKETS: u64 = 256;
+/// Sub-buckets per power of two above `LINEAR_BUCKETS`
+const SUB_BUCKETS: u64 = LINEAR_BUCKETS / 2;
+
+/// Bucket index of a value in microseconds
+fn bucket_index(micros: u64) -> u64 {
+    if micros < LINEAR_BUCKETS {
+        return micros;
+    }
+    // Keep the 8 most significant bits: micros >> shift lands in [128, 256)
+    let shift = u64::from(63 - micros.leading_zeros()) - 7;
+    LINEAR_BUCKETS + (shift - 1) * SUB_BUCKETS + ((micros >> shift) - SUB_BUCKETS)
+}
+
+/// Largest value in microseconds that falls in bucket `index`
+fn bucket_upper_bound(index: u64) -> u64 {
+    if index < LINEAR_BUCKETS {
+        return index;
+    }
+    let shift = (index - LINEAR_BUCKETS) / SUB_BUCKETS + 1;
+    let lower = ((index - LINEAR_BUCKETS) % SUB_BUCKETS + SUB_BUCKETS) << shift;
+    lower + (1 << shift) - 1
+}
+
+fn micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+/// Log-linear histogram of durations with microsecond resolution
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    buckets: BTreeMap<u64, u64>,
+    count: u64,
+    sum_micros: u128,
+    min_micros: u64,
+    max_micros: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let value = micros(latency);
+        *self.buckets.entry(bucket_index(value)).or_insert(0) += 1;
+        self.min_micros = if self.count == 0 {
+            value
+        } else {
+            self.min_micros.min(value)
+        };
+        self.max_micros = self.max_micros.max(value);
+        self.count += 1;
+        self.sum_micros += u128::from(value);
+    }
+
+    /// Add every value recorded in `other`
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        if other.count == 0 {
+            return;
+        }
+        for (index, count) in &other.buckets {
+            *self.buckets.entry(*index).or_insert(0) += count;
+        }
+        self.min_micros = if self.count == 0 {
+            other.min_micros
+        } else {
+            self.min_micros.min(other.min_micros)
+        };
+        self.max_micros = self.max_micros.max(other.max_micros);
+        self.count += other.count;
+        self.sum_micros += other.sum_micros;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Latency below which `percentile` percent (0-100) of the values fall, zero when empty
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * self.count as f64).ceil() as u64;
+        let rank = rank.max(1);
+        let mut seen = 0;
+        for (index, count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                let value = bucket_upper_bound(*index).clamp(self.min_micros, self.max_micros);
+                return Duration::from_micros(value);
+            }
+        }
+        Duration::from_micros(self.max_micros)
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_micros((self.sum_micros / u128::from(count)) as u64),
+        }
+    }
+
+    pub fn snapshot(&self) -> LatencySnapshot {
+        LatencySnapshot {
+            count: self.count,
+            min: Duration::from_micros(self.min_micros),
+            max: Duration::from_micros(self.max_micros),
+            mean: self.mean(),
+            p50: self.percentile(50.0),
+            p95: self.percentile(95.0),
+            p99: self.percentile(99.0),
+        }
+    }
+}
+
+/// Point-in-time summary of a `LatencyHistogram`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySnapshot {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+/// Histograms of the time slices of one (provider, model), keyed by slice start
+type Slices = BTreeMap<DateTime<Utc>, LatencyHistogram>;
+
+/// Latency histograms per (provider, model) over a sliding window, shared between recorders and
+/// readers
+#[derive(Debug)]
+pub struct LatencyTracker {
+    window: Duration,
+    histograms: Mutex<BTreeMap<(String, String), Slices>>,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_LATENCY_WINDOW,
+            histograms: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl LatencyTracker {
+    /// Keep latencies for `DEFAULT_LATENCY_WINDOW`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep latencies for `window`; they age out a tenth of the window at a time
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn record(&self, provider: &str, model: &str, latency: Duration) {
+        self.record_at(provider, model, Utc::now(), latency);
+    }
+
+    /// Record a request that finished at `at`
+    pub fn record_at(&self, provider: &str, model: &str, at: DateTime<Utc>, latency: Duration) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry((provider.to_string(), model.to_string()))
+            .or_default()
+            .entry(bucket_start(at, self.slice()))
+            .or_default()
+            .record(latency);
+    }
+
+    /// Copy of the histogram of a provider and model over the window ending now
+    pub fn histogram(&self, provider: &str, model: &str) -> Option<LatencyHistogram> {
+        self.histogram_at(provider, model, Utc::now())
+    }
+
+    /// Copy of the histogram of a provider and model over the window ending at `now`
+    pub fn histogram_at(
+        &self,
+        provider: &str,
+        model: &str,
+        now: DateTime<Utc>,
+    ) -> Option<LatencyHistogram> {
+        self.windowed(now)
+            .remove(&(provider.to_string(), model.to_string()))
+    }
+
+    /// Percentiles of a provider and model, `None` without requests in the window
+    pub fn snapshot(&self, provider: &str, model: &str) -> Option<LatencySnapshot> {
+        self.histogram(provider, model)
+            .map(|histogram| histogram.snapshot())
+    }
+
+    /// Percentiles of a provider across all its models, `None` without requests in the window
+    pub fn provider_snapshot(&self, provider: &str) -> Option<LatencySnapshot> {
+        let mut merged = LatencyHistogram::new();
+        for ((name, _), histogram) in self.windowed(Utc::now()) {
+            if name == provider {
+                merged.merge(&histogram);
+            }
+        }
+        (!merged.is_empty()).then(|| merged.snapshot())
+    }
+
+    /// Percentiles of every (provider, model) with requests in the window, sorted by provider
+    /// then model
+    pub fn snapshots(&self) -> Vec<(String, String, LatencySnapshot)> {
+        self.windowed(Utc::now())
+            .into_iter()
+            .map(|((provider, model), histogram)| (provider, model, histogram.snapshot()))
+            .collect()
+    }
+
+    fn slice(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.window / WINDOW_SLICES)
+            .unwrap_or(chrono::Duration::MAX)
+            .max(chrono::Duration::milliseconds(1))
+    }
+
+    /// Histograms merged over the window ending at `now`, after dropping slices that left it
+    fn windowed(&self, now: DateTime<Utc>) -> BTreeMap<(String, String), LatencyHistogram> {
+        let window = chrono::Duration::from_std(self.window).unwrap_or(chrono::Duration::MAX);
+        let oldest = bucket_start(
+            now.checked_sub_signed(window).unwrap_or_default(),
+            self.slice(),
+        );
+        let mut histograms = self.histograms.lock().unwrap();
+        histograms.retain(|_, slices| {
+            *slices = slices.split_off(&oldest);
+            !slices.is_empty()
+        });
+        histograms
+            .iter()
+            .map(|(key, slices)| {
+                let mut merged = LatencyHistogram::new();
+                for histogram in slices.values() {
+                    merged.merge(histogram);
+                }
+                (key.clone(), merged)
+            })
+            .collect()
+    }
+
+    /// Forget every recorded latency
+    pub fn reset(&self) {
+        self.histograms.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_precision() {
+        for value in [
+            0,
+            1,
+            255,
+            256,
+            257,
+            511,
+            512,
+            1_000,
+            123_456,
+            9_999_999,
+            u64::MAX / 3,
+        ] {
+            let upper = bucket_upper_bound(bucket_index(value));
+            assert!(upper >= value, "{value} -> {upper}");
+            assert!(
+                (upper - value) as f64 <= value as f64 / 128.0,
+                "{value} -> {upper}"
+            );
+        }
+        // Indices are contiguous and ordered
+        assert_eq!(bucket_index(511) + 1, bucket_index(512));
+        assert!(bucket_index(1_000_000) > bucket_index(999_000));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.snapshot(), LatencySnapshot::default());
+        for ms in 1..=100 {
+            histogram.record(Duration::from_millis(ms));
+        }
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 100);
+        assert_eq!(snapshot.min, Duration::from_millis(1));
+        assert_eq!(snapshot.max, Duration::from_millis(100));
+        let close = |actual: Duration, ms: u64| {
+            let expected = Duration::from_millis(ms).as_secs_f64();
+            (actual.as_secs_f64() - expected).abs() <= expected / 100.0
+        };
+        assert!(close(snapshot.p50, 50), "{:?}", snapshot.p50);
+        assert!(close(snapshot.p95, 95), "{:?}", snapshot.p95);
+        assert!(close(snapshot.p99, 99), "{:?}", snapshot.p99);
+        assert_eq!(snapshot.mean, Duration::from_micros(50_500));
+        assert_eq!(histogram.percentile(100.0), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_tracker_per_provider_and_model() {
+        let tracker = LatencyTracker::new();
+        assert!(tracker.snapshot("openai", "gpt-4o").is_none());
+
+        tracker.record("openai", "gpt-4o", Duration::from_millis(800));
+        tracker.record("openai", "gpt-4o-mini", Duration::from_millis(200));
+        tracker.record("local", "llama3", Duration::from_millis(50));
+
+        let snapshot = tracker.snapshot("openai", "gpt-4o-mini").unwrap();
+        assert_eq!(snapshot.count, 1);
+        assert_eq!(snapshot.p99, Duration::from_millis(200));
+
+        let openai = tracker.provider_snapshot("openai").unwrap();
+        assert_eq!(openai.count, 2);
+        assert_eq!(openai.max, Duration::from_millis(800));
+
+        let keys: Vec<_> = tracker
+            .snapshots()
+            .into_iter()
+            .map(|(provider, model, _)| format!("{provider}/{model}"))
+            .collect();
+        assert_eq!(
+            keys,
+            vec!["local/llama3", "openai/gpt-4o", "openai/gpt-4o-mini"]
+        );
+
+        tracker.reset();
+        assert!(tracker.provider_snapshot("openai").is_none());
+    }
+
+    #[test]
+    fn test_tracker_window_slides() {
+        let tracker = LatencyTracker::new().with_window(Duration::from_secs(60));
+        let start = DateTime::<Utc>::UNIX_EPOCH;
+        let at = |secs: i64| start + chrono::Duration::seconds(secs);
+        tracker.record_at("openai", "gpt-4o", at(0), Duration::from_millis(900));
+        tracker.record_at("openai", "gpt-4o", at(30), Duration::from_millis(100));
+
+        let histogram = tracker.histogram_at("openai", "gpt-4o", at(59)).unwrap();
+        assert_eq!(histogram.count(), 2);
+
+        // The slow request ages out while the recent one stays
+        let histogram = tracker.histogram_at("openai", "gpt-4o", at(70)).unwrap();
+        assert_eq!(histogram.count(), 1);
+        assert_eq!(histogram.percentile(95.0), Duration::from_millis(100));
+        assert!(tracker.histogram_at("openai", "gpt-4o", at(100)).is_none());
+        assert!(tracker.snapshots().is_empty());
+    }
+}
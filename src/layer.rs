@@ -0,0 +1,242 @@
+//! Declarative decorator stacks
+//!
+//! Every decorator in this crate wraps an inner `InferenceService`. An `InferenceLayer` is the
+//! recipe for one such wrapping, and a `ServiceBuilder` stacks layers in the order they are
+//! listed: the first layer is the outermost, so a request passes through the layers top to
+//! bottom before reaching the backend and the response travels back up:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::layer::*;
+//! # use tyl_llm_inference_port::events::*;
+//! # use tyl_llm_inference_port::injection::*;
+//! # #[cfg(feature = "decorators")]
+//! # async fn example(openai: impl InferenceService + 'static, bus: EventBus) -> TylResult<()> {
+//! # use tyl_llm_inference_port::concurrency::*;
+//! # use tyl_llm_inference_port::timeout::*;
+//! let service = ServiceBuilder::new()
+//!     .layer(EventLayer::new(bus.clone()))             // sees every request, even rejected ones
+//!     .layer(ConcurrencyLimitLayer::new(16))           // queues before any work is done
+//!     .layer_fn(|inner| InjectionGuardService::new(inner, HeuristicInjectionDetector::new()))
+//!     .layer(TimeoutLayer::new())                      // bounds only the backend call
+//!     .service(openai);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Decorators without a dedicated layer type plug in through `layer_fn`. Layers are reusable:
+//! the same builder can wrap several backends, each getting its own decorators; shared handles
+//! held by a layer (an `EventBus`, a `TokenBudget`) stay shared between them.
+
+/// Wraps an inner service into a decorated one
+pub trait InferenceLayer<S> {
+    type Service;
+
+    fn layer(&self, inner: S) -> Self::Service;
+}
+
+/// Layer returning the service unchanged
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Identity;
+
+impl<S> InferenceLayer<S> for Identity {
+    type Service = S;
+
+    fn layer(&self, inner: S) -> S {
+        inner
+    }
+}
+
+/// Two layers applied as one, `outer` wrapping the result of `inner`
+#[derive(Debug, Clone)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<S, Inner, Outer> InferenceLayer<S> for Stack<Inner, Outer>
+where
+    Inner: InferenceLayer<S>,
+    Outer: InferenceLayer<Inner::Service>,
+{
+    type Service = Outer::Service;
+
+    fn layer(&self, service: S) -> Self::Service {
+        self.outer.layer(self.inner.layer(service))
+    }
+}
+
+/// Layer built from a closure, see `layer_fn`
+#[derive(Clone)]
+pub struct LayerFn<F> {
+    f: F,
+}
+
+impl<F> std::fmt::Debug for LayerFn<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LayerFn").finish_non_exhaustive()
+    }
+}
+
+impl<S, F, Out> InferenceLayer<S> for LayerFn<F>
+where
+    F: Fn(S) -> Out,
+{
+    type Service = Out;
+
+    fn layer(&self, inner: S) -> Out {
+        (self.f)(inner)
+    }
+}
+
+/// Layer wrapping services with `f`
+///
+/// e.g. `layer_fn(|inner| PiiRedactionService::new(inner, redactor.clone()))`
+pub fn layer_fn<F>(f: F) -> LayerFn<F> {
+    LayerFn { f }
+}
+
+/// Builds a decorator stack, outermost layer first
+#[derive(Debug, Clone)]
+pub struct ServiceBuilder<L> {
+    layer: L,
+}
+
+impl Default for ServiceBuilder<Identity> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceBuilder<Identity> {
+    pub fn new() -> Self {
+        Self { layer: Identity }
+    }
+}
+
+impl<L> ServiceBuilder<L> {
+    /// Add a layer inside the ones added before it
+    pub fn layer<T>(self, layer: T) -> ServiceBuilder<Stack<T, L>> {
+        ServiceBuilder {
+            layer: Stack {
+                inner: layer,
+                outer: self.layer,
+            },
+        }
+    }
+
+    /// Add a closure layer inside the ones added before it
+    pub fn layer_fn<F>(self, f: F) -> ServiceBuilder<Stack<LayerFn<F>, L>> {
+        self.layer(layer_fn(f))
+    }
+
+    /// The composed layer, for use as a layer of another builder
+    pub fn into_inner(self) -> L {
+        self.layer
+    }
+
+    /// Wrap `service` in every layer
+    pub fn service<S>(&self, service: S) -> L::Service
+    where
+        L: InferenceLayer<S>,
+    {
+        self.layer.layer(service)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockInferenceService;
+    use crate::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records its name on the way in and out of every request
+    struct Trace<S> {
+        inner: S,
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl<S: InferenceService> InferenceService for Trace<S> {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            self.log.lock().unwrap().push(format!("> {}", self.name));
+            let response = self.inner.infer(request).await;
+            self.log.lock().unwrap().push(format!("< {}", self.name));
+            response
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            self.inner.health_check().await
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            self.inner.supported_models()
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            self.inner.count_tokens(text)
+        }
+    }
+
+    fn trace<S>(name: &'static str, log: &Arc<Mutex<Vec<String>>>) -> impl Fn(S) -> Trace<S> {
+        let log = log.clone();
+        move |inner| Trace {
+            inner,
+            name,
+            log: log.clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_layers_apply_outermost_first() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let builder = ServiceBuilder::new()
+            .layer_fn(trace("outer", &log))
+            .layer(Identity)
+            .layer_fn(trace("inner", &log));
+
+        let service = builder.service(MockInferenceService::new().with_latency(0));
+        assert_eq!(service.name, "outer");
+        assert_eq!(service.inner.name, "inner");
+
+        service
+            .infer(InferenceRequest::new(
+                "Hi",
+                HashMap::new(),
+                ModelType::General,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["> outer", "> inner", "< inner", "< outer"]
+        );
+
+        // The builder is reusable and nests into other builders
+        let nested = ServiceBuilder::new()
+            .layer(builder.into_inner())
+            .service(MockInferenceService::new());
+        assert_eq!(nested.inner.name, "inner");
+    }
+
+    #[tokio::test]
+    async fn test_layer_shares_state_across_services() {
+        let budget = TokenBudget::new(1000);
+        let builder = ServiceBuilder::new().layer(TokenBudgetLayer::new(budget.clone()));
+        let first = builder.service(MockInferenceService::new().with_latency(0));
+        let second = builder.service(MockInferenceService::new().with_latency(0));
+
+        let request = || InferenceRequest::new("Hi", HashMap::new(), ModelType::General);
+        let used = first
+            .infer(request())
+            .await
+            .unwrap()
+            .metadata
+            .token_usage
+            .total_tokens;
+        second.infer(request()).await.unwrap();
+        assert_eq!(budget.used(), 2 * used as usize);
+    }
+}
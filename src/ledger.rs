@@ -0,0 +1,560 @@
+//! Usage and cost ledger
+//!
+//! Every inference can be recorded as a `UsageRecord` in a `LedgerStore`; failed requests are
+//! recorded with their error and no usage, so error rates can be reported next to spend. Records
+//! are grouped by scope (tenant, team, project...), taken from the request's `scope` metadata key.
+//! `InMemoryLedgerStore` keeps per-process counters; shared stores such as the Postgres adapter
+//! (feature `postgres`) let several gateway replicas aggregate spend consistently.
+//!
+//! `LedgerStore::usage_summary` aggregates records by model, model type, tenant and time bucket
+//! for dashboards (see `usage_summary::UsageFilter`).
+//!
+//! Streams are recorded when they end, with the usage reported by their chunks; a stream its
+//! consumer drops before the end is not recorded.
+
+use crate::ensemble::forward_batch;
+use crate::pricing::{PricingTable, SharedPricing};
+use crate::usage_summary::{UsageFilter, UsageSummary};
+use crate::*;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+/// Request metadata key selecting the ledger scope
+pub const SCOPE_METADATA_KEY: &str = "scope";
+
+/// Scope used for requests without a `scope` metadata entry
+pub const DEFAULT_SCOPE: &str = "default";
+
+/// Ledger scope of a request
+pub fn request_scope(request: &InferenceRequest) -> &str {
+    request
+        .metadata
+        .get(SCOPE_METADATA_KEY)
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_SCOPE)
+}
+
+/// Usage of a single completed inference
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub id: Uuid,
+    /// Accounting scope (tenant, team, project...)
+    pub scope: String,
+    /// Model used for generation
+    pub model: String,
+    pub model_type: ModelType,
+    pub token_usage: TokenUsage,
+    /// Cost in USD, `None` when the model has no known price
+    pub cost_usd: Option<f64>,
+    pub recorded_at: DateTime<Utc>,
+    /// Additional metadata
+    pub metadata: HashMap<String, String>,
+    /// Error message of a failed request, which used no tokens
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl UsageRecord {
+    pub fn new(
+        scope: impl Into<String>,
+        model: impl Into<String>,
+        model_type: ModelType,
+        token_usage: TokenUsage,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            scope: scope.into(),
+            model: model.into(),
+            model_type,
+            token_usage,
+            cost_usd: None,
+            recorded_at: Utc::now(),
+            metadata: HashMap::new(),
+            error: None,
+        }
+    }
+
+    /// Record of a request that failed with `error`
+    pub fn failed(
+        scope: impl Into<String>,
+        model: impl Into<String>,
+        model_type: ModelType,
+        error: impl Into<String>,
+    ) -> Self {
+        let mut record = Self::new(scope, model, model_type, TokenUsage::new(0, 0));
+        record.error = Some(error.into());
+        record
+    }
+
+    pub fn with_cost(mut self, cost_usd: f64) -> Self {
+        self.cost_usd = Some(cost_usd);
+        self
+    }
+
+    pub fn with_recorded_at(mut self, recorded_at: DateTime<Utc>) -> Self {
+        self.recorded_at = recorded_at;
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// Aggregated usage over a set of records
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+    /// Requests that failed
+    #[serde(default)]
+    pub errors: u64,
+}
+
+impl UsageTotals {
+    /// Add a record to the totals
+    pub fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.prompt_tokens += u64::from(record.token_usage.prompt_tokens);
+        self.completion_tokens += u64::from(record.token_usage.completion_tokens);
+        self.total_tokens += u64::from(record.token_usage.total_tokens);
+        self.cost_usd += record.cost_usd.unwrap_or(0.0);
+        if record.error.is_some() {
+            self.errors += 1;
+        }
+    }
+
+    /// Failed requests as a fraction of all requests (0 without requests)
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+}
+
+/// Persistence port for usage records
+#[async_trait]
+pub trait LedgerStore: Send + Sync {
+    /// Append a record (appending the same record id twice must not double count)
+    async fn append(&self, record: UsageRecord) -> InferenceResult<()>;
+
+    /// Records of a scope with `since <= recorded_at < until`, oldest first
+    async fn records(
+        &self,
+        scope: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<Vec<UsageRecord>>;
+
+    /// Aggregated usage of a scope with `since <= recorded_at < until`
+    async fn totals(
+        &self,
+        scope: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<UsageTotals> {
+        let mut totals = UsageTotals::default();
+        for record in self.records(scope, since, until).await? {
+            totals.add(&record);
+        }
+        Ok(totals)
+    }
+
+    /// Every scope with at least one record, for reports spanning all scopes
+    ///
+    /// Stores that cannot enumerate their scopes keep the default, which fails.
+    async fn scopes(&self) -> InferenceResult<Vec<String>> {
+        Err(inference_errors::unsupported_feature(
+            "ledger store",
+            "listing scopes",
+        ))
+    }
+
+    /// Usage matching `filter`, grouped by the filter's dimensions and time bucket
+    ///
+    /// The default reads the records of every matching scope and aggregates them in memory;
+    /// without tenants in the filter it needs `scopes`.
+    async fn usage_summary(&self, filter: &UsageFilter) -> InferenceResult<UsageSummary> {
+        let tenants = if filter.tenants.is_empty() {
+            self.scopes().await?
+        } else {
+            filter.tenants.clone()
+        };
+        let mut records = Vec::new();
+        for tenant in &tenants {
+            records.extend(self.records(tenant, filter.since, filter.until).await?);
+        }
+        Ok(filter.summarize(&records))
+    }
+}
+
+/// Process-local ledger store
+#[derive(Debug, Default)]
+pub struct InMemoryLedgerStore {
+    records: Mutex<Vec<UsageRecord>>,
+}
+
+impl InMemoryLedgerStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored records
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl LedgerStore for InMemoryLedgerStore {
+    async fn append(&self, record: UsageRecord) -> InferenceResult<()> {
+        let mut records = self.records.lock().unwrap();
+        if !records.iter().any(|existing| existing.id == record.id) {
+            records.push(record);
+        }
+        Ok(())
+    }
+
+    async fn records(
+        &self,
+        scope: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<Vec<UsageRecord>> {
+        let mut records: Vec<UsageRecord> = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.scope == scope && r.recorded_at >= since && r.recorded_at < until)
+            .cloned()
+            .collect();
+        records.sort_by_key(|r| r.recorded_at);
+        Ok(records)
+    }
+
+    async fn scopes(&self) -> InferenceResult<Vec<String>> {
+        let mut scopes: Vec<String> = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|record| record.scope.clone())
+            .collect();
+        scopes.sort();
+        scopes.dedup();
+        Ok(scopes)
+    }
+}
+
+/// Inference service decorator recording the usage and cost of every response
+///
+/// Ledger write failures are returned as errors so spend is never silently lost. Failed requests
+/// are recorded with `UsageRecord::failed`; the caller still gets the inference error.
+#[derive(Clone)]
+pub struct LedgerService<S> {
+    inner: S,
+    store: Arc<dyn LedgerStore>,
+    pricing: SharedPricing,
+}
+
+impl<S> std::fmt::Debug for LedgerService<S>
+where
+    S: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LedgerService")
+            .field("inner", &self.inner)
+            .field("pricing", &self.pricing)
+            .finish()
+    }
+}
+
+impl<S: InferenceService> LedgerService<S> {
+    /// Record usage in `store`, pricing responses with `PricingTable::with_defaults`
+    pub fn new(inner: S, store: Arc<dyn LedgerStore>) -> Self {
+        Self {
+            inner,
+            store,
+            pricing: PricingTable::with_defaults().into(),
+        }
+    }
+
+    /// Price responses with a fixed table or a `SharedPricing` refreshed at runtime
+    pub fn with_pricing(mut self, pricing: impl Into<SharedPricing>) -> Self {
+        self.pricing = pricing.into();
+        self
+    }
+
+    pub fn store(&self) -> &Arc<dyn LedgerStore> {
+        &self.store
+    }
+
+    /// Record the outcome of a request, pricing successful responses
+    async fn record(
+        &self,
+        (scope, model_type, requested_model): RequestUsage,
+        result: InferenceResult<InferenceResponse>,
+    ) -> InferenceResult<InferenceResponse> {
+        let mut response = match result {
+            Ok(response) => response,
+            Err(error) => {
+                let record =
+                    UsageRecord::failed(scope, requested_model, model_type, error.to_string());
+                let _ = self.store.append(record).await;
+                return Err(error);
+            }
+        };
+
+        let mut record = UsageRecord::new(
+            scope,
+            response.metadata.model.clone(),
+            model_type,
+            response.metadata.token_usage.clone(),
+        );
+        if let Some(cost) = self
+            .pricing
+            .cost(&response.metadata.model, &response.metadata.token_usage)
+        {
+            record = record.with_cost(cost);
+            response.metadata = response
+                .metadata
+                .with_metadata("cost_usd", format!("{cost:.6}"));
+        }
+        self.store.append(record).await?;
+
+        Ok(response)
+    }
+}
+
+/// Scope, model type and requested model (empty without an override) of a request
+type RequestUsage = (String, ModelType, String);
+
+fn request_usage(request: &InferenceRequest) -> RequestUsage {
+    (
+        request_scope(request).to_string(),
+        request.model_type,
+        request.model_override.clone().unwrap_or_default(),
+    )
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for LedgerService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let usage = request_usage(&request);
+        let result = self.inner.infer(request).await;
+        self.record(usage, result).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        let results =
+            forward_batch(&self.inner, requests, |request| Ok(request_usage(request))).await;
+        let mut responses = Vec::with_capacity(results.len());
+        for result in results {
+            responses.push(match result {
+                Ok((usage, response)) => self.record(usage, response).await,
+                Err(error) => Err(error),
+            });
+        }
+        responses
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let (scope, model_type, requested_model) = request_usage(&request);
+
+        let stream = match self.inner.infer_stream(request).await {
+            Ok(stream) => stream,
+            Err(error) => {
+                let record =
+                    UsageRecord::failed(scope, requested_model, model_type, error.to_string());
+                let _ = self.store.append(record).await;
+                return Err(error);
+            }
+        };
+
+        let store = Arc::clone(&self.store);
+        let pricing = self.pricing.clone();
+        Ok(stream.finish_with(move |outcome| async move {
+            let summary = match outcome {
+                Ok(summary) => summary,
+                Err(error) => {
+                    let record = UsageRecord::failed(scope, requested_model, model_type, error);
+                    let _ = store.append(record).await;
+                    return Ok(());
+                }
+            };
+            let model = summary.model.unwrap_or(requested_model);
+            let token_usage = summary.token_usage.unwrap_or_else(|| TokenUsage::new(0, 0));
+            let cost = pricing.cost(&model, &token_usage);
+            let mut record = UsageRecord::new(scope, model, model_type, token_usage);
+            if let Some(cost) = cost {
+                record = record.with_cost(cost);
+            }
+            store.append(record).await
+        }))
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes_ago: i64) -> DateTime<Utc> {
+        Utc::now() - chrono::Duration::minutes(minutes_ago)
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_totals() {
+        let store = InMemoryLedgerStore::new();
+        let record = UsageRecord::new(
+            "team-a",
+            "gpt-4o",
+            ModelType::Coding,
+            TokenUsage::new(10, 20),
+        )
+        .with_cost(0.5)
+        .with_recorded_at(at(30));
+
+        store.append(record.clone()).await.unwrap();
+        // Appending the same record twice is idempotent
+        store.append(record).await.unwrap();
+        store
+            .append(
+                UsageRecord::new("team-a", "gpt-4o", ModelType::Fast, TokenUsage::new(1, 1))
+                    .with_recorded_at(at(5)),
+            )
+            .await
+            .unwrap();
+        store
+            .append(UsageRecord::new(
+                "team-b",
+                "gpt-4o",
+                ModelType::Fast,
+                TokenUsage::new(1, 1),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 3);
+
+        let totals = store.totals("team-a", at(60), Utc::now()).await.unwrap();
+        assert_eq!(totals.requests, 2);
+        assert_eq!(totals.total_tokens, 32);
+        assert!((totals.cost_usd - 0.5).abs() < 1e-9);
+
+        let recent = store.totals("team-a", at(10), Utc::now()).await.unwrap();
+        assert_eq!(recent.requests, 1);
+        assert_eq!(store.scopes().await.unwrap(), vec!["team-a", "team-b"]);
+    }
+
+    #[test]
+    fn test_request_scope() {
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        assert_eq!(request_scope(&request), DEFAULT_SCOPE);
+
+        let request = request.with_metadata(SCOPE_METADATA_KEY, "tenant-42");
+        assert_eq!(request_scope(&request), "tenant-42");
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_ledger_service_records_usage() {
+        use crate::MockInferenceService;
+
+        let store = Arc::new(InMemoryLedgerStore::new());
+        let service =
+            LedgerService::new(MockInferenceService::new().with_latency(0), store.clone());
+
+        let request = InferenceRequest::new("Hello", HashMap::new(), ModelType::General)
+            .with_metadata(SCOPE_METADATA_KEY, "tenant-1");
+        let response = service.infer(request).await.unwrap();
+        assert!(response.metadata.metadata.contains_key("cost_usd"));
+
+        let records = store
+            .records("tenant-1", at(1), Utc::now() + chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].model, "gpt-4o-mini");
+        assert_eq!(records[0].token_usage, response.metadata.token_usage);
+        assert!(records[0].cost_usd.unwrap() > 0.0);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_ledger_service_records_streams_when_they_end() {
+        use crate::MockInferenceService;
+
+        let store = Arc::new(InMemoryLedgerStore::new());
+        let service =
+            LedgerService::new(MockInferenceService::new().with_latency(0), store.clone());
+
+        let request = InferenceRequest::new("Hello", HashMap::new(), ModelType::General);
+        let stream = service.infer_stream(request).await.unwrap();
+        assert!(store.is_empty());
+        let chunks = stream.assemble().await.unwrap();
+
+        let records = store
+            .records(
+                DEFAULT_SCOPE,
+                at(1),
+                Utc::now() + chrono::Duration::seconds(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].model, "gpt-4o-mini");
+        assert_eq!(
+            Some(&records[0].token_usage),
+            chunks.candidate(0).unwrap().token_usage.as_ref()
+        );
+        assert!(records[0].cost_usd.unwrap() > 0.0);
+    }
+}
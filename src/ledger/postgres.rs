@@ -0,0 +1,376 @@
+//! Postgres ledger store (feature `postgres`)
+//!
+//! Stores usage records in a single table so every gateway replica sees the same spend.
+//! Call `migrate()` once at startup to create the table and its index, and after upgrading to
+//! add new columns to an existing table. Usage summaries are aggregated by Postgres in one
+//! `GROUP BY` query rather than by loading every record.
+
+use super::{LedgerStore, UsageRecord, UsageTotals};
+use crate::usage_summary::{
+    model_type_name, UsageDimension, UsageFilter, UsageGroup, UsageSummary,
+};
+use crate::*;
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::Row;
+
+const DEFAULT_TABLE: &str = "inference_usage_ledger";
+
+/// Aggregates read back by `totals_from_row`
+const TOTALS_COLUMNS: &str = "COUNT(*)::BIGINT AS requests, \
+     COALESCE(SUM(prompt_tokens), 0)::BIGINT AS prompt_tokens, \
+     COALESCE(SUM(completion_tokens), 0)::BIGINT AS completion_tokens, \
+     COALESCE(SUM(total_tokens), 0)::BIGINT AS total_tokens, \
+     COALESCE(SUM(cost_usd), 0)::DOUBLE PRECISION AS cost_usd, \
+     COUNT(error)::BIGINT AS errors";
+
+fn store_error(error: sqlx::Error) -> TylError {
+    inference_errors::ledger_store_failed(error.to_string())
+}
+
+fn parse_model_type(model_type: String) -> InferenceResult<ModelType> {
+    serde_json::from_value(serde_json::Value::String(model_type.clone()))
+        .map_err(|_| inference_errors::invalid_model_type(model_type))
+}
+
+fn totals_from_row(row: &PgRow) -> InferenceResult<UsageTotals> {
+    let count = |column: &str| -> InferenceResult<u64> {
+        let value: i64 = row.try_get(column).map_err(store_error)?;
+        Ok(value.max(0) as u64)
+    };
+
+    Ok(UsageTotals {
+        requests: count("requests")?,
+        prompt_tokens: count("prompt_tokens")?,
+        completion_tokens: count("completion_tokens")?,
+        total_tokens: count("total_tokens")?,
+        cost_usd: row.try_get("cost_usd").map_err(store_error)?,
+        errors: count("errors")?,
+    })
+}
+
+/// `LedgerStore` backed by a Postgres table
+#[derive(Debug, Clone)]
+pub struct PostgresLedgerStore {
+    pool: PgPool,
+    table: String,
+}
+
+impl PostgresLedgerStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            table: DEFAULT_TABLE.to_string(),
+        }
+    }
+
+    /// Use a custom table name (letters, digits, and underscores only)
+    pub fn with_table(mut self, table: impl Into<String>) -> InferenceResult<Self> {
+        self.table = valid_table_name("ledger", table.into())?;
+        Ok(self)
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Create the ledger table and index if they do not exist
+    pub async fn migrate(&self) -> InferenceResult<()> {
+        let table = &self.table;
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {table} (
+                id UUID PRIMARY KEY,
+                scope TEXT NOT NULL,
+                model TEXT NOT NULL,
+                model_type TEXT NOT NULL,
+                prompt_tokens BIGINT NOT NULL,
+                completion_tokens BIGINT NOT NULL,
+                total_tokens BIGINT NOT NULL,
+                cost_usd DOUBLE PRECISION,
+                recorded_at TIMESTAMPTZ NOT NULL,
+                metadata JSONB NOT NULL DEFAULT '{{}}'::jsonb,
+                error TEXT
+            )"
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        // Tables created before failed requests were recorded
+        sqlx::query(&format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS error TEXT"
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS {table}_scope_recorded_at_idx \
+             ON {table} (scope, recorded_at)"
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        Ok(())
+    }
+
+    /// Query aggregating `filter`'s records per group: `$1`/`$2` bound the time range, `$3` to
+    /// `$5` are the tenant, model and model type lists and `$6` the bucket length in
+    /// milliseconds, when there is one
+    fn summary_query(&self, filter: &UsageFilter) -> String {
+        let grouped = |dimension| filter.group_by.contains(&dimension);
+        // date_trunc only knows calendar units; this keeps buckets epoch-aligned multiples of
+        // the bucket length, like `UsageFilter::summarize`
+        let period = match filter.bucket {
+            Some(_) => {
+                "to_timestamp((floor(extract(epoch FROM recorded_at) * 1000 / $6) * $6 \
+                        / 1000)::DOUBLE PRECISION)"
+            }
+            None => "NULL::TIMESTAMPTZ",
+        };
+        let column = |dimension, name: &str| match grouped(dimension) {
+            true => name.to_string(),
+            false => format!("NULL::TEXT AS {name}"),
+        };
+        let mut group_by: Vec<&str> = Vec::new();
+        if filter.bucket.is_some() {
+            group_by.push("period_start");
+        }
+        for (dimension, name) in [
+            (UsageDimension::Tenant, "scope"),
+            (UsageDimension::Model, "model"),
+            (UsageDimension::ModelType, "model_type"),
+        ] {
+            if grouped(dimension) {
+                group_by.push(name);
+            }
+        }
+
+        let mut query = format!(
+            "SELECT {period} AS period_start, {}, {}, {}, {TOTALS_COLUMNS} FROM {} \
+             WHERE recorded_at >= $1 AND recorded_at < $2 \
+             AND (cardinality($3::TEXT[]) = 0 OR scope = ANY($3)) \
+             AND (cardinality($4::TEXT[]) = 0 OR model = ANY($4)) \
+             AND (cardinality($5::TEXT[]) = 0 OR model_type = ANY($5))",
+            column(UsageDimension::Tenant, "scope"),
+            column(UsageDimension::Model, "model"),
+            column(UsageDimension::ModelType, "model_type"),
+            self.table
+        );
+        if !group_by.is_empty() {
+            query.push_str(&format!(" GROUP BY {}", group_by.join(", ")));
+        }
+        query
+    }
+
+    fn record_from_row(row: &PgRow) -> InferenceResult<UsageRecord> {
+        let model_type = parse_model_type(row.try_get("model_type").map_err(store_error)?)?;
+        let metadata: serde_json::Value = row.try_get("metadata").map_err(store_error)?;
+        let prompt_tokens: i64 = row.try_get("prompt_tokens").map_err(store_error)?;
+        let completion_tokens: i64 = row.try_get("completion_tokens").map_err(store_error)?;
+
+        Ok(UsageRecord {
+            id: row.try_get("id").map_err(store_error)?,
+            scope: row.try_get("scope").map_err(store_error)?,
+            model: row.try_get("model").map_err(store_error)?,
+            model_type,
+            token_usage: TokenUsage::new(prompt_tokens as u32, completion_tokens as u32),
+            cost_usd: row.try_get("cost_usd").map_err(store_error)?,
+            recorded_at: row.try_get("recorded_at").map_err(store_error)?,
+            metadata: serde_json::from_value(metadata).unwrap_or_default(),
+            error: row.try_get("error").map_err(store_error)?,
+        })
+    }
+}
+
+#[async_trait]
+impl LedgerStore for PostgresLedgerStore {
+    async fn append(&self, record: UsageRecord) -> InferenceResult<()> {
+        let model_type = serde_json::to_value(record.model_type)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let metadata = serde_json::to_value(&record.metadata).unwrap_or_default();
+
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, scope, model, model_type, prompt_tokens, completion_tokens, \
+             total_tokens, cost_usd, recorded_at, metadata, error) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+             ON CONFLICT (id) DO NOTHING",
+            self.table
+        ))
+        .bind(record.id)
+        .bind(&record.scope)
+        .bind(&record.model)
+        .bind(model_type)
+        .bind(i64::from(record.token_usage.prompt_tokens))
+        .bind(i64::from(record.token_usage.completion_tokens))
+        .bind(i64::from(record.token_usage.total_tokens))
+        .bind(record.cost_usd)
+        .bind(record.recorded_at)
+        .bind(metadata)
+        .bind(&record.error)
+        .execute(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        Ok(())
+    }
+
+    async fn records(
+        &self,
+        scope: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<Vec<UsageRecord>> {
+        let rows = sqlx::query(&format!(
+            "SELECT id, scope, model, model_type, prompt_tokens, completion_tokens, cost_usd, \
+             recorded_at, metadata, error FROM {} \
+             WHERE scope = $1 AND recorded_at >= $2 AND recorded_at < $3 \
+             ORDER BY recorded_at",
+            self.table
+        ))
+        .bind(scope)
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        rows.iter().map(Self::record_from_row).collect()
+    }
+
+    async fn totals(
+        &self,
+        scope: &str,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<UsageTotals> {
+        let row = sqlx::query(&format!(
+            "SELECT {TOTALS_COLUMNS} FROM {} \
+             WHERE scope = $1 AND recorded_at >= $2 AND recorded_at < $3",
+            self.table
+        ))
+        .bind(scope)
+        .bind(since)
+        .bind(until)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        totals_from_row(&row)
+    }
+
+    async fn usage_summary(&self, filter: &UsageFilter) -> InferenceResult<UsageSummary> {
+        let model_types: Vec<String> = filter
+            .model_types
+            .iter()
+            .map(|model_type| model_type_name(*model_type))
+            .collect();
+        let query = self.summary_query(filter);
+        let mut query = sqlx::query(&query)
+            .bind(filter.since)
+            .bind(filter.until)
+            .bind(&filter.tenants)
+            .bind(&filter.models)
+            .bind(model_types);
+        if let Some(bucket) = filter.bucket {
+            query = query.bind(bucket.num_milliseconds().max(1));
+        }
+        let rows = query.fetch_all(&self.pool).await.map_err(store_error)?;
+
+        let mut summary = UsageSummary::default();
+        let mut groups = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let totals = totals_from_row(row)?;
+            if totals.requests == 0 {
+                continue;
+            }
+            let period_start: Option<DateTime<Utc>> =
+                row.try_get("period_start").map_err(store_error)?;
+            let model_type: Option<String> = row.try_get("model_type").map_err(store_error)?;
+            let group = UsageGroup {
+                period_start,
+                period_end: period_start
+                    .zip(filter.bucket)
+                    .map(|(start, bucket)| start + bucket),
+                tenant: row.try_get("scope").map_err(store_error)?,
+                model: row.try_get("model").map_err(store_error)?,
+                model_type: model_type.clone().map(parse_model_type).transpose()?,
+                totals,
+            };
+            summary.totals.requests += group.totals.requests;
+            summary.totals.prompt_tokens += group.totals.prompt_tokens;
+            summary.totals.completion_tokens += group.totals.completion_tokens;
+            summary.totals.total_tokens += group.totals.total_tokens;
+            summary.totals.cost_usd += group.totals.cost_usd;
+            summary.totals.errors += group.totals.errors;
+            let key = (
+                group.period_start,
+                group.tenant.clone(),
+                group.model.clone(),
+                model_type,
+            );
+            groups.push((key, group));
+        }
+
+        // Same order as `UsageFilter::summarize`, whatever the database collation
+        groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+        summary.groups = groups.into_iter().map(|(_, group)| group).collect();
+        Ok(summary)
+    }
+
+    async fn scopes(&self) -> InferenceResult<Vec<String>> {
+        let rows = sqlx::query(&format!(
+            "SELECT DISTINCT scope FROM {} ORDER BY scope",
+            self.table
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(store_error)?;
+
+        rows.iter()
+            .map(|row| row.try_get("scope").map_err(store_error))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_table_name_validation() {
+        let pool = PgPool::connect_lazy("postgres://localhost/ledger").unwrap();
+        let store = PostgresLedgerStore::new(pool);
+        assert_eq!(store.table(), DEFAULT_TABLE);
+
+        let store = store.with_table("tenant_usage").unwrap();
+        assert_eq!(store.table(), "tenant_usage");
+
+        assert!(store.clone().with_table("usage; DROP TABLE x").is_err());
+        assert!(store.clone().with_table("1usage").is_err());
+        assert!(store.with_table("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_summary_query_groups_in_the_database() {
+        let pool = PgPool::connect_lazy("postgres://localhost/ledger").unwrap();
+        let store = PostgresLedgerStore::new(pool);
+        let since = DateTime::<Utc>::UNIX_EPOCH;
+
+        let query = store.summary_query(&UsageFilter::new(since, Utc::now()));
+        assert!(query.contains("NULL::TIMESTAMPTZ AS period_start"));
+        assert!(!query.contains("GROUP BY"));
+        assert!(!query.contains("$6"));
+
+        let filter = UsageFilter::new(since, Utc::now())
+            .group_by(UsageDimension::Model)
+            .group_by(UsageDimension::Tenant)
+            .with_bucket(chrono::Duration::days(1));
+        let query = store.summary_query(&filter);
+        assert!(query.ends_with(" GROUP BY period_start, scope, model"));
+        assert!(query.contains("NULL::TEXT AS model_type"));
+        assert!(query.contains("* $6"));
+    }
+}
@@ -49,6 +49,9 @@
 //! - **General** - General text generation and conversation
 //! - **Fast** - Quick responses for simple tasks
 //! - **Creative** - Creative writing and content generation
+//! - **Vision** - Understanding image attachments
+//! - **Extraction** - Pulling structured fields out of text
+//! - **Summarization** - Condensing long inputs
 //!
 //! ## Examples
 //!
@@ -61,6 +64,7 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 
 /// Simple health status for inference services
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -159,6 +163,14 @@ pub mod inference_errors {
         TylError::validation("model", format!("Unsupported model: {}", model.into()))
     }
 
+    /// Create an unsupported feature error (e.g. image inputs for a text-only model)
+    pub fn unsupported_feature(model: impl Into<String>, feature: impl Into<String>) -> TylError {
+        TylError::validation(
+            "model",
+            format!("Model {} does not support {}", model.into(), feature.into()),
+        )
+    }
+
     /// Create a template processing error
     pub fn template_processing_failed(message: impl Into<String>) -> TylError {
         TylError::validation(
@@ -166,6 +178,198 @@ pub mod inference_errors {
             format!("Template processing failed: {}", message.into()),
         )
     }
+
+    /// Create an unknown post-processor error
+    pub fn unknown_post_processor(name: impl Into<String>) -> TylError {
+        TylError::validation(
+            "post_process",
+            format!("Unknown post-processor: {}", name.into()),
+        )
+    }
+
+    /// Start of the message of `request_timeout` errors
+    const REQUEST_TIMED_OUT: &str = "Inference request timed out after ";
+
+    /// Create a request timeout error
+    pub fn request_timeout(timeout: Duration) -> TylError {
+        TylError::network(format!("{REQUEST_TIMED_OUT}{}ms", timeout.as_millis()))
+    }
+
+    /// Timeout reported by a `request_timeout` error, `None` for any other error
+    pub fn timed_out_after(error: &TylError) -> Option<Duration> {
+        if !is_network(error) {
+            return None;
+        }
+        let message = error.to_string();
+        let start = message.find(REQUEST_TIMED_OUT)? + REQUEST_TIMED_OUT.len();
+        let millis = message[start..].strip_suffix("ms")?.parse().ok()?;
+        Some(Duration::from_millis(millis))
+    }
+
+    /// Whether `error` is a `request_timeout` error
+    pub fn is_timeout(error: &TylError) -> bool {
+        timed_out_after(error).is_some()
+    }
+
+    /// Whether `error` is a network error; rate limits, timeouts and deadlines are network
+    /// errors too
+    pub fn is_network(error: &TylError) -> bool {
+        std::mem::discriminant(error) == std::mem::discriminant(&TylError::network(""))
+    }
+
+    /// Create a request cancelled error
+    pub fn request_cancelled() -> TylError {
+        TylError::internal("Inference request cancelled by caller")
+    }
+
+    /// Create a deadline exceeded error
+    pub fn deadline_exceeded(deadline: DateTime<Utc>) -> TylError {
+        TylError::network(format!(
+            "Inference request deadline {} exceeded",
+            deadline.to_rfc3339()
+        ))
+    }
+
+    /// Create a ledger store failure error
+    pub fn ledger_store_failed(message: impl Into<String>) -> TylError {
+        TylError::internal(format!("Usage ledger store failed: {}", message.into()))
+    }
+
+    /// Create an audit store failure error
+    pub fn audit_store_failed(message: impl Into<String>) -> TylError {
+        TylError::internal(format!("Audit store failed: {}", message.into()))
+    }
+
+    /// Create a job store failure error
+    pub fn job_store_failed(message: impl Into<String>) -> TylError {
+        TylError::internal(format!("Job store failed: {}", message.into()))
+    }
+
+    /// Create a budget exceeded error
+    pub fn budget_exceeded(scope: impl Into<String>, limit_usd: f64) -> TylError {
+        TylError::validation(
+            "budget",
+            format!(
+                "Budget of ${limit_usd:.2} exceeded for scope {}",
+                scope.into()
+            ),
+        )
+    }
+
+    /// Create a token budget exceeded error (shared workflow allocation consumed)
+    pub fn token_budget_exceeded(limit_tokens: usize, requested_tokens: usize) -> TylError {
+        TylError::validation(
+            "budget",
+            format!(
+                "Token budget of {limit_tokens} tokens exceeded ({requested_tokens} tokens requested)"
+            ),
+        )
+    }
+
+    /// Create a response too large error
+    pub fn response_too_large(max_bytes: usize) -> TylError {
+        TylError::validation(
+            "max_response_bytes",
+            format!("Inference response exceeds the {max_bytes} bytes limit"),
+        )
+    }
+
+    /// Create a queue full error (request rejected under load)
+    pub fn queue_full(capacity: usize) -> TylError {
+        TylError::network(format!(
+            "Inference queue is full ({capacity} requests waiting)"
+        ))
+    }
+
+    /// Create a service shutting down error (new requests are rejected)
+    pub fn service_shutting_down() -> TylError {
+        TylError::network("Inference service is shutting down")
+    }
+
+    /// Create an unknown job error (never submitted, or its result was already collected)
+    pub fn job_not_found(job_id: impl Into<String>) -> TylError {
+        TylError::validation("job_id", format!("Unknown inference job {}", job_id.into()))
+    }
+
+    /// Create a job not finished error (its result is not available yet)
+    pub fn job_not_finished(job_id: impl Into<String>) -> TylError {
+        TylError::validation(
+            "job_id",
+            format!("Inference job {} has not finished", job_id.into()),
+        )
+    }
+
+    /// Create a request aborted error
+    pub fn request_aborted(reason: impl Into<String>) -> TylError {
+        TylError::internal(format!("Inference request aborted: {}", reason.into()))
+    }
+
+    /// Create an encryption failure error (sealing or opening an encrypted field)
+    pub fn encryption_failed(field: impl Into<String>) -> TylError {
+        TylError::internal(format!(
+            "Failed to encrypt or decrypt field {}",
+            field.into()
+        ))
+    }
+
+    /// Create a content blocked error (a prompt or response flagged by moderation)
+    pub fn content_blocked(stage: impl Into<String>, categories: &[String]) -> TylError {
+        let stage = stage.into();
+        let message = format!(
+            "The {stage} was blocked by moderation ({})",
+            categories.join(", ")
+        );
+        TylError::validation(stage, message)
+    }
+
+    /// Create a payload too large error (a rendered prompt over the configured size)
+    pub fn payload_too_large(limit: usize, actual: usize, unit: &str) -> TylError {
+        TylError::validation(
+            "payload",
+            format!("Payload too large: {actual} {unit} exceeds the limit of {limit} {unit}"),
+        )
+    }
+
+    /// Create a prompt injection error (untrusted input that tries to instruct the model)
+    pub fn prompt_injection_detected(source: impl Into<String>, reasons: &[String]) -> TylError {
+        TylError::validation(
+            "prompt_injection",
+            format!(
+                "Possible prompt injection in {}: {}",
+                source.into(),
+                reasons.join("; ")
+            ),
+        )
+    }
+
+    /// Create a response validation error (e.g. the response does not match its schema)
+    pub fn response_validation_failed(template: impl Into<String>) -> TylError {
+        TylError::validation(
+            "response",
+            format!(
+                "Response failed validation for template {}",
+                template.into()
+            ),
+        )
+    }
+}
+
+/// Accept a configured SQL table name of letters, digits and underscores that does not start
+/// with a digit, since stores interpolate it into their queries; `kind` names the table in the
+/// error (e.g. "ledger")
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+pub(crate) fn valid_table_name(kind: &str, table: String) -> InferenceResult<String> {
+    let valid = !table.is_empty()
+        && !table.starts_with(|c: char| c.is_ascii_digit())
+        && table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(table)
+    } else {
+        Err(TylError::validation(
+            "table",
+            format!("Invalid {kind} table name: {table}"),
+        ))
+    }
 }
 
 /// Model types for inference optimization
@@ -182,17 +386,38 @@ pub enum ModelType {
     Fast,
     /// Creative writing and content generation
     Creative,
+    /// Understanding image attachments
+    Vision,
+    /// Pulling structured fields out of text
+    Extraction,
+    /// Condensing long inputs
+    Summarization,
 }
 
 impl ModelType {
+    /// Every model type
+    pub const ALL: [ModelType; 8] = [
+        ModelType::Coding,
+        ModelType::Reasoning,
+        ModelType::General,
+        ModelType::Fast,
+        ModelType::Creative,
+        ModelType::Vision,
+        ModelType::Extraction,
+        ModelType::Summarization,
+    ];
+
     /// Get optimal model for this type with OpenAI provider
     pub fn optimal_openai_model(&self) -> &'static str {
         match self {
-            ModelType::Coding => "gpt-4o",       // Code-optimized
-            ModelType::Reasoning => "gpt-4o",    // Best reasoning
-            ModelType::General => "gpt-4o-mini", // Balanced
-            ModelType::Fast => "gpt-3.5-turbo",  // Speed optimized
-            ModelType::Creative => "gpt-4o",     // Creative tasks
+            ModelType::Coding => "gpt-4o",             // Code-optimized
+            ModelType::Reasoning => "gpt-4o",          // Best reasoning
+            ModelType::General => "gpt-4o-mini",       // Balanced
+            ModelType::Fast => "gpt-3.5-turbo",        // Speed optimized
+            ModelType::Creative => "gpt-4o",           // Creative tasks
+            ModelType::Vision => "gpt-4o",             // Image understanding
+            ModelType::Extraction => "gpt-4o-mini",    // Structured output
+            ModelType::Summarization => "gpt-4o-mini", // Long inputs, short outputs
         }
     }
 
@@ -204,21 +429,66 @@ impl ModelType {
             ModelType::General => "claude-3-5-haiku-20241022", // Balanced
             ModelType::Fast => "claude-3-5-haiku-20241022",    // Speed optimized
             ModelType::Creative => "claude-3-5-sonnet-20241022", // Creative tasks
+            ModelType::Vision => "claude-3-5-sonnet-20241022", // Image understanding
+            ModelType::Extraction => "claude-3-5-haiku-20241022", // Structured output
+            ModelType::Summarization => "claude-3-5-haiku-20241022", // Long inputs, short outputs
         }
     }
 
     /// Get typical max tokens for this model type
     pub fn typical_max_tokens(&self) -> usize {
         match self {
-            ModelType::Coding => 4096,    // Longer code completions
-            ModelType::Reasoning => 8192, // Complex reasoning
-            ModelType::General => 2048,   // Standard responses
-            ModelType::Fast => 1024,      // Quick responses
-            ModelType::Creative => 4096,  // Creative content
+            ModelType::Coding => 4096,        // Longer code completions
+            ModelType::Reasoning => 8192,     // Complex reasoning
+            ModelType::General => 2048,       // Standard responses
+            ModelType::Fast => 1024,          // Quick responses
+            ModelType::Creative => 4096,      // Creative content
+            ModelType::Vision => 2048,        // Image descriptions
+            ModelType::Extraction => 2048,    // Extracted fields
+            ModelType::Summarization => 1024, // Summaries
+        }
+    }
+
+    /// Get the context window, in tokens, of the optimal OpenAI model for this type
+    pub fn typical_context_window(&self) -> usize {
+        match self {
+            ModelType::Fast => 16_385, // gpt-3.5-turbo
+            _ => 128_000,              // gpt-4o and gpt-4o-mini
         }
     }
+
+    /// Get typical request timeout for this model type
+    pub fn typical_timeout(&self) -> Duration {
+        match self {
+            ModelType::Coding => Duration::from_secs(60), // Longer code completions
+            ModelType::Reasoning => Duration::from_secs(120), // Complex reasoning
+            ModelType::General => Duration::from_secs(30), // Standard responses
+            ModelType::Fast => Duration::from_secs(5),    // Quick responses
+            ModelType::Creative => Duration::from_secs(60), // Creative content
+            ModelType::Vision => Duration::from_secs(60), // Image uploads and processing
+            ModelType::Extraction => Duration::from_secs(30), // Extracted fields
+            ModelType::Summarization => Duration::from_secs(60), // Long inputs
+        }
+    }
+}
+
+/// Scheduling priority of a request under contention
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default,
+)]
+pub enum Priority {
+    /// Background and batch work
+    Low,
+    /// Regular traffic
+    #[default]
+    Normal,
+    /// Interactive, user-facing traffic
+    High,
 }
 
+/// Temperature providers are sent for requests that do not set one
+pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
 /// Template-based inference request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InferenceRequest {
@@ -226,16 +496,54 @@ pub struct InferenceRequest {
     pub template: String,
     /// Parameters to replace in template
     pub parameters: HashMap<String, String>,
+    /// Images passed to vision-capable models alongside the rendered prompt
+    #[serde(default)]
+    pub attachments: Vec<MediaInput>,
     /// Model type for optimization
     pub model_type: ModelType,
     /// Optional model override
     pub model_override: Option<String>,
     /// Maximum tokens to generate
     pub max_tokens: Option<usize>,
-    /// Temperature for randomness (0.0 to 1.0)
+    /// Temperature for randomness (0.0 to 1.0); `None` leaves it to configured defaults and
+    /// then `DEFAULT_TEMPERATURE`
     pub temperature: Option<f32>,
+    /// Nucleus sampling: only tokens within this cumulative probability (0.0 to 1.0)
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Penalty for tokens by how often they already appeared (-2.0 to 2.0)
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    /// Penalty for tokens that already appeared at all (-2.0 to 2.0)
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    /// Sequences that end generation when produced
+    #[serde(default)]
+    pub stop: Vec<String>,
+    /// Sampling seed for reproducible generations, on providers that support one
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Number of completions to generate (`None` for one)
+    #[serde(default)]
+    pub candidates: Option<usize>,
+    /// Return token log probabilities with this many top alternatives per position
+    #[serde(default)]
+    pub logprobs: Option<usize>,
+    /// Maximum time to wait for a response (overrides the model type default)
+    pub timeout: Option<Duration>,
+    /// Absolute point in time after which the caller no longer needs a response
+    pub deadline: Option<DateTime<Utc>>,
+    /// Scheduling priority when requests queue for capacity (`None` derives it from the
+    /// caller's `RequestContext`)
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Client-supplied key identifying retries of the same logical request
+    pub idempotency_key: Option<String>,
     /// Request metadata
     pub metadata: HashMap<String, String>,
+    /// Render, validate and count tokens without calling the provider (see `dry_run`)
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 impl InferenceRequest {
@@ -247,11 +555,24 @@ impl InferenceRequest {
         Self {
             template: template.into(),
             parameters,
+            attachments: Vec::new(),
             model_type,
             model_override: None,
             max_tokens: Some(model_type.typical_max_tokens()),
-            temperature: Some(0.7),
+            temperature: None,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: Vec::new(),
+            seed: None,
+            candidates: None,
+            logprobs: None,
+            timeout: None,
+            deadline: None,
+            priority: None,
+            idempotency_key: None,
             metadata: HashMap::new(),
+            dry_run: false,
         }
     }
 
@@ -265,16 +586,147 @@ impl InferenceRequest {
         self
     }
 
+    /// Attach an image (or other media) for the model to consider with the prompt
+    pub fn with_attachment(mut self, attachment: MediaInput) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
     pub fn with_temperature(mut self, temperature: f32) -> Self {
         self.temperature = Some(temperature.clamp(0.0, 1.0));
         self
     }
 
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p.clamp(0.0, 1.0));
+        self
+    }
+
+    pub fn with_frequency_penalty(mut self, penalty: f32) -> Self {
+        self.frequency_penalty = Some(penalty.clamp(-2.0, 2.0));
+        self
+    }
+
+    pub fn with_presence_penalty(mut self, penalty: f32) -> Self {
+        self.presence_penalty = Some(penalty.clamp(-2.0, 2.0));
+        self
+    }
+
+    /// Add a stop sequence
+    pub fn with_stop(mut self, sequence: impl Into<String>) -> Self {
+        self.stop.push(sequence.into());
+        self
+    }
+
+    /// Replace the stop sequences
+    pub fn with_stop_sequences(
+        mut self,
+        sequences: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.stop = sequences.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Generate `n` completions in one call (see `InferenceResponse::candidates`)
+    pub fn with_candidates(mut self, n: usize) -> Self {
+        self.candidates = Some(n.max(1));
+        self
+    }
+
+    /// Number of completions requested
+    pub fn candidate_count(&self) -> usize {
+        self.candidates.unwrap_or(1)
+    }
+
+    /// Ask for token log probabilities with up to `top_alternatives` alternatives per token
+    pub fn with_logprobs(mut self, top_alternatives: usize) -> Self {
+        self.logprobs = Some(top_alternatives);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: DateTime<Utc>) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set the priority explicitly, overriding the caller context
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Priority used for scheduling: the explicit priority, else the hint of the current
+    /// `RequestContext`, else `Priority::Normal`
+    pub fn effective_priority(&self) -> Priority {
+        self.priority
+            .or_else(|| RequestContext::current().map(|context| context.priority_hint()))
+            .unwrap_or_default()
+    }
+
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
     pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
         self.metadata.insert(key.into(), value.into());
         self
     }
 
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Time left until the deadline (zero once it has passed), `None` without a deadline
+    pub fn remaining_time(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| (deadline - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+    }
+
+    /// Whether the deadline has already passed
+    pub fn is_past_deadline(&self) -> bool {
+        self.remaining_time() == Some(Duration::ZERO)
+    }
+
+    /// Whether an attempt expected to take `expected` can finish before the deadline
+    ///
+    /// Retry and failover decorators should check this before scheduling another attempt.
+    pub fn can_complete_within(&self, expected: Duration) -> bool {
+        self.remaining_time()
+            .map_or(true, |remaining| remaining >= expected)
+    }
+
+    /// Timeout for a single attempt: the smallest of `timeout`, the time left until the deadline
+    /// and the time left until the deadline of the current `RequestContext`
+    ///
+    /// Adapters use this as their per-attempt HTTP timeout.
+    pub fn attempt_timeout(&self) -> Option<Duration> {
+        [
+            self.timeout,
+            self.remaining_time(),
+            RequestContext::current_remaining_time(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Temperature to send to the provider: `temperature`, or `DEFAULT_TEMPERATURE` when unset
+    pub fn effective_temperature(&self) -> f32 {
+        self.temperature.unwrap_or(DEFAULT_TEMPERATURE)
+    }
+
     /// Process template with parameters to create the final prompt
     pub fn render_template(&self) -> String {
         let mut rendered = self.template.clone();
@@ -287,7 +739,7 @@ impl InferenceRequest {
 }
 
 /// Token usage information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
@@ -315,6 +767,16 @@ pub struct ResponseMetadata {
     pub processing_time_ms: u64,
     /// Response timestamp
     pub created_at: DateTime<Utc>,
+    /// Seed the provider sampled with
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Provider's identifier of the backend configuration (e.g. OpenAI `system_fingerprint`);
+    /// generations with the same seed are only reproducible while it stays the same
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// Token log probabilities, when requested and supported by the adapter
+    #[serde(default)]
+    pub logprobs: Option<Logprobs>,
     /// Additional metadata
     pub metadata: HashMap<String, String>,
 }
@@ -326,28 +788,140 @@ impl ResponseMetadata {
             token_usage,
             processing_time_ms,
             created_at: Utc::now(),
+            seed: None,
+            system_fingerprint: None,
+            logprobs: None,
             metadata: HashMap::new(),
         }
     }
 
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_system_fingerprint(mut self, fingerprint: impl Into<String>) -> Self {
+        self.system_fingerprint = Some(fingerprint.into());
+        self
+    }
+
+    pub fn with_logprobs(mut self, logprobs: Logprobs) -> Self {
+        self.logprobs = Some(logprobs);
+        self
+    }
+
     pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
         self.metadata.insert(key.into(), value.into());
         self
     }
 }
 
+/// One of several completions generated for a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+    pub index: usize,
+    /// Generated JSON content
+    pub content: serde_json::Value,
+    /// Token usage of this completion (the prompt is shared by all candidates)
+    pub token_usage: TokenUsage,
+    /// Why generation stopped (e.g. "stop", "length")
+    pub finish_reason: Option<String>,
+    /// Token log probabilities of this completion
+    #[serde(default)]
+    pub logprobs: Option<Logprobs>,
+}
+
+impl Candidate {
+    pub fn new(index: usize, content: serde_json::Value, token_usage: TokenUsage) -> Self {
+        Self {
+            index,
+            content,
+            token_usage,
+            finish_reason: None,
+            logprobs: None,
+        }
+    }
+
+    pub fn with_finish_reason(mut self, finish_reason: impl Into<String>) -> Self {
+        self.finish_reason = Some(finish_reason.into());
+        self
+    }
+
+    pub fn with_logprobs(mut self, logprobs: Logprobs) -> Self {
+        self.logprobs = Some(logprobs);
+        self
+    }
+}
+
 /// Inference response containing JSON content and metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InferenceResponse {
-    /// Generated JSON content
+    /// Generated JSON content (of the first candidate when several were requested)
     pub content: serde_json::Value,
     /// Response metadata
     pub metadata: ResponseMetadata,
+    /// Every completion, ordered by index, when more than one was requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<Candidate>,
 }
 
 impl InferenceResponse {
     pub fn new(content: serde_json::Value, metadata: ResponseMetadata) -> Self {
-        Self { content, metadata }
+        Self {
+            content,
+            metadata,
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Response carrying several completions
+    ///
+    /// `content` (and `metadata.logprobs`) are the first candidate's; the token usage counts the shared prompt once plus
+    /// the completion tokens of every candidate.
+    pub fn from_candidates(
+        mut candidates: Vec<Candidate>,
+        model: String,
+        processing_time_ms: u64,
+    ) -> InferenceResult<Self> {
+        candidates.sort_by_key(|candidate| candidate.index);
+        let first = candidates
+            .first()
+            .ok_or_else(|| inference_errors::generation_failed("No candidates generated"))?;
+        let prompt_tokens = candidates
+            .iter()
+            .map(|candidate| candidate.token_usage.prompt_tokens)
+            .max()
+            .unwrap_or(0);
+        let completion_tokens = candidates
+            .iter()
+            .map(|candidate| candidate.token_usage.completion_tokens)
+            .sum();
+        let mut metadata = ResponseMetadata::new(
+            model,
+            TokenUsage::new(prompt_tokens, completion_tokens),
+            processing_time_ms,
+        );
+        if let Some(finish_reason) = &first.finish_reason {
+            metadata = metadata.with_metadata("finish_reason", finish_reason.clone());
+        }
+        metadata.logprobs = first.logprobs.clone();
+        Ok(Self {
+            content: first.content.clone(),
+            metadata,
+            candidates,
+        })
+    }
+
+    /// Contents of every candidate, or just `content` for single-completion responses
+    pub fn candidate_contents(&self) -> Vec<&serde_json::Value> {
+        if self.candidates.is_empty() {
+            vec![&self.content]
+        } else {
+            self.candidates
+                .iter()
+                .map(|candidate| &candidate.content)
+                .collect()
+        }
     }
 
     /// Create response with string content (will be converted to JSON string value)
@@ -357,10 +931,10 @@ impl InferenceResponse {
         token_usage: TokenUsage,
         processing_time_ms: u64,
     ) -> Self {
-        Self {
-            content: serde_json::Value::String(content),
-            metadata: ResponseMetadata::new(model, token_usage, processing_time_ms),
-        }
+        Self::new(
+            serde_json::Value::String(content),
+            ResponseMetadata::new(model, token_usage, processing_time_ms),
+        )
     }
 
     /// Try to parse content as JSON, fallback to string if parsing fails
@@ -375,10 +949,10 @@ impl InferenceResponse {
             Err(_) => serde_json::Value::String(content),
         };
 
-        Self {
-            content: json_content,
-            metadata: ResponseMetadata::new(model, token_usage, processing_time_ms),
-        }
+        Self::new(
+            json_content,
+            ResponseMetadata::new(model, token_usage, processing_time_ms),
+        )
     }
 }
 
@@ -388,22 +962,726 @@ pub trait InferenceService: Send + Sync {
     /// Generate inference response from template and parameters
     async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse>;
 
+    /// Generate inference response, aborting early when `cancellation` is triggered
+    ///
+    /// The default implementation drops the in-flight `infer` future on cancellation, which
+    /// aborts the underlying HTTP request for adapters built on async HTTP clients.
+    async fn infer_with_cancellation(
+        &self,
+        request: InferenceRequest,
+        cancellation: CancellationToken,
+    ) -> InferenceResult<InferenceResponse> {
+        cancellation::run_until_cancelled(&cancellation, self.infer(request)).await
+    }
+
+    /// Generate a response as a stream of chunks
+    ///
+    /// Streams must apply backpressure: adapters read from the provider connection only when
+    /// the stream is polled, or push through `InferenceStream::channel`, so a slow consumer pauses
+    /// the socket instead of buffering chunks without bound. The default implementation yields
+    /// the complete `infer` response as a single chunk.
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        Ok(InferenceStream::from_response(self.infer(request).await?))
+    }
+
+    /// Generate responses for several requests, returned in the order of `requests`
+    ///
+    /// Adapters that decode a batch more cheaply than its requests one by one (local GPU
+    /// backends) override this; the default runs `infer` for every request concurrently.
+    /// Decorators that only adjust requests or observe responses forward batches to the service
+    /// they wrap; those that hold a slot per request or retry, hedge, cache or charge each
+    /// request answer the requests of a batch separately.
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        ensemble::JoinAll::new(requests.into_iter().map(|request| {
+            Box::pin(self.infer(request)) as ensemble::BoxedFuture<'_, InferenceResult<_>>
+        }))
+        .await
+    }
+
+    /// Whether `submit`, `status` and `result` are backed by a queue rather than run inline
+    ///
+    /// Queue-backed adapters return `true`; decorators return their inner service's answer.
+    fn handles_jobs(&self) -> bool {
+        false
+    }
+
+    /// Start `request` without waiting for the response, see `jobs`
+    ///
+    /// Queue-backed adapters override `submit`, `status` and `result` together; the default
+    /// runs the request inline and keeps the outcome until `result` collects it.
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_inline(self, request).await
+    }
+
+    /// State of a submitted job
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::inline_status(self, job)
+    }
+
+    /// Collect the outcome of a finished job
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::take_inline(self, job)
+    }
+
     /// Check if service is healthy
     async fn health_check(&self) -> InferenceResult<HealthCheckResult>;
 
     /// Get supported models
     fn supported_models(&self) -> Vec<String>;
 
+    /// List the models the provider currently serves
+    ///
+    /// Adapters with a models endpoint query it (caching the result); the default returns
+    /// `supported_models`.
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        Ok(self.supported_models())
+    }
+
     /// Count tokens in text (approximate)
     fn count_tokens(&self, text: &str) -> InferenceResult<usize>;
 }
 
+/// Boxed services (e.g. adapters selected at runtime) can be wrapped by decorators
+#[async_trait]
+impl<T: InferenceService + ?Sized> InferenceService for Box<T> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        (**self).infer(request).await
+    }
+
+    async fn infer_with_cancellation(
+        &self,
+        request: InferenceRequest,
+        cancellation: CancellationToken,
+    ) -> InferenceResult<InferenceResponse> {
+        (**self)
+            .infer_with_cancellation(request, cancellation)
+            .await
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        (**self).infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        (**self).infer_batch(requests).await
+    }
+
+    fn handles_jobs(&self) -> bool {
+        (**self).handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        (**self).submit(request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        (**self).status(job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        (**self).result(job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        (**self).health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        (**self).supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        (**self).list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        (**self).count_tokens(text)
+    }
+}
+
+#[async_trait]
+impl<T: InferenceService + ?Sized> InferenceService for std::sync::Arc<T> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        (**self).infer(request).await
+    }
+
+    async fn infer_with_cancellation(
+        &self,
+        request: InferenceRequest,
+        cancellation: CancellationToken,
+    ) -> InferenceResult<InferenceResponse> {
+        (**self)
+            .infer_with_cancellation(request, cancellation)
+            .await
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        (**self).infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        (**self).infer_batch(requests).await
+    }
+
+    fn handles_jobs(&self) -> bool {
+        (**self).handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        (**self).submit(request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        (**self).status(job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        (**self).result(job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        (**self).health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        (**self).supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        (**self).list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        (**self).count_tokens(text)
+    }
+}
+
+// Layers composing decorators into declarative stacks
+pub mod layer;
+
+pub use layer::{layer_fn, Identity, InferenceLayer, LayerFn, ServiceBuilder, Stack};
+
+// Request, response and error hooks around any service
+pub mod hooks;
+
+pub use hooks::{ErrorHook, HookContext, HookedService, Hooks, RequestHook, ResponseHook};
+
+// Cancellation tokens for in-flight requests
+pub mod cancellation;
+
+pub use cancellation::CancellationToken;
+
+// Ambient caller context for priority hints
+pub mod context;
+
+pub use context::{RequestContext, RequestOrigin};
+
+// Prompt compression helpers
+pub mod compression;
+
+pub use compression::TruncationBoundary;
+
+// Canonical JSON for cache keys and content hashing
+pub mod canonical;
+
+// Streaming chunk types and candidate assembly
+pub mod streaming;
+
+pub use streaming::{
+    AssembledCandidate, CandidateAssembler, ChunkSender, InferenceStream, StreamChunk, StreamEnd,
+    StreamSummary,
+};
+
+// Server-sent events parsing for streaming adapters
+pub mod sse;
+
+pub use sse::{SseEvent, SseParser};
+
+// Transport-agnostic streaming for message-oriented backends
+pub mod transport;
+
+pub use transport::{
+    FrameCodec, JsonFrameCodec, StreamingTransport, TransportMessage, TransportService,
+    TransportStream,
+};
+
+// Prompt templates with frontmatter configuration
+pub mod template;
+
+pub use template::{
+    PromptTemplate, TemplateFrontmatter, TemplateRegistry, TemplateTestCase, TemplateTestReport,
+};
+
+// Response post-processing plugins
+pub mod postprocess;
+
+pub use postprocess::{PostProcessingService, PostProcessor, PostProcessorRegistry};
+
+// Model pricing for cost accounting
+pub mod pricing;
+
+pub use pricing::{CostEstimate, ModelPricing, PricingSource, PricingTable, SharedPricing};
+
+// Usage and cost ledger with pluggable persistence
+pub mod ledger;
+
+pub use ledger::{InMemoryLedgerStore, LedgerService, LedgerStore, UsageRecord, UsageTotals};
+
+// Aggregated usage queries grouped by model, model type, tenant and time bucket
+pub mod usage_summary;
+
+pub use usage_summary::{UsageDimension, UsageFilter, UsageGroup, UsageSummary};
+
+// CSV and Parquet exports of aggregated ledger usage
+pub mod usage_export;
+
+pub use usage_export::{ExportFormat, UsageExportRow, UsageExporter};
+
+// Spend budgets with threshold alerts
+pub mod budget;
+
+pub use budget::{
+    BudgetAlert, BudgetAlertHandler, BudgetLimit, BudgetPeriod, BudgetService, WebhookAlertHandler,
+};
+
+// Token budgets shared across the requests of a workflow
+pub mod token_budget;
+
+pub use token_budget::{
+    TokenBudget, TokenBudgetLayer, TokenBudgetService, TOKEN_BUDGET_REMAINING_METADATA_KEY,
+};
+
+// Speculative fast-model-first execution
+pub mod speculative;
+
+pub use speculative::{AnswerValidator, SpeculativeService};
+
+// Adaptive temperature based on validation failure history
+pub mod adaptive;
+
+pub use adaptive::AdaptiveTemperatureService;
+
+// Response size limits with overflow storage
+pub mod response_store;
+
+pub use response_store::{
+    FileResponseStore, OverflowMode, ResponseHandle, ResponseStore, SizeLimitedService,
+};
+
+// Credentials providers for adapters
+pub mod credentials;
+
+pub use credentials::{
+    CachedCredentials, Credentials, CredentialsProvider, EnvCredentials, StaticCredentials,
+};
+
+// TLS settings (private root CAs, client certificates) for adapters
+pub mod tls;
+
+pub use tls::{ClientIdentity, TlsConfig};
+
+// HTTP transport injection and HTTP client adapter
+pub mod http_client;
+
+pub use http_client::{HttpInferenceClient, HttpMethod, HttpRequest, HttpResponse, HttpTransport};
+
+// Provider rate-limit errors and Retry-After style reset hints
+pub mod rate_limit;
+
+pub use rate_limit::{retry_after_hint, RateLimitError, RateLimitState, RATE_LIMIT_HEALTH_KEY};
+
+// Backoff policies for retries
+pub mod backoff;
+
+pub use backoff::{
+    BackoffPolicy, DecorrelatedJitterBackoff, ExponentialBackoff, FibonacciBackoff, FixedBackoff,
+};
+
+// Pluggable request authentication and signing for adapters
+pub mod signing;
+
+pub use signing::{AuthSigner, BearerSigner, HmacSigner, SigV4Signer};
+
+// OAuth2 client-credentials tokens for adapters
+pub mod oauth2;
+
+pub use oauth2::{OAuth2Credentials, AZURE_COGNITIVE_SERVICES_SCOPE};
+
+// Configuration loading and service wiring
+pub mod config;
+
+pub use config::{
+    build_service, ConfiguredService, InferenceConfig, InferenceProvider, MODEL_ALIAS_METADATA_KEY,
+};
+
+// Ensembles across multiple backends
+pub mod ensemble;
+
+pub use ensemble::{
+    EnsembleAggregator, EnsembleCandidate, EnsembleInferenceService, JudgeAggregator,
+    MajorityVoteAggregator,
+};
+
+// Audit records of executed requests and the audit decorator
+pub mod audit;
+
+pub use audit::{
+    AuditQuery, AuditRecord, AuditedService, InMemoryAuditSink, InferenceAuditSink,
+    USER_METADATA_KEY,
+};
+
+// Replay of audited requests against new template versions
+pub mod replay;
+
+pub use replay::{FieldDiff, ReplayComparison, ReplaySide, Replayer};
+
+// Degraded mode answering from an FAQ store during outages
+pub mod degraded;
+
+pub use degraded::{
+    cosine_similarity, DegradedModeService, FaqEntry, FaqStore, TextEmbedder, DEGRADED_METADATA_KEY,
+};
+
+// GPU and host resource telemetry for local adapters
+pub mod telemetry;
+
+pub use telemetry::{
+    ResourceProbe, ResourceTelemetry, TelemetryService, LOADED_MODELS_METADATA_KEY,
+    QUEUE_DEPTH_METADATA_KEY, SATURATED_METADATA_KEY, VRAM_TOTAL_METADATA_KEY,
+    VRAM_USED_METADATA_KEY,
+};
+
+// Quantization and context-length variants of local models
+pub mod variants;
+
+pub use variants::{
+    ModelVariant, VariantService, CONTEXT_LENGTH_METADATA_KEY, QUANTIZATION_METADATA_KEY,
+};
+
+// Prompt-boundary handling for raw completion backends
+pub mod boundary;
+
+pub use boundary::{BosHandling, PreparedPrompt, PromptBoundary, Tokenizer};
+
+// Dry-run responses for requests with `dry_run` set
+pub mod dry_run;
+
+pub use dry_run::{dry_run_response, DryRunReport, DRY_RUN_METADATA_KEY};
+
+// Inference lifecycle events for metrics, audit and integrations
+pub mod events;
+
+pub use events::{
+    EventBus, EventLayer, EventService, EventSubscriber, InferenceEvent, REQUEST_ID_METADATA_KEY,
+};
+
+// Token log probabilities of generated text
+pub mod logprobs;
+
+pub use logprobs::{Logprobs, TokenLogprob, TopLogprob};
+
+// Self-consistency voting over repeated samples
+pub mod self_consistency;
+
+pub use self_consistency::{AnswerExtractor, SelfConsistencyService};
+
+// Embeddings port and provider adapters
+pub mod embeddings;
+
+pub use embeddings::{
+    EmbeddingResponse, EmbeddingService, OllamaEmbeddingService, OpenAiEmbeddingService,
+};
+
+// Asynchronous submit/status/result jobs
+pub mod jobs;
+
+pub use jobs::{JobId, JobStatus};
+
+// Durable job queue with pluggable persistence
+pub mod job_queue;
+
+pub use job_queue::{InMemoryJobStore, JobRunner, JobStore, QueuedJob, QueuedJobState};
+
+// Offline batch jobs and the OpenAI Batch API adapter
+pub mod batch_jobs;
+
+pub use batch_jobs::{
+    BatchJob, BatchJobService, BatchJobStatus, BatchRequest, BatchRequestCounts, BatchResult,
+    BatchWatcher, OpenAiBatchService, BATCH_JOB_METADATA_KEY,
+};
+
+// Content moderation guardrails
+pub mod moderation;
+
+pub use moderation::{
+    GuardAction, GuardedInferenceService, ModerationResult, ModerationService,
+    OpenAiModerationService, MODERATION_CATEGORIES_METADATA_KEY, MODERATION_FLAGGED_METADATA_KEY,
+};
+
+// PII redaction before requests leave the process
+pub mod pii;
+
+pub use pii::{
+    PiiRecognizer, PiiRedactionService, PiiRedactor, PiiVault, RegexRecognizer,
+    PII_REDACTED_METADATA_KEY,
+};
+
+// Prompt-injection detection on untrusted input
+pub mod injection;
+
+pub use injection::{
+    screen_context, HeuristicInjectionDetector, InjectionAction, InjectionFinding,
+    InjectionGuardService, PromptInjectionDetector, PROMPT_INJECTION_METADATA_KEY,
+    PROMPT_INJECTION_SCORE_METADATA_KEY,
+};
+
+// Output content filtering with configurable policies
+pub mod output_filter;
+
+pub use output_filter::{
+    FilterAction, FilterVerdict, OutputFilterPolicy, OutputFilterService, OutputValidator,
+    OUTPUT_FILTER_DECISION_METADATA_KEY, OUTPUT_FILTER_RULES_METADATA_KEY,
+};
+
+// Structured responses validated against a JSON schema
+pub mod schema;
+
+pub use schema::{
+    infer_typed, infer_typed_with_response, ResponseSchema, REPAIR_TEMPLATE,
+    SCHEMA_ATTEMPT_TOKENS_METADATA_KEY, SCHEMA_COERCIONS_METADATA_KEY,
+};
+
+// Typed prompt structs
+pub mod prompt;
+
+pub use prompt::Prompt;
+
+#[cfg(feature = "derive")]
+pub use tyl_llm_inference_derive::{template, Prompt};
+
+// Token-aware text chunking
+pub mod chunking;
+
+pub use chunking::{ApproximateTokenCounter, ChunkBoundary, TextChunk, TextChunker, TokenCounter};
+
+// Document loaders producing clean text from plain text, Markdown, HTML and PDF
+pub mod loaders;
+
+#[cfg(feature = "pdf")]
+pub use loaders::pdf_to_text;
+pub use loaders::{clean_text, html_to_text, markdown_to_text, Document, DocumentFormat};
+
+// Image and media attachments of requests
+pub mod media;
+
+pub use media::{MediaInput, MediaSource};
+
+// Request validation before dispatch
+pub mod validation;
+
+pub use validation::{
+    RequestLimitService, RequestLimits, DEFAULT_MAX_PARAMETER_BYTES, DEFAULT_MAX_PROMPT_BYTES,
+};
+
+// Map-reduce and summarization pipelines over long inputs
+pub mod pipeline;
+
+pub use pipeline::{map_reduce, summarize, MapReduceConfig, SummarizeOptions};
+
+// Retrieval-augmented generation with pluggable context providers
+pub mod rag;
+
+pub use rag::{
+    ContextProvider, RagInferenceService, RetrievedDocument, DEFAULT_MAX_CONTEXT_TOKENS,
+    RETRIEVED_DOCUMENTS_METADATA_KEY,
+};
+
+// Conversation memory for multi-turn sessions
+pub mod conversation;
+
+pub use conversation::{
+    ConversationStore, ConversationTurn, InMemoryConversationStore, MemoryPolicy,
+    SessionInferenceService, TurnRole, DROPPED_TURNS_METADATA_KEY, SESSION_ID_METADATA_KEY,
+    SUMMARIZED_TURNS_METADATA_KEY,
+};
+
+// Agent loop calling registered tools
+pub mod agent;
+
+pub use agent::{
+    AgentRun, AgentRunner, AgentStopReason, FnTool, Tool, ToolCall, ToolDefinition, ToolResult,
+    DEFAULT_MAX_ITERATIONS, DEFAULT_MAX_PARALLEL_TOOLS,
+};
+
+// Model capability registry
+pub mod catalog;
+
+pub use catalog::{ModelCatalog, ModelFeature, ModelInfo, ModelListCache, DEFAULT_MODEL_LIST_TTL};
+
+// Provider auto-detection and multiplexing by model name
+pub mod providers;
+
+pub use providers::{
+    detect_provider, MultiProviderService, ProviderResolver, PROVIDER_METADATA_KEY,
+};
+
+// Deprecated model warnings and migration
+pub mod deprecation;
+
+pub use deprecation::{
+    DeprecationService, ModelDeprecation, ModelDeprecations, DEPRECATION_METADATA_KEY,
+};
+
+// Routing across multiple backends
+pub mod routing;
+
+pub use routing::{CostPolicy, RouteBackend, RoutingDecision, RoutingInferenceService};
+
+// Latency histograms and p50/p95/p99 snapshots per provider and model
+pub mod latency;
+
+pub use latency::{LatencyHistogram, LatencySnapshot, LatencyTracker, DEFAULT_LATENCY_WINDOW};
+
+// Rolling SLO compliance and error budgets per backend
+pub mod slo;
+
+pub use slo::{SloReport, SloTarget, SloTracker};
+
+// Metrics port for decorators and the request metrics decorator
+pub mod metrics;
+
+pub use metrics::{InMemoryMetrics, MetricsRecorder, MetricsService, NoopMetrics};
+
 // Mock adapter for testing and demonstration
 #[cfg(feature = "mock")]
 pub mod mock;
 
 #[cfg(feature = "mock")]
-pub use mock::MockInferenceService;
+pub use mock::{MockEmbeddingService, MockInferenceService, MockModerationService};
+
+// WebSocket streaming transport
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketTransport;
+
+// gRPC client adapter and server wrapper
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+#[cfg(feature = "grpc")]
+pub use grpc::{GrpcInferenceClient, GrpcInferenceServer};
+
+// Axum HTTP server facade
+#[cfg(feature = "http-server")]
+pub mod http_server;
+
+// Encryption envelope for persisted requests
+#[cfg(feature = "encryption")]
+pub mod encryption;
+
+#[cfg(feature = "encryption")]
+pub use encryption::{
+    EncryptedField, EncryptedRequest, KeyProvider, RequestEncryptor, StaticKeyProvider,
+};
+
+// Typed structured extraction from free text
+#[cfg(feature = "json-schema")]
+pub mod extract;
+
+#[cfg(feature = "json-schema")]
+pub use extract::{extract, extract_with, json_schema_for, ExtractOptions, EXTRACTION_TEMPLATE};
+
+// Agent tools with arguments schemas derived from Rust types
+#[cfg(feature = "json-schema")]
+pub mod typed_tool;
+
+#[cfg(feature = "json-schema")]
+pub use typed_tool::TypedTool;
+
+// Lifecycle management with graceful shutdown
+#[cfg(feature = "decorators")]
+pub mod managed;
+
+#[cfg(feature = "decorators")]
+pub use managed::{ManagedInferenceService, ShutdownReport};
+
+// Timeout decorator with per-model-type defaults
+#[cfg(feature = "decorators")]
+pub mod timeout;
+
+#[cfg(feature = "decorators")]
+pub use timeout::{TimeoutLayer, TimeoutService};
+
+// Concurrency limiting decorator with a bounded wait queue
+#[cfg(feature = "decorators")]
+pub mod concurrency;
+
+#[cfg(feature = "decorators")]
+pub use concurrency::{ConcurrencyLimitLayer, ConcurrencyLimitedService};
+
+// Adaptive concurrency limiting (AIMD)
+#[cfg(feature = "decorators")]
+pub mod aimd;
+
+#[cfg(feature = "decorators")]
+pub use aimd::{AdaptiveConcurrencyLayer, AdaptiveConcurrencyService};
+
+// Micro-batching of concurrent requests into infer_batch calls
+#[cfg(feature = "decorators")]
+pub mod batching;
+
+#[cfg(feature = "decorators")]
+pub use batching::{MicroBatchLayer, MicroBatchService, BATCH_SIZE_METADATA_KEY};
+
+// Priority scheduling decorator
+#[cfg(feature = "decorators")]
+pub mod priority;
+
+#[cfg(feature = "decorators")]
+pub use priority::PriorityQueueService;
+
+// Hedged requests across two backends
+#[cfg(feature = "decorators")]
+pub mod hedged;
+
+#[cfg(feature = "decorators")]
+pub use hedged::HedgedService;
+
+// Retry decorator honoring provider rate-limit hints
+#[cfg(feature = "decorators")]
+pub mod retry;
+
+#[cfg(feature = "decorators")]
+pub use retry::{RetryService, DEFAULT_MAX_ATTEMPTS, RETRY_ATTEMPTS_METADATA_KEY};
+
+// Idempotency keys and in-flight request deduplication
+#[cfg(feature = "decorators")]
+pub mod idempotency;
+
+#[cfg(feature = "decorators")]
+pub use idempotency::IdempotentService;
+
+// Warm pool of resident models for local adapters
+#[cfg(feature = "decorators")]
+pub mod warm_pool;
+
+#[cfg(feature = "decorators")]
+pub use warm_pool::{ModelLoader, WarmPool, WarmPoolService};
+
+// Stub services and transports shared by unit tests
+#[cfg(test)]
+mod test_support;
 
 #[cfg(test)]
 mod tests {
@@ -425,6 +1703,38 @@ mod tests {
         assert_eq!(request.parameters.get("name"), Some(&"Juan".to_string()));
     }
 
+    #[test]
+    fn test_request_sampling_controls() {
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        assert_eq!(request.top_p, None);
+        assert!(request.stop.is_empty());
+
+        let request = request
+            .with_top_p(1.5)
+            .with_frequency_penalty(0.5)
+            .with_presence_penalty(-3.0)
+            .with_stop("\n\n")
+            .with_stop("END");
+        assert_eq!(request.top_p, Some(1.0));
+        assert_eq!(request.frequency_penalty, Some(0.5));
+        assert_eq!(request.presence_penalty, Some(-2.0));
+        assert_eq!(request.stop, vec!["\n\n", "END"]);
+        assert_eq!(request.with_stop_sequences(["###"]).stop, vec!["###"]);
+
+        // Requests serialized before these fields existed still deserialize
+        let mut legacy = serde_json::to_value(InferenceRequest::new(
+            "Test",
+            HashMap::new(),
+            ModelType::Fast,
+        ))
+        .unwrap();
+        for field in ["top_p", "frequency_penalty", "presence_penalty", "stop"] {
+            legacy.as_object_mut().unwrap().remove(field);
+        }
+        let legacy: InferenceRequest = serde_json::from_value(legacy).unwrap();
+        assert!(legacy.stop.is_empty());
+    }
+
     #[test]
     fn test_template_rendering() {
         let mut params = HashMap::new();
@@ -441,6 +1751,50 @@ mod tests {
         assert_eq!(rendered, "Hello Juan, you are 30 years old!");
     }
 
+    #[test]
+    fn test_request_deadline() {
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        assert_eq!(request.remaining_time(), None);
+        assert!(request.can_complete_within(Duration::from_secs(3600)));
+        assert_eq!(request.attempt_timeout(), None);
+
+        let request = request
+            .with_timeout(Duration::from_secs(30))
+            .with_deadline(Utc::now() + chrono::Duration::seconds(10));
+        let remaining = request.remaining_time().unwrap();
+        assert!(remaining <= Duration::from_secs(10));
+        assert!(remaining > Duration::from_secs(5));
+        assert!(request.can_complete_within(Duration::from_secs(1)));
+        assert!(!request.can_complete_within(Duration::from_secs(20)));
+        assert!(request.attempt_timeout().unwrap() <= Duration::from_secs(10));
+
+        let expired = InferenceRequest::new("Test", HashMap::new(), ModelType::General)
+            .with_deadline(Utc::now() - chrono::Duration::seconds(1));
+        assert!(expired.is_past_deadline());
+        assert_eq!(expired.remaining_time(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_request_priority() {
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        assert_eq!(request.priority, None);
+        assert_eq!(request.effective_priority(), Priority::Normal);
+        assert!(Priority::High > Priority::Normal);
+        assert!(Priority::Normal > Priority::Low);
+
+        let request = request.with_priority(Priority::High);
+        assert_eq!(request.priority, Some(Priority::High));
+    }
+
+    #[test]
+    fn test_request_idempotency_key() {
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        assert_eq!(request.idempotency_key, None);
+
+        let request = request.with_idempotency_key("order-42");
+        assert_eq!(request.idempotency_key.as_deref(), Some("order-42"));
+    }
+
     #[test]
     fn test_model_type_optimal_models() {
         assert_eq!(ModelType::Coding.optimal_openai_model(), "gpt-4o");
@@ -449,6 +1803,17 @@ mod tests {
             ModelType::Reasoning.optimal_anthropic_model(),
             "claude-3-5-sonnet-20241022"
         );
+        assert_eq!(ModelType::Summarization.typical_max_tokens(), 1024);
+        assert_eq!(ModelType::Extraction.optimal_openai_model(), "gpt-4o-mini");
+
+        // Vision requests default to models accepting image attachments
+        let catalog = ModelCatalog::with_defaults();
+        for model in [
+            ModelType::Vision.optimal_openai_model(),
+            ModelType::Vision.optimal_anthropic_model(),
+        ] {
+            assert!(catalog.get(model).unwrap().supports(ModelFeature::Vision));
+        }
     }
 
     #[test]
@@ -471,6 +1836,35 @@ mod tests {
 
         let error = inference_errors::template_processing_failed("invalid placeholder");
         assert!(error.to_string().contains("Template processing failed"));
+
+        let error = inference_errors::request_timeout(Duration::from_millis(1500));
+        assert!(error.to_string().contains("timed out after 1500ms"));
+        assert_eq!(
+            inference_errors::timed_out_after(&error),
+            Some(Duration::from_millis(1500))
+        );
+        assert!(inference_errors::is_network(&error));
+        assert!(!inference_errors::is_timeout(&TylError::internal(
+            "Inference request timed out after 5ms"
+        )));
+        assert!(!inference_errors::is_timeout(&TylError::network(
+            "connection timed out"
+        )));
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast);
+        assert_eq!(request.timeout, None);
+
+        let request = request.with_timeout(Duration::from_secs(2));
+        assert_eq!(request.timeout, Some(Duration::from_secs(2)));
+
+        assert_eq!(ModelType::Fast.typical_timeout(), Duration::from_secs(5));
+        assert_eq!(
+            ModelType::Reasoning.typical_timeout(),
+            Duration::from_secs(120)
+        );
     }
 
     #[test]
@@ -546,5 +1940,13 @@ mod tests {
         assert_eq!(metadata.token_usage.total_tokens, 75);
         assert_eq!(metadata.processing_time_ms, 750);
         assert!(metadata.metadata.is_empty());
+        assert_eq!(metadata.seed, None);
+
+        let metadata = metadata
+            .with_seed(7)
+            .with_system_fingerprint("fp_44709d6fcb");
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["system_fingerprint"], "fp_44709d6fcb");
     }
 }
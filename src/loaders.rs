@@ -0,0 +1,314 @@
+//! Document loaders for context building
+//!
+//! Before a document can be bound to a template parameter it has to become plain text: Markdown
+//! syntax and HTML markup stripped, entities decoded, whitespace normalized. `Document` does that
+//! for plain text, Markdown, HTML and (with the `pdf` feature) PDF, and cuts the result into
+//! token-budgeted chunks with a `TextChunker`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::loaders::*;
+//! # use tyl_llm_inference_port::chunking::*;
+//! # use std::collections::HashMap;
+//! # const TEMPLATE: &str = "Summarize:\n{{section}}";
+//! # async fn example(service: impl InferenceService + 'static, mut params: HashMap<String, String>) -> TylResult<()> {
+//! let document = Document::load("docs/handbook.md")?;
+//! for chunk in document.chunks(&service, &TextChunker::new(800))? {
+//!     params.insert("section".to_string(), chunk.text);
+//!     service.infer(InferenceRequest::new(TEMPLATE, params.clone(), ModelType::Summarization)).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The format of a file is taken from its extension; unknown extensions load as plain text.
+
+use crate::chunking::{TextChunk, TextChunker, TokenCounter};
+use crate::*;
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Format of a document's source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    Text,
+    Markdown,
+    Html,
+    /// Requires the `pdf` feature
+    Pdf,
+}
+
+impl DocumentFormat {
+    /// Format of a file extension such as `md` or `htm`; `None` for unknown extensions
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "txt" | "text" => Some(Self::Text),
+            "md" | "markdown" => Some(Self::Markdown),
+            "html" | "htm" | "xhtml" => Some(Self::Html),
+            "pdf" => Some(Self::Pdf),
+            _ => None,
+        }
+    }
+}
+
+/// Cleaned text of a loaded document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Document {
+    /// Where the document came from, e.g. its path or URL
+    pub source: String,
+    pub format: DocumentFormat,
+    pub text: String,
+}
+
+impl Document {
+    /// Load a file, picking the format from its extension
+    pub fn load(path: impl AsRef<Path>) -> InferenceResult<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .map_err(|e| TylError::internal(format!("Cannot read {}: {e}", path.display())))?;
+        let format = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(DocumentFormat::from_extension)
+            .unwrap_or(DocumentFormat::Text);
+        Self::from_bytes(path.display().to_string(), format, &bytes)
+    }
+
+    /// Clean `bytes` of the given format; text formats are decoded as UTF-8, replacing invalid
+    /// sequences
+    pub fn from_bytes(
+        source: impl Into<String>,
+        format: DocumentFormat,
+        bytes: &[u8],
+    ) -> InferenceResult<Self> {
+        let text = match format {
+            DocumentFormat::Pdf => pdf_to_text(bytes)?,
+            _ => {
+                return Self::from_text(source, format, &String::from_utf8_lossy(bytes));
+            }
+        };
+        Ok(Self {
+            source: source.into(),
+            format,
+            text: clean_text(&text),
+        })
+    }
+
+    /// Clean text content; PDFs are binary and must go through `from_bytes`
+    pub fn from_text(
+        source: impl Into<String>,
+        format: DocumentFormat,
+        content: &str,
+    ) -> InferenceResult<Self> {
+        let text = match format {
+            DocumentFormat::Text => clean_text(content),
+            DocumentFormat::Markdown => markdown_to_text(content),
+            DocumentFormat::Html => html_to_text(content),
+            DocumentFormat::Pdf => {
+                return Err(TylError::validation(
+                    "format",
+                    "PDF documents must be loaded from bytes",
+                ))
+            }
+        };
+        Ok(Self {
+            source: source.into(),
+            format,
+            text,
+        })
+    }
+
+    /// Split the text into chunks measured with `counter`
+    pub fn chunks<C: TokenCounter + ?Sized>(
+        &self,
+        counter: &C,
+        chunker: &TextChunker,
+    ) -> InferenceResult<Vec<TextChunk>> {
+        chunker.split(counter, &self.text)
+    }
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("valid built-in regex"))
+}
+
+/// Normalize line endings and whitespace: runs of spaces collapse to one, lines are trimmed and
+/// consecutive blank lines collapse to one
+pub fn clean_text(text: &str) -> String {
+    static SPACES: OnceLock<Regex> = OnceLock::new();
+
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut cleaned = String::with_capacity(text.len());
+    let mut blank = false;
+    for line in text.lines() {
+        let line = regex(&SPACES, r"[\s\u{a0}]+").replace_all(line.trim(), " ");
+        if line.is_empty() {
+            blank = !cleaned.is_empty();
+            continue;
+        }
+        if !cleaned.is_empty() {
+            cleaned.push_str(if blank { "\n\n" } else { "\n" });
+        }
+        cleaned.push_str(&line);
+        blank = false;
+    }
+    cleaned
+}
+
+/// Plain text of a Markdown document: headings, emphasis, links, images, code fences, quotes
+/// and rules are reduced to their text
+pub fn markdown_to_text(markdown: &str) -> String {
+    static FENCE: OnceLock<Regex> = OnceLock::new();
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    static RULE: OnceLock<Regex> = OnceLock::new();
+    static QUOTE: OnceLock<Regex> = OnceLock::new();
+    static BULLET: OnceLock<Regex> = OnceLock::new();
+    static IMAGE: OnceLock<Regex> = OnceLock::new();
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    static STRONG: OnceLock<Regex> = OnceLock::new();
+    static STRONG_UNDERSCORE: OnceLock<Regex> = OnceLock::new();
+    static EMPHASIS: OnceLock<Regex> = OnceLock::new();
+    static CODE: OnceLock<Regex> = OnceLock::new();
+
+    let text = regex(&FENCE, r"(?m)^[ \t]*(```|~~~).*$").replace_all(markdown, "");
+    let text = regex(&HEADING, r"(?m)^[ \t]{0,3}#{1,6}[ \t]+(.*?)[ \t]*#*[ \t]*$")
+        .replace_all(&text, "$1");
+    let text = regex(&REFERENCE, r"(?m)^[ \t]{0,3}\[[^\]]+\]:[ \t]+\S+.*$").replace_all(&text, "");
+    let text = regex(&RULE, r"(?m)^[ \t]{0,3}([-*_][ \t]*){3,}$").replace_all(&text, "");
+    let text = regex(&QUOTE, r"(?m)^[ \t]{0,3}>[ \t]?").replace_all(&text, "");
+    let text = regex(&BULLET, r"(?m)^([ \t]*)[*+][ \t]+").replace_all(&text, "$1- ");
+    let text = regex(&IMAGE, r"!\[([^\]]*)\]\([^)]*\)").replace_all(&text, "$1");
+    let text = regex(&LINK, r"\[([^\]]+)\]\([^)]*\)").replace_all(&text, "$1");
+    let text = regex(&STRONG, r"\*\*([^*\n]+)\*\*").replace_all(&text, "$1");
+    let text = regex(&STRONG_UNDERSCORE, r"\b__([^_\n]+)__\b").replace_all(&text, "$1");
+    let text = regex(&EMPHASIS, r"\*([^*\s][^*\n]*)\*").replace_all(&text, "$1");
+    let text = regex(&CODE, r"`([^`\n]+)`").replace_all(&text, "$1");
+    // Inline HTML is allowed in Markdown
+    html_to_text(&text)
+}
+
+/// Plain text of an HTML document: scripts, styles, comments and the head are dropped, block
+/// elements become line breaks and entities are decoded
+pub fn html_to_text(html: &str) -> String {
+    static HIDDEN: OnceLock<Regex> = OnceLock::new();
+    static LIST_ITEM: OnceLock<Regex> = OnceLock::new();
+    static BLOCK: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    static ENTITY: OnceLock<Regex> = OnceLock::new();
+
+    let text = regex(
+        &HIDDEN,
+        r"(?is)<!--.*?-->|<(script|style|head|noscript|template)\b[^>]*>.*?</(script|style|head|noscript|template)\s*>",
+    )
+    .replace_all(html, "");
+    let text = regex(&LIST_ITEM, r"(?i)<li\b[^>]*>").replace_all(&text, "\n- ");
+    let text = regex(
+        &BLOCK,
+        r"(?i)</?(p|div|br|hr|h[1-6]|ul|ol|tr|table|section|article|header|footer|nav|aside|main|blockquote|pre|figure|dl|dt|dd)\b[^>]*>",
+    )
+    .replace_all(&text, "\n\n");
+    let text = regex(&TAG, r"<[^>]*>").replace_all(&text, "");
+    let text = regex(&ENTITY, r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").replace_all(
+        &text,
+        |captures: &regex::Captures| {
+            let entity = &captures[1];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            decoded.map_or_else(|| captures[0].to_string(), String::from)
+        },
+    );
+    clean_text(&text)
+}
+
+/// Text layer of a PDF
+#[cfg(feature = "pdf")]
+pub fn pdf_to_text(bytes: &[u8]) -> InferenceResult<String> {
+    pdf_extract::extract_text_from_mem(bytes)
+        .map_err(|e| TylError::validation("document", format!("Cannot read PDF: {e}")))
+}
+
+#[cfg(not(feature = "pdf"))]
+fn pdf_to_text(_bytes: &[u8]) -> InferenceResult<String> {
+    Err(TylError::configuration(
+        "PDF documents require the pdf feature",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::ApproximateTokenCounter;
+
+    #[test]
+    fn test_clean_text() {
+        assert_eq!(
+            clean_text("  Title\r\n\r\n\r\n\tBody   text \nmore\n\n"),
+            "Title\n\nBody text\nmore"
+        );
+    }
+
+    #[test]
+    fn test_markdown_to_text() {
+        let markdown = "# Install\n\nRun **cargo add** with `--features mock`.\n\n\
+            > See the [guide](https://example.com/guide) and ![diagram](d.png).\n\n\
+            * one\n* two\n\n---\n\n```rust\nlet x = 1;\n```\n\n[guide]: https://example.com\n\n\
+            Keep snake_case_names intact.";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "Install\n\nRun cargo add with --features mock.\n\n\
+             See the guide and diagram.\n\n- one\n- two\n\nlet x = 1;\n\n\
+             Keep snake_case_names intact."
+        );
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><title>T</title><style>p{}</style></head><body>\
+            <h1>Release &amp; notes</h1><!-- hidden --><script>alert(1)</script>\
+            <p>Fixed <b>three</b>&nbsp;bugs &lt;fast&gt; &#8212; &#x2713;</p>\
+            <ul><li>one</li><li>two</li></ul></body></html>";
+        assert_eq!(
+            html_to_text(html),
+            "Release & notes\n\nFixed three bugs <fast> \u{2014} \u{2713}\n\n- one\n- two"
+        );
+    }
+
+    #[test]
+    fn test_document_chunks() {
+        let document = Document::from_bytes(
+            "notes.html",
+            DocumentFormat::Html,
+            "<p>First paragraph.</p><p>Second paragraph.</p>".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(document.text, "First paragraph.\n\nSecond paragraph.");
+        let chunks = document
+            .chunks(&ApproximateTokenCounter, &TextChunker::new(5))
+            .unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].text, "Second paragraph.");
+
+        assert_eq!(
+            DocumentFormat::from_extension("MD"),
+            Some(DocumentFormat::Markdown)
+        );
+        assert!(Document::from_text("a.pdf", DocumentFormat::Pdf, "").is_err());
+        #[cfg(not(feature = "pdf"))]
+        assert!(Document::from_bytes("a.pdf", DocumentFormat::Pdf, b"%PDF").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Token log probabilities of generated text
+//!
+//! Requests ask for log probabilities with `InferenceRequest::with_logprobs`; adapters whose
+//! provider returns them fill `ResponseMetadata::logprobs` (and `Candidate::logprobs` for
+//! multi-candidate responses). Adapters that cannot leave them unset, so consumers must treat
+//! `None` as "unknown", not as "confident". The summary helpers are the usual inputs for
+//! confidence scoring and hallucination heuristics:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::logprobs::*;
+//! # async fn example(service: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! let response = service.infer(request.with_logprobs(5)).await?;
+//! if let Some(logprobs) = &response.metadata.logprobs {
+//!     let shaky = logprobs.low_confidence_tokens(-2.3); // below ~10% probability
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::*;
+
+/// A candidate token the model considered at a position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    /// Natural log of the token's probability
+    pub logprob: f64,
+}
+
+/// A generated token with its log probability and the most likely alternatives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    /// Natural log of the token's probability
+    pub logprob: f64,
+    /// Most likely tokens at this position, most likely first
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+impl TokenLogprob {
+    pub fn new(token: impl Into<String>, logprob: f64) -> Self {
+        Self {
+            token: token.into(),
+            logprob,
+            top_logprobs: Vec::new(),
+        }
+    }
+
+    pub fn with_alternative(mut self, token: impl Into<String>, logprob: f64) -> Self {
+        self.top_logprobs.push(TopLogprob {
+            token: token.into(),
+            logprob,
+        });
+        self
+    }
+
+    /// Probability of the token (0.0 to 1.0)
+    pub fn probability(&self) -> f64 {
+        self.logprob.exp()
+    }
+}
+
+/// Log probabilities of every generated token, in order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Logprobs {
+    pub tokens: Vec<TokenLogprob>,
+}
+
+impl Logprobs {
+    pub fn new(tokens: Vec<TokenLogprob>) -> Self {
+        Self { tokens }
+    }
+
+    /// Sum of the token log probabilities: log probability of the whole text
+    pub fn total_logprob(&self) -> f64 {
+        self.tokens.iter().map(|token| token.logprob).sum()
+    }
+
+    /// Average log probability per token, `None` without tokens
+    pub fn mean_logprob(&self) -> Option<f64> {
+        (!self.tokens.is_empty()).then(|| self.total_logprob() / self.tokens.len() as f64)
+    }
+
+    /// Perplexity of the generation (1.0 is fully confident), `None` without tokens
+    pub fn perplexity(&self) -> Option<f64> {
+        self.mean_logprob().map(|mean| (-mean).exp())
+    }
+
+    /// Least likely generated token
+    pub fn least_likely(&self) -> Option<&TokenLogprob> {
+        self.tokens
+            .iter()
+            .min_by(|a, b| a.logprob.total_cmp(&b.logprob))
+    }
+
+    /// Tokens generated with a log probability below `threshold`, in order
+    pub fn low_confidence_tokens(&self, threshold: f64) -> Vec<&TokenLogprob> {
+        self.tokens
+            .iter()
+            .filter(|token| token.logprob < threshold)
+            .collect()
+    }
+
+    /// The generated text
+    pub fn text(&self) -> String {
+        self.tokens
+            .iter()
+            .map(|token| token.token.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confidence_summaries() {
+        let logprobs = Logprobs::new(vec![
+            TokenLogprob::new("Paris", -0.01),
+            TokenLogprob::new(" is", -0.05),
+            TokenLogprob::new(" nice", -2.5).with_alternative(" big", -0.3),
+        ]);
+        assert_eq!(logprobs.text(), "Paris is nice");
+        assert!((logprobs.total_logprob() + 2.56).abs() < 1e-9);
+        assert!(logprobs.perplexity().unwrap() > 1.0);
+        assert_eq!(logprobs.least_likely().unwrap().token, " nice");
+        let shaky = logprobs.low_confidence_tokens(-1.0);
+        assert_eq!(shaky.len(), 1);
+        assert_eq!(shaky[0].top_logprobs[0].token, " big");
+
+        assert_eq!(Logprobs::default().perplexity(), None);
+    }
+}
@@ -0,0 +1,373 @@
+//! Lifecycle management for inference services
+//!
+//! `ManagedInferenceService` wraps any `InferenceService` and tracks in-flight requests so the
+//! service can be drained before the process exits (e.g. during a rolling deploy). A stream
+//! counts as in flight until it ends or is dropped.
+
+use crate::ensemble::{forward_batch, BoxedFuture};
+use crate::streaming::ChunkResult;
+use crate::*;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::sync::{watch, Notify};
+
+/// Outcome of a graceful shutdown
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    /// Requests that were in flight when shutdown started
+    pub in_flight_at_start: usize,
+    /// Requests still running at the deadline that were aborted
+    pub aborted: usize,
+}
+
+impl ShutdownReport {
+    /// Whether every in-flight request finished before the deadline
+    pub fn fully_drained(&self) -> bool {
+        self.aborted == 0
+    }
+}
+
+/// Shared lifecycle state between the service and its in-flight requests
+#[derive(Debug)]
+struct Lifecycle {
+    accepting: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    abort: watch::Sender<bool>,
+}
+
+impl Lifecycle {
+    fn new() -> Self {
+        let (abort, _) = watch::channel(false);
+        Self {
+            accepting: AtomicBool::new(true),
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+            abort,
+        }
+    }
+
+    /// Register a new in-flight request, rejecting it once shutdown has started
+    fn enter(self: &Arc<Self>) -> InferenceResult<InFlightGuard> {
+        // Increment before checking the flag so shutdown never misses a request
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlightGuard {
+            lifecycle: Arc::clone(self),
+        };
+
+        if self.accepting.load(Ordering::SeqCst) {
+            Ok(guard)
+        } else {
+            Err(inference_errors::service_shutting_down())
+        }
+    }
+
+    /// Resolves once shutdown aborts in-flight requests
+    fn aborted(&self) -> BoxedFuture<'static, ()> {
+        let mut abort = self.abort.subscribe();
+        Box::pin(async move {
+            if abort.wait_for(|aborted| *aborted).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        })
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Decrements the in-flight counter when a request finishes (or is dropped)
+struct InFlightGuard {
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.lifecycle.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.lifecycle.idle.notify_waiters();
+        }
+    }
+}
+
+fn aborted_error() -> TylError {
+    inference_errors::request_aborted("service shut down before the request completed")
+}
+
+/// Stream holding its request in flight until it ends, cut short by an abort
+struct ManagedStream {
+    stream: InferenceStream,
+    aborted: BoxedFuture<'static, ()>,
+    guard: Option<InFlightGuard>,
+}
+
+impl Stream for ManagedStream {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        let this = &mut *self;
+        if this.guard.is_none() {
+            return Poll::Ready(None);
+        }
+        if this.aborted.as_mut().poll(cx).is_ready() {
+            this.guard = None;
+            return Poll::Ready(Some(Err(aborted_error())));
+        }
+        let next = Pin::new(&mut this.stream).poll_next(cx);
+        if let Poll::Ready(None) = next {
+            this.guard = None;
+        }
+        next
+    }
+}
+
+/// Inference service wrapper supporting graceful shutdown and in-flight request draining
+#[derive(Debug)]
+pub struct ManagedInferenceService<S> {
+    inner: S,
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl<S: InferenceService> ManagedInferenceService<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            lifecycle: Arc::new(Lifecycle::new()),
+        }
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of requests currently being processed
+    pub fn in_flight(&self) -> usize {
+        self.lifecycle.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Whether new requests are still accepted
+    pub fn is_accepting(&self) -> bool {
+        self.lifecycle.accepting.load(Ordering::SeqCst)
+    }
+
+    /// Stop accepting new requests and wait for in-flight ones until `deadline`
+    ///
+    /// Requests still running at the deadline are aborted and fail with
+    /// `inference_errors::request_aborted`. Calling this more than once is harmless.
+    pub async fn shutdown(&self, deadline: Instant) -> ShutdownReport {
+        self.lifecycle.accepting.store(false, Ordering::SeqCst);
+        let in_flight_at_start = self.in_flight();
+
+        let deadline = tokio::time::Instant::from_std(deadline);
+        let aborted = match tokio::time::timeout_at(deadline, self.lifecycle.wait_idle()).await {
+            Ok(()) => 0,
+            Err(_) => {
+                let remaining = self.in_flight();
+                self.lifecycle.abort.send_replace(true);
+                remaining
+            }
+        };
+
+        ShutdownReport {
+            in_flight_at_start,
+            aborted,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for ManagedInferenceService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let _guard = self.lifecycle.enter()?;
+        let mut abort = self.lifecycle.abort.subscribe();
+
+        tokio::select! {
+            result = self.inner.infer(request) => result,
+            Ok(_) = abort.wait_for(|aborted| *aborted) => Err(aborted_error()),
+        }
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let guard = self.lifecycle.enter()?;
+        let mut aborted = self.lifecycle.aborted();
+        let stream = tokio::select! {
+            stream = self.inner.infer_stream(request) => stream?,
+            _ = &mut aborted => return Err(aborted_error()),
+        };
+        Ok(InferenceStream::new(ManagedStream {
+            stream,
+            aborted,
+            guard: Some(guard),
+        }))
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        let count = requests.len();
+        let mut abort = self.lifecycle.abort.subscribe();
+        let batch = forward_batch(&self.inner, requests, |_| self.lifecycle.enter());
+
+        tokio::select! {
+            results = batch => results
+                .into_iter()
+                .map(|result| result.and_then(|(_guard, response)| response))
+                .collect(),
+            Ok(_) = abort.wait_for(|aborted| *aborted) => (0..count)
+                .map(|_| Err(aborted_error()))
+                .collect(),
+        }
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let in_flight = serde_json::Value::Number(serde_json::Number::from(self.in_flight()));
+
+        if !self.is_accepting() {
+            return Ok(
+                HealthCheckResult::new(HealthStatus::unhealthy("Service is shutting down"))
+                    .with_metadata("in_flight", in_flight),
+            );
+        }
+
+        Ok(self
+            .inner
+            .health_check()
+            .await?
+            .with_metadata("in_flight", in_flight))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Hello!", HashMap::new(), ModelType::General)
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_requests() {
+        let service = Arc::new(ManagedInferenceService::new(
+            MockInferenceService::new().with_latency(50),
+        ));
+
+        let in_flight = {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move { service.infer(request()).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(service.in_flight(), 1);
+
+        let report = service
+            .shutdown(Instant::now() + Duration::from_secs(1))
+            .await;
+
+        assert_eq!(report.in_flight_at_start, 1);
+        assert!(report.fully_drained());
+        assert!(in_flight.await.unwrap().is_ok());
+        assert_eq!(service.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_rejects_new_requests() {
+        let service = ManagedInferenceService::new(MockInferenceService::new().with_latency(0));
+
+        let report = service.shutdown(Instant::now()).await;
+        assert_eq!(report.in_flight_at_start, 0);
+        assert!(!service.is_accepting());
+
+        let error = service.infer(request()).await.unwrap_err();
+        assert!(error.to_string().contains("shutting down"));
+        assert_eq!(service.in_flight(), 0);
+
+        let health = service.health_check().await.unwrap();
+        assert!(!health.status.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_aborts_requests_past_deadline() {
+        let service = Arc::new(ManagedInferenceService::new(
+            MockInferenceService::new().with_latency(5_000),
+        ));
+
+        let in_flight = {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move { service.infer(request()).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let report = service
+            .shutdown(Instant::now() + Duration::from_millis(20))
+            .await;
+        assert_eq!(report.aborted, 1);
+
+        let error = in_flight.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("aborted"));
+    }
+
+    #[tokio::test]
+    async fn test_streams_stay_in_flight_until_they_end() {
+        let service = ManagedInferenceService::new(MockInferenceService::new().with_latency(0));
+        let stream = service.infer_stream(request()).await.unwrap();
+        assert_eq!(service.in_flight(), 1);
+        assert_eq!(stream.assemble().await.unwrap().len(), 1);
+        assert_eq!(service.in_flight(), 0);
+
+        // An unconsumed stream holds up the drain and is cut short at the deadline
+        let mut stream = service.infer_stream(request()).await.unwrap();
+        let report = service
+            .shutdown(Instant::now() + Duration::from_millis(20))
+            .await;
+        assert_eq!(report.aborted, 1);
+        let error = stream.next_chunk().await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("aborted"));
+        assert!(stream.next_chunk().await.is_none());
+        assert_eq!(service.in_flight(), 0);
+    }
+}
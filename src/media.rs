@@ -0,0 +1,148 @@
+//! Media attachments of inference requests
+//!
+//! Vision-capable models take images alongside the rendered prompt. A `MediaInput` is either
+//! inline bytes or a URL the provider fetches, tagged with its MIME type:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::media::*;
+//! # use tyl_llm_inference_port::catalog::*;
+//! # use std::collections::HashMap;
+//! # async fn example(catalog: ModelCatalog) -> Result<(), Box<dyn std::error::Error>> {
+//! let request = InferenceRequest::new("What is in this picture?", HashMap::new(), ModelType::General)
+//!     .with_model("gpt-4o")
+//!     .with_attachment(MediaInput::from_bytes("image/png", std::fs::read("chart.png")?))
+//!     .with_attachment(MediaInput::from_url("image/jpeg", "https://example.com/photo.jpg"));
+//! catalog.validate_request(&request)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Inline bytes serialize as base64, so attachments travel through the HTTP and queue formats
+//! unchanged. `ModelCatalog::validate_request` rejects attachments for models without
+//! `ModelFeature::Vision`.
+
+use crate::*;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+/// Where the content of an attachment comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaSource {
+    /// Inline content, base64 encoded when serialized
+    Bytes(#[serde(with = "base64_bytes")] Vec<u8>),
+    /// Location the provider downloads the content from
+    Url(String),
+}
+
+/// Image (or other media) passed to the model with the prompt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaInput {
+    /// MIME type, e.g. `image/png`
+    pub mime_type: String,
+    pub source: MediaSource,
+}
+
+impl MediaInput {
+    pub fn from_bytes(mime_type: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            source: MediaSource::Bytes(data.into()),
+        }
+    }
+
+    pub fn from_url(mime_type: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            mime_type: mime_type.into(),
+            source: MediaSource::Url(url.into()),
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+    }
+
+    /// URL providers accept for the attachment: the URL itself, or a `data:` URL of the bytes
+    pub fn to_url(&self) -> String {
+        match &self.source {
+            MediaSource::Url(url) => url.clone(),
+            MediaSource::Bytes(data) => {
+                format!("data:{};base64,{}", self.mime_type, BASE64.encode(data))
+            }
+        }
+    }
+
+    /// Reject attachments without a `type/subtype` MIME type or without content
+    pub fn validate(&self) -> InferenceResult<()> {
+        let well_formed = self
+            .mime_type
+            .split_once('/')
+            .is_some_and(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty());
+        if !well_formed {
+            return Err(TylError::validation(
+                "attachments",
+                format!("Invalid MIME type {:?}", self.mime_type),
+            ));
+        }
+        let empty = match &self.source {
+            MediaSource::Bytes(data) => data.is_empty(),
+            MediaSource::Url(url) => url.trim().is_empty(),
+        };
+        if empty {
+            return Err(TylError::validation(
+                "attachments",
+                "Attachment has no content",
+            ));
+        }
+        Ok(())
+    }
+}
+
+mod base64_bytes {
+    use super::{Engine, BASE64};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_bytes_as_base64() {
+        let image = MediaInput::from_bytes("image/png", b"\x89PNG".to_vec());
+        let json = serde_json::to_value(&image).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"mime_type": "image/png", "source": {"bytes": "iVBORw=="}})
+        );
+        assert_eq!(serde_json::from_value::<MediaInput>(json).unwrap(), image);
+        assert_eq!(image.to_url(), "data:image/png;base64,iVBORw==");
+        assert!(image.is_image());
+
+        let url = MediaInput::from_url("image/jpeg", "https://example.com/a.jpg");
+        assert_eq!(url.to_url(), "https://example.com/a.jpg");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(
+            MediaInput::from_url("image/png", "https://example.com/a.png")
+                .validate()
+                .is_ok()
+        );
+        assert!(MediaInput::from_bytes("png", vec![1]).validate().is_err());
+        assert!(MediaInput::from_bytes("image/png", Vec::new())
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,430 @@
+//! Metrics port for inference decorators
+//!
+//! Decorators publish counters and gauges through a `MetricsRecorder`, so operators can plug in
+//! Prometheus, OpenTelemetry, or StatsD exporters without this crate depending on any of them.
+//! `InMemoryMetrics` keeps the latest values in process for tests and ad-hoc inspection.
+//!
+//! `MetricsService` times every request of the service it wraps, counts outcomes, and keeps a
+//! `LatencyTracker` of p50/p95/p99 latencies per provider and model. Streams are timed from the
+//! request to their last chunk.
+
+use crate::ensemble::forward_batch;
+use crate::latency::{LatencyHistogram, LatencySnapshot, LatencyTracker};
+use crate::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Requests waiting for a free slot
+pub const QUEUE_DEPTH: &str = "inference_queue_depth";
+/// Requests currently being processed
+pub const IN_FLIGHT: &str = "inference_in_flight";
+/// Estimated wait for a newly queued request, in seconds
+pub const QUEUE_ESTIMATED_WAIT_SECONDS: &str = "inference_queue_estimated_wait_seconds";
+/// Requests rejected because the queue was full
+pub const QUEUE_REJECTIONS_TOTAL: &str = "inference_queue_rejections_total";
+/// Requests completed, labelled by provider, model and outcome (`success` or `error`)
+pub const REQUESTS_TOTAL: &str = "inference_requests_total";
+/// Request latency in seconds, labelled by provider, model and outcome
+pub const REQUEST_DURATION_SECONDS: &str = "inference_request_duration_seconds";
+
+/// Metric label as a key-value pair
+pub type Label<'a> = (&'a str, &'a str);
+
+/// Sink for metrics published by decorators
+pub trait MetricsRecorder: Send + Sync {
+    /// Add `value` to a monotonically increasing counter
+    fn increment_counter(&self, name: &str, labels: &[Label<'_>], value: u64);
+
+    /// Set the current value of a gauge
+    fn set_gauge(&self, name: &str, labels: &[Label<'_>], value: f64);
+
+    /// Add an observation to a histogram (ignored by default)
+    fn observe_histogram(&self, _name: &str, _labels: &[Label<'_>], _value: f64) {}
+}
+
+/// Recorder discarding every metric
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl MetricsRecorder for NoopMetrics {
+    fn increment_counter(&self, _name: &str, _labels: &[Label<'_>], _value: u64) {}
+
+    fn set_gauge(&self, _name: &str, _labels: &[Label<'_>], _value: f64) {}
+}
+
+/// Metric name plus sorted labels
+type MetricKey = (String, BTreeMap<String, String>);
+
+fn metric_key(name: &str, labels: &[Label<'_>]) -> MetricKey {
+    let labels = labels
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    (name.to_string(), labels)
+}
+
+/// Process-local recorder keeping the current value of every metric
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    counters: Mutex<BTreeMap<MetricKey, u64>>,
+    gauges: Mutex<BTreeMap<MetricKey, f64>>,
+    histograms: Mutex<BTreeMap<MetricKey, LatencyHistogram>>,
+}
+
+impl InMemoryMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current value of a counter (0 when never incremented)
+    pub fn counter(&self, name: &str, labels: &[Label<'_>]) -> u64 {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(&metric_key(name, labels))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Current value of a gauge, `None` when never set
+    pub fn gauge(&self, name: &str, labels: &[Label<'_>]) -> Option<f64> {
+        self.gauges
+            .lock()
+            .unwrap()
+            .get(&metric_key(name, labels))
+            .copied()
+    }
+
+    /// Summary of a histogram of seconds, `None` when never observed
+    pub fn histogram(&self, name: &str, labels: &[Label<'_>]) -> Option<LatencySnapshot> {
+        self.histograms
+            .lock()
+            .unwrap()
+            .get(&metric_key(name, labels))
+            .map(LatencyHistogram::snapshot)
+    }
+}
+
+impl MetricsRecorder for InMemoryMetrics {
+    fn increment_counter(&self, name: &str, labels: &[Label<'_>], value: u64) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(metric_key(name, labels))
+            .or_insert(0) += value;
+    }
+
+    fn set_gauge(&self, name: &str, labels: &[Label<'_>], value: f64) {
+        self.gauges
+            .lock()
+            .unwrap()
+            .insert(metric_key(name, labels), value);
+    }
+
+    fn observe_histogram(&self, name: &str, labels: &[Label<'_>], value: f64) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry(metric_key(name, labels))
+            .or_default()
+            .record(Duration::try_from_secs_f64(value).unwrap_or_default());
+    }
+}
+
+/// Inference service decorator publishing request counts and latencies
+///
+/// Latencies of successful requests go into a `LatencyTracker` under this service's provider
+/// name and the model that answered; share the tracker to read percentiles elsewhere (for
+/// example in `RoutingInferenceService::with_latency_tracker`). Failed requests are counted and
+/// timed in the recorder only, so fast failures do not make a backend look fast.
+pub struct MetricsService<S> {
+    inner: S,
+    provider: String,
+    recorder: Arc<dyn MetricsRecorder>,
+    latencies: Arc<LatencyTracker>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for MetricsService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsService")
+            .field("inner", &self.inner)
+            .field("provider", &self.provider)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> MetricsService<S> {
+    /// Track `inner` under the `provider` label, without an external recorder
+    pub fn new(inner: S, provider: impl Into<String>) -> Self {
+        Self {
+            inner,
+            provider: provider.into(),
+            recorder: Arc::new(NoopMetrics),
+            latencies: Arc::new(LatencyTracker::new()),
+        }
+    }
+
+    /// Publish counters and histograms to `recorder`
+    pub fn with_recorder(mut self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Record latencies in a shared tracker
+    pub fn with_latency_tracker(mut self, latencies: Arc<LatencyTracker>) -> Self {
+        self.latencies = latencies;
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    pub fn latency_tracker(&self) -> &Arc<LatencyTracker> {
+        &self.latencies
+    }
+
+    /// Latency percentiles of `model` on this provider, `None` before its first success
+    pub fn latency(&self, model: &str) -> Option<LatencySnapshot> {
+        self.latencies.snapshot(&self.provider, model)
+    }
+
+    fn record(
+        &self,
+        requested_model: &str,
+        result: &InferenceResult<InferenceResponse>,
+        elapsed: Duration,
+    ) {
+        let (model, outcome) = match result {
+            Ok(response) => (response.metadata.model.as_str(), "success"),
+            Err(_) => (requested_model, "error"),
+        };
+        if result.is_ok() {
+            self.latencies.record(&self.provider, model, elapsed);
+        }
+        let labels = [
+            ("provider", self.provider.as_str()),
+            ("model", model),
+            ("outcome", outcome),
+        ];
+        self.recorder.increment_counter(REQUESTS_TOTAL, &labels, 1);
+        self.recorder
+            .observe_histogram(REQUEST_DURATION_SECONDS, &labels, elapsed.as_secs_f64());
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for MetricsService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let requested_model = request
+            .model_override
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        let started = Instant::now();
+        let result = self.inner.infer(request).await;
+        self.record(&requested_model, &result, started.elapsed());
+        result
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        let started = Instant::now();
+        let results = forward_batch(&self.inner, requests, |request| {
+            Ok(request
+                .model_override
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string()))
+        })
+        .await;
+        // Every request of the batch waited for the whole batch
+        let elapsed = started.elapsed();
+        results
+            .into_iter()
+            .map(|result| {
+                let (requested_model, response) = result?;
+                self.record(&requested_model, &response, elapsed);
+                response
+            })
+            .collect()
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let requested_model = request
+            .model_override
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        let started = Instant::now();
+        let stream = self.inner.infer_stream(request).await;
+
+        let provider = self.provider.clone();
+        let recorder = Arc::clone(&self.recorder);
+        let latencies = Arc::clone(&self.latencies);
+        let record = move |model: &str, outcome: &str| {
+            let elapsed = started.elapsed();
+            if outcome == "success" {
+                latencies.record(&provider, model, elapsed);
+            }
+            let labels = [
+                ("provider", provider.as_str()),
+                ("model", model),
+                ("outcome", outcome),
+            ];
+            recorder.increment_counter(REQUESTS_TOTAL, &labels, 1);
+            recorder.observe_histogram(REQUEST_DURATION_SECONDS, &labels, elapsed.as_secs_f64());
+        };
+
+        match stream {
+            Ok(stream) => Ok(stream.on_end(move |end| match end {
+                StreamEnd::Completed(summary) => record(
+                    summary.model.as_deref().unwrap_or(&requested_model),
+                    "success",
+                ),
+                StreamEnd::Failed(_) => record(&requested_model, "error"),
+                // Abandoned streams are neither successes nor backend errors
+                StreamEnd::Dropped(_) => {}
+            })),
+            Err(error) => {
+                record(&requested_model, "error");
+                Err(error)
+            }
+        }
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_metrics() {
+        let metrics = InMemoryMetrics::new();
+        assert_eq!(metrics.counter(QUEUE_REJECTIONS_TOTAL, &[]), 0);
+        assert_eq!(metrics.gauge(QUEUE_DEPTH, &[]), None);
+
+        metrics.increment_counter(QUEUE_REJECTIONS_TOTAL, &[], 2);
+        metrics.increment_counter(QUEUE_REJECTIONS_TOTAL, &[], 1);
+        assert_eq!(metrics.counter(QUEUE_REJECTIONS_TOTAL, &[]), 3);
+
+        metrics.set_gauge(QUEUE_DEPTH, &[("model_type", "Reasoning")], 4.0);
+        metrics.set_gauge(QUEUE_DEPTH, &[("model_type", "Reasoning")], 2.0);
+        assert_eq!(
+            metrics.gauge(QUEUE_DEPTH, &[("model_type", "Reasoning")]),
+            Some(2.0)
+        );
+        assert_eq!(metrics.gauge(QUEUE_DEPTH, &[("model_type", "Fast")]), None);
+
+        assert_eq!(metrics.histogram(REQUEST_DURATION_SECONDS, &[]), None);
+        metrics.observe_histogram(REQUEST_DURATION_SECONDS, &[], 0.25);
+        let histogram = metrics.histogram(REQUEST_DURATION_SECONDS, &[]).unwrap();
+        assert_eq!(histogram.count, 1);
+        assert_eq!(histogram.p50, Duration::from_millis(250));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_metrics_service_tracks_latency_per_model() {
+        use crate::MockInferenceService;
+
+        let metrics = Arc::new(InMemoryMetrics::new());
+        let service = MetricsService::new(MockInferenceService::new().with_latency(20), "mock")
+            .with_recorder(metrics.clone());
+        for _ in 0..3 {
+            let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General);
+            service.infer(request).await.unwrap();
+        }
+        let request = InferenceRequest::new("", HashMap::new(), ModelType::General);
+        assert!(service.infer(request).await.is_err());
+
+        let latency = service.latency("gpt-4o-mini").unwrap();
+        assert_eq!(latency.count, 3);
+        assert!(latency.p50 >= Duration::from_millis(20));
+        assert!(latency.p99 >= latency.p50);
+
+        let success = [
+            ("provider", "mock"),
+            ("model", "gpt-4o-mini"),
+            ("outcome", "success"),
+        ];
+        assert_eq!(metrics.counter(REQUESTS_TOTAL, &success), 3);
+        let failure = [
+            ("provider", "mock"),
+            ("model", "unknown"),
+            ("outcome", "error"),
+        ];
+        assert_eq!(metrics.counter(REQUESTS_TOTAL, &failure), 1);
+        assert_eq!(
+            metrics
+                .histogram(REQUEST_DURATION_SECONDS, &success)
+                .unwrap()
+                .count,
+            3
+        );
+    }
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_metrics_service_times_streams_to_their_end() {
+        use crate::MockInferenceService;
+
+        let metrics = Arc::new(InMemoryMetrics::new());
+        let service = MetricsService::new(MockInferenceService::new().with_latency(0), "mock")
+            .with_recorder(metrics.clone());
+        let success = [
+            ("provider", "mock"),
+            ("model", "gpt-4o-mini"),
+            ("outcome", "success"),
+        ];
+
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General);
+        let stream = service.infer_stream(request).await.unwrap();
+        assert_eq!(metrics.counter(REQUESTS_TOTAL, &success), 0);
+        stream.assemble().await.unwrap();
+        assert_eq!(metrics.counter(REQUESTS_TOTAL, &success), 1);
+        assert_eq!(service.latency("gpt-4o-mini").unwrap().count, 1);
+
+        // A stream dropped before its end is not counted
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General);
+        drop(service.infer_stream(request).await.unwrap());
+        assert_eq!(metrics.counter(REQUESTS_TOTAL, &success), 1);
+    }
+}
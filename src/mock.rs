@@ -13,6 +13,8 @@ pub struct MockInferenceService {
     pub health_check_fails: bool,
     /// Custom response for testing (JSON string or plain text)
     pub custom_response: Option<String>,
+    /// Limits requests are validated with
+    pub limits: RequestLimits,
 }
 
 impl MockInferenceService {
@@ -21,6 +23,7 @@ impl MockInferenceService {
             simulated_latency_ms: 100,
             health_check_fails: false,
             custom_response: None,
+            limits: RequestLimits::structural(),
         }
     }
 
@@ -39,6 +42,11 @@ impl MockInferenceService {
         self
     }
 
+    pub fn with_limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     fn estimate_tokens(&self, text: &str) -> usize {
         // Simple approximation: ~4 characters per token
         (text.len() + 3) / 4
@@ -99,6 +107,25 @@ impl MockInferenceService {
                     rendered_template.replace('"', r#"\""#)
                 )
             }
+            ModelType::Vision => {
+                format!(
+                    r#"{{"description": "Mock description of {} image(s) for: {}"}}"#,
+                    request.attachments.len(),
+                    rendered_template.replace('"', r#"\""#)
+                )
+            }
+            ModelType::Extraction => {
+                format!(
+                    r#"{{"fields": {{}}, "source": "{}"}}"#,
+                    rendered_template.replace('"', r#"\""#)
+                )
+            }
+            ModelType::Summarization => {
+                format!(
+                    r#"{{"summary": "Mock summary of: {}"}}"#,
+                    rendered_template.replace('"', r#"\""#)
+                )
+            }
         }
     }
 }
@@ -112,6 +139,10 @@ impl Default for MockInferenceService {
 #[async_trait]
 impl InferenceService for MockInferenceService {
     async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        if request.dry_run {
+            return dry_run_response(self, &request, &self.limits);
+        }
+        request.validate_with(self, &self.limits)?;
         let start = Instant::now();
 
         // Simulate processing time
@@ -123,18 +154,58 @@ impl InferenceService for MockInferenceService {
         let rendered_template = request.render_template();
         let prompt_tokens = self.estimate_tokens(&rendered_template);
         let completion_tokens = self.estimate_tokens(&generated_content);
+        // Mock generations are deterministic: every word is generated with probability 1
+        let logprobs = request.logprobs.map(|top_alternatives| {
+            let tokens = generated_content
+                .split_inclusive(' ')
+                .map(|word| {
+                    let token = TokenLogprob::new(word, 0.0);
+                    if top_alternatives > 0 {
+                        token.with_alternative(word, 0.0)
+                    } else {
+                        token
+                    }
+                })
+                .collect();
+            Logprobs::new(tokens)
+        });
 
         let model = request
             .model_override
             .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string());
 
         // Try to parse as JSON, fallback to string if it fails
-        let response = InferenceResponse::from_text_with_json_fallback(
+        let mut response = InferenceResponse::from_text_with_json_fallback(
             generated_content,
             model,
             TokenUsage::new(prompt_tokens as u32, completion_tokens as u32),
             start.elapsed().as_millis() as u64,
         );
+        response.metadata.logprobs = logprobs;
+        let candidate_count = request.candidates.unwrap_or(1);
+        if candidate_count > 1 {
+            let candidates = (0..candidate_count)
+                .map(|index| {
+                    let candidate = Candidate::new(
+                        index,
+                        response.content.clone(),
+                        response.metadata.token_usage.clone(),
+                    )
+                    .with_finish_reason("stop");
+                    match &response.metadata.logprobs {
+                        Some(logprobs) => candidate.with_logprobs(logprobs.clone()),
+                        None => candidate,
+                    }
+                })
+                .collect();
+            response = InferenceResponse::from_candidates(
+                candidates,
+                response.metadata.model,
+                response.metadata.processing_time_ms,
+            )?;
+        }
+        // Mock generations are deterministic, so every seed is honored
+        response.metadata.seed = request.seed;
 
         Ok(response)
     }
@@ -169,6 +240,117 @@ impl InferenceService for MockInferenceService {
     }
 }
 
+/// Mock embedding service producing deterministic bag-of-words vectors
+///
+/// Every word is hashed into one of `dimensions` buckets and the counts are normalized to unit
+/// length, so texts sharing words have a positive cosine similarity.
+#[derive(Debug, Clone)]
+pub struct MockEmbeddingService {
+    pub dimensions: usize,
+}
+
+impl MockEmbeddingService {
+    /// 64-dimensional vectors
+    pub fn new() -> Self {
+        Self { dimensions: 64 }
+    }
+
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions.max(1);
+        self
+    }
+
+    fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dimensions];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            // FNV-1a, stable across platforms and releases
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |hash, byte| {
+                    (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+                });
+            vector[(hash % self.dimensions as u64) as usize] += 1.0;
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+impl Default for MockEmbeddingService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EmbeddingService for MockEmbeddingService {
+    async fn embed(&self, texts: &[String]) -> InferenceResult<EmbeddingResponse> {
+        let prompt_tokens: usize = texts.iter().map(|text| (text.len() + 3) / 4).sum();
+        Ok(EmbeddingResponse::new(
+            texts.iter().map(|text| self.embed_text(text)).collect(),
+            "mock-embedding".to_string(),
+            TokenUsage::new(prompt_tokens as u32, 0),
+            0,
+        ))
+    }
+
+    fn embedding_model(&self) -> String {
+        "mock-embedding".to_string()
+    }
+}
+
+/// Mock moderation service flagging configured terms
+///
+/// Terms match case-insensitively anywhere in the text, and every match is reported as a span.
+#[derive(Debug, Clone, Default)]
+pub struct MockModerationService {
+    /// (term, category) pairs
+    pub blocked_terms: Vec<(String, String)>,
+}
+
+impl MockModerationService {
+    /// Flags nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_blocked_term(
+        mut self,
+        term: impl Into<String>,
+        category: impl Into<String>,
+    ) -> Self {
+        self.blocked_terms
+            .push((term.into().to_ascii_lowercase(), category.into()));
+        self
+    }
+}
+
+#[async_trait]
+impl ModerationService for MockModerationService {
+    async fn moderate(&self, text: &str) -> InferenceResult<ModerationResult> {
+        // ASCII lowercasing keeps byte offsets valid for the original text
+        let lowercase = text.to_ascii_lowercase();
+        let mut result = ModerationResult::allowed();
+        for (term, category) in &self.blocked_terms {
+            for (start, _) in lowercase.match_indices(term.as_str()) {
+                result = result.with_span(start, start + term.len());
+                if !result.categories.contains(category) {
+                    result.categories.push(category.clone());
+                }
+                result.flagged = true;
+            }
+        }
+        Ok(result)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -323,6 +505,61 @@ mod tests {
         assert_eq!(response.metadata.model, "custom-model");
     }
 
+    #[tokio::test]
+    async fn test_mock_service_reports_seed() {
+        let service = MockInferenceService::new().with_latency(0);
+
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        let response = service.infer(request.clone()).await.unwrap();
+        assert_eq!(response.metadata.seed, None);
+
+        let response = service.infer(request.with_seed(42)).await.unwrap();
+        assert_eq!(response.metadata.seed, Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_mock_service_multiple_candidates() {
+        let service = MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response("four words of text");
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        let single = service.infer(request.clone()).await.unwrap();
+        assert!(single.candidates.is_empty());
+        assert_eq!(single.candidate_contents(), vec![&single.content]);
+
+        let response = service.infer(request.with_candidates(3)).await.unwrap();
+        assert_eq!(response.candidates.len(), 3);
+        assert_eq!(response.content, single.content);
+        let usage = &single.metadata.token_usage;
+        assert_eq!(
+            response.metadata.token_usage,
+            TokenUsage::new(usage.prompt_tokens, usage.completion_tokens * 3)
+        );
+        assert_eq!(response.candidates[2].token_usage, *usage);
+    }
+
+    #[tokio::test]
+    async fn test_mock_service_logprobs() {
+        let service = MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response("four words of text");
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        assert!(service
+            .infer(request.clone())
+            .await
+            .unwrap()
+            .metadata
+            .logprobs
+            .is_none());
+
+        let response = service.infer(request.with_logprobs(1)).await.unwrap();
+        let logprobs = response.metadata.logprobs.unwrap();
+        assert_eq!(logprobs.text(), "four words of text");
+        assert_eq!(logprobs.tokens.len(), 4);
+        assert_eq!(logprobs.perplexity(), Some(1.0));
+        assert_eq!(logprobs.tokens[0].top_logprobs.len(), 1);
+    }
+
     #[tokio::test]
     async fn test_mock_service_fallback_to_string() {
         // Test with invalid JSON that should fallback to string
@@ -341,4 +578,33 @@ mod tests {
             _ => panic!("Expected string fallback for invalid JSON"),
         }
     }
+
+    #[tokio::test]
+    async fn test_mock_embeddings_are_deterministic_and_normalized() {
+        let service = MockEmbeddingService::new().with_dimensions(16);
+        let texts = vec![
+            "The cat sat".to_string(),
+            "the CAT sat".to_string(),
+            "Quarterly revenue".to_string(),
+        ];
+        let response = service.embed(&texts).await.unwrap();
+        assert_eq!(response.dimensions(), Some(16));
+        assert_eq!(response.embeddings[0], response.embeddings[1]);
+        let norm: f32 = response.embeddings[2].iter().map(|x| x * x).sum();
+        assert!((norm - 1.0).abs() < 1e-5);
+        assert_eq!(
+            service.embed_one("The cat sat").await.unwrap(),
+            response.embeddings[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_moderation_flags_terms() {
+        let service = MockModerationService::new().with_blocked_term("Secret", "leak");
+        let result = service.moderate("the SECRET is secret").await.unwrap();
+        assert!(result.flagged);
+        assert_eq!(result.categories, vec!["leak"]);
+        assert_eq!(result.redact("the SECRET is secret", "#"), "the # is #");
+        assert!(!service.moderate("nothing here").await.unwrap().flagged);
+    }
 }
@@ -0,0 +1,550 @@
+//! Content moderation guardrails
+//!
+//! A `ModerationService` classifies text as acceptable or not. `GuardedInferenceService` screens
+//! the rendered prompt before it reaches the backend and the generated content before it
+//! reaches the caller, and applies a `GuardAction` to flagged text: block it with an error,
+//! redact it, or let it through annotated in the response metadata:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::moderation::*;
+//! # use tyl_llm_inference_port::credentials::*;
+//! # #[cfg(feature = "http-client")]
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let moderator = OpenAiModerationService::new(reqwest::Client::new())
+//!     .with_credentials(EnvCredentials::new("OPENAI_API_KEY"));
+//! let service = GuardedInferenceService::new(openai, moderator)
+//!     .with_prompt_action(GuardAction::Block)
+//!     .with_response_action(GuardAction::Redact);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Redaction replaces the flagged spans when the moderator locates them and the whole text
+//! otherwise. Every candidate of a multi-candidate response is screened on its own.
+
+use crate::credentials::CredentialsProvider;
+use crate::http_client::{parse_json_response, send_authorized, HttpRequest, HttpTransport};
+use crate::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Response metadata key listing the flagged stages: `prompt`, `response` or `prompt,response`
+pub const MODERATION_FLAGGED_METADATA_KEY: &str = "moderation_flagged";
+/// Response metadata key listing the flagged categories, comma separated
+pub const MODERATION_CATEGORIES_METADATA_KEY: &str = "moderation_categories";
+
+/// Verdict of a `ModerationService` on a text
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    /// Categories the text was flagged for
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Score per category (0.0 to 1.0), when the moderator reports them
+    #[serde(default)]
+    pub scores: BTreeMap<String, f64>,
+    /// Byte ranges of the offending text, when the moderator locates it
+    #[serde(default)]
+    pub spans: Vec<(usize, usize)>,
+}
+
+impl ModerationResult {
+    /// Nothing to object to
+    pub fn allowed() -> Self {
+        Self::default()
+    }
+
+    /// Flagged for `categories`
+    pub fn flagged(categories: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            flagged: true,
+            categories: categories.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_score(mut self, category: impl Into<String>, score: f64) -> Self {
+        self.scores.insert(category.into(), score);
+        self
+    }
+
+    pub fn with_span(mut self, start: usize, end: usize) -> Self {
+        self.spans.push((start, end));
+        self
+    }
+
+    /// `text` with the flagged spans, or all of it without spans, replaced by `replacement`
+    pub fn redact(&self, text: &str, replacement: &str) -> String {
+        if self.spans.is_empty() {
+            return replacement.to_string();
+        }
+        let mut spans = self.spans.clone();
+        spans.sort_unstable();
+        let mut redacted = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end) in spans {
+            let (start, end) = (start.max(cursor), end.min(text.len()));
+            if start >= end || !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+                continue;
+            }
+            redacted.push_str(&text[cursor..start]);
+            redacted.push_str(replacement);
+            cursor = end;
+        }
+        redacted.push_str(&text[cursor..]);
+        redacted
+    }
+}
+
+/// Port for content moderation backends
+#[async_trait]
+pub trait ModerationService: Send + Sync {
+    async fn moderate(&self, text: &str) -> InferenceResult<ModerationResult>;
+}
+
+/// What `GuardedInferenceService` does with flagged text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardAction {
+    /// Do not screen at all
+    Allow,
+    /// Let flagged text through and record the verdict in the response metadata
+    Annotate,
+    /// Replace flagged text and record the verdict in the response metadata
+    Redact,
+    /// Fail the request with a validation error
+    Block,
+}
+
+/// Inference service decorator screening prompts and responses with a `ModerationService`
+pub struct GuardedInferenceService<S> {
+    inner: S,
+    moderator: Arc<dyn ModerationService>,
+    prompt_action: GuardAction,
+    response_action: GuardAction,
+    replacement: String,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for GuardedInferenceService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GuardedInferenceService")
+            .field("inner", &self.inner)
+            .field("prompt_action", &self.prompt_action)
+            .field("response_action", &self.response_action)
+            .field("replacement", &self.replacement)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Flagged stages and categories collected while screening a request
+#[derive(Default)]
+struct Findings {
+    stages: Vec<&'static str>,
+    categories: Vec<String>,
+}
+
+impl Findings {
+    fn record(&mut self, stage: &'static str, result: &ModerationResult) {
+        if !self.stages.contains(&stage) {
+            self.stages.push(stage);
+        }
+        for category in &result.categories {
+            if !self.categories.contains(category) {
+                self.categories.push(category.clone());
+            }
+        }
+    }
+}
+
+impl<S: InferenceService> GuardedInferenceService<S> {
+    /// Block flagged prompts and responses
+    pub fn new(inner: S, moderator: impl ModerationService + 'static) -> Self {
+        Self {
+            inner,
+            moderator: Arc::new(moderator),
+            prompt_action: GuardAction::Block,
+            response_action: GuardAction::Block,
+            replacement: "[REDACTED]".to_string(),
+        }
+    }
+
+    pub fn with_prompt_action(mut self, action: GuardAction) -> Self {
+        self.prompt_action = action;
+        self
+    }
+
+    pub fn with_response_action(mut self, action: GuardAction) -> Self {
+        self.response_action = action;
+        self
+    }
+
+    /// Text substituted for redacted content (default `[REDACTED]`)
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Screen generated `content` in place according to the response action
+    async fn screen_content(
+        &self,
+        content: &mut serde_json::Value,
+        findings: &mut Findings,
+    ) -> InferenceResult<()> {
+        let text = match &*content {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        let result = self.moderator.moderate(&text).await?;
+        if !result.flagged {
+            return Ok(());
+        }
+        match self.response_action {
+            GuardAction::Block => {
+                return Err(inference_errors::content_blocked(
+                    "response",
+                    &result.categories,
+                ))
+            }
+            // Spans index the serialized text, so structured content is replaced whole
+            GuardAction::Redact => {
+                *content = serde_json::Value::String(match &*content {
+                    serde_json::Value::String(_) => result.redact(&text, &self.replacement),
+                    _ => self.replacement.clone(),
+                })
+            }
+            GuardAction::Annotate | GuardAction::Allow => {}
+        }
+        findings.record("response", &result);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for GuardedInferenceService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let mut findings = Findings::default();
+        if self.prompt_action != GuardAction::Allow {
+            let prompt = request.render_template();
+            let result = self.moderator.moderate(&prompt).await?;
+            if result.flagged {
+                match self.prompt_action {
+                    GuardAction::Block => {
+                        return Err(inference_errors::content_blocked(
+                            "prompt",
+                            &result.categories,
+                        ))
+                    }
+                    GuardAction::Redact => {
+                        request.template = result.redact(&prompt, &self.replacement);
+                        request.parameters.clear();
+                    }
+                    GuardAction::Annotate | GuardAction::Allow => {}
+                }
+                findings.record("prompt", &result);
+            }
+        }
+
+        let mut response = self.inner.infer(request).await?;
+        if self.response_action != GuardAction::Allow {
+            self.screen_content(&mut response.content, &mut findings)
+                .await?;
+            for candidate in &mut response.candidates {
+                self.screen_content(&mut candidate.content, &mut findings)
+                    .await?;
+            }
+        }
+
+        if !findings.stages.is_empty() {
+            response.metadata = response
+                .metadata
+                .with_metadata(MODERATION_FLAGGED_METADATA_KEY, findings.stages.join(","))
+                .with_metadata(
+                    MODERATION_CATEGORIES_METADATA_KEY,
+                    findings.categories.join(","),
+                );
+        }
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+/// `ModerationService` over the OpenAI moderation API
+#[derive(Clone)]
+pub struct OpenAiModerationService {
+    base_url: String,
+    model: String,
+    transport: Arc<dyn HttpTransport>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+}
+
+impl std::fmt::Debug for OpenAiModerationService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiModerationService")
+            .field("base_url", &self.base_url)
+            .field("model", &self.model)
+            .field("credentials", &self.credentials.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAiModerations {
+    results: Vec<OpenAiModeration>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiModeration {
+    flagged: bool,
+    #[serde(default)]
+    categories: BTreeMap<String, bool>,
+    #[serde(default)]
+    category_scores: BTreeMap<String, f64>,
+}
+
+impl OpenAiModerationService {
+    /// `omni-moderation-latest` at `https://api.openai.com/v1` through `transport`
+    pub fn new(transport: impl HttpTransport + 'static) -> Self {
+        Self {
+            base_url: "https://api.openai.com/v1".to_string(),
+            model: "omni-moderation-latest".to_string(),
+            transport: Arc::new(transport),
+            credentials: None,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Authenticate with bearer credentials from `provider`, refreshing them once on 401
+    pub fn with_credentials(mut self, provider: impl CredentialsProvider + 'static) -> Self {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+}
+
+#[async_trait]
+impl ModerationService for OpenAiModerationService {
+    async fn moderate(&self, text: &str) -> InferenceResult<ModerationResult> {
+        let body = serde_json::to_vec(&serde_json::json!({ "model": self.model, "input": text }))
+            .map_err(|e| {
+            TylError::internal(format!("Failed to encode moderation request: {e}"))
+        })?;
+        let request = HttpRequest::post_json(format!("{}/moderations", self.base_url), body);
+        let response = send_authorized(
+            self.transport.as_ref(),
+            self.credentials.as_deref(),
+            request,
+        )
+        .await?;
+        let parsed: OpenAiModerations = parse_json_response(&response, "OpenAI")?;
+        let moderation = parsed.results.into_iter().next().ok_or_else(|| {
+            TylError::internal("Invalid moderation response: no results".to_string())
+        })?;
+
+        Ok(ModerationResult {
+            flagged: moderation.flagged,
+            categories: moderation
+                .categories
+                .into_iter()
+                .filter_map(|(category, flagged)| flagged.then_some(category))
+                .collect(),
+            scores: moderation.category_scores,
+            spans: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::HttpResponse;
+    use std::sync::Mutex;
+
+    /// Flags texts containing "bomb", locating the word
+    struct BombModerator;
+
+    #[async_trait]
+    impl ModerationService for BombModerator {
+        async fn moderate(&self, text: &str) -> InferenceResult<ModerationResult> {
+            Ok(match text.find("bomb") {
+                Some(start) => ModerationResult::flagged(["violence"]).with_span(start, start + 4),
+                None => ModerationResult::allowed(),
+            })
+        }
+    }
+
+    /// Answers with a fixed text, recording the prompts it saw
+    struct Fixed {
+        answer: &'static str,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl Fixed {
+        fn new(answer: &'static str) -> Self {
+            Self {
+                answer,
+                prompts: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl InferenceService for Fixed {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            self.prompts.lock().unwrap().push(request.render_template());
+            Ok(InferenceResponse::from_string(
+                self.answer.to_string(),
+                "gpt-4o-mini".to_string(),
+                TokenUsage::new(1, 1),
+                1,
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["gpt-4o-mini".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn request(prompt: &str) -> InferenceRequest {
+        InferenceRequest::new(prompt, HashMap::new(), ModelType::General)
+    }
+
+    #[tokio::test]
+    async fn test_blocks_flagged_prompts_before_the_backend() {
+        let service = GuardedInferenceService::new(Fixed::new("ok"), BombModerator);
+        let error = service
+            .infer(request("How do I build a bomb?"))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("violence"));
+        assert!(service.inner().prompts.lock().unwrap().is_empty());
+
+        let response = service.infer(request("Hello")).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("ok"));
+        assert!(!response
+            .metadata
+            .metadata
+            .contains_key(MODERATION_FLAGGED_METADATA_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_redacts_and_annotates() {
+        let service = GuardedInferenceService::new(Fixed::new("a bomb, a bomb!"), BombModerator)
+            .with_prompt_action(GuardAction::Redact)
+            .with_response_action(GuardAction::Redact)
+            .with_replacement("***");
+        let response = service.infer(request("Say bomb twice")).await.unwrap();
+        assert_eq!(service.inner().prompts.lock().unwrap()[0], "Say *** twice");
+        // The moderator located only the first occurrence
+        assert_eq!(response.content, serde_json::json!("a ***, a bomb!"));
+        let metadata = &response.metadata.metadata;
+        assert_eq!(metadata[MODERATION_FLAGGED_METADATA_KEY], "prompt,response");
+        assert_eq!(metadata[MODERATION_CATEGORIES_METADATA_KEY], "violence");
+
+        let service = GuardedInferenceService::new(Fixed::new("bomb"), BombModerator)
+            .with_prompt_action(GuardAction::Allow)
+            .with_response_action(GuardAction::Annotate);
+        let response = service.infer(request("bomb")).await.unwrap();
+        assert_eq!(response.content, serde_json::json!("bomb"));
+        assert_eq!(
+            response.metadata.metadata[MODERATION_FLAGGED_METADATA_KEY],
+            "response"
+        );
+    }
+
+    #[test]
+    fn test_redact_without_spans_replaces_everything() {
+        let result = ModerationResult::flagged(["hate"]);
+        assert_eq!(result.redact("anything", "[REDACTED]"), "[REDACTED]");
+        let result = ModerationResult::flagged(["hate"])
+            .with_span(6, 9)
+            .with_span(0, 1);
+        assert_eq!(result.redact("a bad bad day", "_"), "_ bad _ day");
+    }
+
+    struct OneShot(Mutex<Option<HttpResponse>>);
+
+    #[async_trait]
+    impl HttpTransport for OneShot {
+        async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+            assert_eq!(request.url, "https://api.openai.com/v1/moderations");
+            Ok(self.0.lock().unwrap().take().unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openai_moderation_adapter() {
+        let transport = OneShot(Mutex::new(Some(HttpResponse::new(
+            200,
+            serde_json::to_vec(&serde_json::json!({
+                "id": "modr-1",
+                "model": "omni-moderation-latest",
+                "results": [{
+                    "flagged": true,
+                    "categories": {"harassment": true, "violence": true, "sexual": false},
+                    "category_scores": {"harassment": 0.91, "violence": 0.72, "sexual": 0.01}
+                }]
+            }))
+            .unwrap(),
+        ))));
+        let result = OpenAiModerationService::new(transport)
+            .moderate("text")
+            .await
+            .unwrap();
+        assert!(result.flagged);
+        assert_eq!(result.categories, vec!["harassment", "violence"]);
+        assert_eq!(result.scores["sexual"], 0.01);
+    }
+}
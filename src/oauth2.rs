@@ -0,0 +1,332 @@
+//! OAuth2 client-credentials tokens for adapters
+//!
+//! `OAuth2Credentials` is a `CredentialsProvider` obtaining bearer tokens from an OAuth2 token
+//! endpoint with the client-credentials grant, for environments where static API keys are not
+//! allowed (Azure AD / Entra ID, enterprise gateways behind an identity provider). Tokens are
+//! cached and fetched again shortly before they expire, or when an adapter reports the current
+//! one as rejected:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::oauth2::*;
+//! # use tyl_llm_inference_port::http_client::*;
+//! # #[cfg(feature = "http-client")]
+//! # async fn example(tenant_id: &str, client_id: String, client_secret: String, transport: impl HttpTransport + 'static) -> TylResult<()> {
+//! let credentials = OAuth2Credentials::azure_ad(tenant_id, client_id, client_secret, reqwest::Client::new());
+//! let service = HttpInferenceClient::new("https://gateway.internal", transport)
+//!     .with_credentials(credentials);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The client secret is sent in the form body (`client_secret_post`). The token request goes
+//! through an `HttpTransport`, so proxies and private CAs apply to it like to the adapters.
+
+use crate::credentials::{Credentials, CredentialsProvider};
+use crate::http_client::{HttpMethod, HttpRequest, HttpTransport};
+use crate::*;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Scope of Azure OpenAI / Azure AI services
+pub const AZURE_COGNITIVE_SERVICES_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+
+/// Bearer tokens from an OAuth2 client-credentials grant, cached until shortly before expiry
+pub struct OAuth2Credentials {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scopes: Vec<String>,
+    parameters: Vec<(String, String)>,
+    refresh_margin: Duration,
+    transport: Arc<dyn HttpTransport>,
+    cached: Mutex<Option<Credentials>>,
+}
+
+impl std::fmt::Debug for OAuth2Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuth2Credentials")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"<redacted>")
+            .field("scopes", &self.scopes)
+            .field("refresh_margin", &self.refresh_margin)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OAuth2Credentials {
+    /// Client-credentials grant against `token_url`, refreshing tokens 60 seconds before expiry
+    pub fn new(
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        transport: impl HttpTransport + 'static,
+    ) -> Self {
+        Self {
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scopes: Vec::new(),
+            parameters: Vec::new(),
+            refresh_margin: Duration::from_secs(60),
+            transport: Arc::new(transport),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Azure AD (Entra ID) v2 endpoint of `tenant_id`, scoped to Azure AI services
+    pub fn azure_ad(
+        tenant_id: &str,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        transport: impl HttpTransport + 'static,
+    ) -> Self {
+        Self::new(
+            format!("https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/token"),
+            client_id,
+            client_secret,
+            transport,
+        )
+        .with_scope(AZURE_COGNITIVE_SERVICES_SCOPE)
+    }
+
+    /// Request this scope (scopes are sent space-separated)
+    pub fn with_scope(mut self, scope: impl Into<String>) -> Self {
+        self.scopes.push(scope.into());
+        self
+    }
+
+    /// Extra form parameter of the token request (e.g. `audience` or `resource`)
+    pub fn with_parameter(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parameters.push((name.into(), value.into()));
+        self
+    }
+
+    /// Fetch a new token this long before the cached one expires
+    pub fn with_refresh_margin(mut self, margin: Duration) -> Self {
+        self.refresh_margin = margin;
+        self
+    }
+
+    pub fn token_url(&self) -> &str {
+        &self.token_url
+    }
+
+    fn token_request(&self) -> HttpRequest {
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+        ];
+        let scope = self.scopes.join(" ");
+        if !scope.is_empty() {
+            form.push(("scope", &scope));
+        }
+        form.extend(
+            self.parameters
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+        let body = form
+            .iter()
+            .map(|(name, value)| format!("{}={}", form_encode(name), form_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        HttpRequest {
+            method: HttpMethod::Post,
+            url: self.token_url.clone(),
+            headers: vec![
+                (
+                    "Content-Type".to_string(),
+                    "application/x-www-form-urlencoded".to_string(),
+                ),
+                ("Accept".to_string(), "application/json".to_string()),
+            ],
+            body: Some(body.into_bytes()),
+            timeout: None,
+        }
+    }
+
+    fn parse_token(&self, body: &[u8]) -> InferenceResult<Credentials> {
+        let token: serde_json::Value = serde_json::from_slice(body)
+            .map_err(|e| TylError::internal(format!("Invalid OAuth2 token response: {e}")))?;
+        let access_token = token
+            .get("access_token")
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| TylError::internal("OAuth2 token response has no access_token"))?;
+        // Azure AD v1 endpoints send `expires_in` as a string
+        let expires_in = token.get("expires_in").and_then(|value| {
+            value
+                .as_i64()
+                .or_else(|| value.as_str().and_then(|text| text.parse().ok()))
+        });
+
+        let credentials = Credentials::new(access_token);
+        Ok(match expires_in {
+            Some(seconds) => {
+                let margin = chrono::Duration::from_std(self.refresh_margin)
+                    .unwrap_or_else(|_| chrono::Duration::zero());
+                credentials.with_expiry(Utc::now() + chrono::Duration::seconds(seconds) - margin)
+            }
+            None => credentials,
+        })
+    }
+}
+
+#[async_trait]
+impl CredentialsProvider for OAuth2Credentials {
+    async fn get_credentials(&self) -> InferenceResult<Credentials> {
+        let cached = self.cached.lock().unwrap().clone();
+        match cached {
+            Some(credentials) if !credentials.is_expired() => Ok(credentials),
+            _ => self.refresh_credentials().await,
+        }
+    }
+
+    async fn refresh_credentials(&self) -> InferenceResult<Credentials> {
+        let response = self.transport.send(self.token_request()).await?;
+        if !response.is_success() {
+            let message = serde_json::from_slice::<serde_json::Value>(&response.body)
+                .ok()
+                .and_then(|body| {
+                    let error = body
+                        .get("error_description")
+                        .or_else(|| body.get("error"))?;
+                    error.as_str().map(str::to_string)
+                })
+                .unwrap_or_else(|| String::from_utf8_lossy(&response.body).into_owned());
+            return Err(match response.status {
+                400..=499 => TylError::configuration(format!(
+                    "OAuth2 token request to {} rejected (HTTP {}): {message}",
+                    self.token_url, response.status
+                )),
+                status => TylError::network(format!(
+                    "OAuth2 token request to {} failed (HTTP {status}): {message}",
+                    self.token_url
+                )),
+            });
+        }
+
+        let credentials = self.parse_token(&response.body)?;
+        *self.cached.lock().unwrap() = Some(credentials.clone());
+        Ok(credentials)
+    }
+}
+
+/// `application/x-www-form-urlencoded` encoding of one name or value
+fn form_encode(text: &str) -> String {
+    text.bytes().fold(String::new(), |mut encoded, byte| {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+        encoded
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::HttpResponse;
+
+    /// Token endpoint answering from a script and recording the requests it saw
+    #[derive(Default)]
+    struct TokenEndpoint {
+        responses: Mutex<Vec<HttpResponse>>,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    #[async_trait]
+    impl HttpTransport for Arc<TokenEndpoint> {
+        async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+            self.requests.lock().unwrap().push(request);
+            Ok(self.responses.lock().unwrap().remove(0))
+        }
+    }
+
+    fn endpoint(responses: Vec<HttpResponse>) -> Arc<TokenEndpoint> {
+        Arc::new(TokenEndpoint {
+            responses: Mutex::new(responses),
+            requests: Mutex::default(),
+        })
+    }
+
+    fn token(access_token: &str, expires_in: serde_json::Value) -> HttpResponse {
+        let body = serde_json::json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": expires_in,
+        });
+        HttpResponse::new(200, serde_json::to_vec(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_caches_and_refreshes_before_expiry() {
+        let endpoint = endpoint(vec![
+            token("first", serde_json::json!(3600)),
+            token("second", serde_json::json!("30")),
+            token("third", serde_json::json!(3600)),
+        ]);
+        let credentials =
+            OAuth2Credentials::azure_ad("contoso", "app-id", "s3cret&=", Arc::clone(&endpoint));
+
+        assert_eq!(
+            credentials.get_credentials().await.unwrap().api_key(),
+            "first"
+        );
+        assert_eq!(
+            credentials.get_credentials().await.unwrap().api_key(),
+            "first"
+        );
+
+        // Rejected by the provider: fetch again; 30s tokens fall inside the 60s margin
+        let refreshed = credentials.refresh_credentials().await.unwrap();
+        assert_eq!(refreshed.api_key(), "second");
+        assert!(refreshed.is_expired());
+        assert_eq!(
+            credentials.get_credentials().await.unwrap().api_key(),
+            "third"
+        );
+
+        let requests = endpoint.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0].url,
+            "https://login.microsoftonline.com/contoso/oauth2/v2.0/token"
+        );
+        assert_eq!(
+            String::from_utf8(requests[0].body.clone().unwrap()).unwrap(),
+            "grant_type=client_credentials&client_id=app-id&client_secret=s3cret%26%3D\
+             &scope=https%3A%2F%2Fcognitiveservices.azure.com%2F.default"
+        );
+        assert!(!format!("{credentials:?}").contains("s3cret"));
+    }
+
+    #[tokio::test]
+    async fn test_token_endpoint_errors() {
+        let endpoint = endpoint(vec![
+            HttpResponse::new(
+                401,
+                br#"{"error": "invalid_client", "error_description": "bad secret"}"#.to_vec(),
+            ),
+            HttpResponse::new(200, br#"{"token_type": "Bearer"}"#.to_vec()),
+        ]);
+        let credentials = OAuth2Credentials::new(
+            "https://idp.internal/token",
+            "gateway",
+            "secret",
+            Arc::clone(&endpoint),
+        )
+        .with_parameter("audience", "inference-gateway");
+
+        let error = credentials.get_credentials().await.unwrap_err();
+        assert!(error.to_string().contains("bad secret"));
+        assert!(credentials.get_credentials().await.is_err());
+    }
+}
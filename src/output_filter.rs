@@ -0,0 +1,422 @@
+//! Output content filtering
+//!
+//! An `OutputFilterPolicy` is an ordered list of rules applied to generated content before it
+//! reaches the caller: deny-listed terms, regular expressions and custom async
+//! `OutputValidator`s (a classifier call, a lookup against a product catalog, ...). Each rule
+//! either blocks the response or sanitizes it by replacing the offending text:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::output_filter::*;
+//! # struct CatalogValidator(Vec<String>);
+//! # impl CatalogValidator { fn new(catalog: Vec<String>) -> Self { Self(catalog) } }
+//! # #[async_trait::async_trait]
+//! # impl OutputValidator for CatalogValidator {
+//! #     async fn validate(&self, content: &serde_json::Value) -> TylResult<FilterVerdict> { Ok(FilterVerdict::Pass) }
+//! # }
+//! # async fn example(openai: impl InferenceService + 'static, catalog: Vec<String>) -> TylResult<()> {
+//! let policy = OutputFilterPolicy::new()
+//!     .with_denied_terms(["internal-only", "codename"], FilterAction::Block)
+//!     .with_pattern("api_keys", r"sk-[A-Za-z0-9]{20,}", FilterAction::Sanitize)?
+//!     .with_validator("catalog", CatalogValidator::new(catalog));
+//! let service = OutputFilterService::new(openai, policy);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Blocked responses fail with a validation error naming the rule. Responses that pass record
+//! the decision (`passed` or `sanitized`) and the rules that fired in the response metadata.
+
+use crate::postprocess::map_strings;
+use crate::*;
+use regex::Regex;
+use std::sync::Arc;
+
+/// Response metadata key holding the filter decision: `passed` or `sanitized`
+pub const OUTPUT_FILTER_DECISION_METADATA_KEY: &str = "output_filter_decision";
+/// Response metadata key listing the rules that sanitized the content, comma separated
+pub const OUTPUT_FILTER_RULES_METADATA_KEY: &str = "output_filter_rules";
+
+/// What a rule does with matching content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Fail the request
+    Block,
+    /// Replace the matching text
+    Sanitize,
+}
+
+/// Outcome of an `OutputValidator`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterVerdict {
+    Pass,
+    /// Continue with this content instead
+    Sanitize(serde_json::Value),
+    /// Fail the request for this reason
+    Block(String),
+}
+
+/// Custom check of generated content
+#[async_trait]
+pub trait OutputValidator: Send + Sync {
+    async fn validate(&self, content: &serde_json::Value) -> InferenceResult<FilterVerdict>;
+}
+
+#[derive(Clone)]
+enum Rule {
+    Pattern {
+        name: String,
+        regex: Regex,
+        action: FilterAction,
+    },
+    Validator {
+        name: String,
+        validator: Arc<dyn OutputValidator>,
+    },
+}
+
+impl Rule {
+    fn name(&self) -> &str {
+        match self {
+            Rule::Pattern { name, .. } | Rule::Validator { name, .. } => name,
+        }
+    }
+}
+
+/// Ordered output filtering rules
+#[derive(Clone)]
+pub struct OutputFilterPolicy {
+    rules: Vec<Rule>,
+    replacement: String,
+}
+
+impl std::fmt::Debug for OutputFilterPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rules: Vec<&str> = self.rules.iter().map(Rule::name).collect();
+        f.debug_struct("OutputFilterPolicy")
+            .field("rules", &rules)
+            .field("replacement", &self.replacement)
+            .finish()
+    }
+}
+
+impl Default for OutputFilterPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether any string inside `content` matches `regex`
+fn matches_any_string(content: &serde_json::Value, regex: &Regex) -> bool {
+    match content {
+        serde_json::Value::String(text) => regex.is_match(text),
+        serde_json::Value::Array(items) => items.iter().any(|v| matches_any_string(v, regex)),
+        serde_json::Value::Object(fields) => fields.values().any(|v| matches_any_string(v, regex)),
+        _ => false,
+    }
+}
+
+impl OutputFilterPolicy {
+    /// No rules; sanitized text is replaced by `[FILTERED]`
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            replacement: "[FILTERED]".to_string(),
+        }
+    }
+
+    /// Match `terms` case-insensitively, as a rule named `denied_terms`
+    pub fn with_denied_terms(
+        mut self,
+        terms: impl IntoIterator<Item = impl AsRef<str>>,
+        action: FilterAction,
+    ) -> Self {
+        let alternatives: Vec<String> = terms
+            .into_iter()
+            .map(|term| regex::escape(term.as_ref()))
+            .filter(|term| !term.is_empty())
+            .collect();
+        if alternatives.is_empty() {
+            return self;
+        }
+        let regex = Regex::new(&format!("(?i){}", alternatives.join("|")))
+            .expect("escaped terms form a valid regex");
+        self.rules.push(Rule::Pattern {
+            name: "denied_terms".to_string(),
+            regex,
+            action,
+        });
+        self
+    }
+
+    /// Match a regular expression
+    pub fn with_pattern(
+        mut self,
+        name: impl Into<String>,
+        pattern: &str,
+        action: FilterAction,
+    ) -> InferenceResult<Self> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| TylError::validation("pattern", format!("Invalid filter pattern: {e}")))?;
+        self.rules.push(Rule::Pattern {
+            name: name.into(),
+            regex,
+            action,
+        });
+        Ok(self)
+    }
+
+    pub fn with_validator(
+        mut self,
+        name: impl Into<String>,
+        validator: impl OutputValidator + 'static,
+    ) -> Self {
+        self.rules.push(Rule::Validator {
+            name: name.into(),
+            validator: Arc::new(validator),
+        });
+        self
+    }
+
+    /// Text substituted for sanitized matches (default `[FILTERED]`)
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// Run every rule over `content`, returning the filtered content and the rules that
+    /// sanitized it
+    ///
+    /// Patterns see every string of structured content; validators see the whole value.
+    pub async fn apply(
+        &self,
+        mut content: serde_json::Value,
+    ) -> InferenceResult<(serde_json::Value, Vec<String>)> {
+        let mut sanitized_by = Vec::new();
+        for rule in &self.rules {
+            match rule {
+                Rule::Pattern {
+                    name,
+                    regex,
+                    action,
+                } => {
+                    if !matches_any_string(&content, regex) {
+                        continue;
+                    }
+                    if *action == FilterAction::Block {
+                        return Err(inference_errors::content_blocked(
+                            "response",
+                            std::slice::from_ref(name),
+                        ));
+                    }
+                    let replace = |text: &str| {
+                        regex
+                            .replace_all(text, self.replacement.as_str())
+                            .into_owned()
+                    };
+                    content = map_strings(content, &replace);
+                    sanitized_by.push(name.clone());
+                }
+                Rule::Validator { name, validator } => match validator.validate(&content).await? {
+                    FilterVerdict::Pass => {}
+                    FilterVerdict::Sanitize(replacement) => {
+                        content = replacement;
+                        sanitized_by.push(name.clone());
+                    }
+                    FilterVerdict::Block(reason) => {
+                        return Err(inference_errors::content_blocked(
+                            "response",
+                            &[format!("{name}: {reason}")],
+                        ))
+                    }
+                },
+            }
+        }
+        Ok((content, sanitized_by))
+    }
+}
+
+/// Inference service decorator filtering generated content with an `OutputFilterPolicy`
+#[derive(Debug)]
+pub struct OutputFilterService<S> {
+    inner: S,
+    policy: OutputFilterPolicy,
+}
+
+impl<S: InferenceService> OutputFilterService<S> {
+    pub fn new(inner: S, policy: OutputFilterPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for OutputFilterService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let mut response = self.inner.infer(request).await?;
+        let (content, mut sanitized_by) = self
+            .policy
+            .apply(std::mem::take(&mut response.content))
+            .await?;
+        response.content = content;
+        for candidate in &mut response.candidates {
+            let (content, rules) = self
+                .policy
+                .apply(std::mem::take(&mut candidate.content))
+                .await?;
+            candidate.content = content;
+            sanitized_by.extend(rules);
+        }
+        sanitized_by.dedup();
+
+        let decision = if sanitized_by.is_empty() {
+            "passed"
+        } else {
+            "sanitized"
+        };
+        response.metadata = response
+            .metadata
+            .with_metadata(OUTPUT_FILTER_DECISION_METADATA_KEY, decision);
+        if !sanitized_by.is_empty() {
+            response.metadata = response
+                .metadata
+                .with_metadata(OUTPUT_FILTER_RULES_METADATA_KEY, sanitized_by.join(","));
+        }
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockInferenceService;
+
+    fn service(
+        answer: &str,
+        policy: OutputFilterPolicy,
+    ) -> OutputFilterService<MockInferenceService> {
+        OutputFilterService::new(
+            MockInferenceService::new()
+                .with_latency(0)
+                .with_custom_response(answer),
+            policy,
+        )
+    }
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Anything", HashMap::new(), ModelType::General)
+    }
+
+    /// Blocks answers without a `sources` field, drops empty ones
+    struct RequiresSources;
+
+    #[async_trait]
+    impl OutputValidator for RequiresSources {
+        async fn validate(&self, content: &serde_json::Value) -> InferenceResult<FilterVerdict> {
+            Ok(match content.get("sources") {
+                None => FilterVerdict::Block("no sources".to_string()),
+                Some(sources) if sources.as_array().is_some_and(Vec::is_empty) => {
+                    FilterVerdict::Sanitize(serde_json::json!({"answer": content["answer"]}))
+                }
+                Some(_) => FilterVerdict::Pass,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sanitizes_terms_and_patterns() {
+        let policy = OutputFilterPolicy::new()
+            .with_denied_terms(["Project Falcon"], FilterAction::Sanitize)
+            .with_pattern("api_keys", r"sk-[A-Za-z0-9]{8,}", FilterAction::Sanitize)
+            .unwrap()
+            .with_replacement("***");
+        let response = service("project falcon uses key sk-abcdef123456", policy)
+            .infer(request())
+            .await
+            .unwrap();
+        assert_eq!(response.content, serde_json::json!("*** uses key ***"));
+        let metadata = &response.metadata.metadata;
+        assert_eq!(metadata[OUTPUT_FILTER_DECISION_METADATA_KEY], "sanitized");
+        assert_eq!(
+            metadata[OUTPUT_FILTER_RULES_METADATA_KEY],
+            "denied_terms,api_keys"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocks_and_passes() {
+        let policy =
+            OutputFilterPolicy::new().with_denied_terms(["confidential"], FilterAction::Block);
+        let error = service(r#"{"note": "CONFIDENTIAL numbers"}"#, policy.clone())
+            .infer(request())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("denied_terms"));
+
+        let response = service("public numbers", policy)
+            .infer(request())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.metadata.metadata[OUTPUT_FILTER_DECISION_METADATA_KEY],
+            "passed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_validators() {
+        let policy = OutputFilterPolicy::new().with_validator("sources", RequiresSources);
+        let error = service(r#"{"answer": "42"}"#, policy.clone())
+            .infer(request())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("sources: no sources"));
+
+        let response = service(r#"{"answer": "42", "sources": []}"#, policy)
+            .infer(request())
+            .await
+            .unwrap();
+        assert_eq!(response.content, serde_json::json!({"answer": "42"}));
+        assert_eq!(
+            response.metadata.metadata[OUTPUT_FILTER_RULES_METADATA_KEY],
+            "sources"
+        );
+    }
+}
@@ -0,0 +1,431 @@
+//! PII redaction before requests leave the process
+//!
+//! `PiiRedactor` finds personal data in text with pluggable `PiiRecognizer`s (regular
+//! expressions for e-mail addresses, credit card numbers and phone numbers by default) and
+//! replaces every value with a numbered placeholder such as `[EMAIL_1]`. The placeholders and
+//! the values they stand for are kept in a `PiiVault`, so the same value always gets the same
+//! placeholder and a response that repeats a placeholder can be restored locally.
+//!
+//! `PiiRedactionService` masks the request parameters before the template is rendered, so
+//! external providers never see the values:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::pii::*;
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let service = PiiRedactionService::new(openai, PiiRedactor::new())
+//!     .with_restored_placeholders(true);
+//! // "Reply to {{email}}" is sent as "Reply to [EMAIL_1]"; "[EMAIL_1]" in the answer is
+//! // turned back into the address
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Templates are trusted and left as is; only parameters carry user data.
+
+use crate::postprocess::map_strings;
+use crate::*;
+use regex::Regex;
+use std::sync::Arc;
+
+/// Response metadata key holding the number of distinct values masked in the request
+pub const PII_REDACTED_METADATA_KEY: &str = "pii_redacted";
+
+/// Finds one kind of personal data in text
+pub trait PiiRecognizer: Send + Sync {
+    /// Kind used in placeholders, e.g. `EMAIL`
+    fn kind(&self) -> &str;
+
+    /// Byte ranges of every value in `text`
+    fn find(&self, text: &str) -> Vec<(usize, usize)>;
+}
+
+/// `PiiRecognizer` matching a regular expression, optionally confirmed by a validator
+#[derive(Debug, Clone)]
+pub struct RegexRecognizer {
+    kind: String,
+    regex: Regex,
+    validator: Option<fn(&str) -> bool>,
+}
+
+impl RegexRecognizer {
+    pub fn new(kind: impl Into<String>, pattern: &str) -> InferenceResult<Self> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| TylError::validation("pattern", format!("Invalid PII pattern: {e}")))?;
+        Ok(Self {
+            kind: kind.into(),
+            regex,
+            validator: None,
+        })
+    }
+
+    /// Only report matches for which `validator` returns true
+    pub fn with_validator(mut self, validator: fn(&str) -> bool) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    pub fn email() -> Self {
+        Self::new("EMAIL", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}")
+            .expect("valid built-in regex")
+    }
+
+    /// Card numbers of 13 to 19 digits, optionally grouped, that pass the Luhn check
+    pub fn credit_card() -> Self {
+        Self::new("CARD", r"\b(?:\d[ -]?){12,18}\d\b")
+            .expect("valid built-in regex")
+            .with_validator(luhn_valid)
+    }
+
+    pub fn phone() -> Self {
+        Self::new("PHONE", r"\+?\(?\d[\d\s().-]{6,}\d").expect("valid built-in regex")
+    }
+}
+
+impl PiiRecognizer for RegexRecognizer {
+    fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    fn find(&self, text: &str) -> Vec<(usize, usize)> {
+        self.regex
+            .find_iter(text)
+            .filter(|m| self.validator.map_or(true, |valid| valid(m.as_str())))
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+}
+
+/// Luhn checksum over the digits of `number`
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(position, &digit)| match position % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    digits.len() >= 13 && sum % 10 == 0
+}
+
+/// Placeholders issued by a `PiiRedactor` and the values they replace
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PiiVault {
+    /// (placeholder, original value), in issue order
+    entries: Vec<(String, String)>,
+}
+
+impl PiiVault {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Placeholder for `value`, issuing the next one of `kind` on first sight
+    fn placeholder(&mut self, kind: &str, value: &str) -> String {
+        if let Some((placeholder, _)) = self.entries.iter().find(|(_, seen)| seen == value) {
+            return placeholder.clone();
+        }
+        let prefix = format!("[{}_", kind.to_uppercase());
+        let number = self
+            .entries
+            .iter()
+            .filter(|(placeholder, _)| placeholder.starts_with(&prefix))
+            .count()
+            + 1;
+        let placeholder = format!("{prefix}{number}]");
+        self.entries.push((placeholder.clone(), value.to_string()));
+        placeholder
+    }
+
+    /// `text` with every known placeholder replaced by its original value
+    pub fn restore(&self, text: &str) -> String {
+        self.entries
+            .iter()
+            .fold(text.to_string(), |text, (placeholder, value)| {
+                text.replace(placeholder, value)
+            })
+    }
+}
+
+/// Masks personal data found by its recognizers
+#[derive(Clone)]
+pub struct PiiRedactor {
+    recognizers: Vec<Arc<dyn PiiRecognizer>>,
+}
+
+impl std::fmt::Debug for PiiRedactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kinds: Vec<&str> = self.recognizers.iter().map(|r| r.kind()).collect();
+        f.debug_struct("PiiRedactor")
+            .field("recognizers", &kinds)
+            .finish()
+    }
+}
+
+impl Default for PiiRedactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PiiRedactor {
+    /// E-mail addresses, credit card numbers and phone numbers
+    pub fn new() -> Self {
+        Self::empty()
+            .with_recognizer(RegexRecognizer::email())
+            .with_recognizer(RegexRecognizer::credit_card())
+            .with_recognizer(RegexRecognizer::phone())
+    }
+
+    /// No recognizers
+    pub fn empty() -> Self {
+        Self {
+            recognizers: Vec::new(),
+        }
+    }
+
+    /// Add a recognizer; where matches overlap, recognizers added earlier win
+    pub fn with_recognizer(mut self, recognizer: impl PiiRecognizer + 'static) -> Self {
+        self.recognizers.push(Arc::new(recognizer));
+        self
+    }
+
+    /// `text` with every recognized value replaced by its placeholder from `vault`
+    pub fn redact(&self, text: &str, vault: &mut PiiVault) -> String {
+        let mut matches: Vec<(usize, usize, &str)> = Vec::new();
+        for recognizer in &self.recognizers {
+            for (start, end) in recognizer.find(text) {
+                if matches.iter().all(|(s, e, _)| end <= *s || start >= *e) {
+                    matches.push((start, end, recognizer.kind()));
+                }
+            }
+        }
+        matches.sort_unstable_by_key(|(start, _, _)| *start);
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end, kind) in matches {
+            redacted.push_str(&text[cursor..start]);
+            redacted.push_str(&vault.placeholder(kind, &text[start..end]));
+            cursor = end;
+        }
+        redacted.push_str(&text[cursor..]);
+        redacted
+    }
+}
+
+/// Inference service decorator masking personal data in request parameters
+#[derive(Debug)]
+pub struct PiiRedactionService<S> {
+    inner: S,
+    redactor: PiiRedactor,
+    restore: bool,
+}
+
+impl<S: InferenceService> PiiRedactionService<S> {
+    pub fn new(inner: S, redactor: PiiRedactor) -> Self {
+        Self {
+            inner,
+            redactor,
+            restore: false,
+        }
+    }
+
+    /// Put the original values back where the response repeats a placeholder
+    pub fn with_restored_placeholders(mut self, restore: bool) -> Self {
+        self.restore = restore;
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Mask the parameters of `request`, numbering placeholders in parameter name order
+    fn redact_request(&self, request: &mut InferenceRequest) -> PiiVault {
+        let mut vault = PiiVault::new();
+        let mut names: Vec<String> = request.parameters.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            if let Some(value) = request.parameters.get_mut(&name) {
+                *value = self.redactor.redact(value, &mut vault);
+            }
+        }
+        vault
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for PiiRedactionService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let vault = self.redact_request(&mut request);
+        let mut response = self.inner.infer(request).await?;
+        if vault.is_empty() {
+            return Ok(response);
+        }
+
+        if self.restore {
+            let restore = |text: &str| vault.restore(text);
+            response.content = map_strings(response.content, &restore);
+            for candidate in &mut response.candidates {
+                candidate.content = map_strings(std::mem::take(&mut candidate.content), &restore);
+            }
+        }
+        response.metadata = response
+            .metadata
+            .with_metadata(PII_REDACTED_METADATA_KEY, vault.len().to_string());
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Echoes the rendered prompt, recording it
+    #[derive(Default)]
+    struct Echo {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl InferenceService for Echo {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            let prompt = request.render_template();
+            self.prompts.lock().unwrap().push(prompt.clone());
+            Ok(InferenceResponse::from_string(
+                format!("Echo: {prompt}"),
+                "gpt-4o-mini".to_string(),
+                TokenUsage::new(1, 1),
+                1,
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["gpt-4o-mini".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    #[test]
+    fn test_redacts_with_stable_placeholders() {
+        let redactor = PiiRedactor::new();
+        let mut vault = PiiVault::new();
+        let text = "Mail ana@example.com or bo@example.org, card 4111 1111 1111 1111, \
+                    call +34 600 123 456. Again: ana@example.com";
+        let redacted = redactor.redact(text, &mut vault);
+        assert_eq!(
+            redacted,
+            "Mail [EMAIL_1] or [EMAIL_2], card [CARD_1], call [PHONE_1]. Again: [EMAIL_1]"
+        );
+        assert_eq!(vault.len(), 4);
+        assert_eq!(vault.restore(&redacted), text);
+    }
+
+    #[test]
+    fn test_card_numbers_must_pass_luhn() {
+        let redactor = PiiRedactor::empty().with_recognizer(RegexRecognizer::credit_card());
+        let mut vault = PiiVault::new();
+        assert_eq!(
+            redactor.redact("order 1234 5678 9012 3456", &mut vault),
+            "order 1234 5678 9012 3456"
+        );
+        assert!(vault.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_service_masks_parameters_and_restores_response() {
+        let mut parameters = HashMap::new();
+        parameters.insert("customer".to_string(), "ana@example.com".to_string());
+        let request = InferenceRequest::new(
+            "Write to {{customer}} at support@tyl.dev",
+            parameters,
+            ModelType::General,
+        );
+
+        let service = PiiRedactionService::new(Echo::default(), PiiRedactor::new());
+        let response = service.infer(request.clone()).await.unwrap();
+        assert_eq!(
+            service.inner().prompts.lock().unwrap()[0],
+            "Write to [EMAIL_1] at support@tyl.dev"
+        );
+        assert_eq!(
+            response.content,
+            serde_json::json!("Echo: Write to [EMAIL_1] at support@tyl.dev")
+        );
+        assert_eq!(response.metadata.metadata[PII_REDACTED_METADATA_KEY], "1");
+
+        let service = PiiRedactionService::new(Echo::default(), PiiRedactor::new())
+            .with_restored_placeholders(true);
+        let response = service.infer(request).await.unwrap();
+        assert_eq!(
+            response.content,
+            serde_json::json!("Echo: Write to ana@example.com at support@tyl.dev")
+        );
+    }
+
+    #[test]
+    fn test_custom_recognizer() {
+        let redactor = PiiRedactor::empty()
+            .with_recognizer(RegexRecognizer::new("iban", r"\bES\d{22}\b").unwrap());
+        let mut vault = PiiVault::new();
+        assert_eq!(
+            redactor.redact("IBAN ES9121000418450200051332", &mut vault),
+            "IBAN [IBAN_1]"
+        );
+        assert!(RegexRecognizer::new("bad", "(").is_err());
+    }
+}
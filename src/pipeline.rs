@@ -0,0 +1,456 @@
+//! Multi-step inference pipelines over long inputs
+//!
+//! `map_reduce` handles inputs too long for one prompt: the input is split into chunks of at
+//! most `chunk_tokens` tokens (counted with the service's tokenizer, on paragraph and sentence boundaries
+//! where possible), the map template runs on every chunk concurrently, and the reduce template combines
+//! the partial results into the final response:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::pipeline::*;
+//! # async fn example(service: impl InferenceService + 'static, transcript: String) -> TylResult<()> {
+//! let config = MapReduceConfig::new(
+//!     "List the action items in:\n{{chunk}}",
+//!     "Merge these action item lists, removing duplicates:\n{{partials}}",
+//! )
+//! .with_chunk_tokens(3000);
+//! let response = map_reduce(&service, &transcript, &config).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The response reports the token usage of every call. Partial results are concatenated as is,
+//! so they must fit the reduce prompt together.
+//!
+//! `summarize` is a ready-made hierarchical summarization on the same machinery: chunks overlap
+//! so facts spanning a boundary survive, section summaries that are still too long for one
+//! prompt are summarized again level by level, and a final call writes a summary of the
+//! requested length:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::pipeline::*;
+//! # async fn example(service: impl InferenceService + 'static, report: String) -> TylResult<()> {
+//! let summary = summarize(&service, &report, &SummarizeOptions::new().with_target_words(150)).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::ensemble::JoinAll;
+use crate::*;
+use std::time::Instant;
+
+/// Response metadata key holding the number of chunks the input was split into
+pub const CHUNKS_METADATA_KEY: &str = "map_reduce_chunks";
+/// Response metadata key holding the number of section-summary levels `summarize` ran
+pub const SUMMARY_LEVELS_METADATA_KEY: &str = "summary_levels";
+
+const SUMMARIZE_SECTION_TEMPLATE: &str = "Summarize this section of a longer document. Keep the \
+facts, names, numbers and decisions that matter and add nothing that is not in the text.\n\n{{chunk}}";
+const SUMMARIZE_FINAL_TEMPLATE: &str = "Summarize the following text in about {{target_words}} \
+words. If it consists of section summaries, combine them into one coherent summary.\n\n{{partials}}";
+
+/// Templates and limits of a `map_reduce` run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapReduceConfig {
+    /// Template run on every chunk; the chunk is bound to `chunk_parameter`
+    pub map_template: String,
+    /// Template combining the partial results, bound to `partials_parameter`
+    pub reduce_template: String,
+    /// Maximum tokens per chunk
+    pub chunk_tokens: usize,
+    pub model_type: ModelType,
+    /// Map calls in flight at once
+    pub max_concurrency: usize,
+    pub chunk_parameter: String,
+    pub partials_parameter: String,
+    /// Parameters shared by the map and reduce templates
+    pub parameters: HashMap<String, String>,
+}
+
+impl MapReduceConfig {
+    /// 2000-token chunks bound to `{{chunk}}`, partials bound to `{{partials}}`, 8 concurrent
+    /// map calls on `ModelType::General`
+    pub fn new(map_template: impl Into<String>, reduce_template: impl Into<String>) -> Self {
+        Self {
+            map_template: map_template.into(),
+            reduce_template: reduce_template.into(),
+            chunk_tokens: 2000,
+            model_type: ModelType::General,
+            max_concurrency: 8,
+            chunk_parameter: "chunk".to_string(),
+            partials_parameter: "partials".to_string(),
+            parameters: HashMap::new(),
+        }
+    }
+
+    pub fn with_chunk_tokens(mut self, chunk_tokens: usize) -> Self {
+        self.chunk_tokens = chunk_tokens.max(1);
+        self
+    }
+
+    pub fn with_model_type(mut self, model_type: ModelType) -> Self {
+        self.model_type = model_type;
+        self
+    }
+
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn with_parameter(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parameters.insert(name.into(), value.into());
+        self
+    }
+
+    fn request(&self, template: &str, name: &str, value: String) -> InferenceRequest {
+        let mut parameters = self.parameters.clone();
+        parameters.insert(name.to_string(), value);
+        InferenceRequest::new(template, parameters, self.model_type)
+    }
+}
+
+/// Text of a partial result as fed to the reduce template
+fn partial_text(response: &InferenceResponse) -> String {
+    match &response.content {
+        serde_json::Value::String(text) => text.clone(),
+        content => content.to_string(),
+    }
+}
+
+fn add_usage(usage: &mut TokenUsage, response: &InferenceResponse) {
+    *usage = TokenUsage::new(
+        usage.prompt_tokens + response.metadata.token_usage.prompt_tokens,
+        usage.completion_tokens + response.metadata.token_usage.completion_tokens,
+    );
+}
+
+/// Partial results of the map template over `chunks`, in order
+async fn map_chunks<S: InferenceService + ?Sized>(
+    service: &S,
+    chunks: &[String],
+    config: &MapReduceConfig,
+    usage: &mut TokenUsage,
+) -> InferenceResult<Vec<String>> {
+    let mut partials = Vec::with_capacity(chunks.len());
+    for batch in chunks.chunks(config.max_concurrency) {
+        let results = JoinAll::new(batch.iter().map(|chunk| {
+            service.infer(config.request(
+                &config.map_template,
+                &config.chunk_parameter,
+                chunk.clone(),
+            ))
+        }))
+        .await;
+        for result in results {
+            let response = result?;
+            add_usage(usage, &response);
+            partials.push(partial_text(&response));
+        }
+    }
+    Ok(partials)
+}
+
+/// Reduce template over `partials`, reporting `usage` plus its own
+async fn reduce<S: InferenceService + ?Sized>(
+    service: &S,
+    partials: &[String],
+    config: &MapReduceConfig,
+    mut usage: TokenUsage,
+) -> InferenceResult<InferenceResponse> {
+    let mut response = service
+        .infer(config.request(
+            &config.reduce_template,
+            &config.partials_parameter,
+            partials.join("\n\n"),
+        ))
+        .await?;
+    add_usage(&mut usage, &response);
+    response.metadata.token_usage = usage;
+    Ok(response)
+}
+
+/// Run `config.map_template` over every chunk of `input`, then `config.reduce_template` over
+/// the partial results
+pub async fn map_reduce<S: InferenceService + ?Sized>(
+    service: &S,
+    input: &str,
+    config: &MapReduceConfig,
+) -> InferenceResult<InferenceResponse> {
+    let started = Instant::now();
+    let chunks: Vec<String> = TextChunker::new(config.chunk_tokens)
+        .split(service, input)?
+        .into_iter()
+        .map(|chunk| chunk.text)
+        .collect();
+    if chunks.is_empty() {
+        return Err(TylError::validation("input", "Nothing to map over"));
+    }
+
+    let mut usage = TokenUsage::new(0, 0);
+    let partials = map_chunks(service, &chunks, config, &mut usage).await?;
+    let mut response = reduce(service, &partials, config, usage).await?;
+    response.metadata.processing_time_ms = started.elapsed().as_millis() as u64;
+    response.metadata = response
+        .metadata
+        .with_metadata(CHUNKS_METADATA_KEY, chunks.len().to_string());
+    Ok(response)
+}
+
+/// Settings of `summarize`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummarizeOptions {
+    pub model_type: ModelType,
+    /// Maximum tokens per section, overlap included
+    pub chunk_tokens: usize,
+    /// Tokens repeated from the end of the previous section
+    pub overlap_tokens: usize,
+    /// Approximate length of the summary
+    pub target_words: usize,
+    /// Section summaries in flight at once
+    pub max_concurrency: usize,
+    /// Levels of section summaries before the final summary is written regardless of length
+    pub max_levels: usize,
+}
+
+impl Default for SummarizeOptions {
+    fn default() -> Self {
+        Self {
+            model_type: ModelType::General,
+            chunk_tokens: 2000,
+            overlap_tokens: 100,
+            target_words: 200,
+            max_concurrency: 8,
+            max_levels: 3,
+        }
+    }
+}
+
+impl SummarizeOptions {
+    /// 2000-token sections overlapping by 100 tokens, summarized in about 200 words
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model_type(mut self, model_type: ModelType) -> Self {
+        self.model_type = model_type;
+        self
+    }
+
+    pub fn with_chunk_tokens(mut self, chunk_tokens: usize) -> Self {
+        self.chunk_tokens = chunk_tokens.max(1);
+        self
+    }
+
+    pub fn with_overlap_tokens(mut self, overlap_tokens: usize) -> Self {
+        self.overlap_tokens = overlap_tokens;
+        self
+    }
+
+    pub fn with_target_words(mut self, target_words: usize) -> Self {
+        self.target_words = target_words.max(1);
+        self
+    }
+
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn with_max_levels(mut self, max_levels: usize) -> Self {
+        self.max_levels = max_levels;
+        self
+    }
+}
+
+/// Summarize `document` hierarchically
+///
+/// Documents that fit one section are summarized in a single call. Longer ones are split into
+/// overlapping sections whose summaries are combined; while the combined summaries still exceed
+/// one section they are summarized again, up to `max_levels` levels.
+pub async fn summarize<S: InferenceService + ?Sized>(
+    service: &S,
+    document: &str,
+    options: &SummarizeOptions,
+) -> InferenceResult<InferenceResponse> {
+    let started = Instant::now();
+    let config = MapReduceConfig::new(SUMMARIZE_SECTION_TEMPLATE, SUMMARIZE_FINAL_TEMPLATE)
+        .with_model_type(options.model_type)
+        .with_max_concurrency(options.max_concurrency)
+        .with_parameter("target_words", options.target_words.to_string());
+    let chunker = TextChunker::new(options.chunk_tokens).with_overlap(options.overlap_tokens);
+
+    let mut usage = TokenUsage::new(0, 0);
+    let mut levels = 0;
+    let mut text = vec![document.trim().to_string()];
+    loop {
+        let joined = text.join("\n\n");
+        if joined.is_empty() {
+            return Err(TylError::validation("document", "Nothing to summarize"));
+        }
+        if levels >= options.max_levels || service.count_tokens(&joined)? <= options.chunk_tokens {
+            break;
+        }
+        let sections: Vec<String> = chunker
+            .split(service, &joined)?
+            .into_iter()
+            .map(|section| section.text)
+            .collect();
+        text = map_chunks(service, &sections, &config, &mut usage).await?;
+        levels += 1;
+    }
+
+    let mut response = reduce(service, &text, &config, usage).await?;
+    response.metadata.processing_time_ms = started.elapsed().as_millis() as u64;
+    response.metadata = response
+        .metadata
+        .with_metadata(SUMMARY_LEVELS_METADATA_KEY, levels.to_string());
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Counts words as tokens and answers with the prompt's first word, recording prompts
+    #[derive(Default)]
+    struct WordService {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl InferenceService for WordService {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            let prompt = request.render_template();
+            self.prompts.lock().unwrap().push(prompt.clone());
+            let answer = prompt.split_whitespace().nth(1).unwrap_or("").to_string();
+            Ok(InferenceResponse::from_string(
+                answer,
+                "words".to_string(),
+                TokenUsage::new(2, 1),
+                1,
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["words".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.split_whitespace().count())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_map_reduce() {
+        let service = WordService::default();
+        let config = MapReduceConfig::new("Map: {{chunk}}", "Reduce: {{partials}} ({{lang}})")
+            .with_chunk_tokens(2)
+            .with_max_concurrency(2)
+            .with_parameter("lang", "en");
+
+        let response = map_reduce(&service, "Alpha beta. Gamma delta. Epsilon.", &config)
+            .await
+            .unwrap();
+        let prompts = service.prompts.lock().unwrap().clone();
+        assert_eq!(
+            &prompts[..3],
+            ["Map: Alpha beta.", "Map: Gamma delta.", "Map: Epsilon."]
+        );
+        assert_eq!(prompts[3], "Reduce: Alpha\n\nGamma\n\nEpsilon. (en)");
+        assert_eq!(response.content, "Alpha");
+        assert_eq!(response.metadata.token_usage, TokenUsage::new(8, 4));
+        assert_eq!(
+            response.metadata.metadata.get(CHUNKS_METADATA_KEY),
+            Some(&"3".to_string())
+        );
+
+        assert!(map_reduce(&service, "", &config).await.is_err());
+    }
+
+    /// Answers with the first three words after the template's instructions
+    #[derive(Default)]
+    struct FirstWords {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl InferenceService for FirstWords {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            let prompt = request.render_template();
+            self.prompts.lock().unwrap().push(prompt.clone());
+            let (_, text) = prompt.split_once("\n\n").unwrap_or_default();
+            let words: Vec<&str> = text.split_whitespace().take(3).collect();
+            Ok(InferenceResponse::from_string(
+                words.join(" "),
+                "words".to_string(),
+                TokenUsage::new(1, 1),
+                1,
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["words".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.split_whitespace().count())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarize_hierarchically() {
+        let service = FirstWords::default();
+        let document: Vec<String> = (1..=30)
+            .map(|n| {
+                if n % 5 == 0 {
+                    format!("w{n}.")
+                } else {
+                    format!("w{n}")
+                }
+            })
+            .collect();
+        let options = SummarizeOptions::new()
+            .with_chunk_tokens(8)
+            .with_overlap_tokens(2)
+            .with_target_words(50)
+            .with_max_levels(2);
+
+        let response = summarize(&service, &document.join(" "), &options)
+            .await
+            .unwrap();
+        let prompts = service.prompts.lock().unwrap().clone();
+        // Second section starts with the overlap from the first
+        assert!(prompts[1].ends_with("\n\nw5. w6 w7 w8 w9 w10. w11 w12"));
+        // Five sections, three summaries of their summaries, then the final summary
+        assert_eq!(prompts.len(), 9);
+        assert!(prompts.last().unwrap().contains("about 50 words"));
+        assert_eq!(
+            response.metadata.metadata.get(SUMMARY_LEVELS_METADATA_KEY),
+            Some(&"2".to_string())
+        );
+        assert_eq!(
+            response.metadata.token_usage.total_tokens as usize,
+            prompts.len() * 2
+        );
+
+        // Short documents take a single call
+        let short = FirstWords::default();
+        let response = summarize(&short, "Just a few words.", &options)
+            .await
+            .unwrap();
+        assert_eq!(response.content, "Just a few");
+        assert_eq!(short.prompts.lock().unwrap().len(), 1);
+        assert!(summarize(&short, "  ", &options).await.is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! Response post-processing plugins
+//!
+//! Post-processors transform `InferenceResponse.content` after generation. They are registered
+//! by name in a `PostProcessorRegistry` and selected per request through the
+//! `post_process` metadata key, which `PromptTemplate::to_request` fills from the template's
+//! frontmatter. `PostProcessingService` applies the selected processors in order.
+
+use crate::*;
+use regex::Regex;
+use std::sync::{Arc, OnceLock};
+
+/// Request metadata key holding comma-separated post-processor names
+pub const POST_PROCESS_METADATA_KEY: &str = "post_process";
+
+/// Named transformation applied to response content
+pub trait PostProcessor: Send + Sync {
+    /// Name used to select this processor in templates
+    fn name(&self) -> &str;
+
+    /// Transform response content
+    fn process(&self, content: serde_json::Value) -> InferenceResult<serde_json::Value>;
+}
+
+/// Apply `f` to every string inside a JSON value
+pub(crate) fn map_strings(
+    value: serde_json::Value,
+    f: &dyn Fn(&str) -> String,
+) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => serde_json::Value::String(f(&text)),
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(|v| map_strings(v, f)).collect())
+        }
+        serde_json::Value::Object(fields) => serde_json::Value::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k, map_strings(v, f)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("valid built-in regex"))
+}
+
+/// Turns almost-JSON text (code fences, surrounding prose, trailing commas) into JSON
+///
+/// Content that cannot be repaired is left unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonRepair;
+
+impl JsonRepair {
+    fn repair(text: &str) -> Option<serde_json::Value> {
+        static TRAILING_COMMA: OnceLock<Regex> = OnceLock::new();
+
+        let mut candidate = text.trim();
+        if candidate.starts_with("```") {
+            candidate = candidate
+                .split_once('\n')
+                .map(|(_, rest)| rest)
+                .unwrap_or_default();
+            candidate = candidate.trim_end().trim_end_matches("```").trim();
+        }
+        if let Ok(json) = serde_json::from_str(candidate) {
+            return Some(json);
+        }
+
+        let start = candidate.find(['{', '['])?;
+        let end = candidate.rfind(['}', ']'])?;
+        if end <= start {
+            return None;
+        }
+        let candidate = &candidate[start..=end];
+        if let Ok(json) = serde_json::from_str(candidate) {
+            return Some(json);
+        }
+
+        let without_trailing_commas =
+            regex(&TRAILING_COMMA, r",\s*([}\]])").replace_all(candidate, "$1");
+        serde_json::from_str(&without_trailing_commas).ok()
+    }
+}
+
+impl PostProcessor for JsonRepair {
+    fn name(&self) -> &str {
+        "json-repair"
+    }
+
+    fn process(&self, content: serde_json::Value) -> InferenceResult<serde_json::Value> {
+        match content {
+            serde_json::Value::String(text) => {
+                Ok(Self::repair(&text).unwrap_or(serde_json::Value::String(text)))
+            }
+            other => Ok(other),
+        }
+    }
+}
+
+/// Removes Markdown formatting from every string in the content
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkdownStrip;
+
+impl MarkdownStrip {
+    fn strip(text: &str) -> String {
+        static FENCE: OnceLock<Regex> = OnceLock::new();
+        static HEADING: OnceLock<Regex> = OnceLock::new();
+        static BULLET: OnceLock<Regex> = OnceLock::new();
+        static BOLD: OnceLock<Regex> = OnceLock::new();
+        static ITALIC: OnceLock<Regex> = OnceLock::new();
+        static INLINE_CODE: OnceLock<Regex> = OnceLock::new();
+        static LINK: OnceLock<Regex> = OnceLock::new();
+
+        let text = regex(&FENCE, r"(?m)^```[^\n]*\n?").replace_all(text, "");
+        let text = regex(&HEADING, r"(?m)^#{1,6}\s+").replace_all(&text, "");
+        let text = regex(&BULLET, r"(?m)^\s*[-*+]\s+").replace_all(&text, "");
+        let text = regex(&BOLD, r"(\*\*|__)(.+?)(\*\*|__)").replace_all(&text, "$2");
+        let text = regex(&ITALIC, r"\*([^*\n]+)\*").replace_all(&text, "$1");
+        let text = regex(&INLINE_CODE, r"`([^`\n]+)`").replace_all(&text, "$1");
+        let text = regex(&LINK, r"\[([^\]]+)\]\([^)]*\)").replace_all(&text, "$1");
+        text.trim().to_string()
+    }
+}
+
+impl PostProcessor for MarkdownStrip {
+    fn name(&self) -> &str {
+        "markdown-strip"
+    }
+
+    fn process(&self, content: serde_json::Value) -> InferenceResult<serde_json::Value> {
+        Ok(map_strings(content, &Self::strip))
+    }
+}
+
+/// Extracts `[n]` citation markers from text content
+///
+/// String content becomes `{"text": ..., "citations": [n, ...]}` with markers removed and
+/// citations listed in order of first appearance. Other content is left unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CitationParse;
+
+impl PostProcessor for CitationParse {
+    fn name(&self) -> &str {
+        "citation-parse"
+    }
+
+    fn process(&self, content: serde_json::Value) -> InferenceResult<serde_json::Value> {
+        static MARKER: OnceLock<Regex> = OnceLock::new();
+        static SPACE_BEFORE_PUNCTUATION: OnceLock<Regex> = OnceLock::new();
+
+        let text = match content {
+            serde_json::Value::String(text) => text,
+            other => return Ok(other),
+        };
+
+        let marker = regex(&MARKER, r"\s?\[(\d+)\]");
+        let mut citations: Vec<u64> = Vec::new();
+        for capture in marker.captures_iter(&text) {
+            if let Ok(number) = capture[1].parse() {
+                if !citations.contains(&number) {
+                    citations.push(number);
+                }
+            }
+        }
+
+        let cleaned = marker.replace_all(&text, "");
+        let cleaned =
+            regex(&SPACE_BEFORE_PUNCTUATION, r"\s+([.,;:!?])").replace_all(&cleaned, "$1");
+
+        Ok(serde_json::json!({
+            "text": cleaned.trim(),
+            "citations": citations,
+        }))
+    }
+}
+
+/// Masks e-mail addresses, credit card numbers, and phone numbers in every string
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PiiScrub;
+
+impl PiiScrub {
+    fn scrub(text: &str) -> String {
+        static EMAIL: OnceLock<Regex> = OnceLock::new();
+        static CARD: OnceLock<Regex> = OnceLock::new();
+        static PHONE: OnceLock<Regex> = OnceLock::new();
+
+        let text = regex(&EMAIL, r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}")
+            .replace_all(text, "[EMAIL]");
+        let text = regex(&CARD, r"\b(?:\d[ -]?){12,18}\d\b").replace_all(&text, "[CARD]");
+        let text = regex(&PHONE, r"\+?\(?\d[\d\s().-]{6,}\d").replace_all(&text, "[PHONE]");
+        text.into_owned()
+    }
+}
+
+impl PostProcessor for PiiScrub {
+    fn name(&self) -> &str {
+        "pii-scrub"
+    }
+
+    fn process(&self, content: serde_json::Value) -> InferenceResult<serde_json::Value> {
+        Ok(map_strings(content, &Self::scrub))
+    }
+}
+
+/// Registry of named post-processors
+#[derive(Clone, Default)]
+pub struct PostProcessorRegistry {
+    processors: HashMap<String, Arc<dyn PostProcessor>>,
+}
+
+impl std::fmt::Debug for PostProcessorRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostProcessorRegistry")
+            .field("processors", &self.names())
+            .finish()
+    }
+}
+
+impl PostProcessorRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the built-in processors
+    /// (`json-repair`, `markdown-strip`, `citation-parse`, `pii-scrub`)
+    pub fn with_defaults() -> Self {
+        Self::new()
+            .with_processor(JsonRepair)
+            .with_processor(MarkdownStrip)
+            .with_processor(CitationParse)
+            .with_processor(PiiScrub)
+    }
+
+    pub fn with_processor(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.register(processor);
+        self
+    }
+
+    /// Register a processor, replacing any processor with the same name
+    pub fn register(&mut self, processor: impl PostProcessor + 'static) {
+        self.processors
+            .insert(processor.name().to_string(), Arc::new(processor));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn PostProcessor>> {
+        self.processors.get(name).cloned()
+    }
+
+    /// Registered processor names, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.processors.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Resolve processor names, failing on the first unknown one
+    pub fn resolve(&self, names: &[String]) -> InferenceResult<Vec<Arc<dyn PostProcessor>>> {
+        names
+            .iter()
+            .map(|name| {
+                self.get(name)
+                    .ok_or_else(|| inference_errors::unknown_post_processor(name))
+            })
+            .collect()
+    }
+
+    /// Apply the named processors in order to every candidate, recording them in the response
+    /// metadata
+    pub fn apply(
+        &self,
+        names: &[String],
+        mut response: InferenceResponse,
+    ) -> InferenceResult<InferenceResponse> {
+        let processors = self.resolve(names)?;
+        if processors.is_empty() {
+            return Ok(response);
+        }
+        let process = |mut content| {
+            for processor in &processors {
+                content = processor.process(content)?;
+            }
+            Ok::<_, TylError>(content)
+        };
+
+        for candidate in &mut response.candidates {
+            candidate.content = process(std::mem::take(&mut candidate.content))?;
+        }
+        response.content = match response.candidates.first() {
+            Some(first) => first.content.clone(),
+            None => process(response.content)?,
+        };
+        response.metadata = response
+            .metadata
+            .with_metadata(POST_PROCESS_METADATA_KEY, names.join(","));
+        Ok(response)
+    }
+}
+
+/// Post-processor names selected by a request's metadata
+pub fn selected_post_processors(request: &InferenceRequest) -> Vec<String> {
+    request
+        .metadata
+        .get(POST_PROCESS_METADATA_KEY)
+        .map(|names| {
+            names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Inference service decorator applying the post-processors selected by each request
+#[derive(Debug, Clone)]
+pub struct PostProcessingService<S> {
+    inner: S,
+    registry: Arc<PostProcessorRegistry>,
+}
+
+impl<S: InferenceService> PostProcessingService<S> {
+    pub fn new(inner: S, registry: PostProcessorRegistry) -> Self {
+        Self {
+            inner,
+            registry: Arc::new(registry),
+        }
+    }
+
+    pub fn registry(&self) -> &PostProcessorRegistry {
+        &self.registry
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for PostProcessingService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let names = selected_post_processors(&request);
+        // Fail fast on unknown processors before spending tokens
+        self.registry.resolve(&names)?;
+
+        let response = self.inner.infer(request).await?;
+        self.registry.apply(&names, response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn text(value: &str) -> serde_json::Value {
+        serde_json::Value::String(value.to_string())
+    }
+
+    #[test]
+    fn test_json_repair() {
+        let repaired = JsonRepair
+            .process(text("```json\n{\"answer\": 42,}\n```"))
+            .unwrap();
+        assert_eq!(repaired, json!({"answer": 42}));
+
+        let repaired = JsonRepair
+            .process(text("Sure! Here it is: [1, 2, 3] Hope it helps."))
+            .unwrap();
+        assert_eq!(repaired, json!([1, 2, 3]));
+
+        let unchanged = JsonRepair.process(text("no json here")).unwrap();
+        assert_eq!(unchanged, text("no json here"));
+    }
+
+    #[test]
+    fn test_markdown_strip() {
+        let stripped = MarkdownStrip
+            .process(json!({
+                "answer": "## Title\n- **Bold** and *italic* with `code` and [a link](https://x.y)"
+            }))
+            .unwrap();
+        assert_eq!(
+            stripped["answer"],
+            "Title\nBold and italic with code and a link"
+        );
+    }
+
+    #[test]
+    fn test_citation_parse() {
+        let parsed = CitationParse
+            .process(text("Rust is fast [1]. It is also safe [2][1]."))
+            .unwrap();
+        assert_eq!(parsed["text"], "Rust is fast. It is also safe.");
+        assert_eq!(parsed["citations"], json!([1, 2]));
+    }
+
+    #[test]
+    fn test_pii_scrub() {
+        let scrubbed = PiiScrub
+            .process(text(
+                "Mail jane.doe@example.com, card 4111 1111 1111 1111, call +1 (555) 123-4567",
+            ))
+            .unwrap();
+        assert_eq!(scrubbed, text("Mail [EMAIL], card [CARD], call [PHONE]"));
+    }
+
+    #[test]
+    fn test_registry_apply_in_order() {
+        let registry = PostProcessorRegistry::with_defaults();
+        assert_eq!(
+            registry.names(),
+            vec![
+                "citation-parse",
+                "json-repair",
+                "markdown-strip",
+                "pii-scrub"
+            ]
+        );
+
+        let response = InferenceResponse::from_string(
+            "```json\n{\"contact\": \"bob@example.com\"}\n```".to_string(),
+            "gpt-4o".to_string(),
+            TokenUsage::new(1, 1),
+            10,
+        );
+        let names = vec!["json-repair".to_string(), "pii-scrub".to_string()];
+        let response = registry.apply(&names, response).unwrap();
+
+        assert_eq!(response.content, json!({"contact": "[EMAIL]"}));
+        assert_eq!(
+            response.metadata.metadata.get(POST_PROCESS_METADATA_KEY),
+            Some(&"json-repair,pii-scrub".to_string())
+        );
+    }
+
+    #[test]
+    fn test_registry_applies_to_every_candidate() {
+        let registry = PostProcessorRegistry::with_defaults();
+        let response = InferenceResponse::from_candidates(
+            vec![
+                Candidate::new(0, text("Mail bob@example.com"), TokenUsage::new(1, 1)),
+                Candidate::new(1, text("Call +1 (555) 123-4567"), TokenUsage::new(1, 1)),
+            ],
+            "gpt-4o".to_string(),
+            10,
+        )
+        .unwrap();
+        let response = registry
+            .apply(&["pii-scrub".to_string()], response)
+            .unwrap();
+
+        assert_eq!(response.content, text("Mail [EMAIL]"));
+        let contents: Vec<_> = response.candidate_contents().into_iter().cloned().collect();
+        assert_eq!(contents, vec![text("Mail [EMAIL]"), text("Call [PHONE]")]);
+    }
+
+    #[test]
+    fn test_registry_unknown_processor() {
+        let registry = PostProcessorRegistry::with_defaults();
+        let error = registry
+            .resolve(&["does-not-exist".to_string()])
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Unknown post-processor"));
+    }
+
+    #[test]
+    fn test_selected_post_processors() {
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::General)
+            .with_metadata(POST_PROCESS_METADATA_KEY, "json-repair, markdown-strip,");
+        assert_eq!(
+            selected_post_processors(&request),
+            vec!["json-repair".to_string(), "markdown-strip".to_string()]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_post_processing_service_uses_template_selection() {
+        use crate::template::PromptTemplate;
+        use crate::MockInferenceService;
+
+        let service = PostProcessingService::new(
+            MockInferenceService::new()
+                .with_latency(0)
+                .with_custom_response("Here you go: {\"total\": 3,}"),
+            PostProcessorRegistry::with_defaults(),
+        );
+
+        let template =
+            PromptTemplate::parse("---\npost_process: [json-repair]\n---\nCount {{items}}")
+                .unwrap();
+        let response = service
+            .infer(template.to_request(HashMap::new()))
+            .await
+            .unwrap();
+
+        assert_eq!(response.content, json!({"total": 3}));
+    }
+}
@@ -0,0 +1,412 @@
+//! Model pricing used for cost accounting
+//!
+//! Prices are expressed in USD per million tokens. Lookups fall back to the longest registered
+//! prefix so dated model versions (e.g. `gpt-4o-2024-08-06`) resolve to their family price.
+//!
+//! Prices change and new models appear faster than crate releases, so tables can be loaded from
+//! JSON, TOML or YAML files or a remote URL (`PricingSource`). Decorators hold a `SharedPricing`
+//! handle; refreshing it updates the prices of every decorator sharing it without a restart:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::pricing::*;
+//! # use tyl_llm_inference_port::ledger::*;
+//! # use std::sync::Arc;
+//! # async fn example(service: impl InferenceService + 'static, store: Arc<dyn LedgerStore>) -> TylResult<()> {
+//! let pricing = SharedPricing::new(PricingTable::with_defaults());
+//! let source = PricingSource::file("/etc/tyl/pricing.toml");
+//! pricing.refresh_from(&source).await?;
+//! let service = LedgerService::new(service, store).with_pricing(pricing.clone());
+//! // later, e.g. on a timer or SIGHUP
+//! pricing.refresh_from(&source).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Files use the serialized form of `PricingTable`:
+//!
+//! ```toml
+//! [models."gpt-4o"]
+//! input_per_million_usd = 2.5
+//! output_per_million_usd = 10.0
+//! ```
+
+use crate::http_client::{HttpRequest, HttpTransport};
+use crate::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Price of a single model
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    /// USD per million prompt tokens
+    pub input_per_million_usd: f64,
+    /// USD per million completion tokens
+    pub output_per_million_usd: f64,
+}
+
+impl ModelPricing {
+    pub fn new(input_per_million_usd: f64, output_per_million_usd: f64) -> Self {
+        Self {
+            input_per_million_usd,
+            output_per_million_usd,
+        }
+    }
+
+    /// Cost in USD of the given token usage
+    pub fn cost(&self, token_usage: &TokenUsage) -> f64 {
+        (token_usage.prompt_tokens as f64 * self.input_per_million_usd
+            + token_usage.completion_tokens as f64 * self.output_per_million_usd)
+            / 1_000_000.0
+    }
+}
+
+/// Pre-flight cost range of a request, from `PricingTable::estimate_cost`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostEstimate {
+    /// Model the request resolves to
+    pub model: String,
+    pub prompt_tokens: usize,
+    /// Completion budget: the request's `max_tokens` (or the model type's typical maximum) for
+    /// each requested candidate
+    pub max_completion_tokens: usize,
+    /// Cost of the prompt alone (empty completion)
+    pub min_cost_usd: f64,
+    /// Cost with the full completion budget used
+    pub max_cost_usd: f64,
+}
+
+/// Pricing lookup table keyed by model name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PricingTable {
+    models: HashMap<String, ModelPricing>,
+}
+
+impl PricingTable {
+    /// Create an empty pricing table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a pricing table with list prices for the default OpenAI and Anthropic models
+    pub fn with_defaults() -> Self {
+        Self::new()
+            .with_model("gpt-4o", ModelPricing::new(2.50, 10.00))
+            .with_model("gpt-4o-mini", ModelPricing::new(0.15, 0.60))
+            .with_model("gpt-3.5-turbo", ModelPricing::new(0.50, 1.50))
+            .with_model("claude-3-5-sonnet", ModelPricing::new(3.00, 15.00))
+            .with_model("claude-3-5-haiku", ModelPricing::new(0.80, 4.00))
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>, pricing: ModelPricing) -> Self {
+        self.set_model(model, pricing);
+        self
+    }
+
+    pub fn set_model(&mut self, model: impl Into<String>, pricing: ModelPricing) {
+        self.models.insert(model.into(), pricing);
+    }
+
+    /// Look up a model's price, falling back to the longest matching prefix
+    pub fn get(&self, model: &str) -> Option<&ModelPricing> {
+        self.models.get(model).or_else(|| {
+            self.models
+                .iter()
+                .filter(|(name, _)| model.starts_with(name.as_str()))
+                .max_by_key(|(name, _)| name.len())
+                .map(|(_, pricing)| pricing)
+        })
+    }
+
+    /// Cost in USD of a response's token usage, `None` for unknown models
+    pub fn cost(&self, model: &str, token_usage: &TokenUsage) -> Option<f64> {
+        self.get(model).map(|pricing| pricing.cost(token_usage))
+    }
+
+    /// Estimate the cost of `request` before running it on `service`
+    ///
+    /// Renders the template and counts its tokens with the service's tokenizer. Fails for models
+    /// without a registered price.
+    pub fn estimate_cost<S: InferenceService + ?Sized>(
+        &self,
+        service: &S,
+        request: &InferenceRequest,
+    ) -> InferenceResult<CostEstimate> {
+        let model = request
+            .model_override
+            .clone()
+            .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string());
+        let pricing = self.get(&model).ok_or_else(|| {
+            TylError::configuration(format!("No price registered for model {model}"))
+        })?;
+        let prompt_tokens = service.count_tokens(&request.render_template())?;
+        let max_completion_tokens = request
+            .max_tokens
+            .unwrap_or_else(|| request.model_type.typical_max_tokens())
+            * request.candidate_count();
+        Ok(CostEstimate {
+            min_cost_usd: pricing.cost(&TokenUsage::new(prompt_tokens as u32, 0)),
+            max_cost_usd: pricing.cost(&TokenUsage::new(
+                prompt_tokens as u32,
+                max_completion_tokens as u32,
+            )),
+            model,
+            prompt_tokens,
+            max_completion_tokens,
+        })
+    }
+
+    /// Models with a registered price, sorted
+    pub fn models(&self) -> Vec<String> {
+        let mut models: Vec<String> = self.models.keys().cloned().collect();
+        models.sort();
+        models
+    }
+
+    /// Add or replace the prices of `other`'s models
+    pub fn merge(mut self, other: PricingTable) -> Self {
+        self.models.extend(other.models);
+        self
+    }
+
+    pub fn from_json(json: &str) -> InferenceResult<Self> {
+        serde_json::from_str(json).map_err(invalid_pricing)
+    }
+
+    pub fn from_toml(toml: &str) -> InferenceResult<Self> {
+        toml::from_str(toml).map_err(invalid_pricing)
+    }
+
+    pub fn from_yaml(yaml: &str) -> InferenceResult<Self> {
+        serde_yaml::from_str(yaml).map_err(invalid_pricing)
+    }
+
+    /// Load a `.toml`, `.yaml`/`.yml` or (otherwise) JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> InferenceResult<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| TylError::configuration(format!("Cannot read {}: {e}", path.display())))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            _ => Self::from_json(&text),
+        }
+    }
+}
+
+fn invalid_pricing(error: impl std::fmt::Display) -> TylError {
+    TylError::configuration(format!("Invalid pricing table: {error}"))
+}
+
+/// Where an updated pricing table is loaded from
+#[derive(Clone)]
+pub enum PricingSource {
+    /// File read with `PricingTable::from_file`
+    File(PathBuf),
+    /// JSON document fetched with a `GET` through the transport
+    Url {
+        url: String,
+        transport: Arc<dyn HttpTransport>,
+    },
+}
+
+impl std::fmt::Debug for PricingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Url { url, .. } => f.debug_struct("Url").field("url", url).finish(),
+        }
+    }
+}
+
+impl PricingSource {
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::File(path.into())
+    }
+
+    pub fn url(url: impl Into<String>, transport: impl HttpTransport + 'static) -> Self {
+        Self::Url {
+            url: url.into(),
+            transport: Arc::new(transport),
+        }
+    }
+
+    pub async fn load(&self) -> InferenceResult<PricingTable> {
+        match self {
+            Self::File(path) => PricingTable::from_file(path),
+            Self::Url { url, transport } => {
+                let response = transport.send(HttpRequest::get(url.clone())).await?;
+                if !response.is_success() {
+                    return Err(TylError::network(format!(
+                        "Fetching pricing from {url} failed with HTTP {}",
+                        response.status
+                    )));
+                }
+                serde_json::from_slice(&response.body).map_err(invalid_pricing)
+            }
+        }
+    }
+}
+
+/// Pricing table shared by decorators and replaceable at runtime
+///
+/// Clones share the same table.
+#[derive(Debug, Clone, Default)]
+pub struct SharedPricing {
+    table: Arc<RwLock<PricingTable>>,
+}
+
+impl SharedPricing {
+    pub fn new(table: PricingTable) -> Self {
+        Self {
+            table: Arc::new(RwLock::new(table)),
+        }
+    }
+
+    /// Copy of the current table
+    pub fn table(&self) -> PricingTable {
+        self.table.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, table: PricingTable) {
+        *self.table.write().unwrap() = table;
+    }
+
+    /// Reload from `source`, keeping registered prices of models the source does not list
+    ///
+    /// On error the current table is left untouched.
+    pub async fn refresh_from(&self, source: &PricingSource) -> InferenceResult<()> {
+        let loaded = source.load().await?;
+        let mut table = self.table.write().unwrap();
+        *table = std::mem::take(&mut *table).merge(loaded);
+        Ok(())
+    }
+
+    pub fn get(&self, model: &str) -> Option<ModelPricing> {
+        self.table.read().unwrap().get(model).copied()
+    }
+
+    /// Cost in USD of a response's token usage, `None` for unknown models
+    pub fn cost(&self, model: &str, token_usage: &TokenUsage) -> Option<f64> {
+        self.table.read().unwrap().cost(model, token_usage)
+    }
+
+    /// See `PricingTable::estimate_cost`
+    pub fn estimate_cost<S: InferenceService + ?Sized>(
+        &self,
+        service: &S,
+        request: &InferenceRequest,
+    ) -> InferenceResult<CostEstimate> {
+        self.table.read().unwrap().estimate_cost(service, request)
+    }
+}
+
+impl From<PricingTable> for SharedPricing {
+    fn from(table: PricingTable) -> Self {
+        Self::new(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_pricing_cost() {
+        let pricing = ModelPricing::new(2.50, 10.00);
+        let cost = pricing.cost(&TokenUsage::new(1_000_000, 500_000));
+        assert!((cost - 7.50).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prefix_lookup() {
+        let table = PricingTable::with_defaults();
+
+        assert_eq!(table.get("gpt-4o"), Some(&ModelPricing::new(2.50, 10.00)));
+        assert_eq!(
+            table.get("gpt-4o-mini-2024-07-18"),
+            Some(&ModelPricing::new(0.15, 0.60))
+        );
+        assert_eq!(
+            table.get("claude-3-5-sonnet-20241022"),
+            Some(&ModelPricing::new(3.00, 15.00))
+        );
+        assert_eq!(table.get("llama3:8b"), None);
+        assert_eq!(table.cost("llama3:8b", &TokenUsage::new(10, 10)), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_estimate_cost() {
+        let service = crate::MockInferenceService::new();
+        let table = PricingTable::new().with_model("gpt-4o-mini", ModelPricing::new(1.0, 2.0));
+        let request = InferenceRequest::new(
+            "Summarize {{text}}",
+            HashMap::from([("text".to_string(), "x".repeat(400))]),
+            ModelType::Fast,
+        )
+        .with_max_tokens(1000)
+        .with_model("gpt-4o-mini");
+
+        let estimate = table.estimate_cost(&service, &request).unwrap();
+        let prompt_tokens = service.count_tokens(&request.render_template()).unwrap();
+        assert_eq!(estimate.model, "gpt-4o-mini");
+        assert_eq!(estimate.prompt_tokens, prompt_tokens);
+        assert_eq!(estimate.max_completion_tokens, 1000);
+        assert!((estimate.min_cost_usd - prompt_tokens as f64 / 1e6).abs() < 1e-12);
+        assert!((estimate.max_cost_usd - (prompt_tokens as f64 + 2000.0) / 1e6).abs() < 1e-12);
+
+        assert!(table
+            .estimate_cost(&service, &request.with_model("llama3:8b"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let toml = PricingTable::from_toml(
+            "[models.\"llama3\"]\ninput_per_million_usd = 0.1\noutput_per_million_usd = 0.2\n",
+        )
+        .unwrap();
+        let json = PricingTable::from_json(
+            r#"{"models": {"llama3": {"input_per_million_usd": 0.1, "output_per_million_usd": 0.2}}}"#,
+        )
+        .unwrap();
+        assert_eq!(toml, json);
+        assert_eq!(toml.get("llama3:8b"), Some(&ModelPricing::new(0.1, 0.2)));
+        assert!(PricingTable::from_json(r#"{"models": {"x": {}}}"#).is_err());
+        assert!(PricingTable::from_file("/nonexistent/pricing.toml").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shared_pricing_refresh_from_file() {
+        let path = std::env::temp_dir().join(format!("pricing-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"{"models": {"gpt-4o": {"input_per_million_usd": 1.0, "output_per_million_usd": 4.0},
+                           "new-model": {"input_per_million_usd": 0.5, "output_per_million_usd": 1.0}}}"#,
+        )
+        .unwrap();
+
+        let pricing = SharedPricing::new(PricingTable::with_defaults());
+        let consumer = pricing.clone();
+        pricing
+            .refresh_from(&PricingSource::file(&path))
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(consumer.get("gpt-4o"), Some(ModelPricing::new(1.0, 4.0)));
+        assert_eq!(consumer.get("new-model"), Some(ModelPricing::new(0.5, 1.0)));
+        assert_eq!(
+            consumer.get("gpt-4o-mini"),
+            Some(ModelPricing::new(0.15, 0.60))
+        );
+
+        // A failed refresh keeps the current prices
+        assert!(pricing
+            .refresh_from(&PricingSource::file(&path))
+            .await
+            .is_err());
+        assert_eq!(consumer.get("gpt-4o"), Some(ModelPricing::new(1.0, 4.0)));
+    }
+}
@@ -0,0 +1,643 @@
+//! Priority scheduling decorator for inference services
+//!
+//! `PriorityQueueService` caps the number of in-flight requests like `ConcurrencyLimitedService`,
+//! but hands free slots to waiting requests by `InferenceRequest::effective_priority` (highest
+//! first, FIFO within a priority) so interactive traffic is not starved by background batch jobs.
+//! Requests without an explicit priority take it from the caller's `RequestContext`.
+//!
+//! Model types can get their own, tighter limits on top of the shared one (e.g. 4 concurrent
+//! Reasoning calls while Fast traffic uses the rest), so expensive models cannot monopolize the
+//! shared capacity. A request first waits for a slot of its model type, then for a shared slot.
+//!
+//! Queue depth, in-flight count, estimated wait, and rejections are published through a
+//! `MetricsRecorder` and reported in health check metadata, for use as autoscaling signals.
+
+use crate::metrics::{self, MetricsRecorder, NoopMetrics};
+use crate::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// Weight of the latest call in the moving average of service time
+const SERVICE_TIME_SMOOTHING: f64 = 0.2;
+
+/// Request waiting for a free slot
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    grant: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher priority first, then earlier arrival
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    in_flight: usize,
+    waiting: BinaryHeap<Waiter>,
+    next_seq: u64,
+}
+
+/// Releases an in-flight slot, handing it to the best waiting request if any
+struct Slot {
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        release(&self.state);
+    }
+}
+
+/// Removes a cancelled request from the queue, returning a slot granted in the meantime
+struct Waiting<'a> {
+    state: &'a Mutex<SchedulerState>,
+    grant: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if let Some(mut grant) = self.grant.take() {
+            grant.close();
+            if grant.try_recv().is_ok() {
+                release(self.state);
+            } else {
+                let mut state = self.state.lock().unwrap();
+                state.waiting.retain(|waiter| !waiter.grant.is_closed());
+            }
+        }
+    }
+}
+
+fn release(state: &Mutex<SchedulerState>) {
+    let mut state = state.lock().unwrap();
+    while let Some(waiter) = state.waiting.pop() {
+        if waiter.grant.send(()).is_ok() {
+            return;
+        }
+    }
+    state.in_flight -= 1;
+}
+
+/// Fold a call duration into the moving average of service time
+fn record_service_time(service_time_ms: &Mutex<Option<f64>>, elapsed: Duration) {
+    let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+    let mut average = service_time_ms.lock().unwrap();
+    *average = Some(match *average {
+        Some(average) => average + SERVICE_TIME_SMOOTHING * (elapsed_ms - average),
+        None => elapsed_ms,
+    });
+}
+
+/// Independently limited pool of in-flight slots
+struct Lane {
+    max_in_flight: usize,
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+impl Lane {
+    fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            state: Arc::new(Mutex::new(SchedulerState::default())),
+        }
+    }
+
+    fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+
+    fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    /// Wait for a slot, ahead of every lower-priority request
+    ///
+    /// `on_queued` runs once the request has joined the wait queue.
+    async fn acquire(
+        &self,
+        priority: Priority,
+        max_queued: usize,
+        on_queued: impl FnOnce(),
+    ) -> InferenceResult<Slot> {
+        let grant = {
+            let mut state = self.state.lock().unwrap();
+            if state.in_flight < self.max_in_flight && state.waiting.is_empty() {
+                state.in_flight += 1;
+                return Ok(Slot {
+                    state: Arc::clone(&self.state),
+                });
+            }
+            if state.waiting.len() >= max_queued {
+                return Err(inference_errors::queue_full(max_queued));
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                grant: sender,
+            });
+            receiver
+        };
+        on_queued();
+
+        let mut waiting = Waiting {
+            state: &self.state,
+            grant: Some(grant),
+        };
+        let granted = waiting.grant.as_mut().unwrap().await;
+        waiting.grant = None;
+
+        granted
+            .map(|_| Slot {
+                state: Arc::clone(&self.state),
+            })
+            .map_err(|_| inference_errors::request_aborted("priority scheduler was dropped"))
+    }
+
+    fn load(&self) -> serde_json::Value {
+        serde_json::json!({
+            "in_flight": self.in_flight(),
+            "queued": self.queued(),
+            "max_in_flight": self.max_in_flight,
+        })
+    }
+}
+
+/// Inference service decorator scheduling requests by priority under a concurrency limit
+pub struct PriorityQueueService<S> {
+    inner: S,
+    max_queued: usize,
+    shared: Lane,
+    model_types: HashMap<ModelType, Lane>,
+    metrics: Arc<dyn MetricsRecorder>,
+    rejected: AtomicU64,
+    /// Moving average of inner call duration in milliseconds
+    service_time_ms: Arc<Mutex<Option<f64>>>,
+    limits: RequestLimits,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for PriorityQueueService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PriorityQueueService")
+            .field("inner", &self.inner)
+            .field("max_in_flight", &self.shared.max_in_flight)
+            .field("max_queued", &self.max_queued)
+            .field("model_types", &self.model_types.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<S: InferenceService> PriorityQueueService<S> {
+    /// Allow `max_in_flight` concurrent requests with an unbounded priority queue
+    pub fn new(inner: S, max_in_flight: usize) -> Self {
+        Self {
+            inner,
+            max_queued: usize::MAX,
+            shared: Lane::new(max_in_flight),
+            model_types: HashMap::new(),
+            metrics: Arc::new(NoopMetrics),
+            rejected: AtomicU64::new(0),
+            service_time_ms: Arc::new(Mutex::new(None)),
+            limits: RequestLimits::structural(),
+        }
+    }
+
+    /// Publish queue metrics to `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<dyn MetricsRecorder>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Limit a model type to `max_in_flight` concurrent requests within the shared limit
+    pub fn with_model_type_limit(mut self, model_type: ModelType, max_in_flight: usize) -> Self {
+        self.model_types
+            .insert(model_type, Lane::new(max_in_flight));
+        self
+    }
+
+    /// Set how many requests may wait for a free slot (0 rejects as soon as the limit is hit)
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    /// Validate requests with `limits` instead of `RequestLimits::structural` before they queue
+    pub fn with_limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.shared.max_in_flight
+    }
+
+    /// Limit of a model type, `None` when it only shares the global limit
+    pub fn model_type_limit(&self, model_type: ModelType) -> Option<usize> {
+        self.model_types
+            .get(&model_type)
+            .map(|lane| lane.max_in_flight)
+    }
+
+    /// Configured model type limits
+    pub fn model_type_limits(&self) -> HashMap<ModelType, usize> {
+        self.model_types
+            .iter()
+            .map(|(model_type, lane)| (*model_type, lane.max_in_flight))
+            .collect()
+    }
+
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+
+    /// Number of requests currently being processed
+    pub fn in_flight(&self) -> usize {
+        self.shared.in_flight()
+    }
+
+    /// Number of requests of a model type currently being processed
+    ///
+    /// Only tracked for model types with their own limit.
+    pub fn in_flight_for(&self, model_type: ModelType) -> Option<usize> {
+        self.model_types.get(&model_type).map(Lane::in_flight)
+    }
+
+    /// Number of requests waiting for a free slot
+    pub fn queued(&self) -> usize {
+        self.shared.queued() + self.model_types.values().map(Lane::queued).sum::<usize>()
+    }
+
+    /// Number of requests rejected because the queue was full
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Estimated wait for a request arriving now, from the average service time
+    pub fn estimated_wait(&self) -> Duration {
+        let queued = self.queued();
+        if queued == 0 && self.in_flight() < self.shared.max_in_flight {
+            return Duration::ZERO;
+        }
+        let service_time_ms = self.service_time_ms.lock().unwrap().unwrap_or(0.0);
+        let rounds = (queued + 1) as f64 / self.shared.max_in_flight as f64;
+        Duration::from_secs_f64(service_time_ms * rounds / 1000.0)
+    }
+
+    fn record_service_time(&self, elapsed: Duration) {
+        record_service_time(&self.service_time_ms, elapsed);
+    }
+
+    fn publish_metrics(&self) {
+        self.metrics
+            .set_gauge(metrics::QUEUE_DEPTH, &[], self.queued() as f64);
+        self.metrics
+            .set_gauge(metrics::IN_FLIGHT, &[], self.in_flight() as f64);
+        self.metrics.set_gauge(
+            metrics::QUEUE_ESTIMATED_WAIT_SECONDS,
+            &[],
+            self.estimated_wait().as_secs_f64(),
+        );
+        for (model_type, lane) in &self.model_types {
+            let model_type = format!("{model_type:?}");
+            let labels = [("model_type", model_type.as_str())];
+            self.metrics
+                .set_gauge(metrics::QUEUE_DEPTH, &labels, lane.queued() as f64);
+            self.metrics
+                .set_gauge(metrics::IN_FLIGHT, &labels, lane.in_flight() as f64);
+        }
+    }
+
+    /// Wait for a model type slot (when limited) and a shared slot
+    async fn acquire(&self, request: &InferenceRequest) -> InferenceResult<(Option<Slot>, Slot)> {
+        let priority = request.effective_priority();
+        let model_type_slot = match self.model_types.get(&request.model_type) {
+            Some(lane) => Some(
+                lane.acquire(priority, self.max_queued, || self.publish_metrics())
+                    .await?,
+            ),
+            None => None,
+        };
+        let slot = self
+            .shared
+            .acquire(priority, self.max_queued, || self.publish_metrics())
+            .await?;
+        Ok((model_type_slot, slot))
+    }
+
+    /// Validate `request` and wait for its slots, counting rejections
+    async fn admit(&self, request: &InferenceRequest) -> InferenceResult<(Option<Slot>, Slot)> {
+        // Reject invalid requests up front rather than after their wait in the queue
+        request.validate_with(&self.inner, &self.limits)?;
+        let slots = match self.acquire(request).await {
+            Ok(slots) => slots,
+            Err(error) => {
+                self.rejected.fetch_add(1, AtomicOrdering::Relaxed);
+                self.metrics
+                    .increment_counter(metrics::QUEUE_REJECTIONS_TOTAL, &[], 1);
+                self.publish_metrics();
+                return Err(error);
+            }
+        };
+        self.publish_metrics();
+        Ok(slots)
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for PriorityQueueService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let slots = self.admit(&request).await?;
+        let started = Instant::now();
+        let result = self.inner.infer(request).await;
+        self.record_service_time(started.elapsed());
+
+        drop(slots);
+        self.publish_metrics();
+        result
+    }
+
+    /// The stream keeps its slots until it ends or is dropped
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let slots = self.admit(&request).await?;
+        let started = Instant::now();
+        let stream = match self.inner.infer_stream(request).await {
+            Ok(stream) => stream,
+            Err(error) => {
+                self.record_service_time(started.elapsed());
+                drop(slots);
+                self.publish_metrics();
+                return Err(error);
+            }
+        };
+        let service_time_ms = Arc::clone(&self.service_time_ms);
+        Ok(stream.on_end(move |_| {
+            record_service_time(&service_time_ms, started.elapsed());
+            drop(slots);
+        }))
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        Ok(self
+            .inner
+            .health_check()
+            .await?
+            .with_metadata("in_flight", serde_json::Value::from(self.in_flight()))
+            .with_metadata("queued", serde_json::Value::from(self.queued()))
+            .with_metadata(
+                "estimated_wait_ms",
+                serde_json::Value::from(self.estimated_wait().as_millis() as u64),
+            )
+            .with_metadata("rejected", serde_json::Value::from(self.rejected()))
+            .with_metadata(
+                "max_in_flight",
+                serde_json::Value::from(self.shared.max_in_flight),
+            )
+            .with_metadata(
+                "model_types",
+                self.model_types
+                    .iter()
+                    .map(|(model_type, lane)| (format!("{model_type:?}"), lane.load()))
+                    .collect(),
+            ))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::metrics::InMemoryMetrics;
+    use crate::MockInferenceService;
+
+    fn request(priority: Priority) -> InferenceRequest {
+        InferenceRequest::new("Test", HashMap::new(), ModelType::Fast).with_priority(priority)
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_requests_run_first() {
+        let service = Arc::new(PriorityQueueService::new(
+            MockInferenceService::new().with_latency(30),
+            1,
+        ));
+        let completed = Arc::new(Mutex::new(Vec::new()));
+
+        let mut handles = Vec::new();
+        for priority in [
+            Priority::Normal,
+            Priority::Low,
+            Priority::Normal,
+            Priority::High,
+        ] {
+            let service = Arc::clone(&service);
+            let completed = Arc::clone(&completed);
+            handles.push(tokio::spawn(async move {
+                service.infer(request(priority)).await.unwrap();
+                completed.lock().unwrap().push(priority);
+            }));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(service.in_flight(), 1);
+        assert_eq!(service.queued(), 3);
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(
+            *completed.lock().unwrap(),
+            vec![
+                Priority::Normal,
+                Priority::High,
+                Priority::Normal,
+                Priority::Low
+            ]
+        );
+        assert_eq!(service.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_model_type_limits_are_independent() {
+        let service = Arc::new(
+            PriorityQueueService::new(MockInferenceService::new().with_latency(50), 8)
+                .with_model_type_limit(ModelType::Reasoning, 1),
+        );
+        assert_eq!(service.model_type_limit(ModelType::Reasoning), Some(1));
+        assert_eq!(service.model_type_limit(ModelType::Fast), None);
+
+        let mut handles = Vec::new();
+        for model_type in [
+            ModelType::Reasoning,
+            ModelType::Reasoning,
+            ModelType::Fast,
+            ModelType::Fast,
+        ] {
+            let service = Arc::clone(&service);
+            handles.push(tokio::spawn(async move {
+                service
+                    .infer(InferenceRequest::new("Test", HashMap::new(), model_type))
+                    .await
+            }));
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // The second Reasoning call waits while Fast calls use the shared capacity
+        assert_eq!(service.in_flight_for(ModelType::Reasoning), Some(1));
+        assert_eq!(service.in_flight(), 3);
+        assert_eq!(service.queued(), 1);
+
+        let health = service.health_check().await.unwrap();
+        assert_eq!(
+            health.metadata["model_types"]["Reasoning"]["queued"],
+            serde_json::json!(1)
+        );
+
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+        assert_eq!(service.in_flight(), 0);
+        assert_eq!(service.in_flight_for(ModelType::Reasoning), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_queue_pressure_metrics() {
+        let metrics = Arc::new(InMemoryMetrics::new());
+        let service = Arc::new(
+            PriorityQueueService::new(MockInferenceService::new().with_latency(50), 1)
+                .with_max_queued(1)
+                .with_metrics(metrics.clone()),
+        );
+        let spawn = |priority| {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move { service.infer(request(priority)).await })
+        };
+
+        let first = spawn(Priority::Normal);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let second = spawn(Priority::Normal);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(metrics.gauge(metrics::QUEUE_DEPTH, &[]), Some(1.0));
+        assert_eq!(metrics.gauge(metrics::IN_FLIGHT, &[]), Some(1.0));
+
+        assert!(service.infer(request(Priority::High)).await.is_err());
+        assert_eq!(service.rejected(), 1);
+        assert_eq!(metrics.counter(metrics::QUEUE_REJECTIONS_TOTAL, &[]), 1);
+
+        // Once a call has completed, queued requests get a wait estimate
+        assert!(first.await.unwrap().is_ok());
+        let third = spawn(Priority::Low);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(service.estimated_wait() >= Duration::from_millis(50));
+
+        let health = service.health_check().await.unwrap();
+        assert_eq!(health.metadata.get("rejected"), Some(&1.into()));
+        assert!(health.metadata["estimated_wait_ms"].as_u64().unwrap() >= 50);
+
+        assert!(second.await.unwrap().is_ok());
+        assert!(third.await.unwrap().is_ok());
+        assert_eq!(metrics.gauge(metrics::QUEUE_DEPTH, &[]), Some(0.0));
+        assert_eq!(metrics.gauge(metrics::IN_FLIGHT, &[]), Some(0.0));
+        assert_eq!(service.estimated_wait(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiters_leave_the_queue() {
+        let service = Arc::new(
+            PriorityQueueService::new(MockInferenceService::new().with_latency(50), 1)
+                .with_max_queued(1),
+        );
+
+        let running = {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move { service.infer(request(Priority::Normal)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let waiting = {
+            let service = Arc::clone(&service);
+            tokio::spawn(async move { service.infer(request(Priority::Low)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(service.queued(), 1);
+
+        let error = service.infer(request(Priority::High)).await.unwrap_err();
+        assert!(error.to_string().contains("queue is full"));
+
+        waiting.abort();
+        let _ = waiting.await;
+        assert_eq!(service.queued(), 0);
+
+        assert!(service.infer(request(Priority::High)).await.is_ok());
+        assert!(running.await.unwrap().is_ok());
+        assert_eq!(service.in_flight(), 0);
+    }
+    #[tokio::test]
+    async fn test_stream_holds_its_slot() {
+        let service = PriorityQueueService::new(MockInferenceService::new().with_latency(0), 1)
+            .with_max_queued(0);
+        let stream = service.infer_stream(request(Priority::High)).await.unwrap();
+        assert_eq!(service.in_flight(), 1);
+        assert!(service.infer(request(Priority::High)).await.is_err());
+
+        drop(stream);
+        assert_eq!(service.in_flight(), 0);
+        assert!(service.infer(request(Priority::High)).await.is_ok());
+    }
+}
@@ -0,0 +1,49 @@
+//! Typed prompts
+//!
+//! A `Prompt` is a struct whose fields are the parameters of a fixed template, so the
+//! parameter names are checked by the compiler instead of being stringly typed at each call
+//! site. With the `derive` feature the implementation is generated and the template
+//! placeholders are checked against the fields at compile time:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::prompt::*;
+//! # #[cfg(feature = "derive")]
+//! #[derive(Prompt)]
+//! #[prompt(template = "Translate to {{language}}:\n{{text}}", model_type = "Fast")]
+//! struct Translate<'a> {
+//!     language: &'a str,
+//!     text: &'a str,
+//! }
+//!
+//! # #[cfg(feature = "derive")]
+//! # async fn example(service: impl InferenceService + 'static) -> TylResult<()> {
+//! let response = service
+//!     .infer(Translate { language: "French", text: "Good morning" }.to_request())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! One-off prompts get the same check from `template!`, which expands to an `InferenceRequest`.
+
+use crate::*;
+
+/// Struct rendered into a fixed template
+pub trait Prompt {
+    /// Template with a `{{placeholder}}` for every parameter
+    const TEMPLATE: &'static str;
+
+    /// Model type of the request (general by default)
+    fn model_type(&self) -> ModelType {
+        ModelType::General
+    }
+
+    /// Parameter values keyed by placeholder name
+    fn parameters(&self) -> HashMap<String, String>;
+
+    /// Inference request for this prompt; sampling options can be chained on it
+    fn to_request(&self) -> InferenceRequest {
+        InferenceRequest::new(Self::TEMPLATE, self.parameters(), self.model_type())
+    }
+}
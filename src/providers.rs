@@ -0,0 +1,372 @@
+//! Provider auto-detection from model names
+//!
+//! `ProviderResolver` maps a model string to the provider that serves it: `gpt-4o` to `openai`,
+//! `claude-3-5-sonnet` to `anthropic`, `llama3:8b` to `ollama`. Custom rules and a
+//! `ModelCatalog` take precedence over the built-in naming conventions.
+//!
+//! `MultiProviderService` fronts several adapters registered under provider names and sends
+//! each request to the one its model resolves to, so callers only ever pick a model:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::providers::*;
+//! # async fn example(openai: impl InferenceService + 'static, anthropic: impl InferenceService + 'static, ollama: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! let service = MultiProviderService::new(ProviderResolver::new().with_prefix("ft:", "openai"))
+//!     .with_provider("openai", openai)
+//!     .with_provider("anthropic", anthropic)
+//!     .with_provider("ollama", ollama)
+//!     .with_default_provider("openai");
+//! service.infer(request.with_model("claude-3-5-sonnet-20241022")).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! When the resolved provider is not registered, the request goes to a registered provider
+//! listing the model in `supported_models`, then to the default provider. Requests without a
+//! model override go to the default provider, or to the provider of their model type's optimal
+//! OpenAI model when there is none.
+
+use crate::catalog::ModelCatalog;
+use crate::*;
+use std::sync::Arc;
+
+/// Response metadata key holding the provider that served the request
+pub const PROVIDER_METADATA_KEY: &str = "provider";
+
+/// Provider of `model` according to common naming conventions
+pub fn detect_provider(model: &str) -> Option<&'static str> {
+    let model = model.to_ascii_lowercase();
+    let starts = |prefixes: &[&str]| prefixes.iter().any(|prefix| model.starts_with(prefix));
+    if starts(&[
+        "gpt-",
+        "o1",
+        "o3",
+        "o4",
+        "chatgpt-",
+        "text-embedding-",
+        "omni-moderation",
+        "dall-e",
+        "whisper",
+    ]) {
+        Some("openai")
+    } else if starts(&["claude-"]) {
+        Some("anthropic")
+    } else if starts(&["gemini-"]) {
+        Some("google")
+    } else if starts(&["mistral-", "mixtral-", "codestral", "ministral", "pixtral"]) {
+        Some("mistral")
+    } else if starts(&["command"]) {
+        Some("cohere")
+    } else if model.contains(':') || starts(&["llama", "qwen", "phi", "gemma", "nomic-embed"]) {
+        // Ollama tags models as `name:size`
+        Some("ollama")
+    } else {
+        None
+    }
+}
+
+/// Maps model names to provider names
+#[derive(Debug, Clone, Default)]
+pub struct ProviderResolver {
+    prefixes: Vec<(String, String)>,
+    catalog: Option<ModelCatalog>,
+}
+
+impl ProviderResolver {
+    /// Built-in naming conventions only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve models starting with `prefix` to `provider`; the longest matching prefix wins
+    pub fn with_prefix(mut self, prefix: impl Into<String>, provider: impl Into<String>) -> Self {
+        self.prefixes.push((prefix.into(), provider.into()));
+        self
+    }
+
+    /// Resolve cataloged models to their `ModelInfo::provider`
+    pub fn with_catalog(mut self, catalog: ModelCatalog) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    /// Provider of `model`, from the custom prefixes, the catalog, then naming conventions
+    pub fn resolve(&self, model: &str) -> Option<String> {
+        let custom = self
+            .prefixes
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, provider)| provider.clone());
+        custom
+            .or_else(|| {
+                self.catalog
+                    .as_ref()
+                    .and_then(|catalog| catalog.get(model))
+                    .and_then(|info| info.provider.clone())
+            })
+            .or_else(|| detect_provider(model).map(str::to_string))
+    }
+}
+
+/// Inference service multiplexing requests across providers by model name
+pub struct MultiProviderService {
+    resolver: ProviderResolver,
+    providers: Vec<(String, Arc<dyn InferenceService>)>,
+    default_provider: Option<String>,
+}
+
+impl std::fmt::Debug for MultiProviderService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let providers: Vec<&str> = self
+            .providers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        f.debug_struct("MultiProviderService")
+            .field("resolver", &self.resolver)
+            .field("providers", &providers)
+            .field("default_provider", &self.default_provider)
+            .finish()
+    }
+}
+
+impl MultiProviderService {
+    pub fn new(resolver: ProviderResolver) -> Self {
+        Self {
+            resolver,
+            providers: Vec::new(),
+            default_provider: None,
+        }
+    }
+
+    /// Register the adapter serving `name` (e.g. `openai`), replacing any previous one
+    pub fn with_provider(
+        mut self,
+        name: impl Into<String>,
+        service: impl InferenceService + 'static,
+    ) -> Self {
+        let name = name.into();
+        self.providers.retain(|(existing, _)| *existing != name);
+        self.providers.push((name, Arc::new(service)));
+        self
+    }
+
+    /// Provider for requests without a model override and for unresolvable models
+    pub fn with_default_provider(mut self, name: impl Into<String>) -> Self {
+        self.default_provider = Some(name.into());
+        self
+    }
+
+    pub fn resolver(&self) -> &ProviderResolver {
+        &self.resolver
+    }
+
+    fn provider(&self, name: &str) -> Option<&(String, Arc<dyn InferenceService>)> {
+        self.providers.iter().find(|(provider, _)| provider == name)
+    }
+
+    /// Name of the registered provider that would serve `request`
+    pub fn provider_for(&self, request: &InferenceRequest) -> InferenceResult<&str> {
+        self.resolve(request).map(|(name, _)| name.as_str())
+    }
+
+    fn resolve(
+        &self,
+        request: &InferenceRequest,
+    ) -> InferenceResult<&(String, Arc<dyn InferenceService>)> {
+        let default = self
+            .default_provider
+            .as_deref()
+            .and_then(|name| self.provider(name));
+        let found = match &request.model_override {
+            Some(model) => self
+                .resolver
+                .resolve(model)
+                .and_then(|name| self.provider(&name))
+                .or_else(|| {
+                    self.providers
+                        .iter()
+                        .find(|(_, service)| service.supported_models().contains(model))
+                })
+                .or(default),
+            None => default.or_else(|| {
+                self.resolver
+                    .resolve(request.model_type.optimal_openai_model())
+                    .and_then(|name| self.provider(&name))
+            }),
+        };
+        found.ok_or_else(|| {
+            inference_errors::unsupported_model(
+                request
+                    .model_override
+                    .as_deref()
+                    .unwrap_or_else(|| request.model_type.optimal_openai_model()),
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl InferenceService for MultiProviderService {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let (name, service) = self.resolve(&request)?;
+        let mut response = service.infer(request).await?;
+        response.metadata = response
+            .metadata
+            .with_metadata(PROVIDER_METADATA_KEY, name.clone());
+        Ok(response)
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let (_, service) = self.resolve(&request)?;
+        service.infer_stream(request).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let mut providers = serde_json::Map::new();
+        let mut unhealthy = Vec::new();
+        for (name, service) in &self.providers {
+            let healthy = matches!(
+                service.health_check().await,
+                Ok(health) if health.status.is_healthy()
+            );
+            if !healthy {
+                unhealthy.push(name.as_str());
+            }
+            providers.insert(name.clone(), serde_json::json!(healthy));
+        }
+        let status = if unhealthy.is_empty() {
+            HealthStatus::healthy()
+        } else {
+            HealthStatus::unhealthy(format!("unhealthy providers: {}", unhealthy.join(", ")))
+        };
+        Ok(HealthCheckResult::new(status).with_metadata("providers", providers.into()))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        let mut models = Vec::new();
+        for (_, service) in &self.providers {
+            for model in service.supported_models() {
+                if !models.contains(&model) {
+                    models.push(model);
+                }
+            }
+        }
+        models
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        let mut models = Vec::new();
+        for (_, service) in &self.providers {
+            for model in service.list_models().await? {
+                if !models.contains(&model) {
+                    models.push(model);
+                }
+            }
+        }
+        Ok(models)
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        match &self.default_provider {
+            Some(name) => match self.provider(name) {
+                Some((_, service)) => service.count_tokens(text),
+                None => Ok((text.len() + 3) / 4),
+            },
+            None => Ok((text.len() + 3) / 4),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_resolve() {
+        assert_eq!(detect_provider("gpt-4o-mini"), Some("openai"));
+        assert_eq!(detect_provider("o3-mini"), Some("openai"));
+        assert_eq!(
+            detect_provider("claude-3-5-sonnet-20241022"),
+            Some("anthropic")
+        );
+        assert_eq!(detect_provider("llama3:8b"), Some("ollama"));
+        assert_eq!(detect_provider("my-finetune"), None);
+
+        let resolver = ProviderResolver::new()
+            .with_prefix("ft:", "openai")
+            .with_prefix("ft:claude", "bedrock")
+            .with_catalog(ModelCatalog::new().with_model(
+                crate::ModelInfo::new("gpt-4o", 128_000, 16_384).with_provider("azure"),
+            ));
+        assert_eq!(
+            resolver.resolve("ft:gpt-4o:acme").as_deref(),
+            Some("openai")
+        );
+        assert_eq!(
+            resolver.resolve("ft:claude-haiku").as_deref(),
+            Some("bedrock")
+        );
+        assert_eq!(
+            resolver.resolve("gpt-4o-2024-08-06").as_deref(),
+            Some("azure")
+        );
+        assert_eq!(resolver.resolve("gpt-4o-mini").as_deref(), Some("azure"));
+        assert_eq!(
+            resolver.resolve("gemini-1.5-pro").as_deref(),
+            Some("google")
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_multiplexes_by_model() {
+        use crate::mock::MockInferenceService;
+
+        let mock = |answer: &str| {
+            MockInferenceService::new()
+                .with_latency(0)
+                .with_custom_response(answer)
+        };
+        let service = MultiProviderService::new(ProviderResolver::new())
+            .with_provider("openai", mock("from openai"))
+            .with_provider("anthropic", mock("from anthropic"))
+            .with_provider("ollama", mock("from ollama"));
+        let request = |model: Option<&str>| {
+            let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General);
+            match model {
+                Some(model) => request.with_model(model),
+                None => request,
+            }
+        };
+
+        let response = service
+            .infer(request(Some("claude-3-5-haiku-20241022")))
+            .await
+            .unwrap();
+        assert_eq!(response.content, serde_json::json!("from anthropic"));
+        assert_eq!(
+            response.metadata.metadata[PROVIDER_METADATA_KEY],
+            "anthropic"
+        );
+        assert_eq!(
+            service.provider_for(&request(Some("llama3:8b"))).unwrap(),
+            "ollama"
+        );
+        // Listed by the mock adapters' supported_models
+        assert_eq!(
+            service.provider_for(&request(Some("mock-fast"))).unwrap(),
+            "openai"
+        );
+        assert_eq!(service.provider_for(&request(None)).unwrap(), "openai");
+        assert!(service.provider_for(&request(Some("unknown"))).is_err());
+
+        let service = service.with_default_provider("ollama");
+        assert_eq!(
+            service.provider_for(&request(Some("unknown"))).unwrap(),
+            "ollama"
+        );
+        assert_eq!(service.provider_for(&request(None)).unwrap(), "ollama");
+    }
+}
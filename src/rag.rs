@@ -0,0 +1,285 @@
+//! Retrieval-augmented generation
+//!
+//! `RagInferenceService` asks a `ContextProvider` for documents relevant to each request and
+//! binds them to the `{{context}}` parameter before the template is rendered. Documents are
+//! added most relevant first until the token budget is spent; the first one that does not fit
+//! is cut at a sentence boundary and the rest are dropped. The ids of the documents that made it
+//! into the prompt are recorded under `retrieved_documents`, as a JSON array, in both the request
+//! and the response metadata:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::rag::*;
+//! # use std::collections::HashMap;
+//! # struct VectorStoreProvider;
+//! # impl VectorStoreProvider { fn new(index: ()) -> Self { Self } }
+//! # #[async_trait::async_trait]
+//! # impl ContextProvider for VectorStoreProvider {
+//! #     async fn retrieve(&self, request: &InferenceRequest) -> TylResult<Vec<RetrievedDocument>> { Ok(Vec::new()) }
+//! # }
+//! # async fn example(openai: impl InferenceService + 'static, index: (), params: HashMap<String, String>) -> TylResult<()> {
+//! let service = RagInferenceService::new(openai, VectorStoreProvider::new(index))
+//!     .with_max_context_tokens(3000);
+//! let request = InferenceRequest::new(
+//!     "Answer from the context only.\n\n{{context}}\n\nQuestion: {{question}}",
+//!     params,
+//!     ModelType::General,
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::chunking::{ChunkBoundary, TextChunker};
+use crate::*;
+use std::sync::Arc;
+
+/// Request and response metadata key holding the ids of the documents put in the context
+pub const RETRIEVED_DOCUMENTS_METADATA_KEY: &str = "retrieved_documents";
+/// Token budget of the retrieved context by default
+pub const DEFAULT_MAX_CONTEXT_TOKENS: usize = 2000;
+
+/// Document returned by a `ContextProvider`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievedDocument {
+    pub id: String,
+    pub content: String,
+    /// Relevance reported by the retriever, higher is more relevant
+    pub score: Option<f32>,
+}
+
+impl RetrievedDocument {
+    pub fn new(id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            content: content.into(),
+            score: None,
+        }
+    }
+
+    pub fn with_score(mut self, score: f32) -> Self {
+        self.score = Some(score);
+        self
+    }
+}
+
+/// Source of documents relevant to a request (vector store, search index, ...)
+#[async_trait]
+pub trait ContextProvider: Send + Sync {
+    /// Documents for `request`, most relevant first
+    async fn retrieve(&self, request: &InferenceRequest)
+        -> InferenceResult<Vec<RetrievedDocument>>;
+}
+
+/// Inference service decorator injecting retrieved documents into a context parameter
+pub struct RagInferenceService<S> {
+    inner: S,
+    provider: Arc<dyn ContextProvider>,
+    context_parameter: String,
+    max_context_tokens: usize,
+    separator: String,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for RagInferenceService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RagInferenceService")
+            .field("inner", &self.inner)
+            .field("context_parameter", &self.context_parameter)
+            .field("max_context_tokens", &self.max_context_tokens)
+            .field("separator", &self.separator)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> RagInferenceService<S> {
+    /// Bind up to `DEFAULT_MAX_CONTEXT_TOKENS` of documents to `{{context}}`, separated by
+    /// blank lines
+    pub fn new(inner: S, provider: impl ContextProvider + 'static) -> Self {
+        Self {
+            inner,
+            provider: Arc::new(provider),
+            context_parameter: "context".to_string(),
+            max_context_tokens: DEFAULT_MAX_CONTEXT_TOKENS,
+            separator: "\n\n".to_string(),
+        }
+    }
+
+    pub fn with_context_parameter(mut self, name: impl Into<String>) -> Self {
+        self.context_parameter = name.into();
+        self
+    }
+
+    /// Tokens of retrieved text allowed in the prompt, counted with the wrapped service
+    pub fn with_max_context_tokens(mut self, tokens: usize) -> Self {
+        self.max_context_tokens = tokens;
+        self
+    }
+
+    /// Text placed between documents
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Bind the documents fitting the budget to the context parameter; returns the metadata
+    /// value listing their ids
+    async fn augment(&self, request: &mut InferenceRequest) -> InferenceResult<String> {
+        let documents = self.provider.retrieve(request).await?;
+        let separator_tokens = self.inner.count_tokens(&self.separator)?;
+        let mut remaining = self.max_context_tokens;
+        let mut parts = Vec::new();
+        let mut ids = Vec::new();
+        for document in documents {
+            let joint = if parts.is_empty() {
+                0
+            } else {
+                separator_tokens
+            };
+            let Some(budget) = remaining.checked_sub(joint).filter(|budget| *budget > 0) else {
+                break;
+            };
+            let tokens = self.inner.count_tokens(&document.content)?;
+            if tokens <= budget {
+                remaining = budget - tokens;
+                parts.push(document.content);
+                ids.push(document.id);
+                continue;
+            }
+            let head = TextChunker::new(budget)
+                .with_boundary(ChunkBoundary::Sentence)
+                .split(&self.inner, &document.content)?
+                .into_iter()
+                .next();
+            if let Some(head) = head {
+                parts.push(head.text);
+                ids.push(document.id);
+            }
+            break;
+        }
+        request
+            .parameters
+            .insert(self.context_parameter.clone(), parts.join(&self.separator));
+        let ids = serde_json::to_string(&ids).unwrap_or_default();
+        request
+            .metadata
+            .insert(RETRIEVED_DOCUMENTS_METADATA_KEY.to_string(), ids.clone());
+        Ok(ids)
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for RagInferenceService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let ids = self.augment(&mut request).await?;
+        let mut response = self.inner.infer(request).await?;
+        response.metadata = response
+            .metadata
+            .with_metadata(RETRIEVED_DOCUMENTS_METADATA_KEY, ids);
+        Ok(response)
+    }
+
+    async fn infer_stream(
+        &self,
+        mut request: InferenceRequest,
+    ) -> InferenceResult<InferenceStream> {
+        self.augment(&mut request).await?;
+        self.inner.infer_stream(request).await
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockInferenceService;
+
+    struct Fixed(Vec<RetrievedDocument>);
+
+    #[async_trait]
+    impl ContextProvider for Fixed {
+        async fn retrieve(
+            &self,
+            _request: &InferenceRequest,
+        ) -> InferenceResult<Vec<RetrievedDocument>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("{{context}}", HashMap::new(), ModelType::General)
+    }
+
+    fn ids(response: &InferenceResponse) -> Vec<String> {
+        serde_json::from_str(&response.metadata.metadata[RETRIEVED_DOCUMENTS_METADATA_KEY]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_injects_documents_within_budget() {
+        let provider = Fixed(vec![
+            RetrievedDocument::new("a", "a".repeat(40)).with_score(0.9),
+            RetrievedDocument::new("b", "b".repeat(40)),
+            RetrievedDocument::new("c", "Third one. ".repeat(20)),
+            RetrievedDocument::new("d", "never reached"),
+        ]);
+        // 10 + 1 + 10 tokens, then 1 separator token and 8 tokens of the third document
+        let service =
+            RagInferenceService::new(MockInferenceService::new().with_latency(0), provider)
+                .with_max_context_tokens(30)
+                .with_separator(" | ");
+        let response = service.infer(request()).await.unwrap();
+
+        assert_eq!(ids(&response), vec!["a", "b", "c"]);
+        let prompt = response.content["message"].as_str().unwrap();
+        assert!(prompt.contains(&format!("{} | {}", "a".repeat(40), "b".repeat(40))));
+        assert!(prompt.ends_with("Third one. Third one."));
+        assert!(!prompt.contains("never reached"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_retrieval_and_custom_parameter() {
+        let service = RagInferenceService::new(
+            MockInferenceService::new().with_latency(0),
+            Fixed(Vec::new()),
+        )
+        .with_context_parameter("docs");
+        let request = InferenceRequest::new("Docs: [{{docs}}]", HashMap::new(), ModelType::General);
+        let response = service.infer(request).await.unwrap();
+        assert!(ids(&response).is_empty());
+        assert_eq!(response.content["message"], "Mock completion for: Docs: []");
+    }
+}
@@ -0,0 +1,364 @@
+//! Provider rate-limit errors and reset hints
+//!
+//! A provider answering 429 usually says when to come back: a standard `Retry-After` header
+//! (seconds or an HTTP date), OpenAI's `retry-after-ms` and `x-ratelimit-reset-*` durations
+//! (`6m0s`, `20ms`), or Anthropic's `anthropic-ratelimit-*-reset` timestamps. HTTP adapters turn
+//! such a response into a `RateLimitError` carrying that wait, and `RetryService` sleeps for it
+//! instead of guessing with exponential backoff:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::rate_limit::*;
+//! # async fn example(service: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! match service.infer(request).await {
+//!     Err(error) => match RateLimitError::from_error(&error) {
+//!         Some(RateLimitError { retry_after: Some(wait), .. }) => tokio::time::sleep(wait).await,
+//!         _ => return Err(error),
+//!     },
+//!     Ok(response) => println!("{}", response.content),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The wait travels inside the `TylError` message (`OpenAI rate limit exceeded, retry after
+//! 1500ms`), so it survives decorators that pass errors through unchanged.
+//!
+//! Successful responses carry the same family of headers (`x-ratelimit-remaining-requests`,
+//! `anthropic-ratelimit-tokens-remaining`, ...). `RateLimitState` captures the quota they
+//! report; HTTP adapters expose the latest one through `rate_limit_state()` and under
+//! `rate_limit` in their health-check metadata, and `RoutingInferenceService::refresh_health`
+//! takes backends whose quota is used up out of rotation until it resets.
+
+use crate::http_client::HttpResponse;
+use crate::*;
+use std::time::Duration;
+
+/// Health-check metadata key holding the last `RateLimitState` reported by the provider
+pub const RATE_LIMIT_HEALTH_KEY: &str = "rate_limit";
+
+const RATE_LIMIT_EXCEEDED: &str = " rate limit exceeded";
+const RETRY_AFTER: &str = ", retry after ";
+
+/// A provider rejected a request for exceeding its rate limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitError {
+    pub provider: String,
+    /// How long the provider asked to wait before retrying, if it said
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimitError {
+    pub fn new(provider: impl Into<String>) -> Self {
+        Self {
+            provider: provider.into(),
+            retry_after: None,
+        }
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Rate-limit error of a 429 `response`, with the wait its headers ask for
+    pub fn from_response(provider: impl Into<String>, response: &HttpResponse) -> Self {
+        Self {
+            provider: provider.into(),
+            retry_after: retry_after_hint(response, Utc::now()),
+        }
+    }
+
+    /// Recover the rate-limit error `error` was created from, if any
+    pub fn from_error(error: &TylError) -> Option<Self> {
+        let message = error.to_string();
+        let end = message.find(RATE_LIMIT_EXCEEDED)?;
+        let provider = message[..end].rsplit(": ").next().unwrap_or_default();
+        let retry_after = message[end + RATE_LIMIT_EXCEEDED.len()..]
+            .strip_prefix(RETRY_AFTER)
+            .and_then(|rest| rest.split("ms").next()?.parse().ok())
+            .map(Duration::from_millis);
+        Some(Self {
+            provider: provider.to_string(),
+            retry_after,
+        })
+    }
+}
+
+impl std::fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{RATE_LIMIT_EXCEEDED}", self.provider)?;
+        if let Some(wait) = self.retry_after {
+            write!(f, "{RETRY_AFTER}{}ms", wait.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+impl From<RateLimitError> for TylError {
+    fn from(error: RateLimitError) -> Self {
+        TylError::network(error.to_string())
+    }
+}
+
+/// Quota a provider reported in its rate-limit headers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitState {
+    pub limit_requests: Option<u64>,
+    pub remaining_requests: Option<u64>,
+    /// When the request quota is back to `limit_requests`
+    pub requests_reset_at: Option<DateTime<Utc>>,
+    pub limit_tokens: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    /// When the token quota is back to `limit_tokens`
+    pub tokens_reset_at: Option<DateTime<Utc>>,
+    /// When the headers were received
+    pub observed_at: DateTime<Utc>,
+}
+
+impl RateLimitState {
+    /// Quota reported by `response` received at `now`, or `None` without rate-limit headers
+    pub fn from_response(response: &HttpResponse, now: DateTime<Utc>) -> Option<Self> {
+        let count = |openai: &str, anthropic: &str| {
+            response
+                .header(openai)
+                .or_else(|| response.header(anthropic))
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        let reset = |openai: &str, anthropic: &str| match response.header(openai) {
+            Some(value) => parse_reset_duration(value)
+                .and_then(|wait| chrono::Duration::from_std(wait).ok())
+                .map(|wait| now + wait),
+            None => DateTime::parse_from_rfc3339(response.header(anthropic)?.trim())
+                .ok()
+                .map(|at| at.with_timezone(&Utc)),
+        };
+        let state = Self {
+            limit_requests: count(
+                "x-ratelimit-limit-requests",
+                "anthropic-ratelimit-requests-limit",
+            ),
+            remaining_requests: count(
+                "x-ratelimit-remaining-requests",
+                "anthropic-ratelimit-requests-remaining",
+            ),
+            requests_reset_at: reset(
+                "x-ratelimit-reset-requests",
+                "anthropic-ratelimit-requests-reset",
+            ),
+            limit_tokens: count(
+                "x-ratelimit-limit-tokens",
+                "anthropic-ratelimit-tokens-limit",
+            ),
+            remaining_tokens: count(
+                "x-ratelimit-remaining-tokens",
+                "anthropic-ratelimit-tokens-remaining",
+            ),
+            tokens_reset_at: reset(
+                "x-ratelimit-reset-tokens",
+                "anthropic-ratelimit-tokens-reset",
+            ),
+            observed_at: now,
+        };
+        (state.remaining_requests.is_some() || state.remaining_tokens.is_some()).then_some(state)
+    }
+
+    /// State stored in `health` under `RATE_LIMIT_HEALTH_KEY`
+    pub fn from_health(health: &HealthCheckResult) -> Option<Self> {
+        serde_json::from_value(health.metadata.get(RATE_LIMIT_HEALTH_KEY)?.clone()).ok()
+    }
+
+    /// Whether the request or token quota is used up and not yet reset at `now`
+    pub fn is_exhausted(&self, now: DateTime<Utc>) -> bool {
+        let used_up = |remaining: Option<u64>, reset_at: Option<DateTime<Utc>>| {
+            remaining == Some(0) && reset_at.map_or(true, |reset_at| reset_at > now)
+        };
+        used_up(self.remaining_requests, self.requests_reset_at)
+            || used_up(self.remaining_tokens, self.tokens_reset_at)
+    }
+}
+
+/// Wait a rate-limited `response` asks for, as of `now`
+///
+/// `retry-after-ms` wins over `Retry-After`; without either, the longest of the provider's
+/// request and token reset hints is used.
+pub fn retry_after_hint(response: &HttpResponse, now: DateTime<Utc>) -> Option<Duration> {
+    if let Some(ms) = response
+        .header("retry-after-ms")
+        .and_then(|value| value.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    if let Some(value) = response.header("retry-after") {
+        let value = value.trim();
+        return match value.parse::<f64>() {
+            Ok(seconds) => Some(Duration::from_secs_f64(seconds.max(0.0))),
+            Err(_) => DateTime::parse_from_rfc2822(value)
+                .ok()
+                .map(|at| until(at.with_timezone(&Utc), now)),
+        };
+    }
+
+    let openai = ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .into_iter()
+        .filter_map(|name| parse_reset_duration(response.header(name)?));
+    let anthropic = [
+        "anthropic-ratelimit-requests-reset",
+        "anthropic-ratelimit-tokens-reset",
+    ]
+    .into_iter()
+    .filter_map(|name| DateTime::parse_from_rfc3339(response.header(name)?.trim()).ok())
+    .map(|at| until(at.with_timezone(&Utc), now));
+    openai.chain(anthropic).max()
+}
+
+fn until(at: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    (at - now).to_std().unwrap_or(Duration::ZERO)
+}
+
+/// Parse durations like `1s`, `6m0s`, `1h2m3.5s` or `20ms`
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut rest = value.trim();
+    let mut total = 0.0;
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let amount: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let (unit, seconds) = if rest.starts_with("ms") {
+            ("ms", 0.001)
+        } else if rest.starts_with('h') {
+            ("h", 3600.0)
+        } else if rest.starts_with('m') {
+            ("m", 60.0)
+        } else if rest.starts_with('s') {
+            ("s", 1.0)
+        } else {
+            return None;
+        };
+        total += amount * seconds;
+        rest = &rest[unit.len()..];
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&str, &str)]) -> HttpResponse {
+        let mut response = HttpResponse::new(429, "");
+        for (name, value) in headers {
+            response = response.with_header(*name, *value);
+        }
+        response
+    }
+
+    #[test]
+    fn test_error_round_trips_through_tyl_error() {
+        let error: TylError = RateLimitError::new("OpenAI")
+            .with_retry_after(Duration::from_millis(1500))
+            .into();
+        assert!(error
+            .to_string()
+            .contains("OpenAI rate limit exceeded, retry after 1500ms"));
+        assert_eq!(
+            RateLimitError::from_error(&error),
+            Some(RateLimitError::new("OpenAI").with_retry_after(Duration::from_millis(1500)))
+        );
+
+        let plain = inference_errors::rate_limit_exceeded("Anthropic");
+        assert_eq!(
+            RateLimitError::from_error(&plain),
+            Some(RateLimitError::new("Anthropic"))
+        );
+        assert_eq!(
+            RateLimitError::from_error(&TylError::network("connection refused")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_retry_after_hints() {
+        let now = Utc::now();
+        let hint = |headers: &[(&str, &str)]| retry_after_hint(&response(headers), now);
+
+        assert_eq!(hint(&[("Retry-After", "7")]), Some(Duration::from_secs(7)));
+        assert_eq!(
+            hint(&[("retry-after", "7"), ("retry-after-ms", "250")]),
+            Some(Duration::from_millis(250))
+        );
+        let date = (now + chrono::Duration::seconds(30)).to_rfc2822();
+        let wait = hint(&[("Retry-After", &date)]).unwrap();
+        assert!(wait > Duration::from_secs(28) && wait <= Duration::from_secs(30));
+        assert_eq!(
+            hint(&[("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")]),
+            Some(Duration::ZERO)
+        );
+
+        assert_eq!(
+            hint(&[
+                ("x-ratelimit-reset-requests", "20ms"),
+                ("x-ratelimit-reset-tokens", "1m30.5s"),
+            ]),
+            Some(Duration::from_millis(90_500))
+        );
+        let reset = (now + chrono::Duration::seconds(12)).to_rfc3339();
+        assert_eq!(
+            hint(&[("anthropic-ratelimit-tokens-reset", &reset)]),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(hint(&[("x-ratelimit-reset-tokens", "soon")]), None);
+        assert_eq!(hint(&[]), None);
+    }
+
+    #[test]
+    fn test_state_from_headers() {
+        let now = Utc::now();
+        let openai = RateLimitState::from_response(
+            &response(&[
+                ("x-ratelimit-limit-requests", "500"),
+                ("x-ratelimit-remaining-requests", "499"),
+                ("x-ratelimit-reset-requests", "120ms"),
+                ("x-ratelimit-remaining-tokens", "0"),
+                ("x-ratelimit-reset-tokens", "6m0s"),
+            ]),
+            now,
+        )
+        .unwrap();
+        assert_eq!(openai.limit_requests, Some(500));
+        assert_eq!(openai.remaining_requests, Some(499));
+        assert_eq!(
+            openai.requests_reset_at,
+            Some(now + chrono::Duration::milliseconds(120))
+        );
+        assert_eq!(openai.limit_tokens, None);
+        assert!(openai.is_exhausted(now));
+        assert!(!openai.is_exhausted(now + chrono::Duration::minutes(7)));
+
+        let reset = now + chrono::Duration::seconds(30);
+        let anthropic = RateLimitState::from_response(
+            &response(&[
+                ("anthropic-ratelimit-tokens-limit", "80000"),
+                ("anthropic-ratelimit-tokens-remaining", "12000"),
+                ("anthropic-ratelimit-tokens-reset", &reset.to_rfc3339()),
+            ]),
+            now,
+        )
+        .unwrap();
+        assert_eq!(anthropic.remaining_tokens, Some(12000));
+        assert_eq!(anthropic.tokens_reset_at, Some(reset));
+        assert!(!anthropic.is_exhausted(now));
+
+        let health = HealthCheckResult::new(HealthStatus::healthy()).with_metadata(
+            RATE_LIMIT_HEALTH_KEY,
+            serde_json::to_value(&anthropic).unwrap(),
+        );
+        assert_eq!(RateLimitState::from_health(&health), Some(anthropic));
+        assert_eq!(RateLimitState::from_response(&response(&[]), now), None);
+    }
+}
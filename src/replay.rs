@@ -0,0 +1,364 @@
+//! Replay of audited requests against new template versions
+//!
+//! `Replayer` takes an `AuditRecord` from production, rebuilds the request with a different
+//! template version and/or model mapping, runs it (or only renders it, in dry-run mode) and
+//! returns a `ReplayComparison` describing what changed: prompt, model, content (field by field
+//! for JSON objects) and cost. Replays drop the original idempotency key and deadline so they
+//! are never deduplicated against, or expired by, the original request.
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::replay::*;
+//! # use tyl_llm_inference_port::audit::*;
+//! # use tyl_llm_inference_port::template::*;
+//! # async fn example(service: impl InferenceService + 'static, records: Vec<AuditRecord>) -> Result<(), Box<dyn std::error::Error>> {
+//! let replayer = Replayer::new(service)
+//!     .with_template(PromptTemplate::parse(&std::fs::read_to_string("summarize.v2.md")?)?)
+//!     .with_model_type_mapping(ModelType::Reasoning, ModelType::General);
+//! for comparison in replayer.replay_all(&records).await {
+//!     println!("{}", serde_json::to_string_pretty(&comparison)?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::audit::AuditRecord;
+use crate::pricing::{PricingTable, SharedPricing};
+use crate::template::PromptTemplate;
+use crate::*;
+use uuid::Uuid;
+
+/// One side of a replay comparison
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplaySide {
+    pub rendered_prompt: String,
+    pub model: String,
+    /// Response content; `None` on failure and for the dry-run side
+    pub content: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub token_usage: Option<TokenUsage>,
+    pub cost_usd: Option<f64>,
+}
+
+/// Difference of one JSON field between the original and the replayed content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldDiff {
+    /// Dotted path of the field (`""` for the whole content)
+    pub path: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// Structured before/after comparison produced by `Replayer::replay`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayComparison {
+    pub record_id: Uuid,
+    pub dry_run: bool,
+    pub before: ReplaySide,
+    pub after: ReplaySide,
+    pub prompt_changed: bool,
+    pub model_changed: bool,
+    /// Whether the content differs; `None` when either side has no content
+    pub content_changed: Option<bool>,
+    pub field_diffs: Vec<FieldDiff>,
+    /// Replayed cost minus original cost, when both are known
+    pub cost_delta_usd: Option<f64>,
+}
+
+/// Re-runs audited requests with a new template version or model mapping
+#[derive(Debug)]
+pub struct Replayer<S> {
+    service: S,
+    template: Option<PromptTemplate>,
+    model_types: HashMap<ModelType, ModelType>,
+    models: HashMap<String, String>,
+    dry_run: bool,
+    pricing: SharedPricing,
+}
+
+impl<S: InferenceService> Replayer<S> {
+    /// Replay records unchanged on `service`, pricing with default list prices
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            template: None,
+            model_types: HashMap::new(),
+            models: HashMap::new(),
+            dry_run: false,
+            pricing: PricingTable::with_defaults().into(),
+        }
+    }
+
+    /// Template version replacing the recorded template (parameters are reused)
+    pub fn with_template(mut self, template: PromptTemplate) -> Self {
+        self.template = Some(template);
+        self
+    }
+
+    /// Replay requests of model type `from` as `to`
+    pub fn with_model_type_mapping(mut self, from: ModelType, to: ModelType) -> Self {
+        self.model_types.insert(from, to);
+        self
+    }
+
+    /// Replay requests resolving to model `from` on model `to`
+    pub fn with_model_mapping(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.models.insert(from.into(), to.into());
+        self
+    }
+
+    /// Render the replayed request without executing it
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_pricing(mut self, pricing: impl Into<SharedPricing>) -> Self {
+        self.pricing = pricing.into();
+        self
+    }
+
+    /// Request `record` is replayed as
+    pub fn replay_request(&self, record: &AuditRecord) -> InferenceRequest {
+        let original = &record.request;
+        let mut request = match &self.template {
+            Some(template) => {
+                let mut request = template.to_request(original.parameters.clone());
+                for (key, value) in &original.metadata {
+                    request
+                        .metadata
+                        .entry(key.clone())
+                        .or_insert_with(|| value.clone());
+                }
+                request.model_override = original.model_override.clone();
+                request.priority = original.priority;
+                request
+            }
+            None => original.clone(),
+        };
+        request.idempotency_key = None;
+        request.deadline = None;
+
+        if let Some(model_type) = self.model_types.get(&request.model_type) {
+            request.model_type = *model_type;
+        }
+        if let Some(model) = self.models.get(&resolved_model(&request)) {
+            request.model_override = Some(model.clone());
+        }
+        request
+    }
+
+    /// Replay one record and compare it with the original outcome
+    pub async fn replay(&self, record: &AuditRecord) -> ReplayComparison {
+        let before = ReplaySide {
+            rendered_prompt: record.rendered_prompt.clone(),
+            model: record
+                .response
+                .as_ref()
+                .map(|response| response.metadata.model.clone())
+                .unwrap_or_else(|| resolved_model(&record.request)),
+            content: record
+                .response
+                .as_ref()
+                .map(|response| response.content.clone()),
+            error: record.error.clone(),
+            token_usage: record
+                .response
+                .as_ref()
+                .map(|response| response.metadata.token_usage.clone()),
+            cost_usd: None,
+        };
+
+        let request = self.replay_request(record);
+        let mut after = ReplaySide {
+            rendered_prompt: request.render_template(),
+            model: resolved_model(&request),
+            content: None,
+            error: None,
+            token_usage: None,
+            cost_usd: None,
+        };
+        if !self.dry_run {
+            match self.service.infer(request).await {
+                Ok(response) => {
+                    after.model = response.metadata.model;
+                    after.content = Some(response.content);
+                    after.token_usage = Some(response.metadata.token_usage);
+                }
+                Err(error) => after.error = Some(error.to_string()),
+            }
+        }
+
+        self.compare(record.id, before, after)
+    }
+
+    /// Replay records one after another
+    pub async fn replay_all(&self, records: &[AuditRecord]) -> Vec<ReplayComparison> {
+        let mut comparisons = Vec::with_capacity(records.len());
+        for record in records {
+            comparisons.push(self.replay(record).await);
+        }
+        comparisons
+    }
+
+    fn compare(
+        &self,
+        record_id: Uuid,
+        mut before: ReplaySide,
+        mut after: ReplaySide,
+    ) -> ReplayComparison {
+        for side in [&mut before, &mut after] {
+            side.cost_usd = side
+                .token_usage
+                .as_ref()
+                .and_then(|usage| self.pricing.cost(&side.model, usage));
+        }
+
+        let (content_changed, field_diffs) = match (&before.content, &after.content) {
+            (Some(old), Some(new)) => {
+                let mut diffs = Vec::new();
+                diff_fields("", Some(old), Some(new), &mut diffs);
+                (Some(!diffs.is_empty()), diffs)
+            }
+            _ => (None, Vec::new()),
+        };
+
+        ReplayComparison {
+            record_id,
+            dry_run: self.dry_run,
+            prompt_changed: before.rendered_prompt != after.rendered_prompt,
+            model_changed: before.model != after.model,
+            content_changed,
+            field_diffs,
+            cost_delta_usd: before
+                .cost_usd
+                .zip(after.cost_usd)
+                .map(|(old, new)| new - old),
+            before,
+            after,
+        }
+    }
+}
+
+fn resolved_model(request: &InferenceRequest) -> String {
+    request
+        .model_override
+        .clone()
+        .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string())
+}
+
+/// Collect differing leaves, descending into objects present on both sides
+fn diff_fields(
+    path: &str,
+    before: Option<&serde_json::Value>,
+    after: Option<&serde_json::Value>,
+    diffs: &mut Vec<FieldDiff>,
+) {
+    if let (Some(serde_json::Value::Object(old)), Some(serde_json::Value::Object(new))) =
+        (before, after)
+    {
+        let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let child = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+            diff_fields(&child, old.get(key), new.get(key), diffs);
+        }
+    } else if before != after {
+        diffs.push(FieldDiff {
+            path: path.to_string(),
+            before: before.cloned(),
+            after: after.cloned(),
+        });
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+
+    fn record() -> AuditRecord {
+        let request = InferenceRequest::new(
+            "Summarize {{text}}",
+            HashMap::from([("text".to_string(), "the report".to_string())]),
+            ModelType::Reasoning,
+        )
+        .with_idempotency_key("req-1")
+        .with_metadata("scope", "team-a");
+        let response = InferenceResponse::new(
+            serde_json::json!({"summary": "long", "meta": {"lang": "en", "words": 12}}),
+            ResponseMetadata::new("gpt-4o".to_string(), TokenUsage::new(100, 50), 900),
+        );
+        AuditRecord::new(request, &Ok(response))
+    }
+
+    fn service(response: &str) -> MockInferenceService {
+        MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response(response)
+    }
+
+    #[tokio::test]
+    async fn test_replay_with_new_template_and_model_mapping() {
+        let template = PromptTemplate::parse(
+            "---\nname: summarize\nmodel_type: Reasoning\n---\nSummarize {{text}} briefly",
+        )
+        .unwrap();
+        let replayer = Replayer::new(service(
+            r#"{"summary": "short", "meta": {"lang": "en", "words": 3}}"#,
+        ))
+        .with_template(template)
+        .with_model_type_mapping(ModelType::Reasoning, ModelType::General);
+
+        let replayed = replayer.replay_request(&record());
+        assert_eq!(replayed.idempotency_key, None);
+        assert_eq!(replayed.model_type, ModelType::General);
+        assert_eq!(replayed.metadata.get("scope"), Some(&"team-a".to_string()));
+        assert_eq!(
+            replayed.metadata.get("template_name"),
+            Some(&"summarize".to_string())
+        );
+
+        let comparison = replayer.replay(&record()).await;
+        assert!(comparison.prompt_changed);
+        assert!(comparison.model_changed);
+        assert_eq!(
+            comparison.after.rendered_prompt,
+            "Summarize the report briefly"
+        );
+        assert_eq!(comparison.content_changed, Some(true));
+        let paths: Vec<&str> = comparison
+            .field_diffs
+            .iter()
+            .map(|d| d.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["meta.words", "summary"]);
+        assert!(comparison.cost_delta_usd.unwrap() < 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_and_unchanged_replay() {
+        let replayer = Replayer::new(service("unused"))
+            .with_model_mapping("gpt-4o", "gpt-4o-mini")
+            .dry_run(true);
+        let comparison = replayer.replay(&record()).await;
+        assert!(comparison.dry_run);
+        assert!(!comparison.prompt_changed);
+        assert_eq!(comparison.after.model, "gpt-4o-mini");
+        assert_eq!(comparison.after.content, None);
+        assert_eq!(comparison.content_changed, None);
+
+        let same = Replayer::new(service(
+            r#"{"summary": "long", "meta": {"lang": "en", "words": 12}}"#,
+        ))
+        .with_model_mapping("gpt-4o", "gpt-4o");
+        let comparison = same.replay(&record()).await;
+        assert_eq!(comparison.content_changed, Some(false));
+        assert!(comparison.field_diffs.is_empty());
+    }
+}
@@ -0,0 +1,362 @@
+//! Response size limits with overflow storage
+//!
+//! `SizeLimitedService` consumes the inner service's `infer_stream` and keeps at most
+//! `max_bytes` of generated text in memory. Oversized outputs are either rejected or, with
+//! `OverflowMode::Spill`, written chunk by chunk to a `ResponseStore` (by default a temp-file
+//! directory); the returned response then carries a `ResponseHandle` instead of the text, so a
+//! model emitting megabytes of output cannot exhaust gateway memory.
+
+use crate::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Response metadata key holding the id of a spilled response
+pub const RESPONSE_HANDLE_METADATA_KEY: &str = "response_handle";
+
+/// Reference to a response stored outside memory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseHandle {
+    pub id: Uuid,
+    /// Store-specific location (e.g. a file path)
+    pub location: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ResponseHandle {
+    /// Handle carried by a spilled response, `None` for regular responses
+    pub fn from_response(response: &InferenceResponse) -> Option<Self> {
+        response
+            .metadata
+            .metadata
+            .get(RESPONSE_HANDLE_METADATA_KEY)?;
+        serde_json::from_value(response.content.clone()).ok()
+    }
+}
+
+/// Incremental writer for a single stored response
+#[async_trait]
+pub trait ResponseWriter: Send {
+    async fn write(&mut self, bytes: &[u8]) -> InferenceResult<()>;
+
+    /// Flush the response and return its handle
+    async fn finish(self: Box<Self>) -> InferenceResult<ResponseHandle>;
+}
+
+/// Storage port for oversized responses
+#[async_trait]
+pub trait ResponseStore: Send + Sync {
+    /// Start storing a new response
+    async fn create(&self) -> InferenceResult<Box<dyn ResponseWriter>>;
+
+    /// Read a stored response back
+    async fn read(&self, handle: &ResponseHandle) -> InferenceResult<Vec<u8>>;
+
+    /// Delete a stored response
+    async fn remove(&self, handle: &ResponseHandle) -> InferenceResult<()>;
+}
+
+fn store_error(error: std::io::Error) -> TylError {
+    TylError::internal(format!("Response store failed: {error}"))
+}
+
+/// `ResponseStore` writing one file per response into a directory
+#[derive(Debug, Clone)]
+pub struct FileResponseStore {
+    dir: PathBuf,
+}
+
+impl FileResponseStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Store responses under the system temp directory
+    pub fn temp() -> Self {
+        Self::new(std::env::temp_dir().join("tyl-inference-responses"))
+    }
+
+    pub fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+}
+
+struct FileResponseWriter {
+    id: Uuid,
+    path: PathBuf,
+    file: std::io::BufWriter<std::fs::File>,
+    size_bytes: u64,
+}
+
+#[async_trait]
+impl ResponseWriter for FileResponseWriter {
+    async fn write(&mut self, bytes: &[u8]) -> InferenceResult<()> {
+        use std::io::Write;
+
+        self.file.write_all(bytes).map_err(store_error)?;
+        self.size_bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> InferenceResult<ResponseHandle> {
+        use std::io::Write;
+
+        self.file.flush().map_err(store_error)?;
+        Ok(ResponseHandle {
+            id: self.id,
+            location: self.path.to_string_lossy().into_owned(),
+            size_bytes: self.size_bytes,
+            created_at: Utc::now(),
+        })
+    }
+}
+
+#[async_trait]
+impl ResponseStore for FileResponseStore {
+    async fn create(&self) -> InferenceResult<Box<dyn ResponseWriter>> {
+        std::fs::create_dir_all(&self.dir).map_err(store_error)?;
+        let id = Uuid::new_v4();
+        let path = self.dir.join(format!("{id}.txt"));
+        let file = std::fs::File::create(&path).map_err(store_error)?;
+        Ok(Box::new(FileResponseWriter {
+            id,
+            path,
+            file: std::io::BufWriter::new(file),
+            size_bytes: 0,
+        }))
+    }
+
+    async fn read(&self, handle: &ResponseHandle) -> InferenceResult<Vec<u8>> {
+        std::fs::read(&handle.location).map_err(store_error)
+    }
+
+    async fn remove(&self, handle: &ResponseHandle) -> InferenceResult<()> {
+        std::fs::remove_file(&handle.location).map_err(store_error)
+    }
+}
+
+/// What to do with responses larger than the limit
+#[derive(Clone)]
+pub enum OverflowMode {
+    /// Fail with `inference_errors::response_too_large`
+    Reject,
+    /// Write the response to a store and return its handle
+    Spill(Arc<dyn ResponseStore>),
+}
+
+impl std::fmt::Debug for OverflowMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reject => f.write_str("Reject"),
+            Self::Spill(_) => f.write_str("Spill"),
+        }
+    }
+}
+
+/// Inference service decorator bounding the in-memory size of responses
+///
+/// Only single-candidate responses are spilled; multi-candidate responses over the limit are
+/// rejected.
+#[derive(Debug)]
+pub struct SizeLimitedService<S> {
+    inner: S,
+    max_bytes: usize,
+    overflow: OverflowMode,
+}
+
+impl<S: InferenceService> SizeLimitedService<S> {
+    /// Reject responses larger than `max_bytes`
+    pub fn new(inner: S, max_bytes: usize) -> Self {
+        Self {
+            inner,
+            max_bytes,
+            overflow: OverflowMode::Reject,
+        }
+    }
+
+    /// Write oversized responses to `store` instead of rejecting them
+    pub fn with_spill_store(mut self, store: Arc<dyn ResponseStore>) -> Self {
+        self.overflow = OverflowMode::Spill(store);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn overflow(&self) -> &OverflowMode {
+        &self.overflow
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for SizeLimitedService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let started = Instant::now();
+        let mut model = request
+            .model_override
+            .clone()
+            .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string());
+        let mut stream = self.inner.infer_stream(request).await?;
+
+        let mut assembler = CandidateAssembler::new();
+        let mut buffered_bytes = 0;
+        let mut spill: Option<Box<dyn ResponseWriter>> = None;
+        let mut finish_reason = None;
+        let mut token_usage = None;
+
+        while let Some(chunk) = stream.next_chunk().await {
+            let chunk = chunk?;
+            if let Some(chunk_model) = &chunk.model {
+                model = chunk_model.clone();
+            }
+
+            if let Some(writer) = spill.as_mut() {
+                if chunk.candidate_index != 0 {
+                    return Err(inference_errors::response_too_large(self.max_bytes));
+                }
+                writer.write(chunk.delta.as_bytes()).await?;
+                finish_reason = chunk.finish_reason.or(finish_reason);
+                token_usage = chunk.token_usage.or(token_usage);
+                continue;
+            }
+
+            buffered_bytes += chunk.delta.len();
+            assembler.push(chunk);
+            if buffered_bytes <= self.max_bytes {
+                continue;
+            }
+
+            let store = match &self.overflow {
+                OverflowMode::Spill(store) if assembler.len() == 1 => store,
+                _ => return Err(inference_errors::response_too_large(self.max_bytes)),
+            };
+            let buffered = assembler
+                .candidate(0)
+                .ok_or_else(|| inference_errors::response_too_large(self.max_bytes))?;
+            finish_reason = buffered.finish_reason.clone();
+            token_usage = buffered.token_usage.clone();
+
+            let mut writer = store.create().await?;
+            writer.write(buffered.text.as_bytes()).await?;
+            spill = Some(writer);
+            assembler = CandidateAssembler::new();
+        }
+
+        let processing_time_ms = started.elapsed().as_millis() as u64;
+        let Some(writer) = spill else {
+            return assembler
+                .into_responses(&model, processing_time_ms)
+                .into_iter()
+                .next()
+                .ok_or_else(|| inference_errors::generation_failed("empty response stream"));
+        };
+
+        let handle = writer.finish().await?;
+        let mut metadata = ResponseMetadata::new(
+            model,
+            token_usage.unwrap_or_else(|| TokenUsage::new(0, 0)),
+            processing_time_ms,
+        )
+        .with_metadata(RESPONSE_HANDLE_METADATA_KEY, handle.id.to_string());
+        if let Some(finish_reason) = finish_reason {
+            metadata = metadata.with_metadata("finish_reason", finish_reason);
+        }
+        Ok(InferenceResponse::new(
+            serde_json::to_value(&handle).unwrap_or_default(),
+            metadata,
+        ))
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Write a novel", HashMap::new(), ModelType::Creative)
+    }
+
+    fn mock(text: &str) -> MockInferenceService {
+        MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response(text)
+    }
+
+    #[tokio::test]
+    async fn test_small_responses_stay_in_memory() {
+        let service = SizeLimitedService::new(mock("short"), 64);
+        let response = service.infer(request()).await.unwrap();
+
+        assert_eq!(response.content, serde_json::Value::String("short".into()));
+        assert!(ResponseHandle::from_response(&response).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_responses_are_rejected() {
+        let service = SizeLimitedService::new(mock(&"x".repeat(100)), 64);
+        let error = service.infer(request()).await.unwrap_err();
+        assert!(error.to_string().contains("64 bytes"));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_responses_spill_to_store() {
+        let text = "chapter ".repeat(50);
+        let dir = std::env::temp_dir().join(format!("tyl-spill-{}", Uuid::new_v4()));
+        let store = Arc::new(FileResponseStore::new(&dir));
+        let service = SizeLimitedService::new(mock(&text), 64).with_spill_store(store.clone());
+
+        let response = service.infer(request()).await.unwrap();
+        let handle = ResponseHandle::from_response(&response).unwrap();
+        assert_eq!(handle.size_bytes, text.len() as u64);
+        assert_eq!(
+            response.metadata.metadata.get("finish_reason"),
+            Some(&"stop".to_string())
+        );
+        assert_eq!(store.read(&handle).await.unwrap(), text.as_bytes());
+
+        store.remove(&handle).await.unwrap();
+        assert!(store.read(&handle).await.is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,386 @@
+//! Retry decorator honoring provider rate-limit hints
+//!
+//! `RetryService` retries failed requests up to `max_attempts` times in total. When the failure
+//! is a `RateLimitError` carrying the provider's requested wait (`Retry-After` and friends, see
+//! `rate_limit`), the next attempt is made after exactly that wait, capped by
+//! `with_max_retry_after`; other failures wait as the `BackoffPolicy` says (exponential with
+//! jitter by default). Only transient failures are retried by default: rate limits, timeouts and
+//! other network errors; `with_retry_if` replaces that condition:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::retry::*;
+//! # use tyl_llm_inference_port::backoff::*;
+//! # use std::time::Duration;
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let service = RetryService::new(openai)
+//!     .with_max_attempts(4)
+//!     .with_backoff(FibonacciBackoff::new(Duration::from_millis(250), Duration::from_secs(8)))
+//!     .with_max_retry_after(Duration::from_secs(30))
+//!     .with_retry_if(|error: &TylError| RateLimitError::from_error(error).is_some());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! No retry is scheduled when the wait would overrun the request's deadline. With
+//! `with_events`, every scheduled retry is published as `InferenceEvent::RetryScheduled`.
+//!
+//! `infer_stream` retries until a stream is opened; errors after that reach the consumer, who
+//! may already have seen part of the response.
+
+use crate::backoff::{BackoffPolicy, ExponentialBackoff};
+use crate::events::{self, EventBus, InferenceEvent};
+use crate::rate_limit::RateLimitError;
+use crate::*;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Response metadata key holding the number of attempts a request took, when more than one
+pub const RETRY_ATTEMPTS_METADATA_KEY: &str = "retry_attempts";
+
+/// Whether another attempt may succeed after `error`: rate limits, timeouts and other network
+/// failures; invalid requests, credentials or configuration fail the same way again
+pub fn is_transient(error: &TylError) -> bool {
+    RateLimitError::from_error(error).is_some() || inference_errors::is_network(error)
+}
+
+/// Attempts made per request by default, the first one included
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+type RetryCondition = Arc<dyn Fn(&TylError) -> bool + Send + Sync>;
+
+/// Inference service decorator retrying failed requests
+#[derive(Clone)]
+pub struct RetryService<S> {
+    inner: S,
+    max_attempts: u32,
+    backoff: Arc<dyn BackoffPolicy>,
+    max_retry_after: Duration,
+    retry_if: Option<RetryCondition>,
+    events: Option<EventBus>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for RetryService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryService")
+            .field("inner", &self.inner)
+            .field("max_attempts", &self.max_attempts)
+            .field("max_retry_after", &self.max_retry_after)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> RetryService<S> {
+    /// Retry transient errors (see `is_transient`), `DEFAULT_MAX_ATTEMPTS` attempts in total,
+    /// with the default `ExponentialBackoff`
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            backoff: Arc::new(ExponentialBackoff::default()),
+            max_retry_after: Duration::from_secs(60),
+            retry_if: None,
+            events: None,
+        }
+    }
+
+    /// Attempts per request, the first one included (at least 1)
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Wait between retries of failures without a provider hint
+    pub fn with_backoff(mut self, backoff: impl BackoffPolicy + 'static) -> Self {
+        self.backoff = Arc::new(backoff);
+        self
+    }
+
+    /// Longest provider-requested wait honored; longer hints are cut to this
+    pub fn with_max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Retry the errors `condition` accepts instead of the transient ones
+    pub fn with_retry_if(
+        mut self,
+        condition: impl Fn(&TylError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_if = Some(Arc::new(condition));
+        self
+    }
+
+    /// Publish `InferenceEvent::RetryScheduled` for requests carrying an event request id
+    pub fn with_events(mut self, bus: EventBus) -> Self {
+        self.events = Some(bus);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Wait before attempt `attempt + 1` after `error`, `previous` being the last wait
+    pub fn delay_for(&self, attempt: u32, previous: Duration, error: &TylError) -> Duration {
+        match RateLimitError::from_error(error).and_then(|error| error.retry_after) {
+            Some(wait) => wait.min(self.max_retry_after),
+            None => self.backoff.delay(attempt, previous),
+        }
+    }
+
+    /// Run `call` until it succeeds or no retry is left, returning its value and the number of
+    /// attempts made
+    async fn retrying<T, Fut>(
+        &self,
+        request: &InferenceRequest,
+        mut call: impl FnMut() -> Fut,
+    ) -> InferenceResult<(T, u32)>
+    where
+        Fut: Future<Output = InferenceResult<T>>,
+    {
+        let mut attempt = 1;
+        let mut delay = Duration::ZERO;
+        loop {
+            let error = match call().await {
+                Ok(value) => return Ok((value, attempt)),
+                Err(error) => error,
+            };
+            let retryable = match &self.retry_if {
+                Some(retry) => retry(&error),
+                None => is_transient(&error),
+            };
+            if attempt >= self.max_attempts || !retryable {
+                return Err(error);
+            }
+            delay = self.delay_for(attempt, delay, &error);
+            if !request.can_complete_within(delay) {
+                return Err(error);
+            }
+
+            attempt += 1;
+            if let (Some(bus), Some(request_id)) = (&self.events, events::request_id(request)) {
+                bus.publish(InferenceEvent::RetryScheduled {
+                    request_id: request_id.to_string(),
+                    attempt,
+                    delay_ms: delay.as_millis() as u64,
+                    error: error.to_string(),
+                });
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for RetryService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let (mut response, attempts) = self
+            .retrying(&request, || self.inner.infer(request.clone()))
+            .await?;
+        if attempts > 1 {
+            response.metadata = response
+                .metadata
+                .with_metadata(RETRY_ATTEMPTS_METADATA_KEY, attempts.to_string());
+        }
+        Ok(response)
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        self.retrying(&request, || self.inner.infer_stream(request.clone()))
+            .await
+            .map(|(stream, _)| stream)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScriptedInferenceService;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast)
+    }
+
+    fn rate_limited(wait_ms: u64) -> TylError {
+        RateLimitError::new("OpenAI")
+            .with_retry_after(Duration::from_millis(wait_ms))
+            .into()
+    }
+
+    #[test]
+    fn test_delays() {
+        let service = RetryService::new(ScriptedInferenceService::new(Vec::new()))
+            .with_backoff(
+                ExponentialBackoff::new(Duration::from_millis(100), Duration::from_millis(300))
+                    .without_jitter(),
+            )
+            .with_max_retry_after(Duration::from_secs(5));
+
+        let error = TylError::network("connection reset");
+        let delays: Vec<_> = (1..=4)
+            .map(|attempt| {
+                service
+                    .delay_for(attempt, Duration::ZERO, &error)
+                    .as_millis()
+            })
+            .collect();
+        assert_eq!(delays, vec![100, 200, 300, 300]);
+
+        assert_eq!(
+            service.delay_for(1, Duration::ZERO, &rate_limited(1500)),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            service.delay_for(1, Duration::ZERO, &rate_limited(60_000)),
+            Duration::from_secs(5)
+        );
+        let plain = inference_errors::rate_limit_exceeded("OpenAI");
+        assert_eq!(
+            service.delay_for(2, Duration::ZERO, &plain),
+            Duration::from_millis(200)
+        );
+
+        // Policies get the previous wait
+        let service = RetryService::new(ScriptedInferenceService::new(Vec::new()))
+            .with_backoff(|_: u32, previous: Duration| previous + Duration::from_millis(50));
+        assert_eq!(
+            service.delay_for(3, Duration::from_millis(100), &error),
+            Duration::from_millis(150)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_retry_after() {
+        let bus = EventBus::new();
+        let scheduled = Arc::new(Mutex::new(Vec::new()));
+        let seen = scheduled.clone();
+        bus.subscribe(move |event: &InferenceEvent| {
+            if let InferenceEvent::RetryScheduled {
+                attempt, delay_ms, ..
+            } = event
+            {
+                seen.lock().unwrap().push((*attempt, *delay_ms));
+            }
+        });
+        let service = RetryService::new(ScriptedInferenceService::new(vec![rate_limited(60)]))
+            .with_backoff(crate::backoff::FixedBackoff::new(Duration::from_secs(10)))
+            .with_events(bus);
+
+        let started = Instant::now();
+        let response = service
+            .infer(request().with_metadata(events::REQUEST_ID_METADATA_KEY, "req-1"))
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(response.metadata.metadata[RETRY_ATTEMPTS_METADATA_KEY], "2");
+        assert_eq!(*scheduled.lock().unwrap(), vec![(2, 60)]);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up() {
+        let errors = || {
+            vec![
+                TylError::network("first"),
+                TylError::network("second"),
+                TylError::network("third"),
+            ]
+        };
+        let service = RetryService::new(ScriptedInferenceService::new(errors()))
+            .with_max_attempts(2)
+            .with_backoff(crate::backoff::FixedBackoff::new(Duration::from_millis(1)));
+        let error = service.infer(request()).await.unwrap_err();
+        assert!(error.to_string().contains("second"));
+        assert_eq!(service.inner().calls(), 2);
+
+        // Rejected by the condition
+        let service = RetryService::new(ScriptedInferenceService::new(errors()))
+            .with_retry_if(|error: &TylError| RateLimitError::from_error(error).is_some());
+        assert!(service.infer(request()).await.is_err());
+        assert_eq!(service.inner().calls(), 1);
+
+        // The provider's wait would overrun the deadline
+        let service = RetryService::new(ScriptedInferenceService::new(vec![rate_limited(5_000)]));
+        let deadline = Utc::now() + chrono::Duration::seconds(1);
+        let error = service
+            .infer(request().with_deadline(deadline))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("retry after 5000ms"));
+        assert_eq!(service.inner().calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_transient_errors_by_default() {
+        let backoff = || crate::backoff::FixedBackoff::new(Duration::ZERO);
+        let service = RetryService::new(ScriptedInferenceService::new(vec![
+            inference_errors::request_timeout(Duration::from_secs(1)),
+            rate_limited(0),
+            TylError::network("HTTP 503: overloaded"),
+        ]))
+        .with_max_attempts(4)
+        .with_backoff(backoff());
+        assert!(service.infer(request()).await.is_ok());
+        assert_eq!(service.inner().calls(), 4);
+
+        for error in [
+            TylError::validation("template", "Template is empty"),
+            inference_errors::invalid_api_key("OpenAI"),
+            inference_errors::generation_failed("malformed output"),
+        ] {
+            assert!(!is_transient(&error));
+            let service = RetryService::new(ScriptedInferenceService::new(vec![error]))
+                .with_backoff(backoff());
+            assert!(service.infer(request()).await.is_err());
+            assert_eq!(service.inner().calls(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_opening_streams() {
+        let service = RetryService::new(ScriptedInferenceService::new(vec![TylError::network(
+            "connection reset",
+        )]))
+        .with_backoff(crate::backoff::FixedBackoff::new(Duration::ZERO));
+        let stream = service.infer_stream(request()).await.unwrap();
+        assert_eq!(stream.assemble().await.unwrap().len(), 1);
+        assert_eq!(service.inner().calls(), 2);
+    }
+}
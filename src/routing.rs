@@ -0,0 +1,833 @@
+//! Routing across multiple inference backends
+//!
+//! `RoutingInferenceService` picks one backend per request in four steps:
+//!
+//! 1. **Capability filter**: backends restricted to some model types, or listing supported
+//!    models that do not include the request's `model_override`, are skipped. With a
+//!    `ModelCatalog`, so are backends whose model cannot fit the prompt and `max_tokens` in its
+//!    context window.
+//! 2. **Health**: backends marked unhealthy by the last `refresh_health` (or `set_healthy`) are
+//!    skipped. A backend whose health check reports a used-up `RateLimitState` counts as
+//!    unhealthy until the quota resets. With an `SloTracker`, backends that exhausted their
+//!    error budget are degraded: they only serve when every other eligible backend is degraded.
+//! 3. **Cost policy**: remaining primary backends are ranked by declaration order, by the
+//!    estimated cost of the request, or by their p95 latency in a shared `LatencyTracker`
+//!    (filled by wrapping each backend in a `MetricsService` named after the backend). Backends
+//!    without latency samples in the tracker's window rank first under `Fastest`, so new and
+//!    idle backends are measured instead of starved.
+//! 4. **Canary weight**: each eligible canary backend receives its percentage of traffic. The
+//!    bucket is derived from the idempotency key or request fingerprint, so retries of a request
+//!    stick to the same backend.
+//!
+//! `explain` runs the same steps without executing and returns a `RoutingDecision` describing
+//! every candidate, for debugging and policy audits.
+
+use crate::canonical::{content_hash, request_fingerprint};
+use crate::catalog::{ModelCatalog, ModelFeature};
+use crate::latency::LatencyTracker;
+use crate::pricing::{PricingTable, SharedPricing};
+use crate::rate_limit::RateLimitState;
+use crate::slo::SloTracker;
+use crate::*;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Response metadata key holding the name of the backend that served the request
+pub const ROUTED_BACKEND_METADATA_KEY: &str = "routed_backend";
+
+/// How primary backends are ranked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CostPolicy {
+    /// First eligible backend in declaration order
+    #[default]
+    FirstAvailable,
+    /// Eligible backend with the lowest estimated cost (unknown costs rank last)
+    Cheapest,
+    /// Eligible backend with the lowest p95 latency; backends without samples rank first, so
+    /// they get measured
+    Fastest,
+}
+
+/// Backend registered with a `RoutingInferenceService`
+pub struct RouteBackend {
+    name: String,
+    service: Arc<dyn InferenceService>,
+    model_types: Option<HashSet<ModelType>>,
+    model: Option<String>,
+    canary_percent: Option<u8>,
+    healthy: AtomicBool,
+}
+
+impl std::fmt::Debug for RouteBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RouteBackend")
+            .field("name", &self.name)
+            .field("model_types", &self.model_types)
+            .field("model", &self.model)
+            .field("canary_percent", &self.canary_percent)
+            .field("healthy", &self.healthy)
+            .finish()
+    }
+}
+
+impl RouteBackend {
+    pub fn new(name: impl Into<String>, service: impl InferenceService + 'static) -> Self {
+        Self {
+            name: name.into(),
+            service: Arc::new(service),
+            model_types: None,
+            model: None,
+            canary_percent: None,
+            healthy: AtomicBool::new(true),
+        }
+    }
+
+    /// Only route requests of these model types to this backend
+    pub fn with_model_types(mut self, model_types: &[ModelType]) -> Self {
+        self.model_types = Some(model_types.iter().copied().collect());
+        self
+    }
+
+    /// Model this backend serves, used to estimate cost
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Mark as a canary receiving `percent` of eligible traffic
+    pub fn canary(mut self, percent: u8) -> Self {
+        self.canary_percent = Some(percent.min(100));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Why this backend cannot serve `request`, `None` when capable
+    fn incapability(
+        &self,
+        request: &InferenceRequest,
+        catalog: Option<&ModelCatalog>,
+    ) -> Option<String> {
+        if let Some(model_types) = &self.model_types {
+            if !model_types.contains(&request.model_type) {
+                return Some(format!("does not serve {:?} requests", request.model_type));
+            }
+        }
+        if let Some(model) = &request.model_override {
+            let supported = self.service.supported_models();
+            if !supported.is_empty() && !supported.contains(model) {
+                return Some(format!("does not support model {model}"));
+            }
+        }
+        let model = request.model_override.as_ref().or(self.model.as_ref());
+        if let Some(info) = catalog
+            .zip(model)
+            .and_then(|(catalog, model)| catalog.get(model))
+        {
+            if !request.attachments.is_empty() && !info.supports(ModelFeature::Vision) {
+                return Some(format!("{} does not accept image inputs", info.name));
+            }
+            let prompt_tokens = self
+                .service
+                .count_tokens(&request.render_template())
+                .unwrap_or(0);
+            let needed = prompt_tokens + request.max_tokens.unwrap_or(0);
+            if needed > info.context_window {
+                return Some(format!(
+                    "needs {needed} tokens, over the {} context window of {}",
+                    info.context_window, info.name
+                ));
+            }
+        }
+        None
+    }
+
+    fn estimated_cost(&self, request: &InferenceRequest, pricing: &SharedPricing) -> Option<f64> {
+        let estimate = match &self.model {
+            Some(model) => {
+                let mut request = request.clone();
+                request.model_override = Some(model.clone());
+                pricing.estimate_cost(&*self.service, &request)
+            }
+            None => pricing.estimate_cost(&*self.service, request),
+        };
+        estimate.ok().map(|estimate| estimate.max_cost_usd)
+    }
+
+    /// p95 latency of this backend's model, or across its models when none is configured
+    fn p95_latency(&self, latencies: &LatencyTracker) -> Option<Duration> {
+        let snapshot = match &self.model {
+            Some(model) => latencies.snapshot(&self.name, model),
+            None => latencies.provider_snapshot(&self.name),
+        };
+        snapshot.map(|snapshot| snapshot.p95)
+    }
+}
+
+/// Evaluation of one backend for a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandidateEvaluation {
+    pub backend: String,
+    /// Passed the capability filter
+    pub capable: bool,
+    pub healthy: bool,
+    /// Error budget exhausted according to the router's `SloTracker`
+    #[serde(default)]
+    pub degraded: bool,
+    /// Estimated cost of the request on this backend, when its model is priced
+    pub estimated_cost_usd: Option<f64>,
+    /// Observed p95 latency, when a latency tracker has samples for this backend
+    #[serde(default)]
+    pub p95_latency: Option<Duration>,
+    /// Traffic percentage when this backend is a canary
+    pub canary_percent: Option<u8>,
+    /// Why the backend was not chosen, `None` for the chosen backend
+    pub rejected_because: Option<String>,
+}
+
+/// Outcome of routing a request, as returned by `RoutingInferenceService::explain`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingDecision {
+    /// Chosen backend, `None` when no backend is eligible
+    pub backend: Option<String>,
+    /// Human readable summary of the decision
+    pub reason: String,
+    pub cost_policy: CostPolicy,
+    /// Canary bucket of the request (0-99)
+    pub canary_bucket: u8,
+    /// Every backend in declaration order
+    pub candidates: Vec<CandidateEvaluation>,
+}
+
+/// Inference service routing each request to one of several backends
+#[derive(Debug)]
+pub struct RoutingInferenceService {
+    backends: Vec<RouteBackend>,
+    cost_policy: CostPolicy,
+    pricing: SharedPricing,
+    catalog: Option<ModelCatalog>,
+    latencies: Option<Arc<LatencyTracker>>,
+    slo: Option<Arc<SloTracker>>,
+}
+
+impl Default for RoutingInferenceService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RoutingInferenceService {
+    /// Route with `CostPolicy::FirstAvailable` and default list prices
+    pub fn new() -> Self {
+        Self {
+            backends: Vec::new(),
+            cost_policy: CostPolicy::FirstAvailable,
+            pricing: PricingTable::with_defaults().into(),
+            catalog: None,
+            latencies: None,
+            slo: None,
+        }
+    }
+
+    pub fn with_backend(mut self, backend: RouteBackend) -> Self {
+        self.backends.push(backend);
+        self
+    }
+
+    pub fn with_cost_policy(mut self, cost_policy: CostPolicy) -> Self {
+        self.cost_policy = cost_policy;
+        self
+    }
+
+    /// Prices used by `CostPolicy::Cheapest` (a `SharedPricing` follows runtime refreshes)
+    pub fn with_pricing(mut self, pricing: impl Into<SharedPricing>) -> Self {
+        self.pricing = pricing.into();
+        self
+    }
+
+    /// Skip backends whose model (per the catalog) cannot fit the request in its context window
+    pub fn with_catalog(mut self, catalog: ModelCatalog) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    /// Latencies read by `CostPolicy::Fastest`, keyed by backend name and model
+    pub fn with_latency_tracker(mut self, latencies: Arc<LatencyTracker>) -> Self {
+        self.latencies = Some(latencies);
+        self
+    }
+
+    /// Record every routed request in `slo` and avoid backends it reports as degraded
+    pub fn with_slo_tracker(mut self, slo: Arc<SloTracker>) -> Self {
+        self.slo = Some(slo);
+        self
+    }
+
+    pub fn backends(&self) -> &[RouteBackend] {
+        &self.backends
+    }
+
+    /// Mark a backend healthy or unhealthy; returns false for unknown backends
+    pub fn set_healthy(&self, backend: &str, healthy: bool) -> bool {
+        match self.backends.iter().find(|b| b.name == backend) {
+            Some(backend) => {
+                backend.healthy.store(healthy, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Health check every backend and record the results for routing
+    pub async fn refresh_health(&self) {
+        for backend in &self.backends {
+            let healthy = match backend.service.health_check().await {
+                Ok(health) => {
+                    health.status.is_healthy()
+                        && !RateLimitState::from_health(&health)
+                            .is_some_and(|state| state.is_exhausted(Utc::now()))
+                }
+                Err(_) => false,
+            };
+            backend.healthy.store(healthy, Ordering::Relaxed);
+        }
+    }
+
+    /// Decide which backend would serve `request`, without executing it
+    pub fn explain(&self, request: &InferenceRequest) -> RoutingDecision {
+        let canary_bucket = canary_bucket(request);
+        let mut candidates: Vec<CandidateEvaluation> = self
+            .backends
+            .iter()
+            .map(|backend| {
+                let incapability = backend.incapability(request, self.catalog.as_ref());
+                let healthy = backend.is_healthy();
+                let rejected_because = match &incapability {
+                    Some(reason) => Some(reason.clone()),
+                    None if !healthy => Some("unhealthy".to_string()),
+                    None => None,
+                };
+                CandidateEvaluation {
+                    backend: backend.name.clone(),
+                    capable: incapability.is_none(),
+                    healthy,
+                    degraded: self
+                        .slo
+                        .as_deref()
+                        .is_some_and(|slo| slo.is_degraded(&backend.name)),
+                    estimated_cost_usd: backend.estimated_cost(request, &self.pricing),
+                    p95_latency: self
+                        .latencies
+                        .as_deref()
+                        .and_then(|latencies| backend.p95_latency(latencies)),
+                    canary_percent: backend.canary_percent,
+                    rejected_because,
+                }
+            })
+            .collect();
+
+        // Degraded backends are the last resort
+        let undegraded = candidates
+            .iter()
+            .any(|candidate| candidate.rejected_because.is_none() && !candidate.degraded);
+        if undegraded {
+            for candidate in candidates.iter_mut() {
+                if candidate.rejected_because.is_none() && candidate.degraded {
+                    candidate.rejected_because = Some("degraded: error budget exhausted".into());
+                }
+            }
+        }
+
+        let eligible = |candidate: &CandidateEvaluation| candidate.rejected_because.is_none();
+
+        // Canaries claim consecutive bucket ranges in declaration order
+        let mut canary = None;
+        let mut range_start = 0u32;
+        for (index, candidate) in candidates.iter().enumerate() {
+            let Some(percent) = candidate.canary_percent else {
+                continue;
+            };
+            if !eligible(candidate) {
+                continue;
+            }
+            let range_end = range_start + percent as u32;
+            if canary.is_none() && (range_start..range_end).contains(&(canary_bucket as u32)) {
+                canary = Some(index);
+            }
+            range_start = range_end;
+        }
+
+        let primaries: Vec<usize> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| candidate.canary_percent.is_none() && eligible(candidate))
+            .map(|(index, _)| index)
+            .collect();
+        let best_primary = match self.cost_policy {
+            CostPolicy::FirstAvailable => primaries.first().copied(),
+            CostPolicy::Cheapest => primaries.iter().copied().min_by(|a, b| {
+                let cost = |index: &usize| candidates[*index].estimated_cost_usd;
+                match (cost(a), cost(b)) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            }),
+            // `None` orders first: backends without latency samples are explored first
+            CostPolicy::Fastest => primaries
+                .iter()
+                .copied()
+                .min_by_key(|index| candidates[*index].p95_latency),
+        };
+
+        let (chosen, reason) = match (canary, best_primary) {
+            (Some(index), _) => (
+                Some(index),
+                format!(
+                    "canary {} selected for bucket {canary_bucket}",
+                    candidates[index].backend
+                ),
+            ),
+            (None, Some(index)) => (
+                Some(index),
+                match self.cost_policy {
+                    CostPolicy::FirstAvailable => {
+                        format!(
+                            "{} is the first eligible backend",
+                            candidates[index].backend
+                        )
+                    }
+                    CostPolicy::Cheapest => format!(
+                        "{} has the lowest estimated cost",
+                        candidates[index].backend
+                    ),
+                    CostPolicy::Fastest => match candidates[index].p95_latency {
+                        Some(_) => {
+                            format!("{} has the lowest p95 latency", candidates[index].backend)
+                        }
+                        None => format!("{} has no latency samples yet", candidates[index].backend),
+                    },
+                },
+            ),
+            (None, None) => (None, "no eligible backend".to_string()),
+        };
+
+        let chosen_unsampled = chosen.is_some_and(|index| candidates[index].p95_latency.is_none());
+        for (index, candidate) in candidates.iter_mut().enumerate() {
+            if candidate.rejected_because.is_some() || Some(index) == chosen {
+                continue;
+            }
+            candidate.rejected_because = Some(match (candidate.canary_percent, chosen) {
+                (Some(_), _) => format!("bucket {canary_bucket} outside canary range"),
+                (None, Some(chosen)) if Some(chosen) == canary => "canary selected".to_string(),
+                (None, _) => match self.cost_policy {
+                    CostPolicy::FirstAvailable => "lower in declaration order".to_string(),
+                    CostPolicy::Cheapest => "higher estimated cost".to_string(),
+                    CostPolicy::Fastest if chosen_unsampled => {
+                        "unsampled backend explored first".to_string()
+                    }
+                    CostPolicy::Fastest => "higher p95 latency".to_string(),
+                },
+            });
+        }
+
+        RoutingDecision {
+            backend: chosen.map(|index| candidates[index].backend.clone()),
+            reason,
+            cost_policy: self.cost_policy,
+            canary_bucket,
+            candidates,
+        }
+    }
+}
+
+/// Stable 0-99 bucket of a request for canary splits
+fn canary_bucket(request: &InferenceRequest) -> u8 {
+    let hash = match &request.idempotency_key {
+        Some(key) => content_hash(&serde_json::Value::String(key.clone())),
+        None => request_fingerprint(request),
+    };
+    let prefix = u32::from_str_radix(&hash[..8], 16).unwrap_or(0);
+    (prefix % 100) as u8
+}
+
+#[async_trait]
+impl InferenceService for RoutingInferenceService {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let decision = self.explain(&request);
+        let backend = decision
+            .backend
+            .as_deref()
+            .and_then(|name| self.backends.iter().find(|backend| backend.name == name))
+            .ok_or_else(|| {
+                TylError::network(format!(
+                    "No inference backend available: {}",
+                    decision.reason
+                ))
+            })?;
+
+        let started = Instant::now();
+        let result = backend.service.infer(request).await;
+        if let Some(slo) = &self.slo {
+            slo.record(&backend.name, result.is_ok(), started.elapsed());
+        }
+
+        let mut response = result?;
+        response.metadata = response
+            .metadata
+            .with_metadata(ROUTED_BACKEND_METADATA_KEY, backend.name.clone());
+        Ok(response)
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let mut backends = serde_json::Map::new();
+        for backend in &self.backends {
+            backends.insert(
+                backend.name.clone(),
+                serde_json::json!(backend.is_healthy()),
+            );
+        }
+        let status = if self.backends.iter().any(RouteBackend::is_healthy) {
+            HealthStatus::healthy()
+        } else {
+            HealthStatus::unhealthy("no healthy backend")
+        };
+        Ok(HealthCheckResult::new(status).with_metadata("backends", backends.into()))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        let mut models = Vec::new();
+        for backend in &self.backends {
+            for model in backend.service.supported_models() {
+                if !models.contains(&model) {
+                    models.push(model);
+                }
+            }
+        }
+        models
+    }
+
+    /// Union of the backends' listings, falling back to `supported_models` for a backend
+    /// whose listing fails
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        let mut models = Vec::new();
+        for backend in &self.backends {
+            let listed = match backend.service.list_models().await {
+                Ok(listed) => listed,
+                Err(_) => backend.service.supported_models(),
+            };
+            for model in listed {
+                if !models.contains(&model) {
+                    models.push(model);
+                }
+            }
+        }
+        Ok(models)
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        match self.backends.first() {
+            Some(backend) => backend.service.count_tokens(text),
+            None => Ok((text.len() + 3) / 4),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::pricing::ModelPricing;
+    use crate::MockInferenceService;
+
+    fn mock() -> MockInferenceService {
+        MockInferenceService::new().with_latency(0)
+    }
+
+    fn request(model_type: ModelType) -> InferenceRequest {
+        InferenceRequest::new("Summarize the report", HashMap::new(), model_type)
+    }
+
+    fn rejection<'a>(decision: &'a RoutingDecision, backend: &str) -> Option<&'a str> {
+        decision
+            .candidates
+            .iter()
+            .find(|candidate| candidate.backend == backend)
+            .and_then(|candidate| candidate.rejected_because.as_deref())
+    }
+
+    #[tokio::test]
+    async fn test_explain_capability_health_and_cost() {
+        let service = RoutingInferenceService::new()
+            .with_cost_policy(CostPolicy::Cheapest)
+            .with_pricing(
+                PricingTable::new()
+                    .with_model("big", ModelPricing::new(10.0, 30.0))
+                    .with_model("small", ModelPricing::new(0.1, 0.3)),
+            )
+            .with_backend(
+                RouteBackend::new("coder", mock())
+                    .with_model_types(&[ModelType::Coding])
+                    .with_model("small"),
+            )
+            .with_backend(RouteBackend::new("premium", mock()).with_model("big"))
+            .with_backend(RouteBackend::new("budget", mock()).with_model("small"))
+            .with_backend(RouteBackend::new("unpriced", mock()).with_model("unknown"));
+
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("budget"));
+        assert!(decision.reason.contains("lowest estimated cost"));
+        assert_eq!(
+            rejection(&decision, "coder"),
+            Some("does not serve General requests")
+        );
+        assert_eq!(
+            rejection(&decision, "premium"),
+            Some("higher estimated cost")
+        );
+
+        assert!(service.set_healthy("budget", false));
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("premium"));
+        assert_eq!(rejection(&decision, "budget"), Some("unhealthy"));
+
+        let response = service.infer(request(ModelType::General)).await.unwrap();
+        assert_eq!(
+            response.metadata.metadata.get(ROUTED_BACKEND_METADATA_KEY),
+            Some(&"premium".to_string())
+        );
+
+        service.refresh_health().await;
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("budget"));
+    }
+
+    /// Healthy backend reporting a used-up request quota
+    struct Throttled(MockInferenceService);
+
+    #[async_trait]
+    impl InferenceService for Throttled {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            self.0.infer(request).await
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            let state = RateLimitState {
+                limit_requests: Some(500),
+                remaining_requests: Some(0),
+                requests_reset_at: Some(Utc::now() + chrono::Duration::seconds(30)),
+                limit_tokens: None,
+                remaining_tokens: None,
+                tokens_reset_at: None,
+                observed_at: Utc::now(),
+            };
+            Ok(
+                HealthCheckResult::new(HealthStatus::healthy()).with_metadata(
+                    crate::rate_limit::RATE_LIMIT_HEALTH_KEY,
+                    serde_json::to_value(state).unwrap(),
+                ),
+            )
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            self.0.supported_models()
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            self.0.count_tokens(text)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_rate_limit_counts_as_unhealthy() {
+        let service = RoutingInferenceService::new()
+            .with_backend(RouteBackend::new("throttled", Throttled(mock())))
+            .with_backend(RouteBackend::new("spare", mock()));
+        service.refresh_health().await;
+
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("spare"));
+        assert_eq!(rejection(&decision, "throttled"), Some("unhealthy"));
+    }
+
+    #[tokio::test]
+    async fn test_canary_weight_is_sticky() {
+        let service = RoutingInferenceService::new()
+            .with_backend(RouteBackend::new("stable", mock()))
+            .with_backend(RouteBackend::new("canary", mock()).canary(30));
+
+        let mut canary = 0;
+        for i in 0..200 {
+            let request = request(ModelType::Fast).with_idempotency_key(format!("req-{i}"));
+            let decision = service.explain(&request);
+            assert_eq!(decision, service.explain(&request));
+            if decision.backend.as_deref() == Some("canary") {
+                canary += 1;
+                assert!(decision.canary_bucket < 30);
+            } else {
+                assert_eq!(rejection(&decision, "stable"), None);
+                assert!(rejection(&decision, "canary").unwrap().contains("outside"));
+            }
+        }
+        assert!((30..90).contains(&canary), "canary share {canary}/200");
+
+        service.set_healthy("stable", false);
+        service.set_healthy("canary", false);
+        let decision = service.explain(&request(ModelType::Fast));
+        assert_eq!(decision.backend, None);
+        assert!(service.infer(request(ModelType::Fast)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fastest_policy_reads_latency_tracker() {
+        use crate::metrics::MetricsService;
+
+        let latencies = Arc::new(LatencyTracker::new());
+        let backend = |name: &str, latency_ms: u64| {
+            let service =
+                MetricsService::new(MockInferenceService::new().with_latency(latency_ms), name)
+                    .with_latency_tracker(latencies.clone());
+            RouteBackend::new(name, service)
+        };
+        let service = RoutingInferenceService::new()
+            .with_cost_policy(CostPolicy::Fastest)
+            .with_latency_tracker(latencies.clone())
+            .with_backend(backend("slow", 40))
+            .with_backend(backend("quick", 0))
+            .with_backend(backend("unmeasured", 0));
+
+        // Before any sample every backend ties, and declaration order wins
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("slow"));
+        service.infer(request(ModelType::General)).await.unwrap();
+
+        // Unsampled backends are tried before measured ones
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("quick"));
+        assert!(decision.reason.contains("no latency samples"));
+        assert_eq!(
+            rejection(&decision, "slow"),
+            Some("unsampled backend explored first")
+        );
+        service.infer(request(ModelType::General)).await.unwrap();
+        service.infer(request(ModelType::General)).await.unwrap();
+
+        let decision = service.explain(&request(ModelType::General));
+        assert!(decision.reason.contains("lowest p95 latency"));
+        assert_eq!(rejection(&decision, "slow"), Some("higher p95 latency"));
+        let slow = &decision.candidates[0];
+        assert!(slow.p95_latency.unwrap() >= Duration::from_millis(40));
+        assert!(decision.candidates[1].p95_latency.is_some());
+        assert!(decision.candidates[2].p95_latency.is_some());
+        assert_ne!(decision.backend.as_deref(), Some("slow"));
+    }
+
+    /// Backend failing every request
+    struct Failing;
+
+    #[async_trait]
+    impl InferenceService for Failing {
+        async fn infer(&self, _request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            Err(TylError::network("connection reset"))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_degraded_backends_are_last_resort() {
+        use crate::slo::{SloTarget, SloTracker};
+
+        let slo = Arc::new(SloTracker::new(SloTarget::new(0.9).with_min_requests(3)));
+        let service = RoutingInferenceService::new()
+            .with_slo_tracker(slo.clone())
+            .with_backend(RouteBackend::new("flaky", Failing))
+            .with_backend(RouteBackend::new("backup", mock()));
+
+        for _ in 0..3 {
+            assert!(service.infer(request(ModelType::General)).await.is_err());
+        }
+        assert!(slo.is_degraded("flaky"));
+
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("backup"));
+        assert!(decision.candidates[0].degraded);
+        assert_eq!(
+            rejection(&decision, "flaky"),
+            Some("degraded: error budget exhausted")
+        );
+        service.infer(request(ModelType::General)).await.unwrap();
+        assert_eq!(slo.report("backup").requests, 1);
+
+        // With the backup down, the degraded backend still serves
+        service.set_healthy("backup", false);
+        let decision = service.explain(&request(ModelType::General));
+        assert_eq!(decision.backend.as_deref(), Some("flaky"));
+    }
+
+    #[test]
+    fn test_catalog_filters_small_context_windows() {
+        let catalog = ModelCatalog::new()
+            .with_model(crate::ModelInfo::new("tiny", 1024, 512))
+            .with_model(crate::ModelInfo::new("large", 128_000, 4096));
+        let service = RoutingInferenceService::new()
+            .with_catalog(catalog)
+            .with_backend(RouteBackend::new("local", mock()).with_model("tiny"))
+            .with_backend(RouteBackend::new("cloud", mock()).with_model("large"));
+
+        let short = request(ModelType::General).with_max_tokens(256);
+        assert_eq!(service.explain(&short).backend.as_deref(), Some("local"));
+
+        let long = request(ModelType::General).with_max_tokens(2000);
+        let decision = service.explain(&long);
+        assert_eq!(decision.backend.as_deref(), Some("cloud"));
+        assert!(rejection(&decision, "local")
+            .unwrap()
+            .contains("over the 1024 context window of tiny"));
+    }
+
+    #[test]
+    fn test_catalog_routes_images_to_vision_models() {
+        let catalog = ModelCatalog::new()
+            .with_model(crate::ModelInfo::new("text", 128_000, 4096))
+            .with_model(
+                crate::ModelInfo::new("vision", 128_000, 4096)
+                    .with_features(&[ModelFeature::Vision]),
+            );
+        let service = RoutingInferenceService::new()
+            .with_catalog(catalog)
+            .with_backend(RouteBackend::new("text", mock()).with_model("text"))
+            .with_backend(RouteBackend::new("vision", mock()).with_model("vision"));
+
+        let plain = request(ModelType::General);
+        assert_eq!(service.explain(&plain).backend.as_deref(), Some("text"));
+
+        let image = plain.with_attachment(crate::MediaInput::from_url(
+            "image/png",
+            "https://example.com/chart.png",
+        ));
+        let decision = service.explain(&image);
+        assert_eq!(decision.backend.as_deref(), Some("vision"));
+        assert!(rejection(&decision, "text")
+            .unwrap()
+            .contains("does not accept image inputs"));
+    }
+}
@@ -0,0 +1,597 @@
+//! Structured responses validated against a JSON schema
+//!
+//! `ResponseSchema` checks response content against a JSON schema (the subset models are asked
+//! to follow: `type`, `properties`, `required`, `additionalProperties: false`, `items` and
+//! `enum`), and `infer_typed` turns a valid response into a Rust value:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::schema::*;
+//! # use serde_json::json;
+//! # #[derive(serde::Deserialize)]
+//! # struct Invoice { total: i64, paid: bool }
+//! # async fn example(service: impl InferenceService + 'static, request: InferenceRequest) -> TylResult<()> {
+//! let schema = ResponseSchema::new(json!({
+//!     "type": "object",
+//!     "properties": { "total": { "type": "integer" }, "paid": { "type": "boolean" } },
+//!     "required": ["total", "paid"]
+//! }))
+//! .lenient();
+//! let invoice: Invoice = infer_typed(&service, request, &schema).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Models regularly get the shape right and the scalar types wrong: `"42"` for `42`, `"true"`
+//! for `true`, a bare value where a one-element array was asked for, a whole object serialized
+//! into a string. A `lenient` schema coerces those mismatches to the declared types before the
+//! strict check runs, and only fails the request when the content still does not match. Values
+//! are never coerced lossily: `"4.5"` does not become an integer.
+//!
+//! Content that still fails can be repaired by the model itself: with `with_repairs(n)`,
+//! `infer_typed` sends the invalid output back together with the validation errors and asks
+//! for corrected JSON, up to `n` times. The returned response's token usage covers every
+//! attempt, and the per-attempt totals are listed under `schema_attempt_tokens`.
+
+use crate::speculative::AnswerValidator;
+use crate::*;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Response metadata key listing the paths `infer_typed` coerced, comma separated (`$` is the
+/// root)
+pub const SCHEMA_COERCIONS_METADATA_KEY: &str = "schema_coercions";
+
+/// Response metadata key listing the total tokens of each attempt, comma separated, when the
+/// content needed repairs
+pub const SCHEMA_ATTEMPT_TOKENS_METADATA_KEY: &str = "schema_attempt_tokens";
+
+/// Prompt asking the model to correct content that failed validation
+pub const REPAIR_TEMPLATE: &str = "Your previous answer is not valid. Fix it so it matches the \
+JSON schema below and answer with the corrected JSON only, without any other text.\n\n\
+JSON schema:\n{{schema}}\n\n\
+Previous answer:\n{{output}}\n\n\
+Errors:\n{{errors}}";
+
+/// JSON schema that response content must match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseSchema {
+    pub schema: Value,
+    /// Coerce common type mismatches before validating
+    #[serde(default)]
+    pub lenient: bool,
+    /// Times the model is asked to fix content that fails validation
+    #[serde(default)]
+    pub max_repairs: u32,
+}
+
+impl ResponseSchema {
+    /// Strict validation against `schema`
+    pub fn new(schema: Value) -> Self {
+        Self {
+            schema,
+            lenient: false,
+            max_repairs: 0,
+        }
+    }
+
+    /// Coerce common type mismatches before validating
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Re-prompt the model with the validation errors up to `max_repairs` times
+    pub fn with_repairs(mut self, max_repairs: u32) -> Self {
+        self.max_repairs = max_repairs;
+        self
+    }
+
+    /// Request asking for `output` to be corrected, keeping the model and settings of `request`
+    fn repair_request(
+        &self,
+        request: &InferenceRequest,
+        output: &Value,
+        error: &TylError,
+    ) -> InferenceRequest {
+        let output = match output {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        let mut repair = request.clone();
+        repair.template = REPAIR_TEMPLATE.to_string();
+        repair.attachments.clear();
+        repair.parameters = HashMap::from([
+            ("schema".to_string(), self.schema.to_string()),
+            ("output".to_string(), output),
+            ("errors".to_string(), error.to_string()),
+        ]);
+        repair
+    }
+
+    /// Check `content`, coercing it first when lenient
+    ///
+    /// Returns the (possibly coerced) content and the paths that were coerced.
+    pub fn validate(&self, content: Value) -> InferenceResult<(Value, Vec<String>)> {
+        let mut coerced = Vec::new();
+        let content = if self.lenient {
+            coerce(content, &self.schema, "$", &mut coerced)
+        } else {
+            content
+        };
+        let mut errors = Vec::new();
+        check(&content, &self.schema, "$", &mut errors);
+        if !errors.is_empty() {
+            return Err(TylError::validation(
+                "response",
+                format!("Response does not match its schema: {}", errors.join("; ")),
+            ));
+        }
+        Ok((content, coerced))
+    }
+}
+
+impl AnswerValidator for ResponseSchema {
+    fn accept(&self, _request: &InferenceRequest, response: &InferenceResponse) -> bool {
+        self.validate(response.content.clone()).is_ok()
+    }
+}
+
+/// Types a schema node allows; empty when it does not constrain the type
+fn allowed_types(schema: &Value) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+/// `value` converted to the type `name` without losing information
+fn convert(value: &Value, name: &str) -> Option<Value> {
+    match (name, value) {
+        ("integer", Value::String(text)) => {
+            let text = text.trim();
+            text.parse::<i64>()
+                .ok()
+                .map(Value::from)
+                .or_else(|| convert(&Value::from(text.parse::<f64>().ok()?), name))
+        }
+        ("integer", Value::Number(number)) => {
+            let float = number.as_f64()?;
+            (float.fract() == 0.0 && float.abs() < i64::MAX as f64)
+                .then(|| Value::from(float as i64))
+        }
+        ("number", Value::String(text)) => text
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())
+            .map(Value::from),
+        ("boolean", Value::String(text)) => match text.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" => Some(Value::Bool(true)),
+            "false" | "no" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ("boolean", Value::Number(number)) => {
+            let number = number.as_f64()?;
+            (number == 0.0 || number == 1.0).then_some(Value::Bool(number == 1.0))
+        }
+        ("string", Value::Number(_) | Value::Bool(_)) => Some(Value::String(value.to_string())),
+        ("null", Value::String(text)) if text.trim().is_empty() || text.trim() == "null" => {
+            Some(Value::Null)
+        }
+        ("object" | "array", Value::String(text)) => serde_json::from_str::<Value>(text.trim())
+            .ok()
+            .filter(|parsed| has_type(parsed, name)),
+        ("array", other) if !other.is_null() => Some(Value::Array(vec![other.clone()])),
+        _ => None,
+    }
+}
+
+/// Coerce `value` towards `schema`, recording the coerced paths
+fn coerce(value: Value, schema: &Value, path: &str, coerced: &mut Vec<String>) -> Value {
+    let types = allowed_types(schema);
+    let mut value = value;
+    if !types.is_empty() && !types.iter().any(|name| has_type(&value, name)) {
+        if let Some(converted) = types.iter().find_map(|name| convert(&value, name)) {
+            value = converted;
+            coerced.push(path.to_string());
+        }
+    }
+
+    if let (Value::String(text), Some(Value::Array(options))) = (&value, schema.get("enum")) {
+        let matching = options.iter().find(|option| {
+            option
+                .as_str()
+                .is_some_and(|option| option != text && option.eq_ignore_ascii_case(text.trim()))
+        });
+        if let Some(option) = matching {
+            value = option.clone();
+            coerced.push(path.to_string());
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, field)| {
+                        let field = match properties.and_then(|p| p.get(&name)) {
+                            Some(field_schema) => {
+                                coerce(field, field_schema, &format!("{path}.{name}"), coerced)
+                            }
+                            None => field,
+                        };
+                        (name, field)
+                    })
+                    .collect(),
+            )
+        }
+        Value::Array(items) => match schema.get("items") {
+            Some(item_schema) => Value::Array(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, item)| {
+                        coerce(item, item_schema, &format!("{path}[{index}]"), coerced)
+                    })
+                    .collect(),
+            ),
+            None => Value::Array(items),
+        },
+        other => other,
+    }
+}
+
+/// Strictly check `value` against `schema`, collecting errors
+fn check(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let types = allowed_types(schema);
+    if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+        errors.push(format!("{path} should be {}", types.join(" or ")));
+        return;
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            errors.push(format!(
+                "{path} should be one of {}",
+                Value::Array(options.clone())
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !fields.contains_key(name) {
+                        errors.push(format!("{path}.{name} is required"));
+                    }
+                }
+            }
+            for (name, field) in fields {
+                match properties.and_then(|p| p.get(name)) {
+                    Some(field_schema) => {
+                        check(field, field_schema, &format!("{path}.{name}"), errors)
+                    }
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        errors.push(format!("{path}.{name} is not allowed"))
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check(item, item_schema, &format!("{path}[{index}]"), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Run `request` and decode the response content, validated against `schema`, into `T`
+pub async fn infer_typed<T, S>(
+    service: &S,
+    request: InferenceRequest,
+    schema: &ResponseSchema,
+) -> InferenceResult<T>
+where
+    T: DeserializeOwned,
+    S: InferenceService + ?Sized,
+{
+    infer_typed_with_response(service, request, schema)
+        .await
+        .map(|(value, _)| value)
+}
+
+/// Like `infer_typed`, also returning the response with the coerced content and the coerced
+/// paths under `SCHEMA_COERCIONS_METADATA_KEY`
+///
+/// When repairs were needed, the token usage sums every attempt and
+/// `SCHEMA_ATTEMPT_TOKENS_METADATA_KEY` lists each attempt's total.
+pub async fn infer_typed_with_response<T, S>(
+    service: &S,
+    request: InferenceRequest,
+    schema: &ResponseSchema,
+) -> InferenceResult<(T, InferenceResponse)>
+where
+    T: DeserializeOwned,
+    S: InferenceService + ?Sized,
+{
+    let mut response = service.infer(request.clone()).await?;
+    let mut attempt_tokens = vec![response.metadata.token_usage.total_tokens];
+    let mut prompt_tokens = 0;
+    let mut completion_tokens = 0;
+    loop {
+        prompt_tokens += response.metadata.token_usage.prompt_tokens;
+        completion_tokens += response.metadata.token_usage.completion_tokens;
+        let output = std::mem::take(&mut response.content);
+        let error = match decode::<T>(schema, output.clone()) {
+            Ok((value, content, coerced)) => {
+                response.content = content;
+                if !coerced.is_empty() {
+                    response.metadata = response
+                        .metadata
+                        .with_metadata(SCHEMA_COERCIONS_METADATA_KEY, coerced.join(","));
+                }
+                if attempt_tokens.len() > 1 {
+                    response.metadata.token_usage =
+                        TokenUsage::new(prompt_tokens, completion_tokens);
+                    let attempts: Vec<String> = attempt_tokens.iter().map(u32::to_string).collect();
+                    response.metadata = response
+                        .metadata
+                        .with_metadata(SCHEMA_ATTEMPT_TOKENS_METADATA_KEY, attempts.join(","));
+                }
+                return Ok((value, response));
+            }
+            Err(error) => error,
+        };
+        if attempt_tokens.len() > schema.max_repairs as usize {
+            return Err(error);
+        }
+        response = service
+            .infer(schema.repair_request(&request, &output, &error))
+            .await?;
+        attempt_tokens.push(response.metadata.token_usage.total_tokens);
+    }
+}
+
+/// Validate `content` and decode it into `T`, returning the value, the (possibly coerced)
+/// content and the coerced paths
+fn decode<T: DeserializeOwned>(
+    schema: &ResponseSchema,
+    content: Value,
+) -> InferenceResult<(T, Value, Vec<String>)> {
+    let (content, coerced) = schema.validate(content)?;
+    let value = serde_json::from_value(content.clone()).map_err(|e| {
+        TylError::validation("response", format!("Response does not match the type: {e}"))
+    })?;
+    Ok((value, content, coerced))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn invoice_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "total": { "type": "integer" },
+                "paid": { "type": "boolean" },
+                "ratio": { "type": "number" },
+                "status": { "enum": ["open", "closed"] },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "note": { "type": ["string", "null"] }
+            },
+            "required": ["total", "paid"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn test_strict_validation_reports_every_error() {
+        let schema = ResponseSchema::new(invoice_schema());
+        assert!(schema
+            .validate(json!({"total": 42, "paid": true, "note": null}))
+            .is_ok());
+        let error = schema
+            .validate(json!({"total": "42", "status": "void", "extra": 1}))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("$.total should be integer"));
+        assert!(error.contains("$.paid is required"));
+        assert!(error.contains("$.status should be one of"));
+        assert!(error.contains("$.extra is not allowed"));
+    }
+
+    #[test]
+    fn test_lenient_coerces_common_mismatches() {
+        let schema = ResponseSchema::new(invoice_schema()).lenient();
+        let (content, coerced) = schema
+            .validate(json!({
+                "total": " 42 ",
+                "paid": "TRUE",
+                "ratio": "0.5",
+                "status": "Open",
+                "tags": 7,
+                "note": ""
+            }))
+            .unwrap();
+        assert_eq!(
+            content,
+            json!({
+                "total": 42,
+                "paid": true,
+                "ratio": 0.5,
+                "status": "open",
+                "tags": ["7"],
+                "note": ""
+            })
+        );
+        assert_eq!(
+            coerced,
+            vec![
+                "$.paid",
+                "$.ratio",
+                "$.status",
+                "$.tags",
+                "$.tags[0]",
+                "$.total"
+            ]
+        );
+
+        // A serialized object is parsed, lossy conversions are refused
+        let (content, _) = schema
+            .validate(json!(r#"{"total": 1.0, "paid": 0}"#))
+            .unwrap();
+        assert_eq!(content, json!({"total": 1, "paid": false}));
+        assert!(schema
+            .validate(json!({"total": "4.5", "paid": true}))
+            .is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_infer_typed() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Invoice {
+            total: i64,
+            paid: bool,
+        }
+
+        let service = crate::MockInferenceService::new()
+            .with_latency(0)
+            .with_custom_response(r#"{"total": "42", "paid": "yes"}"#);
+        let request = InferenceRequest::new("Extract", HashMap::new(), ModelType::Fast);
+
+        let strict = ResponseSchema::new(invoice_schema());
+        assert!(
+            infer_typed::<Invoice, _>(&service, request.clone(), &strict)
+                .await
+                .is_err()
+        );
+
+        let (invoice, response) =
+            infer_typed_with_response::<Invoice, _>(&service, request, &strict.lenient())
+                .await
+                .unwrap();
+        assert_eq!(
+            invoice,
+            Invoice {
+                total: 42,
+                paid: true
+            }
+        );
+        assert_eq!(
+            response.metadata.metadata[SCHEMA_COERCIONS_METADATA_KEY],
+            "$.paid,$.total"
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    /// Answers with the next scripted content, recording the prompts it received
+    struct Scripted {
+        answers: std::sync::Mutex<Vec<&'static str>>,
+        prompts: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[cfg(feature = "mock")]
+    #[async_trait]
+    impl InferenceService for Scripted {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            self.prompts.lock().unwrap().push(request.render_template());
+            let answer = self.answers.lock().unwrap().remove(0);
+            Ok(InferenceResponse::new(
+                serde_json::from_str(answer).unwrap(),
+                ResponseMetadata::new(
+                    "scripted".to_string(),
+                    TokenUsage::new(10, answer.len() as u32),
+                    1,
+                ),
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["scripted".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_repairs_invalid_content() {
+        #[derive(Debug, Deserialize)]
+        struct Invoice {
+            total: i64,
+        }
+
+        let service = Scripted {
+            answers: std::sync::Mutex::new(vec![
+                r#"{"total": "many"}"#,
+                r#"{"total": 4.5}"#,
+                r#"{"total": 42, "paid": true}"#,
+            ]),
+            prompts: std::sync::Mutex::new(Vec::new()),
+        };
+        let request = InferenceRequest::new("Extract", HashMap::new(), ModelType::Fast);
+        let schema = ResponseSchema::new(invoice_schema()).with_repairs(2);
+
+        let (invoice, response) =
+            infer_typed_with_response::<Invoice, _>(&service, request.clone(), &schema)
+                .await
+                .unwrap();
+        assert_eq!(invoice.total, 42);
+        assert_eq!(
+            response.metadata.metadata[SCHEMA_ATTEMPT_TOKENS_METADATA_KEY],
+            "27,24,37"
+        );
+        assert_eq!(response.metadata.token_usage, TokenUsage::new(30, 58));
+
+        {
+            let prompts = service.prompts.lock().unwrap();
+            assert_eq!(prompts[0], "Extract");
+            assert!(prompts[1].contains(r#"{"total":"many"}"#));
+            assert!(prompts[1].contains("$.total should be integer"));
+            assert!(prompts[2].contains(r#"{"total":4.5}"#));
+        }
+
+        // Out of repairs: the last validation error is returned
+        service
+            .answers
+            .lock()
+            .unwrap()
+            .extend([r#"{"total": "x"}"#, r#"{"total": "y"}"#]);
+        let err = infer_typed::<Invoice, _>(&service, request, &schema.with_repairs(1))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("$.total should be integer"));
+        assert!(service.answers.lock().unwrap().is_empty());
+    }
+}
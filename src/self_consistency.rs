@@ -0,0 +1,314 @@
+//! Self-consistency voting over repeated samples
+//!
+//! `SelfConsistencyService` samples the same request several times at a raised temperature,
+//! reduces every sample to a comparable answer with a user-provided `AnswerExtractor` (the final
+//! number of a chain of thought, a label, a normalized JSON field, ...) and returns the sample
+//! holding the most common answer. Reasoning paths that disagree on the way but agree on the
+//! result reinforce each other, which makes the returned answer more reliable than a single
+//! greedy generation:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::self_consistency::*;
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let service = SelfConsistencyService::new(openai, |response: &InferenceResponse| {
+//!     response.content["answer"].as_i64().map(|answer| answer.to_string())
+//! })
+//! .with_samples(7);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every sample is billed, so the response reports the summed token usage. The winning answer,
+//! the tally and the share of votes it got are recorded in the response metadata.
+
+use crate::ensemble::JoinAll;
+use crate::*;
+use std::time::Instant;
+
+/// Response metadata key holding the winning answer
+pub const SELF_CONSISTENCY_ANSWER_METADATA_KEY: &str = "self_consistency_answer";
+/// Response metadata key holding the votes per answer as a JSON object
+pub const SELF_CONSISTENCY_VOTES_METADATA_KEY: &str = "self_consistency_votes";
+/// Response metadata key holding the winning answer's share of the valid votes (0.0 to 1.0)
+pub const SELF_CONSISTENCY_AGREEMENT_METADATA_KEY: &str = "self_consistency_agreement";
+
+/// Reduces a sample to the answer that is voted on, `None` when it has no usable answer
+///
+/// Implemented for closures `Fn(&InferenceResponse) -> Option<String>`.
+pub trait AnswerExtractor: Send + Sync {
+    fn extract(&self, response: &InferenceResponse) -> Option<String>;
+}
+
+impl<F> AnswerExtractor for F
+where
+    F: Fn(&InferenceResponse) -> Option<String> + Send + Sync,
+{
+    fn extract(&self, response: &InferenceResponse) -> Option<String> {
+        self(response)
+    }
+}
+
+/// Inference service decorator returning the most consistent of several sampled answers
+pub struct SelfConsistencyService<S> {
+    inner: S,
+    extractor: Box<dyn AnswerExtractor>,
+    samples: usize,
+    temperature: f32,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for SelfConsistencyService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SelfConsistencyService")
+            .field("inner", &self.inner)
+            .field("samples", &self.samples)
+            .field("temperature", &self.temperature)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> SelfConsistencyService<S> {
+    /// Vote over 5 samples taken at temperature 0.8
+    pub fn new(inner: S, extractor: impl AnswerExtractor + 'static) -> Self {
+        Self {
+            inner,
+            extractor: Box::new(extractor),
+            samples: 5,
+            temperature: 0.8,
+        }
+    }
+
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// Sampling temperature, replacing the request's
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn sample_request(&self, request: &InferenceRequest, index: usize) -> InferenceRequest {
+        let mut sample = request.clone().with_temperature(self.temperature);
+        // A fixed seed would make every sample identical; keep runs reproducible instead
+        sample.seed = request.seed.map(|seed| seed.wrapping_add(index as u64));
+        sample
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for SelfConsistencyService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let started = Instant::now();
+        let results = JoinAll::new((0..self.samples).map(|index| {
+            let sample = self.sample_request(&request, index);
+            self.inner.infer(sample)
+        }))
+        .await;
+
+        let mut prompt_tokens = 0;
+        let mut completion_tokens = 0;
+        let mut first_error = None;
+        // (answer, votes, first sample with it), in order of first appearance
+        let mut tally: Vec<(String, usize, InferenceResponse)> = Vec::new();
+        for result in results {
+            let response = match result {
+                Ok(response) => response,
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                    continue;
+                }
+            };
+            prompt_tokens += response.metadata.token_usage.prompt_tokens;
+            completion_tokens += response.metadata.token_usage.completion_tokens;
+            let Some(answer) = self.extractor.extract(&response) else {
+                continue;
+            };
+            match tally.iter_mut().find(|(seen, _, _)| *seen == answer) {
+                Some((_, votes, _)) => *votes += 1,
+                None => tally.push((answer, 1, response)),
+            }
+        }
+
+        let valid_votes: usize = tally.iter().map(|(_, votes, _)| votes).sum();
+        let votes: serde_json::Map<String, serde_json::Value> = tally
+            .iter()
+            .map(|(answer, votes, _)| (answer.clone(), (*votes).into()))
+            .collect();
+        // Ties go to the answer seen first
+        let mut winner: Option<(String, usize, InferenceResponse)> = None;
+        for entry in tally {
+            if winner.as_ref().map_or(true, |(_, best, _)| entry.1 > *best) {
+                winner = Some(entry);
+            }
+        }
+        let Some((answer, answer_votes, mut response)) = winner else {
+            return Err(first_error.unwrap_or_else(|| {
+                inference_errors::generation_failed(format!(
+                    "None of {} self-consistency samples had an extractable answer",
+                    self.samples
+                ))
+            }));
+        };
+
+        response.metadata.token_usage = TokenUsage::new(prompt_tokens, completion_tokens);
+        response.metadata.processing_time_ms = started.elapsed().as_millis() as u64;
+        response.metadata = response
+            .metadata
+            .with_metadata(SELF_CONSISTENCY_ANSWER_METADATA_KEY, answer)
+            .with_metadata(
+                SELF_CONSISTENCY_VOTES_METADATA_KEY,
+                serde_json::Value::Object(votes).to_string(),
+            )
+            .with_metadata(
+                SELF_CONSISTENCY_AGREEMENT_METADATA_KEY,
+                format!("{:.2}", answer_votes as f64 / valid_votes as f64),
+            );
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Answers from a script, one entry per call; `None` fails the call
+    struct Scripted {
+        answers: Vec<Option<&'static str>>,
+        calls: AtomicUsize,
+        temperatures: Mutex<Vec<Option<f32>>>,
+    }
+
+    impl Scripted {
+        fn new(answers: Vec<Option<&'static str>>) -> Self {
+            Self {
+                answers,
+                calls: AtomicUsize::new(0),
+                temperatures: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl InferenceService for Scripted {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            self.temperatures.lock().unwrap().push(request.temperature);
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.answers[call % self.answers.len()] {
+                Some(answer) => Ok(InferenceResponse::new(
+                    serde_json::json!({ "reasoning": format!("path {call}"), "answer": answer }),
+                    ResponseMetadata::new("gpt-4o".to_string(), TokenUsage::new(10, 5), 1),
+                )),
+                None => Err(TylError::network("sample failed")),
+            }
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            vec!["gpt-4o".to_string()]
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn answer(response: &InferenceResponse) -> Option<String> {
+        response.content["answer"].as_str().map(str::to_string)
+    }
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("What is 6 * 7?", HashMap::new(), ModelType::Reasoning)
+    }
+
+    #[tokio::test]
+    async fn test_returns_majority_answer_with_votes() {
+        let service = SelfConsistencyService::new(
+            Scripted::new(vec![Some("41"), Some("42"), None, Some("42"), Some("oops")]),
+            |response: &InferenceResponse| answer(response).filter(|a| a.parse::<i64>().is_ok()),
+        )
+        .with_temperature(0.9);
+
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(response.content["answer"], "42");
+        assert_eq!(response.content["reasoning"], "path 1");
+        let metadata = &response.metadata.metadata;
+        assert_eq!(
+            metadata.get(SELF_CONSISTENCY_ANSWER_METADATA_KEY),
+            Some(&"42".to_string())
+        );
+        let votes: serde_json::Value =
+            serde_json::from_str(&metadata[SELF_CONSISTENCY_VOTES_METADATA_KEY]).unwrap();
+        assert_eq!(votes, serde_json::json!({"41": 1, "42": 2}));
+        assert_eq!(
+            metadata.get(SELF_CONSISTENCY_AGREEMENT_METADATA_KEY),
+            Some(&"0.67".to_string())
+        );
+        // Four successful samples are billed
+        assert_eq!(response.metadata.token_usage, TokenUsage::new(40, 20));
+        assert!(service
+            .inner()
+            .temperatures
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|temperature| *temperature == Some(0.9)));
+    }
+
+    #[tokio::test]
+    async fn test_fails_without_any_answer() {
+        let service = SelfConsistencyService::new(Scripted::new(vec![None]), answer);
+        let error = service.infer(request()).await.unwrap_err();
+        assert!(error.to_string().contains("sample failed"));
+
+        let service =
+            SelfConsistencyService::new(Scripted::new(vec![Some("x")]), |_: &InferenceResponse| {
+                None
+            })
+            .with_samples(3);
+        assert!(service.infer(request()).await.is_err());
+    }
+}
@@ -0,0 +1,323 @@
+//! SLO and error-budget tracking per backend
+//!
+//! `SloTracker` keeps the outcome and latency of every request over a rolling window per backend
+//! and compares them with an `SloTarget`: the fraction of requests that must succeed, and
+//! optionally the fraction that must finish under a latency threshold. The failures (or slow
+//! requests) a target tolerates form its error budget; a backend that has spent its whole budget
+//! is *degraded* until enough bad requests age out of the window.
+//!
+//! `RoutingInferenceService::with_slo_tracker` records every routed request and sends traffic to
+//! degraded backends only when no other eligible backend is left:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::slo::*;
+//! # use tyl_llm_inference_port::routing::*;
+//! # use std::sync::Arc;
+//! # use std::time::Duration;
+//! # async fn example(primary: impl InferenceService + 'static, secondary: impl InferenceService + 'static) -> TylResult<()> {
+//! let slo = Arc::new(SloTracker::new(
+//!     SloTarget::new(0.99).with_latency(Duration::from_secs(5), 0.95),
+//! ));
+//! let router = RoutingInferenceService::new()
+//!     .with_slo_tracker(slo.clone())
+//!     .with_backend(RouteBackend::new("primary", primary))
+//!     .with_backend(RouteBackend::new("secondary", secondary));
+//! // ...
+//! println!("{:?}", slo.report("primary"));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::*;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Objectives a backend is held to over a rolling window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SloTarget {
+    /// Fraction of requests that must succeed (e.g. 0.99)
+    pub availability: f64,
+    /// Latency a request must finish within to count as fast
+    pub latency_threshold: Option<Duration>,
+    /// Fraction of successful requests that must be fast (e.g. 0.95)
+    pub latency_target: f64,
+    /// Length of the rolling window
+    pub window: Duration,
+    /// Requests needed in the window before a backend can be degraded
+    pub min_requests: u64,
+}
+
+impl SloTarget {
+    /// Availability objective over a 1 hour window, with at least 20 requests to judge
+    pub fn new(availability: f64) -> Self {
+        Self {
+            availability: availability.clamp(0.0, 1.0),
+            latency_threshold: None,
+            latency_target: 0.0,
+            window: Duration::from_secs(3600),
+            min_requests: 20,
+        }
+    }
+
+    /// Also require `target` of successful requests to finish within `threshold`
+    pub fn with_latency(mut self, threshold: Duration, target: f64) -> Self {
+        self.latency_threshold = Some(threshold);
+        self.latency_target = target.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_min_requests(mut self, min_requests: u64) -> Self {
+        self.min_requests = min_requests;
+        self
+    }
+}
+
+/// Remaining share of an error budget: 1 when untouched, 0 when spent (never negative)
+fn budget_remaining(bad: u64, total: u64, objective: f64) -> f64 {
+    let allowed = (1.0 - objective) * total as f64;
+    if bad == 0 {
+        1.0
+    } else if allowed <= 0.0 {
+        0.0
+    } else {
+        (1.0 - bad as f64 / allowed).max(0.0)
+    }
+}
+
+/// SLO compliance of one backend over the current window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SloReport {
+    pub backend: String,
+    /// Requests in the window
+    pub requests: u64,
+    pub failures: u64,
+    /// Successful requests slower than the latency threshold
+    pub slow_requests: u64,
+    /// Fraction of requests that succeeded (1 without requests)
+    pub availability: f64,
+    /// Fraction of successful requests within the latency threshold, `None` without a latency
+    /// objective or successful requests
+    pub latency_compliance: Option<f64>,
+    pub availability_budget_remaining: f64,
+    pub latency_budget_remaining: f64,
+    /// An error budget is exhausted
+    pub degraded: bool,
+}
+
+impl SloReport {
+    fn new(backend: &str, target: &SloTarget, events: &VecDeque<Event>) -> Self {
+        let requests = events.len() as u64;
+        let failures = events.iter().filter(|event| !event.success).count() as u64;
+        let successes = requests - failures;
+        let slow_requests = target.latency_threshold.map_or(0, |threshold| {
+            events
+                .iter()
+                .filter(|event| event.success && event.latency > threshold)
+                .count() as u64
+        });
+
+        let availability = match requests {
+            0 => 1.0,
+            requests => successes as f64 / requests as f64,
+        };
+        let latency_compliance = target
+            .latency_threshold
+            .filter(|_| successes > 0)
+            .map(|_| (successes - slow_requests) as f64 / successes as f64);
+        let availability_budget_remaining =
+            budget_remaining(failures, requests, target.availability);
+        let latency_budget_remaining = match target.latency_threshold {
+            Some(_) => budget_remaining(slow_requests, successes, target.latency_target),
+            None => 1.0,
+        };
+        let degraded = requests >= target.min_requests.max(1)
+            && (availability_budget_remaining <= 0.0 || latency_budget_remaining <= 0.0);
+
+        Self {
+            backend: backend.to_string(),
+            requests,
+            failures,
+            slow_requests,
+            availability,
+            latency_compliance,
+            availability_budget_remaining,
+            latency_budget_remaining,
+            degraded,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Event {
+    at: DateTime<Utc>,
+    success: bool,
+    latency: Duration,
+}
+
+/// Rolling availability and latency compliance per backend
+///
+/// Every request in the window is kept, so memory grows with the request rate times the window.
+#[derive(Debug)]
+pub struct SloTracker {
+    target: SloTarget,
+    targets: HashMap<String, SloTarget>,
+    events: Mutex<HashMap<String, VecDeque<Event>>>,
+}
+
+impl SloTracker {
+    /// Hold every backend to `target`
+    pub fn new(target: SloTarget) -> Self {
+        Self {
+            target,
+            targets: HashMap::new(),
+            events: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Hold one backend to its own target
+    pub fn with_backend_target(mut self, backend: impl Into<String>, target: SloTarget) -> Self {
+        self.targets.insert(backend.into(), target);
+        self
+    }
+
+    /// Target a backend is held to
+    pub fn target(&self, backend: &str) -> &SloTarget {
+        self.targets.get(backend).unwrap_or(&self.target)
+    }
+
+    /// Record a request that just finished
+    pub fn record(&self, backend: &str, success: bool, latency: Duration) {
+        self.record_at(backend, Utc::now(), success, latency);
+    }
+
+    /// Record a request that finished at `at`
+    pub fn record_at(&self, backend: &str, at: DateTime<Utc>, success: bool, latency: Duration) {
+        let mut events = self.events.lock().unwrap();
+        let backend_events = events.entry(backend.to_string()).or_default();
+        backend_events.push_back(Event {
+            at,
+            success,
+            latency,
+        });
+        self.prune(backend, backend_events, at);
+    }
+
+    /// Compliance of a backend over the window ending now
+    pub fn report(&self, backend: &str) -> SloReport {
+        self.report_at(backend, Utc::now())
+    }
+
+    /// Compliance of a backend over the window ending at `now`
+    pub fn report_at(&self, backend: &str, now: DateTime<Utc>) -> SloReport {
+        let mut events = self.events.lock().unwrap();
+        let Some(backend_events) = events.get_mut(backend) else {
+            return SloReport::new(backend, self.target(backend), &VecDeque::new());
+        };
+        self.prune(backend, backend_events, now);
+        SloReport::new(backend, self.target(backend), backend_events)
+    }
+
+    /// Reports of every backend with recorded requests, sorted by backend
+    pub fn reports(&self) -> Vec<SloReport> {
+        let mut backends: Vec<String> = self.events.lock().unwrap().keys().cloned().collect();
+        backends.sort();
+        backends
+            .iter()
+            .map(|backend| self.report(backend))
+            .collect()
+    }
+
+    /// Whether a backend has exhausted an error budget
+    pub fn is_degraded(&self, backend: &str) -> bool {
+        self.report(backend).degraded
+    }
+
+    fn prune(&self, backend: &str, events: &mut VecDeque<Event>, now: DateTime<Utc>) {
+        let window = chrono::Duration::from_std(self.target(backend).window)
+            .unwrap_or(chrono::Duration::MAX);
+        let cutoff = now.checked_sub_signed(window).unwrap_or_default();
+        while events.front().is_some_and(|event| event.at <= cutoff) {
+            events.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(minutes: i64) -> DateTime<Utc> {
+        DateTime::<Utc>::UNIX_EPOCH + chrono::Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn test_availability_budget() {
+        let tracker = SloTracker::new(
+            SloTarget::new(0.9)
+                .with_window(Duration::from_secs(600))
+                .with_min_requests(10),
+        );
+        let fast = Duration::from_millis(100);
+        for _ in 0..19 {
+            tracker.record_at("primary", minutes(0), true, fast);
+        }
+        tracker.record_at("primary", minutes(1), false, fast);
+
+        // 1 failure out of 20 spends half of the 10% budget
+        let report = tracker.report_at("primary", minutes(2));
+        assert_eq!(report.requests, 20);
+        assert!((report.availability - 0.95).abs() < 1e-9);
+        assert!((report.availability_budget_remaining - 0.5).abs() < 1e-9);
+        assert!(!report.degraded);
+
+        tracker.record_at("primary", minutes(3), false, fast);
+        tracker.record_at("primary", minutes(3), false, fast);
+        let report = tracker.report_at("primary", minutes(3));
+        assert_eq!(report.failures, 3);
+        assert!(report.degraded);
+        assert_eq!(report.availability_budget_remaining, 0.0);
+
+        // Once the earlier requests leave the window, too few remain to judge
+        let aged = tracker.report_at("primary", minutes(11));
+        assert_eq!(aged.requests, 2);
+        assert!(!aged.degraded);
+        assert_eq!(tracker.report_at("primary", minutes(14)).requests, 0);
+    }
+
+    #[test]
+    fn test_latency_budget_and_min_requests() {
+        let tracker = SloTracker::new(SloTarget::new(0.99)).with_backend_target(
+            "local",
+            SloTarget::new(0.5)
+                .with_latency(Duration::from_secs(1), 0.8)
+                .with_min_requests(5),
+        );
+        let now = Utc::now();
+        for latency_ms in [200, 300, 400, 1500] {
+            tracker.record_at("local", now, true, Duration::from_millis(latency_ms));
+        }
+        let report = tracker.report_at("local", now);
+        assert_eq!(report.slow_requests, 1);
+        assert_eq!(report.latency_compliance, Some(0.75));
+        // Over budget, but not enough requests to judge yet
+        assert!(!report.degraded);
+
+        tracker.record_at("local", now, true, Duration::from_millis(100));
+        let report = tracker.report_at("local", now);
+        assert!((report.latency_budget_remaining - 0.0).abs() < 1e-9);
+        assert!(report.degraded);
+
+        // Other backends use the default target and are judged independently
+        tracker.record_at("cloud", now, false, Duration::ZERO);
+        assert!(!tracker.report_at("cloud", now).degraded);
+        assert_eq!(tracker.target("cloud").availability, 0.99);
+        let backends: Vec<_> = tracker.reports().into_iter().map(|r| r.backend).collect();
+        assert_eq!(backends, vec!["cloud", "local"]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Speculative fast-model-first execution
+//!
+//! `SpeculativeService` sends each request to the inner service as `ModelType::Fast` first and
+//! only escalates to the expensive model type (Reasoning by default) when the user-provided
+//! `AnswerValidator` rejects the cheap answer or the fast attempt fails. The path taken is
+//! recorded in the response metadata under `SPECULATIVE_PATH_METADATA_KEY`.
+
+use crate::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Response metadata key recording the path taken (`fast` or `escalated`)
+pub const SPECULATIVE_PATH_METADATA_KEY: &str = "speculative_path";
+
+/// Decides whether a fast answer is good enough to return
+///
+/// Implemented for closures `Fn(&InferenceRequest, &InferenceResponse) -> bool`.
+pub trait AnswerValidator: Send + Sync {
+    fn accept(&self, request: &InferenceRequest, response: &InferenceResponse) -> bool;
+}
+
+impl<F> AnswerValidator for F
+where
+    F: Fn(&InferenceRequest, &InferenceResponse) -> bool + Send + Sync,
+{
+    fn accept(&self, request: &InferenceRequest, response: &InferenceResponse) -> bool {
+        self(request, response)
+    }
+}
+
+/// Inference service decorator trying a fast model before escalating to an expensive one
+pub struct SpeculativeService<S> {
+    inner: S,
+    validator: Box<dyn AnswerValidator>,
+    escalation_model_type: ModelType,
+    fast_accepted: AtomicU64,
+    escalated: AtomicU64,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for SpeculativeService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpeculativeService")
+            .field("inner", &self.inner)
+            .field("escalation_model_type", &self.escalation_model_type)
+            .field("fast_accepted", &self.fast_accepted)
+            .field("escalated", &self.escalated)
+            .finish()
+    }
+}
+
+impl<S: InferenceService> SpeculativeService<S> {
+    /// Escalate to `ModelType::Reasoning` whenever `validator` rejects the fast answer
+    pub fn new(inner: S, validator: impl AnswerValidator + 'static) -> Self {
+        Self {
+            inner,
+            validator: Box::new(validator),
+            escalation_model_type: ModelType::Reasoning,
+            fast_accepted: AtomicU64::new(0),
+            escalated: AtomicU64::new(0),
+        }
+    }
+
+    /// Model type used when the fast answer is rejected
+    pub fn with_escalation_model_type(mut self, model_type: ModelType) -> Self {
+        self.escalation_model_type = model_type;
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of requests answered by the fast model
+    pub fn fast_accepted(&self) -> u64 {
+        self.fast_accepted.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests escalated to the expensive model
+    pub fn escalated(&self) -> u64 {
+        self.escalated.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for SpeculativeService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let mut fast_request = request.clone();
+        fast_request.model_type = ModelType::Fast;
+        fast_request.model_override = None;
+
+        if let Ok(mut response) = self.inner.infer(fast_request).await {
+            if self.validator.accept(&request, &response) {
+                self.fast_accepted.fetch_add(1, Ordering::Relaxed);
+                response.metadata = response
+                    .metadata
+                    .with_metadata(SPECULATIVE_PATH_METADATA_KEY, "fast");
+                return Ok(response);
+            }
+        }
+
+        self.escalated.fetch_add(1, Ordering::Relaxed);
+        let mut escalated_request = request;
+        escalated_request.model_type = self.escalation_model_type;
+        escalated_request.model_override = None;
+
+        let mut response = self.inner.infer(escalated_request).await?;
+        response.metadata = response
+            .metadata
+            .with_metadata(SPECULATIVE_PATH_METADATA_KEY, "escalated");
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Is 7919 prime?", HashMap::new(), ModelType::Reasoning)
+    }
+
+    fn path(response: &InferenceResponse) -> Option<&str> {
+        response
+            .metadata
+            .metadata
+            .get(SPECULATIVE_PATH_METADATA_KEY)
+            .map(String::as_str)
+    }
+
+    #[tokio::test]
+    async fn test_accepted_fast_answer_is_returned() {
+        let service = SpeculativeService::new(
+            MockInferenceService::new().with_latency(0),
+            |_: &InferenceRequest, _: &InferenceResponse| true,
+        );
+
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(path(&response), Some("fast"));
+        assert_eq!(
+            response.metadata.model,
+            ModelType::Fast.optimal_openai_model()
+        );
+        assert_eq!(service.fast_accepted(), 1);
+        assert_eq!(service.escalated(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_fast_answer_escalates() {
+        let service = SpeculativeService::new(
+            MockInferenceService::new().with_latency(0),
+            |_: &InferenceRequest, response: &InferenceResponse| {
+                response.content.get("conclusion").is_some()
+            },
+        );
+
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(path(&response), Some("escalated"));
+        assert!(response.content.get("conclusion").is_some());
+        assert_eq!(service.fast_accepted(), 0);
+        assert_eq!(service.escalated(), 1);
+    }
+}
@@ -0,0 +1,234 @@
+//! Server-sent events parsing for streaming adapters
+//!
+//! Most provider streaming APIs use server-sent events. `SseParser` implements the event-stream
+//! framing rules (partial lines across network reads, `\n`/`\r\n`/`\r` line endings, multi-line
+//! `data`, comments, `id` and `retry` fields) so HTTP adapters only map each `SseEvent` to a
+//! `StreamChunk` and stop at the OpenAI-style `[DONE]` sentinel.
+
+use crate::*;
+use serde::de::DeserializeOwned;
+
+/// Data payload marking the end of an OpenAI-style stream
+pub const DONE_SENTINEL: &str = "[DONE]";
+
+/// Single dispatched server-sent event
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SseEvent {
+    /// Event type (`event:` field), `None` for the default `message` type
+    pub event: Option<String>,
+    /// Data lines joined with `\n`
+    pub data: String,
+    /// Last event id seen on the stream
+    pub id: Option<String>,
+    /// Reconnection delay requested by the server
+    pub retry: Option<Duration>,
+}
+
+impl SseEvent {
+    /// Whether this event is the `[DONE]` end-of-stream sentinel
+    pub fn is_done(&self) -> bool {
+        self.data.trim() == DONE_SENTINEL
+    }
+
+    /// Event type, defaulting to `message`
+    pub fn event_type(&self) -> &str {
+        self.event.as_deref().unwrap_or("message")
+    }
+
+    /// Deserialize the data payload as JSON
+    pub fn json<T: DeserializeOwned>(&self) -> InferenceResult<T> {
+        serde_json::from_str(&self.data).map_err(|e| {
+            inference_errors::generation_failed(format!("Invalid stream event payload: {e}"))
+        })
+    }
+}
+
+/// Incremental server-sent events parser
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    started: bool,
+    event: Option<String>,
+    data: Vec<String>,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+    done: bool,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed raw bytes from the network, returning every event completed by them
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(bytes);
+        if !self.started && self.buffer.len() >= 3 {
+            if self.buffer.starts_with(b"\xEF\xBB\xBF") {
+                self.buffer.drain(..3);
+            }
+            self.started = true;
+        }
+
+        let mut events = Vec::new();
+        while let Some(line) = self.next_line(false) {
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flush a trailing line once the connection has closed
+    ///
+    /// Per the specification an event without a terminating blank line is discarded, so this
+    /// only updates `last_event_id` and `retry`.
+    pub fn finish(&mut self) {
+        while let Some(line) = self.next_line(true) {
+            self.process_line(&line);
+        }
+        self.event = None;
+        self.data.clear();
+    }
+
+    /// Last event id, to send as `Last-Event-ID` when reconnecting
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Reconnection delay requested by the server
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Whether the `[DONE]` sentinel has been received
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Pop the next complete line from the buffer
+    fn next_line(&mut self, at_eof: bool) -> Option<String> {
+        let end = self.buffer.iter().position(|b| *b == b'\n' || *b == b'\r');
+        let (end, terminator_len) = match end {
+            Some(end) if self.buffer[end] == b'\r' => match self.buffer.get(end + 1) {
+                Some(b'\n') => (end, 2),
+                Some(_) => (end, 1),
+                // A lone `\r` at the end may be the first half of `\r\n`
+                None if at_eof => (end, 1),
+                None => return None,
+            },
+            Some(end) => (end, 1),
+            None if at_eof && !self.buffer.is_empty() => (self.buffer.len(), 0),
+            None => return None,
+        };
+
+        let line = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+        self.buffer.drain(..end + terminator_len);
+        Some(line)
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(ms) = value.parse::<u64>() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let event = SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+            id: self.last_event_id.clone(),
+            retry: self.retry,
+        };
+        if event.is_done() {
+            self.done = true;
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_reads() {
+        let mut parser = SseParser::new();
+
+        assert!(parser.feed(b"data: {\"delta\":").is_empty());
+        assert!(parser.feed(b" \"Hel\"}\r").is_empty());
+        let events = parser.feed(b"\n\r\ndata: {\"delta\": \"lo\"}\n\n");
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, r#"{"delta": "Hel"}"#);
+        assert_eq!(events[0].event_type(), "message");
+        let payload: serde_json::Value = events[1].json().unwrap();
+        assert_eq!(payload["delta"], "lo");
+    }
+
+    #[test]
+    fn test_fields_and_comments() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(
+            b"\xEF\xBB\xBF: keep-alive\n\
+              retry: 2500\n\
+              id: 42\n\
+              event: content_block_delta\n\
+              data: line one\n\
+              data:line two\n\
+              \n\
+              event: ping\n\
+              \n",
+        );
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.as_deref(), Some("content_block_delta"));
+        assert_eq!(events[0].data, "line one\nline two");
+        assert_eq!(events[0].id.as_deref(), Some("42"));
+        assert_eq!(parser.retry(), Some(Duration::from_millis(2500)));
+        assert_eq!(parser.last_event_id(), Some("42"));
+    }
+
+    #[test]
+    fn test_done_sentinel() {
+        let mut parser = SseParser::new();
+        let events = parser.feed(b"data: {\"x\":1}\n\ndata: [DONE]\n\n");
+
+        assert!(!events[0].is_done());
+        assert!(events[1].is_done());
+        assert!(parser.is_done());
+    }
+
+    #[test]
+    fn test_unterminated_event_is_discarded() {
+        let mut parser = SseParser::new();
+        assert!(parser.feed(b"id: 7\ndata: partial").is_empty());
+        parser.finish();
+        assert_eq!(parser.last_event_id(), Some("7"));
+        assert!(parser.feed(b"\n\n").is_empty());
+    }
+}
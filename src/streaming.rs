@@ -0,0 +1,899 @@
+//! Streaming chunk types and per-candidate assembly
+//!
+//! When several candidates are generated with streaming, chunks for different candidates arrive
+//! interleaved. Every `StreamChunk` is tagged with its `candidate_index` so consumers (e.g. a UI
+//! rendering parallel candidate panes) can route it, and `CandidateAssembler` rebuilds the full
+//! text of each candidate as chunks come in.
+//!
+//! `InferenceStream` is the pull-based stream returned by `InferenceService::infer_stream`.
+//! Chunks are only produced as fast as the consumer polls for them: adapters must read from the
+//! provider connection only when polled, or push through `InferenceStream::channel`, whose
+//! bounded buffer makes `ChunkSender::send` wait while the consumer is behind. Either way a slow
+//! consumer stops socket reads (and TCP flow control pauses the provider) instead of chunks
+//! piling up in memory.
+
+use crate::ensemble::BoxedFuture;
+use crate::*;
+use futures_core::Stream;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Incremental piece of a streamed inference response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamChunk {
+    /// Candidate this chunk belongs to (0 when a single candidate is requested)
+    pub candidate_index: usize,
+    /// Text generated since the previous chunk of the same candidate
+    pub delta: String,
+    /// Set on the last chunk of a candidate (e.g. "stop", "length")
+    pub finish_reason: Option<String>,
+    /// Token usage for the candidate, usually only present on its last chunk
+    pub token_usage: Option<TokenUsage>,
+    /// Model that generated the chunk, when reported by the provider
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Additional metadata, e.g. decorator warnings on a candidate's last chunk
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+}
+
+impl StreamChunk {
+    pub fn new(candidate_index: usize, delta: impl Into<String>) -> Self {
+        Self {
+            candidate_index,
+            delta: delta.into(),
+            finish_reason: None,
+            token_usage: None,
+            model: None,
+            metadata: HashMap::new(),
+        }
+    }
+
+    pub fn with_finish_reason(mut self, finish_reason: impl Into<String>) -> Self {
+        self.finish_reason = Some(finish_reason.into());
+        self
+    }
+
+    pub fn with_token_usage(mut self, token_usage: TokenUsage) -> Self {
+        self.token_usage = Some(token_usage);
+        self
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Whether this is the last chunk of its candidate
+    pub fn is_final(&self) -> bool {
+        self.finish_reason.is_some()
+    }
+}
+
+/// Text accumulated so far for a single candidate
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssembledCandidate {
+    /// Concatenated deltas
+    pub text: String,
+    /// Finish reason once the candidate is complete
+    pub finish_reason: Option<String>,
+    /// Token usage reported for the candidate
+    pub token_usage: Option<TokenUsage>,
+}
+
+impl AssembledCandidate {
+    pub fn is_complete(&self) -> bool {
+        self.finish_reason.is_some()
+    }
+}
+
+/// Reassembles interleaved chunks into one text per candidate
+#[derive(Debug, Clone, Default)]
+pub struct CandidateAssembler {
+    candidates: BTreeMap<usize, AssembledCandidate>,
+}
+
+impl CandidateAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a chunk to its candidate and return the updated candidate state
+    pub fn push(&mut self, chunk: StreamChunk) -> &AssembledCandidate {
+        let candidate = self.candidates.entry(chunk.candidate_index).or_default();
+        candidate.text.push_str(&chunk.delta);
+        if chunk.finish_reason.is_some() {
+            candidate.finish_reason = chunk.finish_reason;
+        }
+        if chunk.token_usage.is_some() {
+            candidate.token_usage = chunk.token_usage;
+        }
+        candidate
+    }
+
+    /// Get a candidate by index
+    pub fn candidate(&self, index: usize) -> Option<&AssembledCandidate> {
+        self.candidates.get(&index)
+    }
+
+    /// Iterate candidates ordered by index
+    pub fn candidates(&self) -> impl Iterator<Item = (usize, &AssembledCandidate)> {
+        self.candidates
+            .iter()
+            .map(|(index, candidate)| (*index, candidate))
+    }
+
+    /// Number of candidates seen so far
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Whether every candidate seen so far has finished
+    pub fn all_complete(&self) -> bool {
+        !self.candidates.is_empty() && self.candidates.values().all(|c| c.is_complete())
+    }
+
+    /// Convert assembled candidates into responses ordered by index
+    ///
+    /// Candidate text goes through the usual JSON-with-string-fallback parsing.
+    pub fn into_responses(self, model: &str, processing_time_ms: u64) -> Vec<InferenceResponse> {
+        self.candidates
+            .into_values()
+            .map(|candidate| {
+                let token_usage = candidate
+                    .token_usage
+                    .unwrap_or_else(|| TokenUsage::new(0, 0));
+                let mut response = InferenceResponse::from_text_with_json_fallback(
+                    candidate.text,
+                    model.to_string(),
+                    token_usage,
+                    processing_time_ms,
+                );
+                if let Some(finish_reason) = candidate.finish_reason {
+                    response.metadata = response
+                        .metadata
+                        .with_metadata("finish_reason", finish_reason);
+                }
+                response
+            })
+            .collect()
+    }
+
+    /// Convert assembled candidates into one multi-candidate response
+    pub fn into_response(
+        self,
+        model: &str,
+        processing_time_ms: u64,
+    ) -> InferenceResult<InferenceResponse> {
+        let candidates = self
+            .candidates
+            .into_iter()
+            .map(|(index, candidate)| {
+                let token_usage = candidate
+                    .token_usage
+                    .unwrap_or_else(|| TokenUsage::new(0, 0));
+                let content = InferenceResponse::from_text_with_json_fallback(
+                    candidate.text,
+                    model.to_string(),
+                    token_usage.clone(),
+                    processing_time_ms,
+                )
+                .content;
+                Candidate {
+                    index,
+                    content,
+                    token_usage,
+                    finish_reason: candidate.finish_reason,
+                    logprobs: None,
+                }
+            })
+            .collect();
+        InferenceResponse::from_candidates(candidates, model.to_string(), processing_time_ms)
+    }
+}
+
+/// Item produced by an inference stream
+pub type ChunkResult = InferenceResult<StreamChunk>;
+
+/// What a stream delivered so far
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamSummary {
+    pub chunks: usize,
+    /// Last model reported by a chunk
+    pub model: Option<String>,
+    /// Last token usage reported by a chunk
+    pub token_usage: Option<TokenUsage>,
+}
+
+impl StreamSummary {
+    fn observe(&mut self, chunk: &StreamChunk) {
+        self.chunks += 1;
+        if let Some(model) = &chunk.model {
+            self.model = Some(model.clone());
+        }
+        if let Some(usage) = &chunk.token_usage {
+            self.token_usage = Some(usage.clone());
+        }
+    }
+}
+
+/// How a stream ended, passed to `InferenceStream::on_end`
+#[derive(Debug)]
+pub enum StreamEnd<'a> {
+    /// Every chunk was delivered
+    Completed(&'a StreamSummary),
+    /// The stream yielded an error
+    Failed(&'a TylError),
+    /// The consumer dropped the stream before its end
+    Dropped(&'a StreamSummary),
+}
+
+/// Backpressure-aware stream of response chunks
+pub struct InferenceStream {
+    inner: Pin<Box<dyn Stream<Item = ChunkResult> + Send>>,
+}
+
+impl std::fmt::Debug for InferenceStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InferenceStream").finish_non_exhaustive()
+    }
+}
+
+impl InferenceStream {
+    /// Wrap a pull-based stream that reads from the provider only when polled
+    pub fn new(stream: impl Stream<Item = ChunkResult> + Send + 'static) -> Self {
+        Self {
+            inner: Box::pin(stream),
+        }
+    }
+
+    /// Stream a complete response as one final chunk per candidate
+    pub fn from_response(response: InferenceResponse) -> Self {
+        fn delta(content: serde_json::Value) -> String {
+            match content {
+                serde_json::Value::String(text) => text,
+                content => content.to_string(),
+            }
+        }
+
+        let model = response.metadata.model;
+        let chunks = if response.candidates.is_empty() {
+            let finish_reason = response
+                .metadata
+                .metadata
+                .get("finish_reason")
+                .cloned()
+                .unwrap_or_else(|| "stop".to_string());
+            vec![StreamChunk::new(0, delta(response.content))
+                .with_finish_reason(finish_reason)
+                .with_token_usage(response.metadata.token_usage)]
+        } else {
+            response
+                .candidates
+                .into_iter()
+                .map(|candidate| {
+                    StreamChunk::new(candidate.index, delta(candidate.content))
+                        .with_finish_reason(
+                            candidate
+                                .finish_reason
+                                .unwrap_or_else(|| "stop".to_string()),
+                        )
+                        .with_token_usage(candidate.token_usage)
+                })
+                .collect()
+        };
+        Self::new(Ready(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok(chunk.with_model(model.clone())))
+                .collect(),
+        ))
+    }
+
+    /// Bounded channel for push-style adapters
+    ///
+    /// At most `capacity` chunks are buffered; `ChunkSender::send` waits for the consumer
+    /// once the buffer is full. The stream ends when every sender has been dropped.
+    pub fn channel(capacity: usize) -> (ChunkSender, InferenceStream) {
+        let channel = Arc::new(Channel {
+            state: Mutex::new(ChannelState {
+                buffer: VecDeque::new(),
+                capacity: capacity.max(1),
+                senders: 1,
+                receiver_alive: true,
+                receiver_waker: None,
+                sender_wakers: Vec::new(),
+            }),
+        });
+        let sender = ChunkSender {
+            channel: Arc::clone(&channel),
+        };
+        (sender, Self::new(ChunkReceiver { channel }))
+    }
+
+    /// Wait for the next chunk, `None` once the stream has ended
+    pub async fn next_chunk(&mut self) -> Option<ChunkResult> {
+        std::future::poll_fn(|cx| self.inner.as_mut().poll_next(cx)).await
+    }
+
+    /// Consume the stream, reassembling chunks per candidate
+    pub async fn assemble(mut self) -> InferenceResult<CandidateAssembler> {
+        let mut assembler = CandidateAssembler::new();
+        while let Some(chunk) = self.next_chunk().await {
+            assembler.push(chunk?);
+        }
+        Ok(assembler)
+    }
+
+    /// Apply `map` to every chunk as the consumer receives it
+    pub fn map_chunks(self, map: impl FnMut(StreamChunk) -> StreamChunk + Send + 'static) -> Self {
+        Self::new(MapChunks {
+            stream: self,
+            map: Box::new(map),
+        })
+    }
+
+    /// Call `on_end` once the stream ends, fails or is dropped by its consumer
+    ///
+    /// Decorators hold capacity (a permit, a scheduler slot) for the stream's lifetime by
+    /// moving it into `on_end`, which releases it along with the closure.
+    pub fn on_end(self, on_end: impl FnOnce(StreamEnd<'_>) + Send + 'static) -> Self {
+        Self::new(OnEnd {
+            stream: self,
+            summary: StreamSummary::default(),
+            on_end: Some(Box::new(on_end)),
+        })
+    }
+
+    /// Run `finish` when the stream ends or fails, before the consumer sees the end
+    ///
+    /// `finish` gets the summary of a complete stream, or the message of the error that ended
+    /// it. An error from `finish` after a complete stream becomes the stream's last item.
+    /// Streams dropped before their end skip `finish`.
+    pub fn finish_with<F, Fut>(self, finish: F) -> Self
+    where
+        F: FnOnce(Result<StreamSummary, String>) -> Fut + Send + 'static,
+        Fut: Future<Output = InferenceResult<()>> + Send + 'static,
+    {
+        Self::new(FinishWith {
+            stream: self,
+            summary: StreamSummary::default(),
+            finish: Some(Box::new(move |outcome| Box::pin(finish(outcome)))),
+            finishing: None,
+            ended: false,
+        })
+    }
+}
+
+impl Stream for InferenceStream {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+/// Stream rewriting its chunks, see `InferenceStream::map_chunks`
+struct MapChunks {
+    stream: InferenceStream,
+    map: Box<dyn FnMut(StreamChunk) -> StreamChunk + Send>,
+}
+
+impl Stream for MapChunks {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        let this = &mut *self;
+        Pin::new(&mut this.stream)
+            .poll_next(cx)
+            .map(|item| item.map(|chunk| chunk.map(&mut this.map)))
+    }
+}
+
+type OnEndFn = Box<dyn FnOnce(StreamEnd<'_>) + Send>;
+
+/// Stream calling its callback once, see `InferenceStream::on_end`
+struct OnEnd {
+    stream: InferenceStream,
+    summary: StreamSummary,
+    on_end: Option<OnEndFn>,
+}
+
+impl Stream for OnEnd {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        let this = &mut *self;
+        let Poll::Ready(item) = Pin::new(&mut this.stream).poll_next(cx) else {
+            return Poll::Pending;
+        };
+        match &item {
+            Some(Ok(chunk)) => this.summary.observe(chunk),
+            Some(Err(error)) => {
+                if let Some(on_end) = this.on_end.take() {
+                    on_end(StreamEnd::Failed(error));
+                }
+            }
+            None => {
+                if let Some(on_end) = this.on_end.take() {
+                    on_end(StreamEnd::Completed(&this.summary));
+                }
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl Drop for OnEnd {
+    fn drop(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end(StreamEnd::Dropped(&self.summary));
+        }
+    }
+}
+
+type FinishFn = Box<
+    dyn FnOnce(Result<StreamSummary, String>) -> BoxedFuture<'static, InferenceResult<()>> + Send,
+>;
+
+/// Stream running an async step at its end, see `InferenceStream::finish_with`
+struct FinishWith {
+    stream: InferenceStream,
+    summary: StreamSummary,
+    finish: Option<FinishFn>,
+    /// Running finish step, and the error that ended the stream if any
+    finishing: Option<(BoxedFuture<'static, InferenceResult<()>>, Option<TylError>)>,
+    ended: bool,
+}
+
+impl Stream for FinishWith {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        let this = &mut *self;
+        loop {
+            if this.ended {
+                return Poll::Ready(None);
+            }
+            if let Some((finish, error)) = &mut this.finishing {
+                let Poll::Ready(finished) = finish.as_mut().poll(cx) else {
+                    return Poll::Pending;
+                };
+                let error = error.take();
+                this.finishing = None;
+                this.ended = true;
+                return Poll::Ready(error.or(finished.err()).map(Err));
+            }
+
+            let Poll::Ready(item) = Pin::new(&mut this.stream).poll_next(cx) else {
+                return Poll::Pending;
+            };
+            let Some(finish) = this.finish.take() else {
+                return Poll::Ready(item);
+            };
+            match item {
+                Some(Ok(chunk)) => {
+                    this.summary.observe(&chunk);
+                    this.finish = Some(finish);
+                    return Poll::Ready(Some(Ok(chunk)));
+                }
+                Some(Err(error)) => {
+                    this.finishing = Some((finish(Err(error.to_string())), Some(error)));
+                }
+                None => {
+                    let summary = std::mem::take(&mut this.summary);
+                    this.finishing = Some((finish(Ok(summary)), None));
+                }
+            }
+        }
+    }
+}
+
+/// Stream yielding a single item
+/// Chunks available up front
+struct Ready(VecDeque<ChunkResult>);
+
+impl Stream for Ready {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        Poll::Ready(self.0.pop_front())
+    }
+}
+
+struct ChannelState {
+    buffer: VecDeque<ChunkResult>,
+    capacity: usize,
+    senders: usize,
+    receiver_alive: bool,
+    receiver_waker: Option<Waker>,
+    sender_wakers: Vec<Waker>,
+}
+
+struct Channel {
+    state: Mutex<ChannelState>,
+}
+
+/// Producer half of `InferenceStream::channel`
+pub struct ChunkSender {
+    channel: Arc<Channel>,
+}
+
+impl std::fmt::Debug for ChunkSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkSender")
+            .field("is_closed", &self.is_closed())
+            .finish()
+    }
+}
+
+impl ChunkSender {
+    /// Send a chunk, waiting while the buffer is full
+    ///
+    /// Fails once the consumer has dropped the stream; adapters should then stop reading from
+    /// the provider and close the connection.
+    pub async fn send(&self, chunk: ChunkResult) -> InferenceResult<()> {
+        let mut chunk = Some(chunk);
+        std::future::poll_fn(|cx| {
+            let mut state = self.channel.state.lock().unwrap();
+            if !state.receiver_alive {
+                return Poll::Ready(Err(inference_errors::request_aborted(
+                    "stream consumer was dropped",
+                )));
+            }
+            if state.buffer.len() < state.capacity {
+                state.buffer.extend(chunk.take());
+                if let Some(waker) = state.receiver_waker.take() {
+                    waker.wake();
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if !state
+                .sender_wakers
+                .iter()
+                .any(|waker| waker.will_wake(cx.waker()))
+            {
+                state.sender_wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Whether the consumer has dropped the stream
+    pub fn is_closed(&self) -> bool {
+        !self.channel.state.lock().unwrap().receiver_alive
+    }
+}
+
+impl Clone for ChunkSender {
+    fn clone(&self) -> Self {
+        self.channel.state.lock().unwrap().senders += 1;
+        Self {
+            channel: Arc::clone(&self.channel),
+        }
+    }
+}
+
+impl Drop for ChunkSender {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            if let Some(waker) = state.receiver_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Consumer half of `InferenceStream::channel`
+struct ChunkReceiver {
+    channel: Arc<Channel>,
+}
+
+impl Stream for ChunkReceiver {
+    type Item = ChunkResult;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        let mut state = self.channel.state.lock().unwrap();
+        if let Some(chunk) = state.buffer.pop_front() {
+            for waker in state.sender_wakers.drain(..) {
+                waker.wake();
+            }
+            return Poll::Ready(Some(chunk));
+        }
+        if state.senders == 0 {
+            return Poll::Ready(None);
+        }
+        state.receiver_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for ChunkReceiver {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap();
+        state.receiver_alive = false;
+        state.buffer.clear();
+        for waker in state.sender_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_interleaved_chunks_are_assembled_per_candidate() {
+        let mut assembler = CandidateAssembler::new();
+
+        assembler.push(StreamChunk::new(0, "Hello"));
+        assembler.push(StreamChunk::new(1, "Bon"));
+        assembler.push(StreamChunk::new(0, " world"));
+        let second = assembler.push(StreamChunk::new(1, "jour").with_finish_reason("stop"));
+        assert!(second.is_complete());
+        assert!(!assembler.all_complete());
+
+        assembler.push(StreamChunk::new(0, "").with_finish_reason("length"));
+
+        assert_eq!(assembler.len(), 2);
+        assert!(assembler.all_complete());
+        assert_eq!(assembler.candidate(0).unwrap().text, "Hello world");
+        assert_eq!(assembler.candidate(1).unwrap().text, "Bonjour");
+
+        let indexes: Vec<usize> = assembler.candidates().map(|(index, _)| index).collect();
+        assert_eq!(indexes, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_into_responses() {
+        let mut assembler = CandidateAssembler::new();
+        assembler.push(StreamChunk::new(1, "plain text").with_finish_reason("stop"));
+        assembler.push(
+            StreamChunk::new(0, r#"{"answer": 42}"#)
+                .with_finish_reason("stop")
+                .with_token_usage(TokenUsage::new(5, 7)),
+        );
+
+        let combined = assembler.clone().into_response("gpt-4o", 120).unwrap();
+        assert_eq!(combined.content["answer"], 42);
+        assert_eq!(combined.candidate_contents().len(), 2);
+        assert_eq!(
+            combined.candidates[1].finish_reason.as_deref(),
+            Some("stop")
+        );
+
+        let responses = assembler.into_responses("gpt-4o", 120);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].content["answer"], 42);
+        assert_eq!(responses[0].metadata.token_usage.total_tokens, 12);
+        assert_eq!(
+            responses[1].content,
+            serde_json::Value::String("plain text".to_string())
+        );
+        assert_eq!(
+            responses[1].metadata.metadata.get("finish_reason"),
+            Some(&"stop".to_string())
+        );
+    }
+
+    #[test]
+    fn test_empty_assembler() {
+        let assembler = CandidateAssembler::new();
+        assert!(assembler.is_empty());
+        assert!(!assembler.all_complete());
+        assert!(assembler.clone().into_response("gpt-4o", 0).is_err());
+        assert!(assembler.into_responses("gpt-4o", 0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_channel_applies_backpressure() {
+        let (sender, mut stream) = InferenceStream::channel(2);
+        let sent = Arc::new(AtomicUsize::new(0));
+
+        let producer = {
+            let sent = Arc::clone(&sent);
+            tokio::spawn(async move {
+                for i in 0..5 {
+                    let chunk = StreamChunk::new(0, i.to_string());
+                    let chunk = if i == 4 {
+                        chunk.with_finish_reason("stop")
+                    } else {
+                        chunk
+                    };
+                    sender.send(Ok(chunk)).await.unwrap();
+                    sent.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+
+        // The producer stalls once the buffer is full
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+
+        let first = stream.next_chunk().await.unwrap().unwrap();
+        assert_eq!(first.delta, "0");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+
+        let assembler = stream.assemble().await.unwrap();
+        producer.await.unwrap();
+        assert_eq!(assembler.candidate(0).unwrap().text, "1234");
+        assert!(assembler.all_complete());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_stream_stops_the_producer() {
+        let (sender, stream) = InferenceStream::channel(1);
+        sender.send(Ok(StreamChunk::new(0, "a"))).await.unwrap();
+
+        let producer = tokio::spawn(async move {
+            let blocked = sender.send(Ok(StreamChunk::new(0, "b"))).await;
+            (blocked, sender.is_closed())
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(stream);
+
+        let (result, closed) = producer.await.unwrap();
+        assert!(result.is_err());
+        assert!(closed);
+    }
+
+    #[tokio::test]
+    async fn test_stream_from_response() {
+        let response = InferenceResponse::from_text_with_json_fallback(
+            r#"{"answer": 42}"#.to_string(),
+            "gpt-4o".to_string(),
+            TokenUsage::new(3, 4),
+            10,
+        );
+        let mut stream = InferenceStream::from_response(response);
+
+        let chunk = stream.next_chunk().await.unwrap().unwrap();
+        assert_eq!(chunk.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(chunk.token_usage, Some(TokenUsage::new(3, 4)));
+        assert_eq!(chunk.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&chunk.delta).unwrap()["answer"],
+            42
+        );
+        assert!(stream.next_chunk().await.is_none());
+
+        let response = InferenceResponse::from_candidates(
+            vec![
+                Candidate::new(0, serde_json::json!("first"), TokenUsage::new(3, 1))
+                    .with_finish_reason("stop"),
+                Candidate::new(1, serde_json::json!({"n": 2}), TokenUsage::new(3, 2))
+                    .with_finish_reason("length"),
+            ],
+            "gpt-4o".to_string(),
+            10,
+        )
+        .unwrap();
+        let assembler = InferenceStream::from_response(response.clone())
+            .assemble()
+            .await
+            .unwrap();
+        let streamed = assembler.into_response("gpt-4o", 10).unwrap();
+        assert_eq!(streamed.candidates, response.candidates);
+        assert_eq!(
+            streamed.candidates[1].finish_reason.as_deref(),
+            Some("length")
+        );
+        assert_eq!(streamed.metadata.token_usage, TokenUsage::new(3, 3));
+    }
+
+    fn chunks(deltas: &[&str]) -> InferenceStream {
+        let (sender, stream) = InferenceStream::channel(deltas.len().max(1));
+        let deltas: Vec<String> = deltas.iter().map(|delta| delta.to_string()).collect();
+        tokio::spawn(async move {
+            let last = deltas.len().saturating_sub(1);
+            for (index, delta) in deltas.into_iter().enumerate() {
+                let mut chunk = StreamChunk::new(0, delta).with_model("gpt-4o");
+                if index == last {
+                    chunk = chunk
+                        .with_finish_reason("stop")
+                        .with_token_usage(TokenUsage::new(2, 3));
+                }
+                if sender.send(Ok(chunk)).await.is_err() {
+                    return;
+                }
+            }
+        });
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_on_end() {
+        let ends = Arc::new(Mutex::new(Vec::new()));
+        let record = |ends: &Arc<Mutex<Vec<String>>>| {
+            let ends = Arc::clone(ends);
+            move |end: StreamEnd<'_>| {
+                let end = match end {
+                    StreamEnd::Completed(summary) => format!("completed {}", summary.chunks),
+                    StreamEnd::Failed(error) => format!("failed {error}"),
+                    StreamEnd::Dropped(summary) => format!("dropped {}", summary.chunks),
+                };
+                ends.lock().unwrap().push(end);
+            }
+        };
+
+        let stream = chunks(&["a", "b"]).on_end(record(&ends));
+        assert_eq!(stream.assemble().await.unwrap().len(), 1);
+
+        let mut stream = chunks(&["a", "b"]).on_end(record(&ends));
+        stream.next_chunk().await.unwrap().unwrap();
+        drop(stream);
+
+        let (sender, stream) = InferenceStream::channel(1);
+        sender
+            .send(Err(TylError::network("connection reset")))
+            .await
+            .unwrap();
+        drop(sender);
+        assert!(stream.on_end(record(&ends)).assemble().await.is_err());
+
+        let ends = ends.lock().unwrap();
+        assert_eq!(ends[0], "completed 2");
+        assert_eq!(ends[1], "dropped 1");
+        assert!(ends[2].contains("connection reset"));
+    }
+
+    #[tokio::test]
+    async fn test_finish_with() {
+        let finished = Arc::new(Mutex::new(None));
+        let seen = Arc::clone(&finished);
+        let stream = chunks(&["a", "b"]).finish_with(move |outcome| async move {
+            *seen.lock().unwrap() = Some(outcome);
+            Ok(())
+        });
+        assert_eq!(stream.assemble().await.unwrap().len(), 1);
+        let summary = finished.lock().unwrap().take().unwrap().unwrap();
+        assert_eq!(summary.chunks, 2);
+        assert_eq!(summary.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(summary.token_usage, Some(TokenUsage::new(2, 3)));
+
+        // A failing finish step ends the stream with its error
+        let mut stream =
+            chunks(&["a"]).finish_with(|_| async { Err(TylError::internal("ledger unavailable")) });
+        assert!(stream.next_chunk().await.unwrap().is_ok());
+        let error = stream.next_chunk().await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("ledger unavailable"));
+        assert!(stream.next_chunk().await.is_none());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_default_infer_stream() {
+        use crate::MockInferenceService;
+
+        let service = MockInferenceService::new().with_latency(0);
+        let request = InferenceRequest::new("Hello", HashMap::new(), ModelType::General);
+        let stream = service.infer_stream(request).await.unwrap();
+
+        let responses = stream
+            .assemble()
+            .await
+            .unwrap()
+            .into_responses("gpt-4o-mini", 0);
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].content.get("message").is_some());
+    }
+}
@@ -0,0 +1,318 @@
+//! GPU and host resource telemetry for local adapters
+//!
+//! Local backends share a fixed pool of VRAM and serve requests from a queue, so "healthy" is not
+//! enough to tell whether they can take more traffic. A `ResourceProbe` reports VRAM usage,
+//! loaded models and queue depth; `TelemetryService` adds them to `HealthCheckResult.metadata`
+//! under the `*_METADATA_KEY` constants and, past configured thresholds, reports the backend as
+//! saturated (unhealthy), so `RoutingInferenceService::refresh_health` spills traffic to cloud
+//! backends until the local tier drains:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::telemetry::*;
+//! # use tyl_llm_inference_port::routing::*;
+//! # use std::sync::Arc;
+//! # #[cfg(feature = "decorators")]
+//! # async fn example(ollama: impl InferenceService + 'static, openai: impl InferenceService + 'static, pool: Arc<impl ResourceProbe + 'static>) -> TylResult<()> {
+//! # use tyl_llm_inference_port::concurrency::*;
+//! let local = TelemetryService::new(ConcurrencyLimitedService::new(ollama, 4), Arc::clone(&pool))
+//!     .with_max_vram_utilization(0.95)
+//!     .with_max_queue_depth(8);
+//! let router = RoutingInferenceService::new()
+//!     .with_backend(RouteBackend::new("local", local))
+//!     .with_backend(RouteBackend::new("cloud", openai));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::*;
+use std::sync::Arc;
+
+/// Health metadata key holding the VRAM in use, in bytes
+pub const VRAM_USED_METADATA_KEY: &str = "vram_used_bytes";
+/// Health metadata key holding the VRAM available to the backend, in bytes
+pub const VRAM_TOTAL_METADATA_KEY: &str = "vram_total_bytes";
+/// Health metadata key holding the models currently loaded
+pub const LOADED_MODELS_METADATA_KEY: &str = "loaded_models";
+/// Health metadata key holding the number of requests waiting to run
+pub const QUEUE_DEPTH_METADATA_KEY: &str = "queue_depth";
+/// Health metadata key set to `true` when a saturation threshold is exceeded
+pub const SATURATED_METADATA_KEY: &str = "saturated";
+
+/// Resource usage of a local backend
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceTelemetry {
+    pub vram_used_bytes: Option<u64>,
+    pub vram_total_bytes: Option<u64>,
+    pub loaded_models: Vec<String>,
+    pub queue_depth: Option<usize>,
+}
+
+impl ResourceTelemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_vram(mut self, used_bytes: u64, total_bytes: u64) -> Self {
+        self.vram_used_bytes = Some(used_bytes);
+        self.vram_total_bytes = Some(total_bytes);
+        self
+    }
+
+    pub fn with_loaded_models(
+        mut self,
+        models: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.loaded_models = models.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_queue_depth(mut self, queue_depth: usize) -> Self {
+        self.queue_depth = Some(queue_depth);
+        self
+    }
+
+    /// Fraction of VRAM in use, when both figures are known
+    pub fn vram_utilization(&self) -> Option<f64> {
+        match (self.vram_used_bytes, self.vram_total_bytes) {
+            (Some(used), Some(total)) if total > 0 => Some(used as f64 / total as f64),
+            _ => None,
+        }
+    }
+
+    /// Add the known figures to `health`'s metadata
+    pub fn apply(&self, mut health: HealthCheckResult) -> HealthCheckResult {
+        if let Some(used) = self.vram_used_bytes {
+            health = health.with_metadata(VRAM_USED_METADATA_KEY, used.into());
+        }
+        if let Some(total) = self.vram_total_bytes {
+            health = health.with_metadata(VRAM_TOTAL_METADATA_KEY, total.into());
+        }
+        if let Some(depth) = self.queue_depth {
+            health = health.with_metadata(QUEUE_DEPTH_METADATA_KEY, depth.into());
+        }
+        health.with_metadata(
+            LOADED_MODELS_METADATA_KEY,
+            serde_json::json!(self.loaded_models),
+        )
+    }
+
+    /// Read the figures back from health metadata (e.g. on the router side)
+    pub fn from_health(health: &HealthCheckResult) -> Self {
+        let number = |key: &str| health.metadata.get(key).and_then(|value| value.as_u64());
+        Self {
+            vram_used_bytes: number(VRAM_USED_METADATA_KEY),
+            vram_total_bytes: number(VRAM_TOTAL_METADATA_KEY),
+            loaded_models: health
+                .metadata
+                .get(LOADED_MODELS_METADATA_KEY)
+                .and_then(|value| serde_json::from_value(value.clone()).ok())
+                .unwrap_or_default(),
+            queue_depth: number(QUEUE_DEPTH_METADATA_KEY).map(|depth| depth as usize),
+        }
+    }
+}
+
+/// Source of resource telemetry for a local backend (runtime API, NVML, warm pool, ...)
+#[async_trait]
+pub trait ResourceProbe: Send + Sync {
+    async fn telemetry(&self) -> InferenceResult<ResourceTelemetry>;
+}
+
+#[async_trait]
+impl<T: ResourceProbe + ?Sized> ResourceProbe for Arc<T> {
+    async fn telemetry(&self) -> InferenceResult<ResourceTelemetry> {
+        (**self).telemetry().await
+    }
+}
+
+/// Inference service decorator reporting resource telemetry in health checks
+pub struct TelemetryService<S> {
+    inner: S,
+    probe: Box<dyn ResourceProbe>,
+    max_vram_utilization: Option<f64>,
+    max_queue_depth: Option<usize>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for TelemetryService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TelemetryService")
+            .field("inner", &self.inner)
+            .field("max_vram_utilization", &self.max_vram_utilization)
+            .field("max_queue_depth", &self.max_queue_depth)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: InferenceService> TelemetryService<S> {
+    /// Report `probe`'s telemetry without saturation thresholds
+    pub fn new(inner: S, probe: impl ResourceProbe + 'static) -> Self {
+        Self {
+            inner,
+            probe: Box::new(probe),
+            max_vram_utilization: None,
+            max_queue_depth: None,
+        }
+    }
+
+    /// Report saturation above this fraction of VRAM in use
+    pub fn with_max_vram_utilization(mut self, max_utilization: f64) -> Self {
+        self.max_vram_utilization = Some(max_utilization);
+        self
+    }
+
+    /// Report saturation above this many waiting requests
+    pub fn with_max_queue_depth(mut self, max_queue_depth: usize) -> Self {
+        self.max_queue_depth = Some(max_queue_depth);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Reason the backend should not take more traffic, if any
+    fn saturation(&self, telemetry: &ResourceTelemetry) -> Option<String> {
+        if let (Some(max), Some(utilization)) =
+            (self.max_vram_utilization, telemetry.vram_utilization())
+        {
+            if utilization > max {
+                return Some(format!(
+                    "saturated: VRAM utilization {:.0}% exceeds {:.0}%",
+                    utilization * 100.0,
+                    max * 100.0
+                ));
+            }
+        }
+        if let (Some(max), Some(depth)) = (self.max_queue_depth, telemetry.queue_depth) {
+            if depth > max {
+                return Some(format!("saturated: {depth} queued requests exceed {max}"));
+            }
+        }
+        None
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for TelemetryService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        self.inner.infer(request).await
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        self.inner.infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        self.inner.infer_batch(requests).await
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let health = self.inner.health_check().await?;
+        let mut telemetry = self.probe.telemetry().await?;
+        if telemetry.queue_depth.is_none() {
+            // Queue depth reported by a `ConcurrencyLimitedService` underneath
+            telemetry.queue_depth = health
+                .metadata
+                .get("queued")
+                .and_then(|value| value.as_u64())
+                .map(|depth| depth as usize);
+        }
+
+        let saturation = self.saturation(&telemetry);
+        let mut health = telemetry
+            .apply(health)
+            .with_metadata(SATURATED_METADATA_KEY, saturation.is_some().into());
+        if let (Some(reason), true) = (saturation, health.status.is_healthy()) {
+            health.status = HealthStatus::unhealthy(reason);
+        }
+        Ok(health)
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+    use std::sync::Mutex;
+
+    /// Probe returning whatever the test last set
+    #[derive(Default)]
+    struct FixedProbe(Mutex<ResourceTelemetry>);
+
+    #[async_trait]
+    impl ResourceProbe for FixedProbe {
+        async fn telemetry(&self) -> InferenceResult<ResourceTelemetry> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_telemetry_and_saturation() {
+        let probe = Arc::new(FixedProbe::default());
+        let service = TelemetryService::new(
+            MockInferenceService::new().with_latency(0),
+            Arc::clone(&probe),
+        )
+        .with_max_vram_utilization(0.9)
+        .with_max_queue_depth(4);
+
+        *probe.0.lock().unwrap() = ResourceTelemetry::new()
+            .with_vram(12, 24)
+            .with_loaded_models(["llama3:8b"])
+            .with_queue_depth(2);
+        let health = service.health_check().await.unwrap();
+        assert!(health.status.is_healthy());
+        assert_eq!(
+            ResourceTelemetry::from_health(&health),
+            *probe.0.lock().unwrap()
+        );
+        assert_eq!(
+            health.metadata.get(SATURATED_METADATA_KEY),
+            Some(&serde_json::json!(false))
+        );
+
+        *probe.0.lock().unwrap() = ResourceTelemetry::new().with_vram(23, 24);
+        let health = service.health_check().await.unwrap();
+        assert!(!health.status.is_healthy());
+        assert_eq!(
+            health.metadata.get(SATURATED_METADATA_KEY),
+            Some(&serde_json::json!(true))
+        );
+
+        *probe.0.lock().unwrap() = ResourceTelemetry::new().with_queue_depth(5);
+        assert!(!service.health_check().await.unwrap().status.is_healthy());
+    }
+}
@@ -0,0 +1,549 @@
+//! Prompt templates with YAML frontmatter
+//!
+//! A template file can carry its own configuration ahead of the prompt body:
+//!
+//! ```text
+//! ---
+//! name: summarize
+//! model_type: Fast
+//! max_tokens: 256
+//! post_process: [json-repair]
+//! ---
+//! Summarize {{text}} as JSON with a "summary" field.
+//! ```
+//!
+//! Templates without a leading `---` block are treated as a plain body.
+//!
+//! Frontmatter may also declare `tests` that check the rendered prompt and the shape of a sample
+//! response; `TemplateRegistry::run_tests` executes them so broken prompts are caught at load
+//! time rather than in production:
+//!
+//! ```text
+//! tests:
+//!   - name: renders the text
+//!     params: { text: "the report" }
+//!     rendered_contains: ["the report"]
+//!     mock_response: '{"summary": "short"}'
+//!     response_fields: [summary]
+//! ```
+
+use crate::postprocess::{PostProcessorRegistry, POST_PROCESS_METADATA_KEY};
+use crate::*;
+use regex::Regex;
+use std::sync::OnceLock;
+
+const FRONTMATTER_DELIMITER: &str = "---";
+
+/// Configuration declared in a template's frontmatter
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateFrontmatter {
+    /// Template name
+    pub name: Option<String>,
+    /// Human readable description
+    pub description: Option<String>,
+    /// Model type to use for requests built from this template
+    pub model_type: Option<ModelType>,
+    /// Maximum tokens to generate
+    pub max_tokens: Option<usize>,
+    /// Temperature for randomness (0.0 to 1.0)
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability mass (0.0 to 1.0)
+    pub top_p: Option<f32>,
+    /// Frequency penalty (-2.0 to 2.0)
+    pub frequency_penalty: Option<f32>,
+    /// Presence penalty (-2.0 to 2.0)
+    pub presence_penalty: Option<f32>,
+    /// Sequences that end generation
+    pub stop: Vec<String>,
+    /// Named post-processors applied to responses, in order
+    pub post_process: Vec<String>,
+    /// Inline test cases for this template
+    pub tests: Vec<TemplateTestCase>,
+}
+
+/// Test case declared in a template's frontmatter
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateTestCase {
+    /// Test case name (defaults to its position)
+    pub name: Option<String>,
+    /// Parameters used to render the template
+    pub params: HashMap<String, String>,
+    /// Substrings the rendered prompt must contain
+    pub rendered_contains: Vec<String>,
+    /// Substrings the rendered prompt must not contain
+    pub rendered_not_contains: Vec<String>,
+    /// Sample model output checked against the expected response shape
+    pub mock_response: Option<String>,
+    /// Top-level JSON fields the (post-processed) sample response must have
+    pub response_fields: Vec<String>,
+}
+
+/// Outcome of a single template test case
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateTestResult {
+    /// Template name
+    pub template: String,
+    /// Test case name
+    pub case: String,
+    /// Failed assertions, empty when the case passed
+    pub failures: Vec<String>,
+}
+
+impl TemplateTestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Results of running template test cases
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TemplateTestReport {
+    pub results: Vec<TemplateTestResult>,
+}
+
+impl TemplateTestReport {
+    /// Whether every test case passed
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(TemplateTestResult::passed)
+    }
+
+    /// Failed test cases
+    pub fn failures(&self) -> Vec<&TemplateTestResult> {
+        self.results
+            .iter()
+            .filter(|result| !result.passed())
+            .collect()
+    }
+
+    /// Human readable summary of the failures
+    pub fn failure_summary(&self) -> String {
+        self.failures()
+            .iter()
+            .map(|result| {
+                format!(
+                    "{}::{}: {}",
+                    result.template,
+                    result.case,
+                    result.failures.join("; ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Prompt template body together with its frontmatter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub frontmatter: TemplateFrontmatter,
+    /// Template body with `{{parameter}}` placeholders
+    pub body: String,
+}
+
+impl PromptTemplate {
+    /// Create a template without frontmatter
+    pub fn new(body: impl Into<String>) -> Self {
+        Self {
+            frontmatter: TemplateFrontmatter::default(),
+            body: body.into(),
+        }
+    }
+
+    /// Parse a template source, splitting optional YAML frontmatter from the body
+    pub fn parse(source: &str) -> InferenceResult<Self> {
+        let source = source.trim_start_matches('\u{feff}');
+        let mut lines = source.split_inclusive('\n');
+
+        let first_line = lines.next().unwrap_or_default();
+        if first_line.trim_end() != FRONTMATTER_DELIMITER {
+            return Ok(Self::new(source));
+        }
+
+        let mut yaml = String::new();
+        let mut consumed = first_line.len();
+        let mut closed = false;
+        for line in lines {
+            consumed += line.len();
+            if line.trim_end() == FRONTMATTER_DELIMITER {
+                closed = true;
+                break;
+            }
+            yaml.push_str(line);
+        }
+
+        if !closed {
+            return Err(inference_errors::template_processing_failed(
+                "frontmatter is missing its closing '---' line",
+            ));
+        }
+
+        let frontmatter = if yaml.trim().is_empty() {
+            TemplateFrontmatter::default()
+        } else {
+            serde_yaml::from_str(&yaml).map_err(|e| {
+                inference_errors::template_processing_failed(format!("invalid frontmatter: {e}"))
+            })?
+        };
+
+        Ok(Self {
+            frontmatter,
+            body: source[consumed..].to_string(),
+        })
+    }
+
+    /// Template name from frontmatter
+    pub fn name(&self) -> Option<&str> {
+        self.frontmatter.name.as_deref()
+    }
+
+    /// Build an inference request from this template and parameters
+    ///
+    /// Frontmatter settings become request settings; selected post-processors are carried in
+    /// the request metadata for `PostProcessingService`.
+    pub fn to_request(&self, parameters: HashMap<String, String>) -> InferenceRequest {
+        let model_type = self.frontmatter.model_type.unwrap_or_default();
+        let mut request = InferenceRequest::new(self.body.clone(), parameters, model_type);
+
+        if let Some(max_tokens) = self.frontmatter.max_tokens {
+            request = request.with_max_tokens(max_tokens);
+        }
+        if let Some(temperature) = self.frontmatter.temperature {
+            request = request.with_temperature(temperature);
+        }
+        if let Some(top_p) = self.frontmatter.top_p {
+            request = request.with_top_p(top_p);
+        }
+        if let Some(penalty) = self.frontmatter.frequency_penalty {
+            request = request.with_frequency_penalty(penalty);
+        }
+        if let Some(penalty) = self.frontmatter.presence_penalty {
+            request = request.with_presence_penalty(penalty);
+        }
+        if !self.frontmatter.stop.is_empty() {
+            request = request.with_stop_sequences(self.frontmatter.stop.clone());
+        }
+        if let Some(name) = &self.frontmatter.name {
+            request = request.with_metadata("template_name", name.clone());
+        }
+        if !self.frontmatter.post_process.is_empty() {
+            request = request.with_metadata(
+                POST_PROCESS_METADATA_KEY,
+                self.frontmatter.post_process.join(","),
+            );
+        }
+
+        request
+    }
+
+    /// Run the test cases declared in the frontmatter
+    ///
+    /// Besides the declared assertions, every case fails when placeholders remain unresolved
+    /// after rendering. Sample responses go through the template's post-processors first.
+    pub fn run_tests(&self, post_processors: &PostProcessorRegistry) -> TemplateTestReport {
+        static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+        let placeholder =
+            PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*[^{}\s]+\s*\}\}").expect("valid regex"));
+
+        let template_name = self.name().unwrap_or("<unnamed>").to_string();
+        let results = self
+            .frontmatter
+            .tests
+            .iter()
+            .enumerate()
+            .map(|(index, case)| {
+                let request = self.to_request(case.params.clone());
+                let rendered = request.render_template();
+                let mut failures = Vec::new();
+
+                for unresolved in placeholder.find_iter(&rendered) {
+                    failures.push(format!("unresolved placeholder {}", unresolved.as_str()));
+                }
+                for expected in &case.rendered_contains {
+                    if !rendered.contains(expected.as_str()) {
+                        failures.push(format!("rendered prompt does not contain {expected:?}"));
+                    }
+                }
+                for unexpected in &case.rendered_not_contains {
+                    if rendered.contains(unexpected.as_str()) {
+                        failures.push(format!("rendered prompt contains {unexpected:?}"));
+                    }
+                }
+                if let Some(mock_response) = &case.mock_response {
+                    failures.extend(self.check_response_shape(
+                        mock_response,
+                        &case.response_fields,
+                        post_processors,
+                    ));
+                }
+
+                TemplateTestResult {
+                    template: template_name.clone(),
+                    case: case.name.clone().unwrap_or_else(|| format!("case {index}")),
+                    failures,
+                }
+            })
+            .collect();
+
+        TemplateTestReport { results }
+    }
+
+    fn check_response_shape(
+        &self,
+        mock_response: &str,
+        fields: &[String],
+        post_processors: &PostProcessorRegistry,
+    ) -> Vec<String> {
+        let response = InferenceResponse::from_text_with_json_fallback(
+            mock_response.to_string(),
+            "template-test".to_string(),
+            TokenUsage::new(0, 0),
+            0,
+        );
+        let response = match post_processors.apply(&self.frontmatter.post_process, response) {
+            Ok(response) => response,
+            Err(error) => return vec![format!("post-processing failed: {error}")],
+        };
+
+        match &response.content {
+            serde_json::Value::Object(object) => fields
+                .iter()
+                .filter(|field| !object.contains_key(field.as_str()))
+                .map(|field| format!("response is missing field {field:?}"))
+                .collect(),
+            _ if fields.is_empty() => Vec::new(),
+            other => vec![format!("response is not a JSON object: {other}")],
+        }
+    }
+}
+
+/// Named collection of prompt templates
+#[derive(Debug, Clone)]
+pub struct TemplateRegistry {
+    templates: HashMap<String, PromptTemplate>,
+    post_processors: PostProcessorRegistry,
+}
+
+impl Default for TemplateRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        Self {
+            templates: HashMap::new(),
+            post_processors: PostProcessorRegistry::with_defaults(),
+        }
+    }
+
+    /// Use a custom post-processor registry when checking sample responses
+    pub fn with_post_processors(mut self, post_processors: PostProcessorRegistry) -> Self {
+        self.post_processors = post_processors;
+        self
+    }
+
+    /// Register a template under its frontmatter name
+    pub fn register(&mut self, template: PromptTemplate) -> InferenceResult<()> {
+        let name = template.name().map(str::to_string).ok_or_else(|| {
+            inference_errors::template_processing_failed("template frontmatter has no name")
+        })?;
+        self.templates.insert(name, template);
+        Ok(())
+    }
+
+    /// Parse and register a template source, returning its name
+    pub fn load(&mut self, source: &str) -> InferenceResult<String> {
+        let template = PromptTemplate::parse(source)?;
+        let name = template.name().unwrap_or_default().to_string();
+        self.register(template)?;
+        Ok(name)
+    }
+
+    /// Parse a template source and register it only if its test cases pass
+    pub fn load_verified(&mut self, source: &str) -> InferenceResult<String> {
+        let template = PromptTemplate::parse(source)?;
+        let report = template.run_tests(&self.post_processors);
+        if !report.passed() {
+            return Err(inference_errors::template_processing_failed(
+                report.failure_summary(),
+            ));
+        }
+
+        let name = template.name().unwrap_or_default().to_string();
+        self.register(template)?;
+        Ok(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PromptTemplate> {
+        self.templates.get(name)
+    }
+
+    /// Registered template names, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.templates.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Build a request from a registered template
+    pub fn request(
+        &self,
+        name: &str,
+        parameters: HashMap<String, String>,
+    ) -> InferenceResult<InferenceRequest> {
+        self.get(name)
+            .map(|template| template.to_request(parameters))
+            .ok_or_else(|| {
+                inference_errors::template_processing_failed(format!("unknown template {name}"))
+            })
+    }
+
+    /// Run the test cases of every registered template
+    pub fn run_tests(&self) -> TemplateTestReport {
+        let results = self
+            .names()
+            .iter()
+            .filter_map(|name| self.templates.get(name))
+            .flat_map(|template| template.run_tests(&self.post_processors).results)
+            .collect();
+
+        TemplateTestReport { results }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "---\nname: summarize\nmodel_type: Fast\nmax_tokens: 256\npost_process: [json-repair, markdown-strip]\n---\nSummarize {{text}}";
+
+    #[test]
+    fn test_parse_frontmatter() {
+        let template = PromptTemplate::parse(SOURCE).unwrap();
+
+        assert_eq!(template.name(), Some("summarize"));
+        assert_eq!(template.frontmatter.model_type, Some(ModelType::Fast));
+        assert_eq!(template.frontmatter.max_tokens, Some(256));
+        assert_eq!(
+            template.frontmatter.post_process,
+            vec!["json-repair".to_string(), "markdown-strip".to_string()]
+        );
+        assert_eq!(template.body, "Summarize {{text}}");
+    }
+
+    #[test]
+    fn test_parse_without_frontmatter() {
+        let template = PromptTemplate::parse("Hello {{name}}!").unwrap();
+        assert_eq!(template.frontmatter, TemplateFrontmatter::default());
+        assert_eq!(template.body, "Hello {{name}}!");
+    }
+
+    #[test]
+    fn test_parse_invalid_frontmatter() {
+        let error = PromptTemplate::parse("---\nname: test\nHello").unwrap_err();
+        assert!(error.to_string().contains("closing '---'"));
+
+        let error = PromptTemplate::parse("---\nmodel_type: Unknown\n---\nHello").unwrap_err();
+        assert!(error.to_string().contains("invalid frontmatter"));
+    }
+
+    #[test]
+    fn test_to_request() {
+        let template = PromptTemplate::parse(SOURCE).unwrap();
+
+        let mut params = HashMap::new();
+        params.insert("text".to_string(), "the report".to_string());
+        let request = template.to_request(params);
+
+        assert_eq!(request.model_type, ModelType::Fast);
+        assert_eq!(request.max_tokens, Some(256));
+        assert_eq!(request.render_template(), "Summarize the report");
+        assert_eq!(
+            request.metadata.get(POST_PROCESS_METADATA_KEY),
+            Some(&"json-repair,markdown-strip".to_string())
+        );
+        assert_eq!(
+            request.metadata.get("template_name"),
+            Some(&"summarize".to_string())
+        );
+    }
+
+    const TESTED_SOURCE: &str = r#"---
+name: summarize
+post_process: [json-repair]
+tests:
+  - name: renders the text
+    params: { text: "the report" }
+    rendered_contains: ["the report"]
+    mock_response: "```json\n{\"summary\": \"short\"}\n```"
+    response_fields: [summary]
+  - name: missing parameter
+    params: {}
+---
+Summarize {{text}}"#;
+
+    #[test]
+    fn test_run_template_tests() {
+        let template = PromptTemplate::parse(TESTED_SOURCE).unwrap();
+        assert_eq!(template.frontmatter.tests.len(), 2);
+
+        let report = template.run_tests(&PostProcessorRegistry::with_defaults());
+        assert!(!report.passed());
+        assert!(report.results[0].passed());
+
+        let failures = report.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].case, "missing parameter");
+        assert!(failures[0].failures[0].contains("unresolved placeholder {{text}}"));
+    }
+
+    #[test]
+    fn test_response_shape_assertions() {
+        let template = PromptTemplate::parse(
+            "---\nname: t\ntests:\n  - mock_response: '{\"other\": 1}'\n    response_fields: [summary]\n  - mock_response: plain text\n    response_fields: [summary]\n---\nHi",
+        )
+        .unwrap();
+
+        let report = template.run_tests(&PostProcessorRegistry::with_defaults());
+        assert!(report.results[0].failures[0].contains("missing field \"summary\""));
+        assert!(report.results[1].failures[0].contains("not a JSON object"));
+    }
+
+    #[test]
+    fn test_registry_load_and_run_tests() {
+        let mut registry = TemplateRegistry::new();
+        let name = registry
+            .load("---\nname: greet\ntests:\n  - params: { name: Ana }\n    rendered_contains: [Ana]\n---\nHello {{name}}")
+            .unwrap();
+        assert_eq!(name, "greet");
+        assert_eq!(registry.names(), vec!["greet".to_string()]);
+        assert!(registry.run_tests().passed());
+
+        let mut params = HashMap::new();
+        params.insert("name".to_string(), "Ana".to_string());
+        let request = registry.request("greet", params).unwrap();
+        assert_eq!(request.render_template(), "Hello Ana");
+
+        assert!(registry.request("unknown", HashMap::new()).is_err());
+        assert!(registry.load("No frontmatter, no name").is_err());
+    }
+
+    #[test]
+    fn test_registry_load_verified_rejects_failing_templates() {
+        let mut registry = TemplateRegistry::new();
+        let error = registry.load_verified(TESTED_SOURCE).unwrap_err();
+
+        assert!(error.to_string().contains("summarize::missing parameter"));
+        assert!(registry.get("summarize").is_none());
+    }
+
+    #[test]
+    fn test_default_registry_has_default_post_processors() {
+        let mut registry = TemplateRegistry::default();
+        let source = "---\nname: strip\npost_process: [markdown-strip]\ntests:\n  - mock_response: plain text\n---\nHi";
+        assert_eq!(registry.load_verified(source).unwrap(), "strip");
+    }
+}
@@ -0,0 +1,78 @@
+//! Stub services and transports shared by unit tests
+
+use crate::http_client::{HttpRequest, HttpResponse, HttpTransport};
+use crate::*;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Test service that fails with scripted errors, then echoes the request template
+#[derive(Debug, Default)]
+pub(crate) struct ScriptedInferenceService {
+    errors: Mutex<VecDeque<TylError>>,
+    /// Every request received, failed or not
+    pub requests: Mutex<Vec<InferenceRequest>>,
+}
+
+impl ScriptedInferenceService {
+    /// Fail the first calls with `errors`, in order
+    pub fn new(errors: impl IntoIterator<Item = TylError>) -> Self {
+        Self {
+            errors: Mutex::new(errors.into_iter().collect()),
+            requests: Mutex::default(),
+        }
+    }
+
+    pub fn calls(&self) -> u32 {
+        self.requests.lock().unwrap().len() as u32
+    }
+}
+
+#[async_trait]
+impl InferenceService for ScriptedInferenceService {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        self.requests.lock().unwrap().push(request.clone());
+        if let Some(error) = self.errors.lock().unwrap().pop_front() {
+            return Err(error);
+        }
+        Ok(InferenceResponse::new(
+            serde_json::json!(request.template),
+            ResponseMetadata::new("scripted-1".to_string(), TokenUsage::new(3, 1), 1),
+        ))
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        Ok(HealthCheckResult::new(HealthStatus::healthy()))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        vec!["scripted-1".to_string()]
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        Ok(text.len())
+    }
+}
+
+/// HTTP transport answering from a script and recording the requests it saw
+#[derive(Debug, Default)]
+pub(crate) struct ScriptedTransport {
+    pub responses: Mutex<Vec<HttpResponse>>,
+    pub requests: Mutex<Vec<HttpRequest>>,
+}
+
+#[async_trait]
+impl HttpTransport for Arc<ScriptedTransport> {
+    async fn send(&self, request: HttpRequest) -> InferenceResult<HttpResponse> {
+        self.requests.lock().unwrap().push(request);
+        Ok(self.responses.lock().unwrap().remove(0))
+    }
+}
+
+/// Transport answering with `responses`, in order
+pub(crate) fn scripted(responses: Vec<HttpResponse>) -> Arc<ScriptedTransport> {
+    Arc::new(ScriptedTransport {
+        responses: Mutex::new(responses),
+        requests: Mutex::default(),
+    })
+}
@@ -0,0 +1,317 @@
+//! Timeout decorator for inference services
+//!
+//! `TimeoutService` bounds every request by `InferenceRequest::timeout` when set, falling back
+//! to a per-`ModelType` default (see `ModelType::typical_timeout`). Requests carrying a deadline
+//! are additionally cut off when the deadline passes.
+//!
+//! Streams are bounded as a whole: once the timeout passes, the stream yields the timeout error
+//! and ends, however many chunks it delivered.
+
+use crate::ensemble::forward_batch;
+use crate::streaming::ChunkResult;
+use crate::*;
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Inference service decorator that fails requests exceeding their timeout
+#[derive(Debug, Clone)]
+pub struct TimeoutService<S> {
+    inner: S,
+    defaults: HashMap<ModelType, Duration>,
+}
+
+impl<S: InferenceService> TimeoutService<S> {
+    /// Wrap a service using `ModelType::typical_timeout` as the defaults
+    pub fn new(inner: S) -> Self {
+        let defaults = ModelType::ALL
+            .into_iter()
+            .map(|model_type| (model_type, model_type.typical_timeout()))
+            .collect();
+
+        Self { inner, defaults }
+    }
+
+    /// Override the default timeout for a model type
+    pub fn with_model_type_timeout(mut self, model_type: ModelType, timeout: Duration) -> Self {
+        self.defaults.insert(model_type, timeout);
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Resolve the effective timeout for a request, bounded by its deadline
+    pub fn timeout_for(&self, request: &InferenceRequest) -> Duration {
+        let timeout = request.timeout.unwrap_or_else(|| {
+            self.defaults
+                .get(&request.model_type)
+                .copied()
+                .unwrap_or_else(|| request.model_type.typical_timeout())
+        });
+
+        request
+            .remaining_time()
+            .map_or(timeout, |remaining| timeout.min(remaining))
+    }
+}
+
+/// `InferenceLayer` wrapping services in a `TimeoutService`
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutLayer {
+    overrides: HashMap<ModelType, Duration>,
+}
+
+impl TimeoutLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the default timeout for a model type
+    pub fn with_model_type_timeout(mut self, model_type: ModelType, timeout: Duration) -> Self {
+        self.overrides.insert(model_type, timeout);
+        self
+    }
+}
+
+impl<S: InferenceService> InferenceLayer<S> for TimeoutLayer {
+    type Service = TimeoutService<S>;
+
+    fn layer(&self, inner: S) -> TimeoutService<S> {
+        self.overrides.iter().fold(
+            TimeoutService::new(inner),
+            |service, (model_type, timeout)| service.with_model_type_timeout(*model_type, *timeout),
+        )
+    }
+}
+
+/// Error of a request cut off after `timeout`, or by its deadline when that has passed
+fn timeout_error(timeout: Duration, deadline: Option<DateTime<Utc>>) -> TylError {
+    match deadline.filter(|deadline| *deadline <= Utc::now()) {
+        Some(deadline) => inference_errors::deadline_exceeded(deadline),
+        None => inference_errors::request_timeout(timeout),
+    }
+}
+
+/// Stream failing with the timeout error once `sleep` fires
+struct TimedStream {
+    stream: InferenceStream,
+    sleep: Pin<Box<tokio::time::Sleep>>,
+    timeout: Duration,
+    deadline: Option<DateTime<Utc>>,
+    expired: bool,
+}
+
+impl Stream for TimedStream {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        let this = &mut *self;
+        if this.expired {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(item) = Pin::new(&mut this.stream).poll_next(cx) {
+            return Poll::Ready(item);
+        }
+        match this.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                this.expired = true;
+                Poll::Ready(Some(Err(timeout_error(this.timeout, this.deadline))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for TimeoutService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let timeout = self.timeout_for(&request);
+        let deadline = request.deadline;
+
+        if let Some(deadline) = deadline.filter(|_| request.is_past_deadline()) {
+            return Err(inference_errors::deadline_exceeded(deadline));
+        }
+
+        match tokio::time::timeout(timeout, self.inner.infer(request)).await {
+            Ok(result) => result,
+            Err(_) => Err(timeout_error(timeout, deadline)),
+        }
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        let timeout = self.timeout_for(&request);
+        let deadline = request.deadline;
+
+        if let Some(deadline) = deadline.filter(|_| request.is_past_deadline()) {
+            return Err(inference_errors::deadline_exceeded(deadline));
+        }
+
+        let sleep = Box::pin(tokio::time::sleep(timeout));
+        match tokio::time::timeout(timeout, self.inner.infer_stream(request)).await {
+            Ok(stream) => Ok(InferenceStream::new(TimedStream {
+                stream: stream?,
+                sleep,
+                timeout,
+                deadline,
+                expired: false,
+            })),
+            Err(_) => Err(timeout_error(timeout, deadline)),
+        }
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        // The batch is answered as a whole, so it gets the longest timeout of its requests
+        let limits: Vec<_> = requests
+            .iter()
+            .map(|request| (self.timeout_for(request), request.deadline))
+            .collect();
+        let timeout = limits.iter().map(|(timeout, _)| *timeout).max();
+        let batch = forward_batch(&self.inner, requests, |request| {
+            match request.deadline.filter(|_| request.is_past_deadline()) {
+                Some(deadline) => Err(inference_errors::deadline_exceeded(deadline)),
+                None => Ok(()),
+            }
+        });
+
+        match tokio::time::timeout(timeout.unwrap_or_default(), batch).await {
+            Ok(results) => results
+                .into_iter()
+                .map(|result| result.and_then(|((), response)| response))
+                .collect(),
+            Err(_) => limits
+                .into_iter()
+                .map(|(_, deadline)| Err(timeout_error(timeout.unwrap_or_default(), deadline)))
+                .collect(),
+        }
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::MockInferenceService;
+
+    #[test]
+    fn test_timeout_resolution() {
+        let service = TimeoutService::new(MockInferenceService::new())
+            .with_model_type_timeout(ModelType::General, Duration::from_secs(10));
+
+        let fast = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast);
+        assert_eq!(service.timeout_for(&fast), Duration::from_secs(5));
+
+        let general = InferenceRequest::new("Test", HashMap::new(), ModelType::General);
+        assert_eq!(service.timeout_for(&general), Duration::from_secs(10));
+
+        let explicit = general.with_timeout(Duration::from_millis(250));
+        assert_eq!(service.timeout_for(&explicit), Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn test_request_within_timeout_succeeds() {
+        let service = TimeoutService::new(MockInferenceService::new().with_latency(10));
+
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast)
+            .with_timeout(Duration::from_secs(1));
+
+        assert!(service.infer(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_times_out() {
+        let service = TimeoutService::new(MockInferenceService::new().with_latency(500))
+            .with_model_type_timeout(ModelType::Fast, Duration::from_millis(20));
+
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast);
+        let error = service.infer(request).await.unwrap_err();
+
+        assert!(error.to_string().contains("timed out after 20ms"));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_bounds_timeout() {
+        let service = TimeoutService::new(MockInferenceService::new().with_latency(500));
+
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::Reasoning)
+            .with_deadline(Utc::now() + chrono::Duration::milliseconds(30));
+        assert!(service.timeout_for(&request) <= Duration::from_millis(30));
+
+        let error = service.infer(request).await.unwrap_err();
+        assert!(error.to_string().contains("deadline"));
+    }
+
+    #[tokio::test]
+    async fn test_expired_deadline_fails_without_calling_inner() {
+        let service = TimeoutService::new(MockInferenceService::new().with_latency(0));
+
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast)
+            .with_deadline(Utc::now() - chrono::Duration::seconds(1));
+
+        let error = service.infer(request).await.unwrap_err();
+        assert!(error.to_string().contains("deadline"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_times_out_as_a_whole() {
+        let service = TimeoutService::new(MockInferenceService::new().with_latency(500))
+            .with_model_type_timeout(ModelType::Fast, Duration::from_millis(20));
+        let request = InferenceRequest::new("Test", HashMap::new(), ModelType::Fast);
+        let error = service.infer_stream(request).await.unwrap_err();
+        assert!(error.to_string().contains("timed out after 20ms"));
+
+        // A stream that opened in time but stalls afterwards
+        let (sender, stream) = InferenceStream::channel(1);
+        sender.send(Ok(StreamChunk::new(0, "Hel"))).await.unwrap();
+        let mut stream = InferenceStream::new(TimedStream {
+            stream,
+            sleep: Box::pin(tokio::time::sleep(Duration::from_millis(20))),
+            timeout: Duration::from_millis(20),
+            deadline: None,
+            expired: false,
+        });
+        assert!(stream.next_chunk().await.unwrap().is_ok());
+        let error = stream.next_chunk().await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("timed out after 20ms"));
+        assert!(stream.next_chunk().await.is_none());
+        drop(sender);
+    }
+}
@@ -0,0 +1,241 @@
+//! TLS settings for adapters talking to self-hosted gateways
+//!
+//! `TlsConfig` holds extra root CAs (e.g. an internal CA bundle) and an optional client
+//! certificate for mutual TLS. Adapters take it on their builders so users do not have to
+//! pre-build the whole HTTP, gRPC or WebSocket client:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::tls::*;
+//! # use tyl_llm_inference_port::http_client::*;
+//! # #[cfg(all(feature = "tls", feature = "http-client"))]
+//! # async fn example() -> TylResult<()> {
+//! let tls = TlsConfig::new()
+//!     .with_root_ca_file("/etc/gateway/ca.pem")?
+//!     .with_client_identity_files("/etc/gateway/client.pem", "/etc/gateway/client.key")?;
+//! let service = HttpInferenceClient::new_with_tls("https://gateway.internal", &tls)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The type itself is always available; applying it to an adapter requires the `tls` feature.
+//! Root CAs are trusted in addition to the public web PKI roots.
+
+use crate::*;
+use std::path::Path;
+
+/// Client certificate chain and private key, PEM encoded
+#[derive(Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub cert_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+}
+
+impl std::fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientIdentity")
+            .field("cert_pem", &String::from_utf8_lossy(&self.cert_pem))
+            .field("key_pem", &"<redacted>")
+            .finish()
+    }
+}
+
+impl ClientIdentity {
+    pub fn new(cert_pem: impl Into<Vec<u8>>, key_pem: impl Into<Vec<u8>>) -> Self {
+        Self {
+            cert_pem: cert_pem.into(),
+            key_pem: key_pem.into(),
+        }
+    }
+
+    pub fn key_pem(&self) -> &[u8] {
+        &self.key_pem
+    }
+}
+
+/// Root CAs and client certificate used by adapter connections
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM bundles of additional trusted root CAs
+    pub root_ca_pem: Vec<Vec<u8>>,
+    /// Client certificate presented for mutual TLS
+    pub client_identity: Option<ClientIdentity>,
+    /// Name verified against the server certificate instead of the URL host
+    pub domain_name: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust the CA certificates of a PEM bundle
+    pub fn with_root_ca_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.root_ca_pem.push(pem.into());
+        self
+    }
+
+    /// Trust the CA certificates of a PEM bundle file
+    pub fn with_root_ca_file(self, path: impl AsRef<Path>) -> InferenceResult<Self> {
+        Ok(self.with_root_ca_pem(read_pem(path.as_ref())?))
+    }
+
+    /// Present a client certificate (chain) and its private key
+    pub fn with_client_identity_pem(
+        mut self,
+        cert_pem: impl Into<Vec<u8>>,
+        key_pem: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.client_identity = Some(ClientIdentity::new(cert_pem, key_pem));
+        self
+    }
+
+    /// Present a client certificate (chain) and private key read from PEM files
+    pub fn with_client_identity_files(
+        self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> InferenceResult<Self> {
+        let cert_pem = read_pem(cert_path.as_ref())?;
+        let key_pem = read_pem(key_path.as_ref())?;
+        Ok(self.with_client_identity_pem(cert_pem, key_pem))
+    }
+
+    /// Verify the server certificate against `domain_name` (e.g. when connecting by IP)
+    pub fn with_domain_name(mut self, domain_name: impl Into<String>) -> Self {
+        self.domain_name = Some(domain_name.into());
+        self
+    }
+
+    /// `reqwest::Client` trusting the root CAs and presenting the client certificate
+    #[cfg(all(feature = "tls", feature = "http-client"))]
+    pub fn reqwest_client(&self) -> InferenceResult<reqwest::Client> {
+        let mut builder = reqwest::Client::builder().use_rustls_tls();
+        for pem in &self.root_ca_pem {
+            for certificate in reqwest::Certificate::from_pem_bundle(pem).map_err(invalid_pem)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(identity) = &self.client_identity {
+            let mut pem = identity.cert_pem.clone();
+            pem.push(b'\n');
+            pem.extend_from_slice(&identity.key_pem);
+            builder = builder.identity(reqwest::Identity::from_pem(&pem).map_err(invalid_pem)?);
+        }
+        builder
+            .build()
+            .map_err(|e| TylError::configuration(format!("Invalid TLS configuration: {e}")))
+    }
+
+    /// tonic channel TLS settings
+    #[cfg(all(feature = "tls", feature = "grpc"))]
+    pub fn tonic_config(&self) -> tonic::transport::ClientTlsConfig {
+        use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+        let mut config = ClientTlsConfig::new().with_webpki_roots();
+        for pem in &self.root_ca_pem {
+            config = config.ca_certificate(Certificate::from_pem(pem));
+        }
+        if let Some(identity) = &self.client_identity {
+            config = config.identity(Identity::from_pem(&identity.cert_pem, &identity.key_pem));
+        }
+        if let Some(domain_name) = &self.domain_name {
+            config = config.domain_name(domain_name.clone());
+        }
+        config
+    }
+
+    /// rustls client settings (used by the WebSocket transport)
+    #[cfg(feature = "tls")]
+    pub fn rustls_config(&self) -> InferenceResult<rustls::ClientConfig> {
+        let mut roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        for pem in &self.root_ca_pem {
+            for certificate in rustls_pemfile::certs(&mut pem.as_slice()) {
+                roots
+                    .add(certificate.map_err(invalid_pem)?)
+                    .map_err(invalid_pem)?;
+            }
+        }
+
+        let provider = std::sync::Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_pem)?
+            .with_root_certificates(roots);
+        let Some(identity) = &self.client_identity else {
+            return Ok(builder.with_no_client_auth());
+        };
+        let chain = rustls_pemfile::certs(&mut identity.cert_pem.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid_pem)?;
+        let key = rustls_pemfile::private_key(&mut identity.key_pem.as_slice())
+            .map_err(invalid_pem)?
+            .ok_or_else(|| TylError::configuration("No private key in client key PEM"))?;
+        builder
+            .with_client_auth_cert(chain, key)
+            .map_err(invalid_pem)
+    }
+}
+
+fn read_pem(path: &Path) -> InferenceResult<Vec<u8>> {
+    std::fs::read(path)
+        .map_err(|e| TylError::configuration(format!("Cannot read {}: {e}", path.display())))
+}
+
+#[cfg(feature = "tls")]
+fn invalid_pem(error: impl std::fmt::Display) -> TylError {
+    TylError::configuration(format!("Invalid TLS configuration: {error}"))
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    use super::*;
+
+    const CA: &[u8] = include_bytes!("../tests/fixtures/tls/ca.pem");
+    const CLIENT_CERT: &[u8] = include_bytes!("../tests/fixtures/tls/client.pem");
+    const CLIENT_KEY: &[u8] = include_bytes!("../tests/fixtures/tls/client.key");
+
+    fn mtls() -> TlsConfig {
+        TlsConfig::new()
+            .with_root_ca_pem(CA)
+            .with_client_identity_pem(CLIENT_CERT, CLIENT_KEY)
+    }
+
+    #[test]
+    fn test_rustls_config_with_client_identity() {
+        let config = mtls().rustls_config().unwrap();
+        assert!(config.client_auth_cert_resolver.has_certs());
+
+        let config = TlsConfig::new()
+            .with_root_ca_pem(CA)
+            .rustls_config()
+            .unwrap();
+        assert!(!config.client_auth_cert_resolver.has_certs());
+    }
+
+    #[test]
+    fn test_files_and_invalid_pem() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+        let config = TlsConfig::new()
+            .with_root_ca_file(format!("{dir}/ca.pem"))
+            .unwrap()
+            .with_client_identity_files(format!("{dir}/client.pem"), format!("{dir}/client.key"))
+            .unwrap();
+        assert_eq!(config, mtls());
+        assert!(!format!("{config:?}").contains("PRIVATE KEY"));
+        assert!(TlsConfig::new()
+            .with_root_ca_file("/nonexistent.pem")
+            .is_err());
+
+        let missing_key = TlsConfig::new().with_client_identity_pem(CLIENT_CERT, CLIENT_CERT);
+        assert!(missing_key.rustls_config().is_err());
+    }
+
+    #[cfg(feature = "http-client")]
+    #[test]
+    fn test_reqwest_client() {
+        assert!(mtls().reqwest_client().is_ok());
+    }
+}
@@ -0,0 +1,220 @@
+//! Token budgets shared across a workflow
+//!
+//! A `TokenBudget` is a cloneable handle on a token allocation, typically created per agent run
+//! or pipeline and handed to every `TokenBudgetService` taking part in it. Each service checks
+//! the rendered prompt against what is left before calling the backend and debits the prompt and
+//! completion tokens reported by the response; once the allocation is consumed, further requests
+//! fail with `inference_errors::token_budget_exceeded` without reaching the backend:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::token_budget::*;
+//! # async fn example(reasoning_backend: impl InferenceService + 'static, fast_backend: impl InferenceService + 'static) -> TylResult<()> {
+//! let budget = TokenBudget::new(50_000);
+//! let planner = TokenBudgetService::new(reasoning_backend, budget.clone());
+//! let worker = TokenBudgetService::new(fast_backend, budget.clone());
+//! // ... run the workflow ...
+//! println!("{} tokens left", budget.remaining());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The remaining allocation after each request is reported under `token_budget_remaining` in the
+//! response metadata.
+
+use crate::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Response metadata key holding the tokens left in the budget after the request
+pub const TOKEN_BUDGET_REMAINING_METADATA_KEY: &str = "token_budget_remaining";
+
+/// Cloneable handle on a token allocation; clones share the same counter
+#[derive(Debug, Clone)]
+pub struct TokenBudget {
+    limit: usize,
+    used: Arc<AtomicUsize>,
+}
+
+impl TokenBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Tokens debited so far, which may exceed the limit by the last completion
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.limit.saturating_sub(self.used())
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Fail unless `tokens` more fit in the allocation
+    pub fn check(&self, tokens: usize) -> InferenceResult<()> {
+        if self.is_exhausted() || tokens > self.remaining() {
+            return Err(inference_errors::token_budget_exceeded(
+                self.limit,
+                self.used() + tokens,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Record spent tokens; returns what is left
+    pub fn debit(&self, tokens: usize) -> usize {
+        let used = self.used.fetch_add(tokens, Ordering::SeqCst) + tokens;
+        self.limit.saturating_sub(used)
+    }
+}
+
+/// Inference service decorator charging every request to a shared `TokenBudget`
+#[derive(Debug)]
+pub struct TokenBudgetService<S> {
+    inner: S,
+    budget: TokenBudget,
+}
+
+impl<S: InferenceService> TokenBudgetService<S> {
+    pub fn new(inner: S, budget: TokenBudget) -> Self {
+        Self { inner, budget }
+    }
+
+    pub fn budget(&self) -> &TokenBudget {
+        &self.budget
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+/// `InferenceLayer` charging every wrapped service to the same `TokenBudget`
+#[derive(Debug, Clone)]
+pub struct TokenBudgetLayer {
+    budget: TokenBudget,
+}
+
+impl TokenBudgetLayer {
+    pub fn new(budget: TokenBudget) -> Self {
+        Self { budget }
+    }
+}
+
+impl<S: InferenceService> InferenceLayer<S> for TokenBudgetLayer {
+    type Service = TokenBudgetService<S>;
+
+    fn layer(&self, inner: S) -> TokenBudgetService<S> {
+        TokenBudgetService::new(inner, self.budget.clone())
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for TokenBudgetService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let prompt_tokens = self.inner.count_tokens(&request.render_template())?;
+        self.budget.check(prompt_tokens)?;
+
+        let mut response = self.inner.infer(request).await?;
+        let remaining = self
+            .budget
+            .debit(response.metadata.token_usage.total_tokens as usize);
+        response.metadata = response
+            .metadata
+            .with_metadata(TOKEN_BUDGET_REMAINING_METADATA_KEY, remaining.to_string());
+        Ok(response)
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_the_allocation() {
+        let budget = TokenBudget::new(100);
+        let other = budget.clone();
+        assert_eq!(other.debit(60), 40);
+        assert_eq!(budget.remaining(), 40);
+        assert!(budget.check(40).is_ok());
+        assert!(budget.check(41).is_err());
+
+        assert_eq!(budget.debit(50), 0);
+        assert_eq!(other.used(), 110);
+        assert!(other.is_exhausted());
+        assert!(other.check(0).is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_rejects_requests_once_consumed() {
+        use crate::mock::MockInferenceService;
+
+        let budget = TokenBudget::new(40);
+        let service = TokenBudgetService::new(
+            MockInferenceService::new()
+                .with_latency(0)
+                .with_custom_response("\"four words of text\""),
+            budget.clone(),
+        );
+        let request = || InferenceRequest::new("a".repeat(40), HashMap::new(), ModelType::General);
+
+        // 10 prompt tokens and 5 completion tokens per request; the third prompt still fits
+        for remaining in ["25", "10", "0"] {
+            let response = service.infer(request()).await.unwrap();
+            assert_eq!(
+                response.metadata.metadata[TOKEN_BUDGET_REMAINING_METADATA_KEY],
+                remaining
+            );
+        }
+        assert_eq!(budget.used(), 45);
+        let err = service.infer(request()).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Token budget of 40 tokens exceeded (55 tokens requested)"));
+    }
+}
@@ -0,0 +1,398 @@
+//! Transport-agnostic streaming
+//!
+//! Backends reached over message-oriented connections (WebSockets, message queues, custom
+//! gateways) implement `StreamingTransport`: open a connection for a request and yield raw
+//! frames. A `FrameCodec` turns the request into the opening frame and each received frame into
+//! a `StreamChunk`, and `TransportService` adapts the pair into an `InferenceService` whose
+//! `infer_stream` reads a frame only when the consumer polls for the next chunk.
+
+use crate::streaming::ChunkResult;
+use crate::*;
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Raw frame exchanged with a streaming transport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Open connection yielding frames from the backend
+#[async_trait]
+pub trait TransportStream: Send {
+    /// Next frame, `None` once the backend has closed the stream
+    async fn next_message(&mut self) -> Option<InferenceResult<TransportMessage>>;
+}
+
+/// Connection factory for a message-oriented backend
+#[async_trait]
+pub trait StreamingTransport: Send + Sync {
+    /// Open a stream and send the opening `message`
+    async fn open(&self, message: TransportMessage) -> InferenceResult<Box<dyn TransportStream>>;
+
+    /// Check that the backend is reachable
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        Ok(HealthCheckResult::new(HealthStatus::healthy()))
+    }
+}
+
+/// Meaning of a decoded frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedFrame {
+    Chunk(StreamChunk),
+    /// Keep-alives and other frames without content
+    Skip,
+    /// End of the response; the stream stops without reading further frames
+    Done,
+}
+
+/// Wire format of a streaming backend
+pub trait FrameCodec: Send + Sync {
+    /// Opening frame for a request
+    fn encode(&self, request: &InferenceRequest) -> InferenceResult<TransportMessage>;
+
+    /// Interpret a frame received from the backend
+    fn decode(&self, message: &TransportMessage) -> InferenceResult<DecodedFrame>;
+}
+
+/// JSON codec sending the request as JSON and expecting serialized `StreamChunk` frames
+///
+/// The text `[DONE]` or `{"done": true}` ends the stream, and an `{"error": ...}` frame fails it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFrameCodec;
+
+impl FrameCodec for JsonFrameCodec {
+    fn encode(&self, request: &InferenceRequest) -> InferenceResult<TransportMessage> {
+        serde_json::to_string(request)
+            .map(TransportMessage::Text)
+            .map_err(|e| TylError::internal(format!("Failed to encode request: {e}")))
+    }
+
+    fn decode(&self, message: &TransportMessage) -> InferenceResult<DecodedFrame> {
+        let text = match message {
+            TransportMessage::Text(text) => text.as_str(),
+            TransportMessage::Binary(bytes) => std::str::from_utf8(bytes).map_err(|_| {
+                inference_errors::generation_failed("Stream frame is not valid UTF-8")
+            })?,
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(DecodedFrame::Skip);
+        }
+        if text == crate::sse::DONE_SENTINEL {
+            return Ok(DecodedFrame::Done);
+        }
+
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| {
+            inference_errors::generation_failed(format!("Invalid stream frame: {e}"))
+        })?;
+        if let Some(error) = value.get("error") {
+            let message = error
+                .as_str()
+                .map_or_else(|| error.to_string(), String::from);
+            return Err(inference_errors::generation_failed(message));
+        }
+        if value.get("done").and_then(|done| done.as_bool()) == Some(true) {
+            return Ok(DecodedFrame::Done);
+        }
+        serde_json::from_value(value)
+            .map(DecodedFrame::Chunk)
+            .map_err(|e| inference_errors::generation_failed(format!("Invalid stream frame: {e}")))
+    }
+}
+
+/// Transport handed back together with the frame it produced
+type Frame = (
+    Box<dyn TransportStream>,
+    Option<InferenceResult<TransportMessage>>,
+);
+type NextFrame = Pin<Box<dyn Future<Output = Frame> + Send>>;
+
+enum FrameState {
+    Idle(Box<dyn TransportStream>),
+    Reading(NextFrame),
+    Finished,
+}
+
+/// Pull-based adapter from transport frames to chunks
+struct FrameChunks {
+    state: FrameState,
+    codec: Arc<dyn FrameCodec>,
+}
+
+impl Stream for FrameChunks {
+    type Item = ChunkResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkResult>> {
+        loop {
+            match std::mem::replace(&mut self.state, FrameState::Finished) {
+                FrameState::Idle(mut transport) => {
+                    self.state = FrameState::Reading(Box::pin(async move {
+                        let message = transport.next_message().await;
+                        (transport, message)
+                    }));
+                }
+                FrameState::Reading(mut next) => {
+                    let (transport, message) = match next.as_mut().poll(cx) {
+                        Poll::Ready(frame) => frame,
+                        Poll::Pending => {
+                            self.state = FrameState::Reading(next);
+                            return Poll::Pending;
+                        }
+                    };
+                    let message = match message {
+                        Some(Ok(message)) => message,
+                        Some(Err(error)) => return Poll::Ready(Some(Err(error))),
+                        None => return Poll::Ready(None),
+                    };
+                    match self.codec.decode(&message) {
+                        Ok(DecodedFrame::Chunk(chunk)) => {
+                            self.state = FrameState::Idle(transport);
+                            return Poll::Ready(Some(Ok(chunk)));
+                        }
+                        Ok(DecodedFrame::Skip) => self.state = FrameState::Idle(transport),
+                        Ok(DecodedFrame::Done) => return Poll::Ready(None),
+                        Err(error) => return Poll::Ready(Some(Err(error))),
+                    }
+                }
+                FrameState::Finished => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Decode frames from an open transport stream into an `InferenceStream`
+///
+/// Dropping the returned stream drops (and thereby closes) the transport stream.
+pub fn frame_stream(
+    transport: Box<dyn TransportStream>,
+    codec: Arc<dyn FrameCodec>,
+) -> InferenceStream {
+    InferenceStream::new(FrameChunks {
+        state: FrameState::Idle(transport),
+        codec,
+    })
+}
+
+/// Inference service backed by a `StreamingTransport`
+pub struct TransportService<T> {
+    transport: T,
+    codec: Arc<dyn FrameCodec>,
+    supported_models: Vec<String>,
+    limits: RequestLimits,
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for TransportService<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransportService")
+            .field("transport", &self.transport)
+            .field("supported_models", &self.supported_models)
+            .finish()
+    }
+}
+
+impl<T: StreamingTransport> TransportService<T> {
+    /// Use `JsonFrameCodec` as the wire format
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            codec: Arc::new(JsonFrameCodec),
+            supported_models: Vec::new(),
+            limits: RequestLimits::structural(),
+        }
+    }
+
+    pub fn with_codec(mut self, codec: impl FrameCodec + 'static) -> Self {
+        self.codec = Arc::new(codec);
+        self
+    }
+
+    /// Models reported by `supported_models`
+    pub fn with_supported_models(mut self, models: Vec<String>) -> Self {
+        self.supported_models = models;
+        self
+    }
+
+    /// Validate requests with `limits` instead of `RequestLimits::structural`
+    pub fn with_limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
+
+#[async_trait]
+impl<T: StreamingTransport> InferenceService for TransportService<T> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        if request.dry_run {
+            return dry_run_response(self, &request, &self.limits);
+        }
+        let started = Instant::now();
+        let mut model = request
+            .model_override
+            .clone()
+            .unwrap_or_else(|| request.model_type.optimal_openai_model().to_string());
+        let mut stream = self.infer_stream(request).await?;
+
+        let mut assembler = CandidateAssembler::new();
+        while let Some(chunk) = stream.next_chunk().await {
+            let chunk = chunk?;
+            if let Some(chunk_model) = &chunk.model {
+                model = chunk_model.clone();
+            }
+            assembler.push(chunk);
+        }
+
+        assembler
+            .into_responses(&model, started.elapsed().as_millis() as u64)
+            .into_iter()
+            .next()
+            .ok_or_else(|| inference_errors::generation_failed("empty response stream"))
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        if request.dry_run {
+            return Ok(InferenceStream::from_response(dry_run_response(
+                self,
+                &request,
+                &self.limits,
+            )?));
+        }
+        request.validate_with(self, &self.limits)?;
+        let message = self.codec.encode(&request)?;
+        let transport = self.transport.open(message).await?;
+        Ok(frame_stream(transport, Arc::clone(&self.codec)))
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.transport.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.supported_models.clone()
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        // Simple approximation: ~4 characters per token
+        Ok((text.len() + 3) / 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// Transport replaying scripted frames and recording opening messages
+    #[derive(Debug, Default)]
+    struct ScriptedTransport {
+        frames: Vec<TransportMessage>,
+        opened: Mutex<Vec<TransportMessage>>,
+    }
+
+    struct ScriptedStream(VecDeque<TransportMessage>);
+
+    #[async_trait]
+    impl TransportStream for ScriptedStream {
+        async fn next_message(&mut self) -> Option<InferenceResult<TransportMessage>> {
+            self.0.pop_front().map(Ok)
+        }
+    }
+
+    #[async_trait]
+    impl StreamingTransport for ScriptedTransport {
+        async fn open(
+            &self,
+            message: TransportMessage,
+        ) -> InferenceResult<Box<dyn TransportStream>> {
+            self.opened.lock().unwrap().push(message);
+            Ok(Box::new(ScriptedStream(self.frames.clone().into())))
+        }
+    }
+
+    fn text(frame: &str) -> TransportMessage {
+        TransportMessage::Text(frame.to_string())
+    }
+
+    fn request() -> InferenceRequest {
+        InferenceRequest::new("Say hi", HashMap::new(), ModelType::Fast)
+    }
+
+    #[tokio::test]
+    async fn test_frames_are_streamed_as_chunks() {
+        let transport = ScriptedTransport {
+            frames: vec![
+                text(
+                    r#"{"candidate_index":0,"delta":"Hel","finish_reason":null,"token_usage":null}"#,
+                ),
+                text(""),
+                text(
+                    r#"{"candidate_index":0,"delta":"lo","finish_reason":"stop","token_usage":null,"model":"gw-1"}"#,
+                ),
+                text("[DONE]"),
+                text(
+                    r#"{"candidate_index":0,"delta":"ignored","finish_reason":null,"token_usage":null}"#,
+                ),
+            ],
+            ..Default::default()
+        };
+        let service = TransportService::new(transport);
+
+        let response = service.infer(request()).await.unwrap();
+        assert_eq!(response.content, serde_json::Value::String("Hello".into()));
+        assert_eq!(response.metadata.model, "gw-1");
+        assert_eq!(
+            response.metadata.metadata.get("finish_reason"),
+            Some(&"stop".to_string())
+        );
+
+        let opened = service.transport().opened.lock().unwrap();
+        let TransportMessage::Text(payload) = &opened[0] else {
+            panic!("expected a text frame");
+        };
+        let sent: InferenceRequest = serde_json::from_str(payload).unwrap();
+        assert_eq!(sent.template, "Say hi");
+    }
+
+    #[tokio::test]
+    async fn test_error_frames_fail_the_stream() {
+        let transport = ScriptedTransport {
+            frames: vec![text(r#"{"error":"model overloaded"}"#)],
+            ..Default::default()
+        };
+        let service = TransportService::new(transport);
+
+        let mut stream = service.infer_stream(request()).await.unwrap();
+        let error = stream.next_chunk().await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("model overloaded"));
+        assert!(stream.next_chunk().await.is_none());
+    }
+
+    #[test]
+    fn test_json_codec_decoding() {
+        let codec = JsonFrameCodec;
+        assert_eq!(
+            codec.decode(&text(r#"{"done":true}"#)).unwrap(),
+            DecodedFrame::Done
+        );
+        assert_eq!(
+            codec
+                .decode(&TransportMessage::Binary(
+                    br#"{"candidate_index":1,"delta":"x","finish_reason":null,"token_usage":null}"#
+                        .to_vec()
+                ))
+                .unwrap(),
+            DecodedFrame::Chunk(StreamChunk::new(1, "x"))
+        );
+        assert!(codec.decode(&text("not json")).is_err());
+    }
+}
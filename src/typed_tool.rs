@@ -0,0 +1,174 @@
+//! Agent tools with typed arguments
+//!
+//! `TypedTool` registers an async Rust function as an agent `Tool` without handwritten JSON:
+//! the argument schema shown to the model is generated from the argument type with `schemars`,
+//! the model's arguments are deserialized into that type before the function runs, and its
+//! output is serialized back to JSON:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::typed_tool::*;
+//! # use tyl_llm_inference_port::agent::*;
+//! # use serde::Deserialize;
+//! # use schemars::JsonSchema;
+//! # struct Weather { celsius: f64 }
+//! # async fn lookup(city: &str) -> TylResult<Weather> { Ok(Weather { celsius: 21.0 }) }
+//! #[derive(Deserialize, JsonSchema)]
+//! struct WeatherArgs {
+//!     /// City name, e.g. "Oslo"
+//!     city: String,
+//!     fahrenheit: Option<bool>,
+//! }
+//!
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let weather = TypedTool::new("weather", "Current weather in a city", |args: WeatherArgs| async move {
+//!     Ok(lookup(&args.city).await?.celsius)
+//! });
+//! let runner = AgentRunner::new(openai).with_tool(weather);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Doc comments on the argument fields become schema descriptions. Arguments that do not match
+//! the type are reported back to the model as a failed call.
+
+use crate::agent::{Tool, ToolDefinition};
+use crate::extract::json_schema_for;
+use crate::*;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::marker::PhantomData;
+
+impl ToolDefinition {
+    /// Definition whose arguments schema is generated from `A`, which should be a struct
+    pub fn for_arguments<A: JsonSchema>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        let mut schema = json_schema_for::<A>();
+        if let Some(object) = schema.as_object_mut() {
+            object.remove("$schema");
+        }
+        Self::new(name, description).with_parameters(schema)
+    }
+}
+
+/// Tool backed by an async function of typed arguments `A` returning a serializable output
+pub struct TypedTool<A, F> {
+    definition: ToolDefinition,
+    handler: F,
+    arguments: PhantomData<fn(A)>,
+}
+
+impl<A, F> std::fmt::Debug for TypedTool<A, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedTool")
+            .field("definition", &self.definition)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A: JsonSchema, F> TypedTool<A, F> {
+    pub fn new(name: impl Into<String>, description: impl Into<String>, handler: F) -> Self {
+        Self {
+            definition: ToolDefinition::for_arguments::<A>(name, description),
+            handler,
+            arguments: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<A, F, Fut, O> Tool for TypedTool<A, F>
+where
+    A: DeserializeOwned + Send,
+    F: Fn(A) -> Fut + Send + Sync,
+    Fut: Future<Output = InferenceResult<O>> + Send,
+    O: Serialize,
+{
+    fn definition(&self) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    async fn call(&self, arguments: serde_json::Value) -> InferenceResult<serde_json::Value> {
+        let arguments = serde_json::from_value(arguments).map_err(|e| {
+            TylError::validation(
+                "arguments",
+                format!("Invalid arguments for tool {}: {e}", self.definition.name),
+            )
+        })?;
+        let output = (self.handler)(arguments).await?;
+        serde_json::to_value(output)
+            .map_err(|e| TylError::internal(format!("Failed to serialize tool output: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct WeatherArgs {
+        /// City name
+        city: String,
+        fahrenheit: Option<bool>,
+    }
+
+    #[derive(Serialize)]
+    struct Weather {
+        city: String,
+        temperature: i32,
+    }
+
+    fn weather() -> impl Tool {
+        TypedTool::new(
+            "weather",
+            "Current weather in a city.",
+            |args: WeatherArgs| async move {
+                let temperature = if args.fahrenheit.unwrap_or(false) {
+                    70
+                } else {
+                    21
+                };
+                Ok(Weather {
+                    city: args.city,
+                    temperature,
+                })
+            },
+        )
+    }
+
+    #[test]
+    fn test_definition_from_argument_type() {
+        let definition = weather().definition();
+        assert_eq!(definition.name, "weather");
+        let schema = &definition.parameters;
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["city"]["type"], "string");
+        assert_eq!(schema["properties"]["city"]["description"], "City name");
+        assert_eq!(schema["required"], serde_json::json!(["city"]));
+        assert!(schema.get("$schema").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_call_decodes_arguments() {
+        let tool = weather();
+        let output = tool
+            .call(serde_json::json!({"city": "Oslo", "fahrenheit": true}))
+            .await
+            .unwrap();
+        assert_eq!(
+            output,
+            serde_json::json!({"city": "Oslo", "temperature": 70})
+        );
+
+        let err = tool
+            .call(serde_json::json!({"town": "Oslo"}))
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Invalid arguments for tool weather"));
+    }
+}
@@ -0,0 +1,422 @@
+//! Usage export for BI tooling
+//!
+//! `UsageExporter` summarizes a `LedgerStore` per time bucket (a day by default), scope, model
+//! and model type with `LedgerStore::usage_summary`, and writes one row per group as CSV or,
+//! with the `parquet` feature, as a Parquet file:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::usage_export::*;
+//! # use tyl_llm_inference_port::ledger::*;
+//! # use chrono::{DateTime, Utc};
+//! # use std::sync::Arc;
+//! # #[cfg(feature = "parquet")]
+//! # async fn example(ledger: Arc<dyn LedgerStore>, since: DateTime<Utc>, until: DateTime<Utc>) -> TylResult<()> {
+//! let exporter = UsageExporter::new(ledger).with_bucket(chrono::Duration::hours(1));
+//! // On demand
+//! exporter.export_to_file("usage.csv", ExportFormat::Csv, since, until).await?;
+//! // From a nightly job: yesterday's usage into `exports/usage-20240314T0000.parquet`
+//! exporter.export_last_bucket("exports", ExportFormat::Parquet, Utc::now()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Buckets are aligned to the Unix epoch, so daily buckets are UTC days. Every scope of the
+//! store is exported unless `with_scopes` limits the export.
+
+use crate::ledger::{LedgerStore, UsageTotals};
+use crate::usage_summary::{bucket_start, model_type_name, UsageDimension, UsageFilter};
+use crate::*;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// File format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl ExportFormat {
+    /// File extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// Aggregated usage of one scope, model and model type over one bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageExportRow {
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub scope: String,
+    pub model: String,
+    pub model_type: ModelType,
+    pub totals: UsageTotals,
+}
+
+/// Column names of exported files, in order
+pub const EXPORT_COLUMNS: [&str; 11] = [
+    "period_start",
+    "period_end",
+    "scope",
+    "model",
+    "model_type",
+    "requests",
+    "prompt_tokens",
+    "completion_tokens",
+    "total_tokens",
+    "cost_usd",
+    "errors",
+];
+
+/// CSV field, quoted when it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_error(error: impl std::fmt::Display) -> TylError {
+    TylError::internal(format!("Usage export failed: {error}"))
+}
+
+/// Writes aggregated ledger usage to CSV or Parquet
+#[derive(Clone)]
+pub struct UsageExporter {
+    store: Arc<dyn LedgerStore>,
+    scopes: Option<Vec<String>>,
+    bucket: chrono::Duration,
+}
+
+impl std::fmt::Debug for UsageExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UsageExporter")
+            .field("scopes", &self.scopes)
+            .field("bucket", &self.bucket)
+            .finish_non_exhaustive()
+    }
+}
+
+impl UsageExporter {
+    /// Export every scope of `store` in daily buckets
+    pub fn new(store: Arc<dyn LedgerStore>) -> Self {
+        Self {
+            store,
+            scopes: None,
+            bucket: chrono::Duration::days(1),
+        }
+    }
+
+    /// Only export these scopes (needed for stores that cannot list their scopes)
+    pub fn with_scopes(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.scopes = Some(scopes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Length of the aggregation buckets (at least a millisecond)
+    pub fn with_bucket(mut self, bucket: chrono::Duration) -> Self {
+        self.bucket = bucket.max(chrono::Duration::milliseconds(1));
+        self
+    }
+
+    /// Aggregated rows for records with `since <= recorded_at < until`, ordered by bucket,
+    /// scope, model and model type
+    pub async fn rows(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<Vec<UsageExportRow>> {
+        let mut filter = UsageFilter::new(since, until)
+            .group_by(UsageDimension::Tenant)
+            .group_by(UsageDimension::Model)
+            .group_by(UsageDimension::ModelType)
+            .with_bucket(self.bucket);
+        if let Some(scopes) = &self.scopes {
+            filter.tenants = scopes.clone();
+        }
+        let summary = self.store.usage_summary(&filter).await?;
+
+        Ok(summary
+            .groups
+            .into_iter()
+            .map(|group| UsageExportRow {
+                period_start: group.period_start.unwrap_or(since),
+                period_end: group.period_end.unwrap_or(until),
+                scope: group.tenant.unwrap_or_default(),
+                model: group.model.unwrap_or_default(),
+                model_type: group.model_type.unwrap_or_default(),
+                totals: group.totals,
+            })
+            .collect())
+    }
+
+    /// Write the rows for `since..until` as CSV with a header line, returning the row count
+    pub async fn export_csv(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        writer: &mut (impl Write + Send),
+    ) -> InferenceResult<usize> {
+        let rows = self.rows(since, until).await?;
+        write_csv(&rows, writer)?;
+        Ok(rows.len())
+    }
+
+    /// Write the rows for `since..until` as a Parquet file, returning the row count
+    #[cfg(feature = "parquet")]
+    pub async fn export_parquet(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        writer: impl Write + Send,
+    ) -> InferenceResult<usize> {
+        let rows = self.rows(since, until).await?;
+        parquet_export::write(&rows, writer)?;
+        Ok(rows.len())
+    }
+
+    /// Write the rows for `since..until` to a new file at `path`, returning the row count
+    pub async fn export_to_file(
+        &self,
+        path: impl AsRef<Path>,
+        format: ExportFormat,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> InferenceResult<usize> {
+        let rows = self.rows(since, until).await?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path).map_err(write_error)?);
+        match format {
+            ExportFormat::Csv => write_csv(&rows, &mut file)?,
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => parquet_export::write(&rows, &mut file)?,
+        }
+        file.flush().map_err(write_error)?;
+        Ok(rows.len())
+    }
+
+    /// Export the last bucket completed before `now` into `dir`, in a file named after the
+    /// bucket start (`usage-20240314T0000.csv`); returns the file path
+    ///
+    /// Meant to be called once per bucket by a scheduler; running it twice rewrites the file.
+    pub async fn export_last_bucket(
+        &self,
+        dir: impl AsRef<Path>,
+        format: ExportFormat,
+        now: DateTime<Utc>,
+    ) -> InferenceResult<PathBuf> {
+        let until = bucket_start(now, self.bucket);
+        let since = until - self.bucket;
+        let path = dir.as_ref().join(format!(
+            "usage-{}.{}",
+            since.format("%Y%m%dT%H%M"),
+            format.extension()
+        ));
+        self.export_to_file(&path, format, since, until).await?;
+        Ok(path)
+    }
+}
+
+fn write_csv(rows: &[UsageExportRow], writer: &mut impl Write) -> InferenceResult<()> {
+    writeln!(writer, "{}", EXPORT_COLUMNS.join(",")).map_err(write_error)?;
+    for row in rows {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{:.6},{}",
+            row.period_start.to_rfc3339(),
+            row.period_end.to_rfc3339(),
+            csv_field(&row.scope),
+            csv_field(&row.model),
+            model_type_name(row.model_type),
+            row.totals.requests,
+            row.totals.prompt_tokens,
+            row.totals.completion_tokens,
+            row.totals.total_tokens,
+            row.totals.cost_usd,
+            row.totals.errors,
+        )
+        .map_err(write_error)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_export {
+    use super::{model_type_name, write_error, UsageExportRow, EXPORT_COLUMNS};
+    use crate::InferenceResult;
+    use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+    use arrow_array::{TimestampMillisecondArray, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use parquet::arrow::ArrowWriter;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    pub(super) fn write(rows: &[UsageExportRow], writer: impl Write + Send) -> InferenceResult<()> {
+        let timestamp = DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()));
+        let types = [
+            timestamp.clone(),
+            timestamp,
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::UInt64,
+            DataType::UInt64,
+            DataType::UInt64,
+            DataType::UInt64,
+            DataType::Float64,
+            DataType::UInt64,
+        ];
+        let schema = Arc::new(Schema::new(
+            EXPORT_COLUMNS
+                .iter()
+                .zip(types)
+                .map(|(name, data_type)| Field::new(*name, data_type, false))
+                .collect::<Vec<_>>(),
+        ));
+
+        let times = |time: fn(&UsageExportRow) -> i64| -> ArrayRef {
+            Arc::new(
+                TimestampMillisecondArray::from_iter_values(rows.iter().map(time))
+                    .with_timezone("UTC"),
+            )
+        };
+        let strings = |text: fn(&UsageExportRow) -> String| -> ArrayRef {
+            Arc::new(StringArray::from_iter_values(rows.iter().map(text)))
+        };
+        let counts = |count: fn(&UsageExportRow) -> u64| -> ArrayRef {
+            Arc::new(UInt64Array::from_iter_values(rows.iter().map(count)))
+        };
+        let columns = vec![
+            times(|row| row.period_start.timestamp_millis()),
+            times(|row| row.period_end.timestamp_millis()),
+            strings(|row| row.scope.clone()),
+            strings(|row| row.model.clone()),
+            strings(|row| model_type_name(row.model_type)),
+            counts(|row| row.totals.requests),
+            counts(|row| row.totals.prompt_tokens),
+            counts(|row| row.totals.completion_tokens),
+            counts(|row| row.totals.total_tokens),
+            Arc::new(Float64Array::from_iter_values(
+                rows.iter().map(|row| row.totals.cost_usd),
+            )),
+            counts(|row| row.totals.errors),
+        ];
+
+        let batch = RecordBatch::try_new(schema.clone(), columns).map_err(write_error)?;
+        let mut writer = ArrowWriter::try_new(writer, schema, None).map_err(write_error)?;
+        writer.write(&batch).map_err(write_error)?;
+        writer.close().map_err(write_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::{InMemoryLedgerStore, UsageRecord};
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        use chrono::TimeZone;
+        Utc.with_ymd_and_hms(2024, 3, 14, hour, minute, 0).unwrap()
+    }
+
+    async fn exporter() -> UsageExporter {
+        let store = Arc::new(InMemoryLedgerStore::new());
+        let records = [
+            ("team-a", "gpt-4o", ModelType::Coding, at(9, 0), 0.25),
+            ("team-a", "gpt-4o", ModelType::Coding, at(9, 30), 0.5),
+            ("team-a", "gpt-4o", ModelType::Fast, at(9, 45), 0.125),
+            (
+                "team \"b\", inc",
+                "gpt-4o",
+                ModelType::Coding,
+                at(10, 5),
+                1.0,
+            ),
+        ];
+        for (scope, model, model_type, recorded_at, cost) in records {
+            store
+                .append(
+                    UsageRecord::new(scope, model, model_type, TokenUsage::new(100, 10))
+                        .with_cost(cost)
+                        .with_recorded_at(recorded_at),
+                )
+                .await
+                .unwrap();
+        }
+        UsageExporter::new(store).with_bucket(chrono::Duration::hours(1))
+    }
+
+    #[tokio::test]
+    async fn test_rows_are_grouped_per_bucket() {
+        let exporter = exporter().await;
+        let rows = exporter.rows(at(0, 0), at(23, 0)).await.unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].period_start, at(9, 0));
+        assert_eq!(rows[0].period_end, at(10, 0));
+        assert_eq!(rows[0].model_type, ModelType::Coding);
+        assert_eq!(rows[0].totals.requests, 2);
+        assert_eq!(rows[0].totals.total_tokens, 220);
+        assert!((rows[0].totals.cost_usd - 0.75).abs() < 1e-9);
+        assert_eq!(rows[1].model_type, ModelType::Fast);
+        assert_eq!(rows[2].period_start, at(10, 0));
+
+        let only_a = exporter.clone().with_scopes(["team-a"]);
+        assert_eq!(only_a.rows(at(0, 0), at(23, 0)).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_csv_export() {
+        let exporter = exporter().await;
+        let mut csv = Vec::new();
+        let count = exporter
+            .export_csv(at(0, 0), at(23, 0), &mut csv)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], EXPORT_COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            "2024-03-14T09:00:00+00:00,2024-03-14T10:00:00+00:00,team-a,gpt-4o,Coding,\
+             2,200,20,220,0.750000,0"
+        );
+        assert!(lines[3].contains(",\"team \"\"b\"\", inc\",gpt-4o,"));
+    }
+
+    #[tokio::test]
+    async fn test_exports_last_bucket_to_a_file() {
+        let exporter = exporter().await;
+        let dir = std::env::temp_dir().join(format!("tyl-usage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = exporter
+            .export_last_bucket(&dir, ExportFormat::Csv, at(10, 20))
+            .await
+            .unwrap();
+        assert_eq!(path, dir.join("usage-20240314T0900.csv"));
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+
+        #[cfg(feature = "parquet")]
+        {
+            let path = exporter
+                .export_last_bucket(&dir, ExportFormat::Parquet, at(10, 20))
+                .await
+                .unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            assert!(bytes.starts_with(b"PAR1") && bytes.ends_with(b"PAR1"));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+//! Aggregated usage queries over the ledger
+//!
+//! `LedgerStore::usage_summary` answers the questions a usage dashboard asks: how many tokens,
+//! how much spend, how many requests and how many failures, per model, model type, tenant
+//! (ledger scope) and time bucket:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::usage_summary::*;
+//! # use tyl_llm_inference_port::ledger::*;
+//! # use chrono::Utc;
+//! # async fn example(ledger: impl LedgerStore) -> TylResult<()> {
+//! let filter = UsageFilter::new(Utc::now() - chrono::Duration::days(7), Utc::now())
+//!     .for_tenant("team-a")
+//!     .group_by(UsageDimension::Model)
+//!     .with_bucket(chrono::Duration::days(1));
+//! for group in ledger.usage_summary(&filter).await?.groups {
+//!     println!("{:?} {:?}: ${:.2}, {:.1}% errors", group.period_start, group.model,
+//!         group.totals.cost_usd, group.totals.error_rate() * 100.0);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Buckets are aligned to the Unix epoch, so daily buckets are UTC days.
+
+use crate::ledger::{UsageRecord, UsageTotals};
+use crate::*;
+
+/// Start of the epoch-aligned bucket of length `bucket` containing `at`
+pub(crate) fn bucket_start(at: DateTime<Utc>, bucket: chrono::Duration) -> DateTime<Utc> {
+    let size = bucket.num_milliseconds().max(1);
+    let millis = at.timestamp_millis();
+    DateTime::from_timestamp_millis(millis - millis.rem_euclid(size)).unwrap_or(at)
+}
+
+/// Serialized name of a model type (`Coding`, `Reasoning`...)
+pub(crate) fn model_type_name(model_type: ModelType) -> String {
+    serde_json::to_value(model_type)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Record attribute usage can be grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageDimension {
+    Model,
+    ModelType,
+    /// Ledger scope
+    Tenant,
+}
+
+/// Records selected by a usage summary and how they are grouped
+///
+/// Empty `tenants`, `models` and `model_types` lists match every record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageFilter {
+    /// Records with `recorded_at >= since`
+    pub since: DateTime<Utc>,
+    /// Records with `recorded_at < until`
+    pub until: DateTime<Utc>,
+    pub tenants: Vec<String>,
+    pub models: Vec<String>,
+    pub model_types: Vec<ModelType>,
+    /// Dimensions of the groups; none sums everything into one group per bucket
+    pub group_by: Vec<UsageDimension>,
+    /// Length of the time buckets; `None` sums the whole range
+    #[serde(default, with = "optional_millis")]
+    pub bucket: Option<chrono::Duration>,
+}
+
+impl UsageFilter {
+    /// Every record with `since <= recorded_at < until`, in a single group
+    pub fn new(since: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        Self {
+            since,
+            until,
+            tenants: Vec::new(),
+            models: Vec::new(),
+            model_types: Vec::new(),
+            group_by: Vec::new(),
+            bucket: None,
+        }
+    }
+
+    pub fn for_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenants.push(tenant.into());
+        self
+    }
+
+    pub fn for_model(mut self, model: impl Into<String>) -> Self {
+        self.models.push(model.into());
+        self
+    }
+
+    pub fn for_model_type(mut self, model_type: ModelType) -> Self {
+        self.model_types.push(model_type);
+        self
+    }
+
+    /// Add a grouping dimension
+    pub fn group_by(mut self, dimension: UsageDimension) -> Self {
+        if !self.group_by.contains(&dimension) {
+            self.group_by.push(dimension);
+        }
+        self
+    }
+
+    /// Group by time buckets of length `bucket` (at least a millisecond)
+    pub fn with_bucket(mut self, bucket: chrono::Duration) -> Self {
+        self.bucket = Some(bucket.max(chrono::Duration::milliseconds(1)));
+        self
+    }
+
+    /// Whether a record falls in the time range and matches every criterion
+    pub fn matches(&self, record: &UsageRecord) -> bool {
+        record.recorded_at >= self.since
+            && record.recorded_at < self.until
+            && (self.tenants.is_empty() || self.tenants.contains(&record.scope))
+            && (self.models.is_empty() || self.models.contains(&record.model))
+            && (self.model_types.is_empty() || self.model_types.contains(&record.model_type))
+    }
+
+    /// Aggregate the matching records into groups ordered by bucket, tenant, model and model type
+    pub fn summarize<'a>(
+        &self,
+        records: impl IntoIterator<Item = &'a UsageRecord>,
+    ) -> UsageSummary {
+        let grouped = |dimension| self.group_by.contains(&dimension);
+        let mut summary = UsageSummary::default();
+        let mut groups: HashMap<UsageGroupKey, UsageGroup> = HashMap::new();
+
+        for record in records.into_iter().filter(|record| self.matches(record)) {
+            summary.totals.add(record);
+            let period_start = self
+                .bucket
+                .map(|bucket| bucket_start(record.recorded_at, bucket));
+            let tenant = grouped(UsageDimension::Tenant).then(|| record.scope.clone());
+            let model = grouped(UsageDimension::Model).then(|| record.model.clone());
+            let model_type = grouped(UsageDimension::ModelType).then_some(record.model_type);
+            let key = (
+                period_start,
+                tenant.clone(),
+                model.clone(),
+                model_type.map(model_type_name),
+            );
+            groups
+                .entry(key)
+                .or_insert_with(|| UsageGroup {
+                    period_start,
+                    period_end: period_start
+                        .zip(self.bucket)
+                        .map(|(start, bucket)| start + bucket),
+                    tenant,
+                    model,
+                    model_type,
+                    totals: UsageTotals::default(),
+                })
+                .totals
+                .add(record);
+        }
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+        summary.groups = groups.into_iter().map(|(_, group)| group).collect();
+        summary
+    }
+}
+
+type UsageGroupKey = (
+    Option<DateTime<Utc>>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Usage of one group; dimensions the filter does not group by are `None`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageGroup {
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub tenant: Option<String>,
+    pub model: Option<String>,
+    pub model_type: Option<ModelType>,
+    pub totals: UsageTotals,
+}
+
+/// Result of `LedgerStore::usage_summary`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    /// Usage of every matching record
+    pub totals: UsageTotals,
+    pub groups: Vec<UsageGroup>,
+}
+
+mod optional_millis {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<chrono::Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .map(|bucket| bucket.num_milliseconds())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<chrono::Duration>, D::Error> {
+        Ok(Option::<i64>::deserialize(deserializer)?.map(chrono::Duration::milliseconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::{InMemoryLedgerStore, LedgerStore};
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap()
+    }
+
+    async fn store() -> InMemoryLedgerStore {
+        let store = InMemoryLedgerStore::new();
+        let records = [
+            UsageRecord::new(
+                "team-a",
+                "gpt-4o",
+                ModelType::Coding,
+                TokenUsage::new(10, 5),
+            )
+            .with_cost(0.5)
+            .with_recorded_at(at(14, 9)),
+            UsageRecord::new(
+                "team-a",
+                "gpt-4o-mini",
+                ModelType::Fast,
+                TokenUsage::new(4, 1),
+            )
+            .with_cost(0.01)
+            .with_recorded_at(at(14, 10)),
+            UsageRecord::failed("team-a", "gpt-4o", ModelType::Coding, "overloaded")
+                .with_recorded_at(at(15, 8)),
+            UsageRecord::new(
+                "team-b",
+                "gpt-4o",
+                ModelType::Reasoning,
+                TokenUsage::new(20, 20),
+            )
+            .with_cost(1.0)
+            .with_recorded_at(at(15, 9)),
+        ];
+        for record in records {
+            store.append(record).await.unwrap();
+        }
+        store
+    }
+
+    #[tokio::test]
+    async fn test_summary_by_model_and_day() {
+        let store = store().await;
+        let filter = UsageFilter::new(at(1, 0), at(31, 0))
+            .group_by(UsageDimension::Model)
+            .with_bucket(chrono::Duration::days(1));
+        let summary = store.usage_summary(&filter).await.unwrap();
+
+        assert_eq!(summary.totals.requests, 4);
+        assert_eq!(summary.totals.errors, 1);
+        assert_eq!(summary.totals.total_tokens, 60);
+        assert!((summary.totals.error_rate() - 0.25).abs() < 1e-9);
+
+        let groups: Vec<_> = summary
+            .groups
+            .iter()
+            .map(|g| {
+                (
+                    g.period_start.unwrap(),
+                    g.model.as_deref().unwrap(),
+                    g.totals.requests,
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (at(14, 0), "gpt-4o", 1),
+                (at(14, 0), "gpt-4o-mini", 1),
+                (at(15, 0), "gpt-4o", 2),
+            ]
+        );
+        let failing_day = &summary.groups[2];
+        assert_eq!(failing_day.period_end, Some(at(16, 0)));
+        assert_eq!(failing_day.tenant, None);
+        assert!((failing_day.totals.error_rate() - 0.5).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_summary_filters() {
+        let store = store().await;
+        let filter = UsageFilter::new(at(1, 0), at(31, 0))
+            .for_tenant("team-a")
+            .for_model_type(ModelType::Coding)
+            .group_by(UsageDimension::Tenant)
+            .group_by(UsageDimension::ModelType);
+        let summary = store.usage_summary(&filter).await.unwrap();
+
+        assert_eq!(summary.groups.len(), 1);
+        let group = &summary.groups[0];
+        assert_eq!(group.tenant.as_deref(), Some("team-a"));
+        assert_eq!(group.model_type, Some(ModelType::Coding));
+        assert_eq!(group.period_start, None);
+        assert_eq!(group.totals.requests, 2);
+        assert!((group.totals.cost_usd - 0.5).abs() < 1e-9);
+
+        let filter = filter.with_bucket(chrono::Duration::hours(1));
+        let json = serde_json::to_value(&filter).unwrap();
+        assert_eq!(json["bucket"], 3_600_000);
+        let filter: UsageFilter = serde_json::from_value(json).unwrap();
+        assert_eq!(filter.bucket, Some(chrono::Duration::hours(1)));
+    }
+}
@@ -0,0 +1,391 @@
+//! Request validation before dispatch
+//!
+//! `InferenceRequest::validate` catches requests that can only fail (or silently misbehave)
+//! once they reach a provider: an empty template, `max_tokens` of zero, NaN or out-of-range
+//! sampling values, malformed attachments, huge parameter values, and prompts that cannot fit the model's context
+//! window together with the requested output. Each failure is a validation error naming the
+//! offending field, e.g. `parameters.document` or `temperature`.
+//!
+//! The rendered prompt is also capped in bytes (4 MiB by default) and optionally in tokens, so a
+//! caller that inlines a whole file into a parameter gets a `payload_too_large` error instead of
+//! sending it to a gateway:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::validation::*;
+//! # async fn example(openai: impl InferenceService + 'static) -> TylResult<()> {
+//! let limits = RequestLimits::new()
+//!     .with_max_prompt_bytes(256 * 1024)
+//!     .with_max_prompt_tokens(32_000);
+//! let service = RequestLimitService::new(openai, limits);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Adapters validate every request before contacting their backend, and queueing decorators
+//! validate before a request waits for capacity. Both default to `RequestLimits::structural`,
+//! which leaves the context window unchecked because they cannot know the model's; pass the
+//! window with `with_limits`, e.g. from the `ModelCatalog`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::validation::*;
+//! # use tyl_llm_inference_port::catalog::*;
+//! # use tyl_llm_inference_port::http_client::*;
+//! # async fn example(url: &str, transport: impl HttpTransport + 'static, catalog: ModelCatalog) -> TylResult<()> {
+//! let ollama = HttpInferenceClient::new(url, transport)
+//!     .with_limits(catalog.get("llama3:8b").unwrap().request_limits());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Custom adapters call `validate_with` to count tokens with their own tokenizer.
+
+use crate::chunking::{ApproximateTokenCounter, TokenCounter};
+use crate::ensemble::forward_batch;
+use crate::*;
+
+/// Largest parameter value accepted by default, in bytes
+pub const DEFAULT_MAX_PARAMETER_BYTES: usize = 1024 * 1024;
+/// Largest rendered prompt accepted by default, in bytes
+pub const DEFAULT_MAX_PROMPT_BYTES: usize = 4 * 1024 * 1024;
+
+/// Bounds applied by `InferenceRequest::validate_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestLimits {
+    /// Largest accepted parameter value, in bytes
+    pub max_parameter_bytes: usize,
+    /// Largest accepted rendered prompt, in bytes
+    pub max_prompt_bytes: Option<usize>,
+    /// Largest accepted rendered prompt, in tokens
+    pub max_prompt_tokens: Option<usize>,
+    /// Context window of the target model
+    pub context_window: Option<usize>,
+    /// Check requests without a model override against their model type's typical window when
+    /// `context_window` is unset
+    #[serde(default = "typical_context_window")]
+    pub typical_context_window: bool,
+}
+
+fn typical_context_window() -> bool {
+    true
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_parameter_bytes: DEFAULT_MAX_PARAMETER_BYTES,
+            max_prompt_bytes: Some(DEFAULT_MAX_PROMPT_BYTES),
+            max_prompt_tokens: None,
+            context_window: None,
+            typical_context_window: true,
+        }
+    }
+}
+
+impl RequestLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Default limits without a context window check, for services that cannot know the window
+    /// of the model they end up calling
+    pub fn structural() -> Self {
+        Self {
+            typical_context_window: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_parameter_bytes(mut self, bytes: usize) -> Self {
+        self.max_parameter_bytes = bytes;
+        self
+    }
+
+    pub fn with_max_prompt_bytes(mut self, bytes: usize) -> Self {
+        self.max_prompt_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_max_prompt_tokens(mut self, tokens: usize) -> Self {
+        self.max_prompt_tokens = Some(tokens);
+        self
+    }
+
+    pub fn with_context_window(mut self, tokens: usize) -> Self {
+        self.context_window = Some(tokens);
+        self
+    }
+}
+
+fn check_range(field: &str, value: Option<f32>, min: f32, max: f32) -> InferenceResult<()> {
+    match value {
+        Some(value) if value.is_nan() => Err(TylError::validation(field, "must be a number")),
+        Some(value) if !(min..=max).contains(&value) => Err(TylError::validation(
+            field,
+            format!("{value} is outside {min} to {max}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+impl InferenceRequest {
+    /// Validate with the default limits and a four-characters-per-token estimate
+    pub fn validate(&self) -> InferenceResult<()> {
+        self.validate_with(&ApproximateTokenCounter, &RequestLimits::default())
+    }
+
+    /// Validate with `limits`, counting prompt tokens with `counter`
+    pub fn validate_with<C: TokenCounter + ?Sized>(
+        &self,
+        counter: &C,
+        limits: &RequestLimits,
+    ) -> InferenceResult<()> {
+        if self.template.trim().is_empty() {
+            return Err(TylError::validation("template", "Template is empty"));
+        }
+        if self.max_tokens == Some(0) {
+            return Err(TylError::validation(
+                "max_tokens",
+                "max_tokens must be greater than zero",
+            ));
+        }
+        if self.candidates == Some(0) {
+            return Err(TylError::validation(
+                "candidates",
+                "candidates must be greater than zero",
+            ));
+        }
+        check_range("temperature", self.temperature, 0.0, 1.0)?;
+        check_range("top_p", self.top_p, 0.0, 1.0)?;
+        check_range("frequency_penalty", self.frequency_penalty, -2.0, 2.0)?;
+        check_range("presence_penalty", self.presence_penalty, -2.0, 2.0)?;
+        for attachment in &self.attachments {
+            attachment.validate()?;
+        }
+
+        let mut parameters: Vec<(&String, &String)> = self.parameters.iter().collect();
+        parameters.sort();
+        for (name, value) in parameters {
+            if value.len() > limits.max_parameter_bytes {
+                return Err(TylError::validation(
+                    format!("parameters.{name}"),
+                    format!(
+                        "Value of {} bytes exceeds the {} byte limit",
+                        value.len(),
+                        limits.max_parameter_bytes
+                    ),
+                ));
+            }
+        }
+
+        let prompt = self.render_template();
+        if let Some(limit) = limits.max_prompt_bytes {
+            if prompt.len() > limit {
+                return Err(inference_errors::payload_too_large(
+                    limit,
+                    prompt.len(),
+                    "bytes",
+                ));
+            }
+        }
+
+        let context_window = limits.context_window.or_else(|| {
+            (limits.typical_context_window && self.model_override.is_none())
+                .then(|| self.model_type.typical_context_window())
+        });
+        if limits.max_prompt_tokens.is_none() && context_window.is_none() {
+            return Ok(());
+        }
+        let prompt_tokens = counter.count(&prompt)?;
+        if let Some(limit) = limits.max_prompt_tokens {
+            if prompt_tokens > limit {
+                return Err(inference_errors::payload_too_large(
+                    limit,
+                    prompt_tokens,
+                    "tokens",
+                ));
+            }
+        }
+        if let Some(context_window) = context_window {
+            let total = prompt_tokens + self.max_tokens.unwrap_or(0);
+            if total > context_window {
+                return Err(inference_errors::context_window_exceeded(
+                    context_window,
+                    total,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Inference service decorator rejecting requests outside its `RequestLimits`
+///
+/// Prompt tokens are counted with the wrapped service's tokenizer.
+#[derive(Debug)]
+pub struct RequestLimitService<S> {
+    inner: S,
+    limits: RequestLimits,
+}
+
+impl<S: InferenceService> RequestLimitService<S> {
+    pub fn new(inner: S, limits: RequestLimits) -> Self {
+        Self { inner, limits }
+    }
+
+    pub fn limits(&self) -> &RequestLimits {
+        &self.limits
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for RequestLimitService<S> {
+    async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        request.validate_with(&self.inner, &self.limits)?;
+        self.inner.infer(request).await
+    }
+
+    async fn infer_stream(&self, request: InferenceRequest) -> InferenceResult<InferenceStream> {
+        request.validate_with(&self.inner, &self.limits)?;
+        self.inner.infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        forward_batch(&self.inner, requests, |request| {
+            request.validate_with(&self.inner, &self.limits)
+        })
+        .await
+        .into_iter()
+        .map(|result| result.and_then(|((), response)| response))
+        .collect()
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> InferenceRequest {
+        let mut parameters = HashMap::new();
+        parameters.insert("text".to_string(), "Rust is fast.".to_string());
+        InferenceRequest::new("Summarize: {{text}}", parameters, ModelType::Fast)
+    }
+
+    fn field_error(request: &InferenceRequest) -> String {
+        request.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_valid_request() {
+        assert!(request().validate().is_ok());
+    }
+
+    #[test]
+    fn test_rejects_invalid_fields() {
+        let mut empty = request();
+        empty.template = "  \n".to_string();
+        assert!(field_error(&empty).contains("Template is empty"));
+        assert!(field_error(&request().with_max_tokens(0)).contains("max_tokens"));
+        assert!(field_error(&request().with_temperature(f32::NAN)).contains("temperature"));
+        // Builders clamp, but the fields are public and deserialized
+        let mut sampling = request();
+        sampling.top_p = Some(1.5);
+        assert!(field_error(&sampling).contains("top_p"));
+        let mut candidates = request();
+        candidates.candidates = Some(0);
+        assert!(field_error(&candidates).contains("candidates"));
+    }
+
+    #[test]
+    fn test_parameter_size_and_context_window() {
+        let mut oversized = request();
+        oversized
+            .parameters
+            .insert("text".to_string(), "x".repeat(2048));
+        let limits = RequestLimits::new().with_max_parameter_bytes(1024);
+        let error = oversized
+            .validate_with(&ApproximateTokenCounter, &limits)
+            .unwrap_err();
+        assert!(error.to_string().contains("parameters.text"));
+
+        // ~16k prompt tokens plus 1024 output tokens exceed the fast model's window
+        let mut long = request();
+        long.parameters
+            .insert("text".to_string(), "word ".repeat(13_000));
+        assert!(field_error(&long).contains("Context window 16385 exceeded"));
+        // Structural limits leave the window to the backend
+        assert!(long
+            .validate_with(&ApproximateTokenCounter, &RequestLimits::structural())
+            .is_ok());
+        // An overridden model is only checked against an explicit window
+        let long = long.with_model("gpt-4.1");
+        assert!(long.validate().is_ok());
+        let limits = RequestLimits::new().with_context_window(8192);
+        assert!(long
+            .validate_with(&ApproximateTokenCounter, &limits)
+            .is_err());
+    }
+
+    #[test]
+    fn test_prompt_size_limits() {
+        let mut large = request();
+        large
+            .parameters
+            .insert("text".to_string(), "word ".repeat(400));
+        let limits = RequestLimits::new().with_max_prompt_bytes(1000);
+        let error = large
+            .validate_with(&ApproximateTokenCounter, &limits)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Payload too large: 2011 bytes exceeds the limit of 1000 bytes"));
+
+        let limits = RequestLimits::new().with_max_prompt_tokens(100);
+        let error = large
+            .validate_with(&ApproximateTokenCounter, &limits)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("503 tokens exceeds the limit of 100 tokens"));
+        assert!(large.validate().is_ok());
+    }
+}
@@ -0,0 +1,390 @@
+//! Quantization and context-length variants of local models
+//!
+//! Local runtimes serve the same model in several builds: quantizations (q4 vs q8) and context
+//! lengths (8k vs 32k) trade quality and memory for speed. `VariantService` lets requests pick a
+//! build through request metadata (`QUANTIZATION_METADATA_KEY`, `CONTEXT_LENGTH_METADATA_KEY`),
+//! checks that a matching variant is configured and served by the adapter, and runs the request
+//! on it. Responses report the variant in `ResponseMetadata.model`:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::variants::*;
+//! # use std::collections::HashMap;
+//! # async fn example(ollama: impl InferenceService + 'static) -> TylResult<()> {
+//! let service = VariantService::new(ollama)
+//!     .with_variant("llama3:8b", ModelVariant::new("llama3:8b-instruct-q4_K_M").with_quantization("q4").with_context_length(8192))
+//!     .with_variant("llama3:8b", ModelVariant::new("llama3:8b-instruct-q8_0").with_quantization("q8").with_context_length(8192))
+//!     .with_default_model(ModelType::General, "llama3:8b");
+//! let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::General)
+//!     .with_metadata(QUANTIZATION_METADATA_KEY, "q8");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Requests without a preference run on the first variant configured for their model; requests
+//! for models without variants pass through unchanged.
+
+use crate::ensemble::forward_batch;
+use crate::*;
+
+/// Request metadata key selecting a quantization (e.g. `q4`, `q8`); also set on responses
+pub const QUANTIZATION_METADATA_KEY: &str = "quantization";
+/// Request metadata key holding the minimum context length in tokens; responses hold the
+/// variant's context length
+pub const CONTEXT_LENGTH_METADATA_KEY: &str = "context_length";
+
+/// One build of a local model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelVariant {
+    /// Model name the runtime serves this build under
+    pub model: String,
+    pub quantization: Option<String>,
+    pub context_length: Option<usize>,
+}
+
+impl ModelVariant {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            quantization: None,
+            context_length: None,
+        }
+    }
+
+    pub fn with_quantization(mut self, quantization: impl Into<String>) -> Self {
+        self.quantization = Some(quantization.into());
+        self
+    }
+
+    pub fn with_context_length(mut self, context_length: usize) -> Self {
+        self.context_length = Some(context_length);
+        self
+    }
+
+    fn matches(&self, quantization: Option<&str>, context_length: Option<usize>) -> bool {
+        let quantization_ok = quantization.map_or(true, |wanted| {
+            self.quantization
+                .as_deref()
+                .is_some_and(|have| have.eq_ignore_ascii_case(wanted))
+        });
+        let context_ok = context_length.map_or(true, |wanted| {
+            self.context_length.is_some_and(|have| have >= wanted)
+        });
+        quantization_ok && context_ok
+    }
+}
+
+/// Inference service decorator running requests on the requested variant of a local model
+#[derive(Debug)]
+pub struct VariantService<S> {
+    inner: S,
+    variants: HashMap<String, Vec<ModelVariant>>,
+    default_models: HashMap<ModelType, String>,
+}
+
+impl<S: InferenceService> VariantService<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            variants: HashMap::new(),
+            default_models: HashMap::new(),
+        }
+    }
+
+    /// Add a variant of `model`; the first one added is the default
+    pub fn with_variant(mut self, model: impl Into<String>, variant: ModelVariant) -> Self {
+        self.variants.entry(model.into()).or_default().push(variant);
+        self
+    }
+
+    /// Model of requests of `model_type` that have no `model_override`
+    pub fn with_default_model(mut self, model_type: ModelType, model: impl Into<String>) -> Self {
+        self.default_models.insert(model_type, model.into());
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Variant `request` runs on, `None` for models without configured variants
+    pub fn select(&self, request: &InferenceRequest) -> InferenceResult<Option<&ModelVariant>> {
+        let Some(model) = request
+            .model_override
+            .as_ref()
+            .or_else(|| self.default_models.get(&request.model_type))
+        else {
+            return Ok(None);
+        };
+        let Some(variants) = self.variants.get(model) else {
+            return Ok(None);
+        };
+
+        let quantization = request
+            .metadata
+            .get(QUANTIZATION_METADATA_KEY)
+            .map(String::as_str);
+        let context_length = match request.metadata.get(CONTEXT_LENGTH_METADATA_KEY) {
+            Some(value) => Some(value.trim().parse::<usize>().map_err(|_| {
+                TylError::validation(
+                    CONTEXT_LENGTH_METADATA_KEY,
+                    format!("Invalid context length {value:?}"),
+                )
+            })?),
+            None => None,
+        };
+
+        // Smallest sufficient context first; otherwise configuration order
+        let variant = variants
+            .iter()
+            .filter(|variant| variant.matches(quantization, context_length))
+            .min_by_key(|variant| context_length.and(variant.context_length))
+            .ok_or_else(|| {
+                let available: Vec<String> = variants
+                    .iter()
+                    .map(|variant| {
+                        format!(
+                            "{} ({}, {})",
+                            variant.model,
+                            variant.quantization.as_deref().unwrap_or("default"),
+                            variant
+                                .context_length
+                                .map_or("default".to_string(), |length| length.to_string())
+                        )
+                    })
+                    .collect();
+                TylError::validation(
+                    "metadata",
+                    format!(
+                        "No variant of {model} matches quantization {} and context length {}; available: {}",
+                        quantization.unwrap_or("any"),
+                        context_length.map_or("any".to_string(), |length| length.to_string()),
+                        available.join(", ")
+                    ),
+                )
+            })?;
+
+        let supported = self.inner.supported_models();
+        if !supported.is_empty() && !supported.contains(&variant.model) {
+            return Err(inference_errors::unsupported_model(&variant.model));
+        }
+        Ok(Some(variant))
+    }
+
+    fn report(variant: &ModelVariant, response: &mut InferenceResponse) {
+        response.metadata.model = variant.model.clone();
+        if let Some(quantization) = &variant.quantization {
+            response
+                .metadata
+                .metadata
+                .insert(QUANTIZATION_METADATA_KEY.to_string(), quantization.clone());
+        }
+        if let Some(context_length) = variant.context_length {
+            response.metadata.metadata.insert(
+                CONTEXT_LENGTH_METADATA_KEY.to_string(),
+                context_length.to_string(),
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for VariantService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        let Some(variant) = self.select(&request)?.cloned() else {
+            return self.inner.infer(request).await;
+        };
+        request.model_override = Some(variant.model.clone());
+        let mut response = self.inner.infer(request).await?;
+        Self::report(&variant, &mut response);
+        Ok(response)
+    }
+
+    async fn infer_stream(
+        &self,
+        mut request: InferenceRequest,
+    ) -> InferenceResult<InferenceStream> {
+        if let Some(variant) = self.select(&request)? {
+            request.model_override = Some(variant.model.clone());
+        }
+        self.inner.infer_stream(request).await
+    }
+
+    async fn infer_batch(
+        &self,
+        requests: Vec<InferenceRequest>,
+    ) -> Vec<InferenceResult<InferenceResponse>> {
+        forward_batch(&self.inner, requests, |request| {
+            let variant = self.select(request)?.cloned();
+            if let Some(variant) = &variant {
+                request.model_override = Some(variant.model.clone());
+            }
+            Ok(variant)
+        })
+        .await
+        .into_iter()
+        .map(|result| {
+            let (variant, response) = result?;
+            let mut response = response?;
+            if let Some(variant) = variant {
+                Self::report(&variant, &mut response);
+            }
+            Ok(response)
+        })
+        .collect()
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        self.inner.health_check().await
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runtime serving the q4 and q8 builds, answering with the model it ran
+    #[derive(Debug)]
+    struct LocalRuntime;
+
+    #[async_trait]
+    impl InferenceService for LocalRuntime {
+        async fn infer(&self, request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+            let model = request.model_override.unwrap_or_default();
+            Ok(InferenceResponse::new(
+                serde_json::json!("hi"),
+                ResponseMetadata::new(model, TokenUsage::new(1, 1), 1),
+            ))
+        }
+
+        async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+            Ok(HealthCheckResult::new(HealthStatus::healthy()))
+        }
+
+        fn supported_models(&self) -> Vec<String> {
+            ["llama3:8b-q4", "llama3:8b-q8", "llama3:8b-q8-32k", "gpt-4o"]
+                .map(String::from)
+                .to_vec()
+        }
+
+        fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+            Ok(text.len())
+        }
+    }
+
+    fn service() -> VariantService<LocalRuntime> {
+        VariantService::new(LocalRuntime)
+            .with_variant(
+                "llama3:8b",
+                ModelVariant::new("llama3:8b-q4")
+                    .with_quantization("q4")
+                    .with_context_length(8192),
+            )
+            .with_variant(
+                "llama3:8b",
+                ModelVariant::new("llama3:8b-q8")
+                    .with_quantization("q8")
+                    .with_context_length(8192),
+            )
+            .with_variant(
+                "llama3:8b",
+                ModelVariant::new("llama3:8b-q8-32k")
+                    .with_quantization("q8")
+                    .with_context_length(32768),
+            )
+            .with_variant(
+                "mistral:7b",
+                ModelVariant::new("mistral:7b-q4").with_quantization("q4"),
+            )
+            .with_default_model(ModelType::General, "llama3:8b")
+    }
+
+    fn request(metadata: &[(&str, &str)]) -> InferenceRequest {
+        metadata.iter().fold(
+            InferenceRequest::new("Hi", HashMap::new(), ModelType::General),
+            |request, (key, value)| request.with_metadata(*key, *value),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_selects_and_reports_variant() {
+        let service = service();
+        let response = service.infer(request(&[])).await.unwrap();
+        assert_eq!(response.metadata.model, "llama3:8b-q4");
+
+        let response = service
+            .infer(request(&[(QUANTIZATION_METADATA_KEY, "Q8")]))
+            .await
+            .unwrap();
+        assert_eq!(response.metadata.model, "llama3:8b-q8");
+        assert_eq!(
+            response.metadata.metadata.get(CONTEXT_LENGTH_METADATA_KEY),
+            Some(&"8192".to_string())
+        );
+
+        let response = service
+            .infer(request(&[(CONTEXT_LENGTH_METADATA_KEY, "16000")]))
+            .await
+            .unwrap();
+        assert_eq!(response.metadata.model, "llama3:8b-q8-32k");
+
+        // Models without variants pass through
+        let response = service
+            .infer(request(&[]).with_model("gpt-4o"))
+            .await
+            .unwrap();
+        assert_eq!(response.metadata.model, "gpt-4o");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unavailable_variants() {
+        let service = service();
+        let error = service
+            .infer(request(&[
+                (QUANTIZATION_METADATA_KEY, "q4"),
+                (CONTEXT_LENGTH_METADATA_KEY, "32768"),
+            ]))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("llama3:8b-q8-32k (q8, 32768)"));
+        assert!(service
+            .infer(request(&[(CONTEXT_LENGTH_METADATA_KEY, "lots")]))
+            .await
+            .is_err());
+
+        // Configured but not served by the runtime
+        assert!(service
+            .infer(request(&[]).with_model("mistral:7b"))
+            .await
+            .is_err());
+    }
+}
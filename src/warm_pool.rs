@@ -0,0 +1,393 @@
+//! Warm pool of resident models for local adapters
+//!
+//! Local runtimes (Ollama, candle, llama.cpp) load model weights on first use, which turns the
+//! first request into a multi-second cold start. `WarmPool` preloads the configured models at
+//! startup through a `ModelLoader` and keeps them resident within a VRAM budget, evicting the
+//! least recently used model when a new one does not fit. `WarmPoolService` makes sure the
+//! model of each request is loaded before passing it on:
+//!
+//! ```rust
+//! # use tyl_llm_inference_port::*;
+//! # use tyl_llm_inference_port::warm_pool::*;
+//! # use std::sync::Arc;
+//! # const GIB: u64 = 1 << 30;
+//! # struct OllamaLoader;
+//! # impl OllamaLoader { fn new(url: &str) -> Self { Self } }
+//! # #[async_trait::async_trait]
+//! # impl ModelLoader for OllamaLoader {
+//! #     async fn load(&self, model: &str) -> TylResult<u64> { Ok(0) }
+//! #     async fn unload(&self, model: &str) -> TylResult<()> { Ok(()) }
+//! # }
+//! # async fn example(ollama: impl InferenceService + 'static, url: &str) -> TylResult<()> {
+//! let pool = Arc::new(
+//!     WarmPool::new(OllamaLoader::new(url), 24 * GIB).with_preload(["llama3:8b", "codellama:13b"]),
+//! );
+//! pool.warm_up().await?;
+//! let service = WarmPoolService::new(ollama, Arc::clone(&pool))
+//!     .with_model_type(ModelType::Coding, "codellama:13b");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Loads are serialized: a request for a resident model may wait behind another model's load.
+
+use crate::telemetry::{ResourceProbe, ResourceTelemetry};
+use crate::*;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Loads and unloads models of a local runtime
+#[async_trait]
+pub trait ModelLoader: Send + Sync {
+    /// Load `model` and keep it resident, returning the VRAM it occupies in bytes
+    ///
+    /// Loading a model that is already resident must be cheap; it refreshes the runtime's
+    /// keep-alive timer (see `WarmPool::keep_alive`).
+    async fn load(&self, model: &str) -> InferenceResult<u64>;
+
+    async fn unload(&self, model: &str) -> InferenceResult<()>;
+
+    /// VRAM `model` will need, if known before loading, so room is made ahead of the load
+    fn estimated_vram_bytes(&self, _model: &str) -> Option<u64> {
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    /// Resident models: VRAM bytes and last use tick
+    resident: HashMap<String, (u64, u64)>,
+    tick: u64,
+}
+
+impl PoolState {
+    fn vram_used(&self) -> u64 {
+        self.resident.values().map(|(bytes, _)| bytes).sum()
+    }
+
+    fn least_recently_used(&self, except: &str) -> Option<String> {
+        self.resident
+            .iter()
+            .filter(|(model, _)| model.as_str() != except)
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(model, _)| model.clone())
+    }
+}
+
+/// Keeps models resident within a VRAM budget, evicting the least recently used
+pub struct WarmPool {
+    loader: Arc<dyn ModelLoader>,
+    vram_budget_bytes: u64,
+    preload: Vec<String>,
+    state: Mutex<PoolState>,
+}
+
+impl std::fmt::Debug for WarmPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WarmPool")
+            .field("vram_budget_bytes", &self.vram_budget_bytes)
+            .field("preload", &self.preload)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WarmPool {
+    pub fn new(loader: impl ModelLoader + 'static, vram_budget_bytes: u64) -> Self {
+        Self {
+            loader: Arc::new(loader),
+            vram_budget_bytes,
+            preload: Vec::new(),
+            state: Mutex::new(PoolState::default()),
+        }
+    }
+
+    /// Models loaded by `warm_up`, in order (later ones are the most recently used)
+    pub fn with_preload(mut self, models: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.preload.extend(models.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn vram_budget_bytes(&self) -> u64 {
+        self.vram_budget_bytes
+    }
+
+    /// Load the preload models; call at startup before serving traffic
+    pub async fn warm_up(&self) -> InferenceResult<()> {
+        for model in &self.preload {
+            self.ensure_loaded(model).await?;
+        }
+        Ok(())
+    }
+
+    /// Load `model` unless resident, evicting least recently used models to fit the budget
+    pub async fn ensure_loaded(&self, model: &str) -> InferenceResult<()> {
+        let mut state = self.state.lock().await;
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some((_, last_used)) = state.resident.get_mut(model) {
+            *last_used = tick;
+            return Ok(());
+        }
+
+        if let Some(estimate) = self.loader.estimated_vram_bytes(model) {
+            self.check_fits(model, estimate)?;
+            self.evict_until(&mut state, model, self.vram_budget_bytes - estimate)
+                .await?;
+        }
+        let bytes = self.loader.load(model).await?;
+        if let Err(error) = self.check_fits(model, bytes) {
+            self.loader.unload(model).await?;
+            return Err(error);
+        }
+        state.resident.insert(model.to_string(), (bytes, tick));
+        self.evict_until(&mut state, model, self.vram_budget_bytes)
+            .await
+    }
+
+    /// Re-issue a load for every resident model, refreshing runtime keep-alive timers
+    ///
+    /// Run periodically for runtimes that unload idle models on their own (e.g. Ollama).
+    pub async fn keep_alive(&self) -> InferenceResult<()> {
+        let state = self.state.lock().await;
+        for model in state.resident.keys() {
+            self.loader.load(model).await?;
+        }
+        Ok(())
+    }
+
+    /// Resident models, most recently used first
+    pub async fn resident_models(&self) -> Vec<String> {
+        let state = self.state.lock().await;
+        let mut models: Vec<(&String, u64)> = state
+            .resident
+            .iter()
+            .map(|(model, (_, last_used))| (model, *last_used))
+            .collect();
+        models.sort_by_key(|(_, last_used)| std::cmp::Reverse(*last_used));
+        models.into_iter().map(|(model, _)| model.clone()).collect()
+    }
+
+    pub async fn vram_used_bytes(&self) -> u64 {
+        self.state.lock().await.vram_used()
+    }
+
+    fn check_fits(&self, model: &str, bytes: u64) -> InferenceResult<()> {
+        if bytes > self.vram_budget_bytes {
+            return Err(TylError::configuration(format!(
+                "Model {model} needs {bytes} bytes of VRAM, more than the {} byte budget",
+                self.vram_budget_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Unload least recently used models (never `keep`) until at most `limit` bytes are used
+    async fn evict_until(
+        &self,
+        state: &mut PoolState,
+        keep: &str,
+        limit: u64,
+    ) -> InferenceResult<()> {
+        while state.vram_used() > limit {
+            let Some(victim) = state.least_recently_used(keep) else {
+                break;
+            };
+            self.loader.unload(&victim).await?;
+            state.resident.remove(&victim);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ResourceProbe for WarmPool {
+    async fn telemetry(&self) -> InferenceResult<ResourceTelemetry> {
+        Ok(ResourceTelemetry::new()
+            .with_vram(self.vram_used_bytes().await, self.vram_budget_bytes)
+            .with_loaded_models(self.resident_models().await))
+    }
+}
+
+/// Inference service decorator loading each request's model into a `WarmPool` first
+#[derive(Debug)]
+pub struct WarmPoolService<S> {
+    inner: S,
+    pool: Arc<WarmPool>,
+    models: HashMap<ModelType, String>,
+}
+
+impl<S: InferenceService> WarmPoolService<S> {
+    pub fn new(inner: S, pool: Arc<WarmPool>) -> Self {
+        Self {
+            inner,
+            pool,
+            models: HashMap::new(),
+        }
+    }
+
+    /// Local model serving requests of `model_type` that have no `model_override`
+    pub fn with_model_type(mut self, model_type: ModelType, model: impl Into<String>) -> Self {
+        self.models.insert(model_type, model.into());
+        self
+    }
+
+    /// Get the wrapped service
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn pool(&self) -> &Arc<WarmPool> {
+        &self.pool
+    }
+
+    /// Load the request's model and pin it as the model to run
+    async fn prepare(&self, request: &mut InferenceRequest) -> InferenceResult<()> {
+        if request.model_override.is_none() {
+            request.model_override = self.models.get(&request.model_type).cloned();
+        }
+        match &request.model_override {
+            Some(model) => self.pool.ensure_loaded(model).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: InferenceService> InferenceService for WarmPoolService<S> {
+    async fn infer(&self, mut request: InferenceRequest) -> InferenceResult<InferenceResponse> {
+        self.prepare(&mut request).await?;
+        self.inner.infer(request).await
+    }
+
+    async fn infer_stream(
+        &self,
+        mut request: InferenceRequest,
+    ) -> InferenceResult<InferenceStream> {
+        self.prepare(&mut request).await?;
+        self.inner.infer_stream(request).await
+    }
+
+    fn handles_jobs(&self) -> bool {
+        self.inner.handles_jobs()
+    }
+
+    async fn submit(&self, request: InferenceRequest) -> InferenceResult<JobId> {
+        jobs::submit_via(self, &self.inner, request).await
+    }
+
+    async fn status(&self, job: &JobId) -> InferenceResult<JobStatus> {
+        jobs::status_via(self, &self.inner, job).await
+    }
+
+    async fn result(&self, job: &JobId) -> InferenceResult<InferenceResponse> {
+        jobs::result_via(self, &self.inner, job).await
+    }
+
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let health = self.inner.health_check().await?;
+        Ok(self.pool.telemetry().await?.apply(health))
+    }
+
+    fn supported_models(&self) -> Vec<String> {
+        self.inner.supported_models()
+    }
+
+    async fn list_models(&self) -> InferenceResult<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn count_tokens(&self, text: &str) -> InferenceResult<usize> {
+        self.inner.count_tokens(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    /// Loader with fixed model sizes recording its calls
+    #[derive(Default)]
+    struct FakeLoader {
+        calls: StdMutex<Vec<String>>,
+    }
+
+    fn size(model: &str) -> u64 {
+        match model {
+            "small" => 4,
+            "medium" => 8,
+            "huge" => 100,
+            _ => 6,
+        }
+    }
+
+    #[async_trait]
+    impl ModelLoader for Arc<FakeLoader> {
+        async fn load(&self, model: &str) -> InferenceResult<u64> {
+            self.calls.lock().unwrap().push(format!("load {model}"));
+            Ok(size(model))
+        }
+
+        async fn unload(&self, model: &str) -> InferenceResult<()> {
+            self.calls.lock().unwrap().push(format!("unload {model}"));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preload_and_lru_eviction() {
+        let loader = Arc::new(FakeLoader::default());
+        let pool = WarmPool::new(Arc::clone(&loader), 16).with_preload(["small", "medium"]);
+        pool.warm_up().await.unwrap();
+        assert_eq!(pool.vram_used_bytes().await, 12);
+
+        // Touch "small" so "medium" becomes the eviction candidate
+        pool.ensure_loaded("small").await.unwrap();
+        pool.ensure_loaded("other").await.unwrap();
+        assert_eq!(pool.resident_models().await, vec!["other", "small"]);
+        assert_eq!(pool.vram_used_bytes().await, 10);
+
+        assert!(pool.ensure_loaded("huge").await.is_err());
+        assert_eq!(
+            *loader.calls.lock().unwrap(),
+            vec![
+                "load small",
+                "load medium",
+                "load other",
+                "unload medium",
+                "load huge",
+                "unload huge"
+            ]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn test_service_loads_request_model() {
+        let loader = Arc::new(FakeLoader::default());
+        let pool = Arc::new(WarmPool::new(Arc::clone(&loader), 16));
+        let service = WarmPoolService::new(
+            crate::MockInferenceService::new().with_latency(0),
+            Arc::clone(&pool),
+        )
+        .with_model_type(ModelType::Coding, "medium");
+
+        let request = InferenceRequest::new("Write code", HashMap::new(), ModelType::Coding);
+        let response = service.infer(request).await.unwrap();
+        assert_eq!(response.metadata.model, "medium");
+
+        let request = InferenceRequest::new("Hi", HashMap::new(), ModelType::Fast);
+        service.infer(request).await.unwrap();
+        assert_eq!(pool.resident_models().await, vec!["medium"]);
+
+        let health = service.health_check().await.unwrap();
+        assert_eq!(
+            health.metadata.get(crate::LOADED_MODELS_METADATA_KEY),
+            Some(&serde_json::json!(["medium"]))
+        );
+        assert_eq!(
+            health.metadata.get(crate::VRAM_USED_METADATA_KEY),
+            Some(&serde_json::json!(8))
+        );
+    }
+}
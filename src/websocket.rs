@@ -0,0 +1,341 @@
+//! WebSocket streaming transport
+//!
+//! `WebSocketTransport` opens one WebSocket connection per request, sends the encoded request as
+//! the first frame, and yields text and binary frames until the server closes the connection.
+//! Wrap it in `TransportService` to get an `InferenceService`. With the `tls` feature `wss://` URLs
+//! are supported, and `with_tls` adds private root CAs and a client certificate (mTLS).
+//!
+//! With `with_credentials`, every handshake carries an `Authorization: Bearer` header from the
+//! `CredentialsProvider`; a handshake rejected with 401 is retried once with refreshed credentials.
+//! `with_signer` runs an `AuthSigner` over each handshake for gateways with custom auth schemes.
+
+use crate::credentials::{Credentials, CredentialsProvider};
+use crate::http_client::HttpRequest;
+use crate::signing::AuthSigner;
+use crate::transport::{StreamingTransport, TransportMessage, TransportStream};
+use crate::*;
+use futures_util::{SinkExt, StreamExt};
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{header, HeaderName, HeaderValue, Request, StatusCode};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// `StreamingTransport` over WebSockets
+#[derive(Clone)]
+pub struct WebSocketTransport {
+    url: String,
+    headers: Vec<(String, String)>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+    signer: Option<Arc<dyn AuthSigner>>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl std::fmt::Debug for WebSocketTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header_names: Vec<&String> = self.headers.iter().map(|(name, _)| name).collect();
+        let mut debug = f.debug_struct("WebSocketTransport");
+        debug
+            .field("url", &self.url)
+            .field("headers", &header_names)
+            .field("credentials", &self.credentials.is_some())
+            .field("signer", &self.signer.is_some());
+        #[cfg(feature = "tls")]
+        debug.field("tls", &self.tls.is_some());
+        debug.finish()
+    }
+}
+
+impl WebSocketTransport {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            headers: Vec::new(),
+            credentials: None,
+            signer: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Authenticate handshakes with bearer credentials from `provider`
+    pub fn with_credentials(mut self, provider: impl CredentialsProvider + 'static) -> Self {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    /// Connect `wss://` URLs with custom root CAs and/or a client certificate
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: &crate::tls::TlsConfig) -> InferenceResult<Self> {
+        self.tls = Some(Arc::new(tls.rustls_config()?));
+        Ok(self)
+    }
+
+    /// Pass every handshake through `signer` (as a `GET` of the socket URL) before connecting
+    pub fn with_signer(mut self, signer: impl AuthSigner + 'static) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Add a header to the handshake request (e.g. `Authorization`)
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    async fn handshake_request(
+        &self,
+        credentials: Option<&Credentials>,
+    ) -> InferenceResult<Request<()>> {
+        let mut signable = HttpRequest::get(self.url.clone());
+        signable.headers = self.headers.clone();
+        if let Some(credentials) = credentials {
+            signable.set_header(header::AUTHORIZATION.as_str(), credentials.bearer());
+        }
+        if let Some(signer) = &self.signer {
+            signer.sign(&mut signable).await?;
+        }
+
+        let mut request = self.url.as_str().into_client_request().map_err(|e| {
+            TylError::configuration(format!("Invalid WebSocket URL {}: {e}", self.url))
+        })?;
+        for (name, value) in &signable.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                TylError::configuration(format!("Invalid WebSocket header {name}: {e}"))
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                TylError::configuration(format!("Invalid WebSocket header {name}: {e}"))
+            })?;
+            request.headers_mut().append(name, value);
+        }
+        Ok(request)
+    }
+
+    async fn connect(&self) -> InferenceResult<Socket> {
+        let Some(provider) = &self.credentials else {
+            return self.connect_with(None).await;
+        };
+        let credentials = provider.get_credentials().await?;
+        match self.handshake(Some(&credentials)).await? {
+            Err(WsError::Http(response)) if response.status() == StatusCode::UNAUTHORIZED => {
+                let credentials = provider.refresh_credentials().await?;
+                self.connect_with(Some(&credentials)).await
+            }
+            result => result.map_err(connection_failed),
+        }
+    }
+
+    async fn connect_with(&self, credentials: Option<&Credentials>) -> InferenceResult<Socket> {
+        self.handshake(credentials)
+            .await?
+            .map_err(connection_failed)
+    }
+
+    async fn handshake(
+        &self,
+        credentials: Option<&Credentials>,
+    ) -> InferenceResult<Result<Socket, WsError>> {
+        let request = self.handshake_request(credentials).await?;
+        #[cfg(feature = "tls")]
+        let connected = tokio_tungstenite::connect_async_tls_with_config(
+            request,
+            None,
+            false,
+            self.tls.clone().map(tokio_tungstenite::Connector::Rustls),
+        )
+        .await;
+        #[cfg(not(feature = "tls"))]
+        let connected = tokio_tungstenite::connect_async(request).await;
+        Ok(connected.map(|(socket, _)| socket))
+    }
+}
+
+fn connection_failed(error: WsError) -> TylError {
+    TylError::network(format!("WebSocket connection failed: {error}"))
+}
+
+struct WebSocketFrames(Socket);
+
+#[async_trait]
+impl TransportStream for WebSocketFrames {
+    async fn next_message(&mut self) -> Option<InferenceResult<TransportMessage>> {
+        loop {
+            return match self.0.next().await? {
+                Ok(Message::Text(text)) => Some(Ok(TransportMessage::Text(text))),
+                Ok(Message::Binary(bytes)) => Some(Ok(TransportMessage::Binary(bytes))),
+                Ok(Message::Close(_)) => None,
+                // Pings are answered by the socket itself
+                Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => continue,
+                Err(e) => Some(Err(TylError::network(format!(
+                    "WebSocket read failed: {e}"
+                )))),
+            };
+        }
+    }
+}
+
+#[async_trait]
+impl StreamingTransport for WebSocketTransport {
+    async fn open(&self, message: TransportMessage) -> InferenceResult<Box<dyn TransportStream>> {
+        let mut socket = self.connect().await?;
+        let message = match message {
+            TransportMessage::Text(text) => Message::Text(text),
+            TransportMessage::Binary(bytes) => Message::Binary(bytes),
+        };
+        socket
+            .send(message)
+            .await
+            .map_err(|e| TylError::network(format!("WebSocket send failed: {e}")))?;
+        Ok(Box::new(WebSocketFrames(socket)))
+    }
+
+    /// Connect and immediately close a connection
+    async fn health_check(&self) -> InferenceResult<HealthCheckResult> {
+        let status = match self.connect().await {
+            Ok(mut socket) => {
+                let _ = socket.close(None).await;
+                HealthStatus::healthy()
+            }
+            Err(error) => HealthStatus::unhealthy(error.to_string()),
+        };
+        Ok(HealthCheckResult::new(status).with_metadata("url", serde_json::json!(self.url)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::TransportService;
+    use tokio::net::TcpListener;
+
+    /// Server reading one request frame and answering with `frames`, then closing
+    async fn serve(frames: Vec<&'static str>) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let request = match socket.next().await.unwrap().unwrap() {
+                Message::Text(text) => text,
+                other => panic!("unexpected request frame {other:?}"),
+            };
+            socket.send(Message::Ping(Vec::new())).await.unwrap();
+            for frame in frames {
+                socket.send(Message::Text(frame.to_string())).await.unwrap();
+            }
+            let _ = socket.close(None).await;
+            request
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_streams_frames_over_websocket() {
+        let (url, server) = serve(vec![
+            r#"{"candidate_index":0,"delta":"Hola","finish_reason":null,"token_usage":null}"#,
+            r#"{"candidate_index":0,"delta":" mundo","finish_reason":"stop","token_usage":null}"#,
+        ])
+        .await;
+        let service = TransportService::new(WebSocketTransport::new(url));
+
+        let request = InferenceRequest::new("Greet {{name}}", HashMap::new(), ModelType::Fast);
+        let mut stream = service.infer_stream(request).await.unwrap();
+        assert_eq!(stream.next_chunk().await.unwrap().unwrap().delta, "Hola");
+        let last = stream.next_chunk().await.unwrap().unwrap();
+        assert_eq!(last.delta, " mundo");
+        assert!(last.is_final());
+        assert!(stream.next_chunk().await.is_none());
+
+        let sent: InferenceRequest = serde_json::from_str(&server.await.unwrap()).unwrap();
+        assert_eq!(sent.template, "Greet {{name}}");
+    }
+
+    #[tokio::test]
+    async fn test_rejected_credentials_are_refreshed() {
+        use crate::credentials::CachedCredentials;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+        #[derive(Debug, Default)]
+        struct Rotating(AtomicUsize);
+
+        #[async_trait]
+        impl CredentialsProvider for Rotating {
+            async fn get_credentials(&self) -> InferenceResult<Credentials> {
+                let fetch = self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(Credentials::new(if fetch == 0 {
+                    "revoked"
+                } else {
+                    "current"
+                }))
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut rejected = 0;
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                #[allow(clippy::result_large_err)]
+                let check = |request: &Request, response: Response| {
+                    let authorized = request
+                        .headers()
+                        .get(header::AUTHORIZATION)
+                        .is_some_and(|value| value == "Bearer current");
+                    if authorized {
+                        Ok(response)
+                    } else {
+                        let mut error = ErrorResponse::new(None);
+                        *error.status_mut() = StatusCode::UNAUTHORIZED;
+                        Err(error)
+                    }
+                };
+                match tokio_tungstenite::accept_hdr_async(stream, check).await {
+                    Ok(mut socket) => {
+                        socket.next().await.unwrap().unwrap();
+                        socket
+                            .send(Message::Text(r#"{"done":true}"#.to_string()))
+                            .await
+                            .unwrap();
+                        let _ = socket.close(None).await;
+                        return rejected;
+                    }
+                    Err(_) => rejected += 1,
+                }
+            }
+        });
+
+        let transport = WebSocketTransport::new(url)
+            .with_credentials(CachedCredentials::new(Rotating::default()));
+        let mut stream = transport
+            .open(TransportMessage::Text("{}".into()))
+            .await
+            .unwrap();
+        assert!(stream.next_message().await.is_some());
+        assert_eq!(server.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_configuration_and_unreachable_server() {
+        let transport = WebSocketTransport::new("ws://127.0.0.1:1").with_header("bad header", "x");
+        assert!(transport
+            .open(TransportMessage::Text("{}".into()))
+            .await
+            .is_err());
+
+        let health = WebSocketTransport::new("ws://127.0.0.1:1")
+            .health_check()
+            .await
+            .unwrap();
+        assert!(!health.status.is_healthy());
+    }
+}
@@ -384,3 +384,27 @@ fn test_response_metadata_structure() {
         Some(&"custom_value".to_string())
     );
 }
+
+#[cfg(feature = "mock")]
+#[tokio::test]
+async fn test_infer_with_cancellation() {
+    use std::time::{Duration, Instant};
+    use tyl_llm_inference_port::{CancellationToken, MockInferenceService};
+
+    let service: Box<dyn InferenceService> =
+        Box::new(MockInferenceService::new().with_latency(5_000));
+    let token = CancellationToken::new();
+
+    let canceller = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        canceller.cancel();
+    });
+
+    let start = Instant::now();
+    let request = InferenceRequest::new("Expensive {{task}}", HashMap::new(), ModelType::Reasoning);
+    let result = service.infer_with_cancellation(request, token).await;
+
+    assert!(result.unwrap_err().to_string().contains("cancelled"));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
@@ -0,0 +1,73 @@
+//! Tests for `#[derive(Prompt)]` and `template!`
+
+#![cfg(feature = "derive")]
+
+use tyl_llm_inference_port::{template, ModelType, Prompt};
+
+#[derive(Prompt)]
+#[prompt(
+    template = "Summarize in {{words}} words:\n{{text}}",
+    model_type = "Fast"
+)]
+struct Summarize {
+    text: String,
+    words: usize,
+    #[prompt(skip)]
+    #[allow(dead_code)]
+    request_id: u64,
+}
+
+#[derive(Prompt)]
+#[prompt(template = "Translate to {{language}}: {{text}}")]
+struct Translate<'a> {
+    #[prompt(rename = "language")]
+    target: &'a str,
+    text: &'a str,
+}
+
+#[test]
+fn test_derived_prompt_builds_request() {
+    let prompt = Summarize {
+        text: "Rust is a systems programming language.".to_string(),
+        words: 5,
+        request_id: 7,
+    };
+    let request = prompt.to_request().with_temperature(0.2);
+    assert_eq!(request.template, Summarize::TEMPLATE);
+    assert_eq!(request.model_type, ModelType::Fast);
+    assert_eq!(request.parameters.len(), 2);
+    assert_eq!(
+        request.render_template(),
+        "Summarize in 5 words:\nRust is a systems programming language."
+    );
+}
+
+#[test]
+fn test_renamed_fields_and_default_model_type() {
+    let prompt = Translate {
+        target: "French",
+        text: "Good morning",
+    };
+    assert_eq!(prompt.model_type(), ModelType::General);
+    assert_eq!(
+        prompt.to_request().render_template(),
+        "Translate to French: Good morning"
+    );
+}
+
+#[test]
+fn test_template_macro() {
+    let user = "Ana";
+    let count = 3;
+    let request = template!(
+        "Hello {{name}}, you have {{count}} messages ({{name}})",
+        name = user.to_uppercase(),
+        count,
+    );
+    assert_eq!(request.model_type, ModelType::General);
+    assert_eq!(
+        request.render_template(),
+        "Hello ANA, you have 3 messages (ANA)"
+    );
+    assert!(template!("No parameters").parameters.is_empty());
+}